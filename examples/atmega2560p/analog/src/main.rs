@@ -18,8 +18,8 @@ pub fn main() {
 
     // Infinite loop for read and write continuously through the I/O pins.
     loop {
-        // Take input into the zeroth analog pin.
-        let a: u32 = pins.analog[0].read();
+        // Take input into the zeroth analog pin, which always exists.
+        let a: u32 = pins.analog[0].read().unwrap();
 
         // Make the input value ready to be sent through a digital pin.
         let b: u8 = map(a as u64, 0, 255, 0, 1023) as u8;
@@ -15,7 +15,8 @@ pub fn main() {
     // Send's the required message to the peripheral device at interval of 2 seconds.
     loop {
         // Print function to transmit string through USART.
-        println_string("Hello World !!!");
+        // A timeout of the transmitter is ignored, the message is sent again later.
+        let _ = println_string("Hello World !!!");
 
         rustduino::delay::delay_ms(2000);
     }
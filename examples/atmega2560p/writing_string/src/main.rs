@@ -16,13 +16,18 @@ pub fn main() {
     let mut serial = unsafe { Serial::new() };
 
     // This initializes USART0 and makes it ready to transmit and recieve.
-    unsafe { serial.usart[0].begin() };
+    if unsafe { serial.usart[0].begin() }.is_err() {
+        return;
+    }
 
     // Loop to send a string using the USART multiple times.
     let mut i: u8 = 100;
     while i != 0 {
         // This sends string from arduino through TxD0 pin.
-        serial.usart[0].write_string("Hello World!");
+        // Stop sending if the transmitter is stuck.
+        if serial.usart[0].write_string("Hello World!").is_err() {
+            break;
+        }
 
         rustduino::delay::delay_ms(1000);
 
@@ -30,7 +35,7 @@ pub fn main() {
     }
 
     // This disables USART0.
-    unsafe { serial.usart[0].end() };
+    let _ = unsafe { serial.usart[0].end() };
 }
 
 /// This function is called on panic.
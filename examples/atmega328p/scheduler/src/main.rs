@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+#![deny(warnings)]
+
+use rustduino::hal::millis::millis_init;
+use rustduino::hal::pin::Pins;
+use rustduino::hal::watchdog::WatchDog;
+use rustduino::system::scheduler::Scheduler;
+
+fn blink() {
+    // Toggle the on board LED.
+    Pins::new().digital[13].toggle();
+}
+
+fn blink_fast() {
+    // Toggle the LED on pin 12.
+    Pins::new().digital[12].toggle();
+}
+
+#[no_mangle]
+pub extern "C" fn main() {
+    // Disable watchdog
+    let wdog = unsafe { WatchDog::new() };
+    wdog.disable();
+
+    let mut pins = Pins::new();
+    pins.digital[13].set_output();
+    pins.digital[12].set_output();
+
+    // Start the millisecond counter the scheduler depends on.
+    millis_init();
+
+    let mut scheduler: Scheduler<2> = Scheduler::new();
+    scheduler.every(1000, blink);
+    scheduler.every(250, blink_fast);
+
+    loop {
+        // Runs the due tasks and sleeps till the next timer tick otherwise.
+        scheduler.run();
+    }
+}
+
+/// This function is called on panic.
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
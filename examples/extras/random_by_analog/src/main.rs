@@ -17,7 +17,9 @@ pub fn main() {
     loop {
         // Generate Random numbers using Analog pin inputs.
         // This number could be sent to peripheral device using USART.
-        let _x: u8 = rand.generate_by_analog();
+        if let Ok(_x) = rand.generate_by_analog() {
+            // Use the random number here.
+        }
     }
 }
 
@@ -2,9 +2,11 @@
 #![no_main]
 #![deny(warnings)]
 
+use rustduino::delay::Delay;
 use rustduino::hal::watchdog::WatchDog;
 /// Source codes required.
-use rustduino::math::{Generator, RandomNumberGenerator};
+use rustduino::math::RandomNumberGenerator;
+use rustduino::sensors::{I2cBus, MPU6050};
 
 #[no_mangle]
 pub fn main() {
@@ -12,12 +14,19 @@ pub fn main() {
     let wdog = unsafe { WatchDog::new() };
     wdog.disable();
 
-    let mut rand = RandomNumberGenerator::new(Generator::Mpu);
+    // The MPU6050 sensor is attached to the I2C bus.
+    let bus = match I2cBus::take() {
+        Ok(bus) => bus,
+        Err(_) => return,
+    };
+    let mut rand = RandomNumberGenerator::with_mpu(MPU6050::new(&bus, Delay));
 
     loop {
         // Generate Random numbers by MPU6050 gyroscopic sensor.
         // This number could be sent to peripheral device using USART.
-        let _y: u8 = rand.generate_by_mpu();
+        if let Ok(_y) = rand.generate_by_mpu() {
+            // Use the random number here.
+        }
     }
 }
 
@@ -3,18 +3,33 @@
 #![deny(warnings)]
 
 // Crates included which are to be used for the AHT10 example.
+use rustduino::delay::Delay;
 use rustduino::sensors::*;
 
 #[no_mangle]
 fn main() {
-    let sensor = AHT10::new(&mut AHT10::get());
+    // Take the I2C bus, the sensors share it through references.
+    let bus = match I2cBus::take() {
+        Ok(bus) => bus,
+        Err(_) => return,
+    };
+
+    // Give up if the sensor is missing or did not calibrate.
+    let mut sensor = match AHT10::new(&bus, Delay) {
+        Ok(sensor) => sensor,
+        Err(_) => return,
+    };
 
     loop {
         // Get relative humidity.
-        sensor.relative_humidity();
+        if let Ok(_humidity) = sensor.relative_humidity() {
+            // Send the value to a peripheral device using USART.
+        }
 
         // Get temperature
-        sensor.temperature();
+        if let Ok(_temperature) = sensor.temperature() {
+            // Send the value to a peripheral device using USART.
+        }
 
         // Waiting for 2 seconds.
         rustduino::delay::delay_ms(2000);
@@ -25,4 +40,4 @@ fn main() {
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     loop {}
-}
\ No newline at end of file
+}
@@ -2,7 +2,7 @@
 #![no_main]
 #![deny(warnings)]
 
-use rustduino::delay::delay_ms;
+use rustduino::delay::{delay_ms, Delay};
 use rustduino::hal::watchdog::*;
 use rustduino::sensors::*;
 
@@ -11,19 +11,33 @@ pub fn main() {
     // Disable watchdog
     let watchdog = unsafe { WatchDog::new() };
     watchdog.disable();
+    // Take the I2C bus, the sensors share it through references.
+    let bus = match I2cBus::take() {
+        Ok(bus) => bus,
+        Err(_) => return,
+    };
     // Initialize MPU6050 struct.
-    let sensor = MPU6050::new();
+    let mut sensor = MPU6050::new(&bus, Delay);
 
     loop {
-        sensor.begin(MPUdpsT::MPU6050Scale250DPS, MPURangeT::MPU6050Range2G);
+        // Retry on the next round if the sensor did not answer.
+        if sensor
+            .begin(MPUdpsT::MPU6050Scale250DPS, MPURangeT::MPU6050Range2G)
+            .is_err()
+        {
+            delay_ms(2000);
+            continue;
+        }
 
-        sensor.read_gyro();
-        //Print these values on screen using USART;
-        //The vec gyro_output stores the raw values of the gyroscope where gyro_output[0] is the x-axis, gyro_output[1] is the y-axis and gyro_output[2] is the z-axis output respectively.These raw values are then converted to degrees per second according to the scale given as input in `begin()` function.
+        if sensor.read_gyro().is_ok() {
+            //Print these values on screen using USART;
+            //The array gyro_output stores the raw values of the gyroscope where gyro_output[0] is the x-axis, gyro_output[1] is the y-axis and gyro_output[2] is the z-axis output respectively.These raw values are then converted to degrees per second according to the scale given as input in `begin()` function.
+        }
 
-        sensor.read_accel();
-        //Print these values on screen using USART;
-        //The vec accel_output stores the raw values of the accelerometer where accel_output[0] is the x-axis, accel_output[1] is the y-axis and accel_output[2] is the z-axis output respectively.These raw values are then converted to g's per second according to the scale given as input in `begin()` function.
+        if sensor.read_accel().is_ok() {
+            //Print these values on screen using USART;
+            //The array accel_output stores the raw values of the accelerometer where accel_output[0] is the x-axis, accel_output[1] is the y-axis and accel_output[2] is the z-axis output respectively.These raw values are then converted to g's per second according to the scale given as input in `begin()` function.
+        }
 
         // Waiting for 2 seconds.
         delay_ms(2000);
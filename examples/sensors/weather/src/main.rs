@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+#![deny(warnings)]
+
+// Crates included which are to be used for the weather station example.
+use rustduino::com::usart_initialize::{UsartNum, UsartObject};
+use rustduino::delay::Delay;
+use rustduino::hal::millis::{millis, millis_init};
+use rustduino::sensors::*;
+use rustduino::system::datalogger::CsvSink;
+use rustduino::system::weather::{WeatherSensors, WeatherStation};
+use rustduino::Result;
+
+// The sensors of the station, here only an AHT10.
+struct Sensors<'a> {
+    aht10: AHT10<&'a I2cBus, Delay>,
+}
+
+impl WeatherSensors for Sensors<'_> {
+    fn temperature(&mut self) -> Result<i32> {
+        Ok((self.aht10.temperature()? * 100.0) as i32)
+    }
+
+    fn humidity(&mut self) -> Result<u32> {
+        Ok((self.aht10.relative_humidity()? * 100.0) as u32)
+    }
+}
+
+#[no_mangle]
+fn main() {
+    // Take the I2C bus, the sensors share it through references.
+    let bus = match I2cBus::take() {
+        Ok(bus) => bus,
+        Err(_) => return,
+    };
+
+    // Give up if the sensor is missing or did not calibrate.
+    let aht10 = match AHT10::new(&bus, Delay) {
+        Ok(sensor) => sensor,
+        Err(_) => return,
+    };
+
+    // The observations are sent as CSV lines through USART0.
+    let mut usart = unsafe { UsartObject::new(UsartNum::Usart0) };
+    if unsafe { usart.begin() }.is_err() {
+        return;
+    }
+    let mut sink = CsvSink::new(|byte| {
+        let _ = usart.transmit_data(byte);
+    });
+    sink.header();
+
+    millis_init();
+
+    // One observation every minute.
+    let mut station = WeatherStation::new(Sensors { aht10 }, 60_000);
+
+    loop {
+        if let Some(observation) = station.poll(millis()) {
+            // Channels are numbered as the CHANNEL_ constants of the weather module.
+            observation.log(&mut sink);
+        }
+    }
+}
+
+// This function is called on panic.
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
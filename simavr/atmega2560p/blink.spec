@@ -0,0 +1,3 @@
+# Digital pin 13 is PB7, DDRB is at 0x24 and PORTB at 0x25.
+trace ddrb7 0x24 0x80 1
+trace portb7 0x25 0x80 4
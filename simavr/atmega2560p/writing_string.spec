@@ -0,0 +1 @@
+uart Hello World!
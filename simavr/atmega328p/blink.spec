@@ -0,0 +1,3 @@
+# Digital pin 13 is PB5, DDRB is at 0x24 and PORTB at 0x25.
+trace ddrb5 0x24 0x20 1
+trace portb5 0x25 0x20 4
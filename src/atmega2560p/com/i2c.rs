@@ -20,122 +20,210 @@
 //!  with the attached peripheral devices.
 //!* This has been implemented according to the chip ATMEGA2560P here.
 
-use crate::delay::delay_ms;
+use crate::atmega2560p::hal::interrupts;
+#[cfg(feature = "async")]
+use crate::executor::WaitForBits;
+use crate::{Error, Result};
 use bit_field::BitField;
+use core::arch::asm;
 use core::ptr::read_volatile;
-use fixed_slice_vec::FixedSliceVec;
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
 use volatile::Volatile;
 
 /// It will be used to control the I2C Twi
 /// implementations with the data registers assigned to
-/// it according to the data sheet.
+/// it according to the data sheet, from TWBR at 0xB8 to TWAMR at 0xBD.
 #[repr(C, packed)]
 pub struct Twi {
-    _twbr: Volatile<u8>,
-    twcr: Volatile<u8>,
+    twbr: Volatile<u8>,
     twsr: Volatile<u8>,
-    twdr: Volatile<u8>,
     _twar: Volatile<u8>,
+    twdr: Volatile<u8>,
+    twcr: Volatile<u8>,
     _twamr: Volatile<u8>,
 }
 
-// TWCR register's bits definitions
-const TWINT: u8 = 0;
-const TWEA: u8 = 1;
-const TWSTA: u8 = 2;
-const TWSTO: u8 = 3;
-const _TWWC: u8 = 4;
-const TWEN: u8 = 5;
-const _TWIE: u8 = 7;
+// TWCR register's bits definitions, counted from the least significant bit
+const TWINT: u8 = 7;
+const TWEA: u8 = 6;
+const TWSTA: u8 = 5;
+const TWSTO: u8 = 4;
+const TWEN: u8 = 2;
+
+// TWSR register's bits definitions
+const TWPS0: u8 = 0;
+const TWPS1: u8 = 1;
 
 static TWI_FREQUENCY: u32 = 100000;
 
 ///* This function reads the device clock freequency setup and provide
 ///  the details in form of boolean numbers and a 8 bit unsigned integer to
 ///  check the settings of the I2C carefully.
-///* If the clock freequency in very low then the function panics as the I2C protocol cannot be activated
-///  properly at such low freequencies.
+///* If the clock freequency is too low for the I2C protocol to work at
+///  `TWI_FREQUENCY` an error is returned.
 ///  # Returns
-///  * `a tuple` - Consisting of the following 3 Items -
+///  * `a Result` - Consisting of the following 3 Items -
 ///     * `a u8` - Which is a 2's exponent till 64 which defines the bandwidth rate for TWI I2C initialization.
 ///     * `a boolean` - Which denotes the TWPS bit 1 settings.
 ///     * `a boolean` - Which denotes the TWPS bit 2 settings.
-pub fn prescaler() -> (u8, bool, bool) {
-    if (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 1) >= 10
-        && (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 1) <= 0xFF
-    {
-        return (1, false, false);
-    } else if (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 4) >= 10
-        && (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 4) <= 0xFF
-    {
-        return (4, true, false);
-    } else if (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 16) >= 10
-        && (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 16) <= 0xFF
-    {
-        return (16, false, true);
-    } else if (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 64) >= 10
-        && (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 64) <= 0xFF
-    {
-        return (64, true, true);
+pub fn prescaler() -> Result<(u8, bool, bool)> {
+    if bit_rate(1).is_some() {
+        Ok((1, false, false))
+    } else if bit_rate(4).is_some() {
+        Ok((4, true, false))
+    } else if bit_rate(16).is_some() {
+        Ok((16, false, true))
+    } else if bit_rate(64).is_some() {
+        Ok((64, true, true))
     } else {
-        panic!("TWI_FREQUENCY too low!");
+        Err(Error::InvalidArgument)
     }
 }
 
-// TWCR register's bits definitions
-const TWPS1: u8 = 6;
-const TWPS0: u8 = 7;
+/// Calculates the value of TWBR for the given prescaler.
+/// # Arguments
+/// * `prescale` - a u32, the prescaler value selected by the TWPS bits.
+/// # Returns
+/// * `a Option<u8>` - The TWBR value, or `None` if it is out of the usable range.
+fn bit_rate(prescale: u32) -> Option<u8> {
+    let cycles = crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY;
+    if cycles < 16 {
+        return None;
+    }
+    let twbr = (cycles - 16) / (2 * prescale);
+    if twbr >= 10 && twbr <= 0xFF {
+        Some(twbr as u8)
+    } else {
+        None
+    }
+}
 
 // TWSR status codes
-// Master
 // (taken from avr-libc twi.h)
+// Master
 const START: u8 = 0x08;
 const REP_START: u8 = 0x10;
+const ARB_LOST: u8 = 0x38;
 
 // Master Transmitter
 const MT_SLA_ACK: u8 = 0x18;
-const _MT_SLA_NACK: u8 = 0x20;
+const MT_SLA_NACK: u8 = 0x20;
 const MT_DATA_ACK: u8 = 0x28;
-const _MT_DATA_NACK: u8 = 0x30;
-const _MT_ARB_LOST: u8 = 0x38;
+const MT_DATA_NACK: u8 = 0x30;
 
 // Master Receiver
-const _MR_ARB_LOST: u8 = 0x38;
 const MR_SLA_ACK: u8 = 0x40;
-const _MR_SLA_NACK: u8 = 0x48;
+const MR_SLA_NACK: u8 = 0x48;
 const MR_DATA_ACK: u8 = 0x50;
 const MR_DATA_NACK: u8 = 0x58;
 
-// Slave Transmitter
-const _ST_SLA_ACK: u8 = 0xA8;
-const _ST_ARB_LOST_SLA_ACK: u8 = 0xB0;
-const _ST_DATA_ACK: u8 = 0xB8;
-const _ST_DATA_NACK: u8 = 0xC0;
-const _ST_LAST_DATA: u8 = 0xC8;
-
-// Slave Receiver
-const _SR_SLA_ACK: u8 = 0x60;
-const _SR_ARB_LOST_SLA_ACK: u8 = 0x68;
-const _SR_GCALL_ACK: u8 = 0x70;
-const _SR_ARB_LOST_GCALL_ACK: u8 = 0x78;
-const _SR_DATA_ACK: u8 = 0x80;
-const _SR_DATA_NACK: u8 = 0x88;
-const _SR_GCALL_DATA_ACK: u8 = 0x90;
-const _SR_GCALL_DATA_NACK: u8 = 0x98;
-const _SR_STOP: u8 = 0xA0;
-
 // Miscellaneous
-const _NO_INFO: u8 = 0xF8;
-const _BUS_ERROR: u8 = 0x00;
+const BUS_ERROR: u8 = 0x00;
 
 // Defines and constants
-const _TWCR_CMD_MASK: u8 = 0x0F;
 const TWSR_STATUS_MASK: u8 = 0xF8;
 
-// return values
-const _I2C_OK: u8 = 0x00;
-const _I2C_ERROR_NODEV: u8 = 0x01;
-const I2C_TIMEOUT: u32 = 100;
+// Number of polls of TWINT before giving up.
+const I2C_TIMEOUT: u32 = 10000;
+
+// Milliseconds to wait for TWINT in the async functions.
+#[cfg(feature = "async")]
+const I2C_ASYNC_TIMEOUT: u32 = 10;
+
+// Set once the registers have been handed out by `Twi::take()`.
+static mut TAKEN: bool = false;
+
+/// A step of an I2C transfer, given to the trace handler of the master
+/// and returned by the bus sniffer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusEvent {
+    /// A start condition.
+    Start,
+    /// A repeated start condition.
+    RepeatedStart,
+    /// The address byte after a start, and whether a slave acknowledged it.
+    Address { address: u8, read: bool, ack: bool },
+    /// A data byte, and whether its receiver acknowledged it.
+    Data { value: u8, read: bool, ack: bool },
+    /// A stop condition.
+    Stop,
+    /// The TWI did not finish the operation in time.
+    Timeout,
+    /// Any other status of TWSR, like a lost arbitration or a bus error.
+    Status(u8),
+}
+
+// Handler given every step of the transfers of the master, if any.
+static mut TRACE: Option<fn(BusEvent)> = None;
+
+/// Sets a function called with every start, address, byte, acknowledge and
+/// stop of the transfers of the master, for debugging devices which refuse
+/// them. The handler runs in the middle of the transfers, so it should be quick.
+/// # Arguments
+/// * `handler` - an optional function, None to stop tracing.
+pub fn set_trace(handler: Option<fn(BusEvent)>) {
+    interrupts::free(|| unsafe { TRACE = handler });
+}
+
+/// A trace handler writing every step to the defmt log, see `set_trace`.
+/// # Arguments
+/// * `event` - a `BusEvent`, the step of the transfer.
+#[cfg(feature = "defmt")]
+pub fn log_event(event: BusEvent) {
+    defmt::info!("I2C {}", event);
+}
+
+/// Gives a step of a transfer to the trace handler.
+fn trace(event: BusEvent) {
+    if let Some(handler) = unsafe { TRACE } {
+        handler(event);
+    }
+}
+
+/// Converts the status of TWSR after an operation into the step of the transfer.
+/// # Arguments
+/// * `status` - a u8, the masked value of TWSR.
+/// * `data` - a u8, the value of TWDR.
+/// # Returns
+/// * `a BusEvent` - the step which ended.
+fn bus_event(status: u8, data: u8) -> BusEvent {
+    match status {
+        START => BusEvent::Start,
+        REP_START => BusEvent::RepeatedStart,
+        MT_SLA_ACK | MT_SLA_NACK | MR_SLA_ACK | MR_SLA_NACK => BusEvent::Address {
+            address: data >> 1,
+            read: data & 0x01 != 0,
+            ack: status == MT_SLA_ACK || status == MR_SLA_ACK,
+        },
+        MT_DATA_ACK | MT_DATA_NACK => BusEvent::Data {
+            value: data,
+            read: false,
+            ack: status == MT_DATA_ACK,
+        },
+        MR_DATA_ACK | MR_DATA_NACK => BusEvent::Data {
+            value: data,
+            read: true,
+            ack: status == MR_DATA_ACK,
+        },
+        _ => BusEvent::Status(status),
+    }
+}
+
+/// Converts an unexpected TWSR status into an error.
+/// # Arguments
+/// * `status` - a u8, the masked value of TWSR.
+/// # Returns
+/// * `a Error` - The reason of the failure.
+fn status_error(status: u8) -> Error {
+    match status {
+        MT_SLA_NACK | MR_SLA_NACK => Error::AddressNack,
+        MT_DATA_NACK => Error::DataNack,
+        ARB_LOST => Error::ArbitrationLost,
+        BUS_ERROR => Error::BusError,
+        _ => Error::BusBusy,
+    }
+}
 
 /// Sets DDRC to write direction.
 pub fn write_sda() {
@@ -157,263 +245,427 @@ pub fn read_sda() {
 
 impl Twi {
     /// Creates a pointer to TWI structure objects.
+    /// Unsafe as every call aliases the same registers, `take()` gives them only once.
     /// # Returns
     /// * `a reference to Twi struct object` - Which would be used to control the implementation.
-    pub fn new() -> &'static mut Self {
-        unsafe { &mut *(0xB8 as *mut Self) }
+    pub unsafe fn new() -> &'static mut Self {
+        &mut *(0xB8 as *mut Self)
     }
 
-    /// Waits for the TWI bus to be ready.
+    /// Gives the TWI registers only the first time it is called,
+    /// so that the bus has a single owner.
     /// # Returns
-    /// * `a boolean` - Which is true if the TWI is ready, false otherwise.
-    pub fn wait_to_complete(&mut self, start: u8) -> bool {
+    /// * `a Option` - The reference to the Twi struct object, or `None` if it was already taken.
+    pub fn take() -> Option<&'static mut Self> {
+        interrupts::free(|| unsafe {
+            if TAKEN {
+                None
+            } else {
+                TAKEN = true;
+                Some(&mut *(0xB8 as *mut Self))
+            }
+        })
+    }
+
+    /// Waits for the current operation of the TWI to complete.
+    /// Times out if TWINT is not set after `I2C_TIMEOUT` polls.
+    /// # Arguments
+    /// * `operation` - a u8, the status expected in TWSR after the operation.
+    /// # Returns
+    /// * `a Result` - Which is `Ok` if the expected status was reached.
+    pub fn wait_to_complete(&mut self, operation: u8) -> Result<()> {
         let mut i: u32 = 0;
-        //Waiting for TWINT flag set.
-        //This indicates that start condition has been transmitted.
-        while !self.twcr.read().get_bit(TWINT) || i <= I2C_TIMEOUT {
+        // Waiting for TWINT flag set.
+        while !self.twcr.read().get_bit(TWINT) {
+            if i >= I2C_TIMEOUT {
+                #[cfg(feature = "defmt")]
+                defmt::warn!("I2C timeout, expected status {=u8:#x}", operation);
+                trace(BusEvent::Timeout);
+                return Err(Error::Timeout);
+            }
             unsafe {
-                llvm_asm!("nop");
+                asm!("nop");
             }
             i += 1;
         }
-        // if TWSR_STATUS_MASK is different from start, error.
-        if self.twsr.read() & TWSR_STATUS_MASK != start || i >= I2C_TIMEOUT {
-            return false;
+
+        let status = self.twsr.read() & TWSR_STATUS_MASK;
+        trace(bus_event(status, self.twdr.read()));
+        if status == operation {
+            Ok(())
         } else {
-            return true;
+            #[cfg(feature = "defmt")]
+            defmt::debug!("I2C status {=u8:#x}, expected {=u8:#x}", status, operation);
+            Err(status_error(status))
         }
     }
 
-    /// Initiates the TWI Bus.
-    pub fn init(&mut self) {
+    /// Initiates the TWI bus at `TWI_FREQUENCY`.
+    /// # Returns
+    /// * `a Result` - Which is an error if the clock is too slow for I2C.
+    pub fn init(&mut self) -> Result<()> {
+        let (prescale, twps0, twps1) = prescaler()?;
+        let twbr = match bit_rate(prescale as u32) {
+            Some(twbr) => twbr,
+            None => return Err(Error::InvalidArgument),
+        };
         self.twsr.update(|sr| {
-            sr.set_bit(TWPS0, prescaler().1);
-            sr.set_bit(TWPS1, prescaler().2);
+            sr.set_bit(TWPS0, twps0);
+            sr.set_bit(TWPS1, twps1);
         });
-        self.twcr.update(|cr| {
-            cr.set_bit(TWEN, true);
-        })
+        self.twbr.write(twbr);
+        self.twcr.write(1 << TWEN);
+        Ok(())
     }
 
     /// Sends a Start Signal for TWI.
     /// # Returns
-    /// * `a boolean` - Which is true if process is successful, false otherwise.
-    pub fn start(&mut self) -> bool {
+    /// * `a Result` - Which is `Ok` if the bus was taken.
+    pub fn start(&mut self) -> Result<()> {
         write_sda();
-        self.twcr.update(|x| {
-            // TWCR: Enable TWI module
-            x.set_bit(TWSTA, true);
-            x.set_bit(TWINT, true);
-            x.set_bit(TWEN, true);
-        });
-        return self.wait_to_complete(START);
+        self.twcr.write((1 << TWINT) | (1 << TWSTA) | (1 << TWEN));
+        self.wait_to_complete(START)
     }
 
-    /// Stops the TWI Bus.
-    pub fn stop(&mut self) {
-        self.twcr.update(|x| {
-            // TWCR: Disable TWI module
-            x.set_bit(TWSTO, true);
-            x.set_bit(TWINT, true);
-            x.set_bit(TWEN, true);
-        });
+    /// Sends a Repeated Start Signal for TWI.
+    /// # Returns
+    /// * `a Result` - Which is `Ok` if the repeated start was sent.
+    pub fn rep_start(&mut self) -> Result<()> {
+        self.twcr.write((1 << TWINT) | (1 << TWSTA) | (1 << TWEN));
+        self.wait_to_complete(REP_START)
     }
 
-    /// Sends the Repeated Start Signal.
-    /// # Returns
-    /// * `a boolean` - Which is true if process is successful, false otherwise.
-    pub fn rep_start(&mut self) -> bool {
-        self.twcr.update(|x| {
-            // TWCR: Enable TWI module
-            x.set_bit(TWSTA, true);
-            x.set_bit(TWINT, true);
-            x.set_bit(TWEN, true);
-        });
-        return self.wait_to_complete(REP_START);
+    /// Sends a Stop Signal and releases the TWI bus.
+    pub fn stop(&mut self) {
+        self.twcr.write((1 << TWINT) | (1 << TWSTO) | (1 << TWEN));
+        let mut i: u32 = 0;
+        // TWSTO is cleared by hardware once the stop is on the bus.
+        while self.twcr.read().get_bit(TWSTO) && i < I2C_TIMEOUT {
+            i += 1;
+        }
+        trace(BusEvent::Stop);
     }
 
-    /// Loads the address of the slave device on SDA.
+    /// Sends the address of a slave in write mode.
     /// # Arguments
-    /// * `address` - It is passed into the function and  is a seven bit integer used for location of implementation.
+    /// * `address` - a u8, the seven bit address of the slave.
     /// # Returns
-    /// * `a boolean` - Which is true if the checking process is sucessful otherwise false.
-    pub fn address_write(&mut self, address: u8) -> bool {
+    /// * `a Result` - Which is an `AddressNack` error if no slave answered.
+    pub fn address_write(&mut self, address: u8) -> Result<()> {
         self.twdr.write(address << 1);
-        self.twcr.update(|x| {
-            // TWCR: Enables TWI to pass address
-            x.set_bit(TWINT, true);
-            x.set_bit(TWEN, true);
-        });
-        return self.wait_to_complete(MT_SLA_ACK);
+        self.twcr.write((1 << TWINT) | (1 << TWEN));
+        self.wait_to_complete(MT_SLA_ACK)
     }
 
-    /// Loads the address of the slave device from SDA for other implementations.
+    /// Sends the address of a slave in read mode.
     /// # Arguments
-    /// * `address` - It is passed into the function and  is a seven bit integer used for location of implementation.
+    /// * `address` - a u8, the seven bit address of the slave.
     /// # Returns
-    /// * `a boolean` - Which is true if the checking process is sucessful otherwise false.
-    pub fn address_read(&mut self, address: u8) -> bool {
+    /// * `a Result` - Which is an `AddressNack` error if no slave answered.
+    pub fn address_read(&mut self, address: u8) -> Result<()> {
         self.twdr.write(address << 1 | 0x01);
-        self.twcr.update(|x| {
-            x.set_bit(TWINT, true);
-            x.set_bit(TWEN, true);
-        });
-        return self.wait_to_complete(MR_SLA_ACK);
+        self.twcr.write((1 << TWINT) | (1 << TWEN));
+        self.wait_to_complete(MR_SLA_ACK)
     }
 
-    /// Appends the value in TWCR to the given vector.
+    /// Writes one byte of data to the Slave.
+    /// Need to set address first.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which will be filled with the data read.
+    /// * `data` - a u8, the byte which is to be written.
     /// # Returns
-    /// * `a boolean` - Which is true if process is completed otherwise false.
-    pub fn read_ack(&mut self, data: &mut FixedSliceVec<u8>) -> bool {
-        self.twcr.update(|x| {
-            x.set_bit(TWINT, true);
-            x.set_bit(TWEA, true);
-            x.set_bit(TWEN, true);
-        });
-        data.push(self.twdr.read());
-        return self.wait_to_complete(MR_DATA_ACK);
+    /// * `a Result` - Which is a `DataNack` error if the slave refused the byte.
+    pub fn write(&mut self, data: u8) -> Result<()> {
+        self.twdr.write(data);
+        self.twcr.write((1 << TWINT) | (1 << TWEN));
+        self.wait_to_complete(MT_DATA_ACK)
     }
 
-    /// Appends the value in TWCR to the given vector.
+    /// Writes consecutive bytes of data to the Slave.
+    /// Need to set address first.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which is filled with the data read.
-    /// * `length` - a usize integer, which is the theoretically set value of length of the sliced vector `data`.
+    /// * `data` - a slice of u8, the bytes to be written.
     /// # Returns
-    /// * `a usize integer` - Which gives the critical length of the data bus above which no data exists.
-    pub fn read_ack_burst(&mut self, data: &mut FixedSliceVec<u8>, length: usize) -> usize {
-        let mut x: usize = 0;
-        while x < length {
-            if !self.read_ack(data) {
-                break;
-            }
-            x += 1;
+    /// * `a Result` - Which is an error as soon as one byte fails.
+    pub fn write_burst(&mut self, data: &[u8]) -> Result<()> {
+        for byte in data {
+            self.write(*byte)?;
         }
-        return x + 1;
+        Ok(())
     }
 
-    /// Writes one byte of data to the Slave.
-    /// # Arguments
-    /// * `data` - a u8, the integer which is to be written.
+    /// Reads one byte from the Slave and acknowledges it,
+    /// so that the slave sends another one.
     /// # Returns
-    /// * `a boolean` - Whcih is true if process is successful otherwise false.
-    pub fn write(&mut self, data: u8) -> bool {
-        delay_ms(1);
-        self.twdr.write(data);
-        self.twcr.update(|x| {
-            // TWCR: Enables TWI module to pass data to slave.
-            x.set_bit(TWINT, true);
-            x.set_bit(TWEN, true);
-        });
-        return self.wait_to_complete(MT_DATA_ACK);
+    /// * `a Result<u8>` - The byte read.
+    pub fn read_ack(&mut self) -> Result<u8> {
+        self.twcr.write((1 << TWINT) | (1 << TWEA) | (1 << TWEN));
+        self.wait_to_complete(MR_DATA_ACK)?;
+        Ok(self.twdr.read())
     }
 
-    /// Writes continuous bytes on the TWCR.
+    /// Reads one byte from the Slave without acknowledging it,
+    /// which ends the transfer from the slave.
+    /// # Returns
+    /// * `a Result<u8>` - The byte read.
+    pub fn read_nack(&mut self) -> Result<u8> {
+        self.twcr.write((1 << TWINT) | (1 << TWEN));
+        self.wait_to_complete(MR_DATA_NACK)?;
+        Ok(self.twdr.read())
+    }
+
+    /// Reads consecutive bytes from the Slave.
+    /// Every byte is acknowledged except the last one.
+    /// Need to set address first.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which is filled with the data read.
+    /// * `data` - a mutable slice of u8, which is filled with the data read.
     /// # Returns
-    /// * `a usize integer` - Which gives the critical length of the data bus above which no data could be written.
-    pub fn write_burst(&mut self, data: &FixedSliceVec<u8>) -> usize {
-        let mut x: usize = 0;
-        while x < data.len() {
-            if !self.write(data[x]) {
-                break;
-            }
-            x += 1;
+    /// * `a Result` - Which is an error as soon as one byte fails.
+    pub fn read_burst(&mut self, data: &mut [u8]) -> Result<()> {
+        let len = data.len();
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = if i + 1 < len {
+                self.read_ack()?
+            } else {
+                self.read_nack()?
+            };
         }
-        return x + 1;
+        Ok(())
     }
 
-    /// Reads the nack value data in TWCR to the given vector.
+    /// Writes consecutive Data bytes to slave.
+    /// The bus is always released at the end, even on failure.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which will be filled with the data read.
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `data` - a slice of u8, the bytes to be written.
     /// # Returns
-    /// * `a boolean` - Which is true if process is completed otherwise false.
-    pub fn read_nack(&mut self, data: &mut FixedSliceVec<u8>) -> bool {
-        self.twcr.update(|x| {
-            x.set_bit(TWINT, true);
-            x.set_bit(TWEN, true);
-        });
-        data.push(self.twdr.read());
-        return self.wait_to_complete(MR_DATA_NACK);
+    /// * `a Result` - Which is an error if any of the steps, i.e start, setting address or writing fails.
+    pub fn write_to_slave(&mut self, address: u8, data: &[u8]) -> Result<()> {
+        let result = self.transfer_write(address, data);
+        self.stop();
+        result
     }
 
-    /// Reads the nack value data in TWCR to the given vector.
+    /// Reads consecutive Data bytes from slave.
+    /// The bus is always released at the end, even on failure.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which is filled with the data read.
-    /// * `length` - a usize integer, which is the theoretically set value of length of the sliced vector `data`.
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `data` - a mutable slice of u8, filled with `data.len()` bytes from the slave.
     /// # Returns
-    /// * `a usize integer` - Which gives the critical length of the data bus above which no data exists.
-    pub fn read_nack_burst(&mut self, data: &mut FixedSliceVec<u8>, length: usize) -> usize {
-        let mut x: usize = 0;
+    /// * `a Result` - Which is an error if any of the steps, i.e start, reading address or reading data fails.
+    pub fn read_from_slave(&mut self, address: u8, data: &mut [u8]) -> Result<()> {
+        let result = self.transfer_read(address, data);
+        self.stop();
+        result
+    }
 
-        while x < length {
-            if !self.read_nack(data) {
-                break;
-            }
-            x += 1;
-        }
-        return x + 1;
+    /// Writes some bytes to a slave and then reads its answer after a repeated start.
+    /// This is the usual way of reading the registers of a sensor.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `bytes` - a slice of u8, the bytes to be written, for example a register number.
+    /// * `buffer` - a mutable slice of u8, filled with the bytes read.
+    /// # Returns
+    /// * `a Result` - Which is an error if any of the steps fails.
+    pub fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<()> {
+        let result = self.transfer_write(address, bytes).and_then(|_| {
+            self.rep_start()?;
+            self.address_read(address)?;
+            self.read_burst(buffer)
+        });
+        self.stop();
+        result
     }
 
-    /// Reads consecutive Data bytes from slave.
-    /// Sends a stop signal if either of the steps fail or reading is successful.
+    /// Runs a sequence of reads and writes with one slave, as described by
+    /// `embedded_hal::i2c::Operation`. A repeated start is sent whenever the
+    /// direction changes and consecutive reads are acknowledged as one read.
+    /// The bus is always released at the end, even on failure.
     /// # Arguments
-    /// * `address` - a u8, consisting the target address of the read implementation.
-    /// * `length` - a usize integer, showing the number of bytes to read.
-    /// * `data` - a sliced vector consisting of u8, where the data will be stored after reading.
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `operations` - a mutable slice of `Operation`, the reads and writes to be done.
     /// # Returns
-    /// * `a boolean` - Which is true if process is completed otherwise false and aborts the process if any of the steps, i.e
-    /// start, reading address, reading ACK or reading NACK fails.
-    pub fn read_from_slave(
-        &mut self,
-        address: u8,
-        length: usize,
-        data: &mut FixedSliceVec<u8>,
-    ) -> bool {
-        delay_ms(1);
+    /// * `a Result` - Which is an error as soon as one step fails.
+    pub fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        let result = self.transfer_operations(address, operations);
+        self.stop();
+        result
+    }
+
+    fn transfer_write(&mut self, address: u8, data: &[u8]) -> Result<()> {
+        self.start()?;
+        self.address_write(address)?;
+        self.write_burst(data)
+    }
+
+    fn transfer_read(&mut self, address: u8, data: &mut [u8]) -> Result<()> {
         read_sda();
+        self.start()?;
+        self.address_read(address)?;
+        self.read_burst(data)
+    }
 
-        if !self.start() {
-            return false;
-        }
-        if !self.address_read(address) {
-            self.stop();
-            return false;
-        }
-        if length > 1 && self.read_ack_burst(data, length - 1) != length - 1 {
-            self.stop();
-            return false;
+    fn transfer_operations(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        // Direction of the previous operation, `Some(true)` for a read.
+        let mut previous: Option<bool> = None;
+        let count = operations.len();
+        for i in 0..count {
+            let read_follows = match operations.get(i + 1) {
+                Some(Operation::Read(_)) => true,
+                _ => false,
+            };
+            match &mut operations[i] {
+                Operation::Write(bytes) => {
+                    if previous != Some(false) {
+                        self.start_or_repeat(previous.is_some())?;
+                        self.address_write(address)?;
+                    }
+                    self.write_burst(bytes)?;
+                    previous = Some(false);
+                }
+                Operation::Read(buffer) => {
+                    if previous != Some(true) {
+                        self.start_or_repeat(previous.is_some())?;
+                        self.address_read(address)?;
+                    }
+                    // Only the last byte of the whole read is not acknowledged.
+                    let len = buffer.len();
+                    for (j, byte) in buffer.iter_mut().enumerate() {
+                        *byte = if read_follows || j + 1 < len {
+                            self.read_ack()?
+                        } else {
+                            self.read_nack()?
+                        };
+                    }
+                    previous = Some(true);
+                }
+            }
         }
-        if length > 0 && self.read_nack(data) {
-            self.stop();
-            return false;
+        Ok(())
+    }
+
+    fn start_or_repeat(&mut self, repeat: bool) -> Result<()> {
+        if repeat {
+            self.rep_start()
+        } else {
+            self.start()
         }
+    }
+}
 
-        self.stop();
+// Implementation of the embedded-hal I2C trait, so that platform agnostic
+// drivers can use the TWI. The bus must be initiated with `init()` first.
+impl ErrorType for Twi {
+    type Error = Error;
+}
 
-        return true;
+impl I2c for Twi {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        Twi::transaction(self, address, operations)
     }
+}
 
-    /// Writes consecutive Data bytes to slave.
-    /// Also sends a stop signal if either of the steps fail or writing is successful.
+// Implementation of the embedded-hal-async I2C trait. Every byte is started
+// as in the blocking functions and the end of it is awaited, so that the
+// other tasks of the executor run during the transfer.
+#[cfg(feature = "async")]
+impl Twi {
+    /// Writes TWCR to start an operation and waits for its end.
+    /// # Arguments
+    /// * `twcr` - a u8, the value of TWCR starting the operation.
+    /// * `operation` - a u8, the status expected in TWSR after the operation.
     /// # Returns
-    /// * `a boolean` - Which is true if process is completed and aborts if any of the steps, i.e start, setting address or writing fails.
-    pub fn write_to_slave(&mut self, address: u8, data: &FixedSliceVec<u8>) -> bool {
-        delay_ms(1);
-        if !self.start() {
-            return false;
-        }
-        if !self.address_write(address) {
-            return false;
+    /// * `a Result` - Which is an error if the status is not the expected one.
+    async fn step_async(&mut self, twcr: u8, operation: u8) -> Result<()> {
+        self.twcr.write(twcr);
+        let register = core::ptr::addr_of!(self.twcr) as *const u8;
+        unsafe { WaitForBits::new(register, 1 << TWINT, I2C_ASYNC_TIMEOUT) }.await?;
+
+        let status = self.twsr.read() & TWSR_STATUS_MASK;
+        trace(bus_event(status, self.twdr.read()));
+        if status == operation {
+            Ok(())
+        } else {
+            Err(status_error(status))
         }
+    }
 
-        if self.write_burst(data) != data.len() {
-            self.stop();
-            return false;
-        }
+    /// Same as `transaction`, but awaits the end of every byte.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `operations` - a mutable slice of `Operation`, the reads and writes to be done.
+    /// # Returns
+    /// * `a Result` - Which is an error as soon as one step fails.
+    pub async fn transaction_async(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<()> {
+        let result = self.transfer_operations_async(address, operations).await;
         self.stop();
-        return true;
+        result
+    }
+
+    async fn transfer_operations_async(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<()> {
+        let mut previous: Option<bool> = None;
+        let count = operations.len();
+        for i in 0..count {
+            let read_follows = match operations.get(i + 1) {
+                Some(Operation::Read(_)) => true,
+                _ => false,
+            };
+            let read = match operations[i] {
+                Operation::Read(_) => true,
+                Operation::Write(_) => false,
+            };
+            if previous != Some(read) {
+                let start = if previous.is_some() { REP_START } else { START };
+                self.step_async((1 << TWINT) | (1 << TWSTA) | (1 << TWEN), start)
+                    .await?;
+                if read {
+                    self.twdr.write(address << 1 | 0x01);
+                    self.step_async((1 << TWINT) | (1 << TWEN), MR_SLA_ACK)
+                        .await?;
+                } else {
+                    self.twdr.write(address << 1);
+                    self.step_async((1 << TWINT) | (1 << TWEN), MT_SLA_ACK)
+                        .await?;
+                }
+            }
+            match &mut operations[i] {
+                Operation::Write(bytes) => {
+                    for byte in bytes.iter() {
+                        self.twdr.write(*byte);
+                        self.step_async((1 << TWINT) | (1 << TWEN), MT_DATA_ACK)
+                            .await?;
+                    }
+                }
+                Operation::Read(buffer) => {
+                    let len = buffer.len();
+                    for (j, byte) in buffer.iter_mut().enumerate() {
+                        if read_follows || j + 1 < len {
+                            self.step_async((1 << TWINT) | (1 << TWEA) | (1 << TWEN), MR_DATA_ACK)
+                                .await?;
+                        } else {
+                            self.step_async((1 << TWINT) | (1 << TWEN), MR_DATA_NACK)
+                                .await?;
+                        }
+                        *byte = self.twdr.read();
+                    }
+                }
+            }
+            previous = Some(read);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c for Twi {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        self.transaction_async(address, operations).await
     }
 }
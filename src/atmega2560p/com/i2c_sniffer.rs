@@ -0,0 +1,173 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A passive sniffer of the I2C bus, for debugging third party sensors which
+//! refuse transfers for no visible reason. The TWI is switched off and SCL and
+//! SDA, PD0 and PD1 (digital pins 21 and 20), are sampled as plain inputs without pull ups, so the board
+//! only listens to a transfer between another master and the sensor, and
+//! decodes its starts, addresses, bytes, acknowledges and stops.
+//! The pins are polled with interrupts off, which keeps up with a 100 kHz bus
+//! on a 16 MHz board, not with a 400 kHz one.
+
+use crate::atmega2560p::com::i2c::BusEvent;
+use crate::atmega2560p::hal::interrupts;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Address of the TWI control register, cleared to switch the TWI off.
+const TWCR: *mut u8 = 0xBC as *mut u8;
+
+/// Addresses of the registers of the port holding SCL and SDA.
+const PIN: *mut u8 = 0x29 as *mut u8;
+const DDR: *mut u8 = 0x2A as *mut u8;
+const PORT: *mut u8 = 0x2B as *mut u8;
+/// Bits of SCL and SDA in the port.
+const SCL: u8 = 1 << 0;
+const SDA: u8 = 1 << 1;
+
+/// Decodes the levels of SCL and SDA, sampled faster than the bus clock, into bus events.
+/// # Elements
+/// * `scl` - a boolean, the last level of SCL.
+/// * `sda` - a boolean, the last level of SDA.
+/// * `active` - a boolean, true between a start and a stop.
+/// * `address` - a boolean, true while the first byte after a start is received.
+/// * `read` - a boolean, the direction given by the last address byte.
+/// * `bits` - a u8, the number of bits of the current byte received, acknowledge included.
+/// * `shift` - a u16, the bits of the current byte received.
+#[derive(Clone, Copy, Debug)]
+pub struct Sniffer {
+    scl: bool,
+    sda: bool,
+    active: bool,
+    address: bool,
+    read: bool,
+    bits: u8,
+    shift: u16,
+}
+
+impl Sniffer {
+    /// Returns a decoder for an idle bus, both lines high.
+    pub fn new() -> Sniffer {
+        Sniffer {
+            scl: true,
+            sda: true,
+            active: false,
+            address: false,
+            read: false,
+            bits: 0,
+            shift: 0,
+        }
+    }
+
+    /// Takes a sample of both lines.
+    /// A change of SDA while SCL is high is a start or a stop, the other bits
+    /// are taken at the rising edges of SCL, the ninth being the acknowledge.
+    /// # Arguments
+    /// * `scl` - a boolean, the level of SCL.
+    /// * `sda` - a boolean, the level of SDA.
+    /// # Returns
+    /// * `a Option<BusEvent>` - the event ended by this sample, if any.
+    pub fn sample(&mut self, scl: bool, sda: bool) -> Option<BusEvent> {
+        let (last_scl, last_sda) = (self.scl, self.sda);
+        self.scl = scl;
+        self.sda = sda;
+
+        if scl && last_scl && sda != last_sda {
+            self.bits = 0;
+            self.shift = 0;
+            if sda {
+                self.active = false;
+                return Some(BusEvent::Stop);
+            }
+            let repeated = self.active;
+            self.active = true;
+            self.address = true;
+            return Some(if repeated {
+                BusEvent::RepeatedStart
+            } else {
+                BusEvent::Start
+            });
+        }
+
+        if !scl || last_scl || !self.active {
+            return None;
+        }
+        self.shift = (self.shift << 1) | sda as u16;
+        self.bits += 1;
+        if self.bits < 9 {
+            return None;
+        }
+
+        let value = (self.shift >> 1) as u8;
+        let ack = self.shift & 0x01 == 0;
+        self.bits = 0;
+        self.shift = 0;
+        if self.address {
+            self.address = false;
+            self.read = value & 0x01 != 0;
+            Some(BusEvent::Address {
+                address: value >> 1,
+                read: self.read,
+                ack,
+            })
+        } else {
+            Some(BusEvent::Data {
+                value,
+                read: self.read,
+                ack,
+            })
+        }
+    }
+}
+
+impl Default for Sniffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Switches the TWI off and records the events of the bus until the buffer is
+/// full or the lines stay still for `idle` polls. Interrupts are off meanwhile.
+/// The TWI has to be initialised again before the board uses the bus itself.
+/// # Arguments
+/// * `events` - a mutable slice of `BusEvent`, receiving the events.
+/// * `idle` - a u32, the number of polls without any change ending the capture.
+/// # Returns
+/// * `a usize` - the number of events recorded.
+pub fn sniff(events: &mut [BusEvent], idle: u32) -> usize {
+    interrupts::free(|| unsafe {
+        write_volatile(TWCR, 0);
+        write_volatile(DDR, read_volatile(DDR) & !(SCL | SDA));
+        write_volatile(PORT, read_volatile(PORT) & !(SCL | SDA));
+
+        let mut sniffer = Sniffer::new();
+        let mut last = SCL | SDA;
+        let (mut count, mut still) = (0, 0);
+        while count < events.len() && still < idle {
+            let lines = read_volatile(PIN) & (SCL | SDA);
+            if lines == last {
+                still += 1;
+                continue;
+            }
+            last = lines;
+            still = 0;
+            if let Some(event) = sniffer.sample(lines & SCL != 0, lines & SDA != 0) {
+                events[count] = event;
+                count += 1;
+            }
+        }
+        count
+    })
+}
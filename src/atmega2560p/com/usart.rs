@@ -26,6 +26,7 @@ use crate::atmega2560p::com::usart_initialize::UsartObject;
 use crate::atmega2560p::com::usart_initialize::{
     UsartDataSize, UsartModes, UsartNum, UsartParity, UsartPolarity, UsartStop,
 };
+use crate::Result;
 
 // Standard datatypes to be used
 use core::{f64, u32};
@@ -55,29 +56,30 @@ impl Serial {
 
 impl UsartObject {
     /// Can be use to initialize a USART with default settings.
-    pub unsafe fn begin(&mut self) {
+    pub unsafe fn begin(&mut self) -> Result<()> {
         self.disable();
         self.transmit_enable();
         self.recieve_enable();
-        self.initialize(MODE, BAUD, STOP, SIZE, PARITY);
+        self.initialize(MODE, BAUD, STOP, SIZE, PARITY)
     }
 
     /// Can be use to initialize with given baud rate and remaining settings will be set to default.
     /// # Arguments
     /// * `baud1` - a i64, the baud rate of USART the user wants to set.
-    pub unsafe fn begin_set_baud(&mut self, baud1: i64) {
+    pub unsafe fn begin_set_baud(&mut self, baud1: i64) -> Result<()> {
         self.disable();
         self.transmit_enable();
         self.recieve_enable();
-        self.initialize(MODE, baud1, STOP, SIZE, PARITY);
+        self.initialize(MODE, baud1, STOP, SIZE, PARITY)
     }
 
     /// Can be used to stop the functioning of initialized USART.
-    pub unsafe fn end(&mut self) {
-        self.transmit_disable();
+    pub unsafe fn end(&mut self) -> Result<()> {
+        let result = self.transmit_disable();
         self.recieve_disable();
         self.reset();
         self.enable();
+        result
     }
 }
 
@@ -88,16 +90,18 @@ impl UsartObject {
 /// This will be used to transmit string data.
 /// # Arguments
 /// * `data` - a string object, which is to be transmitted using USART.
-pub fn println_string(data: &'static str) {
+pub fn println_string(data: &'static str) -> Result<()> {
     unsafe {
         let mut u: UsartObject = UsartObject::new(NUM);
         u.disable();
         u.transmit_enable();
-        u.initialize(MODE, BAUD, STOP, SIZE, PARITY);
-        u.write_string(data);
-        u.transmit_disable();
+        let result = u
+            .initialize(MODE, BAUD, STOP, SIZE, PARITY)
+            .and_then(|_| u.write_string(data))
+            .and_then(|_| u.transmit_disable());
         u.reset();
         u.enable();
+        result
     }
 }
 
@@ -108,16 +112,18 @@ pub fn println_string(data: &'static str) {
 /// This will be used to transmit integer data.
 /// # Arguments
 /// * `data` - a u32, which is to be transmitted using USART.
-pub fn println_integer(data: u32) {
+pub fn println_integer(data: u32) -> Result<()> {
     unsafe {
         let mut u: UsartObject = UsartObject::new(NUM);
         u.disable();
         u.transmit_enable();
-        u.initialize(MODE, BAUD, STOP, SIZE, PARITY);
-        u.write_integer(data);
-        u.transmit_disable();
+        let result = u
+            .initialize(MODE, BAUD, STOP, SIZE, PARITY)
+            .and_then(|_| u.write_integer(data))
+            .and_then(|_| u.transmit_disable());
         u.reset();
         u.enable();
+        result
     }
 }
 
@@ -129,16 +135,18 @@ pub fn println_integer(data: u32) {
 /// # Arguments
 /// * `data` - a f32, which is to be transmitted using USART.
 /// * `precision` - a u32, the number of decimal precision required in the transmission.
-pub fn println_float(data: f64, precision: u32) {
+pub fn println_float(data: f64, precision: u32) -> Result<()> {
     unsafe {
         let mut u: UsartObject = UsartObject::new(NUM);
         u.disable();
         u.transmit_enable();
-        u.initialize(MODE, BAUD, STOP, SIZE, PARITY);
-        u.write_float(data, precision);
-        u.transmit_disable();
+        let result = u
+            .initialize(MODE, BAUD, STOP, SIZE, PARITY)
+            .and_then(|_| u.write_float(data, precision))
+            .and_then(|_| u.transmit_disable());
         u.reset();
         u.enable();
+        result
     }
 }
 
@@ -149,16 +157,18 @@ pub fn println_float(data: f64, precision: u32) {
 /// # Arguments
 /// * `data` - a string object, which is to be transmitted using USART.
 /// * `baud1` - a i64, the baud rate of USART the user wants to set.
-pub fn println_set_baud(data: &'static str, baud1: i64) {
+pub fn println_set_baud(data: &'static str, baud1: i64) -> Result<()> {
     unsafe {
         let mut u: UsartObject = UsartObject::new(NUM);
         u.disable();
         u.transmit_enable();
-        u.initialize(MODE, baud1, STOP, SIZE, PARITY);
-        u.write_string(data);
-        u.transmit_disable();
+        let result = u
+            .initialize(MODE, baud1, STOP, SIZE, PARITY)
+            .and_then(|_| u.write_string(data))
+            .and_then(|_| u.transmit_disable());
         u.reset();
         u.enable();
+        result
     }
 }
 
@@ -176,16 +186,18 @@ pub fn println_set_frame(
     size1: UsartDataSize,
     parity1: UsartParity,
     stop1: UsartStop,
-) {
+) -> Result<()> {
     unsafe {
         let mut u: UsartObject = UsartObject::new(NUM);
         u.disable();
         u.transmit_enable();
-        u.initialize(MODE, BAUD, stop1, size1, parity1);
-        u.write_string(data);
-        u.transmit_disable();
+        let result = u
+            .initialize(MODE, BAUD, stop1, size1, parity1)
+            .and_then(|_| u.write_string(data))
+            .and_then(|_| u.transmit_disable());
         u.reset();
         u.enable();
+        result
     }
 }
 
@@ -209,15 +221,17 @@ pub fn println_detail(
     size1: UsartDataSize,
     parity1: UsartParity,
     stop1: UsartStop,
-) {
+) -> Result<()> {
     unsafe {
         let mut u: UsartObject = UsartObject::new(num1);
         u.disable();
         u.transmit_enable();
-        u.initialize(mode1, baud1, stop1, size1, parity1);
-        u.write_string(data);
-        u.transmit_disable();
+        let result = u
+            .initialize(mode1, baud1, stop1, size1, parity1)
+            .and_then(|_| u.write_string(data))
+            .and_then(|_| u.transmit_disable());
         u.reset();
         u.enable();
+        result
     }
 }
@@ -0,0 +1,142 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Interrupt driven reception for all the four USARTs.
+//! Once enabled, every recieved byte is moved from the data register into a
+//! buffer by the recieve complete interrupt, so no data is lost while the
+//! main loop is busy or the MCU is sleeping.
+//! The module needs the `usart-buffered` feature, because its interrupt
+//! service routines take the recieve complete vectors of all the USARTs and
+//! its buffers take 256 bytes of RAM as soon as it is built.
+//! See the section 22.7 of ATMEGA2560P datasheet.
+
+use crate::atmega2560p::com::usart_initialize::UsartObject;
+#[cfg(target_arch = "avr")]
+use crate::atmega2560p::com::usart_initialize::{Usart, UsartNum};
+use crate::collections::Queue;
+#[cfg(target_arch = "avr")]
+use crate::system::events::Event;
+use bit_field::BitField;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Size of every reception buffer, each holds one byte less than this.
+pub const RX_BUFFER_SIZE: usize = 64;
+
+/// Bytes recieved by the interrupts and not read yet, one buffer per USART.
+static RX_BUFFERS: [Queue<u8, RX_BUFFER_SIZE>; 4] =
+    [Queue::new(), Queue::new(), Queue::new(), Queue::new()];
+
+/// If set for a USART, its recieved bytes are published on the event bus instead of being buffered.
+static PUBLISH_EVENTS: [AtomicBool; 4] = [
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+];
+
+impl UsartObject {
+    /// Returns the reception buffer of this USART.
+    fn rx_buffer(&self) -> &'static Queue<u8, RX_BUFFER_SIZE> {
+        &RX_BUFFERS[self.name as usize]
+    }
+
+    /// Enables the reciever along with the recieve complete interrupt,
+    /// so that recieved bytes are buffered. Global interrupts must be enabled.
+    pub fn recieve_buffered_enable(&mut self) {
+        unsafe {
+            self.recieve_enable();
+            (*self.usart).ucsrb.update(|ucsrb| {
+                ucsrb.set_bit(7, true);
+            });
+        }
+    }
+
+    /// Enables the reciever along with the recieve complete interrupt, publishing
+    /// every recieved byte as an `Event::ByteReceived` on `system::events::EVENTS`.
+    pub fn recieve_events_enable(&mut self) {
+        PUBLISH_EVENTS[self.name as usize].store(true, Ordering::Release);
+        self.recieve_buffered_enable();
+    }
+
+    /// Disables the recieve complete interrupt, bytes already buffered can still be read.
+    pub fn recieve_buffered_disable(&mut self) {
+        unsafe {
+            (*self.usart).ucsrb.update(|ucsrb| {
+                ucsrb.set_bit(7, false);
+            });
+        }
+    }
+
+    /// Returns the number of bytes waiting in the reception buffer.
+    pub fn buffered_available(&self) -> usize {
+        self.rx_buffer().len()
+    }
+
+    /// Reads one byte from the reception buffer.
+    /// # Returns
+    /// * `a Option<u8>` - `None` if no byte is waiting.
+    pub fn read_buffered(&mut self) -> Option<u8> {
+        // Only the main program reads from the buffer.
+        unsafe { self.rx_buffer().dequeue() }
+    }
+
+    /// Returns a future which completes with the next recieved byte.
+    #[cfg(feature = "async")]
+    pub fn read_async(&mut self) -> crate::executor::ReadByte<'static, RX_BUFFER_SIZE> {
+        crate::executor::ReadByte::new(self.rx_buffer())
+    }
+}
+
+/// Moves the recieved byte of a USART into its buffer.
+#[cfg(target_arch = "avr")]
+unsafe fn recieve_complete(num: UsartNum) {
+    let byte = Usart::new(num).udr.read();
+    // The byte is dropped if the buffer is full.
+    if PUBLISH_EVENTS[num as usize].load(Ordering::Acquire) {
+        let usart = num as u8;
+        crate::system::events::publish(Event::ByteReceived { usart, byte });
+    } else {
+        let _ = RX_BUFFERS[num as usize].enqueue(byte);
+    }
+}
+
+/// USART0 recieve complete interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_25"]
+pub unsafe extern "avr-interrupt" fn usart0_recieve_complete() {
+    recieve_complete(UsartNum::Usart0);
+}
+
+/// USART1 recieve complete interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_36"]
+pub unsafe extern "avr-interrupt" fn usart1_recieve_complete() {
+    recieve_complete(UsartNum::Usart1);
+}
+
+/// USART2 recieve complete interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_51"]
+pub unsafe extern "avr-interrupt" fn usart2_recieve_complete() {
+    recieve_complete(UsartNum::Usart2);
+}
+
+/// USART3 recieve complete interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_54"]
+pub unsafe extern "avr-interrupt" fn usart3_recieve_complete() {
+    recieve_complete(UsartNum::Usart3);
+}
@@ -29,6 +29,7 @@ use crate::atmega2560p::hal::power;
 // Crates which would be used in the implementation.
 // We will be using standard volatile and bit_field crates now for a better read and write.
 use crate::delay::delay_ms;
+use crate::{Error, Result};
 use bit_field::BitField;
 use core::ptr::write_volatile;
 use core::{f64, u32, u8};
@@ -527,7 +528,7 @@ impl UsartObject {
         stop: UsartStop,
         size: UsartDataSize,
         parity: UsartParity,
-    ) {
+    ) -> Result<()> {
         // Check that recieve and transmit buffers are completely cleared
         // and no transmission or recieve of data is already in process.
         let mut i: i32 = 100;
@@ -536,7 +537,7 @@ impl UsartObject {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
@@ -557,5 +558,6 @@ impl UsartObject {
 
         //  Set the frame format according to input.
         self.set_frame(stop, size, parity);
+        Ok(())
     }
 }
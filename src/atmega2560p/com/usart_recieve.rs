@@ -24,6 +24,7 @@ use crate::atmega2560p::com::usart_initialize::UsartObject;
 // Crates which would be used in the implementation.
 // We will be using standard volatile and bit_field crates now for a better read and write.
 use crate::delay::delay_ms;
+use crate::{Error, Result};
 use bit_field::BitField;
 use core::u32;
 
@@ -51,10 +52,10 @@ impl UsartObject {
     /// Either 5 to 8 bits and 9 bits of data can be recieved from this function.
     /// In case of 5 to 8 bits this function returns u8.
     /// In case of 9 bits it retuns u32 of which first 9 bits are data recieved and remaining bits are insignificant.
-    /// In case if an frame error or parity error occurs, this function returns Nothing.
+    /// In case if an frame error or parity error occurs, this function returns the error.
     /// # Returns
-    /// * `a Option<u32>` - which is NULL in case of wrong settings and read data u32 if valid input.
-    pub fn recieve_data(&mut self) -> Option<u32> {
+    /// * `a Result<u32>` - which is the data read, or the error if no data came in time or the frame was corrupted.
+    pub fn recieve_data(&mut self) -> Result<u32> {
         let ucsrc = unsafe { (*self.usart).ucsrc.read() };
         let ucsrb = unsafe { (*self.usart).ucsrb.read() };
 
@@ -64,7 +65,7 @@ impl UsartObject {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
@@ -73,11 +74,11 @@ impl UsartObject {
             let ucsra = unsafe { (*self.usart).ucsra.read() };
             let mut udr: u32 = unsafe { (*self.usart).udr.read() as u32 };
             if ucsra.get_bits(2..5) != 0b000 {
-                None
+                Err(frame_error(ucsra))
             } else {
                 let rxb8: u32 = ucsrb.get_bits(1..2) as u32;
                 udr.set_bits(8..9, rxb8);
-                Some(udr)
+                Ok(udr)
             }
         }
         //  Case when there is a case of 5 to 8 bits.
@@ -85,9 +86,9 @@ impl UsartObject {
             let ucsra = unsafe { (*self.usart).ucsra.read() };
             let udr: u32 = unsafe { (*self.usart).udr.read() as u32 };
             if ucsra.get_bits(2..5) != 0b000 {
-                None
+                Err(frame_error(ucsra))
             } else {
-                Some(udr)
+                Ok(udr)
             }
         }
     }
@@ -124,7 +125,7 @@ impl UsartObject {
     }
 
     /// Clears the unread data in the receive buffer by flushing it
-    pub unsafe fn flush_recieve(&mut self) {
+    pub unsafe fn flush_recieve(&mut self) -> Result<()> {
         let mut _udr = (*self.usart).udr.read();
         let mut ucsra = (*self.usart).ucsra.read();
         let mut i: i32 = 100;
@@ -135,13 +136,14 @@ impl UsartObject {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
         (*self.usart).ucsra.update(|ucsra| {
             ucsra.set_bit(7, false);
         });
+        Ok(())
     }
 
     ///  This is used to recieve data of one frame.
@@ -149,10 +151,10 @@ impl UsartObject {
     ///  Either 5 to 8 bits and 9 bits of data can be recieved from this function.
     ///  In case of 5 to 8 bits this function returns u8.
     ///  In case of 9 bits it retuns u32 of which first 9 bits are data recieved and remaining bits are insignificant.
-    ///  In case ,if an frame error or parity error occurs, this function returns nothing.
+    ///  In case ,if an frame error or parity error occurs, this function returns the error.
     /// # Returns
-    /// * `a Option<u32>` - which is NULL in case of wrong settings and read data u32 if valid input.
-    pub fn read(&mut self) -> Option<u32> {
+    /// * `a Result<u32>` - which is the data read, or the error if no data came in time or the frame was corrupted.
+    pub fn read(&mut self) -> Result<u32> {
         let ucsrc = unsafe { (*self.usart).ucsrc.read() };
         let ucsrb = unsafe { (*self.usart).ucsrb.read() };
 
@@ -162,7 +164,7 @@ impl UsartObject {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
@@ -171,20 +173,35 @@ impl UsartObject {
             let ucsrb = unsafe { (*self.usart).ucsrb.read() };
             let mut udr: u32 = unsafe { (*self.usart).udr.read() as u32 };
             if ucsra.get_bits(2..5) != 0b000 {
-                None
+                Err(frame_error(ucsra))
             } else {
                 let rxb8: u32 = ucsrb.get_bits(1..2) as u32;
                 udr.set_bits(8..9, rxb8);
-                Some(udr)
+                Ok(udr)
             }
         } else {
             let ucsra = unsafe { (*self.usart).ucsra.read() };
             let udr: u32 = unsafe { (*self.usart).udr.read() as u32 };
             if ucsra.get_bits(2..5) != 0b000 {
-                None
+                Err(frame_error(ucsra))
             } else {
-                Some(udr)
+                Ok(udr)
             }
         }
     }
 }
+
+/// Converts the error flags of UCSRnA into an error.
+/// # Arguments
+/// * `ucsra` - a u8, the value of UCSRnA read before the data register.
+/// # Returns
+/// * `a Error` - The reason of the reception failure.
+fn frame_error(ucsra: u8) -> Error {
+    if ucsra.get_bit(4) {
+        Error::FrameError
+    } else if ucsra.get_bit(3) {
+        Error::Overrun
+    } else {
+        Error::ParityError
+    }
+}
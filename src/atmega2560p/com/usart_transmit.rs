@@ -26,6 +26,7 @@ use fixed_slice_vec::FixedSliceVec;
 // Other source code files to be used.
 use crate::atmega2560p::com::usart_initialize::{UsartDataSize, UsartObject};
 use crate::delay::delay_ms;
+use crate::{Error, Result};
 
 impl UsartObject {
     /// Enables the Transmitter, once it is enabled it takes control of the TXDn pin as a transmitting output.   
@@ -39,7 +40,7 @@ impl UsartObject {
     /// # Arguments
     /// * `data` - a u32, the data to be transmitted.
     /// * `len` -  a `UsartDataSize` object, which contains the length of data frame of USART.
-    pub unsafe fn transmitting_data(&mut self, data: u32, len: UsartDataSize) {
+    pub unsafe fn transmitting_data(&mut self, data: u32, len: UsartDataSize) -> Result<()> {
         // Checks if the Transmit buffer is empty to receive data.
         // If not the program waits till the time comes.
         let mut i: i32 = 10;
@@ -48,7 +49,7 @@ impl UsartObject {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
@@ -75,6 +76,7 @@ impl UsartObject {
                 udr.set_bits(0..8, data.get_bits(0..8) as u8);
             }
         }
+        Ok(())
     }
 
     /// Checks that transmission buffer if ready for transmission.
@@ -90,7 +92,7 @@ impl UsartObject {
     }
 
     /// This waits for the transmission to complete by checking the appropriate register.
-    pub unsafe fn flush_transmit(&mut self) {
+    pub unsafe fn flush_transmit(&mut self) -> Result<()> {
         let mut ucsra = (*self.usart).ucsra.read();
         let mut i: i32 = 10;
         while ucsra.get_bit(6) == false {
@@ -99,14 +101,15 @@ impl UsartObject {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
+        Ok(())
     }
 
     /// This is used to disable the Transmitter and once disabled the pins used for USART
     /// return into their default I/O pin mode.
-    pub fn transmit_disable(&mut self) {
+    pub fn transmit_disable(&mut self) -> Result<()> {
         let ucsra = unsafe { (*self.usart).ucsra.read() };
         let mut uscra6 = ucsra.get_bit(6);
         let mut uscra5 = ucsra.get_bit(5);
@@ -121,7 +124,7 @@ impl UsartObject {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
@@ -130,12 +133,13 @@ impl UsartObject {
                 srb.set_bit(3, false);
             });
         }
+        Ok(())
     }
 
     /// Sends a character byte of 5,6,7 or 8 bits.
     /// # Arguments
     /// * `data` - a u8, consisting of the current data frame to send from USART.
-    pub fn transmit_data(&mut self, data: u8) {
+    pub fn transmit_data(&mut self, data: u8) -> Result<()> {
         let mut ucsra = unsafe { (*self.usart).ucsra.read() };
         let mut udre = ucsra.get_bit(5);
 
@@ -148,7 +152,7 @@ impl UsartObject {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!();
+                return Err(Error::Timeout);
             }
         }
 
@@ -156,12 +160,13 @@ impl UsartObject {
             self.set_txn();
             (*self.usart).udr.write(data)
         };
+        Ok(())
     }
 
     /// Send's data of type string byte by byte using USART.
     /// # Arguments
     /// * `data` - a static string object, which is to be transmitted using USART.
-    pub fn write_string(&mut self, data: &'static str) {
+    pub fn write_string(&mut self, data: &'static str) -> Result<()> {
         let mut vec: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
 
         for c in data.chars() {
@@ -169,14 +174,15 @@ impl UsartObject {
         }
 
         for i in 0..(vec.len()) {
-            self.transmit_data(vec[i]);
+            self.transmit_data(vec[i])?;
         }
+        Ok(())
     }
 
     /// Send's data of type integer(u32) byte by byte.
     /// # Arguments
     /// * `data` - a u32, which is to be transmitted using USART.
-    pub fn write_integer(&mut self, data: u32) {
+    pub fn write_integer(&mut self, data: u32) -> Result<()> {
         let mut vec: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
         let mut a = data;
         while a != 0 {
@@ -197,15 +203,16 @@ impl UsartObject {
             }
         }
         for i in 0..(vec.len()) {
-            self.transmit_data(vec[vec.len() - 1 - i]);
+            self.transmit_data(vec[vec.len() - 1 - i])?;
         }
+        Ok(())
     }
 
     /// Send's data of type float(f64) byte by byte till the precision required.
     /// # Arguments
     /// * `data` - a f32, which is to be transmitted using USART.
     /// * `precision` - a u32, the number of decimal precision required in the transmission.
-    pub fn write_float(&mut self, data: f64, precision: u32) {
+    pub fn write_float(&mut self, data: f64, precision: u32) -> Result<()> {
         let mut vec: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
         let a: f64 = data;
         let mut f: f64 = a % 1.0;
@@ -253,11 +260,12 @@ impl UsartObject {
         }
 
         for ia in 0..(vec.len() - n - 1) {
-            self.transmit_data(vec[vec.len() - 1 - ia]);
+            self.transmit_data(vec[vec.len() - 1 - ia])?;
         }
 
         for ia in 0..n - 1 {
-            self.transmit_data(vec[ia]);
+            self.transmit_data(vec[ia])?;
         }
+        Ok(())
     }
 }
@@ -0,0 +1,687 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Driver of the analog to digital converter, for measurements on any of
+//! the 16 analog inputs. The input multiplexer is set through ADMUX and the
+//! MUX5 bit of ADCSRB, the ADC clock through the prescaler bits of ADCSRA.
+//! `read` selects an input and waits for the 10 bit result of a single
+//! conversion, `start` and `poll` let the main loop do something else meanwhile.
+//! Conversions are referenced to AVcc unless another `Reference` is chosen,
+//! after which the reference settles and a conversion is discarded.
+//! An external voltage on the AREF pin must only be used with `Reference::Aref`,
+//! the other references being connected to the pin. The ADC clock must stay between 50 and
+//! 200 kHz for the full 10 bits, which is `Prescaler::Div128` at 16 MHz.
+//! A conversion takes 13 ADC clock cycles, 25 for the first one after `new`.
+//! `read_differential` converts the difference between two inputs, amplified
+//! 1, 10 or 200 times for some pairs, into a signed result, for bridge sensors
+//! and current shunts. The gain stage needs `GAIN_SETTLING_US` after a change.
+//! `read_oversampled` adds up 4^n conversions and decimates the sum to gain
+//! n bits, following the application note AVR121.
+//! `scan` converts a list of inputs one after the other into a buffer, and
+//! `start_scan` does the same from the conversion complete interrupt, served
+//! by the `sampler` module, the results being collected with `scan_results`.
+//! The interrupt driven scans need the `sampler` feature.
+//! The `sampler` module uses the same ADC, the two cannot be used at once.
+//! Section 26 of the manual.
+
+#[cfg(feature = "sampler")]
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::power::Power;
+use crate::delay::{delay_ms, delay_us};
+use crate::{Error, Result};
+#[cfg(feature = "sampler")]
+use core::ptr::addr_of_mut;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Addresses of the ADC registers.
+const ADCL: *mut u8 = 0x78 as *mut u8;
+const ADCH: *mut u8 = 0x79 as *mut u8;
+const ADCSRA: *mut u8 = 0x7A as *mut u8;
+const ADCSRB: *mut u8 = 0x7B as *mut u8;
+const ADMUX: *mut u8 = 0x7C as *mut u8;
+const DIDR2: *mut u8 = 0x7D as *mut u8;
+const DIDR0: *mut u8 = 0x7E as *mut u8;
+
+/// Bits of ADCSRA.
+const ADEN: u8 = 0x80;
+const ADSC: u8 = 0x40;
+const ADIF: u8 = 0x10;
+#[cfg(feature = "sampler")]
+const ADIE: u8 = 0x08;
+
+/// Bit of ADCSRB completing the channel selection.
+const MUX5: u8 = 0x08;
+
+/// REFS1:0 bits of ADMUX.
+const REFS_MASK: u8 = 0xC0;
+
+/// Time for the capacitor on the AREF pin to settle after a change of reference.
+pub const REFERENCE_SETTLING_MS: u32 = 5;
+
+/// Number of analog inputs.
+pub const CHANNELS: u8 = 16;
+
+/// Channel of the internal 1.1 V bandgap reference.
+pub const BANDGAP: u8 = 30;
+
+/// Channel connected to ground, to check the offset of the ADC.
+pub const GROUND: u8 = 31;
+
+/// Largest result of a conversion.
+pub const MAX_VALUE: u16 = 1023;
+
+/// Largest number of bits gained by `read_oversampled`, for 16 bit results.
+pub const MAX_EXTRA_BITS: u8 = 6;
+
+/// Largest number of inputs converted by `start_scan`.
+pub const MAX_SCAN: usize = 16;
+
+/// Time for the gain stage to settle after a change of differential channel.
+pub const GAIN_SETTLING_US: u32 = 125;
+
+/// Division factors between the CPU clock and the ADC clock.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Prescaler {
+    Div2,
+    Div4,
+    Div8,
+    Div16,
+    Div32,
+    Div64,
+    Div128,
+}
+
+impl Prescaler {
+    /// Returns the division factor.
+    pub fn divider(&self) -> u8 {
+        1 << self.bits()
+    }
+
+    /// Returns the ADPS2:0 bits of ADCSRA.
+    fn bits(&self) -> u8 {
+        match self {
+            Prescaler::Div2 => 1,
+            Prescaler::Div4 => 2,
+            Prescaler::Div8 => 3,
+            Prescaler::Div16 => 4,
+            Prescaler::Div32 => 5,
+            Prescaler::Div64 => 6,
+            Prescaler::Div128 => 7,
+        }
+    }
+}
+
+/// Voltages to which the inputs are compared, a result of `MAX_VALUE` being
+/// the voltage of the reference.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reference {
+    /// The voltage applied to the AREF pin.
+    Aref,
+    /// The analog supply, with a capacitor on the AREF pin.
+    AVcc,
+    /// The internal 1.1 V reference, for small signals like thermocouple amplifiers.
+    Internal1V1,
+    /// The internal 2.56 V reference, for battery dividers independent of the supply.
+    Internal2V56,
+}
+
+impl Reference {
+    /// Returns the nominal voltage of an internal reference in millivolts,
+    /// `None` for the external ones. The internal ones vary by up to 10% between chips.
+    pub fn millivolts(&self) -> Option<u32> {
+        match self {
+            Reference::Internal1V1 => Some(1100),
+            Reference::Internal2V56 => Some(2560),
+            Reference::Aref | Reference::AVcc => None,
+        }
+    }
+
+    /// Returns the REFS1:0 bits of ADMUX.
+    fn bits(&self) -> u8 {
+        match self {
+            Reference::Aref => 0x00,
+            Reference::AVcc => 0x40,
+            Reference::Internal1V1 => 0x80,
+            Reference::Internal2V56 => 0xC0,
+        }
+    }
+}
+
+/// Amplification of the difference between two inputs.
+/// Only some pairs of inputs can be amplified, see `read_differential`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Gain {
+    X1,
+    X10,
+    X200,
+}
+
+impl Gain {
+    /// Returns the amplification factor.
+    pub fn factor(&self) -> u16 {
+        match self {
+            Gain::X1 => 1,
+            Gain::X10 => 10,
+            Gain::X200 => 200,
+        }
+    }
+}
+
+/// Returns the MUX5:0 bits of a channel, split between ADCSRB and ADMUX.
+/// # Arguments
+/// * `channel` - a u8, the analog input from 0 to 15, `BANDGAP` or `GROUND`.
+/// # Returns
+/// * `a Result<u8>` - the bits, `InvalidArgument` if there is no such channel.
+fn mux(channel: u8) -> Result<u8> {
+    match channel {
+        0..=7 => Ok(channel),
+        8..=15 => Ok(0x20 | (channel - 8)),
+        BANDGAP => Ok(0x1E),
+        GROUND => Ok(0x1F),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+/// Returns the MUX5:0 bits of a pair of differential inputs, following table 26-4.
+/// Both inputs must be in the same bank, 0 to 7 or 8 to 15.
+/// # Arguments
+/// * `positive` - a u8, the positive input.
+/// * `negative` - a u8, the negative input.
+/// * `gain` - a `Gain` object, the amplification.
+/// # Returns
+/// * `a Result<u8>` - the bits, `InvalidArgument` if the pair cannot be converted with that gain.
+fn differential_mux(positive: u8, negative: u8, gain: Gain) -> Result<u8> {
+    let (bank, positive, negative) = match (positive, negative) {
+        (0..=7, 0..=7) => (0x00, positive, negative),
+        (8..=15, 8..=15) => (0x20, positive - 8, negative - 8),
+        _ => return Err(Error::InvalidArgument),
+    };
+    let bits = match (gain, positive, negative) {
+        (Gain::X10, 0..=1, 0) => 0x08 | positive,
+        (Gain::X200, 0..=1, 0) => 0x0A | positive,
+        (Gain::X10, 2..=3, 2) => 0x0C | (positive - 2),
+        (Gain::X200, 2..=3, 2) => 0x0E | (positive - 2),
+        (Gain::X1, _, 1) => 0x10 | positive,
+        (Gain::X1, 0..=5, 2) => 0x18 | positive,
+        _ => return Err(Error::InvalidArgument),
+    };
+    Ok(bank | bits)
+}
+
+/// Disables the digital input buffer of an analog input.
+fn disable_digital(channel: u8) {
+    unsafe {
+        if channel < 8 {
+            write_volatile(DIDR0, read_volatile(DIDR0) | (1 << channel));
+        } else if channel < CHANNELS {
+            write_volatile(DIDR2, read_volatile(DIDR2) | (1 << (channel - 8)));
+        }
+    }
+}
+
+/// Writes the MUX5:0 bits, split between ADMUX and ADCSRB.
+fn set_mux(mux: u8) {
+    unsafe {
+        let admux = read_volatile(ADMUX) & 0xE0;
+        write_volatile(ADMUX, admux | (mux & 0x1F));
+        let adcsrb = read_volatile(ADCSRB) & !MUX5;
+        write_volatile(ADCSRB, adcsrb | ((mux >> 2) & MUX5));
+    }
+}
+
+/// Starts a conversion without clearing a pending interrupt flag.
+fn start_conversion() {
+    unsafe {
+        let adcsra = read_volatile(ADCSRA) & !ADIF;
+        write_volatile(ADCSRA, adcsra | ADSC);
+    }
+}
+
+/// A scan run by the conversion complete interrupt.
+/// # Elements
+/// * `muxes` - an array of u8, the MUX5:0 bits of the inputs.
+/// * `results` - an array of u16, the results of the inputs converted.
+/// * `len` - a u8, the number of inputs.
+/// * `index` - a u8, the input being converted.
+/// * `discard` - a boolean, true if the first conversion of every input is thrown away.
+/// * `skipping` - a boolean, true while the conversion being made is thrown away.
+/// * `active` - a boolean, true while the scan runs.
+/// * `done` - a boolean, true once the results are ready.
+#[cfg(feature = "sampler")]
+struct Scan {
+    muxes: [u8; MAX_SCAN],
+    results: [u16; MAX_SCAN],
+    len: u8,
+    index: u8,
+    discard: bool,
+    skipping: bool,
+    active: bool,
+    done: bool,
+}
+
+#[cfg(feature = "sampler")]
+static mut SCAN: Scan = Scan {
+    muxes: [0; MAX_SCAN],
+    results: [0; MAX_SCAN],
+    len: 0,
+    index: 0,
+    discard: false,
+    skipping: false,
+    active: false,
+    done: false,
+};
+/// Adds up 4^n conversions and decimates the sum, keeping n more bits than
+/// a single conversion, following the application note AVR121.
+/// # Arguments
+/// * `extra_bits` - a u8, the number n of bits gained, up to `MAX_EXTRA_BITS`.
+/// * `convert` - a function making one conversion.
+/// # Returns
+/// * `a u16` - the result of `10 + extra_bits` bits.
+fn decimate(extra_bits: u8, mut convert: impl FnMut() -> u16) -> u16 {
+    let mut sum = 0u32;
+    for _ in 0..1u32 << (2 * extra_bits) {
+        sum += convert() as u32;
+    }
+    (sum >> extra_bits) as u16
+}
+
+/// The analog to digital converter.
+/// # Elements
+/// * `prescaler` - a `Prescaler` object, the division of the CPU clock.
+/// * `reference` - a `Reference` object, the reference of the conversions.
+/// * `discard` - a boolean, true if the first conversion after a change of input is thrown away.
+pub struct Adc {
+    prescaler: Prescaler,
+    reference: Reference,
+    discard: bool,
+}
+
+impl Adc {
+    /// Powers and enables the ADC with the `Div128` prescaler and the AVcc
+    /// reference, input 0 selected.
+    /// # Returns
+    /// * `a Adc object` - which will be used for further implementations.
+    pub fn new() -> Adc {
+        let adc = Adc {
+            prescaler: Prescaler::Div128,
+            reference: Reference::AVcc,
+            discard: false,
+        };
+        unsafe {
+            let power = Power::new();
+            write_volatile(&mut power.prr0, read_volatile(&power.prr0) & !0x01);
+            write_volatile(ADMUX, adc.reference.bits());
+            write_volatile(ADCSRB, 0);
+            write_volatile(ADCSRA, ADEN | ADIF | adc.prescaler.bits());
+        }
+        adc
+    }
+
+    /// Sets the division between the CPU clock and the ADC clock.
+    /// # Arguments
+    /// * `prescaler` - a `Prescaler` object, `Div128` for 10 bits at 16 MHz,
+    /// `Div16` trades 2 bits for conversions 8 times faster.
+    pub fn set_prescaler(&mut self, prescaler: Prescaler) {
+        self.prescaler = prescaler;
+        unsafe {
+            let adcsra = read_volatile(ADCSRA) & !(0x07 | ADIF);
+            write_volatile(ADCSRA, adcsra | prescaler.bits());
+        }
+    }
+
+    /// Returns the division between the CPU clock and the ADC clock.
+    pub fn prescaler(&self) -> Prescaler {
+        self.prescaler
+    }
+
+    /// Changes the reference of the conversions. The reference is given
+    /// `REFERENCE_SETTLING_MS` to settle and the first conversion, which is
+    /// not accurate, is made and discarded, so the next result can be trusted.
+    /// # Arguments
+    /// * `reference` - a `Reference` object, the new reference.
+    pub fn set_reference(&mut self, reference: Reference) {
+        if reference == self.reference {
+            return;
+        }
+        self.reference = reference;
+        unsafe {
+            let admux = read_volatile(ADMUX) & !REFS_MASK;
+            write_volatile(ADMUX, admux | reference.bits());
+        }
+        delay_ms(REFERENCE_SETTLING_MS);
+        self.convert();
+    }
+
+    /// Returns the reference of the conversions.
+    pub fn reference(&self) -> Reference {
+        self.reference
+    }
+
+    /// Selects the input of the next conversions and disables the digital
+    /// input buffer of its pin, which saves power and noise.
+    /// # Arguments
+    /// * `channel` - a u8, the analog input from 0 to 15, `BANDGAP` or `GROUND`.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if there is no such channel.
+    pub fn select(&mut self, channel: u8) -> Result<()> {
+        let mux = mux(channel)?;
+        disable_digital(channel);
+        set_mux(mux);
+        Ok(())
+    }
+
+    /// Selects a pair of differential inputs for the next conversions.
+    /// The gain stage is given `GAIN_SETTLING_US` to settle when the pair changes.
+    /// # Arguments
+    /// * `positive` - a u8, the positive input.
+    /// * `negative` - a u8, the negative input.
+    /// * `gain` - a `Gain` object, the amplification.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if the pair cannot be converted with that gain.
+    pub fn select_differential(&mut self, positive: u8, negative: u8, gain: Gain) -> Result<()> {
+        let mux = differential_mux(positive, negative, gain)?;
+        let changed = unsafe {
+            let selected = (read_volatile(ADMUX) & 0x1F) | ((read_volatile(ADCSRB) & MUX5) << 2);
+            selected != mux
+        };
+        disable_digital(positive);
+        disable_digital(negative);
+        set_mux(mux);
+        if changed {
+            delay_us(GAIN_SETTLING_US);
+        }
+        Ok(())
+    }
+
+    /// Starts a single conversion of the selected input.
+    pub fn start(&mut self) {
+        start_conversion();
+    }
+
+    /// Returns true while a conversion is running.
+    pub fn is_busy(&self) -> bool {
+        unsafe { read_volatile(ADCSRA) & ADSC != 0 }
+    }
+
+    /// Returns the result of the conversion started by `start` once it is done.
+    /// # Returns
+    /// * `a Option<u16>` - the 10 bit result, `None` while the conversion runs.
+    pub fn poll(&mut self) -> Option<u16> {
+        if self.is_busy() {
+            None
+        } else {
+            Some(self.result())
+        }
+    }
+
+    /// Converts the selected input and waits for the result.
+    /// # Returns
+    /// * `a u16` - the 10 bit result, from 0 to `MAX_VALUE`.
+    pub fn convert(&mut self) -> u16 {
+        self.start();
+        while self.is_busy() {}
+        self.result()
+    }
+
+    /// Selects an input, converts it and waits for the result.
+    /// # Arguments
+    /// * `channel` - a u8, the analog input from 0 to 15, `BANDGAP` or `GROUND`.
+    /// # Returns
+    /// * `a Result<u16>` - the 10 bit result, `InvalidArgument` if there is no such channel.
+    pub fn read(&mut self, channel: u8) -> Result<u16> {
+        self.select(channel)?;
+        Ok(self.convert())
+    }
+
+    /// Converts the difference between two inputs, amplified by the gain.
+    /// The pairs which can be converted are, in each bank of 8 inputs:
+    /// * with `Gain::X10` or `Gain::X200` - inputs 0 or 1 against 0, and 2 or 3 against 2,
+    ///   a pair of twice the same input giving the offset to subtract.
+    /// * with `Gain::X1` - any input against 1, and inputs 0 to 5 against 2.
+    ///
+    /// Both inputs must stay between ground and the reference, only their
+    /// difference may be negative. `Gain::X200` has about 7 bits of accuracy.
+    /// # Arguments
+    /// * `positive` - a u8, the positive input, like 1 or 9.
+    /// * `negative` - a u8, the negative input, like 0 or 8.
+    /// * `gain` - a `Gain` object, the amplification.
+    /// # Returns
+    /// * `a Result<i16>` - the result from -512 to 511, being `512 * gain * (positive - negative) / reference`,
+    ///   `InvalidArgument` if the pair cannot be converted with that gain.
+    pub fn read_differential(&mut self, positive: u8, negative: u8, gain: Gain) -> Result<i16> {
+        self.select_differential(positive, negative, gain)?;
+        // The 10 bit result is in two's complement, the sign is extended.
+        Ok(((self.convert() << 6) as i16) >> 6)
+    }
+
+    /// Converts an input 4^n times and decimates the sum, which gives n more bits
+    /// of resolution as long as the input carries at least 1 LSB of noise, as
+    /// it usually does. A steady input or a too quiet one needs a dither added.
+    /// 256 conversions are made for 14 bits, about 27 ms with `Prescaler::Div128`.
+    /// # Arguments
+    /// * `channel` - a u8, the analog input from 0 to 15, `BANDGAP` or `GROUND`.
+    /// * `extra_bits` - a u8, the number n of bits gained, up to `MAX_EXTRA_BITS`.
+    /// # Returns
+    /// * `a Result<u16>` - the result of `10 + extra_bits` bits, `InvalidArgument` if there is
+    ///   no such channel or too many bits are asked for.
+    pub fn read_oversampled(&mut self, channel: u8, extra_bits: u8) -> Result<u16> {
+        if extra_bits > MAX_EXTRA_BITS {
+            return Err(Error::InvalidArgument);
+        }
+        self.select(channel)?;
+        if self.discard {
+            self.convert();
+        }
+        Ok(decimate(extra_bits, || self.convert()))
+    }
+
+    /// Throws the first conversion after every change of input away in the scans
+    /// and `read_oversampled`, for sources above 10 kOhm which charge the sample and hold capacitor slowly.
+    /// # Arguments
+    /// * `discard` - a boolean, true to convert every input twice.
+    pub fn set_discard(&mut self, discard: bool) {
+        self.discard = discard;
+    }
+
+    /// Converts several inputs one after the other and waits for the results.
+    /// # Arguments
+    /// * `channels` - a reference to `[u8]`, the analog inputs from 0 to 15, `BANDGAP` or `GROUND`.
+    /// * `results` - a mutable reference to `[u16]`, receiving the result of each input in order.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if there is no such channel, `BufferTooSmall` if
+    ///   `results` is shorter than `channels`, in which case nothing is converted.
+    pub fn scan(&mut self, channels: &[u8], results: &mut [u16]) -> Result<()> {
+        if results.len() < channels.len() {
+            return Err(Error::BufferTooSmall);
+        }
+        for channel in channels {
+            mux(*channel)?;
+        }
+        for (channel, result) in channels.iter().zip(results.iter_mut()) {
+            self.select(*channel)?;
+            if self.discard {
+                self.convert();
+            }
+            *result = self.convert();
+        }
+        Ok(())
+    }
+
+    /// Starts converting several inputs one after the other from the conversion
+    /// complete interrupt, which must not be used by `sampler` meanwhile.
+    /// Global interrupts must be enabled.
+    /// # Arguments
+    /// * `channels` - a reference to `[u8]`, up to `MAX_SCAN` analog inputs from 0 to 15,
+    ///   `BANDGAP` or `GROUND`.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if there is no such channel or too many,
+    ///   `NotReady` if a scan is running.
+    #[cfg(feature = "sampler")]
+    pub fn start_scan(&mut self, channels: &[u8]) -> Result<()> {
+        if channels.is_empty() || channels.len() > MAX_SCAN {
+            return Err(Error::InvalidArgument);
+        }
+        let mut muxes = [0; MAX_SCAN];
+        for (channel, bits) in channels.iter().zip(muxes.iter_mut()) {
+            *bits = mux(*channel)?;
+            disable_digital(*channel);
+        }
+        interrupts::free(|| unsafe {
+            if SCAN.active {
+                return Err(Error::NotReady);
+            }
+            SCAN.muxes = muxes;
+            SCAN.len = channels.len() as u8;
+            SCAN.index = 0;
+            SCAN.discard = self.discard;
+            SCAN.skipping = self.discard;
+            SCAN.active = true;
+            SCAN.done = false;
+            set_mux(muxes[0]);
+            write_volatile(ADCSRA, read_volatile(ADCSRA) | ADIF | ADIE);
+            start_conversion();
+            Ok(())
+        })
+    }
+
+    /// Returns true while a scan started by `start_scan` runs.
+    #[cfg(feature = "sampler")]
+    pub fn is_scanning(&self) -> bool {
+        interrupts::free(|| unsafe { SCAN.active })
+    }
+
+    /// Collects the results of the scan started by `start_scan` once it is done.
+    /// # Arguments
+    /// * `results` - a mutable reference to `[u16]`, receiving the result of each input in order.
+    /// # Returns
+    /// * `a Option<usize>` - the number of results, `None` while the scan runs or if they were collected.
+    #[cfg(feature = "sampler")]
+    pub fn scan_results(&mut self, results: &mut [u16]) -> Option<usize> {
+        interrupts::free(|| unsafe {
+            if !SCAN.done {
+                return None;
+            }
+            SCAN.done = false;
+            let len = (SCAN.len as usize).min(results.len());
+            results[..len].copy_from_slice(&SCAN.results[..len]);
+            Some(len)
+        })
+    }
+
+    /// Converts an input into millivolts.
+    /// # Arguments
+    /// * `channel` - a u8, the analog input from 0 to 15, `BANDGAP` or `GROUND`.
+    /// * `reference` - a u32, the voltage of the reference in millivolts,
+    /// for example `Reference::millivolts` for the internal ones.
+    /// # Returns
+    /// * `a Result<u32>` - the voltage, `InvalidArgument` if there is no such channel.
+    pub fn read_millivolts(&mut self, channel: u8, reference: u32) -> Result<u32> {
+        Ok(self.read(channel)? as u32 * reference / (MAX_VALUE as u32 + 1))
+    }
+
+    /// Disables and powers down the ADC, which then draws no current.
+    pub fn disable(self) {
+        unsafe {
+            write_volatile(ADCSRA, read_volatile(ADCSRA) & !(ADEN | ADIF));
+            let power = Power::new();
+            write_volatile(&mut power.prr0, read_volatile(&power.prr0) | 0x01);
+        }
+    }
+
+    /// Reads the result of the last conversion, ADCL first.
+    fn result(&self) -> u16 {
+        unsafe {
+            let low = read_volatile(ADCL) as u16;
+            let high = read_volatile(ADCH) as u16;
+            (high << 8) | low
+        }
+    }
+}
+
+/// Stores a result of the scan started by `Adc::start_scan` and converts the
+/// next input, called by the conversion complete interrupt service routine.
+/// # Arguments
+/// * `result` - a u16, the result of the conversion.
+/// # Returns
+/// * `a boolean` - false if no scan runs and the result belongs to someone else.
+#[cfg(feature = "sampler")]
+pub(crate) unsafe fn scan_complete(result: u16) -> bool {
+    let scan = &mut *addr_of_mut!(SCAN);
+    if !scan.active {
+        return false;
+    }
+    if scan.skipping {
+        scan.skipping = false;
+    } else {
+        scan.results[scan.index as usize] = result;
+        scan.index += 1;
+        if scan.index == scan.len {
+            scan.active = false;
+            scan.done = true;
+            write_volatile(ADCSRA, read_volatile(ADCSRA) & !(ADIE | ADIF));
+            return true;
+        }
+        set_mux(scan.muxes[scan.index as usize]);
+        scan.skipping = scan.discard;
+    }
+    start_conversion();
+    true
+}
+
+impl Default for Adc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decimation() {
+        assert_eq!(decimate(0, || 700), 700);
+        // 4 conversions for one more bit, a steady input doubles.
+        assert_eq!(decimate(1, || 700), 1400);
+        let mut count = 0;
+        assert_eq!(
+            decimate(MAX_EXTRA_BITS, || {
+                count += 1;
+                1023
+            }),
+            65472
+        );
+        assert_eq!(count, 4096);
+        // Half a step of noise between 512 and 513 gives 512.5, that is 1025 in 11 bits.
+        let mut high = false;
+        assert_eq!(
+            decimate(1, || {
+                high = !high;
+                512 + high as u16
+            }),
+            1025
+        );
+        // 2 bits gained from 16 conversions, a quarter of them one step higher.
+        let mut n = 0;
+        assert_eq!(
+            decimate(2, || {
+                n += 1;
+                100 + (n % 4 == 0) as u16
+            }),
+            401
+        );
+    }
+}
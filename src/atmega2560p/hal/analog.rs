@@ -20,10 +20,12 @@
 //! This code implements the Analog Write function to write into the buffer using analog signals.
 //! Refer to section 16,17,25 and 26 of ATMEGA2560P datasheet.
 
+use crate::atmega2560p::hal::adc::Adc;
 use crate::atmega2560p::hal::pin::{AnalogPin, DigitalPin};
 // Other source codes required.
-use crate::__nop;
 use crate::atmega2560p::hal::power::Power;
+use crate::{Error, Result};
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
 
 // Crates to be used for the implementation.
 use bit_field::BitField;
@@ -149,228 +151,49 @@ impl AnalogComparator {
 impl AnalogPin {
     /// Read the signal input to the analog pin.
     /// Any analog pin can be freely used for this purpose.
+    /// The conversion is made by `Adc::read`, which is disabled after it,
+    /// against the reference chosen by `analog_reference`. `Adc::new` runs the
+    /// ADC clock at a 128th of the CPU clock, replacing the prescaler set by
+    /// `Analog::analog_prescaler`, use `Adc::set_prescaler` for another one.
     /// # Returns
-    /// * `a u32` - Value read from the analog pin.
-    pub fn read(&mut self) -> u32 {
+    /// * `a Result<u32>` - Value read from the analog pin, `InvalidArgument` if there is no such input.
+    pub fn read(&mut self) -> Result<u32> {
         self.pin.set_input();
 
-        let pin = self.pinno;
-
-        unsafe {
-            let analog = Analog::new();
-
-            analog.power_adc_disable(); //PRADC disable to enable ADC
-
-            analog.adc_enable();
-
-            analog.analog_prescaler(2);
-
-            analog.adc_auto_trig();
-
-            match pin {
-                0 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b000);
-                    });
-                    analog.didr0.update(|didr0| {
-                        didr0.set_bit(0, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, false);
-                    });
-                }
-                1 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b001);
-                    });
-                    analog.didr0.update(|didr0| {
-                        didr0.set_bit(1, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, false);
-                    });
-                }
-                2 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b010);
-                    });
-                    analog.didr0.update(|didr0| {
-                        didr0.set_bit(2, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, false);
-                    });
-                }
-                3 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b011);
-                    });
-                    analog.didr0.update(|didr0| {
-                        didr0.set_bit(3, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, false);
-                    });
-                }
-                4 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b100);
-                    });
-                    analog.didr0.update(|didr0| {
-                        didr0.set_bit(4, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, false);
-                    });
-                }
-                5 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b101);
-                    });
-                    analog.didr0.update(|didr0| {
-                        didr0.set_bit(5, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, false);
-                    });
-                }
-                6 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b110);
-                    });
-                    analog.didr0.update(|didr0| {
-                        didr0.set_bit(6, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, false);
-                    });
-                }
-                7 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b111);
-                    });
-                    analog.didr0.update(|didr0| {
-                        didr0.set_bit(7, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, false);
-                    });
-                }
-                8 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b000);
-                    });
-                    analog.didr2.update(|didr2| {
-                        didr2.set_bit(0, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, true);
-                    });
-                }
-                9 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b001);
-                    });
-                    analog.didr2.update(|didr2| {
-                        didr2.set_bit(1, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, true);
-                    });
-                }
-                10 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b010);
-                    });
-                    analog.didr2.update(|didr2| {
-                        didr2.set_bit(2, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, true);
-                    });
-                }
-                11 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b011);
-                    });
-                    analog.didr2.update(|didr2| {
-                        didr2.set_bit(4, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, true);
-                    });
-                }
-                12 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b100);
-                    });
-                    analog.didr2.update(|didr2| {
-                        didr2.set_bit(4, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, true);
-                    });
-                }
-                13 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b101);
-                    });
-                    analog.didr2.update(|didr2| {
-                        didr2.set_bit(5, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, true);
-                    });
-                }
-                14 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b110);
-                    });
-                    analog.didr2.update(|didr2| {
-                        didr2.set_bit(6, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, true);
-                    });
-                }
-                15 => {
-                    analog.admux.update(|admux| {
-                        admux.set_bits(0..3, 0b111);
-                    });
-                    analog.didr2.update(|didr2| {
-                        didr2.set_bit(7, true);
-                    });
-                    analog.adcsrb.update(|mux| {
-                        mux.set_bit(3, true);
-                    });
-                }
-                _ => unreachable!(),
-            }
-
-            analog.adc_con_start();
-
-            // wait 25 ADC cycles
-            let mut i: i32 = 25;
-            let adcsra = analog.adcsra.read();
+        let analog = unsafe { Analog::new() };
+        let reference = analog.admux.read().get_bits(6..8);
+        let mut adc = Adc::new();
+        analog.admux.update(|admux| {
+            admux.set_bits(6..8, reference);
+        });
+        let value = adc.read(self.pinno as u8);
+        adc.disable();
 
-            while adcsra.get_bit(4) == true {
-                if i != 0 {
-                    i = i - 1;
-                    __nop();
-                    __nop(); //add delay of system clock
-                } else {
-                    unreachable!()
-                }
-            }
-            let mut a: u32 = 0;
-            a.set_bits(0..8, analog.adcl.read() as u32);
+        value.map(|value| value as u32)
+    }
 
-            a.set_bits(8..10, analog.adch.read() as u32);
+    /// Reads the analog pin with more resolution by adding up 4^n readings
+    /// and decimating the sum, with `Adc::read_oversampled`.
+    /// It works as long as the input carries at least 1 LSB of noise.
+    /// The prescaler is replaced as in `read`.
+    /// # Arguments
+    /// * `extra_bits` - a u8, the number n of bits gained, up to `adc::MAX_EXTRA_BITS`.
+    /// # Returns
+    /// * `a Result<u16>` - Value read from the analog pin, of `10 + extra_bits` bits,
+    ///   `InvalidArgument` if too many bits are asked for.
+    pub fn read_oversampled(&mut self, extra_bits: u8) -> Result<u16> {
+        self.pin.set_input();
 
-            analog.adc_disable();
+        let analog = unsafe { Analog::new() };
+        let reference = analog.admux.read().get_bits(6..8);
+        let mut adc = Adc::new();
+        analog.admux.update(|admux| {
+            admux.set_bits(6..8, reference);
+        });
+        let value = adc.read_oversampled(self.pinno as u8, extra_bits);
+        adc.disable();
 
-            a
-        }
+        value
     }
 }
 
@@ -562,6 +385,40 @@ impl DigitalPin {
     }
 }
 
+/// Digital pins which are connected to an output compare unit of a timer.
+const PWM_PINS: [u32; 15] = [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 44, 45, 46];
+
+impl DigitalPin {
+    /// Checks if the pin can output a PWM wave with `write`.
+    /// # Returns
+    /// * `a boolean` - Which is true for a PWM pin.
+    pub fn is_pwm(&self) -> bool {
+        let pinno = self.pinno;
+        PWM_PINS.contains(&pinno)
+    }
+}
+
+// Implementation of the embedded-hal PWM trait, so that platform agnostic
+// drivers can set the duty cycle of the PWM pins.
+impl ErrorType for DigitalPin {
+    type Error = Error;
+}
+
+impl SetDutyCycle for DigitalPin {
+    fn max_duty_cycle(&self) -> u16 {
+        0xFF
+    }
+
+    /// Writes the duty cycle with `write`, pins without PWM give `InvalidArgument`.
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<()> {
+        if !self.is_pwm() || duty > 0xFF {
+            return Err(Error::InvalidArgument);
+        }
+        self.write(duty as u8);
+        Ok(())
+    }
+}
+
 impl Analog {
     /// New pointer object created for Analog Structure.
     /// # Returns
@@ -693,3 +550,45 @@ pub fn analog_reference(reftype: RefType) {
         }
     }
 }
+
+/// Nominal voltage of the internal bandgap reference in millivolts.
+/// It varies by up to 10% between chips, measure it on the AREF pin with
+/// `analog_reference(RefType::INTERNAL1V1)` to calibrate `vcc_millivolts`.
+pub const BANDGAP_MILLIVOLTS: u32 = 1100;
+
+/// Measures the supply voltage by converting the internal bandgap reference
+/// against AVCC, which needs no external part. This monitors a battery which
+/// powers the chip directly, without a regulator in between.
+/// The ADC is left disabled and the reference set to AVCC.
+/// # Arguments
+/// * `bandgap` - a u32, the voltage of the bandgap in millivolts, `BANDGAP_MILLIVOLTS` if not calibrated.
+/// # Returns
+/// * `a u16` - The supply voltage in millivolts.
+pub fn vcc_millivolts(bandgap: u32) -> u16 {
+    unsafe {
+        let analog = Analog::new();
+        analog.power_adc_disable();
+        // AVCC reference (REFS1:0 = 01) and the bandgap as input.
+        analog.admux.write(0x5E);
+        analog.adcsrb.update(|adcsrb| {
+            adcsrb.set_bit(3, false);
+        });
+        // Enabled with a prescaler of 128, 125 kHz at 16 MHz.
+        analog.adcsra.write(0x87);
+        // The bandgap takes about 1 ms to settle after being selected.
+        crate::delay::delay_ms(1);
+        let mut value: u32 = 0;
+        // The first conversion after a change of reference is discarded.
+        for _ in 0..2 {
+            analog.adc_con_start();
+            while analog.adcsra.read().get_bit(6) {}
+            value = analog.adcl.read() as u32;
+            value |= (analog.adch.read() as u32) << 8;
+        }
+        analog.adc_disable();
+        if value == 0 {
+            return 0;
+        }
+        (bandgap * 1024 / value) as u16
+    }
+}
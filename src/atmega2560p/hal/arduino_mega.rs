@@ -0,0 +1,173 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Pin numbers printed on the Arduino Mega 2560 translated to the ports of
+//! the ATmega2560, so that D13 gives PB7 without looking at the schematic.
+//! As in the Arduino IDE, the analog pins A0 to A15 can also be used as the
+//! digital pins 54 to 69.
+
+use crate::atmega2560p::hal::port::{Pin, PortName};
+
+/// Number of digital pins, D0 to D53.
+pub const DIGITAL_PINS: u8 = 54;
+
+/// Number of analog pins, A0 to A15.
+pub const ANALOG_PINS: u8 = 16;
+
+/// Digital pin of the onboard LED.
+pub const LED_BUILTIN: u8 = 13;
+
+/// Port and bit of each digital pin.
+const DIGITAL: [(PortName, u8); DIGITAL_PINS as usize] = [
+    (PortName::E, 0),
+    (PortName::E, 1),
+    (PortName::E, 4),
+    (PortName::E, 5),
+    (PortName::G, 5),
+    (PortName::E, 3),
+    (PortName::H, 3),
+    (PortName::H, 4),
+    (PortName::H, 5),
+    (PortName::H, 6),
+    (PortName::B, 4),
+    (PortName::B, 5),
+    (PortName::B, 6),
+    (PortName::B, 7),
+    (PortName::J, 1),
+    (PortName::J, 0),
+    (PortName::H, 1),
+    (PortName::H, 0),
+    (PortName::D, 3),
+    (PortName::D, 2),
+    (PortName::D, 1),
+    (PortName::D, 0),
+    (PortName::A, 0),
+    (PortName::A, 1),
+    (PortName::A, 2),
+    (PortName::A, 3),
+    (PortName::A, 4),
+    (PortName::A, 5),
+    (PortName::A, 6),
+    (PortName::A, 7),
+    (PortName::C, 7),
+    (PortName::C, 6),
+    (PortName::C, 5),
+    (PortName::C, 4),
+    (PortName::C, 3),
+    (PortName::C, 2),
+    (PortName::C, 1),
+    (PortName::C, 0),
+    (PortName::D, 7),
+    (PortName::G, 2),
+    (PortName::G, 1),
+    (PortName::G, 0),
+    (PortName::L, 7),
+    (PortName::L, 6),
+    (PortName::L, 5),
+    (PortName::L, 4),
+    (PortName::L, 3),
+    (PortName::L, 2),
+    (PortName::L, 1),
+    (PortName::L, 0),
+    (PortName::B, 3),
+    (PortName::B, 2),
+    (PortName::B, 1),
+    (PortName::B, 0),
+];
+
+/// Translates a digital pin number.
+/// # Arguments
+/// * `number` - a u8, the number printed on the board, 54 to 69 for A0 to A15.
+/// # Returns
+/// * `a Option<(PortName, u8)>` - the port and the bit in the port, None for a pin the board does not have.
+pub fn digital_pin(number: u8) -> Option<(PortName, u8)> {
+    match number {
+        0..=53 => Some(DIGITAL[number as usize]),
+        54..=69 => analog_pin(number - DIGITAL_PINS),
+        _ => None,
+    }
+}
+
+/// Translates an analog pin number.
+/// # Arguments
+/// * `number` - a u8, the number printed after the A on the board.
+/// # Returns
+/// * `a Option<(PortName, u8)>` - the port and the bit in the port, None for a pin the board does not have.
+pub fn analog_pin(number: u8) -> Option<(PortName, u8)> {
+    match number {
+        0..=7 => Some((PortName::F, number)),
+        8..=15 => Some((PortName::K, number - 8)),
+        _ => None,
+    }
+}
+
+/// Returns the ADC channel read by an analog pin.
+/// # Arguments
+/// * `number` - a u8, the number printed after the A on the board.
+/// # Returns
+/// * `a Option<u8>` - the channel, None for a pin the board does not have.
+pub fn analog_channel(number: u8) -> Option<u8> {
+    if number < ANALOG_PINS {
+        Some(number)
+    } else {
+        None
+    }
+}
+
+/// Returns the pin of a digital pin number.
+/// # Arguments
+/// * `number` - a u8, the number printed on the board, 54 to 69 for A0 to A15.
+/// # Returns
+/// * `a Option<Pin>` - the pin, None for a pin the board does not have.
+pub fn pin(number: u8) -> Option<Pin> {
+    let (port, bit) = digital_pin(number)?;
+    Pin::new(port, bit as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn digital_pins() {
+        assert_eq!(digital_pin(0), Some((PortName::E, 0)));
+        assert_eq!(digital_pin(2), Some((PortName::E, 4)));
+        assert_eq!(digital_pin(4), Some((PortName::G, 5)));
+        assert_eq!(digital_pin(LED_BUILTIN), Some((PortName::B, 7)));
+        // TX3 is PJ1 and RX3 is PJ0.
+        assert_eq!(digital_pin(14), Some((PortName::J, 1)));
+        assert_eq!(digital_pin(15), Some((PortName::J, 0)));
+        assert_eq!(digital_pin(21), Some((PortName::D, 0)));
+        assert_eq!(digital_pin(22), Some((PortName::A, 0)));
+        assert_eq!(digital_pin(30), Some((PortName::C, 7)));
+        assert_eq!(digital_pin(38), Some((PortName::D, 7)));
+        assert_eq!(digital_pin(42), Some((PortName::L, 7)));
+        assert_eq!(digital_pin(53), Some((PortName::B, 0)));
+    }
+
+    #[test]
+    fn analog_pins() {
+        assert_eq!(digital_pin(54), Some((PortName::F, 0)));
+        assert_eq!(digital_pin(61), Some((PortName::F, 7)));
+        assert_eq!(digital_pin(62), Some((PortName::K, 0)));
+        assert_eq!(digital_pin(69), Some((PortName::K, 7)));
+        assert_eq!(digital_pin(70), None);
+        assert_eq!(analog_pin(9), Some((PortName::K, 1)));
+        assert_eq!(analog_pin(16), None);
+        assert_eq!(analog_channel(15), Some(15));
+        assert_eq!(analog_channel(16), None);
+    }
+}
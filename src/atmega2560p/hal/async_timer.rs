@@ -0,0 +1,269 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Timer2 in asynchronous mode, clocked from a 32.768 kHz watch crystal or an
+//! external clock on the TOSC pins instead of the system clock. The timer keeps
+//! counting in power-save mode, so the MCU can sleep for seconds at a time
+//! and still keep the real time, or total pulses arriving on TOSC1 while asleep.
+//! The counter overflows wake the MCU up and are counted by the overflow
+//! interrupt, which is shared with `hal::audio`, so `hal::audio`, `hal::tone` and
+//! `hal::sigma_delta` cannot be used meanwhile.
+//! On the Mega TOSC2 and TOSC1 are PG3 and PG4, which are not wired to headers,
+//! so the crystal has to be soldered to the chip.
+//! The module needs the `async-timer` feature, which brings in `hal::audio`
+//! for the overflow interrupt service routine.
+//! Section 20 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::power::Power;
+use crate::atmega2560p::hal::sleep_mode;
+use core::ptr::{addr_of, read_volatile, write_volatile};
+
+/// Addresses of the Timer/Counter2 registers.
+const TCCR2A: *mut u8 = 0xB0 as *mut u8;
+const TCCR2B: *mut u8 = 0xB1 as *mut u8;
+const TCNT2: *mut u8 = 0xB2 as *mut u8;
+const ASSR: *mut u8 = 0xB6 as *mut u8;
+const TIMSK2: *mut u8 = 0x70 as *mut u8;
+const TIFR2: *mut u8 = 0x37 as *mut u8;
+
+/// Bits of ASSR.
+const EXCLK: u8 = 0x40;
+const AS2: u8 = 0x20;
+/// TCN2UB, OCR2AUB, OCR2BUB, TCR2AUB and TCR2BUB, set while a write is synchronised.
+const UPDATE_BUSY: u8 = 0x1F;
+
+/// Bit of the overflow interrupt in TIMSK2 and TIFR2.
+const TOIE2: u8 = 0x01;
+
+/// Frequency of a watch crystal in Hz.
+pub const CRYSTAL_HZ: u32 = 32_768;
+
+/// What clocks the timer.
+/// * **Crystal**: a 32.768 kHz watch crystal between TOSC1 and TOSC2.
+/// * **External**: a clock or pulses on TOSC1, at most a quarter of the CPU frequency.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Source {
+    Crystal,
+    External,
+}
+
+/// Division of the timer clock, setting how often the counter overflows.
+/// With the crystal, an overflow happens every 7.8125 ms for `Div1`,
+/// and every second for `Div128`, up to every 8 seconds for `Div1024`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Prescaler {
+    Div1,
+    Div8,
+    Div32,
+    Div64,
+    Div128,
+    Div256,
+    Div1024,
+}
+
+impl Prescaler {
+    /// Returns the division factor.
+    pub fn divider(&self) -> u32 {
+        match self {
+            Prescaler::Div1 => 1,
+            Prescaler::Div8 => 8,
+            Prescaler::Div32 => 32,
+            Prescaler::Div64 => 64,
+            Prescaler::Div128 => 128,
+            Prescaler::Div256 => 256,
+            Prescaler::Div1024 => 1024,
+        }
+    }
+
+    /// Returns the clock select bits of TCCR2B.
+    fn bits(&self) -> u8 {
+        match self {
+            Prescaler::Div1 => 1,
+            Prescaler::Div8 => 2,
+            Prescaler::Div32 => 3,
+            Prescaler::Div64 => 4,
+            Prescaler::Div128 => 5,
+            Prescaler::Div256 => 6,
+            Prescaler::Div1024 => 7,
+        }
+    }
+}
+
+/// Overflows counted since the timer was started.
+static mut OVERFLOWS: u32 = 0;
+
+/// True while the timer runs in asynchronous mode.
+static mut RUNNING: bool = false;
+
+/// The timer running from its own clock.
+/// # Elements
+/// * `source` - a `Source`, what clocks the timer.
+/// * `prescaler` - a `Prescaler`, the division of that clock.
+pub struct AsyncTimer {
+    source: Source,
+    prescaler: Prescaler,
+}
+
+/// Waits until the writes to the timer registers reached the asynchronous clock domain.
+fn synchronise() {
+    while unsafe { read_volatile(ASSR) } & UPDATE_BUSY != 0 {}
+}
+
+impl AsyncTimer {
+    /// Switches Timer2 to the asynchronous clock, counting from zero, and enables
+    /// its overflow interrupt. A crystal needs up to a second to settle after power up.
+    /// # Arguments
+    /// * `source` - a `Source`, what clocks the timer.
+    /// * `prescaler` - a `Prescaler`, `Div128` for one overflow per second with the crystal,
+    ///   `Div1` to count every pulse of an external source.
+    /// # Returns
+    /// * `a AsyncTimer object` - Which will be used for further implementations.
+    pub fn start(source: Source, prescaler: Prescaler) -> AsyncTimer {
+        interrupts::free(|| unsafe {
+            let power = Power::new();
+            write_volatile(&mut power.prr0, read_volatile(&power.prr0) & !(1 << 6));
+            // The interrupts are disabled while the clock is switched, as the
+            // registers may be corrupted meanwhile. EXCLK goes before AS2.
+            write_volatile(TIMSK2, 0);
+            let exclk = if source == Source::External { EXCLK } else { 0 };
+            write_volatile(ASSR, exclk);
+            write_volatile(ASSR, exclk | AS2);
+            write_volatile(TCNT2, 0);
+            write_volatile(TCCR2A, 0);
+            write_volatile(TCCR2B, prescaler.bits());
+            synchronise();
+            OVERFLOWS = 0;
+            RUNNING = true;
+            write_volatile(TIFR2, 0x07);
+            write_volatile(TIMSK2, TOIE2);
+            interrupts::Interrupt::enable(&mut interrupts::Interrupt::new());
+        });
+        AsyncTimer { source, prescaler }
+    }
+
+    /// Returns what clocks the timer.
+    pub fn source(&self) -> Source {
+        self.source
+    }
+
+    /// Returns the division of the timer clock.
+    pub fn prescaler(&self) -> Prescaler {
+        self.prescaler
+    }
+
+    /// Returns the number of overflows since the timer was started.
+    /// With the crystal and `Div128`, these are the seconds.
+    pub fn overflows(&self) -> u32 {
+        interrupts::free(|| unsafe { OVERFLOWS })
+    }
+
+    /// Returns the number of timer ticks since the timer was started,
+    /// which are the pulses received on TOSC1 for an external source and `Div1`.
+    /// # Returns
+    /// * `a u32` - The ticks, wrapping around after 2^32.
+    pub fn count(&self) -> u32 {
+        self.ticks() as u32
+    }
+
+    /// Returns the ticks since the timer was started without wrapping around.
+    fn ticks(&self) -> u64 {
+        interrupts::free(|| unsafe {
+            let mut count = read_volatile(TCNT2);
+            let mut overflows = OVERFLOWS as u64;
+            // An overflow not yet served is counted here.
+            if read_volatile(TIFR2) & TOIE2 != 0 {
+                count = read_volatile(TCNT2);
+                overflows += 1;
+            }
+            overflows * 256 + count as u64
+        })
+    }
+
+    /// Returns the time counted by the crystal since the timer was started.
+    /// # Returns
+    /// * `a u32` - The milliseconds, meaningless for an external source.
+    pub fn millis(&self) -> u32 {
+        let ticks = self.ticks() * self.prescaler.divider() as u64;
+        (ticks * 1000 / CRYSTAL_HZ as u64) as u32
+    }
+
+    /// Returns the whole seconds counted by the crystal since the timer was started.
+    pub fn seconds(&self) -> u32 {
+        let ticks = self.ticks() * self.prescaler.divider() as u64;
+        (ticks / CRYSTAL_HZ as u64) as u32
+    }
+
+    /// Returns the time between two overflows, the longest sleep of `sleep`.
+    /// # Returns
+    /// * `a u32` - The period in milliseconds for the crystal.
+    pub fn period_ms(&self) -> u32 {
+        256 * self.prescaler.divider() * 1000 / CRYSTAL_HZ
+    }
+
+    /// Puts the MCU in power-save mode until the next overflow or another interrupt.
+    pub fn sleep(&self) {
+        sleep();
+    }
+
+    /// Stops the timer and switches it back to the system clock.
+    /// # Returns
+    /// * `a u32` - The ticks counted, as given by `count`.
+    pub fn stop(self) -> u32 {
+        let count = self.count();
+        interrupts::free(|| unsafe {
+            write_volatile(TIMSK2, 0);
+            write_volatile(TCCR2B, 0);
+            synchronise();
+            write_volatile(ASSR, 0);
+            write_volatile(TIFR2, 0x07);
+            RUNNING = false;
+        });
+        count
+    }
+}
+
+/// Puts the MCU in power-save mode, where Timer2 keeps running from its
+/// asynchronous clock, until its overflow or another interrupt wakes it up.
+/// After a wake up the timer needs one cycle of its clock before the MCU may
+/// sleep again, or the interrupt logic is not reset, so a register of the timer
+/// is written and the write waited for first.
+/// Global interrupts must be enabled.
+pub fn sleep() {
+    unsafe {
+        if RUNNING {
+            write_volatile(TCCR2A, read_volatile(TCCR2A));
+            synchronise();
+        }
+    }
+    sleep_mode::power_save();
+}
+
+/// Returns true if the timer runs in asynchronous mode.
+pub fn is_running() -> bool {
+    unsafe { read_volatile(addr_of!(RUNNING)) }
+}
+
+/// Counts an overflow, called by the Timer2 overflow interrupt service routine.
+/// # Returns
+/// * `a boolean` - false if the timer is not in asynchronous mode and the interrupt belongs to someone else.
+pub(crate) unsafe fn overflow() -> bool {
+    if !RUNNING {
+        return false;
+    }
+    OVERFLOWS = OVERFLOWS.wrapping_add(1);
+    true
+}
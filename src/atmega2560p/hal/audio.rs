@@ -0,0 +1,280 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Playback of 8-bit PCM audio through fast PWM on Timer/Counter2.
+//! The timer runs at 62.5 kHz on a 16 MHz clock, above the audible range,
+//! and its overflow interrupt moves to the next sample at the chosen sample
+//! rate. Samples are played from two RAM buffers, one of them being refilled
+//! by `AudioPlayer::poll` from the main loop while the other one plays, so the
+//! samples can come from program memory or any other `SampleSource`.
+//! The output is pin 9 (PH6), which needs a low pass filter (for example 1 kOhm
+//! and 100 nF) and an amplifier to drive a speaker.
+//! Tones from `hal::tone` cannot be played at the same time, as they use Timer2 as well.
+//! The module needs the `audio` feature, because its interrupt service
+//! routine takes the Timer2 overflow vector.
+//! Section 20 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::port::{Pin, PortName};
+use crate::atmega2560p::hal::power::Power;
+use crate::config::CPU_FREQUENCY_HZ;
+use crate::progmem::ProgMem;
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+
+/// Addresses of the Timer/Counter2 registers.
+const TCCR2A: *mut u8 = 0xB0 as *mut u8;
+const TCCR2B: *mut u8 = 0xB1 as *mut u8;
+const OCR2B: *mut u8 = 0xB4 as *mut u8;
+const TIMSK2: *mut u8 = 0x70 as *mut u8;
+
+/// Port and bit of the OC2B output.
+const OC2B_PORT: PortName = PortName::H;
+const OC2B_BIT: usize = 6;
+
+/// Number of samples in each of the two buffers.
+pub const AUDIO_BUFFER_SIZE: usize = 64;
+
+/// Value of a sample at the middle of the range, output while nothing plays.
+const SILENCE: u8 = 0x80;
+
+/// Rate of the PWM carrier, one timer overflow per 256 clock cycles.
+const CARRIER_RATE: u32 = CPU_FREQUENCY_HZ / 256;
+
+/// A provider of unsigned 8-bit samples.
+pub trait SampleSource {
+    /// Copies the next samples.
+    /// # Arguments
+    /// * `buffer` - a mutable reference to `[u8]`, filled with samples.
+    /// # Returns
+    /// * `a usize` - The number of samples copied, 0 once the source is exhausted.
+    fn fill(&mut self, buffer: &mut [u8]) -> usize;
+}
+
+/// Samples stored in program memory, declared with `progmem!`.
+/// # Elements
+/// * `samples` - a reference to the samples.
+/// * `position` - a usize, the next sample to be read.
+pub struct FlashSamples<const N: usize> {
+    samples: &'static ProgMem<[u8; N]>,
+    position: usize,
+}
+
+impl<const N: usize> FlashSamples<N> {
+    /// Creates a source playing the samples from the start.
+    /// # Arguments
+    /// * `samples` - a reference to the samples in program memory.
+    /// # Returns
+    /// * `a FlashSamples object` - The source.
+    pub fn new(samples: &'static ProgMem<[u8; N]>) -> FlashSamples<N> {
+        FlashSamples {
+            samples,
+            position: 0,
+        }
+    }
+
+    /// Moves back to the first sample.
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+}
+
+impl<const N: usize> SampleSource for FlashSamples<N> {
+    fn fill(&mut self, buffer: &mut [u8]) -> usize {
+        let count = core::cmp::min(buffer.len(), N - self.position);
+        self.samples.load_into(self.position, &mut buffer[..count]);
+        self.position += count;
+        count
+    }
+}
+
+/// Buffers shared with the interrupt service routine.
+/// # Elements
+/// * `samples` - the two buffers.
+/// * `length` - the number of valid samples in each buffer, 0 once it has been played.
+/// * `active` - the buffer being played.
+/// * `index` - the next sample of the active buffer.
+/// * `phase` - accumulates the sample rate step, a sample is taken when it overflows.
+/// * `step` - added to `phase` on every timer overflow.
+struct AudioState {
+    samples: [[u8; AUDIO_BUFFER_SIZE]; 2],
+    length: [u8; 2],
+    active: u8,
+    index: u8,
+    phase: u16,
+    step: u16,
+}
+
+static mut AUDIO: AudioState = AudioState {
+    samples: [[SILENCE; AUDIO_BUFFER_SIZE]; 2],
+    length: [0; 2],
+    active: 0,
+    index: 0,
+    phase: 0,
+    step: 0,
+};
+
+/// Plays the samples of a `SampleSource`.
+/// # Elements
+/// * `source` - the source of the samples.
+/// * `step` - a u16, the phase step of the sample rate.
+/// * `playing` - a boolean, true between `start` and the end of the samples or `stop`.
+pub struct AudioPlayer<S: SampleSource> {
+    source: S,
+    step: u16,
+    playing: bool,
+}
+
+impl<S: SampleSource> AudioPlayer<S> {
+    /// Creates a player.
+    /// # Arguments
+    /// * `source` - a `SampleSource` object, for example `FlashSamples`.
+    /// * `sample_rate` - a u16, the rate in Hz, typically 8000 to 16000.
+    /// # Returns
+    /// * `a Option<AudioPlayer>` - `None` if the rate is 0 or above the PWM carrier rate.
+    pub fn new(source: S, sample_rate: u16) -> Option<AudioPlayer<S>> {
+        if sample_rate == 0 || sample_rate as u32 >= CARRIER_RATE {
+            return None;
+        }
+        Some(AudioPlayer {
+            source,
+            step: (((sample_rate as u32) << 16) / CARRIER_RATE) as u16,
+            playing: false,
+        })
+    }
+
+    /// Gives back the source.
+    pub fn release(mut self) -> S {
+        self.stop();
+        self.source
+    }
+
+    /// Fills both buffers and starts the playback, taking over Timer2.
+    pub fn start(&mut self) {
+        self.stop();
+        let mut first = [SILENCE; AUDIO_BUFFER_SIZE];
+        let mut second = [SILENCE; AUDIO_BUFFER_SIZE];
+        let lengths = [
+            self.source.fill(&mut first) as u8,
+            self.source.fill(&mut second) as u8,
+        ];
+        if lengths[0] == 0 {
+            return;
+        }
+        interrupts::free(|| unsafe {
+            let state = &mut *addr_of_mut!(AUDIO);
+            state.samples = [first, second];
+            state.length = lengths;
+            state.active = 0;
+            state.index = 0;
+            state.phase = 0;
+            state.step = self.step;
+
+            let power = Power::new();
+            write_volatile(&mut power.prr0, read_volatile(&power.prr0) & !(1 << 6));
+            if let Some(mut pin) = Pin::new(OC2B_PORT, OC2B_BIT) {
+                pin.set_output();
+            }
+            // Fast PWM (WGM22:0 = 011), non inverting output on OC2B, no prescaler.
+            write_volatile(OCR2B, SILENCE);
+            write_volatile(TCCR2A, 0x23);
+            write_volatile(TCCR2B, 0x01);
+            // Enable the overflow interrupt (TOIE2).
+            write_volatile(TIMSK2, read_volatile(TIMSK2) | 0x01);
+            interrupts::Interrupt::enable(&mut interrupts::Interrupt::new());
+        });
+        self.playing = true;
+    }
+
+    /// Refills the buffer which has been played, must be called more often than
+    /// a buffer lasts, every 4 ms at 16 kHz.
+    /// # Returns
+    /// * `a boolean` - true while samples are left to be played.
+    pub fn poll(&mut self) -> bool {
+        if !self.playing {
+            return false;
+        }
+        let (free, other_empty) = interrupts::free(|| unsafe {
+            let free = AUDIO.active as usize ^ 1;
+            (
+                if AUDIO.length[free] == 0 {
+                    Some(free)
+                } else {
+                    None
+                },
+                AUDIO.length[AUDIO.active as usize] == 0,
+            )
+        });
+        if let Some(free) = free {
+            let mut samples = [SILENCE; AUDIO_BUFFER_SIZE];
+            let length = self.source.fill(&mut samples) as u8;
+            if length == 0 && other_empty {
+                self.stop();
+                return false;
+            }
+            interrupts::free(|| unsafe {
+                AUDIO.samples[free] = samples;
+                AUDIO.length[free] = length;
+            });
+        }
+        true
+    }
+
+    /// Stops the playback and releases Timer2.
+    pub fn stop(&mut self) {
+        interrupts::free(|| unsafe {
+            write_volatile(TIMSK2, read_volatile(TIMSK2) & !0x01);
+            write_volatile(TCCR2A, 0);
+            write_volatile(TCCR2B, 0);
+            AUDIO.length = [0; 2];
+        });
+        self.playing = false;
+    }
+
+    /// Returns true while the samples are being played.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+}
+
+/// Timer/Counter2 overflow interrupt service routine, which counts the overflows
+/// of `hal::async_timer` instead while it runs.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_15"]
+pub unsafe extern "avr-interrupt" fn timer2_overflow() {
+    #[cfg(feature = "async-timer")]
+    if crate::atmega2560p::hal::async_timer::overflow() {
+        return;
+    }
+    let state = &mut *addr_of_mut!(AUDIO);
+    let (phase, carry) = state.phase.overflowing_add(state.step);
+    state.phase = phase;
+    if !carry {
+        return;
+    }
+    let active = state.active as usize;
+    if state.index >= state.length[active] {
+        // The buffer is played, switch to the other one if it was refilled.
+        state.length[active] = 0;
+        if state.length[active ^ 1] == 0 {
+            write_volatile(OCR2B, SILENCE);
+            return;
+        }
+        state.active ^= 1;
+        state.index = 0;
+    }
+    let active = state.active as usize;
+    write_volatile(OCR2B, state.samples[active][state.index as usize]);
+    state.index += 1;
+}
@@ -0,0 +1,263 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Debouncing of buttons, switches and relay contacts, whose level bounces
+//! for a few milliseconds at every change.
+//! A `DebouncedPin` wraps any input pin and is updated from the main loop or
+//! a periodic task. A new level is only accepted once it has been read for
+//! the whole window, given either as a time, measured on a `Clocked` clock,
+//! or as a number of consecutive samples, when the updates are regular.
+//! `rose` and `fell` tell the level changes seen by the last update, and
+//! `is_pressed` the state of the button, which is low for a button to ground.
+//! The module needs the `millis` feature, which runs `HardwareClock`.
+
+use crate::time::{Clocked, HardwareClock};
+use core::convert::Infallible;
+use embedded_hal::digital::{ErrorType, InputPin};
+
+/// The window a new level must last before it is accepted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Debounce {
+    /// A time in milliseconds, 5 to 20 suits most buttons.
+    Millis(u16),
+    /// A number of updates in a row.
+    Samples(u8),
+}
+
+/// An input pin whose level is debounced, see the module documentation.
+/// # Elements
+/// * `pin` - an `InputPin` object, the pin read.
+/// * `clock` - a `Clocked` object, measuring the windows given as a time.
+/// * `window` - a `Debounce` object, the window of a new level.
+/// * `active_low` - a boolean, true if the button pulls the pin low when pressed.
+/// * `level` - a boolean, the debounced level.
+/// * `changing` - a boolean, true while the pin reads the other level.
+/// * `since` - a u32, the time at which the pin first read the other level.
+/// * `samples` - a u8, the number of updates in a row which read the other level.
+/// * `rose` - a boolean, true if the last update accepted a high level.
+/// * `fell` - a boolean, true if the last update accepted a low level.
+pub struct DebouncedPin<P, C = HardwareClock> {
+    pin: P,
+    clock: C,
+    window: Debounce,
+    active_low: bool,
+    level: bool,
+    changing: bool,
+    since: u32,
+    samples: u8,
+    rose: bool,
+    fell: bool,
+}
+
+impl<P: InputPin> DebouncedPin<P, HardwareClock> {
+    /// Debounces a pin on the `millis` counter, which must have been started
+    /// by `millis_init` for the windows given as a time.
+    /// The pin is taken as a button to ground, see `set_active_low`.
+    /// # Arguments
+    /// * `pin` - an `InputPin` object, already an input, usually pulled up.
+    /// * `window` - a `Debounce` object, the window of a new level.
+    /// # Returns
+    /// * `a DebouncedPin object` - which will be used for further implementations.
+    pub fn new(pin: P, window: Debounce) -> DebouncedPin<P, HardwareClock> {
+        DebouncedPin::with_clock(pin, HardwareClock::new(), window)
+    }
+}
+
+impl<P: InputPin, C: Clocked> DebouncedPin<P, C> {
+    /// Debounces a pin on the given clock, starting from its current level.
+    /// # Arguments
+    /// * `pin` - an `InputPin` object, already an input, usually pulled up.
+    /// * `clock` - a `Clocked` object, for example a `VirtualClock` in tests.
+    /// * `window` - a `Debounce` object, the window of a new level.
+    /// # Returns
+    /// * `a DebouncedPin object` - which will be used for further implementations.
+    pub fn with_clock(mut pin: P, clock: C, window: Debounce) -> DebouncedPin<P, C> {
+        let level = pin.is_high().unwrap_or(true);
+        DebouncedPin {
+            pin,
+            clock,
+            window,
+            active_low: true,
+            level,
+            changing: false,
+            since: 0,
+            samples: 0,
+            rose: false,
+            fell: false,
+        }
+    }
+
+    /// Chooses the level of a pressed button, low by default.
+    /// # Arguments
+    /// * `active_low` - a boolean, false for a button to VCC with a pull down resistor.
+    pub fn set_active_low(&mut self, active_low: bool) {
+        self.active_low = active_low;
+    }
+
+    /// Reads the pin and accepts its level if it has lasted for the window.
+    /// Call it more often than the window, or at every sample.
+    /// # Returns
+    /// * `a boolean` - true if the debounced level changed.
+    pub fn update(&mut self) -> bool {
+        self.rose = false;
+        self.fell = false;
+        let reading = match self.pin.is_high() {
+            Ok(reading) => reading,
+            Err(_) => return false,
+        };
+
+        if reading == self.level {
+            self.changing = false;
+            return false;
+        }
+        if !self.changing {
+            self.changing = true;
+            self.since = self.clock.millis();
+            self.samples = 0;
+        }
+        self.samples = self.samples.saturating_add(1);
+
+        let lasted = match self.window {
+            Debounce::Millis(ms) => self.clock.millis().wrapping_sub(self.since) >= ms as u32,
+            Debounce::Samples(count) => self.samples >= count,
+        };
+        if !lasted {
+            return false;
+        }
+        self.level = reading;
+        self.changing = false;
+        self.rose = reading;
+        self.fell = !reading;
+        true
+    }
+
+    /// Returns the debounced level of the pin.
+    pub fn is_high(&self) -> bool {
+        self.level
+    }
+
+    /// Returns true if the button is pressed, see `set_active_low`.
+    pub fn is_pressed(&self) -> bool {
+        self.level != self.active_low
+    }
+
+    /// Returns true if the last update accepted a high level.
+    pub fn rose(&self) -> bool {
+        self.rose
+    }
+
+    /// Returns true if the last update accepted a low level.
+    pub fn fell(&self) -> bool {
+        self.fell
+    }
+
+    /// Returns the clock, so that a simulated one can be moved forward.
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Gives back the pin and the clock.
+    pub fn release(self) -> (P, C) {
+        (self.pin, self.clock)
+    }
+}
+
+// A debounced pin can be given to drivers expecting an input pin,
+// which read the debounced level without updating it.
+impl<P, C> ErrorType for DebouncedPin<P, C> {
+    type Error = Infallible;
+}
+
+impl<P: InputPin, C: Clocked> InputPin for DebouncedPin<P, C> {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.level)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::time::VirtualClock;
+    use core::cell::Cell;
+
+    /// A pin whose level is set by the test.
+    struct Button<'a>(&'a Cell<bool>);
+
+    impl<'a> ErrorType for Button<'a> {
+        type Error = Infallible;
+    }
+
+    impl<'a> InputPin for Button<'a> {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0.get())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0.get())
+        }
+    }
+
+    #[test]
+    fn time_window() {
+        let level = Cell::new(true);
+        let mut pin =
+            DebouncedPin::with_clock(Button(&level), VirtualClock::new(), Debounce::Millis(10));
+        assert!(pin.is_high() && !pin.is_pressed());
+
+        // A bounce shorter than the window is ignored.
+        level.set(false);
+        assert!(!pin.update());
+        pin.clock().delay_ms(4);
+        level.set(true);
+        assert!(!pin.update());
+
+        // A press lasting the window is accepted once.
+        level.set(false);
+        assert!(!pin.update());
+        pin.clock().delay_ms(9);
+        assert!(!pin.update());
+        pin.clock().delay_ms(1);
+        assert!(pin.update());
+        assert!(pin.fell() && !pin.rose() && pin.is_pressed());
+        assert!(!pin.update());
+        assert!(!pin.fell());
+    }
+
+    #[test]
+    fn sample_window() {
+        let level = Cell::new(false);
+        let mut pin =
+            DebouncedPin::with_clock(Button(&level), VirtualClock::new(), Debounce::Samples(3));
+        pin.set_active_low(false);
+        assert!(!pin.is_pressed());
+
+        level.set(true);
+        assert!(!pin.update());
+        assert!(!pin.update());
+        level.set(false);
+        assert!(!pin.update());
+        level.set(true);
+        assert!(!pin.update());
+        assert!(!pin.update());
+        assert!(pin.update());
+        assert!(pin.rose() && pin.is_pressed());
+        assert_eq!(InputPin::is_high(&mut pin), Ok(true));
+    }
+}
@@ -0,0 +1,362 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Phase control of AC loads like incandescent lamps and universal motors,
+//! through triacs whose gates are fired a delay after each zero crossing of
+//! the mains. The zero crossings come from an optocoupler on INT0 (digital
+//! pin 21, shared with the I2C clock), rising edges, or a stepped down mains
+//! signal on the analog comparator, between AIN0 (PE2, not on the Arduino
+//! Mega headers) and AIN1 (digital pin 5).
+//! Timer/Counter1 restarts at each crossing and its compare match interrupts
+//! fire the gates as one-shots, A at the delay of each channel and B at the
+//! end of the gate pulses, so no interrupt runs between the events.
+//! The length of a half wave is measured at every crossing, so 50 and 60 Hz
+//! mains both work. Timer1 is taken over, so PWM output on pins 11 and 12 does not work meanwhile.
+//! The module needs the `dimmer` feature, because its interrupt service routines
+//! take the Timer1 compare A and B and analog comparator vectors.
+//! Section 17 of the manual.
+
+use crate::atmega2560p::hal::external_interrupt::{ExternalInterrupt, Trigger};
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::port::Pin;
+use crate::atmega2560p::hal::power::Power;
+use crate::config::CPU_FREQUENCY_HZ;
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+use embedded_hal::digital::OutputPin;
+
+/// Addresses of the Timer/Counter1 registers.
+const TCCR1A: *mut u8 = 0x80 as *mut u8;
+const TCCR1B: *mut u8 = 0x81 as *mut u8;
+const TCNT1L: *mut u8 = 0x84 as *mut u8;
+const TCNT1H: *mut u8 = 0x85 as *mut u8;
+const OCR1AL: *mut u8 = 0x88 as *mut u8;
+const OCR1AH: *mut u8 = 0x89 as *mut u8;
+const OCR1BL: *mut u8 = 0x8A as *mut u8;
+const OCR1BH: *mut u8 = 0x8B as *mut u8;
+const TIMSK1: *mut u8 = 0x6F as *mut u8;
+const TIFR1: *mut u8 = 0x36 as *mut u8;
+
+/// Address of the analog comparator control and status register.
+const ACSR: *mut u8 = 0x50 as *mut u8;
+
+/// Timer1 runs at an eighth of the CPU clock.
+const TICKS_PER_SECOND: u32 = CPU_FREQUENCY_HZ / 8;
+
+/// Length of the gate pulses, 100 microseconds.
+const GATE_TICKS: u16 = (TICKS_PER_SECOND / 10_000) as u16;
+
+/// Number of triacs which can be controlled.
+pub const DIMMER_CHANNELS: usize = 4;
+
+/// Sources of the zero crossing signal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ZeroCross {
+    /// Rising edges on INT0, from a zero crossing detector module.
+    Int0,
+    /// Both edges of the analog comparator output.
+    Comparator,
+}
+
+/// A triac gate output.
+/// # Elements
+/// * `port` - the address of the PORT register of the gate pin, null when the channel is not attached.
+/// * `mask` - a u8, the bit of the gate pin in the register.
+/// * `level` - a u8, the brightness in percent.
+/// * `delay` - a u16, the ticks from the crossing to the gate pulse, 0 to keep the triac off.
+/// * `fired` - a boolean, true once the gate was fired in the current half wave.
+#[derive(Clone, Copy)]
+struct Channel {
+    port: *mut u8,
+    mask: u8,
+    level: u8,
+    delay: u16,
+    fired: bool,
+}
+
+/// State shared with the interrupt service routines.
+/// # Elements
+/// * `channels` - the gate outputs.
+/// * `half_wave` - a u16, the measured length of a half wave in ticks.
+struct DimmerState {
+    channels: [Channel; DIMMER_CHANNELS],
+    half_wave: u16,
+}
+
+static mut DIMMER: DimmerState = DimmerState {
+    channels: [Channel {
+        port: core::ptr::null_mut(),
+        mask: 0,
+        level: 0,
+        delay: 0,
+        fired: false,
+    }; DIMMER_CHANNELS],
+    half_wave: 0,
+};
+
+/// Reads the 16 bit counter, low byte first so that the high byte is latched.
+unsafe fn read_counter() -> u16 {
+    let low = read_volatile(TCNT1L) as u16;
+    let high = read_volatile(TCNT1H) as u16;
+    (high << 8) | low
+}
+
+/// Writes a 16 bit register, high byte first as it goes to the temporary register.
+unsafe fn write_wide(high: *mut u8, low: *mut u8, value: u16) {
+    write_volatile(high, (value >> 8) as u8);
+    write_volatile(low, value as u8);
+}
+
+/// Converts a brightness to the delay of the gate pulse.
+/// The delay is linear in phase, which looks about linear in brightness for lamps.
+fn delay_for(level: u8, half_wave: u16) -> u16 {
+    if level == 0 || half_wave <= 3 * GATE_TICKS {
+        return 0;
+    }
+    let span = (half_wave - 3 * GATE_TICKS) as u32;
+    GATE_TICKS + (span * (100 - level.min(100)) as u32 / 100) as u16
+}
+
+/// Sets compare A to the next channel due after `now`, or disables it.
+unsafe fn schedule_fire(state: &mut DimmerState, now: u16) {
+    let next = state
+        .channels
+        .iter()
+        .filter(|channel| !channel.fired && channel.delay != 0 && !channel.port.is_null())
+        .map(|channel| channel.delay.max(now + 1))
+        .min();
+    match next {
+        Some(at) => {
+            write_wide(OCR1AH, OCR1AL, at);
+            write_volatile(TIFR1, 0x02);
+            write_volatile(TIMSK1, read_volatile(TIMSK1) | 0x02);
+        }
+        None => write_volatile(TIMSK1, read_volatile(TIMSK1) & !0x02),
+    }
+}
+
+/// Starts a half wave: restarts the timer, measures the last half wave and computes the delays.
+unsafe fn zero_cross() {
+    let state = &mut *addr_of_mut!(DIMMER);
+    let elapsed = read_counter();
+    write_wide(TCNT1H, TCNT1L, 0);
+
+    // Only crossings from 40 to 70 Hz mains update the measure, the others are noise.
+    let shortest = (TICKS_PER_SECOND / 140) as u16;
+    let longest = (TICKS_PER_SECOND / 80) as u16;
+    if (shortest..=longest).contains(&elapsed) {
+        state.half_wave = if state.half_wave == 0 {
+            elapsed
+        } else {
+            ((state.half_wave as u32 * 3 + elapsed as u32) / 4) as u16
+        };
+    } else if elapsed < shortest {
+        // A bounce of the detector right after the crossing, keep the half wave going.
+        write_wide(TCNT1H, TCNT1L, elapsed);
+        return;
+    }
+
+    let half_wave = state.half_wave;
+    for channel in state.channels.iter_mut() {
+        if !channel.port.is_null() {
+            write_volatile(channel.port, read_volatile(channel.port) & !channel.mask);
+        }
+        channel.fired = false;
+        channel.delay = delay_for(channel.level, half_wave);
+    }
+    write_volatile(TIMSK1, read_volatile(TIMSK1) & !0x04);
+    schedule_fire(state, 0);
+}
+
+/// Starts the dimmer, with all channels off.
+/// # Arguments
+/// * `source` - a `ZeroCross` object, where the zero crossings come from.
+/// * `frequency` - a u8, the nominal mains frequency in Hz, used till the first half wave is measured.
+pub fn start(source: ZeroCross, frequency: u8) {
+    interrupts::free(|| unsafe {
+        let state = &mut *addr_of_mut!(DIMMER);
+        state.half_wave = (TICKS_PER_SECOND / (2 * frequency.clamp(40, 70) as u32)) as u16;
+
+        let power = Power::new();
+        write_volatile(&mut power.prr0, read_volatile(&power.prr0) & !(1 << 3));
+        // Normal mode, prescaler 8, no output on the compare pins.
+        write_volatile(TCCR1A, 0);
+        write_volatile(TCCR1B, 0x02);
+        write_volatile(TIMSK1, read_volatile(TIMSK1) & !0x07);
+
+        match source {
+            ZeroCross::Int0 => {
+                if let Some(mut int0) = ExternalInterrupt::new(0) {
+                    int0.attach(Trigger::Rising, int0_crossing);
+                }
+            }
+            ZeroCross::Comparator => {
+                // Interrupt on output toggle (ACIS1:0 = 00), AIN1 as negative input.
+                write_volatile(ACSR, 0x10);
+                write_volatile(ACSR, 0x08);
+            }
+        }
+        interrupts::Interrupt::enable(&mut interrupts::Interrupt::new());
+    })
+}
+
+/// Stops the dimmer and turns all channels off.
+pub fn stop() {
+    interrupts::free(|| unsafe {
+        if let Some(mut int0) = ExternalInterrupt::new(0) {
+            int0.detach();
+        }
+        write_volatile(ACSR, read_volatile(ACSR) & !0x08);
+        write_volatile(TIMSK1, read_volatile(TIMSK1) & !0x07);
+        write_volatile(TCCR1B, 0);
+        for channel in (*addr_of_mut!(DIMMER)).channels.iter_mut() {
+            if !channel.port.is_null() {
+                write_volatile(channel.port, read_volatile(channel.port) & !channel.mask);
+            }
+            channel.level = 0;
+            channel.delay = 0;
+        }
+    })
+}
+
+/// Connects a triac gate driver to a channel, off at first.
+/// # Arguments
+/// * `channel` - a usize, the channel number below `DIMMER_CHANNELS`.
+/// * `pin` - a `Pin` object, the pin driving the optotriac of the gate.
+/// # Returns
+/// * `a boolean` - false if there is no such channel.
+pub fn attach(channel: usize, mut pin: Pin) -> bool {
+    if channel >= DIMMER_CHANNELS {
+        return false;
+    }
+    let _ = pin.set_low();
+    pin.set_output();
+    interrupts::free(|| unsafe {
+        let channel = &mut DIMMER.channels[channel];
+        channel.port = &mut (*pin.port).port as *mut u8;
+        channel.mask = 1 << pin.pin;
+        channel.level = 0;
+        channel.delay = 0;
+        channel.fired = false;
+    });
+    true
+}
+
+/// Disconnects a channel, leaving its pin low.
+/// # Arguments
+/// * `channel` - a usize, the channel number.
+pub fn detach(channel: usize) {
+    if channel >= DIMMER_CHANNELS {
+        return;
+    }
+    interrupts::free(|| unsafe {
+        let channel = &mut DIMMER.channels[channel];
+        if !channel.port.is_null() {
+            write_volatile(channel.port, read_volatile(channel.port) & !channel.mask);
+        }
+        channel.port = core::ptr::null_mut();
+        channel.level = 0;
+    })
+}
+
+/// Sets the brightness of a channel, used from the next half wave.
+/// # Arguments
+/// * `channel` - a usize, the channel number.
+/// * `percent` - a u8, from 0 for off to 100 for fully on.
+pub fn set_level(channel: usize, percent: u8) {
+    if channel >= DIMMER_CHANNELS {
+        return;
+    }
+    interrupts::free(|| unsafe { DIMMER.channels[channel].level = percent.min(100) })
+}
+
+/// Returns the brightness of a channel in percent.
+/// # Arguments
+/// * `channel` - a usize, the channel number.
+pub fn level(channel: usize) -> u8 {
+    if channel >= DIMMER_CHANNELS {
+        return 0;
+    }
+    interrupts::free(|| unsafe { DIMMER.channels[channel].level })
+}
+
+/// Returns the mains frequency measured from the zero crossings.
+/// # Returns
+/// * `a u16` - The frequency in tenths of Hz.
+pub fn frequency() -> u16 {
+    let half_wave = interrupts::free(|| unsafe { DIMMER.half_wave });
+    if half_wave == 0 {
+        return 0;
+    }
+    (TICKS_PER_SECOND * 5 / half_wave as u32) as u16
+}
+
+/// Handler of external interrupt 0, a zero crossing.
+fn int0_crossing() {
+    unsafe { zero_cross() }
+}
+
+/// Analog comparator interrupt service routine, a zero crossing.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_28"]
+pub unsafe extern "avr-interrupt" fn analog_comparator() {
+    zero_cross();
+}
+
+/// Timer/Counter1 compare match A interrupt service routine, fires the gates which are due.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_17"]
+pub unsafe extern "avr-interrupt" fn timer1_compare_a() {
+    let state = &mut *addr_of_mut!(DIMMER);
+    let now = read_counter();
+    let mut fired = false;
+    for channel in state.channels.iter_mut() {
+        if !channel.fired && channel.delay != 0 && !channel.port.is_null() && channel.delay <= now {
+            write_volatile(channel.port, read_volatile(channel.port) | channel.mask);
+            channel.fired = true;
+            fired = true;
+        }
+    }
+    if fired {
+        write_wide(OCR1BH, OCR1BL, now + GATE_TICKS);
+        write_volatile(TIFR1, 0x04);
+        write_volatile(TIMSK1, read_volatile(TIMSK1) | 0x04);
+    }
+    schedule_fire(state, now);
+}
+
+/// Timer/Counter1 compare match B interrupt service routine, ends the gate pulses.
+/// The triacs stay on by themselves till the next zero crossing.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_18"]
+pub unsafe extern "avr-interrupt" fn timer1_compare_b() {
+    let state = &mut *addr_of_mut!(DIMMER);
+    let now = read_counter();
+    let mut next: Option<u16> = None;
+    for channel in state.channels.iter_mut() {
+        if !channel.fired || channel.port.is_null() {
+            continue;
+        }
+        let end = channel.delay + GATE_TICKS;
+        if end <= now + 1 {
+            write_volatile(channel.port, read_volatile(channel.port) & !channel.mask);
+        } else {
+            next = Some(next.map_or(end, |next| next.min(end)));
+        }
+    }
+    match next {
+        Some(end) => write_wide(OCR1BH, OCR1BL, end),
+        None => write_volatile(TIMSK1, read_volatile(TIMSK1) & !0x04),
+    }
+}
@@ -0,0 +1,238 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! External interrupts INT0 to INT7, which run a callback on a level or an
+//! edge of their pin. INT0 to INT3 are on digital pins 21, 20, 19 and 18
+//! (PD0 to PD3), INT4 and INT5 on digital pins 2 and 3 (PE4, PE5); INT6 and
+//! INT7 (PE6, PE7) are not on the Arduino Mega headers.
+//! The pin is not configured here, it has to be an input, with its pull up
+//! enabled if nothing drives it. Edges are detected even while the pin is an
+//! output, so an interrupt can also be triggered by software.
+//! This module owns the interrupt vectors of INT0 to INT7: the other drivers
+//! using an external interrupt, like `dimmer` and `powerfail`, attach their
+//! handlers through it.
+//! The module needs the `external-interrupt` feature, because its interrupt
+//! service routines take the INT0 to INT7 vectors.
+//! Section 15 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Addresses of the external interrupt registers.
+const EICRA: *mut u8 = 0x69 as *mut u8;
+const EICRB: *mut u8 = 0x6A as *mut u8;
+const EIMSK: *mut u8 = 0x3D as *mut u8;
+const EIFR: *mut u8 = 0x3C as *mut u8;
+
+/// Number of external interrupts.
+pub const EXTERNAL_INTERRUPTS: u8 = 8;
+
+/// Conditions on which an external interrupt fires.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Trigger {
+    /// As long as the pin is low, the handler runs again and again.
+    LowLevel = 0,
+    /// On both edges.
+    Change = 1,
+    /// On falling edges.
+    Falling = 2,
+    /// On rising edges.
+    Rising = 3,
+}
+
+static mut HANDLERS: [Option<fn()>; EXTERNAL_INTERRUPTS as usize] =
+    [None; EXTERNAL_INTERRUPTS as usize];
+
+/// One of the external interrupts INT0 to INT7.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ExternalInterrupt {
+    number: u8,
+}
+
+impl ExternalInterrupt {
+    /// Gives access to an external interrupt.
+    /// # Arguments
+    /// * `number` - a u8, the interrupt number from 0 to 7.
+    /// # Returns
+    /// * `a Option<ExternalInterrupt>` - `None` if the number is out of range.
+    pub fn new(number: u8) -> Option<ExternalInterrupt> {
+        if number < EXTERNAL_INTERRUPTS {
+            Some(ExternalInterrupt { number })
+        } else {
+            None
+        }
+    }
+
+    /// Gives the external interrupt of a digital pin of the Arduino Mega.
+    /// # Arguments
+    /// * `pin` - a usize, the digital pin number, 2, 3 or 18 to 21.
+    /// # Returns
+    /// * `a Option<ExternalInterrupt>` - `None` if the pin has no external interrupt.
+    pub fn from_pin(pin: usize) -> Option<ExternalInterrupt> {
+        match pin {
+            2 => ExternalInterrupt::new(4),
+            3 => ExternalInterrupt::new(5),
+            18..=21 => ExternalInterrupt::new(21 - pin as u8),
+            _ => None,
+        }
+    }
+
+    /// Returns the interrupt number.
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// Returns the bit of the interrupt in EIMSK and EIFR.
+    fn mask(&self) -> u8 {
+        1 << self.number
+    }
+
+    /// Sets the condition on which the interrupt fires.
+    /// The interrupt is masked during the change, which can raise a spurious
+    /// flag, and the flag is then cleared.
+    /// # Arguments
+    /// * `trigger` - a `Trigger`, the new condition.
+    pub fn set_trigger(&mut self, trigger: Trigger) {
+        let (register, shift) = if self.number < 4 {
+            (EICRA, 2 * self.number)
+        } else {
+            (EICRB, 2 * (self.number - 4))
+        };
+        interrupts::free(|| unsafe {
+            let enabled = read_volatile(EIMSK) & self.mask();
+            write_volatile(EIMSK, read_volatile(EIMSK) & !self.mask());
+            let control = read_volatile(register) & !(0x03 << shift);
+            write_volatile(register, control | ((trigger as u8) << shift));
+            write_volatile(EIFR, self.mask());
+            write_volatile(EIMSK, read_volatile(EIMSK) | enabled);
+        });
+    }
+
+    /// Registers the handler, sets the trigger and enables the interrupt.
+    /// An edge which happened before is ignored.
+    /// # Arguments
+    /// * `trigger` - a `Trigger`, the condition on which the handler runs.
+    /// * `handler` - a function, run inside the interrupt with interrupts disabled.
+    pub fn attach(&mut self, trigger: Trigger, handler: fn()) {
+        interrupts::free(|| unsafe {
+            write_volatile(EIMSK, read_volatile(EIMSK) & !self.mask());
+            HANDLERS[self.number as usize] = Some(handler);
+        });
+        self.set_trigger(trigger);
+        self.enable();
+    }
+
+    /// Disables the interrupt and removes its handler.
+    pub fn detach(&mut self) {
+        interrupts::free(|| unsafe {
+            write_volatile(EIMSK, read_volatile(EIMSK) & !self.mask());
+            HANDLERS[self.number as usize] = None;
+        });
+    }
+
+    /// Unmasks the interrupt, a pending edge then runs the handler at once.
+    pub fn enable(&mut self) {
+        interrupts::free(|| unsafe {
+            write_volatile(EIMSK, read_volatile(EIMSK) | self.mask());
+        });
+    }
+
+    /// Masks the interrupt, edges are still recorded by `is_pending`.
+    pub fn disable(&mut self) {
+        interrupts::free(|| unsafe {
+            write_volatile(EIMSK, read_volatile(EIMSK) & !self.mask());
+        });
+    }
+
+    /// Returns true while the interrupt is unmasked.
+    pub fn is_enabled(&self) -> bool {
+        unsafe { read_volatile(EIMSK) & self.mask() != 0 }
+    }
+
+    /// Returns true if an edge happened which did not run the handler yet.
+    /// Always false with the `LowLevel` trigger.
+    pub fn is_pending(&self) -> bool {
+        unsafe { read_volatile(EIFR) & self.mask() != 0 }
+    }
+
+    /// Forgets an edge which did not run the handler yet.
+    pub fn clear_pending(&mut self) {
+        unsafe { write_volatile(EIFR, self.mask()) }
+    }
+}
+
+/// Runs the handler of an interrupt.
+unsafe fn dispatch(number: usize) {
+    if let Some(handler) = HANDLERS[number] {
+        handler();
+    }
+}
+
+/// External interrupt 0 service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_1"]
+pub unsafe extern "avr-interrupt" fn int0() {
+    dispatch(0);
+}
+
+/// External interrupt 1 service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_2"]
+pub unsafe extern "avr-interrupt" fn int1() {
+    dispatch(1);
+}
+
+/// External interrupt 2 service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_3"]
+pub unsafe extern "avr-interrupt" fn int2() {
+    dispatch(2);
+}
+
+/// External interrupt 3 service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_4"]
+pub unsafe extern "avr-interrupt" fn int3() {
+    dispatch(3);
+}
+
+/// External interrupt 4 service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_5"]
+pub unsafe extern "avr-interrupt" fn int4() {
+    dispatch(4);
+}
+
+/// External interrupt 5 service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_6"]
+pub unsafe extern "avr-interrupt" fn int5() {
+    dispatch(5);
+}
+
+/// External interrupt 6 service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_7"]
+pub unsafe extern "avr-interrupt" fn int6() {
+    dispatch(6);
+}
+
+/// External interrupt 7 service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_8"]
+pub unsafe extern "avr-interrupt" fn int7() {
+    dispatch(7);
+}
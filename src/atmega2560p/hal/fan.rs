@@ -0,0 +1,160 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! 25 kHz PWM for the speed input of 4-wire PC fans on Timer/Counter4,
+//! digital pins 6, 7 and 8. The fan specification asks for a frequency
+//! around 25 kHz, well above the 490 Hz of `analog`, which makes the fans
+//! hum. Timer4 runs in phase correct mode with ICR4 as top, so that
+//! 16 MHz / (2 * 320) = 25 kHz with 320 steps of duty cycle.
+//! The speed input is pulled up inside the fan and can be driven by the pin directly.
+//! The tach output is measured with `hal::counter` and `robotics::Tachometer`,
+//! see `system::fan`. Timer4 is taken over, so `analog` PWM on pins 6 to 8 does not work meanwhile.
+//! Section 17 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::port::{Pin, PortName};
+use crate::atmega2560p::hal::power::Power;
+use crate::{Error, Result};
+use core::ptr::{read_volatile, write_volatile};
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+/// Addresses of the Timer/Counter4 registers.
+const TCCR4A: *mut u8 = 0xA0 as *mut u8;
+const TCCR4B: *mut u8 = 0xA1 as *mut u8;
+const ICR4L: *mut u8 = 0xA6 as *mut u8;
+const ICR4H: *mut u8 = 0xA7 as *mut u8;
+const OCR4AL: *mut u8 = 0xA8 as *mut u8;
+
+/// Address of the output register of port H.
+const PORTH: *mut u8 = 0x102 as *mut u8;
+
+/// Top of the timer, the number of steps of duty cycle.
+pub const TOP: u16 = 320;
+
+/// TCCR4B for phase correct PWM with ICR4 as top (WGM4 = 1010) and no prescaler.
+const RUNNING: u8 = 0x11;
+
+/// Outputs of Timer4.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FanChannel {
+    /// OC4A, digital pin 6 (PH3).
+    A = 0,
+    /// OC4B, digital pin 7 (PH4).
+    B = 1,
+    /// OC4C, digital pin 8 (PH5).
+    C = 2,
+}
+
+impl FanChannel {
+    /// Returns the bit of the pin in port H.
+    fn bit(&self) -> u8 {
+        3 + *self as u8
+    }
+
+    /// Returns the COM4x1 bit of the channel in TCCR4A, for non inverted output.
+    fn compare_bit(&self) -> u8 {
+        1 << (7 - 2 * *self as u8)
+    }
+
+    /// Returns the address of OCR4xL, OCR4xH follows it.
+    fn ocr(&self) -> *mut u8 {
+        unsafe { OCR4AL.add(2 * *self as usize) }
+    }
+}
+
+/// The speed input of a fan on an output of Timer4.
+/// # Elements
+/// * `channel` - a `FanChannel` object, the output used.
+/// * `duty` - a u16, the duty cycle set, from 0 to `TOP`.
+pub struct FanPwm {
+    channel: FanChannel,
+    duty: u16,
+}
+
+impl FanPwm {
+    /// Starts the 25 kHz PWM on an output, at a duty cycle of 0.
+    /// The timer is set up by the first output started and shared by the others.
+    /// # Arguments
+    /// * `channel` - a `FanChannel` object, the output to be used.
+    /// # Returns
+    /// * `a FanPwm object` - Which will be used for further implementations.
+    pub fn new(channel: FanChannel) -> FanPwm {
+        if let Some(mut pin) = Pin::new(PortName::H, channel.bit() as usize) {
+            pin.set_output();
+        }
+        interrupts::free(|| unsafe {
+            if read_volatile(TCCR4B) != RUNNING {
+                let power = Power::new();
+                write_volatile(&mut power.prr1, read_volatile(&power.prr1) & !(1 << 4));
+                write_volatile(TCCR4B, 0);
+                write_volatile(TCCR4A, 0x02);
+                write_volatile(ICR4H, (TOP >> 8) as u8);
+                write_volatile(ICR4L, TOP as u8);
+                write_volatile(TCCR4B, RUNNING);
+            }
+            write_volatile(channel.ocr().add(1), 0);
+            write_volatile(channel.ocr(), 0);
+            write_volatile(TCCR4A, read_volatile(TCCR4A) | channel.compare_bit());
+        });
+        FanPwm { channel, duty: 0 }
+    }
+
+    /// Stops the PWM on the output, which is driven low, and stops the timer
+    /// once none of its outputs is used.
+    pub fn stop(self) {
+        interrupts::free(|| unsafe {
+            let control = read_volatile(TCCR4A) & !self.channel.compare_bit();
+            write_volatile(TCCR4A, control);
+            write_volatile(PORTH, read_volatile(PORTH) & !(1 << self.channel.bit()));
+            if control & 0xA8 == 0 {
+                write_volatile(TCCR4B, 0);
+            }
+        });
+    }
+
+    /// Returns the output used.
+    pub fn channel(&self) -> FanChannel {
+        self.channel
+    }
+
+    /// Returns the duty cycle set, from 0 to `TOP`.
+    pub fn duty(&self) -> u16 {
+        self.duty
+    }
+}
+
+impl ErrorType for FanPwm {
+    type Error = Error;
+}
+
+impl SetDutyCycle for FanPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        TOP
+    }
+
+    /// Writes the compare register, duty cycles above `TOP` give `InvalidArgument`.
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<()> {
+        if duty > TOP {
+            return Err(Error::InvalidArgument);
+        }
+        interrupts::free(|| unsafe {
+            write_volatile(self.channel.ocr().add(1), (duty >> 8) as u8);
+            write_volatile(self.channel.ocr(), duty as u8);
+        });
+        self.duty = duty;
+        Ok(())
+    }
+}
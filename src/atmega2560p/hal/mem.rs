@@ -0,0 +1,111 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Introspection of the internal SRAM of the ATMEGA2560P.
+//! RAM holds, from the bottom up, the initialized statics (`.data`), the zeroed
+//! statics (`.bss`), the statics kept across resets (`.noinit`), then free RAM,
+//! and the stack growing down from the end of RAM. Nothing in the crate uses a
+//! heap, so the free RAM is the gap between the end of the statics, where a
+//! heap would start, and the stack pointer. The boundaries come from the
+//! symbols of the avr-libc linker script.
+//! Section 8.2 of the manual.
+
+use core::ptr::{addr_of, read_volatile};
+
+/// First address of the internal SRAM, after the register file and the I/O space.
+pub const RAM_START: usize = 0x0200;
+
+/// Last address of the internal SRAM, where the stack starts.
+pub const RAM_END: usize = 0x21FF;
+
+/// Size of the internal SRAM in bytes.
+pub const RAM_SIZE: usize = RAM_END + 1 - RAM_START;
+
+/// Addresses of the stack pointer registers.
+const SPL: *const u8 = 0x5D as *const u8;
+const SPH: *const u8 = 0x5E as *const u8;
+
+extern "C" {
+    static __data_start: u8;
+    static __data_end: u8;
+    static __bss_start: u8;
+    static __bss_end: u8;
+    static __noinit_start: u8;
+    static __noinit_end: u8;
+    static __heap_start: u8;
+}
+
+/// Boundaries of the regions of RAM, each as the first address and the address after the last byte.
+/// # Elements
+/// * `data` - the initialized statics.
+/// * `bss` - the zeroed statics.
+/// * `noinit` - the statics kept across resets.
+/// * `free` - the free RAM, from the end of the statics to the stack pointer.
+/// * `stack` - the used stack, from the stack pointer to the end of RAM.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryMap {
+    pub data: (usize, usize),
+    pub bss: (usize, usize),
+    pub noinit: (usize, usize),
+    pub free: (usize, usize),
+    pub stack: (usize, usize),
+}
+
+/// Reads the stack pointer, which points to the first free byte below the stack.
+pub fn stack_pointer() -> usize {
+    unsafe { (read_volatile(SPH) as usize) << 8 | read_volatile(SPL) as usize }
+}
+
+/// Returns the first address after all the statics, where a heap would start.
+pub fn heap_start() -> usize {
+    unsafe { addr_of!(__heap_start) as usize }
+}
+
+/// Returns the number of bytes taken by the statics.
+pub fn static_bytes() -> usize {
+    heap_start() - RAM_START
+}
+
+/// Returns the number of bytes currently used by the stack.
+pub fn stack_bytes() -> usize {
+    RAM_END - stack_pointer()
+}
+
+/// Returns the number of free bytes between the statics and the stack.
+/// The value changes with the depth of the stack at the point of the call.
+pub fn free_bytes() -> usize {
+    (stack_pointer() + 1).saturating_sub(heap_start())
+}
+
+/// Returns the boundaries of every region of RAM.
+pub fn memory_map() -> MemoryMap {
+    let sp = stack_pointer();
+    unsafe {
+        MemoryMap {
+            data: (
+                addr_of!(__data_start) as usize,
+                addr_of!(__data_end) as usize,
+            ),
+            bss: (addr_of!(__bss_start) as usize, addr_of!(__bss_end) as usize),
+            noinit: (
+                addr_of!(__noinit_start) as usize,
+                addr_of!(__noinit_end) as usize,
+            ),
+            free: (heap_start(), sp + 1),
+            stack: (sp + 1, RAM_END + 1),
+        }
+    }
+}
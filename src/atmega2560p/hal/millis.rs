@@ -0,0 +1,185 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Millisecond and microsecond counters driven by the Timer/Counter0 overflow interrupt.
+//! Timer0 is run in fast PWM mode with a prescaler of 64, which is the same setting
+//! used for PWM output on pins 4 and 13, so analog write keeps working on those pins.
+//! The module needs the `millis` feature, because its interrupt service
+//! routines take the Timer0 compare A and overflow vectors.
+//! Section 16 of the manual.
+
+use crate::config::CPU_FREQUENCY_HZ;
+use crate::hal::interrupts;
+use crate::hal::power::Power;
+use core::ptr::{addr_of, read_volatile, write_volatile};
+
+/// Address of TIMSK0, the Timer/Counter0 interrupt mask register.
+const TIMSK0: *mut u8 = 0x6E as *mut u8;
+
+/// Address of SREG, the status register holding the global interrupt flag.
+const SREG: *const u8 = 0x5F as *const u8;
+
+/// Address of TIFR0, the Timer/Counter0 interrupt flag register.
+const TIFR0: *mut u8 = 0x35 as *mut u8;
+
+/// Address of OCR0A, the Timer/Counter0 output compare register A.
+const OCR0A: *mut u8 = 0x47 as *mut u8;
+
+/// Number of CPU clock cycles in one Timer0 tick.
+const PRESCALER: u32 = 64;
+
+/// Time taken by one overflow of Timer0 in microseconds.
+const MICROS_PER_OVERFLOW: u32 =
+    (PRESCALER as u64 * 256 * 1_000_000 / CPU_FREQUENCY_HZ as u64) as u32;
+
+/// Whole milliseconds added on every overflow.
+const MILLIS_INCREMENT: u32 = MICROS_PER_OVERFLOW / 1000;
+
+/// Fractional milliseconds added on every overflow, in units of 8 microseconds
+/// so that it fits in a u8.
+const FRACT_INCREMENT: u8 = ((MICROS_PER_OVERFLOW % 1000) >> 3) as u8;
+
+/// One millisecond in units of 8 microseconds.
+const FRACT_MAX: u8 = (1000 >> 3) as u8;
+
+/// Structure to control Timer/Counter0.
+#[repr(C, packed)]
+pub struct Timer0 {
+    tccra: u8,
+    tccrb: u8,
+    tcnt: u8,
+}
+
+static mut MILLIS_COUNT: u32 = 0;
+static mut MILLIS_FRACT: u8 = 0;
+static mut OVERFLOW_COUNT: u32 = 0;
+static mut TICK: Option<fn()> = None;
+
+impl Timer0 {
+    /// Creates a memory mapped structure to control Timer0.
+    /// # Returns
+    /// * `a reference to Timer0 object` - which will be used for further implementations.
+    pub unsafe fn new() -> &'static mut Timer0 {
+        &mut *(0x44 as *mut Timer0)
+    }
+}
+
+/// Starts Timer0 and enables its overflow interrupt and the global interrupts.
+/// Must be called once before `millis` or `micros` are used.
+pub fn millis_init() {
+    unsafe {
+        // Enable the clock of Timer0 by clearing PRTIM0.
+        let power = Power::new();
+        write_volatile(&mut power.prr0, read_volatile(&power.prr0) & !(1 << 5));
+
+        let timer = Timer0::new();
+        // Fast PWM mode (WGM01:0 = 11).
+        write_volatile(&mut timer.tccra, read_volatile(&timer.tccra) | 0b11);
+        // Prescaler of 64 (CS02:0 = 011).
+        write_volatile(
+            &mut timer.tccrb,
+            (read_volatile(&timer.tccrb) & 0xF8) | 0b011,
+        );
+        // Enable the overflow interrupt (TOIE0).
+        write_volatile(TIMSK0, read_volatile(TIMSK0) | 0x01);
+
+        interrupts::Interrupt::enable(&mut interrupts::Interrupt::new());
+    }
+}
+
+/// Checks if the counters are running, which is the case after `millis_init`
+/// was called as long as the global interrupts are enabled.
+/// # Returns
+/// * `a boolean` - Which is true if `millis` and `micros` are counting.
+pub fn millis_running() -> bool {
+    unsafe { read_volatile(TIMSK0) & 0x01 != 0 && read_volatile(SREG) & 0x80 != 0 }
+}
+
+/// Returns the number of milliseconds passed since `millis_init` was called.
+/// The counter overflows after about 49.7 days.
+pub fn millis() -> u32 {
+    interrupts::free(|| unsafe { read_volatile(addr_of!(MILLIS_COUNT)) })
+}
+
+/// Returns the number of microseconds passed since `millis_init` was called.
+/// The resolution is 4 microseconds on a 16 MHz clock
+/// and the counter overflows after about 71.6 minutes.
+pub fn micros() -> u32 {
+    interrupts::free(|| unsafe {
+        let mut overflows = read_volatile(addr_of!(OVERFLOW_COUNT));
+        let ticks = read_volatile(&Timer0::new().tcnt);
+
+        // An overflow which happened after interrupts were disabled has not been counted yet.
+        if read_volatile(TIFR0) & 0x01 != 0 && ticks < 255 {
+            overflows = overflows.wrapping_add(1);
+        }
+
+        overflows
+            .wrapping_mul(MICROS_PER_OVERFLOW)
+            .wrapping_add(ticks as u32 * PRESCALER * 1_000_000 / CPU_FREQUENCY_HZ)
+    })
+}
+
+/// Calls a function from the compare match A interrupt of Timer0, half way
+/// between two overflows, that is 976.5625 times per second, for periodic
+/// work like refreshing multiplexed displays without taking another timer.
+/// `millis_init` must have been called. OCR0A is set, so analog write on pin 13
+/// does not work meanwhile. A function attached before is replaced.
+/// # Arguments
+/// * `handler` - a function, called with interrupts disabled, which must return quickly.
+pub fn attach_tick(handler: fn()) {
+    interrupts::free(|| unsafe {
+        TICK = Some(handler);
+        write_volatile(OCR0A, 128);
+        write_volatile(TIFR0, 0x02);
+        write_volatile(TIMSK0, read_volatile(TIMSK0) | 0x02);
+    });
+}
+
+/// Stops calling the function given to `attach_tick`.
+pub fn detach_tick() {
+    interrupts::free(|| unsafe {
+        write_volatile(TIMSK0, read_volatile(TIMSK0) & !0x02);
+        TICK = None;
+    });
+}
+
+/// Timer/Counter0 overflow interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_23"]
+pub unsafe extern "avr-interrupt" fn timer0_overflow() {
+    let mut m = read_volatile(addr_of!(MILLIS_COUNT)).wrapping_add(MILLIS_INCREMENT);
+    let mut f = read_volatile(&MILLIS_FRACT) + FRACT_INCREMENT;
+    if f >= FRACT_MAX {
+        f -= FRACT_MAX;
+        m = m.wrapping_add(1);
+    }
+    write_volatile(&mut MILLIS_COUNT, m);
+    write_volatile(&mut MILLIS_FRACT, f);
+    write_volatile(
+        &mut OVERFLOW_COUNT,
+        read_volatile(addr_of!(OVERFLOW_COUNT)).wrapping_add(1),
+    );
+}
+
+/// Timer/Counter0 compare match A interrupt service routine, calls the tick handler.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_21"]
+pub unsafe extern "avr-interrupt" fn timer0_compare_a() {
+    if let Some(handler) = TICK {
+        handler();
+    }
+}
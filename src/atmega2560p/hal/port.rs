@@ -15,6 +15,14 @@
 //     along with this program.  If not, see <https://www.gnu.org/licenses/>
 
 //! Various pins and ports in the ATMEGA2560P chip is controlled here.
+//! A `Pin` carries its mode in its type: `Pin<Output>`, `Pin<Input<Floating>>`
+//! and `Pin<Input<PullUp>>` only have the methods making sense in their mode,
+//! and `into_output`, `into_floating_input` and `into_pull_up_input` consume a
+//! pin to give it back in another mode. `Pin<OpenDrain<PULL>>` pulls a line
+//! low or releases it, never driving it high, for lines shared by several
+//! devices like the bus of DHT sensors. `Pin<Dynamic>`, simply written `Pin`,
+//! is the pin of `Pins` and `Pin::new` whose mode is set at runtime.
+//! A `PortBus` reads and writes several pins of a port in one access.
 //! Section 13.2 to 13.4 of ATMEGA2560P datasheet.
 
 // Source codes required.
@@ -22,12 +30,15 @@ use crate::atmega2560p::hal::pin::{AnalogPin, DigitalPin};
 
 // Core Crate functions required in the code for reading and writing to registers.
 use core::{
+    convert::Infallible,
+    marker::PhantomData,
     ptr::{read_volatile, write_volatile},
     usize,
 };
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
 
 /// Represents the name of the ports in ATMEGA2560P , can vary from A-L leaving I.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum PortName {
     A,
     B,
@@ -44,10 +55,12 @@ pub enum PortName {
 
 /// Type `IOMode`
 /// Represents the Input/Output mode of the pin.
+/// An `OpenDrain` pin is driven low or released as an input, never driven high.
 #[derive(Clone, Copy)]
 pub enum IOMode {
     Input,
     Output,
+    OpenDrain,
 }
 
 /// These will control the ports ( set of 8 pins each controlled by a bit ).
@@ -68,11 +81,51 @@ pub struct Port {
 }
 
 /// The structure Pin contains the address of the port to which the pin belongs and the pin's number.
+/// Its type parameter is the mode of the pin, see the module documentation.
 #[repr(C, packed)]
 #[derive(Clone, Copy)]
-pub struct Pin {
+pub struct Pin<MODE = Dynamic> {
     pub port: *mut Port,
     pub pin: usize,
+    mode: PhantomData<MODE>,
+}
+
+/// Mode of the pins which are set as inputs or outputs at runtime.
+#[derive(Clone, Copy)]
+pub struct Dynamic;
+
+/// Mode of the output pins.
+pub struct Output;
+
+/// Mode of the input pins, with the state of their pull up.
+pub struct Input<PULL> {
+    _pull: PhantomData<PULL>,
+}
+
+/// Input without pull up, for inputs driven both ways.
+pub struct Floating;
+
+/// Input with the internal pull up enabled, for buttons and open collector outputs.
+pub struct PullUp;
+
+/// Mode of the pins pulling a line low or releasing it, with the state of
+/// their pull up when released.
+pub struct OpenDrain<PULL> {
+    _pull: PhantomData<PULL>,
+}
+
+/// The pull up states, `Floating` and `PullUp`.
+pub trait Pull {
+    /// True if the internal pull up is enabled.
+    const ENABLED: bool;
+}
+
+impl Pull for Floating {
+    const ENABLED: bool = false;
+}
+
+impl Pull for PullUp {
+    const ENABLED: bool = true;
 }
 
 impl Port {
@@ -119,11 +172,122 @@ impl Port {
     /// Returns a `Some<Pin>` if pin number is valid and returns none if not valid.
     pub fn pin(&mut self, pin: usize) -> Option<Pin> {
         if pin < 0x8 {
-            Some(Pin { port: self, pin })
+            Some(Pin {
+                port: self,
+                pin,
+                mode: PhantomData,
+            })
         } else {
             None
         }
     }
+
+    /// Returns a pin whose number is known to be valid, like the number of a
+    /// `StaticPin`, which is checked at compile time.
+    pub(crate) fn pin_unchecked(&mut self, pin: usize) -> Pin {
+        Pin {
+            port: self,
+            pin,
+            mode: PhantomData,
+        }
+    }
+}
+
+/// Some or all of the pins of a port, read and written together in one
+/// register access, for parallel displays, R2R DACs and bit patterns
+/// which must change at once.
+/// # Elements
+/// * `port` - a pointer to the `Port` of the pins.
+/// * `mask` - a u8, the bits of the pins in the port registers.
+#[derive(Clone, Copy)]
+pub struct PortBus {
+    port: *mut Port,
+    mask: u8,
+}
+
+impl Port {
+    /// Groups pins of the port in a bus, leaving their mode unchanged.
+    /// # Arguments
+    /// * `mask` - a u8, the bits of the pins in the bus, 0xFF for the whole port.
+    /// # Returns
+    /// * `a PortBus object` - which will be used for further implementations.
+    pub fn bus(&mut self, mask: u8) -> PortBus {
+        PortBus { port: self, mask }
+    }
+}
+
+impl PortBus {
+    /// Creates a bus on pins of the given port.
+    /// # Arguments
+    /// * `name` - a `PortName` object, the port of the pins.
+    /// * `mask` - a u8, the bits of the pins in the bus, 0xFF for the whole port.
+    /// # Returns
+    /// * `a PortBus object` - which will be used for further implementations.
+    pub fn new(name: PortName, mask: u8) -> PortBus {
+        Port::new(name).bus(mask)
+    }
+
+    /// Returns the bits of the pins in the bus.
+    pub fn mask(&self) -> u8 {
+        self.mask
+    }
+
+    /// Makes all the pins of the bus outputs.
+    pub fn set_output(&mut self) {
+        unsafe {
+            let ddr_val = read_volatile(&(*self.port).ddr);
+            write_volatile(&mut (*self.port).ddr, ddr_val | self.mask);
+        }
+    }
+
+    /// Makes all the pins of the bus inputs, with or without pull up.
+    /// # Arguments
+    /// * `pull_up` - a boolean, true to enable the internal pull ups.
+    pub fn set_input(&mut self, pull_up: bool) {
+        unsafe {
+            let ddr_val = read_volatile(&(*self.port).ddr);
+            write_volatile(&mut (*self.port).ddr, ddr_val & !self.mask);
+        }
+        self.write(if pull_up { 0xFF } else { 0x00 });
+    }
+
+    /// Reads the levels of the pins from PINx.
+    /// # Returns
+    /// * `a u8` - the levels, with the bits outside of the bus cleared.
+    pub fn read(&self) -> u8 {
+        unsafe { read_volatile(&(*self.port).pin) & self.mask }
+    }
+
+    /// Returns the levels last written to PORTx.
+    /// # Returns
+    /// * `a u8` - the levels, with the bits outside of the bus cleared.
+    pub fn output(&self) -> u8 {
+        unsafe { read_volatile(&(*self.port).port) & self.mask }
+    }
+
+    /// Writes the levels of all the pins of the bus at once.
+    /// A bus on a whole port writes PORTx, otherwise the bits which differ
+    /// are flipped through PINx, so that the other pins of the port keep
+    /// their level even if an interrupt changes them meanwhile.
+    /// # Arguments
+    /// * `value` - a u8, the levels, the bits outside of the bus are ignored.
+    pub fn write(&mut self, value: u8) {
+        unsafe {
+            if self.mask == 0xFF {
+                write_volatile(&mut (*self.port).port, value);
+            } else {
+                let port_val = read_volatile(&(*self.port).port);
+                write_volatile(&mut (*self.port).pin, (port_val ^ value) & self.mask);
+            }
+        }
+    }
+
+    /// Flips the level of some pins of the bus at once through PINx.
+    /// # Arguments
+    /// * `bits` - a u8, the bits to flip, the bits outside of the bus are ignored.
+    pub fn toggle(&mut self, bits: u8) {
+        unsafe { write_volatile(&mut (*self.port).pin, bits & self.mask) }
+    }
 }
 
 impl Pin {
@@ -135,9 +299,15 @@ impl Pin {
     }
 
     /// Change pin mode to input or output by changing the DDr register.
+    /// `IOMode::OpenDrain` releases the pin without pull up.
     /// # Arguments
     /// * `mode` - a `IOMode` object, which defines the mode of the pin to be set.
     pub fn set_pin_mode(&mut self, mode: IOMode) {
+        // An open drain pin drives a low level only, keep PORTxn cleared.
+        if let IOMode::OpenDrain = mode {
+            self.write_port(false);
+        }
+
         //  Read the value of DDxn register.
         let mut ddr_val = unsafe { read_volatile(&mut (*self.port).ddr) };
 
@@ -147,7 +317,7 @@ impl Pin {
 
         ddr_val &= !(0x1 << self.pin);
         ddr_val |= match mode {
-            IOMode::Input => 0x0,
+            IOMode::Input | IOMode::OpenDrain => 0x0,
             IOMode::Output => 0x1 << self.pin,
         };
 
@@ -164,6 +334,38 @@ impl Pin {
     pub fn set_input(&mut self) {
         self.set_pin_mode(IOMode::Input);
     }
+
+    /// Reads the level on the pin from the PINxn register.
+    /// # Returns
+    /// * `a boolean` - True if the pin is high.
+    pub fn read(&self) -> bool {
+        unsafe { read_volatile(&(*self.port).pin) & self.mask() != 0 }
+    }
+
+    /// Enables or disables the internal pull up of an input pin by writing
+    /// the PORTxn register, so that buttons and open collector outputs need
+    /// no external resistor. On an output pin this sets the level instead.
+    /// # Arguments
+    /// * `enabled` - a boolean, true to enable the pull up.
+    pub fn set_pull_up(&mut self, enabled: bool) {
+        self.write_port(enabled);
+    }
+
+    /// Pulls an open drain line low, making the pin an output.
+    /// PORTxn is cleared before DDRxn is set, so the line is never driven high.
+    pub fn drive_low(&mut self) {
+        self.write_port(false);
+        self.write_ddr(true);
+    }
+
+    /// Releases an open drain line, making the pin an input.
+    /// DDRxn is cleared before the pull up is enabled, for the same reason.
+    /// # Arguments
+    /// * `pull_up` - a boolean, true to pull the line up with the internal pull up.
+    pub fn release(&mut self, pull_up: bool) {
+        self.write_ddr(false);
+        self.write_port(pull_up);
+    }
 }
 
 impl AnalogPin {
@@ -189,17 +391,350 @@ impl DigitalPin {
         self.pin.set_pin_mode(IOMode::Input);
     }
 
-    /// Returns the I/O state of the Digital Pin.
+    /// Returns the level on the Digital Pin, read from the PINxn register.
     /// # Returns
-    /// * `a u8` - The read data from the digital pin.
+    /// * `a u8` - 1 if the pin is high, 0 if it is low.
     pub fn read(&mut self) -> u8 {
-        let port_val = unsafe { read_volatile(&mut (*self.pin.port).port) };
+        self.pin.read() as u8
+    }
+}
+
+impl<MODE> Pin<MODE> {
+    /// Gives the pin another mode, the registers must have been set for it.
+    fn retype<NEW>(self) -> Pin<NEW> {
+        Pin {
+            port: self.port,
+            pin: self.pin,
+            mode: PhantomData,
+        }
+    }
 
-        // Check if value of PORTxn is already high, toggle if it isn't.
-        if port_val & (1 << self.pin.pin) == 0 {
-            return 0;
+    /// Returns the bit of the pin in the port registers.
+    fn mask(&self) -> u8 {
+        if self.pin < 8 {
+            1 << self.pin
         } else {
-            return 1;
+            0
+        }
+    }
+
+    /// Writes the pin bit of the PORTxn register.
+    /// # Arguments
+    /// * `high` - a boolean, the new value of the bit.
+    fn write_port(&mut self, high: bool) {
+        let mask = self.mask();
+        unsafe {
+            let mut port_val = read_volatile(&mut (*self.port).port);
+            if high {
+                port_val |= mask;
+            } else {
+                port_val &= !mask;
+            }
+            write_volatile(&mut (*self.port).port, port_val);
+        }
+    }
+
+    /// Writes the pin bit of the DDxn register.
+    /// # Arguments
+    /// * `output` - a boolean, true for an output.
+    fn write_ddr(&mut self, output: bool) {
+        let mask = self.mask();
+        unsafe {
+            let mut ddr_val = read_volatile(&mut (*self.port).ddr);
+            if output {
+                ddr_val |= mask;
+            } else {
+                ddr_val &= !mask;
+            }
+            write_volatile(&mut (*self.port).ddr, ddr_val);
         }
     }
+
+    /// Makes the pin an output, which keeps the level last written to PORTxn.
+    /// # Returns
+    /// * `a Pin<Output> object` - the same pin as an output.
+    pub fn into_output(mut self) -> Pin<Output> {
+        self.write_ddr(true);
+        self.retype()
+    }
+
+    /// Makes the pin an input without pull up.
+    /// # Returns
+    /// * `a Pin<Input<Floating>> object` - the same pin as a floating input.
+    pub fn into_floating_input(mut self) -> Pin<Input<Floating>> {
+        self.write_ddr(false);
+        self.write_port(false);
+        self.retype()
+    }
+
+    /// Makes the pin an input with the internal pull up enabled.
+    /// # Returns
+    /// * `a Pin<Input<PullUp>> object` - the same pin as an input pulled up.
+    pub fn into_pull_up_input(mut self) -> Pin<Input<PullUp>> {
+        self.write_ddr(false);
+        self.write_port(true);
+        self.retype()
+    }
+
+    /// Makes the pin an open drain pin released without pull up, for lines
+    /// with an external pull up resistor.
+    /// # Returns
+    /// * `a Pin<OpenDrain<Floating>> object` - the same pin, which can only pull the line low.
+    pub fn into_open_drain(mut self) -> Pin<OpenDrain<Floating>> {
+        self.write_ddr(false);
+        self.write_port(false);
+        self.retype()
+    }
+
+    /// Makes the pin an open drain pin released with the internal pull up enabled.
+    /// # Returns
+    /// * `a Pin<OpenDrain<PullUp>> object` - the same pin, which can only pull the line low.
+    pub fn into_open_drain_pull_up(mut self) -> Pin<OpenDrain<PullUp>> {
+        self.write_ddr(false);
+        self.write_port(true);
+        self.retype()
+    }
+
+    /// Forgets the mode of the pin, for the code setting it at runtime.
+    /// # Returns
+    /// * `a Pin object` - the same pin, in the mode it had.
+    pub fn into_dynamic(self) -> Pin {
+        self.retype()
+    }
+
+    /// Returns the number of the pin in its port.
+    pub fn number(&self) -> usize {
+        self.pin
+    }
+}
+
+impl Pin<Output> {
+    /// Drives the pin high.
+    pub fn high(&mut self) {
+        self.write_port(true);
+    }
+
+    /// Drives the pin low.
+    pub fn low(&mut self) {
+        self.write_port(false);
+    }
+
+    /// Inverts the level of the pin, in a single register write.
+    pub fn toggle(&mut self) {
+        // Writing a one to PINxn toggles PORTxn.
+        unsafe { write_volatile(&mut (*self.port).pin, self.mask()) };
+    }
+
+    /// Returns true if the pin is driven high.
+    pub fn is_set_high(&self) -> bool {
+        unsafe { read_volatile(&(*self.port).port) & self.mask() != 0 }
+    }
+
+    /// Returns true if the pin is driven low.
+    pub fn is_set_low(&self) -> bool {
+        !self.is_set_high()
+    }
+}
+
+impl<PULL> Pin<Input<PULL>> {
+    /// Returns true if the level on the pin is high.
+    pub fn is_high(&self) -> bool {
+        unsafe { read_volatile(&(*self.port).pin) & self.mask() != 0 }
+    }
+
+    /// Returns true if the level on the pin is low.
+    pub fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+}
+
+impl<PULL: Pull> Pin<OpenDrain<PULL>> {
+    /// Pulls the line low.
+    pub fn low(&mut self) {
+        self.write_port(false);
+        self.write_ddr(true);
+    }
+
+    /// Releases the line, which goes high unless a device pulls it low.
+    pub fn high(&mut self) {
+        self.write_ddr(false);
+        self.write_port(PULL::ENABLED);
+    }
+
+    /// Returns true if the level of the line is high.
+    pub fn is_high(&self) -> bool {
+        unsafe { read_volatile(&(*self.port).pin) & self.mask() != 0 }
+    }
+
+    /// Returns true if the level of the line is low.
+    pub fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+
+    /// Returns true if the line is released.
+    pub fn is_released(&self) -> bool {
+        unsafe { read_volatile(&(*self.port).ddr) & self.mask() == 0 }
+    }
+}
+
+// Implementations of the embedded-hal digital traits, so that platform
+// agnostic drivers can use the pins. A `Pin<Dynamic>` must be in the right
+// mode, the typed pins only implement the traits of their mode.
+impl<MODE> ErrorType for Pin<MODE> {
+    type Error = Infallible;
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.write_port(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.write_port(true);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for Pin {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        let port_val = unsafe { read_volatile(&mut (*self.port).port) };
+        Ok(port_val & self.mask() != 0)
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        self.is_set_high().map(|high| !high)
+    }
+
+    fn toggle(&mut self) -> Result<(), Infallible> {
+        // Writing a one to PINxn toggles PORTxn.
+        unsafe { write_volatile(&mut (*self.port).pin, self.mask()) };
+        Ok(())
+    }
+}
+
+impl InputPin for Pin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        let pin_val = unsafe { read_volatile(&mut (*self.port).pin) };
+        Ok(pin_val & self.mask() != 0)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+impl OutputPin for Pin<Output> {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.low();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.high();
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for Pin<Output> {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(Pin::<Output>::is_set_high(self))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(Pin::<Output>::is_set_low(self))
+    }
+
+    fn toggle(&mut self) -> Result<(), Infallible> {
+        Pin::<Output>::toggle(self);
+        Ok(())
+    }
+}
+
+impl<PULL> InputPin for Pin<Input<PULL>> {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(Pin::<Input<PULL>>::is_high(self))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(Pin::<Input<PULL>>::is_low(self))
+    }
+}
+
+impl<PULL: Pull> OutputPin for Pin<OpenDrain<PULL>> {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.low();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.high();
+        Ok(())
+    }
+}
+
+impl<PULL: Pull> StatefulOutputPin for Pin<OpenDrain<PULL>> {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.is_released())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.is_released())
+    }
+}
+
+impl<PULL: Pull> InputPin for Pin<OpenDrain<PULL>> {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(Pin::<OpenDrain<PULL>>::is_high(self))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(Pin::<OpenDrain<PULL>>::is_low(self))
+    }
+}
+
+// The pins of `Pins` forward to their `Pin`.
+impl ErrorType for DigitalPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for DigitalPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        let mut pin = self.pin;
+        pin.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        let mut pin = self.pin;
+        pin.set_high()
+    }
+}
+
+impl StatefulOutputPin for DigitalPin {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        let mut pin = self.pin;
+        pin.is_set_high()
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        let mut pin = self.pin;
+        pin.is_set_low()
+    }
+
+    fn toggle(&mut self) -> Result<(), Infallible> {
+        let mut pin = self.pin;
+        StatefulOutputPin::toggle(&mut pin)
+    }
+}
+
+impl InputPin for DigitalPin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        let mut pin = self.pin;
+        pin.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        let mut pin = self.pin;
+        pin.is_low()
+    }
 }
@@ -19,6 +19,7 @@
 //! Also references from Section 11.8.
 
 // Crates required in the code for reading and writing to registers.
+use crate::atmega2560p::hal::interrupts;
 use core::ptr::{read_volatile, write_volatile};
 
 /// The options correspond to real world as shown -
@@ -259,3 +260,74 @@ impl Power {
         }
     }
 }
+
+/// Division of the system clock by the prescaler of CLKPR. Dividing the clock
+/// saves power while awake, but `millis`, the delays and the baud rates of
+/// the USARTs are computed for the undivided clock.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum ClockPrescaler {
+    Div1 = 0,
+    Div2 = 1,
+    Div4 = 2,
+    Div8 = 3,
+    Div16 = 4,
+    Div32 = 5,
+    Div64 = 6,
+    Div128 = 7,
+    Div256 = 8,
+}
+
+impl ClockPrescaler {
+    /// Returns the factor by which the clock is divided.
+    pub fn divider(&self) -> u16 {
+        1 << (*self as u8)
+    }
+}
+
+/// Sets the division of the system clock, with the timed sequence of CLKPR.
+/// # Arguments
+/// * `prescaler` - a `ClockPrescaler`, the new division.
+pub fn set_clock_prescaler(prescaler: ClockPrescaler) {
+    let clkpr = 0x61 as *mut u8;
+    interrupts::free(|| unsafe {
+        // CLKPCE must be set alone, then the prescaler written within four cycles.
+        write_volatile(clkpr, 0x80);
+        write_volatile(clkpr, prescaler as u8);
+    });
+}
+
+/// Stops the clocks of all the peripherals except the given ones, and
+/// restarts those, with one write of each PRR register. The ADC is turned
+/// off before its clock is stopped, as the datasheet requires.
+/// # Arguments
+/// * `running` - a slice of `Peripherals`, the peripherals to keep clocked.
+pub fn gate_all_except(running: &[Peripherals]) {
+    let (mut prr0, mut prr1): (u8, u8) = (0xEF, 0x3F);
+    for peripheral in running {
+        match peripheral {
+            Peripherals::TWI => prr0 &= !0x80,
+            Peripherals::TIMER2 => prr0 &= !0x40,
+            Peripherals::TIMER0 => prr0 &= !0x20,
+            Peripherals::TIMER1 => prr0 &= !0x08,
+            Peripherals::SPI => prr0 &= !0x04,
+            Peripherals::USART0 => prr0 &= !0x02,
+            Peripherals::ADC => prr0 &= !0x01,
+            Peripherals::TIMER5 => prr1 &= !0x20,
+            Peripherals::TIMER4 => prr1 &= !0x10,
+            Peripherals::TIMER3 => prr1 &= !0x08,
+            Peripherals::USART3 => prr1 &= !0x04,
+            Peripherals::USART2 => prr1 &= !0x02,
+            Peripherals::USART1 => prr1 &= !0x01,
+        }
+    }
+    unsafe {
+        if prr0 & 0x01 != 0 {
+            let adcsra = 0x7A as *mut u8;
+            write_volatile(adcsra, read_volatile(adcsra) & !0x80);
+        }
+        let power = Power::new();
+        write_volatile(&mut power.prr0, prr0);
+        write_volatile(&mut power.prr1, prr1);
+    }
+}
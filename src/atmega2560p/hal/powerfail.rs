@@ -0,0 +1,99 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Last gasp hook run when the supply is about to fail.
+//! The brown-out detector of the ATmega2560 can only hold the chip in reset,
+//! it has no interrupt, so an early warning has to come from outside:
+//! a voltage supervisor or comparator watching the unregulated supply pulls
+//! digital pin 3 (PE5, INT5) low while the regulator output, helped by a
+//! large enough hold up capacitor, still keeps the chip running.
+//! The falling edge, attached through `external_interrupt`, runs a user callback which should flush the critical state
+//! to EEPROM or FRAM, for example through `crate::storage::Storage`.
+//! An EEPROM byte takes about 3.4 ms to write, so the capacitor sizes how much can be saved.
+//! Whether the last reset came from the brown-out detector can be checked with `brown_out_reset`.
+//! The module needs the `powerfail` feature, which brings in `hal::external_interrupt`.
+//! Sections 12 and 15 of the manual.
+
+use crate::atmega2560p::hal::external_interrupt::{ExternalInterrupt, Trigger};
+use crate::atmega2560p::hal::interrupts;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Addresses of the registers of the power fail pin.
+const DDR: *mut u8 = 0x2D as *mut u8;
+const PORT: *mut u8 = 0x2E as *mut u8;
+const PIN: *mut u8 = 0x2C as *mut u8;
+
+/// Address of the MCU status register holding the reset flags.
+const MCUSR: *mut u8 = 0x54 as *mut u8;
+
+/// Bit of the power fail pin in its port, which is also the number of its external interrupt.
+const BIT: u8 = 5;
+
+static mut HANDLER: Option<fn()> = None;
+
+/// Enables the power fail interrupt on the falling edge of digital pin 3.
+/// # Arguments
+/// * `handler` - a function, the last gasp callback, run with interrupts disabled.
+/// * `pull_up` - a boolean, true to enable the internal pull up, for open drain supervisors.
+pub fn enable(handler: fn(), pull_up: bool) {
+    interrupts::free(|| unsafe {
+        HANDLER = Some(handler);
+        write_volatile(DDR, read_volatile(DDR) & !(1 << BIT));
+        if pull_up {
+            write_volatile(PORT, read_volatile(PORT) | (1 << BIT));
+        } else {
+            write_volatile(PORT, read_volatile(PORT) & !(1 << BIT));
+        }
+    });
+    if let Some(mut int5) = ExternalInterrupt::new(BIT) {
+        int5.attach(Trigger::Falling, power_fail);
+    }
+}
+
+/// Disables the power fail interrupt.
+pub fn disable() {
+    if let Some(mut int5) = ExternalInterrupt::new(BIT) {
+        int5.detach();
+    }
+    interrupts::free(|| unsafe { HANDLER = None });
+}
+
+/// Returns true while the power fail input is low, that is while the supply is failing.
+/// Useful after the callback ran, to wait and see whether the supply comes back.
+pub fn is_failing() -> bool {
+    unsafe { read_volatile(PIN) & (1 << BIT) == 0 }
+}
+
+/// Returns true if the last reset was caused by the brown-out detector,
+/// as recorded by the BORF bit of MCUSR, and clears the flag.
+pub fn brown_out_reset() -> bool {
+    unsafe {
+        let flags = read_volatile(MCUSR);
+        write_volatile(MCUSR, flags & !(1 << 2));
+        flags & (1 << 2) != 0
+    }
+}
+
+/// Runs the last gasp callback once. The interrupt is disabled first so that
+/// a bouncing supply cannot run it again, `enable` re-arms it.
+fn power_fail() {
+    if let Some(mut int5) = ExternalInterrupt::new(BIT) {
+        int5.disable();
+    }
+    if let Some(handler) = unsafe { HANDLER } {
+        handler();
+    }
+}
@@ -0,0 +1,118 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Debounced pulse counting for tipping bucket rain gauges, water flow
+//! meters and other sensors closing a contact once per unit of quantity.
+//! The inputs are analog pins A8 to A15 used as digital pins 62 to 69, watched through `hal::pcint`
+//! with their internal pull ups enabled. Every falling edge counts as a pulse,
+//! unless it comes within the debounce time of the previous pulse of its pin.
+//! `take` hands the counts to a `crate::system::accumulator::PulseAccumulator`,
+//! which keeps the interval and lifetime totals.
+//! The module needs the `pcint` and `millis` features.
+//! Section 13 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::millis::millis;
+use crate::atmega2560p::hal::pcint::{self, Edge};
+
+/// State shared with the interrupt service routine, one entry per bit of the port.
+/// # Elements
+/// * `enabled` - a u8, the bits of the counted pins.
+/// * `counts` - an array of u16, the pulses counted since the last `take`.
+/// * `debounce` - an array of u8, the shortest time between two pulses in milliseconds.
+/// * `last_pulse` - an array of u32, the value of `millis` at the last pulse.
+struct PulseState {
+    enabled: u8,
+    counts: [u16; 8],
+    debounce: [u8; 8],
+    last_pulse: [u32; 8],
+}
+
+static mut PULSES: PulseState = PulseState {
+    enabled: 0,
+    counts: [0; 8],
+    debounce: [0; 8],
+    last_pulse: [0; 8],
+};
+
+/// Gives the bit in port K of a digital pin.
+fn port_bit(pin: u8) -> Option<u8> {
+    match pin {
+        62..=69 => Some(pin - 62),
+        _ => None,
+    }
+}
+
+/// Starts counting the pulses of a pin.
+/// # Arguments
+/// * `pin` - a u8, the digital pin, analog pins A8 to A15 used as digital pins 62 to 69.
+/// * `debounce` - a u8, the shortest time between two pulses in milliseconds,
+///                a few milliseconds for reed switches and 0 for electronic outputs.
+/// # Returns
+/// * `a boolean` - false if the pin is not in port K.
+pub fn begin(pin: u8, debounce: u8) -> bool {
+    let bit = match port_bit(pin) {
+        Some(bit) => bit,
+        None => return false,
+    };
+    interrupts::free(|| unsafe {
+        PULSES.enabled |= 1 << bit;
+        PULSES.counts[bit as usize] = 0;
+        PULSES.debounce[bit as usize] = debounce;
+        PULSES.last_pulse[bit as usize] = millis().wrapping_sub(debounce as u32);
+    });
+    pcint::attach(pin, Edge::Falling, true, count)
+}
+
+/// Stops counting the pulses of a pin.
+/// # Arguments
+/// * `pin` - a u8, the digital pin given to `begin`.
+pub fn stop(pin: u8) {
+    if let Some(bit) = port_bit(pin) {
+        pcint::detach(pin);
+        interrupts::free(|| unsafe { PULSES.enabled &= !(1 << bit) });
+    }
+}
+
+/// Takes the pulses counted since the previous call.
+/// # Arguments
+/// * `pin` - a u8, the digital pin given to `begin`.
+/// # Returns
+/// * `a u16` - the number of pulses, 0 for a pin which is not counted.
+pub fn take(pin: u8) -> u16 {
+    match port_bit(pin) {
+        Some(bit) => interrupts::free(|| unsafe {
+            let count = PULSES.counts[bit as usize];
+            PULSES.counts[bit as usize] = 0;
+            count
+        }),
+        None => 0,
+    }
+}
+
+/// Counts a falling edge of a pin, called from the pin change interrupt.
+fn count(pin: u8, _high: bool) {
+    if let Some(bit) = port_bit(pin) {
+        let bit = bit as usize;
+        let now = millis();
+        unsafe {
+            if now.wrapping_sub(PULSES.last_pulse[bit]) >= PULSES.debounce[bit] as u32 {
+                PULSES.counts[bit] = PULSES.counts[bit].wrapping_add(1);
+                PULSES.last_pulse[bit] = now;
+            }
+        }
+    }
+}
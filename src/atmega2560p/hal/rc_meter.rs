@@ -0,0 +1,291 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Measurement of unknown capacitors and resistors from the time an RC circuit
+//! takes to charge, which turns the board into a basic component tester.
+//! The node between the resistor and the capacitor, whose other side is
+//! grounded, is wired to the input capture pin ICP4 (digital pin 49). The capacitor is first
+//! discharged through that pin, then charged through the resistor from a
+//! charge pin driven high, or through the internal pull up of the capture pin.
+//! Timer/Counter4 counts CPU cycles until the capture pin reads high, which
+//! happens after `k * R * C`, `k` depending on the input threshold of the pin.
+//! With a known resistor the capacitor is measured, with a known capacitor
+//! the resistor is. The default `k` suits a 5 V supply, `calibrate` finds the
+//! exact one, and the resistance of the source, from a known component.
+//! Timer/Counter4 is taken over during the measurements, its settings are restored after.
+//! Section 17.6 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::port::{Pin, PortName};
+use crate::atmega2560p::hal::power::Power;
+use crate::config::CPU_FREQUENCY_HZ;
+use crate::delay::delay_ms;
+use crate::{Error, Result};
+use core::ptr::{read_volatile, write_volatile};
+use embedded_hal::digital::OutputPin;
+
+/// Addresses of the Timer/Counter4 registers.
+const TCCRA: *mut u8 = 0xA0 as *mut u8;
+const TCCRB: *mut u8 = 0xA1 as *mut u8;
+const TCNTL: *mut u8 = 0xA4 as *mut u8;
+const TCNTH: *mut u8 = 0xA5 as *mut u8;
+const ICRL: *mut u8 = 0xA6 as *mut u8;
+const ICRH: *mut u8 = 0xA7 as *mut u8;
+const TIMSK: *mut u8 = 0x72 as *mut u8;
+const TIFR: *mut u8 = 0x39 as *mut u8;
+
+/// Bit of Timer/Counter4 in the power reduction register 1.
+const PRTIM: u8 = 1 << 4;
+/// Bits of the flag register, input capture and overflow.
+const ICF: u8 = 0x20;
+const TOV: u8 = 0x01;
+
+/// Port and bit of the input capture pin.
+const ICP_PORT: PortName = PortName::L;
+const ICP_BIT: usize = 0;
+
+/// Time taken to reach the input threshold in RC time constants, for a
+/// threshold of 0.55 VCC.
+pub const DEFAULT_THRESHOLD: f32 = 0.8;
+
+/// Nominal resistance of the internal pull up, between 20 and 50 kOhm.
+pub const PULL_UP_OHMS: f32 = 35_000.0;
+
+/// The source charging the capacitor.
+pub enum Charge {
+    /// A pin driving the known or unknown resistor.
+    Pin(Pin),
+    /// The internal pull up of the capture pin, needing no other part.
+    PullUp,
+}
+
+/// A tester of capacitors and resistors, see the module documentation.
+/// # Elements
+/// * `charge` - a `Charge` object, the source charging the capacitor.
+/// * `ohms` - a f32, the resistance of the source in ohms.
+/// * `threshold` - a f32, the charge time in time constants.
+/// * `discharge_ms` - a u32, the time given to the capacitor to discharge.
+/// * `timeout` - a u32, the longest charge time in CPU cycles.
+pub struct RcMeter {
+    charge: Charge,
+    ohms: f32,
+    threshold: f32,
+    discharge_ms: u32,
+    timeout: u32,
+}
+
+impl RcMeter {
+    /// Creates a tester charging through a resistor from a pin.
+    /// # Arguments
+    /// * `charge` - a `Pin` object, the pin driving the resistor.
+    /// * `ohms` - a f32, the resistance of the resistor, ignored when measuring it.
+    /// # Returns
+    /// * `a RcMeter object` - which will be used for further implementations.
+    pub fn new(charge: Pin, ohms: f32) -> RcMeter {
+        RcMeter::with_charge(Charge::Pin(charge), ohms)
+    }
+
+    /// Creates a tester charging through the internal pull up of the capture pin,
+    /// whose resistance is only known once calibrated with a known capacitor.
+    /// # Returns
+    /// * `a RcMeter object` - which will be used for further implementations.
+    pub fn with_pull_up() -> RcMeter {
+        RcMeter::with_charge(Charge::PullUp, PULL_UP_OHMS)
+    }
+
+    fn with_charge(charge: Charge, ohms: f32) -> RcMeter {
+        let mut meter = RcMeter {
+            charge,
+            ohms,
+            threshold: DEFAULT_THRESHOLD,
+            discharge_ms: 10,
+            timeout: CPU_FREQUENCY_HZ,
+        };
+        meter.discharge();
+        meter
+    }
+
+    /// Sets the time given to the capacitor to discharge, which must be several
+    /// times the time constant of the capacitor with the output resistance of the pin.
+    /// # Arguments
+    /// * `ms` - a u32, the time in milliseconds, 10 by default.
+    pub fn set_discharge_ms(&mut self, ms: u32) {
+        self.discharge_ms = ms;
+    }
+
+    /// Sets the longest charge time, after which the measurements fail.
+    /// # Arguments
+    /// * `ms` - a u32, the time in milliseconds, 1000 by default.
+    pub fn set_timeout_ms(&mut self, ms: u32) {
+        self.timeout = (CPU_FREQUENCY_HZ / 1000).saturating_mul(ms);
+    }
+
+    /// Returns the charge time in time constants.
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Returns the resistance of the source in ohms.
+    pub fn ohms(&self) -> f32 {
+        self.ohms
+    }
+
+    /// Finds the charge time in time constants from a known resistor and capacitor.
+    /// # Arguments
+    /// * `nanofarads` - a f32, the capacitance of the known capacitor.
+    /// # Returns
+    /// * `a Result<f32>` - the threshold found, `Timeout` if the pin never went high.
+    pub fn calibrate(&mut self, nanofarads: f32) -> Result<f32> {
+        let seconds = self.charge_seconds()?;
+        self.threshold = seconds / (self.ohms * nanofarads * 1e-9);
+        Ok(self.threshold)
+    }
+
+    /// Finds the resistance of the source from a known capacitor, keeping the
+    /// threshold, for the internal pull up.
+    /// # Arguments
+    /// * `nanofarads` - a f32, the capacitance of the known capacitor.
+    /// # Returns
+    /// * `a Result<f32>` - the resistance found, `Timeout` if the pin never went high.
+    pub fn calibrate_pull_up(&mut self, nanofarads: f32) -> Result<f32> {
+        self.ohms = self.resistance(nanofarads)?;
+        Ok(self.ohms)
+    }
+
+    /// Measures a capacitor charged through the source.
+    /// # Returns
+    /// * `a Result<f32>` - the capacitance in nanofarads, `Timeout` if it took too long.
+    pub fn capacitance(&mut self) -> Result<f32> {
+        let seconds = self.charge_seconds()?;
+        Ok(seconds / (self.threshold * self.ohms) * 1e9)
+    }
+
+    /// Measures a resistor charging a known capacitor.
+    /// # Arguments
+    /// * `nanofarads` - a f32, the capacitance of the known capacitor.
+    /// # Returns
+    /// * `a Result<f32>` - the resistance in ohms, `Timeout` if it took too long.
+    pub fn resistance(&mut self, nanofarads: f32) -> Result<f32> {
+        if nanofarads <= 0.0 {
+            return Err(Error::InvalidArgument);
+        }
+        let seconds = self.charge_seconds()?;
+        Ok(seconds / (self.threshold * nanofarads * 1e-9))
+    }
+
+    /// Gives back the charge pin, left low.
+    pub fn release(self) -> Option<Pin> {
+        match self.charge {
+            Charge::Pin(pin) => Some(pin),
+            Charge::PullUp => None,
+        }
+    }
+
+    /// Returns the capture pin.
+    fn capture_pin() -> Pin {
+        Pin::new(ICP_PORT, ICP_BIT).unwrap()
+    }
+
+    /// Drives the node and the charge pin low.
+    fn discharge(&mut self) {
+        if let Charge::Pin(pin) = &mut self.charge {
+            pin.set_low().ok();
+            pin.set_output();
+        }
+        let mut capture = RcMeter::capture_pin();
+        capture.set_low().ok();
+        capture.set_output();
+    }
+
+    /// Discharges the capacitor and times its charge.
+    /// # Returns
+    /// * `a Result<f32>` - the charge time in seconds.
+    fn charge_seconds(&mut self) -> Result<f32> {
+        self.discharge();
+        delay_ms(self.discharge_ms);
+        let cycles = self.charge_cycles();
+        self.discharge();
+        let cycles = cycles?;
+        Ok(cycles as f32 / CPU_FREQUENCY_HZ as f32)
+    }
+
+    /// Starts the charge and counts the CPU cycles until the capture pin reads high.
+    fn charge_cycles(&mut self) -> Result<u32> {
+        let saved = unsafe {
+            (
+                read_volatile(TCCRA),
+                read_volatile(TCCRB),
+                read_volatile(TIMSK),
+            )
+        };
+        interrupts::free(|| unsafe {
+            let power = Power::new();
+            write_volatile(&mut power.prr1, read_volatile(&power.prr1) & !PRTIM);
+            // Normal mode, no interrupt, capture on a rising edge with the
+            // noise canceler, clock not divided.
+            write_volatile(TIMSK, 0);
+            write_volatile(TCCRA, 0);
+            write_volatile(TCCRB, 0);
+            write_volatile(TCNTH, 0);
+            write_volatile(TCNTL, 0);
+            write_volatile(TIFR, ICF | TOV);
+
+            let mut capture = RcMeter::capture_pin();
+            capture.set_input();
+            write_volatile(TCCRB, 0xC1);
+            match &mut self.charge {
+                Charge::Pin(pin) => {
+                    pin.set_high().ok();
+                }
+                Charge::PullUp => capture.set_pull_up(true),
+            }
+        });
+
+        // Interrupts are served meanwhile, an overflow is only missed if
+        // they hold the CPU for more than 65536 cycles.
+        let mut overflows: u32 = 0;
+        let result = loop {
+            let flags = unsafe { read_volatile(TIFR) };
+            if flags & ICF != 0 {
+                let count = unsafe {
+                    let low = read_volatile(ICRL) as u32;
+                    let high = read_volatile(ICRH) as u32;
+                    (high << 8) | low
+                };
+                // An overflow flagged with a capture just after it is still to be counted.
+                if flags & TOV != 0 && count < 0x8000 {
+                    overflows += 1;
+                }
+                break Ok((overflows << 16) | count);
+            }
+            if flags & TOV != 0 {
+                unsafe { write_volatile(TIFR, TOV) };
+                overflows += 1;
+                if overflows >= self.timeout >> 16 {
+                    break Err(Error::Timeout);
+                }
+            }
+        };
+        unsafe {
+            write_volatile(TCCRB, 0);
+            write_volatile(TIFR, ICF | TOV);
+            write_volatile(TCCRA, saved.0);
+            write_volatile(TCCRB, saved.1);
+            write_volatile(TIMSK, saved.2);
+        }
+        result
+    }
+}
@@ -0,0 +1,185 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Continuous sampling of one analog input into a queue, for signals which
+//! must be sampled at a steady rate, like biopotentials, audio or vibrations.
+//! The ADC is auto triggered, either free running, starting a conversion as
+//! soon as the previous one completes, or by the overflow of Timer0, which
+//! runs `millis` and overflows 976.5625 times per second. The conversion
+//! complete interrupt puts every result in a queue of `QUEUE - 1` samples
+//! which the main loop empties with `take`, samples coming while it is full are counted as overruns.
+//! A callback set with `set_callback` receives the results in the interrupt
+//! instead, for filters or triggers which must keep up with every sample.
+//! The input is referenced to AVcc. `analog` reads must not be made while sampling.
+//! The module needs the `sampler` feature, because its interrupt service
+//! routine takes the ADC conversion complete vector.
+//! Section 26 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::power::Power;
+use crate::collections::Queue;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Addresses of the ADC registers.
+const ADCL: *mut u8 = 0x78 as *mut u8;
+const ADCH: *mut u8 = 0x79 as *mut u8;
+const ADCSRA: *mut u8 = 0x7A as *mut u8;
+const ADCSRB: *mut u8 = 0x7B as *mut u8;
+const ADMUX: *mut u8 = 0x7C as *mut u8;
+const DIDR2: *mut u8 = 0x7D as *mut u8;
+const DIDR0: *mut u8 = 0x7E as *mut u8;
+
+/// Bits of ADCSRA.
+const ADEN: u8 = 0x80;
+const ADSC: u8 = 0x40;
+const ADATE: u8 = 0x20;
+const ADIF: u8 = 0x10;
+const ADIE: u8 = 0x08;
+
+/// Size of the sample queue, which holds one sample less.
+pub const QUEUE: usize = 64;
+
+/// Number of analog inputs.
+pub const CHANNELS: u8 = 16;
+
+/// Rates at which the input is sampled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleRate {
+    /// On every overflow of Timer0, 976.5625 samples per second.
+    Timer0,
+    /// Free running with an ADC clock of 125 kHz, 9615 samples per second at full accuracy.
+    Free9615,
+    /// Free running with an ADC clock of 250 kHz, 19231 samples per second.
+    Free19231,
+    /// Free running with an ADC clock of 500 kHz, 38462 samples per second with about 8 bits of accuracy.
+    Free38462,
+}
+
+impl SampleRate {
+    /// Returns the number of samples per second.
+    pub fn hertz(&self) -> f32 {
+        match self {
+            SampleRate::Timer0 => 976.5625,
+            SampleRate::Free9615 => 16_000_000.0 / 128.0 / 13.0,
+            SampleRate::Free19231 => 16_000_000.0 / 64.0 / 13.0,
+            SampleRate::Free38462 => 16_000_000.0 / 32.0 / 13.0,
+        }
+    }
+
+    /// Returns the prescaler bits of ADCSRA and the trigger source bits of ADCSRB.
+    fn bits(&self) -> (u8, u8) {
+        match self {
+            SampleRate::Timer0 => (0x07, 0x04),
+            SampleRate::Free9615 => (0x07, 0x00),
+            SampleRate::Free19231 => (0x06, 0x00),
+            SampleRate::Free38462 => (0x05, 0x00),
+        }
+    }
+}
+
+static SAMPLES: Queue<u16, QUEUE> = Queue::new();
+
+/// Number of samples lost because the queue was full.
+static mut OVERRUNS: u16 = 0;
+
+/// Function receiving the samples instead of the queue.
+static mut CALLBACK: Option<fn(u16)> = None;
+
+/// Starts sampling an analog input, dropping the samples of a previous run.
+/// # Arguments
+/// * `channel` - a u8, the analog input, from 0 to 15.
+/// * `rate` - a `SampleRate` object, the sample rate.
+/// # Returns
+/// * `a boolean` - false if the channel does not exist.
+pub fn start(channel: u8, rate: SampleRate) -> bool {
+    if channel >= CHANNELS {
+        return false;
+    }
+    stop();
+    while take().is_some() {}
+    let (prescaler, trigger) = rate.bits();
+    interrupts::free(|| unsafe {
+        let power = Power::new();
+        write_volatile(&mut power.prr0, read_volatile(&power.prr0) & !0x01);
+        OVERRUNS = 0;
+        if channel < 8 {
+            write_volatile(DIDR0, read_volatile(DIDR0) | (1 << channel));
+        } else {
+            write_volatile(DIDR2, read_volatile(DIDR2) | (1 << (channel - 8)));
+        }
+        // AVcc reference, MUX5 in ADCSRB selects channels 8 to 15.
+        write_volatile(ADMUX, 0x40 | (channel & 0x07));
+        write_volatile(ADCSRB, ((channel & 0x08) | trigger) & 0x0F);
+        write_volatile(ADCSRA, ADEN | ADATE | ADIF | ADIE | prescaler);
+        if trigger == 0 {
+            write_volatile(ADCSRA, read_volatile(ADCSRA) | ADSC);
+        }
+    });
+    true
+}
+
+/// Stops sampling. The samples queued can still be taken.
+pub fn stop() {
+    interrupts::free(|| unsafe {
+        write_volatile(ADCSRA, read_volatile(ADCSRA) & !(ADATE | ADIE));
+        write_volatile(ADCSRB, read_volatile(ADCSRB) & !0x07);
+    });
+}
+
+/// Sets a function called with every sample from the conversion complete
+/// interrupt, the samples are then not queued. The function runs at the
+/// sample rate with the interrupts disabled, so it must return well within
+/// a sample period, 26 us at `SampleRate::Free38462`.
+/// # Arguments
+/// * `callback` - an optional function, `None` to queue the samples again.
+pub fn set_callback(callback: Option<fn(u16)>) {
+    interrupts::free(|| unsafe { CALLBACK = callback });
+}
+
+/// Takes the oldest sample.
+/// # Returns
+/// * `a Option<u16>` - the 10 bit sample, `None` if the queue is empty.
+pub fn take() -> Option<u16> {
+    unsafe { SAMPLES.dequeue() }
+}
+
+/// Returns the number of samples waiting in the queue.
+pub fn available() -> usize {
+    SAMPLES.len()
+}
+
+/// Returns the number of samples lost since `start` because the queue was full.
+pub fn overruns() -> u16 {
+    interrupts::free(|| unsafe { OVERRUNS })
+}
+
+/// ADC conversion complete interrupt service routine, queues the result
+/// or passes it to the callback, unless it belongs to a scan of `hal::adc`.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_29"]
+pub unsafe extern "avr-interrupt" fn adc_complete() {
+    let low = read_volatile(ADCL) as u16;
+    let high = read_volatile(ADCH) as u16;
+    let sample = (high << 8) | low;
+    if crate::atmega2560p::hal::adc::scan_complete(sample) {
+        return;
+    }
+    if let Some(callback) = CALLBACK {
+        callback(sample);
+    } else if SAMPLES.enqueue(sample).is_err() {
+        OVERRUNS = OVERRUNS.saturating_add(1);
+    }
+}
@@ -0,0 +1,208 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A 1-bit sigma-delta modulator turning any output pin and an RC low pass
+//! filter into an analog output, for control voltages where the ripple of PWM
+//! is unacceptable. At every tick of Timer2 the level of each channel is added
+//! to an accumulator and the pin is high when it overflows, so the pin is high
+//! for `level` ticks out of 256, spread as evenly as possible instead of in one
+//! pulse. The ripple is then at the tick rate for the middle levels, and only
+//! goes down to the rate divided by 256 near both ends of the range.
+//! At the default 31.25 kHz a filter of 10 kOhm and 1 uF gives about 8 bits.
+//! Up to `SIGMA_DELTA_CHANNELS` pins are driven from the Timer2 compare match B
+//! interrupt, which runs in CTC mode, so `hal::tone` and `hal::audio` cannot be
+//! used meanwhile.
+//! The module needs the `sigma-delta` feature, because its interrupt service
+//! routine takes the Timer2 compare B vector.
+//! Section 20 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::port::{Pin, Port};
+use crate::atmega2560p::hal::power::Power;
+use crate::config::CPU_FREQUENCY_HZ;
+use core::ptr::{addr_of, read_volatile, write_volatile};
+use embedded_hal::digital::OutputPin;
+
+/// Addresses of the Timer/Counter2 registers.
+const TCCR2A: *mut u8 = 0xB0 as *mut u8;
+const TCCR2B: *mut u8 = 0xB1 as *mut u8;
+const OCR2A: *mut u8 = 0xB3 as *mut u8;
+const OCR2B: *mut u8 = 0xB4 as *mut u8;
+const TIMSK2: *mut u8 = 0x70 as *mut u8;
+const TIFR2: *mut u8 = 0x37 as *mut u8;
+
+/// Bit of the compare match B interrupt in TIMSK2 and TIFR2.
+const OCIE2B: u8 = 0x04;
+
+/// Number of pins which can be modulated at once.
+pub const SIGMA_DELTA_CHANNELS: usize = 4;
+
+/// Tick rate used when none is given, in Hz.
+pub const DEFAULT_RATE: u32 = 31_250;
+
+/// Highest tick rate, leaving time to the main loop between the interrupts.
+pub const MAX_RATE: u32 = 62_500;
+
+/// Timer2 counts with the clock divided by 8.
+const PRESCALER: u32 = 8;
+
+/// A modulated pin, shared with the interrupt service routine.
+/// # Elements
+/// * `port` - a pointer to the `Port` of the pin, null for a free channel.
+/// * `mask` - a u8, the bit of the pin in the port registers.
+/// * `level` - a u8, the output level, the pin being high `level` ticks out of 256.
+/// * `accumulator` - a u8, the sum of the levels, which overflows into the output.
+#[derive(Clone, Copy)]
+struct Channel {
+    port: *mut Port,
+    mask: u8,
+    level: u8,
+    accumulator: u8,
+}
+
+const FREE: Channel = Channel {
+    port: core::ptr::null_mut(),
+    mask: 0,
+    level: 0,
+    accumulator: 0,
+};
+
+static mut CHANNELS: [Channel; SIGMA_DELTA_CHANNELS] = [FREE; SIGMA_DELTA_CHANNELS];
+
+/// An analog output on a pin modulated by Timer2.
+/// # Elements
+/// * `pin` - a `Pin` object, the output pin.
+/// * `channel` - a usize, the channel of the pin.
+pub struct SigmaDeltaDac {
+    pin: Pin,
+    channel: usize,
+}
+
+impl SigmaDeltaDac {
+    /// Makes a pin an output of the modulator, at level 0.
+    /// The modulator runs once `start` has been called.
+    /// # Arguments
+    /// * `pin` - a `Pin` object, any pin, followed by the RC filter.
+    /// # Returns
+    /// * `a Option<SigmaDeltaDac>` - `None` if every channel is taken.
+    pub fn new(mut pin: Pin) -> Option<SigmaDeltaDac> {
+        pin.set_low().ok();
+        pin.set_output();
+        let mask = 1 << pin.pin;
+        let channel = interrupts::free(|| unsafe {
+            let channel = (*addr_of!(CHANNELS))
+                .iter()
+                .position(|c| c.port.is_null())?;
+            CHANNELS[channel] = Channel {
+                port: pin.port,
+                mask,
+                ..FREE
+            };
+            Some(channel)
+        })?;
+        Some(SigmaDeltaDac { pin, channel })
+    }
+
+    /// Sets the output level.
+    /// # Arguments
+    /// * `level` - a u8, the output voltage in 256ths of VCC.
+    pub fn set_level(&mut self, level: u8) {
+        interrupts::free(|| unsafe { CHANNELS[self.channel].level = level });
+    }
+
+    /// Sets the output voltage.
+    /// # Arguments
+    /// * `millivolts` - a u16, the voltage, limited to the supply voltage.
+    /// * `supply` - a u16, the supply voltage in millivolts, usually 5000.
+    pub fn set_millivolts(&mut self, millivolts: u16, supply: u16) {
+        let level = if supply == 0 {
+            0
+        } else {
+            (millivolts as u32 * 256 / supply as u32).min(255)
+        };
+        self.set_level(level as u8);
+    }
+
+    /// Returns the output level.
+    pub fn level(&self) -> u8 {
+        interrupts::free(|| unsafe { CHANNELS[self.channel].level })
+    }
+
+    /// Frees the channel and gives back the pin, left low.
+    /// Timer2 keeps running, see `stop`.
+    pub fn release(mut self) -> Pin {
+        interrupts::free(|| unsafe { CHANNELS[self.channel] = FREE });
+        self.pin.set_low().ok();
+        self.pin
+    }
+}
+
+/// Starts the modulator at the given tick rate, taking over Timer2.
+/// # Arguments
+/// * `rate` - a u32, the tick rate in Hz, `DEFAULT_RATE` or up to `MAX_RATE`.
+/// # Returns
+/// * `a boolean` - false if the rate cannot be reached, the timer is then left untouched.
+pub fn start(rate: u32) -> bool {
+    if rate == 0 || rate > MAX_RATE {
+        return false;
+    }
+    let top = CPU_FREQUENCY_HZ / PRESCALER / rate;
+    if top == 0 || top > 256 {
+        return false;
+    }
+    interrupts::free(|| unsafe {
+        let power = Power::new();
+        write_volatile(&mut power.prr0, read_volatile(&power.prr0) & !(1 << 6));
+        // CTC mode (WGM22:0 = 010) with OCR2A as TOP, clock divided by 8,
+        // compare match B once per period when the counter restarts.
+        write_volatile(TCCR2B, 0);
+        write_volatile(TCCR2A, 0x02);
+        write_volatile(OCR2A, (top - 1) as u8);
+        write_volatile(OCR2B, 0);
+        write_volatile(TIFR2, OCIE2B);
+        write_volatile(TIMSK2, read_volatile(TIMSK2) | OCIE2B);
+        write_volatile(TCCR2B, 0x02);
+        interrupts::Interrupt::enable(&mut interrupts::Interrupt::new());
+    });
+    true
+}
+
+/// Stops the modulator and releases Timer2, the pins keep their last level.
+pub fn stop() {
+    interrupts::free(|| unsafe {
+        write_volatile(TIMSK2, read_volatile(TIMSK2) & !OCIE2B);
+        write_volatile(TCCR2A, 0);
+        write_volatile(TCCR2B, 0);
+    });
+}
+
+/// Timer/Counter2 compare match B interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_14"]
+pub unsafe extern "avr-interrupt" fn timer2_compare_b() {
+    for channel in CHANNELS.iter_mut() {
+        if channel.port.is_null() {
+            continue;
+        }
+        let (accumulator, high) = channel.accumulator.overflowing_add(channel.level);
+        channel.accumulator = accumulator;
+        // Writing a one to PINxn toggles the pin, in a single write.
+        let port = &mut *channel.port;
+        if (read_volatile(&port.port) & channel.mask != 0) != high {
+            write_volatile(&mut port.pin, channel.mask);
+        }
+    }
+}
@@ -19,6 +19,8 @@
 //! Also references from Section 11.4.
 
 // Crates required in the code for reading and writing to registers.
+#[cfg(feature = "watchdog-interrupt")]
+use crate::atmega2560p::hal::watchdog::{WatchDog, WatchdogTimeout};
 use core::ptr::{read_volatile, write_volatile};
 
 /// Various modes are
@@ -105,3 +107,49 @@ impl Sleep {
         }
     }
 }
+
+/// Puts the MCU in idle mode until the next interrupt occurs
+/// and disables the sleep mode again after waking up.
+/// Timers keep running in idle mode, so the millisecond counter interrupt
+/// wakes the MCU up at least once every millisecond.
+pub fn idle() {
+    let sleep = unsafe { Sleep::new() };
+    sleep.select_mode(SleepMode::IDLE);
+    crate::__sleep();
+    sleep.disable();
+}
+
+/// Puts the MCU in power-down mode until it is woken by an external interrupt,
+/// a pin change, a TWI address match or the watchdog, and disables the sleep mode
+/// again after waking up. All clocks stop, so `millis` does not advance meanwhile.
+pub fn power_down() {
+    let sleep = unsafe { Sleep::new() };
+    sleep.select_mode(SleepMode::PD);
+    crate::__sleep();
+    sleep.disable();
+}
+
+/// Puts the MCU in power-save mode until it is woken by Timer2, running from its
+/// asynchronous clock, or by any source of power-down mode, and disables the
+/// sleep mode again after waking up. See `hal::async_timer`.
+pub fn power_save() {
+    let sleep = unsafe { Sleep::new() };
+    sleep.select_mode(SleepMode::PS);
+    crate::__sleep();
+    sleep.disable();
+}
+
+/// Puts the MCU in power-down mode for about the given time, woken up by
+/// the watchdog in interrupt mode, which is disabled again afterwards.
+/// Needs the `watchdog-interrupt` feature.
+/// Global interrupts must be enabled. A watchdog used in system reset mode,
+/// for example by the supervisor, is left disabled too.
+/// # Arguments
+/// * `timeout` - a `WatchdogTimeout`, the time to sleep.
+#[cfg(feature = "watchdog-interrupt")]
+pub fn power_down_for(timeout: WatchdogTimeout) {
+    let watchdog = unsafe { WatchDog::new() };
+    watchdog.enable_interrupt(timeout);
+    power_down();
+    watchdog.disable();
+}
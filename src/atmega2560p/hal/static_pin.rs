@@ -0,0 +1,178 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Pins whose port and number are const generics, like `StaticPin<PORTB, 5>`,
+//! for the bit banged protocols too fast for `Pin`, like WS2812 LEDs or
+//! software serial ports. The register addresses and the bit are known at
+//! compile time, so the pins need no lookup and `toggle` is a single write
+//! to PINx. `high`, `low` and the mode changes read, change and write their
+//! register with interrupts off, so that a write made by an interrupt
+//! handler to another pin of the port in between is not lost.
+//! A pin number above 7 does not compile.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::port::{Pin, Port};
+use core::convert::Infallible;
+use core::ptr::{read_volatile, write_volatile};
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
+
+/// Addresses of the ports, the address of their PINx register.
+pub const PORTA: usize = 0x20;
+pub const PORTB: usize = 0x23;
+pub const PORTC: usize = 0x26;
+pub const PORTD: usize = 0x29;
+pub const PORTE: usize = 0x2C;
+pub const PORTF: usize = 0x2F;
+pub const PORTG: usize = 0x32;
+pub const PORTH: usize = 0x100;
+pub const PORTJ: usize = 0x103;
+pub const PORTK: usize = 0x106;
+pub const PORTL: usize = 0x109;
+
+/// A pin known at compile time, holding no data.
+/// `PORT` is one of the port addresses of this module and `PIN` the bit in the port.
+#[derive(Clone, Copy)]
+pub struct StaticPin<const PORT: usize, const PIN: u8>;
+
+impl<const PORT: usize, const PIN: u8> StaticPin<PORT, PIN> {
+    /// The bit of the pin in the port registers, checked at compile time.
+    const MASK: u8 = 1 << PIN;
+
+    /// The PINx, DDRx and PORTx registers of the port.
+    const PINX: *mut u8 = PORT as *mut u8;
+    const DDRX: *mut u8 = (PORT + 1) as *mut u8;
+    const PORTX: *mut u8 = (PORT + 2) as *mut u8;
+
+    /// Returns the pin, leaving its mode unchanged.
+    pub const fn new() -> StaticPin<PORT, PIN> {
+        StaticPin
+    }
+
+    /// Sets or clears the bit of the pin in a register, with interrupts off.
+    #[inline(always)]
+    fn write_bit(register: *mut u8, set: bool) {
+        interrupts::free(|| unsafe {
+            let value = read_volatile(register);
+            if set {
+                write_volatile(register, value | Self::MASK);
+            } else {
+                write_volatile(register, value & !Self::MASK);
+            }
+        });
+    }
+
+    /// Makes the pin an output.
+    #[inline(always)]
+    pub fn set_output(&mut self) {
+        Self::write_bit(Self::DDRX, true);
+    }
+
+    /// Makes the pin an input, with or without pull up.
+    /// # Arguments
+    /// * `pull_up` - a boolean, true to enable the internal pull up.
+    #[inline(always)]
+    pub fn set_input(&mut self, pull_up: bool) {
+        Self::write_bit(Self::DDRX, false);
+        Self::write_bit(Self::PORTX, pull_up);
+    }
+
+    /// Drives the pin high.
+    #[inline(always)]
+    pub fn high(&mut self) {
+        Self::write_bit(Self::PORTX, true);
+    }
+
+    /// Drives the pin low.
+    #[inline(always)]
+    pub fn low(&mut self) {
+        Self::write_bit(Self::PORTX, false);
+    }
+
+    /// Inverts the level of the pin, writing a one to PINxn.
+    #[inline(always)]
+    pub fn toggle(&mut self) {
+        unsafe { write_volatile(Self::PINX, Self::MASK) }
+    }
+
+    /// Returns true if the level on the pin is high.
+    #[inline(always)]
+    pub fn read(&self) -> bool {
+        unsafe { read_volatile(Self::PINX) & Self::MASK != 0 }
+    }
+
+    /// Returns true if the pin is driven high, or pulled up as an input.
+    #[inline(always)]
+    pub fn is_set_high(&self) -> bool {
+        unsafe { read_volatile(Self::PORTX) & Self::MASK != 0 }
+    }
+
+    /// Returns the pin as a `Pin`, whose mode is set at runtime.
+    pub fn into_pin(self) -> Pin {
+        let port = unsafe { &mut *(PORT as *mut Port) };
+        // Evaluating the mask does not compile for a pin number above 7.
+        let _ = Self::MASK;
+        port.pin_unchecked(PIN as usize)
+    }
+}
+
+impl<const PORT: usize, const PIN: u8> Default for StaticPin<PORT, PIN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Implementations of the embedded-hal digital traits, so that platform
+// agnostic drivers can use the pins. The pin must be in the right mode.
+impl<const PORT: usize, const PIN: u8> ErrorType for StaticPin<PORT, PIN> {
+    type Error = Infallible;
+}
+
+impl<const PORT: usize, const PIN: u8> OutputPin for StaticPin<PORT, PIN> {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.low();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.high();
+        Ok(())
+    }
+}
+
+impl<const PORT: usize, const PIN: u8> StatefulOutputPin for StaticPin<PORT, PIN> {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(StaticPin::is_set_high(self))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!StaticPin::is_set_high(self))
+    }
+
+    fn toggle(&mut self) -> Result<(), Infallible> {
+        StaticPin::toggle(self);
+        Ok(())
+    }
+}
+
+impl<const PORT: usize, const PIN: u8> InputPin for StaticPin<PORT, PIN> {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.read())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.read())
+    }
+}
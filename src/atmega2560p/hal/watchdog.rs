@@ -15,12 +15,57 @@
 //     along with this program.  If not, see <https://www.gnu.org/licenses/>
 
 //! Control on Watchdog timer in ATMEGA2560P
+//! `enable_interrupt` needs the `watchdog-interrupt` feature, which adds the
+//! interrupt service routine of the watchdog time-out vector.
 //! Section 12.5 and 28.6 of manual
 
 // Crates required in the code for reading and writing to registers.
+use crate::__wdr;
 use crate::atmega2560p::hal::interrupts;
 use core::ptr::{read_volatile, write_volatile};
 
+/// Timeout of the watchdog timer, selected by the prescaler bits WDP3:0.
+/// The watchdog runs from a separate 128 kHz oscillator, so these values
+/// are nominal and independent of the CPU clock.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum WatchdogTimeout {
+    Ms16 = 0,
+    Ms32 = 1,
+    Ms64 = 2,
+    Ms125 = 3,
+    Ms250 = 4,
+    Ms500 = 5,
+    S1 = 6,
+    S2 = 7,
+    S4 = 8,
+    S8 = 9,
+}
+
+impl WatchdogTimeout {
+    /// Returns the nominal timeout in milliseconds.
+    pub fn millis(&self) -> u32 {
+        match self {
+            WatchdogTimeout::Ms16 => 16,
+            WatchdogTimeout::Ms32 => 32,
+            WatchdogTimeout::Ms64 => 64,
+            WatchdogTimeout::Ms125 => 125,
+            WatchdogTimeout::Ms250 => 250,
+            WatchdogTimeout::Ms500 => 500,
+            WatchdogTimeout::S1 => 1000,
+            WatchdogTimeout::S2 => 2000,
+            WatchdogTimeout::S4 => 4000,
+            WatchdogTimeout::S8 => 8000,
+        }
+    }
+
+    /// Returns the prescaler bits as laid out in WDTCSR, WDP3 being bit 5.
+    fn bits(&self) -> u8 {
+        let value = *self as u8;
+        (value & 0x07) | ((value & 0x08) << 2)
+    }
+}
+
 /// Use interrupts to enable/disable global interrupts,
 /// prior to disabling watchdog, all interrupts must be disabled.
 /// A new struct of WatchDog can be created through new() function.
@@ -67,4 +112,60 @@ impl WatchDog {
             interrupts::Interrupt::enable(&mut interrupts::Interrupt::new());
         }
     }
+
+    /// Enables the watchdog in system reset mode with the given timeout.
+    /// From then on `feed` must be called more often than the timeout,
+    /// otherwise the microcontroller is reset.
+    /// # Arguments
+    /// * `timeout` - a `WatchdogTimeout`, the time after which the system is reset.
+    pub fn enable(&mut self, timeout: WatchdogTimeout) {
+        interrupts::free(|| {
+            __wdr();
+            unsafe {
+                // Timed sequence, WDCE and WDE must be set before changing the prescaler.
+                write_volatile(&mut self.wdtcsr, (1 << 4) | (1 << 3));
+                write_volatile(&mut self.wdtcsr, (1 << 3) | timeout.bits());
+            }
+        });
+    }
+
+    /// Enables the watchdog in interrupt mode with the given timeout, so that
+    /// it wakes the microcontroller up from any sleep mode instead of resetting it.
+    /// # Arguments
+    /// * `timeout` - a `WatchdogTimeout`, the time after which the interrupt occurs.
+    #[cfg(feature = "watchdog-interrupt")]
+    pub fn enable_interrupt(&mut self, timeout: WatchdogTimeout) {
+        interrupts::free(|| {
+            __wdr();
+            unsafe {
+                // Timed sequence, WDCE and WDE must be set before changing the prescaler.
+                write_volatile(&mut self.wdtcsr, (1 << 4) | (1 << 3));
+                write_volatile(&mut self.wdtcsr, (1 << 6) | timeout.bits());
+            }
+        });
+    }
+
+    /// Restarts the watchdog timer so that it does not time out.
+    pub fn feed(&self) {
+        __wdr();
+    }
+
+    /// Returns true if the last reset of the microcontroller was caused
+    /// by the watchdog, as recorded by the WDRF bit of MCUSR.
+    pub fn caused_reset(&self) -> bool {
+        unsafe { read_volatile(&self.mcusr) & (1 << 3) != 0 }
+    }
+
+    /// Resets the microcontroller by letting the watchdog time out
+    /// with its shortest timeout.
+    pub fn system_reset(&mut self) -> ! {
+        self.enable(WatchdogTimeout::Ms16);
+        loop {}
+    }
 }
+
+/// Watchdog time-out interrupt service routine, which only wakes the microcontroller up.
+#[cfg(feature = "watchdog-interrupt")]
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_12"]
+pub unsafe extern "avr-interrupt" fn watchdog_timeout() {}
@@ -0,0 +1,130 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Wiegand reader for access control keypads and card readers.
+//! The DATA0 and DATA1 lines idle high and are pulled low for about 50 us
+//! for every 0 and 1 bit respectively, so the reader watches the falling
+//! edges of both through `hal::pcint`, on any pins with a pin change interrupt.
+//! The internal pull ups are enabled, as the readers have open collector outputs.
+//! A frame ends once no bit arrived for `FRAME_TIMEOUT` milliseconds, it is then
+//! checked and decoded by `crate::encoding::wiegand::decode`.
+//! The module needs the `pcint` and `millis` features.
+//! Section 13 of the manual.
+
+use crate::atmega2560p::hal::interrupts;
+use crate::atmega2560p::hal::millis::millis;
+use crate::atmega2560p::hal::pcint::{self, Edge};
+use crate::encoding::wiegand::{decode, WiegandData};
+
+/// Marks the data pins of a stopped reader.
+const STOPPED: u8 = 0xFF;
+
+/// Time in milliseconds without a bit after which a frame is complete.
+pub const FRAME_TIMEOUT: u32 = 25;
+
+/// State shared with the interrupt service routine.
+/// # Elements
+/// * `d0` - a u8, the digital pin connected to DATA0, `STOPPED` when the reader is stopped.
+/// * `d1` - a u8, the digital pin connected to DATA1.
+/// * `frame` - a u64, the bits received so far, the last one in bit 0.
+/// * `bits` - a u8, the number of bits received so far.
+/// * `last_bit` - a u32, the value of `millis` at the last bit.
+struct WiegandState {
+    d0: u8,
+    d1: u8,
+    frame: u64,
+    bits: u8,
+    last_bit: u32,
+}
+
+static mut WIEGAND: WiegandState = WiegandState {
+    d0: STOPPED,
+    d1: STOPPED,
+    frame: 0,
+    bits: 0,
+    last_bit: 0,
+};
+
+/// Starts the reader.
+/// # Arguments
+/// * `d0` - a u8, the digital pin connected to DATA0, a pin with a pin change interrupt.
+/// * `d1` - a u8, the digital pin connected to DATA1, a pin with a pin change interrupt.
+/// # Returns
+/// * `a boolean` - false if a pin has no pin change interrupt or both are the same.
+pub fn begin(d0: u8, d1: u8) -> bool {
+    if d0 == d1 || pcint::locate(d0).is_none() || pcint::locate(d1).is_none() {
+        return false;
+    }
+    stop();
+    interrupts::free(|| unsafe {
+        WIEGAND = WiegandState {
+            d0,
+            d1,
+            frame: 0,
+            bits: 0,
+            last_bit: millis(),
+        };
+    });
+    pcint::attach(d0, Edge::Falling, true, shift_in);
+    pcint::attach(d1, Edge::Falling, true, shift_in);
+    true
+}
+
+/// Stops the reader and drops a partially received frame.
+pub fn stop() {
+    let (d0, d1) = unsafe { (WIEGAND.d0, WIEGAND.d1) };
+    pcint::detach(d0);
+    pcint::detach(d1);
+    interrupts::free(|| unsafe {
+        WIEGAND.d0 = STOPPED;
+        WIEGAND.d1 = STOPPED;
+        WIEGAND.bits = 0;
+    });
+}
+
+/// Takes the last frame once it is complete.
+/// # Returns
+/// * `a Option<(u64, u8)>` - the bits and the number of bits of the frame, `None` if no frame is complete.
+pub fn read_raw() -> Option<(u64, u8)> {
+    interrupts::free(|| unsafe {
+        if WIEGAND.bits == 0 || millis().wrapping_sub(WIEGAND.last_bit) < FRAME_TIMEOUT {
+            return None;
+        }
+        let frame = (WIEGAND.frame, WIEGAND.bits);
+        WIEGAND.frame = 0;
+        WIEGAND.bits = 0;
+        Some(frame)
+    })
+}
+
+/// Takes and decodes the last frame once it is complete.
+/// Frames with a wrong parity or an unknown length are dropped.
+/// # Returns
+/// * `a Option<WiegandData>` - the card or key read, `None` if no valid frame is complete.
+pub fn read() -> Option<WiegandData> {
+    let (frame, bits) = read_raw()?;
+    decode(frame, bits)
+}
+
+/// Shifts in a bit on every falling edge of DATA0 or DATA1, called from the pin change interrupt.
+fn shift_in(pin: u8, _high: bool) {
+    unsafe {
+        let bit = (pin == WIEGAND.d1) as u64;
+        WIEGAND.frame = (WIEGAND.frame << 1) | bit;
+        WIEGAND.bits = WIEGAND.bits.saturating_add(1);
+        WIEGAND.last_bit = millis();
+    }
+}
@@ -23,11 +23,15 @@
 
 // Standard crates to be used
 use bit_field::BitField;
-use fixed_slice_vec::FixedSliceVec;
+use core::arch::asm;
 use volatile::Volatile;
 
 // Source code crates required
-use crate::delay::delay_ms;
+use crate::atmega328p::hal::interrupts;
+#[cfg(feature = "async")]
+use crate::executor::WaitForBits;
+use crate::{Error, Result};
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
 
 ///  Contains registers fow TWI.
 ///
@@ -69,7 +73,7 @@ use crate::delay::delay_ms;
 /// address bit and the corresponding bit in TWAR.
 #[repr(C, packed)]
 pub struct Twi {
-    _twbr: Volatile<u8>,
+    twbr: Volatile<u8>,
     twsr: Volatile<u8>,
     _twar: Volatile<u8>,
     twdr: Volatile<u8>,
@@ -77,66 +81,187 @@ pub struct Twi {
     _twamr: Volatile<u8>,
 }
 
-// for twcr
-const TWINT: u8 = 0;
-const TWEN: u8 = 5;
+// TWCR register's bits definitions, counted from the least significant bit
+const TWINT: u8 = 7;
+const TWEA: u8 = 6;
+const TWSTA: u8 = 5;
+const TWSTO: u8 = 4;
+const TWEN: u8 = 2;
 
-// for twsr
-const TWPS1: u8 = 6;
-const TWPS0: u8 = 7;
+// TWSR register's bits definitions
+const TWPS0: u8 = 0;
+const TWPS1: u8 = 1;
 
 static TWI_FREQUENCY: u32 = 100000;
 
 ///* This function reads the device clock freequency setup and provide
 ///  the details in form of boolean numbers and a 8 bit unsigned integer to
 ///  check the settings of the I2C carefully.
-///* If the clock freequency in very low then the function panics as the I2C protocol cannot be activated
-///  properly at such low freequencies.
+///* If the clock freequency is too low for the I2C protocol to work at
+///  `TWI_FREQUENCY` an error is returned.
 ///  # Returns
-///  * `a tuple` - Consisting of the following 3 Items -
+///  * `a Result` - Consisting of the following 3 Items -
 ///     * `a u8` - Which is a 2's exponent till 64 which defines the bandwidth rate for TWI I2C initialization.
 ///     * `a boolean` - Which denotes the TWPS bit 1 settings.
 ///     * `a boolean` - Which denotes the TWPS bit 2 settings.
-pub fn prescaler() -> (u8, bool, bool) {
-    if (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 1) >= 10
-        && (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 1) <= 0xFF
-    {
-        return (1, false, false);
-    } else if (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 4) >= 10
-        && (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 4) <= 0xFF
-    {
-        return (4, true, false);
-    } else if (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 16) >= 10
-        && (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 16) <= 0xFF
-    {
-        return (16, false, true);
-    } else if (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 64) >= 10
-        && (crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY - 16) / (2 * 64) <= 0xFF
-    {
-        return (64, true, true);
+pub fn prescaler() -> Result<(u8, bool, bool)> {
+    if bit_rate(1).is_some() {
+        Ok((1, false, false))
+    } else if bit_rate(4).is_some() {
+        Ok((4, true, false))
+    } else if bit_rate(16).is_some() {
+        Ok((16, false, true))
+    } else if bit_rate(64).is_some() {
+        Ok((64, true, true))
     } else {
-        unreachable!();
-        // return (-1, -1, -1);
+        Err(Error::InvalidArgument)
     }
 }
 
-// TWSR values (not bits)
+/// Calculates the value of TWBR for the given prescaler.
+/// # Arguments
+/// * `prescale` - a u32, the prescaler value selected by the TWPS bits.
+/// # Returns
+/// * `a Option<u8>` - The TWBR value, or `None` if it is out of the usable range.
+fn bit_rate(prescale: u32) -> Option<u8> {
+    let cycles = crate::config::CPU_FREQUENCY_HZ / TWI_FREQUENCY;
+    if cycles < 16 {
+        return None;
+    }
+    let twbr = (cycles - 16) / (2 * prescale);
+    if twbr >= 10 && twbr <= 0xFF {
+        Some(twbr as u8)
+    } else {
+        None
+    }
+}
+
+// TWSR status codes
 // (taken from avr-libc twi.h)
 // Master
 const START: u8 = 0x08;
 const REP_START: u8 = 0x10;
-// Master Transmitter;
+const ARB_LOST: u8 = 0x38;
+
+// Master Transmitter
 const MT_SLA_ACK: u8 = 0x18;
+const MT_SLA_NACK: u8 = 0x20;
 const MT_DATA_ACK: u8 = 0x28;
-// Master Receiver;
+const MT_DATA_NACK: u8 = 0x30;
+
+// Master Receiver
 const MR_SLA_ACK: u8 = 0x40;
+const MR_SLA_NACK: u8 = 0x48;
 const MR_DATA_ACK: u8 = 0x50;
 const MR_DATA_NACK: u8 = 0x58;
-// defines and constants;
+
+// Miscellaneous
+const BUS_ERROR: u8 = 0x00;
+
+// Defines and constants
 const TWSR_STATUS_MASK: u8 = 0xF8;
 
-// return values;
-const I2C_TIMEOUT: u32 = 100;
+// Number of polls of TWINT before giving up.
+const I2C_TIMEOUT: u32 = 10000;
+
+// Milliseconds to wait for TWINT in the async functions.
+#[cfg(feature = "async")]
+const I2C_ASYNC_TIMEOUT: u32 = 10;
+
+// Set once the registers have been handed out by `Twi::take()`.
+static mut TAKEN: bool = false;
+
+/// A step of an I2C transfer, given to the trace handler of the master
+/// and returned by the bus sniffer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusEvent {
+    /// A start condition.
+    Start,
+    /// A repeated start condition.
+    RepeatedStart,
+    /// The address byte after a start, and whether a slave acknowledged it.
+    Address { address: u8, read: bool, ack: bool },
+    /// A data byte, and whether its receiver acknowledged it.
+    Data { value: u8, read: bool, ack: bool },
+    /// A stop condition.
+    Stop,
+    /// The TWI did not finish the operation in time.
+    Timeout,
+    /// Any other status of TWSR, like a lost arbitration or a bus error.
+    Status(u8),
+}
+
+// Handler given every step of the transfers of the master, if any.
+static mut TRACE: Option<fn(BusEvent)> = None;
+
+/// Sets a function called with every start, address, byte, acknowledge and
+/// stop of the transfers of the master, for debugging devices which refuse
+/// them. The handler runs in the middle of the transfers, so it should be quick.
+/// # Arguments
+/// * `handler` - an optional function, None to stop tracing.
+pub fn set_trace(handler: Option<fn(BusEvent)>) {
+    interrupts::free(|| unsafe { TRACE = handler });
+}
+
+/// A trace handler writing every step to the defmt log, see `set_trace`.
+/// # Arguments
+/// * `event` - a `BusEvent`, the step of the transfer.
+#[cfg(feature = "defmt")]
+pub fn log_event(event: BusEvent) {
+    defmt::info!("I2C {}", event);
+}
+
+/// Gives a step of a transfer to the trace handler.
+fn trace(event: BusEvent) {
+    if let Some(handler) = unsafe { TRACE } {
+        handler(event);
+    }
+}
+
+/// Converts the status of TWSR after an operation into the step of the transfer.
+/// # Arguments
+/// * `status` - a u8, the masked value of TWSR.
+/// * `data` - a u8, the value of TWDR.
+/// # Returns
+/// * `a BusEvent` - the step which ended.
+fn bus_event(status: u8, data: u8) -> BusEvent {
+    match status {
+        START => BusEvent::Start,
+        REP_START => BusEvent::RepeatedStart,
+        MT_SLA_ACK | MT_SLA_NACK | MR_SLA_ACK | MR_SLA_NACK => BusEvent::Address {
+            address: data >> 1,
+            read: data & 0x01 != 0,
+            ack: status == MT_SLA_ACK || status == MR_SLA_ACK,
+        },
+        MT_DATA_ACK | MT_DATA_NACK => BusEvent::Data {
+            value: data,
+            read: false,
+            ack: status == MT_DATA_ACK,
+        },
+        MR_DATA_ACK | MR_DATA_NACK => BusEvent::Data {
+            value: data,
+            read: true,
+            ack: status == MR_DATA_ACK,
+        },
+        _ => BusEvent::Status(status),
+    }
+}
+
+/// Converts an unexpected TWSR status into an error.
+/// # Arguments
+/// * `status` - a u8, the masked value of TWSR.
+/// # Returns
+/// * `a Error` - The reason of the failure.
+fn status_error(status: u8) -> Error {
+    match status {
+        MT_SLA_NACK | MR_SLA_NACK => Error::AddressNack,
+        MT_DATA_NACK => Error::DataNack,
+        ARB_LOST => Error::ArbitrationLost,
+        BUS_ERROR => Error::BusError,
+        _ => Error::BusBusy,
+    }
+}
 
 /// Sets DDRC to write direction.
 pub fn write_sda() {
@@ -157,257 +282,428 @@ pub fn read_sda() {
 }
 
 impl Twi {
-    /// Returns a pointer to TWBR.
+    /// Creates a pointer to TWI structure objects.
+    /// Unsafe as every call aliases the same registers, `take()` gives them only once.
     /// # Returns
     /// * `a reference to Twi struct object` - Which would be used to control the implementation.
-    pub fn new() -> &'static mut Self {
-        unsafe { &mut *(0xB8 as *mut Self) }
+    pub unsafe fn new() -> &'static mut Self {
+        &mut *(0xB8 as *mut Self)
     }
 
-    /// Waits for the process to be complete.
-    /// Times out if TWINT is not set in 100 seconds.
+    /// Gives the TWI registers only the first time it is called,
+    /// so that the bus has a single owner.
     /// # Returns
-    /// * `a boolean` - Which is true if the TWI is ready, false otherwise.
-    pub fn wait_to_complete(&mut self, operation: u8) -> bool {
+    /// * `a Option` - The reference to the Twi struct object, or `None` if it was already taken.
+    pub fn take() -> Option<&'static mut Self> {
+        interrupts::free(|| unsafe {
+            if TAKEN {
+                None
+            } else {
+                TAKEN = true;
+                Some(&mut *(0xB8 as *mut Self))
+            }
+        })
+    }
+
+    /// Waits for the current operation of the TWI to complete.
+    /// Times out if TWINT is not set after `I2C_TIMEOUT` polls.
+    /// # Arguments
+    /// * `operation` - a u8, the status expected in TWSR after the operation.
+    /// # Returns
+    /// * `a Result` - Which is `Ok` if the expected status was reached.
+    pub fn wait_to_complete(&mut self, operation: u8) -> Result<()> {
         let mut i: u32 = 0;
-        while !self.twcr.read().get_bit(TWINT) || i <= I2C_TIMEOUT {
-            // waiting for TWINT to be set
+        // Waiting for TWINT flag set.
+        while !self.twcr.read().get_bit(TWINT) {
+            if i >= I2C_TIMEOUT {
+                #[cfg(feature = "defmt")]
+                defmt::warn!("I2C timeout, expected status {=u8:#x}", operation);
+                trace(BusEvent::Timeout);
+                return Err(Error::Timeout);
+            }
             unsafe {
-                llvm_asm!("nop");
+                asm!("nop");
             }
             i += 1;
         }
 
-        if self.twsr.read() & TWSR_STATUS_MASK != operation || i >= I2C_TIMEOUT {
-            //if status id ok return else panic
-            return false; // for timeout... ignoring other error case...
+        let status = self.twsr.read() & TWSR_STATUS_MASK;
+        trace(bus_event(status, self.twdr.read()));
+        if status == operation {
+            Ok(())
         } else {
-            return true; // if everything is fine.
+            #[cfg(feature = "defmt")]
+            defmt::debug!("I2C status {=u8:#x}, expected {=u8:#x}", status, operation);
+            Err(status_error(status))
         }
     }
 
-    /// Iniates the TWI bus.
-    pub fn init(&mut self) {
+    /// Initiates the TWI bus at `TWI_FREQUENCY`.
+    /// # Returns
+    /// * `a Result` - Which is an error if the clock is too slow for I2C.
+    pub fn init(&mut self) -> Result<()> {
+        let (prescale, twps0, twps1) = prescaler()?;
+        let twbr = match bit_rate(prescale as u32) {
+            Some(twbr) => twbr,
+            None => return Err(Error::InvalidArgument),
+        };
         self.twsr.update(|sr| {
-            sr.set_bit(TWPS0, prescaler().1);
-            sr.set_bit(TWPS1, prescaler().2);
+            sr.set_bit(TWPS0, twps0);
+            sr.set_bit(TWPS1, twps1);
         });
-        self.twcr.update(|cr| {
-            cr.set_bit(TWEN, true);
-        })
+        self.twbr.write(twbr);
+        self.twcr.write(1 << TWEN);
+        Ok(())
     }
 
-    /// Sends a Start Signal
+    /// Sends a Start Signal for TWI.
     /// # Returns
-    /// * `a boolean` - Which is true if process is successful, false otherwise.
-    pub fn start(&mut self) -> bool {
+    /// * `a Result` - Which is `Ok` if the bus was taken.
+    pub fn start(&mut self) -> Result<()> {
         write_sda();
-        self.twcr.write(0xA4); // TWINT TWSTA and TWA set to 1
-
-        return self.wait_to_complete(START);
+        self.twcr.write((1 << TWINT) | (1 << TWSTA) | (1 << TWEN));
+        self.wait_to_complete(START)
     }
 
-    /// Sends a Repeat Start Signal
+    /// Sends a Repeated Start Signal for TWI.
     /// # Returns
-    /// * `a boolean` - Which is true if process is successful, false otherwise.
-    pub fn rep_start(&mut self) -> bool {
-        self.twcr.write(0xA4); // TWINT TWSTA and TWA set to 1
-
-        return self.wait_to_complete(REP_START);
+    /// * `a Result` - Which is `Ok` if the repeated start was sent.
+    pub fn rep_start(&mut self) -> Result<()> {
+        self.twcr.write((1 << TWINT) | (1 << TWSTA) | (1 << TWEN));
+        self.wait_to_complete(REP_START)
     }
 
-    /// Stops the TWI bus.
+    /// Sends a Stop Signal and releases the TWI bus.
     pub fn stop(&mut self) {
-        self.twcr.write(0xB0);
+        self.twcr.write((1 << TWINT) | (1 << TWSTO) | (1 << TWEN));
+        let mut i: u32 = 0;
+        // TWSTO is cleared by hardware once the stop is on the bus.
+        while self.twcr.read().get_bit(TWSTO) && i < I2C_TIMEOUT {
+            i += 1;
+        }
+        trace(BusEvent::Stop);
     }
 
-    /// Sets address of Slave.
-    /// Used in Master Slave Mode.
+    /// Sends the address of a slave in write mode.
     /// # Arguments
-    /// * `addr` - It is passed into the function and  is a seven bit integer used for location of implementation.
+    /// * `address` - a u8, the seven bit address of the slave.
     /// # Returns
-    /// * `a boolean` - Which is true if the checking process is sucessful otherwise false.
-    pub fn set_address(&mut self, addr: u8) -> bool {
-        self.twdr.write(addr << 1 & !0x01); // loading SLA_W to TWDR
-        self.twcr.update(|cr| {
-            cr.set_bit(TWINT, true);
-            cr.set_bit(TWEN, true);
-        });
-
-        return self.wait_to_complete(MT_SLA_ACK);
+    /// * `a Result` - Which is an `AddressNack` error if no slave answered.
+    pub fn address_write(&mut self, address: u8) -> Result<()> {
+        self.twdr.write(address << 1);
+        self.twcr.write((1 << TWINT) | (1 << TWEN));
+        self.wait_to_complete(MT_SLA_ACK)
     }
 
-    /// Checks if slave is acknowledged.
+    /// Sends the address of a slave in read mode.
     /// # Arguments
-    /// * `address` - It is passed into the function and  is a seven bit integer used for location of implementation.
+    /// * `address` - a u8, the seven bit address of the slave.
     /// # Returns
-    /// * `a boolean` - Which is true if the checking process is sucessful otherwise false.
-    pub fn address_read(&mut self, address: u8) -> bool {
+    /// * `a Result` - Which is an `AddressNack` error if no slave answered.
+    pub fn address_read(&mut self, address: u8) -> Result<()> {
         self.twdr.write(address << 1 | 0x01);
-        self.twcr.update(|cr| {
-            cr.set_bit(TWINT, true);
-            cr.set_bit(TWEN, true);
-        });
-
-        return self.wait_to_complete(MR_SLA_ACK);
+        self.twcr.write((1 << TWINT) | (1 << TWEN));
+        self.wait_to_complete(MR_SLA_ACK)
     }
 
     /// Writes one byte of data to the Slave.
     /// Need to set address first.
     /// # Arguments
-    /// * `data` - a u8, the integer which is to be written.
+    /// * `data` - a u8, the byte which is to be written.
     /// # Returns
-    /// * `a boolean` - Whcih is true if process is successful otherwise false.
-    pub fn write(&mut self, data: u8) -> bool {
-        delay_ms(1);
-        // write_sda(); // doubt if neended
+    /// * `a Result` - Which is a `DataNack` error if the slave refused the byte.
+    pub fn write(&mut self, data: u8) -> Result<()> {
         self.twdr.write(data);
-        self.twcr.write(0x84); // TWCR = (1<<TWINT)|(1<<TWEN);
-
-        return self.wait_to_complete(MT_DATA_ACK);
+        self.twcr.write((1 << TWINT) | (1 << TWEN));
+        self.wait_to_complete(MT_DATA_ACK)
     }
 
     /// Writes consecutive bytes of data to the Slave.
     /// Need to set address first.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which is filled with the data read.
+    /// * `data` - a slice of u8, the bytes to be written.
     /// # Returns
-    /// * `a usize integer` - Which gives the critical length of the data bus above which no data could be written.
-    pub fn write_burst(&mut self, data: &FixedSliceVec<u8>) -> usize {
-        let mut x: usize = 0;
-        while x < data.len() {
-            delay_ms(1);
-            if !self.write(data[x]) {
-                break;
-            }
-            x += 1;
+    /// * `a Result` - Which is an error as soon as one byte fails.
+    pub fn write_burst(&mut self, data: &[u8]) -> Result<()> {
+        for byte in data {
+            self.write(*byte)?;
         }
-        return x + 1;
+        Ok(())
     }
 
-    /// Appends the value in TWCR to the given vector.
-    /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which will be filled with the data read.
+    /// Reads one byte from the Slave and acknowledges it,
+    /// so that the slave sends another one.
     /// # Returns
-    /// * `a boolean` - Which is true if process is completed otherwise false.
-    pub fn read_ack(&mut self, data: &mut FixedSliceVec<u8>) -> bool {
-        self.twcr.write(0xC4); //TWCR = (1 << TWINT) | (1 << TWEA) | (1 << TWEN)
-        data.push(self.twdr.read());
+    /// * `a Result<u8>` - The byte read.
+    pub fn read_ack(&mut self) -> Result<u8> {
+        self.twcr.write((1 << TWINT) | (1 << TWEA) | (1 << TWEN));
+        self.wait_to_complete(MR_DATA_ACK)?;
+        Ok(self.twdr.read())
+    }
 
-        return self.wait_to_complete(MR_DATA_ACK);
+    /// Reads one byte from the Slave without acknowledging it,
+    /// which ends the transfer from the slave.
+    /// # Returns
+    /// * `a Result<u8>` - The byte read.
+    pub fn read_nack(&mut self) -> Result<u8> {
+        self.twcr.write((1 << TWINT) | (1 << TWEN));
+        self.wait_to_complete(MR_DATA_NACK)?;
+        Ok(self.twdr.read())
     }
 
-    /// Appends the value in TWCR to the given vector.
+    /// Reads consecutive bytes from the Slave.
+    /// Every byte is acknowledged except the last one.
     /// Need to set address first.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which is filled with the data read.
-    /// * `length` - a usize integer, which is the theoretically set value of length of the sliced vector `data`.
+    /// * `data` - a mutable slice of u8, which is filled with the data read.
     /// # Returns
-    /// * `a usize integer` - Which gives the critical length of the data bus above which no data exists.
-    pub fn read_ack_burst(&mut self, data: &mut FixedSliceVec<u8>, length: usize) -> usize {
-        let mut x: usize = 0;
-        // let mut vec:FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
-        while x < length {
-            if !self.read_ack(data) {
-                break;
-            }
-            x += 1;
+    /// * `a Result` - Which is an error as soon as one byte fails.
+    pub fn read_burst(&mut self, data: &mut [u8]) -> Result<()> {
+        let len = data.len();
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = if i + 1 < len {
+                self.read_ack()?
+            } else {
+                self.read_nack()?
+            };
         }
-        return x + 1;
+        Ok(())
     }
 
-    /// Reads the nack value data in TWCR to the given vector.
+    /// Writes consecutive Data bytes to slave.
+    /// The bus is always released at the end, even on failure.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which will be filled with the data read.
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `data` - a slice of u8, the bytes to be written.
     /// # Returns
-    /// * `a boolean` - Which is true if process is completed otherwise false.
-    pub fn read_nack(&mut self, data: &mut FixedSliceVec<u8>) -> bool {
-        self.twcr.write(0x84); //TWCR = (1 << TWINT) | (1 << TWEN)
+    /// * `a Result` - Which is an error if any of the steps, i.e start, setting address or writing fails.
+    pub fn write_to_slave(&mut self, address: u8, data: &[u8]) -> Result<()> {
+        let result = self.transfer_write(address, data);
+        self.stop();
+        result
+    }
 
-        data.push(self.twdr.read());
-        return self.wait_to_complete(MR_DATA_NACK);
+    /// Reads consecutive Data bytes from slave.
+    /// The bus is always released at the end, even on failure.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `data` - a mutable slice of u8, filled with `data.len()` bytes from the slave.
+    /// # Returns
+    /// * `a Result` - Which is an error if any of the steps, i.e start, reading address or reading data fails.
+    pub fn read_from_slave(&mut self, address: u8, data: &mut [u8]) -> Result<()> {
+        let result = self.transfer_read(address, data);
+        self.stop();
+        result
     }
 
-    /// Reads the nack value data in TWCR to the given vector.
+    /// Writes some bytes to a slave and then reads its answer after a repeated start.
+    /// This is the usual way of reading the registers of a sensor.
     /// # Arguments
-    /// * `data` - a sliced vector consisting of u8, which is filled with the data read.
-    /// * `length` - a usize integer, which is the theoretically set value of length of the sliced vector `data`.
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `bytes` - a slice of u8, the bytes to be written, for example a register number.
+    /// * `buffer` - a mutable slice of u8, filled with the bytes read.
     /// # Returns
-    /// * `a usize integer` - Which gives the critical length of the data bus above which no data exists.    
-    pub fn read_nack_burst(&mut self, data: &mut FixedSliceVec<u8>, length: usize) -> usize {
-        let mut x: usize = 0;
-        // let mut vec:FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
-
-        while x < length {
-            if !self.read_nack(data) {
-                break;
-            }
-            x += 1;
-        }
-        return x + 1;
+    /// * `a Result` - Which is an error if any of the steps fails.
+    pub fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<()> {
+        let result = self.transfer_write(address, bytes).and_then(|_| {
+            self.rep_start()?;
+            self.address_read(address)?;
+            self.read_burst(buffer)
+        });
+        self.stop();
+        result
     }
 
-    /// Writes consecutive Data bytes to slave
+    /// Runs a sequence of reads and writes with one slave, as described by
+    /// `embedded_hal::i2c::Operation`. A repeated start is sent whenever the
+    /// direction changes and consecutive reads are acknowledged as one read.
+    /// The bus is always released at the end, even on failure.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `operations` - a mutable slice of `Operation`, the reads and writes to be done.
     /// # Returns
-    /// * `a boolean` - Which is true if process is completed and aborts if any of the steps, i.e start, setting address or writing fails.
-    pub fn write_to_slave(&mut self, address: u8, data: &FixedSliceVec<u8>) -> bool {
-        delay_ms(1);
-        write_sda();
+    /// * `a Result` - Which is an error as soon as one step fails.
+    pub fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        let result = self.transfer_operations(address, operations);
+        self.stop();
+        result
+    }
 
-        if !self.start() {
-            return false;
-        }
-        if !self.set_address(address) {
-            self.stop();
-            return false;
+    fn transfer_write(&mut self, address: u8, data: &[u8]) -> Result<()> {
+        self.start()?;
+        self.address_write(address)?;
+        self.write_burst(data)
+    }
+
+    fn transfer_read(&mut self, address: u8, data: &mut [u8]) -> Result<()> {
+        read_sda();
+        self.start()?;
+        self.address_read(address)?;
+        self.read_burst(data)
+    }
+
+    fn transfer_operations(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        // Direction of the previous operation, `Some(true)` for a read.
+        let mut previous: Option<bool> = None;
+        let count = operations.len();
+        for i in 0..count {
+            let read_follows = match operations.get(i + 1) {
+                Some(Operation::Read(_)) => true,
+                _ => false,
+            };
+            match &mut operations[i] {
+                Operation::Write(bytes) => {
+                    if previous != Some(false) {
+                        self.start_or_repeat(previous.is_some())?;
+                        self.address_write(address)?;
+                    }
+                    self.write_burst(bytes)?;
+                    previous = Some(false);
+                }
+                Operation::Read(buffer) => {
+                    if previous != Some(true) {
+                        self.start_or_repeat(previous.is_some())?;
+                        self.address_read(address)?;
+                    }
+                    // Only the last byte of the whole read is not acknowledged.
+                    let len = buffer.len();
+                    for (j, byte) in buffer.iter_mut().enumerate() {
+                        *byte = if read_follows || j + 1 < len {
+                            self.read_ack()?
+                        } else {
+                            self.read_nack()?
+                        };
+                    }
+                    previous = Some(true);
+                }
+            }
         }
-        if self.write_burst(data) != data.len() {
-            self.stop();
-            return false;
+        Ok(())
+    }
+
+    fn start_or_repeat(&mut self, repeat: bool) -> Result<()> {
+        if repeat {
+            self.rep_start()
+        } else {
+            self.start()
         }
+    }
+}
 
-        self.stop();
+// Implementation of the embedded-hal I2C trait, so that platform agnostic
+// drivers can use the TWI. The bus must be initiated with `init()` first.
+impl ErrorType for Twi {
+    type Error = Error;
+}
+
+impl I2c for Twi {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        Twi::transaction(self, address, operations)
+    }
+}
 
-        return true;
+// Implementation of the embedded-hal-async I2C trait. Every byte is started
+// as in the blocking functions and the end of it is awaited, so that the
+// other tasks of the executor run during the transfer.
+#[cfg(feature = "async")]
+impl Twi {
+    /// Writes TWCR to start an operation and waits for its end.
+    /// # Arguments
+    /// * `twcr` - a u8, the value of TWCR starting the operation.
+    /// * `operation` - a u8, the status expected in TWSR after the operation.
+    /// # Returns
+    /// * `a Result` - Which is an error if the status is not the expected one.
+    async fn step_async(&mut self, twcr: u8, operation: u8) -> Result<()> {
+        self.twcr.write(twcr);
+        let register = core::ptr::addr_of!(self.twcr) as *const u8;
+        unsafe { WaitForBits::new(register, 1 << TWINT, I2C_ASYNC_TIMEOUT) }.await?;
+
+        let status = self.twsr.read() & TWSR_STATUS_MASK;
+        trace(bus_event(status, self.twdr.read()));
+        if status == operation {
+            Ok(())
+        } else {
+            Err(status_error(status))
+        }
     }
 
-    /// Reads consecutive Data bytes from slave
-    /// Requires number of bytes ro be read
+    /// Same as `transaction`, but awaits the end of every byte.
     /// # Arguments
-    /// * `address` - a u8, consisting the target address of the read implementation.
-    /// * `length` - a usize integer, showing the number of bytes to read.
-    /// * `data` - a sliced vector consisting of u8, where the data will be stored after reading.
+    /// * `address` - a u8, the seven bit address of the slave.
+    /// * `operations` - a mutable slice of `Operation`, the reads and writes to be done.
     /// # Returns
-    /// * `a boolean` - Which is true if process is completed otherwise false and aborts the process if any of the steps, i.e
-    /// start, reading address, reading ACK or reading NACK fails.
-    pub fn read_from_slave(
+    /// * `a Result` - Which is an error as soon as one step fails.
+    pub async fn transaction_async(
         &mut self,
         address: u8,
-        length: usize,
-        data: &mut FixedSliceVec<u8>,
-    ) -> bool {
-        delay_ms(1);
-        read_sda();
+        operations: &mut [Operation<'_>],
+    ) -> Result<()> {
+        let result = self.transfer_operations_async(address, operations).await;
+        self.stop();
+        result
+    }
 
-        // let mut vec:FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
-        if !self.start() {
-            return false;
-        }
-        if !self.address_read(address) {
-            self.stop();
-            return false;
-        }
-        if length > 1 && self.read_ack_burst(data, length - 1) != length - 1 {
-            self.stop();
-            return false;
-        }
-        if length > 0 && self.read_nack(data) {
-            self.stop();
-            return false;
+    async fn transfer_operations_async(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<()> {
+        let mut previous: Option<bool> = None;
+        let count = operations.len();
+        for i in 0..count {
+            let read_follows = match operations.get(i + 1) {
+                Some(Operation::Read(_)) => true,
+                _ => false,
+            };
+            let read = match operations[i] {
+                Operation::Read(_) => true,
+                Operation::Write(_) => false,
+            };
+            if previous != Some(read) {
+                let start = if previous.is_some() { REP_START } else { START };
+                self.step_async((1 << TWINT) | (1 << TWSTA) | (1 << TWEN), start)
+                    .await?;
+                if read {
+                    self.twdr.write(address << 1 | 0x01);
+                    self.step_async((1 << TWINT) | (1 << TWEN), MR_SLA_ACK)
+                        .await?;
+                } else {
+                    self.twdr.write(address << 1);
+                    self.step_async((1 << TWINT) | (1 << TWEN), MT_SLA_ACK)
+                        .await?;
+                }
+            }
+            match &mut operations[i] {
+                Operation::Write(bytes) => {
+                    for byte in bytes.iter() {
+                        self.twdr.write(*byte);
+                        self.step_async((1 << TWINT) | (1 << TWEN), MT_DATA_ACK)
+                            .await?;
+                    }
+                }
+                Operation::Read(buffer) => {
+                    let len = buffer.len();
+                    for (j, byte) in buffer.iter_mut().enumerate() {
+                        if read_follows || j + 1 < len {
+                            self.step_async((1 << TWINT) | (1 << TWEA) | (1 << TWEN), MR_DATA_ACK)
+                                .await?;
+                        } else {
+                            self.step_async((1 << TWINT) | (1 << TWEN), MR_DATA_NACK)
+                                .await?;
+                        }
+                        *byte = self.twdr.read();
+                    }
+                }
+            }
+            previous = Some(read);
         }
+        Ok(())
+    }
+}
 
-        self.stop();
-
-        return true;
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c for Twi {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        self.transaction_async(address, operations).await
     }
 }
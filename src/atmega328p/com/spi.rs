@@ -0,0 +1,326 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Master mode of the Serial Peripheral Interface for the ATMEGA328P.
+//! The SPI shifts one byte out on MOSI while one byte is shifted in on MISO,
+//! at a clock derived from the CPU clock. Chip select lines are not handled
+//! here, see `SpiDevice` for a wrapper which drives them.
+//! Section 19 of ATmega328P datasheet.
+
+use bit_field::BitField;
+use volatile::Volatile;
+
+use crate::atmega328p::hal::interrupts;
+#[cfg(feature = "async")]
+use crate::executor::WaitForBits;
+use crate::{Error, Result};
+use embedded_hal::spi::{ErrorType, SpiBus};
+
+/// Contains registers for the SPI.
+///
+/// * **SPCR**: *SPI Control Register*. Enables the SPI and the master mode,
+/// and selects the data order, the clock polarity and phase and the clock rate.
+///
+/// * **SPSR**: *SPI Status Register*. SPIF is set when a transfer is complete,
+/// and SPI2X doubles the clock rate selected in SPCR.
+///
+/// * **SPDR**: *SPI Data Register*. Writing to it starts a transfer,
+/// reading it gives the byte received during the last transfer.
+#[repr(C, packed)]
+pub struct Spi {
+    spcr: Volatile<u8>,
+    spsr: Volatile<u8>,
+    spdr: Volatile<u8>,
+}
+
+/// The clock polarity and phase used by the slave, see its datasheet.
+/// * `Mode0` - Clock idle low, data sampled on the rising edge.
+/// * `Mode1` - Clock idle low, data sampled on the falling edge.
+/// * `Mode2` - Clock idle high, data sampled on the falling edge.
+/// * `Mode3` - Clock idle high, data sampled on the rising edge.
+#[derive(Clone, Copy)]
+pub enum SpiMode {
+    Mode0,
+    Mode1,
+    Mode2,
+    Mode3,
+}
+
+/// The division of the CPU clock which gives the SPI clock.
+#[derive(Clone, Copy)]
+pub enum SpiClock {
+    Div2,
+    Div4,
+    Div8,
+    Div16,
+    Div32,
+    Div64,
+    Div128,
+}
+
+// SPCR register's bits definitions
+const SPE: u8 = 6;
+const DORD: u8 = 5;
+const MSTR: u8 = 4;
+const CPOL: u8 = 3;
+const CPHA: u8 = 2;
+
+// SPSR register's bits definitions
+const SPIF: u8 = 7;
+const SPI2X: u8 = 0;
+
+// Data direction register of port B, which holds the SPI pins.
+const DDRB: *mut u8 = 0x24 as *mut u8;
+
+// Bits of the SPI pins in port B.
+const SS: u8 = 2;
+const SCK: u8 = 5;
+const MOSI: u8 = 3;
+const MISO: u8 = 4;
+
+// Power reduction register, PRSPI is the bit 2.
+const PRR: *mut u8 = 0x64 as *mut u8;
+const PRSPI: u8 = 2;
+
+// Number of polls of SPIF before giving up.
+const SPI_TIMEOUT: u32 = 10000;
+
+// Milliseconds to wait for SPIF in the async functions.
+#[cfg(feature = "async")]
+const SPI_ASYNC_TIMEOUT: u32 = 10;
+
+// Set once the registers have been handed out by `Spi::take()`.
+static mut TAKEN: bool = false;
+
+impl Spi {
+    /// Creates a pointer to the SPI registers.
+    /// Unsafe as every call aliases the same registers, `take()` gives them only once.
+    /// # Returns
+    /// * `a reference to Spi struct object` - Which would be used to control the implementation.
+    pub unsafe fn new() -> &'static mut Self {
+        &mut *(0x4C as *mut Self)
+    }
+
+    /// Gives the SPI registers only the first time it is called,
+    /// so that the bus has a single owner.
+    /// # Returns
+    /// * `a Option` - The reference to the Spi struct object, or `None` if it was already taken.
+    pub fn take() -> Option<&'static mut Self> {
+        interrupts::free(|| unsafe {
+            if TAKEN {
+                None
+            } else {
+                TAKEN = true;
+                Some(&mut *(0x4C as *mut Self))
+            }
+        })
+    }
+
+    /// Powers the SPI and enables it in master mode.
+    /// The SS pin is made an output, as an input pulled low would turn
+    /// the SPI back into a slave. It can be used as a chip select.
+    /// # Arguments
+    /// * `mode` - a `SpiMode` object, the clock polarity and phase.
+    /// * `clock` - a `SpiClock` object, the division of the CPU clock.
+    /// * `lsb_first` - a boolean, true to send the least significant bit first.
+    pub fn begin(&mut self, mode: SpiMode, clock: SpiClock, lsb_first: bool) {
+        unsafe {
+            let prr = core::ptr::read_volatile(PRR);
+            core::ptr::write_volatile(PRR, prr & !(1 << PRSPI));
+
+            let mut ddrb = core::ptr::read_volatile(DDRB);
+            ddrb |= (1 << SS) | (1 << SCK) | (1 << MOSI);
+            ddrb &= !(1 << MISO);
+            core::ptr::write_volatile(DDRB, ddrb);
+        }
+
+        let (cpol, cpha) = match mode {
+            SpiMode::Mode0 => (false, false),
+            SpiMode::Mode1 => (false, true),
+            SpiMode::Mode2 => (true, false),
+            SpiMode::Mode3 => (true, true),
+        };
+        // SPR1 and SPR0 are the two low bits of SPCR.
+        let (spr, double) = match clock {
+            SpiClock::Div2 => (0, true),
+            SpiClock::Div4 => (0, false),
+            SpiClock::Div8 => (1, true),
+            SpiClock::Div16 => (1, false),
+            SpiClock::Div32 => (2, true),
+            SpiClock::Div64 => (2, false),
+            SpiClock::Div128 => (3, false),
+        };
+
+        let mut spcr: u8 = spr;
+        spcr.set_bit(SPE, true);
+        spcr.set_bit(DORD, lsb_first);
+        spcr.set_bit(MSTR, true);
+        spcr.set_bit(CPOL, cpol);
+        spcr.set_bit(CPHA, cpha);
+        self.spcr.write(spcr);
+        self.spsr.write(if double { 1 << SPI2X } else { 0 });
+    }
+
+    /// Disables the SPI, the pins go back to normal I/O.
+    pub fn end(&mut self) {
+        self.spcr.update(|spcr| {
+            spcr.set_bit(SPE, false);
+        });
+    }
+
+    /// Sends one byte and receives the byte shifted in at the same time.
+    /// # Arguments
+    /// * `data` - a u8, the byte to be sent.
+    /// # Returns
+    /// * `a Result<u8>` - The byte received, or `Timeout` if the SPI is not enabled.
+    pub fn transfer_byte(&mut self, data: u8) -> Result<u8> {
+        self.spdr.write(data);
+        let mut i: u32 = 0;
+        while !self.spsr.read().get_bit(SPIF) {
+            if i >= SPI_TIMEOUT {
+                return Err(Error::Timeout);
+            }
+            i += 1;
+        }
+        Ok(self.spdr.read())
+    }
+
+    /// Sends bytes and stores the bytes received at the same time.
+    /// The longer of the two slices gives the length of the transfer,
+    /// zeros are sent after `write` and the bytes after `read` are dropped.
+    /// # Arguments
+    /// * `read` - a mutable slice of u8, filled with the bytes received.
+    /// * `write` - a slice of u8, the bytes to be sent.
+    /// # Returns
+    /// * `a Result` - Which is an error as soon as one byte fails.
+    pub fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<()> {
+        let len = if read.len() > write.len() {
+            read.len()
+        } else {
+            write.len()
+        };
+        for i in 0..len {
+            let byte = self.transfer_byte(*write.get(i).unwrap_or(&0))?;
+            if let Some(slot) = read.get_mut(i) {
+                *slot = byte;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the bytes of a buffer and replaces them with the bytes received.
+    /// # Arguments
+    /// * `data` - a mutable slice of u8, the bytes to be sent and then received.
+    /// # Returns
+    /// * `a Result` - Which is an error as soon as one byte fails.
+    pub fn transfer_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        for byte in data.iter_mut() {
+            *byte = self.transfer_byte(*byte)?;
+        }
+        Ok(())
+    }
+}
+
+// Implementation of the embedded-hal SPI bus trait, so that platform agnostic
+// drivers can use the SPI. The SPI must be enabled with `begin()` first.
+impl ErrorType for Spi {
+    type Error = Error;
+}
+
+impl SpiBus for Spi {
+    fn read(&mut self, words: &mut [u8]) -> Result<()> {
+        self.transfer(words, &[])
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<()> {
+        self.transfer(&mut [], words)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<()> {
+        Spi::transfer(self, read, write)
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<()> {
+        Spi::transfer_in_place(self, words)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        // Every transfer waits for its last byte.
+        Ok(())
+    }
+}
+
+// Implementation of the embedded-hal-async SPI bus trait, the end of every
+// byte is awaited so that the other tasks of the executor run meanwhile.
+#[cfg(feature = "async")]
+impl Spi {
+    /// Same as `transfer_byte`, but awaits the end of the transfer.
+    /// # Arguments
+    /// * `data` - a u8, the byte to be sent.
+    /// # Returns
+    /// * `a Result<u8>` - The byte received, or `Timeout` if the SPI is not enabled.
+    pub async fn transfer_byte_async(&mut self, data: u8) -> Result<u8> {
+        self.spdr.write(data);
+        let register = core::ptr::addr_of!(self.spsr) as *const u8;
+        unsafe { WaitForBits::new(register, 1 << SPIF, SPI_ASYNC_TIMEOUT) }.await?;
+        Ok(self.spdr.read())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::spi::SpiBus for Spi {
+    async fn read(&mut self, words: &mut [u8]) -> Result<()> {
+        for word in words.iter_mut() {
+            *word = self.transfer_byte_async(0).await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<()> {
+        for word in words {
+            self.transfer_byte_async(*word).await?;
+        }
+        Ok(())
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<()> {
+        let len = if read.len() > write.len() {
+            read.len()
+        } else {
+            write.len()
+        };
+        for i in 0..len {
+            let byte = self
+                .transfer_byte_async(*write.get(i).unwrap_or(&0))
+                .await?;
+            if let Some(slot) = read.get_mut(i) {
+                *slot = byte;
+            }
+        }
+        Ok(())
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<()> {
+        for word in words.iter_mut() {
+            *word = self.transfer_byte_async(*word).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
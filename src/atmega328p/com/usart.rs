@@ -27,6 +27,7 @@ use crate::atmega328p::com::usart_initialize::Usart;
 use crate::atmega328p::com::usart_initialize::{
     UsartDataSize, UsartModes, UsartNum, UsartParity, UsartPolarity, UsartStop,
 };
+use crate::Result;
 
 // Standard datatypes to be used
 use core::{f64, u32};
@@ -58,26 +59,27 @@ impl Serial {
 impl Usart {
     /// This function can be use to initialize with default settings.
     /// Like Mode:Normal asynchronuous,stopbit:one,data bit:8,parity type:no
-    pub unsafe fn begin(&mut self) {
+    pub unsafe fn begin(&mut self) -> Result<()> {
         self.transmit_enable();
         self.recieve_enable();
-        self.initialize(MODE, BAUD, STOP, SIZE, PARITY);
+        self.initialize(MODE, BAUD, STOP, SIZE, PARITY)
     }
 
     /// This function can be use to initialize with baud rate and remaining settings will be set to default
     /// Like Mode:Normal asynchronuous,stopbit:one,data bit:8,parity type:no
     /// # Arguments
     /// * `baud1` - a i64, the baud rate of USART the user wants to set.
-    pub unsafe fn begin_set_baud(&mut self, baud1: i64) {
+    pub unsafe fn begin_set_baud(&mut self, baud1: i64) -> Result<()> {
         self.transmit_enable();
         self.recieve_enable();
-        self.initialize(MODE, baud1, STOP, SIZE, PARITY);
+        self.initialize(MODE, baud1, STOP, SIZE, PARITY)
     }
 
     /// This function can be used to stop the functioning of USART.
-    pub unsafe fn end(&mut self) {
-        self.transmit_disable();
+    pub unsafe fn end(&mut self) -> Result<()> {
+        let result = self.transmit_disable();
         self.recieve_disable();
+        result
     }
 }
 
@@ -87,12 +89,12 @@ impl Usart {
 /// Then the string given by the user is transmitted through the USART.
 /// # Arguments
 /// * `data` - a string object, which is to be transmitted using USART.
-pub fn println_string(data: &'static str) {
+pub fn println_string(data: &'static str) -> Result<()> {
     let u: &mut Usart = unsafe { Usart::new(NUM) };
     u.transmit_enable();
-    u.initialize(MODE, BAUD, STOP, SIZE, PARITY);
-    u.write_string(data);
-    u.transmit_disable();
+    u.initialize(MODE, BAUD, STOP, SIZE, PARITY)?;
+    u.write_string(data)?;
+    u.transmit_disable()
 }
 
 /// Main println() function for using USART according to default used values.
@@ -102,12 +104,12 @@ pub fn println_string(data: &'static str) {
 /// This will be used to transmit integer data.
 /// # Arguments
 /// * `data` - a u32, which is to be transmitted using USART.
-pub fn println_integer(data: u32) {
+pub fn println_integer(data: u32) -> Result<()> {
     let u: &mut Usart = unsafe { Usart::new(NUM) };
     u.transmit_enable();
-    u.initialize(MODE, BAUD, STOP, SIZE, PARITY);
-    u.write_integer(data);
-    u.transmit_disable();
+    u.initialize(MODE, BAUD, STOP, SIZE, PARITY)?;
+    u.write_integer(data)?;
+    u.transmit_disable()
 }
 
 /// Main println() function for using USART according to default used values.
@@ -118,12 +120,12 @@ pub fn println_integer(data: u32) {
 /// # Arguments
 /// * `data` - a f32, which is to be transmitted using USART.
 /// * `precision` - a u32, the number of decimal precision required in the transmission.
-pub fn println_float(data: f64, precision: u32) {
+pub fn println_float(data: f64, precision: u32) -> Result<()> {
     let u: &mut Usart = unsafe { Usart::new(NUM) };
     u.transmit_enable();
-    u.initialize(MODE, BAUD, STOP, SIZE, PARITY);
-    u.write_float(data, precision);
-    u.transmit_disable();
+    u.initialize(MODE, BAUD, STOP, SIZE, PARITY)?;
+    u.write_float(data, precision)?;
+    u.transmit_disable()
 }
 
 /// println() function for using USART according to default used values and user defined value of baud rate.
@@ -133,12 +135,12 @@ pub fn println_float(data: f64, precision: u32) {
 /// # Arguments
 /// * `data` - a string object, which is to be transmitted using USART.
 /// * `baud1` - a i64, the baud rate of USART the user wants to set.
-pub fn println_set_baud(data: &'static str, baud1: i64) {
+pub fn println_set_baud(data: &'static str, baud1: i64) -> Result<()> {
     let u: &mut Usart = unsafe { Usart::new(NUM) };
     u.transmit_enable();
-    u.initialize(MODE, baud1, STOP, SIZE, PARITY);
-    u.write_string(data);
-    u.transmit_disable();
+    u.initialize(MODE, baud1, STOP, SIZE, PARITY)?;
+    u.write_string(data)?;
+    u.transmit_disable()
 }
 
 /// Main println() function for using USART according to default used values and user defined value of frame.
@@ -155,12 +157,12 @@ pub fn println_set_frame(
     size1: UsartDataSize,
     parity1: UsartParity,
     stop1: UsartStop,
-) {
+) -> Result<()> {
     let u: &mut Usart = unsafe { Usart::new(NUM) };
     u.transmit_enable();
-    u.initialize(MODE, BAUD, stop1, size1, parity1);
-    u.write_string(data);
-    u.transmit_disable();
+    u.initialize(MODE, BAUD, stop1, size1, parity1)?;
+    u.write_string(data)?;
+    u.transmit_disable()
 }
 
 /// Main println() function for using USART according to user defined mode parameters.
@@ -183,10 +185,10 @@ pub fn println_detail(
     size1: UsartDataSize,
     parity1: UsartParity,
     stop1: UsartStop,
-) {
+) -> Result<()> {
     let u: &mut Usart = unsafe { Usart::new(num1) };
     u.transmit_enable();
-    u.initialize(mode1, baud1, stop1, size1, parity1);
-    u.write_string(data);
-    u.transmit_disable();
+    u.initialize(mode1, baud1, stop1, size1, parity1)?;
+    u.write_string(data)?;
+    u.transmit_disable()
 }
@@ -0,0 +1,98 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Interrupt driven reception for the USART.
+//! Once enabled, every recieved byte is moved from the data register into a
+//! buffer by the recieve complete interrupt, so no data is lost while the
+//! main loop is busy or the MCU is sleeping.
+//! The module needs the `usart-buffered` feature, because its interrupt
+//! service routine takes the recieve complete vector of the USART.
+//! See the section 19.7 of ATMEGA328P datasheet.
+
+use crate::atmega328p::com::usart_initialize::Usart;
+#[cfg(target_arch = "avr")]
+use crate::atmega328p::com::usart_initialize::UsartNum;
+use crate::collections::Queue;
+#[cfg(target_arch = "avr")]
+use crate::system::events::Event;
+use bit_field::BitField;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Size of the reception buffer, it holds one byte less than this.
+pub const RX_BUFFER_SIZE: usize = 64;
+
+/// Bytes recieved by the interrupt and not read yet.
+static RX_BUFFER: Queue<u8, RX_BUFFER_SIZE> = Queue::new();
+
+/// If set, recieved bytes are published on the event bus instead of being buffered.
+static PUBLISH_EVENTS: AtomicBool = AtomicBool::new(false);
+
+impl Usart {
+    /// Enables the reciever along with the recieve complete interrupt,
+    /// so that recieved bytes are buffered. Global interrupts must be enabled.
+    pub fn recieve_buffered_enable(&mut self) {
+        self.recieve_enable();
+        self.ucsrb.update(|ucsrb| {
+            ucsrb.set_bit(7, true);
+        });
+    }
+
+    /// Enables the reciever along with the recieve complete interrupt, publishing
+    /// every recieved byte as an `Event::ByteReceived` on `system::events::EVENTS`.
+    pub fn recieve_events_enable(&mut self) {
+        PUBLISH_EVENTS.store(true, Ordering::Release);
+        self.recieve_buffered_enable();
+    }
+
+    /// Disables the recieve complete interrupt, bytes already buffered can still be read.
+    pub fn recieve_buffered_disable(&mut self) {
+        self.ucsrb.update(|ucsrb| {
+            ucsrb.set_bit(7, false);
+        });
+    }
+
+    /// Returns the number of bytes waiting in the reception buffer.
+    pub fn buffered_available(&self) -> usize {
+        RX_BUFFER.len()
+    }
+
+    /// Reads one byte from the reception buffer.
+    /// # Returns
+    /// * `a Option<u8>` - `None` if no byte is waiting.
+    pub fn read_buffered(&mut self) -> Option<u8> {
+        // Only the main program reads from the buffer.
+        unsafe { RX_BUFFER.dequeue() }
+    }
+
+    /// Returns a future which completes with the next recieved byte.
+    #[cfg(feature = "async")]
+    pub fn read_async(&mut self) -> crate::executor::ReadByte<'static, RX_BUFFER_SIZE> {
+        crate::executor::ReadByte::new(&RX_BUFFER)
+    }
+}
+
+/// USART recieve complete interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_18"]
+pub unsafe extern "avr-interrupt" fn usart0_recieve_complete() {
+    let byte = Usart::new(UsartNum::Usart0).udr.read();
+    // The byte is dropped if the buffer is full.
+    if PUBLISH_EVENTS.load(Ordering::Acquire) {
+        crate::system::events::publish(Event::ByteReceived { usart: 0, byte });
+    } else {
+        let _ = RX_BUFFER.enqueue(byte);
+    }
+}
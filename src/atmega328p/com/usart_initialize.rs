@@ -23,6 +23,7 @@
 
 // Standard crates to be used
 use crate::delay::delay_ms;
+use crate::{Error, Result};
 use bit_field::BitField;
 use core::ptr::write_volatile;
 use core::{f64, u32, u8};
@@ -431,7 +432,7 @@ impl Usart {
         stop: UsartStop,
         size: UsartDataSize,
         parity: UsartParity,
-    ) {
+    ) -> Result<()> {
         // Check that recieve and transmit buffers are completely cleared
         // and no transmission or recieve of data is already in process.
         let mut i: i32 = 10;
@@ -440,7 +441,7 @@ impl Usart {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
@@ -463,5 +464,6 @@ impl Usart {
         self.set_frame(stop, size, parity);
 
         self.enable(); //  Enable Global interrupts.
+        Ok(())
     }
 }
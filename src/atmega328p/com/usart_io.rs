@@ -0,0 +1,112 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Implementations of the `embedded-io` and the `embedded-hal-nb` serial traits
+//! for the USART, so that protocol crates written against them can use it.
+//! The USART must be initialized first, for example with `begin()`, and
+//! works with frames of 5 to 8 bits.
+
+use crate::atmega328p::com::usart_initialize::Usart;
+use crate::{Error, Result};
+use embedded_hal_nb::serial;
+
+impl embedded_io::ErrorType for Usart {
+    type Error = Error;
+}
+
+impl embedded_io::Read for Usart {
+    /// Waits for one byte and then takes the bytes already received, up to `buf.len()`.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.recieve_data()? as u8;
+        let mut count = 1;
+        while count < buf.len() && self.available() {
+            buf[count] = self.recieve_data()? as u8;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl embedded_io::ReadReady for Usart {
+    fn read_ready(&mut self) -> Result<bool> {
+        Ok(self.available())
+    }
+}
+
+impl embedded_io::Write for Usart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        for byte in buf {
+            self.transmit_data(*byte)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Waits until the last byte has been moved out of the data register.
+    fn flush(&mut self) -> Result<()> {
+        let mut i: u32 = 0;
+        while !self.avai_write() {
+            if i >= FLUSH_TIMEOUT {
+                return Err(Error::Timeout);
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+impl embedded_io::WriteReady for Usart {
+    fn write_ready(&mut self) -> Result<bool> {
+        Ok(self.avai_write())
+    }
+}
+
+impl serial::ErrorType for Usart {
+    type Error = Error;
+}
+
+impl serial::Read<u8> for Usart {
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        if self.available() {
+            Ok(self.recieve_data()? as u8)
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl serial::Write<u8> for Usart {
+    fn write(&mut self, word: u8) -> nb::Result<(), Error> {
+        if self.avai_write() {
+            Ok(self.transmit_data(word)?)
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Error> {
+        if self.avai_write() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+// Number of polls of the data register empty flag before giving up.
+const FLUSH_TIMEOUT: u32 = 100000;
@@ -21,6 +21,7 @@
 // Source code crates required
 use crate::atmega328p::com::usart_initialize::{Usart, UsartDataSize};
 use crate::delay::delay_ms;
+use crate::{Error, Result};
 
 // Crates which would be used in the implementation.
 // We will be using standard volatile and bit_field crates now for a better read and write.
@@ -43,7 +44,7 @@ impl Usart {
     /// # Arguments
     /// * `data` - a u32, the data to be transmitted.
     /// * `len` -  a `UsartDataSize` object, which contains the length of data frame of USART.
-    pub fn transmitting_data(&mut self, data: u32, len: UsartDataSize) {
+    pub fn transmitting_data(&mut self, data: u32, len: UsartDataSize) -> Result<()> {
         // Checks if the Transmit buffer is empty to receive data.
         // If not the program waits till the time comes.
         let mut i: i32 = 10;
@@ -52,7 +53,7 @@ impl Usart {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
@@ -79,6 +80,7 @@ impl Usart {
                 udr.set_bits(0..8, data.get_bits(0..8) as u8);
             }
         }
+        Ok(())
     }
 
     /// Checks that transmission buffer if ready for transmission.
@@ -95,7 +97,7 @@ impl Usart {
 
     /// This functions waits for the transmission to complete by checking TXCn bit in the ucsrna register
     /// TXCn is set 1 when the transmit is completed and it can start transmitting new data.
-    pub fn flush_transmit(&mut self) {
+    pub fn flush_transmit(&mut self) -> Result<()> {
         let mut ucsra = self.ucsra.read();
         let mut i: i32 = 10;
         while ucsra.get_bit(6) == false {
@@ -104,14 +106,15 @@ impl Usart {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
+        Ok(())
     }
 
     /// This function is used to disable the Transmitter and once disabled the TXDn pin is no longer
     /// used as the transmitter output pin and functions as a normal I/O pin.
-    pub fn transmit_disable(&mut self) {
+    pub fn transmit_disable(&mut self) -> Result<()> {
         let ucsra = self.ucsra.read();
         let mut uscra6 = ucsra.get_bit(6);
         let mut uscra5 = ucsra.get_bit(5);
@@ -126,19 +129,20 @@ impl Usart {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!()
+                return Err(Error::Timeout);
             }
         }
 
         self.ucsrb.update(|srb| {
             srb.set_bit(3, false);
         });
+        Ok(())
     }
 
     /// This function sends a character byte of 5,6,7 or 8 bits
     /// # Arguments
     /// * `data` - a u8, consisting of the current data frame to send from USART.
-    pub fn transmit_data(&mut self, data: u8) {
+    pub fn transmit_data(&mut self, data: u8) -> Result<()> {
         let mut ucsra = self.ucsra.read();
         let mut udre = ucsra.get_bit(5);
 
@@ -151,18 +155,19 @@ impl Usart {
                 delay_ms(1000);
                 i = i - 1;
             } else {
-                unreachable!();
+                return Err(Error::Timeout);
             }
         }
 
         self.udr.write(data);
+        Ok(())
     }
 
     /// This function send data type of string byte by byte.
     /// This function send data type of string byte by byte.
     /// # Arguments
     /// * `data` - a static string object, which is to be transmitted using USART.
-    pub fn write_string(&mut self, data: &'static str) {
+    pub fn write_string(&mut self, data: &'static str) -> Result<()> {
         let mut vec: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
 
         for c in data.chars() {
@@ -170,14 +175,15 @@ impl Usart {
         }
 
         for i in 0..(vec.len()) {
-            self.transmit_data(vec[i]);
+            self.transmit_data(vec[i])?;
         }
+        Ok(())
     }
 
     /// This function send data type of int(u32) byte by byte.
     /// # Arguments
     /// * `data` - a u32, which is to be transmitted using USART.
-    pub fn write_integer(&mut self, data: u32) {
+    pub fn write_integer(&mut self, data: u32) -> Result<()> {
         let mut vec: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
         let mut a = data;
         while a != 0 {
@@ -198,15 +204,16 @@ impl Usart {
             }
         }
         for i in 0..(vec.len()) {
-            self.transmit_data(vec[vec.len() - 1 - i]);
+            self.transmit_data(vec[vec.len() - 1 - i])?;
         }
+        Ok(())
     }
 
     /// This function send data type of float(f32) byte by byte.
     /// # Arguments
     /// * `data` - a f64, which is to be transmitted using USART.
     /// * `precision` - a u32, the number of decimal precision required in the transmission.
-    pub fn write_float(&mut self, data: f64, precision: u32) {
+    pub fn write_float(&mut self, data: f64, precision: u32) -> Result<()> {
         let mut vec: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
         let a: f64 = data;
         let mut f: f64 = a % 1.0;
@@ -254,11 +261,12 @@ impl Usart {
         }
 
         for ia in 0..(vec.len() - n - 1) {
-            self.transmit_data(vec[vec.len() - 1 - ia]);
+            self.transmit_data(vec[vec.len() - 1 - ia])?;
         }
 
         for ia in 0..n - 1 {
             vec.push(vec[ia]);
         }
+        Ok(())
     }
 }
@@ -28,6 +28,27 @@ use volatile::Volatile;
 // Source codes to be used here.
 use crate::atmega328p::hal::pin::{AnalogPin, DigitalPin};
 use crate::atmega328p::hal::power::Power;
+use crate::system::chip_temperature::TemperatureCalibration;
+use crate::{Error, Result};
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+/// Largest number of bits gained by `AnalogPin::read_oversampled`, for 16 bit results.
+pub const MAX_EXTRA_BITS: u8 = 6;
+
+/// Adds up 4^n conversions and decimates the sum, keeping n more bits than
+/// a single conversion, following the application note AVR121.
+/// # Arguments
+/// * `extra_bits` - a u8, the number n of bits gained, up to `MAX_EXTRA_BITS`.
+/// * `convert` - a function making one conversion.
+/// # Returns
+/// * `a u16` - the result of `10 + extra_bits` bits.
+fn decimate(extra_bits: u8, mut convert: impl FnMut() -> u16) -> u16 {
+    let mut sum = 0u32;
+    for _ in 0..1u32 << (2 * extra_bits) {
+        sum += convert() as u32;
+    }
+    (sum >> extra_bits) as u16
+}
 
 /// Selection of reference type for the implementation of Analog Pins.
 #[derive(Clone, Copy)]
@@ -270,6 +291,21 @@ impl AnalogPin {
             a
         }
     }
+
+    /// Reads the analog pin with more resolution by adding up 4^n readings
+    /// and decimating the sum, following the application note AVR121.
+    /// It works as long as the input carries at least 1 LSB of noise.
+    /// # Arguments
+    /// * `extra_bits` - a u8, the number n of bits gained, up to `MAX_EXTRA_BITS`.
+    /// # Returns
+    /// * `a Result<u16>` - Value read from the analog pin, of `10 + extra_bits` bits,
+    ///   `InvalidArgument` if too many bits are asked for.
+    pub fn read_oversampled(&mut self, extra_bits: u8) -> Result<u16> {
+        if extra_bits > MAX_EXTRA_BITS {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(decimate(extra_bits, || self.read() as u16))
+    }
 }
 
 impl DigitalPin {
@@ -363,6 +399,40 @@ impl DigitalPin {
     }
 }
 
+/// Digital pins which are connected to an output compare unit of a timer.
+const PWM_PINS: [usize; 6] = [3, 5, 6, 9, 10, 11];
+
+impl DigitalPin {
+    /// Checks if the pin can output a PWM wave with `write`.
+    /// # Returns
+    /// * `a boolean` - Which is true for a PWM pin.
+    pub fn is_pwm(&self) -> bool {
+        let pinno = self.pinno;
+        PWM_PINS.contains(&pinno)
+    }
+}
+
+// Implementation of the embedded-hal PWM trait, so that platform agnostic
+// drivers can set the duty cycle of the PWM pins.
+impl ErrorType for DigitalPin {
+    type Error = Error;
+}
+
+impl SetDutyCycle for DigitalPin {
+    fn max_duty_cycle(&self) -> u16 {
+        0xFF
+    }
+
+    /// Writes the duty cycle with `write`, pins without PWM give `InvalidArgument`.
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<()> {
+        if !self.is_pwm() || duty > 0xFF {
+            return Err(Error::InvalidArgument);
+        }
+        self.write(duty as u8);
+        Ok(())
+    }
+}
+
 impl Analog {
     /// New pointer object created for Analog Structure.
     /// # Returns
@@ -489,3 +559,122 @@ pub fn analog_reference(reftype: RefType) {
         }
     }
 }
+
+/// Nominal voltage of the internal bandgap reference in millivolts.
+/// It varies by up to 10% between chips, measure it on the AREF pin with
+/// `analog_reference(RefType::INTERNAL1V1)` to calibrate `vcc_millivolts`.
+pub const BANDGAP_MILLIVOLTS: u32 = 1100;
+
+/// Measures the supply voltage by converting the internal bandgap reference
+/// against AVCC, which needs no external part. This monitors a battery which
+/// powers the chip directly, without a regulator in between.
+/// The ADC is left disabled and the reference set to AVCC.
+/// # Arguments
+/// * `bandgap` - a u32, the voltage of the bandgap in millivolts, `BANDGAP_MILLIVOLTS` if not calibrated.
+/// # Returns
+/// * `a u16` - The supply voltage in millivolts.
+pub fn vcc_millivolts(bandgap: u32) -> u16 {
+    unsafe {
+        let analog = Analog::new();
+        analog.power_adc_disable();
+        // AVCC reference (REFS1:0 = 01) and the bandgap as input.
+        analog.admux.write(0x4E);
+        // Enabled with a prescaler of 128, 125 kHz at 16 MHz.
+        analog.adcsra.write(0x87);
+        // The bandgap takes about 1 ms to settle after being selected.
+        crate::delay::delay_ms(1);
+        let mut value: u32 = 0;
+        // The first conversion after a change of reference is discarded.
+        for _ in 0..2 {
+            analog.adc_con_start();
+            while analog.adcsra.read().get_bit(6) {}
+            value = analog.adcl.read() as u32;
+            value |= (analog.adch.read() as u32) << 8;
+        }
+        analog.adc_disable();
+        if value == 0 {
+            return 0;
+        }
+        (bandgap * 1024 / value) as u16
+    }
+}
+
+/// Converts the temperature sensor of the chip, on channel 8 against the
+/// internal 1.1 V reference. ADMUX and ADCSRA are restored afterwards, so
+/// the input, the reference and the enable of the ADC are kept for the other
+/// readings, although a return to another reference needs about 1 ms to settle.
+/// # Returns
+/// * `a u16` - The 10 bit reading of the sensor.
+pub fn chip_temperature_raw() -> u16 {
+    unsafe {
+        let analog = Analog::new();
+        analog.power_adc_disable();
+        let (admux, adcsra) = (analog.admux.read(), analog.adcsra.read());
+        // Internal 1.1 V reference (REFS1:0 = 11) and the sensor as input.
+        analog.admux.write(0xC8);
+        // Enabled with a prescaler of 128, 125 kHz at 16 MHz.
+        analog.adcsra.write(0x87);
+        // The reference takes about 1 ms to settle after being selected.
+        crate::delay::delay_ms(1);
+        let mut value = 0;
+        // The first conversion after a change of reference is discarded.
+        for _ in 0..2 {
+            analog.adc_con_start();
+            while analog.adcsra.read().get_bit(6) {}
+            value = analog.adcl.read() as u16;
+            value |= (analog.adch.read() as u16) << 8;
+        }
+        analog.admux.write(admux);
+        // No conversion is started again, ADSC is left cleared.
+        analog.adcsra.write(adcsra & !0x40);
+        value
+    }
+}
+
+/// Measures the temperature of the die with the internal sensor.
+/// # Arguments
+/// * `calibration` - a `TemperatureCalibration` object, `DATASHEET` or one loaded from the EEPROM.
+/// # Returns
+/// * `a f32` - The temperature of the die in degrees Celsius.
+pub fn read_chip_temperature_c(calibration: &TemperatureCalibration) -> f32 {
+    calibration.celsius(chip_temperature_raw())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decimation() {
+        assert_eq!(decimate(0, || 700), 700);
+        // 4 conversions for one more bit, a steady input doubles.
+        assert_eq!(decimate(1, || 700), 1400);
+        let mut count = 0;
+        assert_eq!(
+            decimate(MAX_EXTRA_BITS, || {
+                count += 1;
+                1023
+            }),
+            65472
+        );
+        assert_eq!(count, 4096);
+        // Half a step of noise between 512 and 513 gives 512.5, that is 1025 in 11 bits.
+        let mut high = false;
+        assert_eq!(
+            decimate(1, || {
+                high = !high;
+                512 + high as u16
+            }),
+            1025
+        );
+        // 2 bits gained from 16 conversions, a quarter of them one step higher.
+        let mut n = 0;
+        assert_eq!(
+            decimate(2, || {
+                n += 1;
+                100 + (n % 4 == 0) as u16
+            }),
+            401
+        );
+    }
+}
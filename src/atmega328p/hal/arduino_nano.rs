@@ -0,0 +1,64 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Pin numbers printed on the Arduino Nano translated to the ports of the
+//! ATmega328P. The digital pins and A0 to A5 are those of the Uno, the
+//! Nano adds A6 and A7, which are analog inputs only and have no port.
+
+use crate::atmega328p::hal::arduino_uno;
+pub use crate::atmega328p::hal::arduino_uno::{digital_pin, pin, DIGITAL_PINS, LED_BUILTIN};
+use crate::atmega328p::hal::port::PortName;
+
+/// Number of analog pins, A0 to A7.
+pub const ANALOG_PINS: u8 = 8;
+
+/// Translates an analog pin number.
+/// # Arguments
+/// * `number` - a u8, the number printed after the A on the board.
+/// # Returns
+/// * `a Option<(PortName, u8)>` - the port and the bit in the port, None for A6, A7 and a pin the board does not have.
+pub fn analog_pin(number: u8) -> Option<(PortName, u8)> {
+    arduino_uno::analog_pin(number)
+}
+
+/// Returns the ADC channel read by an analog pin.
+/// # Arguments
+/// * `number` - a u8, the number printed after the A on the board.
+/// # Returns
+/// * `a Option<u8>` - the channel, None for a pin the board does not have.
+pub fn analog_channel(number: u8) -> Option<u8> {
+    if number < ANALOG_PINS {
+        Some(number)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn analog_pins() {
+        assert_eq!(digital_pin(LED_BUILTIN), Some((PortName::B, 5)));
+        assert_eq!(analog_pin(0), Some((PortName::C, 0)));
+        // A6 and A7 are only inputs of the ADC.
+        assert_eq!(analog_pin(6), None);
+        assert_eq!(analog_channel(6), Some(6));
+        assert_eq!(analog_channel(7), Some(7));
+        assert_eq!(analog_channel(8), None);
+    }
+}
@@ -0,0 +1,122 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Pin numbers printed on the Arduino Uno translated to the ports of the
+//! ATmega328P, so that D13 gives PB5 without looking at the schematic.
+//! As in the Arduino IDE, the analog pins A0 to A5 can also be used as the
+//! digital pins 14 to 19. The Nano has the same pins, see `arduino_nano`.
+
+use crate::atmega328p::hal::port::{Pin, PortName};
+
+/// Number of digital pins, D0 to D13.
+pub const DIGITAL_PINS: u8 = 14;
+
+/// Number of analog pins, A0 to A5.
+pub const ANALOG_PINS: u8 = 6;
+
+/// Digital pin of the onboard LED.
+pub const LED_BUILTIN: u8 = 13;
+
+/// Port and bit of each digital pin.
+const DIGITAL: [(PortName, u8); DIGITAL_PINS as usize] = [
+    (PortName::D, 0),
+    (PortName::D, 1),
+    (PortName::D, 2),
+    (PortName::D, 3),
+    (PortName::D, 4),
+    (PortName::D, 5),
+    (PortName::D, 6),
+    (PortName::D, 7),
+    (PortName::B, 0),
+    (PortName::B, 1),
+    (PortName::B, 2),
+    (PortName::B, 3),
+    (PortName::B, 4),
+    (PortName::B, 5),
+];
+
+/// Translates a digital pin number.
+/// # Arguments
+/// * `number` - a u8, the number printed on the board, 14 to 19 for A0 to A5.
+/// # Returns
+/// * `a Option<(PortName, u8)>` - the port and the bit in the port, None for a pin the board does not have.
+pub fn digital_pin(number: u8) -> Option<(PortName, u8)> {
+    match number {
+        0..=13 => Some(DIGITAL[number as usize]),
+        14..=19 => analog_pin(number - DIGITAL_PINS),
+        _ => None,
+    }
+}
+
+/// Translates an analog pin number.
+/// # Arguments
+/// * `number` - a u8, the number printed after the A on the board.
+/// # Returns
+/// * `a Option<(PortName, u8)>` - the port and the bit in the port, None for a pin the board does not have.
+pub fn analog_pin(number: u8) -> Option<(PortName, u8)> {
+    if number < ANALOG_PINS {
+        Some((PortName::C, number))
+    } else {
+        None
+    }
+}
+
+/// Returns the ADC channel read by an analog pin.
+/// # Arguments
+/// * `number` - a u8, the number printed after the A on the board.
+/// # Returns
+/// * `a Option<u8>` - the channel, None for a pin the board does not have.
+pub fn analog_channel(number: u8) -> Option<u8> {
+    if number < ANALOG_PINS {
+        Some(number)
+    } else {
+        None
+    }
+}
+
+/// Returns the pin of a digital pin number.
+/// # Arguments
+/// * `number` - a u8, the number printed on the board, 14 to 19 for A0 to A5.
+/// # Returns
+/// * `a Option<Pin>` - the pin, None for a pin the board does not have.
+pub fn pin(number: u8) -> Option<Pin> {
+    let (port, bit) = digital_pin(number)?;
+    Pin::new(port, bit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn digital_pins() {
+        assert_eq!(digital_pin(0), Some((PortName::D, 0)));
+        assert_eq!(digital_pin(7), Some((PortName::D, 7)));
+        assert_eq!(digital_pin(8), Some((PortName::B, 0)));
+        assert_eq!(digital_pin(LED_BUILTIN), Some((PortName::B, 5)));
+    }
+
+    #[test]
+    fn analog_pins() {
+        assert_eq!(digital_pin(14), Some((PortName::C, 0)));
+        assert_eq!(digital_pin(19), Some((PortName::C, 5)));
+        assert_eq!(digital_pin(20), None);
+        assert_eq!(analog_pin(5), Some((PortName::C, 5)));
+        assert_eq!(analog_pin(6), None);
+        assert_eq!(analog_channel(5), Some(5));
+        assert_eq!(analog_channel(6), None);
+    }
+}
@@ -0,0 +1,244 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Hardware event counter on the external clock input of the 16 bit timer,
+//! T1 (digital pin 5). The timer counts the edges itself, which suits fast
+//! signals like flow meters or RPM pickups, and the counts are turned into
+//! frequencies over gated windows timed with `micros`. T0 is not offered as
+//! Timer0 runs `millis`. Timer1 gives no PWM on pins 9 and 10 while counting.
+//! The module needs the `counter` feature, because its interrupt service
+//! routine takes the Timer1 overflow vector.
+//! Section 16 of the manual.
+
+use crate::atmega328p::hal::interrupts;
+use crate::atmega328p::hal::millis::micros;
+use crate::atmega328p::hal::port::{Pin, PortName};
+use crate::atmega328p::hal::power::Power;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Registers of a 16 bit timer used as a counter.
+/// # Elements
+/// * `tccra` - the address of TCCRnA.
+/// * `tccrb` - the address of TCCRnB.
+/// * `tcnt` - the address of TCNTnL, TCNTnH follows it.
+/// * `timsk` - the address of TIMSKn.
+/// * `tifr` - the address of TIFRn.
+struct TimerRegisters {
+    tccra: *mut u8,
+    tccrb: *mut u8,
+    tcnt: *mut u8,
+    timsk: *mut u8,
+    tifr: *mut u8,
+}
+
+/// Number of timers which can count.
+const COUNTERS: usize = 1;
+
+/// Timers whose clock input can be counted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CounterInput {
+    /// Timer1, counting on T1 (PD5, digital pin 5).
+    T1 = 0,
+}
+
+impl CounterInput {
+    /// Returns the registers of the timer.
+    fn registers(&self) -> TimerRegisters {
+        TimerRegisters {
+            tccra: 0x80 as *mut u8,
+            tccrb: 0x81 as *mut u8,
+            tcnt: 0x84 as *mut u8,
+            timsk: 0x6F as *mut u8,
+            tifr: 0x36 as *mut u8,
+        }
+    }
+
+    /// Returns the port and pin number of the clock input.
+    fn pin(&self) -> (PortName, u8) {
+        (PortName::D, 5)
+    }
+
+    /// Clears the power reduction bit of the timer.
+    unsafe fn power_up(&self) {
+        let power = Power::new();
+        write_volatile(&mut power.prr, read_volatile(&power.prr) & !(1 << 3));
+    }
+}
+
+/// Number of times each counter wrapped around, counted by its overflow interrupt.
+static mut OVERFLOWS: [u16; COUNTERS] = [0; COUNTERS];
+
+/// Reads the 32 bit count of a timer, the overflows as high half.
+unsafe fn read_count(input: CounterInput) -> u32 {
+    interrupts::free(|| {
+        let registers = input.registers();
+        let mut overflows = OVERFLOWS[input as usize];
+        let low = read_volatile(registers.tcnt) as u16;
+        let high = read_volatile(registers.tcnt.add(1)) as u16;
+        let ticks = (high << 8) | low;
+        // An overflow which happened after interrupts were disabled has not been counted yet.
+        if read_volatile(registers.tifr) & 0x01 != 0 && ticks < 0x8000 {
+            overflows = overflows.wrapping_add(1);
+        }
+        ((overflows as u32) << 16) | ticks as u32
+    })
+}
+
+/// Counts the edges of a signal on the clock input of a timer.
+/// The timer counts in hardware, so signals up to about a third of the
+/// CPU clock can be counted with one interrupt every 65536 edges.
+/// # Elements
+/// * `input` - a `CounterInput` object, the timer used.
+/// * `window` - a u32, the length of the measurement windows in microseconds.
+/// * `window_start` - a u32, the value of `micros` when the window started.
+/// * `count_start` - a u32, the count when the window started.
+/// * `frequency` - a u32, the result of the last complete window in Hz.
+pub struct FrequencyCounter {
+    input: CounterInput,
+    window: u32,
+    window_start: u32,
+    count_start: u32,
+    frequency: u32,
+}
+
+impl FrequencyCounter {
+    /// Takes over a timer and starts counting, with windows of one second.
+    /// `millis_init` must have been called for the measurement windows.
+    /// # Arguments
+    /// * `input` - a `CounterInput` object, the timer whose clock input the signal is on.
+    /// * `falling` - a boolean, true to count falling edges instead of rising ones.
+    /// # Returns
+    /// * `a FrequencyCounter object` - Which will be used for further implementations.
+    pub fn new(input: CounterInput, falling: bool) -> FrequencyCounter {
+        let (port, pin) = input.pin();
+        if let Some(mut pin) = Pin::new(port, pin) {
+            pin.set_input();
+        }
+        interrupts::free(|| unsafe {
+            input.power_up();
+            let registers = input.registers();
+            OVERFLOWS[input as usize] = 0;
+            // Normal mode, clocked by the Tn pin (CSn2:0 = 110 falling, 111 rising).
+            write_volatile(registers.tccra, 0);
+            write_volatile(registers.tccrb, if falling { 0x06 } else { 0x07 });
+            write_volatile(registers.tcnt.add(1), 0);
+            write_volatile(registers.tcnt, 0);
+            write_volatile(registers.tifr, 0x01);
+            write_volatile(registers.timsk, 0x01);
+            interrupts::Interrupt::enable(&mut interrupts::Interrupt::new());
+        });
+        FrequencyCounter {
+            input,
+            window: 1_000_000,
+            window_start: micros(),
+            count_start: 0,
+            frequency: 0,
+        }
+    }
+
+    /// Stops the timer, which is free for other uses again.
+    pub fn stop(self) {
+        interrupts::free(|| unsafe {
+            let registers = self.input.registers();
+            write_volatile(registers.timsk, 0);
+            write_volatile(registers.tccrb, 0);
+        })
+    }
+
+    /// Returns the number of edges counted since the counter was created or reset.
+    /// The count wraps around after 2^32 edges.
+    pub fn count(&self) -> u32 {
+        unsafe { read_count(self.input) }
+    }
+
+    /// Sets the count back to 0 and starts a new window.
+    pub fn reset(&mut self) {
+        interrupts::free(|| unsafe {
+            let registers = self.input.registers();
+            OVERFLOWS[self.input as usize] = 0;
+            write_volatile(registers.tcnt.add(1), 0);
+            write_volatile(registers.tcnt, 0);
+            write_volatile(registers.tifr, 0x01);
+        });
+        self.window_start = micros();
+        self.count_start = 0;
+    }
+
+    /// Sets the length of the measurement windows and starts a new one.
+    /// A longer window gives a finer resolution, 1 Hz for one second.
+    /// # Arguments
+    /// * `window` - a u32, the length in milliseconds, at most 60000.
+    pub fn set_window(&mut self, window: u32) {
+        self.window = window.clamp(1, 60_000) * 1000;
+        self.restart();
+    }
+
+    /// Checks if the window has ended, and if so computes the frequency and starts the next one.
+    /// Call it more often than the window length.
+    /// # Returns
+    /// * `a Option<u32>` - The frequency in Hz measured in the window which just ended.
+    pub fn poll(&mut self) -> Option<u32> {
+        let (now, count) = interrupts::free(|| (micros(), self.count()));
+        let elapsed = now.wrapping_sub(self.window_start);
+        if elapsed < self.window {
+            return None;
+        }
+        let edges = count.wrapping_sub(self.count_start);
+        self.frequency = (edges as u64 * 1_000_000 / elapsed as u64) as u32;
+        self.window_start = now;
+        self.count_start = count;
+        Some(self.frequency)
+    }
+
+    /// Returns the frequency measured in the last complete window, 0 before the first one.
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    /// Counts for one window, waiting for its end.
+    /// # Returns
+    /// * `a u32` - The frequency in Hz.
+    pub fn measure(&mut self) -> u32 {
+        self.restart();
+        loop {
+            if let Some(frequency) = self.poll() {
+                return frequency;
+            }
+        }
+    }
+
+    /// Converts the last frequency to a rotation speed.
+    /// # Arguments
+    /// * `pulses_per_revolution` - a u16, the edges per turn of the shaft.
+    /// # Returns
+    /// * `a u32` - The speed in revolutions per minute.
+    pub fn rpm(&self, pulses_per_revolution: u16) -> u32 {
+        self.frequency * 60 / pulses_per_revolution.max(1) as u32
+    }
+
+    /// Starts a new window from the current count.
+    fn restart(&mut self) {
+        let (now, count) = interrupts::free(|| (micros(), self.count()));
+        self.window_start = now;
+        self.count_start = count;
+    }
+}
+
+/// Timer/Counter1 overflow interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_13"]
+pub unsafe extern "avr-interrupt" fn timer1_overflow() {
+    OVERFLOWS[CounterInput::T1 as usize] = OVERFLOWS[CounterInput::T1 as usize].wrapping_add(1);
+}
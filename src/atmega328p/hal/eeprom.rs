@@ -0,0 +1,134 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Internal EEPROM of the ATMEGA328P, 1 KB of non volatile memory.
+//! A byte is only written if its content changes, which saves time and the
+//! limited number of erase/write cycles of the memory.
+//! Section 8.4 of the manual.
+
+use crate::atmega328p::hal::interrupts;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Size of the EEPROM in bytes.
+pub const EEPROM_SIZE: u16 = 1024;
+
+/// Contains the registers controlling access to the EEPROM.
+#[repr(C, packed)]
+pub struct Eeprom {
+    eecr: u8,
+    eedr: u8,
+    eearl: u8,
+    eearh: u8,
+}
+
+impl Eeprom {
+    /// Creates a memory mapped structure to control the EEPROM.
+    /// # Returns
+    /// * `a reference to Eeprom object` - which will be used for further implementations.
+    pub fn new() -> &'static mut Eeprom {
+        unsafe { &mut *(0x3F as *mut Eeprom) }
+    }
+
+    /// Waits till the previous write operation is complete (EEPE is cleared).
+    fn wait(&mut self) {
+        while unsafe { read_volatile(&self.eecr) } & 0x02 != 0 {}
+    }
+
+    /// Loads the address registers.
+    fn set_address(&mut self, address: u16) {
+        unsafe {
+            write_volatile(&mut self.eearh, (address >> 8) as u8);
+            write_volatile(&mut self.eearl, address as u8);
+        }
+    }
+
+    /// Reads one byte from the EEPROM.
+    /// # Arguments
+    /// * `address` - a u16, the address of the byte, less than `EEPROM_SIZE`.
+    /// # Returns
+    /// * `a u8` - the byte read, 0xFF for an address outside the EEPROM.
+    pub fn read_byte(&mut self, address: u16) -> u8 {
+        if address >= EEPROM_SIZE {
+            return 0xFF;
+        }
+        self.wait();
+        self.set_address(address);
+        unsafe {
+            // Start the read by writing EERE.
+            write_volatile(&mut self.eecr, 0x01);
+            read_volatile(&self.eedr)
+        }
+    }
+
+    /// Writes one byte to the EEPROM if it differs from the stored value.
+    /// # Arguments
+    /// * `address` - a u16, the address of the byte, less than `EEPROM_SIZE`.
+    /// * `data` - a u8, the byte to be stored.
+    /// # Returns
+    /// * `a boolean` - false if the address is outside the EEPROM.
+    pub fn write_byte(&mut self, address: u16, data: u8) -> bool {
+        if address >= EEPROM_SIZE {
+            return false;
+        }
+        if self.read_byte(address) == data {
+            return true;
+        }
+        self.wait();
+        self.set_address(address);
+        unsafe { write_volatile(&mut self.eedr, data) };
+        // EEPE has to be written within four cycles of EEMPE,
+        // so no interrupt may run in between.
+        interrupts::free(|| unsafe {
+            // Atomic erase and write mode with the master write enable (EEMPE).
+            write_volatile(&mut self.eecr, 0x04);
+            // Start the write (EEPE).
+            write_volatile(&mut self.eecr, 0x06);
+        });
+        true
+    }
+
+    /// Reads consecutive bytes from the EEPROM.
+    /// # Arguments
+    /// * `address` - a u16, the address of the first byte.
+    /// * `data` - a mutable reference to `[u8]`, filled with the bytes read.
+    /// # Returns
+    /// * `a boolean` - false if the range does not fit in the EEPROM.
+    pub fn read(&mut self, address: u16, data: &mut [u8]) -> bool {
+        if address as usize + data.len() > EEPROM_SIZE as usize {
+            return false;
+        }
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = self.read_byte(address + i as u16);
+        }
+        true
+    }
+
+    /// Writes consecutive bytes to the EEPROM.
+    /// # Arguments
+    /// * `address` - a u16, the address of the first byte.
+    /// * `data` - a reference to `[u8]`, the bytes to be stored.
+    /// # Returns
+    /// * `a boolean` - false if the range does not fit in the EEPROM.
+    pub fn write(&mut self, address: u16, data: &[u8]) -> bool {
+        if address as usize + data.len() > EEPROM_SIZE as usize {
+            return false;
+        }
+        for (i, byte) in data.iter().enumerate() {
+            self.write_byte(address + i as u16, *byte);
+        }
+        true
+    }
+}
@@ -0,0 +1,131 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! 25 kHz PWM for the speed input of 4-wire PC fans on Timer/Counter2,
+//! digital pin 3 (OC2B). The fan specification asks for a frequency
+//! around 25 kHz, well above the 490 Hz of `analog`, which makes the fans
+//! hum. Timer2 runs in fast PWM mode with OCR2A as top and a prescaler of 8,
+//! so that 16 MHz / (8 * 80) = 25 kHz with 80 steps of duty cycle, and
+//! OC2A (pin 11) cannot be used for PWM meanwhile.
+//! The speed input is pulled up inside the fan and can be driven by the pin directly.
+//! The tach output is measured with `hal::counter` and `robotics::Tachometer`,
+//! see `system::fan`. Tones from `hal::tone` and `hal::audio` also use Timer2
+//! and cannot be played while the fan runs.
+//! Section 18 of the manual.
+
+use crate::atmega328p::hal::interrupts;
+use crate::atmega328p::hal::port::{Pin, PortName};
+use crate::atmega328p::hal::power::Power;
+use crate::{Error, Result};
+use core::ptr::{read_volatile, write_volatile};
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+/// Addresses of the Timer/Counter2 registers.
+const TCCR2A: *mut u8 = 0xB0 as *mut u8;
+const TCCR2B: *mut u8 = 0xB1 as *mut u8;
+const OCR2A: *mut u8 = 0xB3 as *mut u8;
+const OCR2B: *mut u8 = 0xB4 as *mut u8;
+
+/// Address of the output register of port D.
+const PORTD: *mut u8 = 0x2B as *mut u8;
+
+/// Bit of digital pin 3 in port D.
+const BIT: u8 = 3;
+
+/// Number of steps of duty cycle, the timer counts from 0 to `STEPS - 1`.
+pub const STEPS: u16 = 80;
+
+/// COM2B1, non inverted output on OC2B.
+const COMPARE_B: u8 = 0x20;
+
+/// The speed input of a fan on digital pin 3.
+/// # Elements
+/// * `duty` - a u16, the duty cycle set, from 0 to `STEPS`.
+pub struct FanPwm {
+    duty: u16,
+}
+
+impl FanPwm {
+    /// Starts the 25 kHz PWM on digital pin 3, at a duty cycle of 0.
+    /// # Returns
+    /// * `a FanPwm object` - Which will be used for further implementations.
+    pub fn new() -> FanPwm {
+        if let Some(mut pin) = Pin::new(PortName::D, BIT) {
+            pin.set_output();
+        }
+        interrupts::free(|| unsafe {
+            let power = Power::new();
+            write_volatile(&mut power.prr, read_volatile(&power.prr) & !(1 << 6));
+            write_volatile(PORTD, read_volatile(PORTD) & !(1 << BIT));
+            // Fast PWM with OCR2A as top (WGM2 = 111), clock divided by 8.
+            write_volatile(TCCR2B, 0);
+            write_volatile(TCCR2A, 0x03);
+            write_volatile(OCR2A, (STEPS - 1) as u8);
+            write_volatile(OCR2B, 0);
+            write_volatile(TCCR2B, 0x0A);
+        });
+        FanPwm { duty: 0 }
+    }
+
+    /// Stops the PWM and the timer, the pin is driven low.
+    pub fn stop(self) {
+        interrupts::free(|| unsafe {
+            write_volatile(TCCR2B, 0);
+            write_volatile(TCCR2A, 0);
+            write_volatile(PORTD, read_volatile(PORTD) & !(1 << BIT));
+        });
+    }
+
+    /// Returns the duty cycle set, from 0 to `STEPS`.
+    pub fn duty(&self) -> u16 {
+        self.duty
+    }
+}
+
+impl Default for FanPwm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ErrorType for FanPwm {
+    type Error = Error;
+}
+
+impl SetDutyCycle for FanPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        STEPS
+    }
+
+    /// Writes the compare register, duty cycles above `STEPS` give `InvalidArgument`.
+    /// The output is high for OCR2B + 1 steps in fast PWM mode, so a duty
+    /// cycle of 0 disconnects it and leaves the pin low instead.
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<()> {
+        if duty > STEPS {
+            return Err(Error::InvalidArgument);
+        }
+        interrupts::free(|| unsafe {
+            if duty == 0 {
+                write_volatile(TCCR2A, read_volatile(TCCR2A) & !COMPARE_B);
+            } else {
+                write_volatile(OCR2B, (duty - 1) as u8);
+                write_volatile(TCCR2A, read_volatile(TCCR2A) | COMPARE_B);
+            }
+        });
+        self.duty = duty;
+        Ok(())
+    }
+}
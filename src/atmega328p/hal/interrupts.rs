@@ -54,8 +54,44 @@ impl Interrupt {
     pub fn enable(&mut self) {
         unsafe {
             let mut ctrl_sreg = read_volatile(&self.sreg);
-            ctrl_sreg &= 0xFF;
+            ctrl_sreg |= 0x80;
             write_volatile(&mut self.sreg, ctrl_sreg);
         }
     }
 }
+
+/// Runs the given closure with global interrupts disabled and then restores
+/// the previous state of the global interrupt flag.
+/// Use it to access data shared with an interrupt service routine consistently.
+/// Other targets, like the host running the tests, have no interrupts to
+/// mask and only run the closure.
+/// # Arguments
+/// * `f` - a closure, the code to be run as a critical section.
+/// # Returns
+/// * `the value returned by the closure`.
+#[cfg(target_arch = "avr")]
+pub fn free<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    use core::sync::atomic::{compiler_fence, Ordering};
+
+    let interrupt = unsafe { Interrupt::new() };
+    let sreg = unsafe { read_volatile(&interrupt.sreg) };
+    interrupt.disable();
+    compiler_fence(Ordering::SeqCst);
+
+    let result = f();
+
+    compiler_fence(Ordering::SeqCst);
+    unsafe { write_volatile(&mut interrupt.sreg, sreg) };
+    result
+}
+
+#[cfg(not(target_arch = "avr"))]
+pub fn free<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    f()
+}
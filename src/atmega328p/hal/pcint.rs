@@ -0,0 +1,355 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Pin change interrupts, PCINT0 to PCINT23, in three banks of eight pins
+//! sharing one interrupt each. Any change of an enabled pin raises the
+//! interrupt of its bank, which finds the pins that changed and dispatches
+//! them to the handlers attached, per pin with an edge or per bank with the
+//! mask of the changed pins. This lets rotary encoders, buttons and software
+//! serial receivers react to their pins instead of polling them.
+//! The banks cover digital pins 8 to 13 (port B, bank 0), analog pins
+//! A0 to A5 used as digital pins 14 to 19 (port C, bank 1) and digital
+//! pins 0 to 7 (port D, bank 2).
+//! `hal::wiegand` and `hal::pulse` attach their pins here.
+//! The module needs the `pcint` feature, because its interrupt service
+//! routines take the pin change vectors.
+//! Section 12 of the manual.
+
+use crate::atmega328p::hal::interrupts;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Addresses of the pin change interrupt registers, PCMSK1 and PCMSK2 follow PCMSK0.
+const PCICR: *mut u8 = 0x68 as *mut u8;
+const PCIFR: *mut u8 = 0x3B as *mut u8;
+const PCMSK0: *mut u8 = 0x6B as *mut u8;
+
+/// Addresses of the PIN registers of the ports with pin change interrupts,
+/// the DDR and PORT registers follow them.
+const PINB: *mut u8 = 0x23 as *mut u8;
+const PINC: *mut u8 = 0x26 as *mut u8;
+const PIND: *mut u8 = 0x29 as *mut u8;
+
+/// Number of banks of pin change interrupts.
+pub const BANKS: usize = 3;
+
+/// Marks the bits of a bank whose pin is not on the headers.
+const NONE: u8 = 0xFF;
+
+/// Digital pins of the bits of each bank.
+const PINS: [[u8; 8]; BANKS] = [
+    [8, 9, 10, 11, 12, 13, NONE, NONE],
+    [14, 15, 16, 17, 18, 19, NONE, NONE],
+    [0, 1, 2, 3, 4, 5, 6, 7],
+];
+
+/// Edges of a pin reported to its handler.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Edge {
+    /// Changes from low to high.
+    Rising,
+    /// Changes from high to low.
+    Falling,
+    /// All changes.
+    Both,
+}
+
+impl Edge {
+    /// Returns true if a change to the given level is reported.
+    fn matches(&self, high: bool) -> bool {
+        match self {
+            Edge::Rising => high,
+            Edge::Falling => !high,
+            Edge::Both => true,
+        }
+    }
+}
+
+/// A handler attached to a pin, with the changes reported to it.
+type PinHandler = (Edge, fn(u8, bool));
+
+/// A handler attached to a bank, with the mask of the bits reported to it.
+type BankHandler = (fn(u8, u8), u8);
+
+/// State shared with the interrupt service routines.
+/// # Elements
+/// * `pins` - the edge and handler attached to every bit of every bank.
+/// * `banks` - the handler and the mask of the bits attached to every bank.
+/// * `levels` - an array of u8, the banks as read by their last interrupt, to find the changes.
+struct PinChangeState {
+    pins: [[Option<PinHandler>; 8]; BANKS],
+    banks: [Option<BankHandler>; BANKS],
+    levels: [u8; BANKS],
+}
+
+static mut PIN_CHANGE: PinChangeState = PinChangeState {
+    pins: [[None; 8]; BANKS],
+    banks: [None; BANKS],
+    levels: [0; BANKS],
+};
+
+/// Finds the bank and bit of a digital pin.
+/// # Arguments
+/// * `pin` - a u8, the digital pin.
+/// # Returns
+/// * `a Option<(usize, u8)>` - the bank and the bit of the pin in it, `None` for pins without pin change interrupt.
+pub fn locate(pin: u8) -> Option<(usize, u8)> {
+    if pin == NONE {
+        return None;
+    }
+    for (bank, pins) in PINS.iter().enumerate() {
+        if let Some(bit) = pins.iter().position(|&p| p == pin) {
+            return Some((bank, bit as u8));
+        }
+    }
+    None
+}
+
+/// Returns the PIN register and the bit in it of a bit of a bank.
+fn register(bank: usize, bit: u8) -> (*mut u8, u8) {
+    match bank {
+        0 => (PINB, bit),
+        1 => (PINC, bit),
+        _ => (PIND, bit),
+    }
+}
+
+/// Reads the levels of the pins of a bank.
+/// # Arguments
+/// * `bank` - a usize, the bank, from 0 to 2.
+/// # Returns
+/// * `a u8` - the levels, bit n for the bit n of the bank, 0 for a bank which does not exist.
+pub fn read(bank: usize) -> u8 {
+    unsafe {
+        match bank {
+            0 => read_volatile(PINB),
+            1 => read_volatile(PINC),
+            2 => read_volatile(PIND),
+            _ => 0,
+        }
+    }
+}
+
+/// Sets the pin change interrupt of a bank on while any of its bits is enabled.
+unsafe fn update_bank(bank: usize) {
+    if read_volatile(PCMSK0.add(bank)) != 0 {
+        write_volatile(PCICR, read_volatile(PCICR) | (1 << bank));
+    } else {
+        write_volatile(PCICR, read_volatile(PCICR) & !(1 << bank));
+    }
+}
+
+/// Enables the bits of a bank, after taking their current levels so that
+/// only the changes made from now on are reported.
+unsafe fn enable_bits(bank: usize, mask: u8) {
+    let levels = read(bank);
+    PIN_CHANGE.levels[bank] = (PIN_CHANGE.levels[bank] & !mask) | (levels & mask);
+    write_volatile(PCMSK0.add(bank), read_volatile(PCMSK0.add(bank)) | mask);
+    write_volatile(PCIFR, 1 << bank);
+    update_bank(bank);
+}
+
+/// Disables the bits of a bank.
+unsafe fn disable_bits(bank: usize, mask: u8) {
+    write_volatile(PCMSK0.add(bank), read_volatile(PCMSK0.add(bank)) & !mask);
+    update_bank(bank);
+}
+
+/// Attaches a handler to the changes of a pin, which is made an input, and enables its interrupt.
+/// A handler attached before to the pin is replaced.
+/// # Arguments
+/// * `pin` - a u8, the digital pin, see the module documentation.
+/// * `edge` - a `Edge` object, the changes reported.
+/// * `pull_up` - a boolean, true to enable the internal pull up.
+/// * `handler` - a function called from the interrupt with the pin and its new level, true for high.
+/// # Returns
+/// * `a boolean` - false if the pin has no pin change interrupt.
+pub fn attach(pin: u8, edge: Edge, pull_up: bool, handler: fn(u8, bool)) -> bool {
+    let (bank, bit) = match locate(pin) {
+        Some(location) => location,
+        None => return false,
+    };
+    let (pin_register, port_bit) = register(bank, bit);
+    interrupts::free(|| unsafe {
+        let (ddr, port) = (pin_register.add(1), pin_register.add(2));
+        write_volatile(ddr, read_volatile(ddr) & !(1 << port_bit));
+        if pull_up {
+            write_volatile(port, read_volatile(port) | (1 << port_bit));
+        } else {
+            write_volatile(port, read_volatile(port) & !(1 << port_bit));
+        }
+        PIN_CHANGE.pins[bank][bit as usize] = Some((edge, handler));
+        enable_bits(bank, 1 << bit);
+    });
+    true
+}
+
+/// Detaches the handler of a pin. Its interrupt stays enabled if it belongs to the mask of a bank handler.
+/// # Arguments
+/// * `pin` - a u8, the digital pin given to `attach`.
+pub fn detach(pin: u8) {
+    if let Some((bank, bit)) = locate(pin) {
+        interrupts::free(|| unsafe {
+            PIN_CHANGE.pins[bank][bit as usize] = None;
+            let owned = PIN_CHANGE.banks[bank].map_or(0, |(_, mask)| mask);
+            disable_bits(bank, (1 << bit) & !owned);
+        });
+    }
+}
+
+/// Enables again the interrupt of a pin, keeping its handler.
+/// # Arguments
+/// * `pin` - a u8, the digital pin.
+/// # Returns
+/// * `a boolean` - false if the pin has no pin change interrupt.
+pub fn enable(pin: u8) -> bool {
+    match locate(pin) {
+        Some((bank, bit)) => {
+            interrupts::free(|| unsafe { enable_bits(bank, 1 << bit) });
+            true
+        }
+        None => false,
+    }
+}
+
+/// Masks the interrupt of a pin, keeping its handler, for example while a button is debounced.
+/// # Arguments
+/// * `pin` - a u8, the digital pin.
+pub fn disable(pin: u8) {
+    if let Some((bank, bit)) = locate(pin) {
+        interrupts::free(|| unsafe { disable_bits(bank, 1 << bit) });
+    }
+}
+
+/// Returns true if the interrupt of a pin is enabled.
+/// # Arguments
+/// * `pin` - a u8, the digital pin.
+pub fn is_enabled(pin: u8) -> bool {
+    match locate(pin) {
+        Some((bank, bit)) => unsafe { read_volatile(PCMSK0.add(bank)) & (1 << bit) != 0 },
+        None => false,
+    }
+}
+
+/// Attaches a handler to the changes of several pins of a bank at once,
+/// for example the two channels of a rotary encoder or the bits of a parallel bus.
+/// The pins keep their direction and pull ups. A handler attached before to the bank is replaced.
+/// # Arguments
+/// * `bank` - a usize, the bank, from 0 to 2.
+/// * `mask` - a u8, the bits of the bank whose interrupt is enabled.
+/// * `handler` - a function called from the interrupt with the bits which changed and the levels of the bank.
+/// # Returns
+/// * `a boolean` - false if the bank does not exist.
+pub fn attach_bank(bank: usize, mask: u8, handler: fn(u8, u8)) -> bool {
+    if bank >= BANKS {
+        return false;
+    }
+    interrupts::free(|| unsafe {
+        PIN_CHANGE.banks[bank] = Some((handler, mask));
+        enable_bits(bank, mask);
+    });
+    true
+}
+
+/// Detaches the handler of a bank. The interrupts of the pins with their own handler stay enabled.
+/// # Arguments
+/// * `bank` - a usize, the bank given to `attach_bank`.
+pub fn detach_bank(bank: usize) {
+    if bank >= BANKS {
+        return;
+    }
+    interrupts::free(|| unsafe {
+        if let Some((_, mask)) = PIN_CHANGE.banks[bank].take() {
+            let mut owned = 0;
+            for (bit, handler) in PIN_CHANGE.pins[bank].iter().enumerate() {
+                if handler.is_some() {
+                    owned |= 1 << bit;
+                }
+            }
+            disable_bits(bank, mask & !owned);
+        }
+    });
+}
+
+/// Finds the enabled pins of a bank which changed and calls their handlers.
+unsafe fn dispatch(bank: usize) {
+    let levels = read(bank);
+    let changed = (PIN_CHANGE.levels[bank] ^ levels) & read_volatile(PCMSK0.add(bank));
+    PIN_CHANGE.levels[bank] = levels;
+    if changed == 0 {
+        return;
+    }
+    if let Some((handler, mask)) = PIN_CHANGE.banks[bank] {
+        if changed & mask != 0 {
+            handler(changed & mask, levels);
+        }
+    }
+    for (bit, &pin) in PINS[bank].iter().enumerate() {
+        if changed & (1 << bit) == 0 {
+            continue;
+        }
+        if let Some((edge, handler)) = PIN_CHANGE.pins[bank][bit] {
+            let high = levels & (1 << bit) != 0;
+            if edge.matches(high) {
+                handler(pin, high);
+            }
+        }
+    }
+}
+
+/// Pin change interrupt 0 service routine, bank 0.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_3"]
+pub unsafe extern "avr-interrupt" fn pin_change_0() {
+    dispatch(0);
+}
+
+/// Pin change interrupt 1 service routine, bank 1.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_4"]
+pub unsafe extern "avr-interrupt" fn pin_change_1() {
+    dispatch(1);
+}
+
+/// Pin change interrupt 2 service routine, bank 2.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_5"]
+pub unsafe extern "avr-interrupt" fn pin_change_2() {
+    dispatch(2);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pin_locations() {
+        assert_eq!(locate(8), Some((0, 0)));
+        assert_eq!(locate(13), Some((0, 5)));
+        assert_eq!(locate(14), Some((1, 0)));
+        assert_eq!(locate(19), Some((1, 5)));
+        assert_eq!(locate(0), Some((2, 0)));
+        assert_eq!(locate(7), Some((2, 7)));
+        assert_eq!(locate(20), None);
+        assert_eq!(locate(NONE), None);
+    }
+
+    #[test]
+    fn pin_registers() {
+        assert_eq!(register(0, 5), (PINB, 5));
+        assert_eq!(register(1, 2), (PINC, 2));
+        assert_eq!(register(2, 7), (PIND, 7));
+    }
+}
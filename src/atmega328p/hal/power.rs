@@ -17,6 +17,9 @@
 //! Generic implementation of power control through clock gating in ATMEGA2560P.
 //! Section 9.11 of ATmega328p Datasheet
 
+use crate::atmega328p::hal::interrupts;
+use core::ptr::{read_volatile, write_volatile};
+
 /// Power reduction for ATmega328p chip
 /// Each of the Peripherals below refers to a bit in the PRR
 /// Setting 7th bit shuts down the TWI(2-wire serial interface) by stopping the clock to the module.
@@ -131,3 +134,66 @@ impl Power {
         }
     }
 }
+
+/// Division of the system clock by the prescaler of CLKPR. Dividing the clock
+/// saves power while awake, but `millis`, the delays and the baud rates of
+/// the USARTs are computed for the undivided clock.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum ClockPrescaler {
+    Div1 = 0,
+    Div2 = 1,
+    Div4 = 2,
+    Div8 = 3,
+    Div16 = 4,
+    Div32 = 5,
+    Div64 = 6,
+    Div128 = 7,
+    Div256 = 8,
+}
+
+impl ClockPrescaler {
+    /// Returns the factor by which the clock is divided.
+    pub fn divider(&self) -> u16 {
+        1 << (*self as u8)
+    }
+}
+
+/// Sets the division of the system clock, with the timed sequence of CLKPR.
+/// # Arguments
+/// * `prescaler` - a `ClockPrescaler`, the new division.
+pub fn set_clock_prescaler(prescaler: ClockPrescaler) {
+    let clkpr = 0x61 as *mut u8;
+    interrupts::free(|| unsafe {
+        // CLKPCE must be set alone, then the prescaler written within four cycles.
+        write_volatile(clkpr, 0x80);
+        write_volatile(clkpr, prescaler as u8);
+    });
+}
+
+/// Stops the clocks of all the peripherals except the given ones, and
+/// restarts those, with one write of PRR. The ADC is turned off before its
+/// clock is stopped, as the datasheet requires.
+/// # Arguments
+/// * `running` - a slice of `Peripherals`, the peripherals to keep clocked.
+pub fn gate_all_except(running: &[Peripherals]) {
+    let mut prr: u8 = 0xEF;
+    for peripheral in running {
+        prr &= !match peripheral {
+            Peripherals::TWI => 0x80,
+            Peripherals::Timer2 => 0x40,
+            Peripherals::Timer0 => 0x20,
+            Peripherals::Timer1 => 0x08,
+            Peripherals::SPI => 0x04,
+            Peripherals::USART0 => 0x02,
+            Peripherals::ADC => 0x01,
+        };
+    }
+    unsafe {
+        if prr & 0x01 != 0 {
+            let adcsra = 0x7A as *mut u8;
+            write_volatile(adcsra, read_volatile(adcsra) & !0x80);
+        }
+        write_volatile(&mut Power::new().prr, prr);
+    }
+}
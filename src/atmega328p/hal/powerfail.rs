@@ -0,0 +1,106 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Last gasp hook run when the supply is about to fail.
+//! The brown-out detector of the ATmega328P can only hold the chip in reset,
+//! it has no interrupt, so an early warning has to come from outside:
+//! a voltage supervisor or comparator watching the unregulated supply pulls
+//! digital pin 3 (PD3, INT1) low while the regulator output, helped by a
+//! large enough hold up capacitor, still keeps the chip running.
+//! The falling edge runs a user callback which should flush the critical state
+//! to EEPROM or FRAM, for example through `crate::storage::Storage`.
+//! An EEPROM byte takes about 3.4 ms to write, so the capacitor sizes how much can be saved.
+//! Whether the last reset came from the brown-out detector can be checked with `brown_out_reset`.
+//! The module needs the `powerfail` feature, because its interrupt service
+//! routine takes the INT1 vector.
+//! Sections 10 and 12 of the manual.
+
+use crate::atmega328p::hal::interrupts;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Addresses of the external interrupt registers.
+const EICRA: *mut u8 = 0x69 as *mut u8;
+const EIMSK: *mut u8 = 0x3D as *mut u8;
+const EIFR: *mut u8 = 0x3C as *mut u8;
+
+/// Addresses of the registers of the power fail pin.
+const DDR: *mut u8 = 0x2A as *mut u8;
+const PORT: *mut u8 = 0x2B as *mut u8;
+const PIN: *mut u8 = 0x29 as *mut u8;
+
+/// Address of the MCU status register holding the reset flags.
+const MCUSR: *mut u8 = 0x54 as *mut u8;
+
+/// Bit of the power fail pin in its port, which is also its bit in EIMSK and EIFR.
+const BIT: u8 = 3;
+
+static mut HANDLER: Option<fn()> = None;
+
+/// Enables the power fail interrupt on the falling edge of digital pin 3.
+/// # Arguments
+/// * `handler` - a function, the last gasp callback, run with interrupts disabled.
+/// * `pull_up` - a boolean, true to enable the internal pull up, for open drain supervisors.
+pub fn enable(handler: fn(), pull_up: bool) {
+    interrupts::free(|| unsafe {
+        HANDLER = Some(handler);
+        write_volatile(DDR, read_volatile(DDR) & !(1 << BIT));
+        if pull_up {
+            write_volatile(PORT, read_volatile(PORT) | (1 << BIT));
+        } else {
+            write_volatile(PORT, read_volatile(PORT) & !(1 << BIT));
+        }
+        // ISC11:0 = 10, falling edge.
+        let control = read_volatile(EICRA) & !(0x03 << 2);
+        write_volatile(EICRA, control | (0x02 << 2));
+        write_volatile(EIFR, 1 << BIT);
+        write_volatile(EIMSK, read_volatile(EIMSK) | (1 << BIT));
+    });
+}
+
+/// Disables the power fail interrupt.
+pub fn disable() {
+    interrupts::free(|| unsafe {
+        write_volatile(EIMSK, read_volatile(EIMSK) & !(1 << BIT));
+        HANDLER = None;
+    });
+}
+
+/// Returns true while the power fail input is low, that is while the supply is failing.
+/// Useful after the callback ran, to wait and see whether the supply comes back.
+pub fn is_failing() -> bool {
+    unsafe { read_volatile(PIN) & (1 << BIT) == 0 }
+}
+
+/// Returns true if the last reset was caused by the brown-out detector,
+/// as recorded by the BORF bit of MCUSR, and clears the flag.
+pub fn brown_out_reset() -> bool {
+    unsafe {
+        let flags = read_volatile(MCUSR);
+        write_volatile(MCUSR, flags & !(1 << 2));
+        flags & (1 << 2) != 0
+    }
+}
+
+/// Runs the last gasp callback once. The interrupt is disabled first so that
+/// a bouncing supply cannot run it again, `enable` re-arms it.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_2"]
+pub unsafe extern "avr-interrupt" fn power_fail() {
+    write_volatile(EIMSK, read_volatile(EIMSK) & !(1 << BIT));
+    if let Some(handler) = HANDLER {
+        handler();
+    }
+}
@@ -17,6 +17,8 @@
 //! Power management for ATmega328p chip using sleep modes.
 //! Section 9.11 of ATmega328p Datasheet is to be used.
 
+#[cfg(feature = "watchdog-interrupt")]
+use crate::atmega328p::hal::watchdog::{WatchDog, WatchdogTimeout};
 use core;
 
 /// Contains sleep modes.
@@ -149,3 +151,46 @@ pub fn enable_mode(mode: SleepMode) {
         SleepMode::Disable => Sleep::disable(&mut Sleep::new()),
     }
 }
+
+/// Puts the MCU in idle mode until the next interrupt occurs
+/// and disables the sleep mode again after waking up.
+/// Timers keep running in idle mode, so the millisecond counter interrupt
+/// wakes the MCU up at least once every millisecond.
+pub fn idle() {
+    enable_mode(SleepMode::Idle);
+    crate::__sleep();
+    enable_mode(SleepMode::Disable);
+}
+
+/// Puts the MCU in power-down mode until it is woken by an external interrupt,
+/// a pin change, a TWI address match or the watchdog, and disables the sleep mode
+/// again after waking up. All clocks stop, so `millis` does not advance meanwhile.
+pub fn power_down() {
+    enable_mode(SleepMode::PowerDown);
+    crate::__sleep();
+    enable_mode(SleepMode::Disable);
+}
+
+/// Puts the MCU in power-save mode until it is woken by Timer2, running from its
+/// asynchronous clock, or by any source of power-down mode, and disables the
+/// sleep mode again after waking up. See `hal::async_timer`.
+pub fn power_save() {
+    enable_mode(SleepMode::PowerSave);
+    crate::__sleep();
+    enable_mode(SleepMode::Disable);
+}
+
+/// Puts the MCU in power-down mode for about the given time, woken up by
+/// the watchdog in interrupt mode, which is disabled again afterwards.
+/// Needs the `watchdog-interrupt` feature.
+/// Global interrupts must be enabled. A watchdog used in system reset mode,
+/// for example by the supervisor, is left disabled too.
+/// # Arguments
+/// * `timeout` - a `WatchdogTimeout`, the time to sleep.
+#[cfg(feature = "watchdog-interrupt")]
+pub fn power_down_for(timeout: WatchdogTimeout) {
+    let watchdog = unsafe { WatchDog::new() };
+    watchdog.enable_interrupt(timeout);
+    power_down();
+    watchdog.disable();
+}
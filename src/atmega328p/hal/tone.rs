@@ -0,0 +1,291 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Square wave tones on any pin and a melody player, driven by the
+//! Timer/Counter2 compare match interrupt so that the main loop keeps running.
+//! The interrupt toggles the pin at twice the note frequency and counts the
+//! toggles to end the note, then loads the next note of the melody from
+//! program memory. Timer2 is taken over while a tone plays, so PWM output
+//! on pins 3 and 11 does not work meanwhile.
+//! The module needs the `tone` feature, because its interrupt service
+//! routine takes the Timer2 compare A vector.
+//! Section 18 of the manual.
+
+use crate::atmega328p::hal::interrupts;
+use crate::atmega328p::hal::port::Pin;
+use crate::atmega328p::hal::power::Power;
+use crate::config::CPU_FREQUENCY_HZ;
+use crate::progmem::{Iter, ProgMem};
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use embedded_hal::digital::OutputPin;
+
+/// Addresses of the Timer/Counter2 registers.
+const TCCR2A: *mut u8 = 0xB0 as *mut u8;
+const TCCR2B: *mut u8 = 0xB1 as *mut u8;
+const TCNT2: *mut u8 = 0xB2 as *mut u8;
+const OCR2A: *mut u8 = 0xB3 as *mut u8;
+const TIMSK2: *mut u8 = 0x70 as *mut u8;
+
+/// Prescalers of Timer2 with their clock select bits.
+const PRESCALERS: [(u32, u8); 7] = [
+    (1, 1),
+    (8, 2),
+    (32, 3),
+    (64, 4),
+    (128, 5),
+    (256, 6),
+    (1024, 7),
+];
+
+/// Interrupt rate used during rests, one interrupt per millisecond.
+const REST_RATE: u32 = 1000;
+
+/// A note of a melody.
+/// # Elements
+/// * `frequency` - a u16, the frequency in Hz, 0 for a rest.
+/// * `duration` - a u16, the length of the note in milliseconds.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Note {
+    pub frequency: u16,
+    pub duration: u16,
+}
+
+impl Note {
+    /// Creates a note.
+    pub const fn new(frequency: u16, duration: u16) -> Note {
+        Note {
+            frequency,
+            duration,
+        }
+    }
+
+    /// Creates a rest, a silence of the given number of milliseconds.
+    pub const fn rest(duration: u16) -> Note {
+        Note {
+            frequency: 0,
+            duration,
+        }
+    }
+}
+
+/// State shared with the interrupt service routine.
+/// # Elements
+/// * `pin` - the pin the tone is played on.
+/// * `toggles` - the number of interrupts left in the current note.
+/// * `endless` - true for a tone which plays till `no_tone`.
+/// * `silent` - true during a rest.
+/// * `melody` - the notes still to be played.
+struct ToneState {
+    pin: Option<Pin>,
+    toggles: u32,
+    endless: bool,
+    silent: bool,
+    melody: Option<Iter<'static, Note>>,
+}
+
+static mut TONE: ToneState = ToneState {
+    pin: None,
+    toggles: 0,
+    endless: false,
+    silent: false,
+    melody: None,
+};
+
+/// Programs Timer2 in CTC mode for the given interrupt rate and restarts it.
+/// # Returns
+/// * `a boolean` - false if the rate cannot be reached.
+unsafe fn start_timer(rate: u32) -> bool {
+    let setting = PRESCALERS
+        .iter()
+        .map(|&(prescaler, bits)| (CPU_FREQUENCY_HZ / (prescaler * rate), bits))
+        .find(|&(count, _)| (1..=256).contains(&count));
+    let (count, bits) = match setting {
+        Some(setting) => setting,
+        None => return false,
+    };
+    // CTC mode with OCR2A as top (WGM22:0 = 010), no output on the compare pins.
+    write_volatile(TCCR2A, 0x02);
+    write_volatile(TCCR2B, bits);
+    write_volatile(OCR2A, (count - 1) as u8);
+    write_volatile(TCNT2, 0);
+    true
+}
+
+/// Stops Timer2 and its interrupt.
+unsafe fn stop_timer() {
+    write_volatile(TIMSK2, read_volatile(TIMSK2) & !0x02);
+    write_volatile(TCCR2B, 0);
+}
+
+/// Starts a note, a rest when the frequency is 0.
+/// # Returns
+/// * `a boolean` - false if the frequency is out of the range of the timer.
+unsafe fn start_note(state: &mut ToneState, note: Note) -> bool {
+    state.silent = note.frequency == 0;
+    if let Some(pin) = state.pin.as_mut() {
+        let _ = pin.set_low();
+    }
+    let rate = if state.silent {
+        REST_RATE
+    } else {
+        note.frequency as u32 * 2
+    };
+    state.toggles = rate * note.duration as u32 / 1000;
+    start_timer(rate)
+}
+
+/// Loads the next note of the melody.
+/// # Returns
+/// * `a boolean` - false once the melody is over.
+unsafe fn next_note(state: &mut ToneState) -> bool {
+    while let Some(note) = state.melody.as_mut().and_then(|notes| notes.next()) {
+        if note.duration != 0 && start_note(state, note) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Ends the tone or melody and drives the pin low.
+unsafe fn finish(state: &mut ToneState) {
+    stop_timer();
+    if let Some(pin) = state.pin.as_mut() {
+        let _ = pin.set_low();
+    }
+    state.pin = None;
+    state.melody = None;
+}
+
+/// Takes over the pin and starts the interrupt.
+unsafe fn begin(state: &mut ToneState, mut pin: Pin) {
+    let power = Power::new();
+    write_volatile(&mut power.prr, read_volatile(&power.prr) & !(1 << 6));
+    pin.set_output();
+    state.pin = Some(pin);
+    write_volatile(TIMSK2, read_volatile(TIMSK2) | 0x02);
+    interrupts::Interrupt::enable(&mut interrupts::Interrupt::new());
+}
+
+/// Plays a square wave on a pin, replacing any tone or melody playing.
+/// # Arguments
+/// * `pin` - a `Pin` object, the pin the buzzer or speaker is connected to.
+/// * `frequency` - a u16, the frequency in Hz, from 31 Hz at 16 MHz.
+/// * `duration` - a u16, the length in milliseconds, 0 to play till `no_tone` is called.
+/// # Returns
+/// * `a boolean` - false if the frequency is out of range.
+pub fn tone(pin: Pin, frequency: u16, duration: u16) -> bool {
+    interrupts::free(|| unsafe {
+        let state = &mut *addr_of_mut!(TONE);
+        finish(state);
+        if frequency == 0 {
+            return false;
+        }
+        state.pin = Some(pin);
+        state.endless = duration == 0;
+        if !start_note(state, Note::new(frequency, duration)) {
+            state.pin = None;
+            return false;
+        }
+        begin(state, pin);
+        true
+    })
+}
+
+/// Stops the tone or melody playing.
+pub fn no_tone() {
+    interrupts::free(|| unsafe { finish(&mut *addr_of_mut!(TONE)) })
+}
+
+/// Plays a melody stored in program memory, replacing any tone or melody playing.
+/// `Event::MelodyFinished` is published on `system::events::EVENTS` at its end.
+///
+/// ```ignore
+/// progmem! {
+///     static JINGLE: [Note; 3] = [Note::new(659, 150), Note::rest(50), Note::new(784, 300)];
+/// }
+/// play(pins.digital[8].pin, &JINGLE);
+/// ```
+/// # Arguments
+/// * `pin` - a `Pin` object, the pin the buzzer or speaker is connected to.
+/// * `notes` - a reference to the notes, declared with `progmem!`.
+pub fn play<const N: usize>(pin: Pin, notes: &'static ProgMem<[Note; N]>) {
+    interrupts::free(|| unsafe {
+        let state = &mut *addr_of_mut!(TONE);
+        finish(state);
+        state.pin = Some(pin);
+        state.endless = false;
+        state.melody = Some(notes.iter());
+        if next_note(state) {
+            begin(state, pin);
+        } else {
+            state.pin = None;
+            state.melody = None;
+        }
+    })
+}
+
+/// Pauses the tone or melody, leaving the pin low.
+pub fn pause() {
+    interrupts::free(|| unsafe {
+        let state = &mut *addr_of_mut!(TONE);
+        if let Some(pin) = state.pin.as_mut() {
+            write_volatile(TIMSK2, read_volatile(TIMSK2) & !0x02);
+            let _ = pin.set_low();
+        }
+    })
+}
+
+/// Continues a tone or melody stopped by `pause`.
+pub fn resume() {
+    interrupts::free(|| unsafe {
+        if (*addr_of!(TONE)).pin.is_some() {
+            write_volatile(TIMSK2, read_volatile(TIMSK2) | 0x02);
+        }
+    })
+}
+
+/// Returns true while a tone or melody is playing or paused.
+pub fn is_playing() -> bool {
+    interrupts::free(|| unsafe { (*addr_of!(TONE)).pin.is_some() })
+}
+
+/// Timer/Counter2 compare match A interrupt service routine.
+#[cfg(target_arch = "avr")]
+#[export_name = "__vector_7"]
+pub unsafe extern "avr-interrupt" fn timer2_compare_a() {
+    use crate::system::events::{publish, Event};
+    use embedded_hal::digital::StatefulOutputPin;
+
+    let state = &mut *addr_of_mut!(TONE);
+    if !state.silent {
+        if let Some(pin) = state.pin.as_mut() {
+            let _ = StatefulOutputPin::toggle(pin);
+        }
+    }
+    if state.endless {
+        return;
+    }
+    state.toggles = state.toggles.saturating_sub(1);
+    if state.toggles == 0 {
+        let melody = state.melody.is_some();
+        if !next_note(state) {
+            finish(state);
+            if melody {
+                publish(Event::MelodyFinished);
+            }
+        }
+    }
+}
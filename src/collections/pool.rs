@@ -0,0 +1,169 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A pool of `N` fixed size blocks, giving drivers occasional dynamic
+//! allocation (packet buffers, display command queues) without a global heap.
+//! The storage of all the blocks is reserved when the pool is created and a
+//! block goes back to the pool when its `PoolBox` is dropped.
+//! Blocks are taken and given back with interrupts disabled, so a pool can be
+//! a `static` shared between interrupt service routines and the main loop.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+
+/// A pool of `N` blocks each able to hold one `T`.
+pub struct Pool<T, const N: usize> {
+    blocks: UnsafeCell<MaybeUninit<[T; N]>>,
+    used: UnsafeCell<[bool; N]>,
+}
+
+// The flags are only changed with interrupts disabled, and a block is only
+// reached through the single `PoolBox` owning it. There are no interrupts to
+// mask without a chip, so the pool is not shared between threads on the host.
+#[cfg(any(feature = "atmega328p", feature = "atmega2560p"))]
+unsafe impl<T: Send, const N: usize> Sync for Pool<T, N> {}
+
+/// Runs a closure with interrupts disabled.
+#[cfg(any(feature = "atmega328p", feature = "atmega2560p"))]
+fn critical<R>(f: impl FnOnce() -> R) -> R {
+    crate::hal::interrupts::free(f)
+}
+
+#[cfg(not(any(feature = "atmega328p", feature = "atmega2560p")))]
+fn critical<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/// An owned block taken from a `Pool`, it is returned to the pool on drop.
+pub struct PoolBox<'a, T, const N: usize> {
+    pool: &'a Pool<T, N>,
+    index: usize,
+}
+
+impl<T, const N: usize> Pool<T, N> {
+    /// Creates a new pool with all the blocks free.
+    pub const fn new() -> Self {
+        Pool {
+            blocks: UnsafeCell::new(MaybeUninit::uninit()),
+            used: UnsafeCell::new([false; N]),
+        }
+    }
+
+    /// Pointer to the storage of the `index`th block.
+    fn block(&self, index: usize) -> *mut T {
+        unsafe { ((*self.blocks.get()).as_mut_ptr() as *mut T).add(index) }
+    }
+
+    /// Moves a value into a free block of the pool.
+    /// # Arguments
+    /// * `value` - a `T`, the value to be stored.
+    /// # Returns
+    /// * `a Result` - a `PoolBox` owning the block, or the value back if the pool is exhausted.
+    pub fn alloc(&self, value: T) -> Result<PoolBox<'_, T, N>, T> {
+        let index = critical(|| {
+            let used = unsafe { &mut *self.used.get() };
+            let index = used.iter().position(|u| !*u)?;
+            used[index] = true;
+            Some(index)
+        });
+        match index {
+            Some(index) => {
+                unsafe { self.block(index).write(value) };
+                Ok(PoolBox { pool: self, index })
+            }
+            None => Err(value),
+        }
+    }
+
+    /// Returns the number of free blocks.
+    pub fn available(&self) -> usize {
+        critical(|| unsafe { &*self.used.get() }.iter().filter(|u| !**u).count())
+    }
+
+    /// Returns the total number of blocks.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Default for Pool<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T, const N: usize> Deref for PoolBox<'a, T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.pool.block(self.index) }
+    }
+}
+
+impl<'a, T, const N: usize> DerefMut for PoolBox<'a, T, N> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.pool.block(self.index) }
+    }
+}
+
+impl<'a, T, const N: usize> Drop for PoolBox<'a, T, N> {
+    fn drop(&mut self) {
+        unsafe { core::ptr::drop_in_place(self.pool.block(self.index)) };
+        critical(|| unsafe { (*self.pool.used.get())[self.index] = false });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test]
+    fn alloc_free_and_exhaustion() {
+        let pool: Pool<[u8; 4], 2> = Pool::new();
+        assert_eq!((pool.capacity(), pool.available()), (2, 2));
+        let mut first = pool.alloc([1; 4]).unwrap();
+        let second = pool.alloc([2; 4]).unwrap();
+        assert_eq!(pool.available(), 0);
+        assert_eq!(pool.alloc([3; 4]).err(), Some([3; 4]));
+        first[0] = 9;
+        assert_eq!((*first, *second), ([9, 1, 1, 1], [2; 4]));
+        drop(first);
+        assert_eq!(pool.available(), 1);
+        let third = pool.alloc([3; 4]).unwrap();
+        assert_eq!(*third, [3; 4]);
+        assert_eq!(*second, [2; 4]);
+    }
+
+    struct Counted<'a>(&'a Cell<u8>);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn drops_the_value_of_a_freed_block() {
+        let drops = Cell::new(0);
+        let pool: Pool<Counted, 1> = Pool::new();
+        let block = pool.alloc(Counted(&drops)).ok().unwrap();
+        assert_eq!(drops.get(), 0);
+        drop(block);
+        assert_eq!((drops.get(), pool.available()), (1, 1));
+    }
+}
@@ -0,0 +1,187 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A lock free single producer single consumer queue.
+//! One side may run inside an interrupt service routine and the other
+//! in the main loop without disabling interrupts, because each index is
+//! written by only one side and 8 bit loads and stores are atomic on AVR.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// A queue which holds at most `N - 1` elements, `N` must be between 2 and 256.
+pub struct Queue<T, const N: usize> {
+    buffer: UnsafeCell<MaybeUninit<[T; N]>>,
+    head: AtomicU8,
+    tail: AtomicU8,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for Queue<T, N> {}
+
+/// The enqueuing half of a `Queue`.
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a Queue<T, N>,
+}
+
+/// The dequeuing half of a `Queue`.
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a Queue<T, N>,
+}
+
+unsafe impl<'a, T: Send, const N: usize> Send for Producer<'a, T, N> {}
+unsafe impl<'a, T: Send, const N: usize> Send for Consumer<'a, T, N> {}
+
+impl<T, const N: usize> Queue<T, N> {
+    /// Creates a new empty queue, usable in a `static`.
+    /// The size must be from 2 to 256 so that the indices fit in the u8 counters.
+    pub const fn new() -> Self {
+        debug_assert!(N >= 2 && N <= 256, "a Queue needs a size from 2 to 256");
+        Queue {
+            buffer: UnsafeCell::new(MaybeUninit::uninit()),
+            head: AtomicU8::new(0),
+            tail: AtomicU8::new(0),
+        }
+    }
+
+    /// Index following `i` in the storage.
+    fn next(i: u8) -> u8 {
+        ((i as usize + 1) % N) as u8
+    }
+
+    /// Pointer to the `i`th slot of the storage.
+    fn slot(&self, i: u8) -> *mut T {
+        unsafe { ((*self.buffer.get()).as_mut_ptr() as *mut T).add(i as usize) }
+    }
+
+    /// Returns the number of elements in the queue.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire) as usize;
+        let tail = self.tail.load(Ordering::Acquire) as usize;
+        (tail + N - head) % N
+    }
+
+    /// Returns true if the queue holds no element.
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+
+    /// Returns true if no more elements can be enqueued.
+    pub fn is_full(&self) -> bool {
+        Self::next(self.tail.load(Ordering::Acquire)) == self.head.load(Ordering::Acquire)
+    }
+
+    /// Splits the queue into its two halves.
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        (Producer { queue: self }, Consumer { queue: self })
+    }
+
+    /// Enqueues an element.
+    /// # Arguments
+    /// * `value` - a `T`, the element to be added.
+    /// # Returns
+    /// * `a Result` - the element is given back if the queue is full.
+    /// # Safety
+    /// Only one execution context at a time may enqueue to a queue.
+    pub unsafe fn enqueue(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next = Self::next(tail);
+        if next == self.head.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        self.slot(tail).write(value);
+        self.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// Dequeues the oldest element.
+    /// # Returns
+    /// * `a Option<T>` - `None` if the queue is empty.
+    /// # Safety
+    /// Only one execution context at a time may dequeue from a queue.
+    pub unsafe fn dequeue(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let value = self.slot(head).read();
+        self.head.store(Self::next(head), Ordering::Release);
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Queue<T, N> {
+    fn drop(&mut self) {
+        while unsafe { self.dequeue() }.is_some() {}
+    }
+}
+
+impl<'a, T, const N: usize> Producer<'a, T, N> {
+    /// Enqueues an element, see `Queue::enqueue`.
+    pub fn enqueue(&mut self, value: T) -> Result<(), T> {
+        unsafe { self.queue.enqueue(value) }
+    }
+
+    /// Returns true if no more elements can be enqueued.
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+}
+
+impl<'a, T, const N: usize> Consumer<'a, T, N> {
+    /// Dequeues the oldest element, see `Queue::dequeue`.
+    pub fn dequeue(&mut self) -> Option<T> {
+        unsafe { self.queue.dequeue() }
+    }
+
+    /// Returns true if the queue holds no element.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Returns the number of elements waiting in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fifo_order_and_capacity() {
+        let mut queue: Queue<u8, 4> = Queue::new();
+        let (mut tx, mut rx) = queue.split();
+        assert_eq!(tx.enqueue(1), Ok(()));
+        assert_eq!(tx.enqueue(2), Ok(()));
+        assert_eq!(tx.enqueue(3), Ok(()));
+        assert_eq!(tx.enqueue(4), Err(4));
+        assert_eq!(rx.dequeue(), Some(1));
+        assert_eq!(tx.enqueue(4), Ok(()));
+        assert_eq!(rx.len(), 3);
+        assert_eq!(rx.dequeue(), Some(2));
+        assert_eq!(rx.dequeue(), Some(3));
+        assert_eq!(rx.dequeue(), Some(4));
+        assert_eq!(rx.dequeue(), None);
+    }
+}
@@ -0,0 +1,181 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A fixed capacity circular buffer for use within one execution context.
+
+use core::mem::MaybeUninit;
+
+/// A first in first out buffer holding at most `N` elements.
+/// It is not meant to be shared between an interrupt and the main loop,
+/// use `Queue` for that.
+pub struct RingBuffer<T: Copy, const N: usize> {
+    buffer: [MaybeUninit<T>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    /// Fails to compile for an empty buffer, whose first index would panic.
+    const VALID: () = assert!(N > 0, "a RingBuffer needs a capacity of at least 1");
+
+    /// Creates a new empty buffer.
+    pub const fn new() -> Self {
+        let () = Self::VALID;
+        RingBuffer {
+            buffer: [MaybeUninit::uninit(); N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the maximum number of elements the buffer can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of elements in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the buffer holds no element.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if no more elements can be pushed.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Removes all the elements.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Index in the storage of the `i`th element from the front.
+    fn slot(&self, i: usize) -> usize {
+        (self.head + i) % N
+    }
+
+    /// Adds an element at the back of the buffer.
+    /// # Arguments
+    /// * `value` - a `T`, the element to be added.
+    /// # Returns
+    /// * `a boolean` - false if the buffer was full and the element was dropped.
+    pub fn push(&mut self, value: T) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        let slot = self.slot(self.len);
+        self.buffer[slot] = MaybeUninit::new(value);
+        self.len += 1;
+        true
+    }
+
+    /// Adds an element at the back, discarding the oldest element if the buffer is full.
+    /// # Arguments
+    /// * `value` - a `T`, the element to be added.
+    /// # Returns
+    /// * `a Option<T>` - the element which was discarded, if any.
+    pub fn push_overwrite(&mut self, value: T) -> Option<T> {
+        let old = if self.is_full() { self.pop() } else { None };
+        self.push(value);
+        old
+    }
+
+    /// Removes the element at the front of the buffer.
+    /// # Returns
+    /// * `a Option<T>` - the oldest element, `None` if the buffer is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let value = unsafe { self.buffer[self.head].assume_init() };
+        self.head = self.slot(1);
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Returns the `i`th element from the front without removing it.
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+        Some(unsafe { self.buffer[self.slot(i)].assume_init() })
+    }
+
+    /// Returns the element at the front without removing it.
+    pub fn peek(&self) -> Option<T> {
+        self.get(0)
+    }
+
+    /// Returns an iterator over the elements from the oldest to the newest.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).map(move |i| unsafe { self.buffer[self.slot(i)].assume_init() })
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wraps_around() {
+        let mut buffer: RingBuffer<u8, 3> = RingBuffer::new();
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.push(1) && buffer.push(2) && buffer.push(3));
+        assert!(buffer.is_full());
+        assert!(!buffer.push(4));
+        assert_eq!(buffer.pop(), Some(1));
+        assert!(buffer.push(4));
+        assert_eq!(buffer.peek(), Some(2));
+        assert_eq!(buffer.get(2), Some(4));
+        assert_eq!(buffer.get(3), None);
+        let mut elements = [0; 3];
+        buffer
+            .iter()
+            .zip(elements.iter_mut())
+            .for_each(|(e, out)| *out = e);
+        assert_eq!(elements, [2, 3, 4]);
+        assert_eq!(
+            (buffer.pop(), buffer.pop(), buffer.pop()),
+            (Some(2), Some(3), Some(4))
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn overwrites_the_oldest() {
+        let mut buffer: RingBuffer<u16, 2> = RingBuffer::new();
+        assert_eq!(buffer.push_overwrite(10), None);
+        assert_eq!(buffer.push_overwrite(20), None);
+        assert_eq!(buffer.push_overwrite(30), Some(10));
+        assert_eq!(buffer.push_overwrite(40), Some(20));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!((buffer.get(0), buffer.get(1)), (Some(30), Some(40)));
+        buffer.clear();
+        assert_eq!(buffer.peek(), None);
+        assert_eq!(buffer.capacity(), 2);
+    }
+}
@@ -0,0 +1,358 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Software AES-128 block cipher with CTR and CBC modes of operation.
+//! The S-boxes are placed in program memory so that only the 176 byte
+//! key schedule and one block of state live in RAM, which keeps this
+//! usable on an ATMEGA328P alongside a radio driver.
+//! See the FIPS-197 standard for the description of the algorithm.
+
+use crate::progmem;
+
+/// Size of one AES block in bytes.
+pub const BLOCK_SIZE: usize = 16;
+
+/// Number of rounds done by AES-128.
+const ROUNDS: usize = 10;
+
+progmem! {
+    /// The forward substitution box.
+    static SBOX: [u8; 256] = [
+        0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+        0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+        0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+        0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+        0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+        0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+        0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+        0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+        0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+        0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+        0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+        0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+        0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+        0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+        0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+        0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+    ];
+}
+
+progmem! {
+    /// The inverse substitution box.
+    static INV_SBOX: [u8; 256] = [
+        0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb,
+        0x7c, 0xe3, 0x39, 0x82, 0x9b, 0x2f, 0xff, 0x87, 0x34, 0x8e, 0x43, 0x44, 0xc4, 0xde, 0xe9, 0xcb,
+        0x54, 0x7b, 0x94, 0x32, 0xa6, 0xc2, 0x23, 0x3d, 0xee, 0x4c, 0x95, 0x0b, 0x42, 0xfa, 0xc3, 0x4e,
+        0x08, 0x2e, 0xa1, 0x66, 0x28, 0xd9, 0x24, 0xb2, 0x76, 0x5b, 0xa2, 0x49, 0x6d, 0x8b, 0xd1, 0x25,
+        0x72, 0xf8, 0xf6, 0x64, 0x86, 0x68, 0x98, 0x16, 0xd4, 0xa4, 0x5c, 0xcc, 0x5d, 0x65, 0xb6, 0x92,
+        0x6c, 0x70, 0x48, 0x50, 0xfd, 0xed, 0xb9, 0xda, 0x5e, 0x15, 0x46, 0x57, 0xa7, 0x8d, 0x9d, 0x84,
+        0x90, 0xd8, 0xab, 0x00, 0x8c, 0xbc, 0xd3, 0x0a, 0xf7, 0xe4, 0x58, 0x05, 0xb8, 0xb3, 0x45, 0x06,
+        0xd0, 0x2c, 0x1e, 0x8f, 0xca, 0x3f, 0x0f, 0x02, 0xc1, 0xaf, 0xbd, 0x03, 0x01, 0x13, 0x8a, 0x6b,
+        0x3a, 0x91, 0x11, 0x41, 0x4f, 0x67, 0xdc, 0xea, 0x97, 0xf2, 0xcf, 0xce, 0xf0, 0xb4, 0xe6, 0x73,
+        0x96, 0xac, 0x74, 0x22, 0xe7, 0xad, 0x35, 0x85, 0xe2, 0xf9, 0x37, 0xe8, 0x1c, 0x75, 0xdf, 0x6e,
+        0x47, 0xf1, 0x1a, 0x71, 0x1d, 0x29, 0xc5, 0x89, 0x6f, 0xb7, 0x62, 0x0e, 0xaa, 0x18, 0xbe, 0x1b,
+        0xfc, 0x56, 0x3e, 0x4b, 0xc6, 0xd2, 0x79, 0x20, 0x9a, 0xdb, 0xc0, 0xfe, 0x78, 0xcd, 0x5a, 0xf4,
+        0x1f, 0xdd, 0xa8, 0x33, 0x88, 0x07, 0xc7, 0x31, 0xb1, 0x12, 0x10, 0x59, 0x27, 0x80, 0xec, 0x5f,
+        0x60, 0x51, 0x7f, 0xa9, 0x19, 0xb5, 0x4a, 0x0d, 0x2d, 0xe5, 0x7a, 0x9f, 0x93, 0xc9, 0x9c, 0xef,
+        0xa0, 0xe0, 0x3b, 0x4d, 0xae, 0x2a, 0xf5, 0xb0, 0xc8, 0xeb, 0xbb, 0x3c, 0x83, 0x53, 0x99, 0x61,
+        0x17, 0x2b, 0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d,
+    ];
+}
+
+/// Round constants used in the key expansion.
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+/// Reads the substitution of a byte from the forward S-box in flash.
+fn sub(byte: u8) -> u8 {
+    SBOX.get(byte as usize).unwrap_or(0)
+}
+
+/// Reads the substitution of a byte from the inverse S-box in flash.
+fn inv_sub(byte: u8) -> u8 {
+    INV_SBOX.get(byte as usize).unwrap_or(0)
+}
+
+/// Multiplies a byte by x (that is 2) in GF(2^8).
+fn xtime(byte: u8) -> u8 {
+    (byte << 1) ^ (((byte >> 7) & 1) * 0x1b)
+}
+
+/// Multiplies two bytes in GF(2^8).
+fn gmul(mut a: u8, mut b: u8) -> u8 {
+    let mut res = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            res ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    res
+}
+
+/// Contains the expanded key used for encryption and decryption.
+#[derive(Clone)]
+pub struct Aes128 {
+    round_keys: [u8; BLOCK_SIZE * (ROUNDS + 1)],
+}
+
+impl Aes128 {
+    /// Creates a new cipher instance by expanding the given key.
+    /// # Arguments
+    /// * `key` - a reference to `[u8; 16]`, the secret key.
+    /// # Returns
+    /// * `a Aes128 object` - which will be used to encrypt or decrypt blocks.
+    pub fn new(key: &[u8; 16]) -> Aes128 {
+        let mut round_keys = [0; BLOCK_SIZE * (ROUNDS + 1)];
+        round_keys[..16].copy_from_slice(key);
+
+        for i in 4..4 * (ROUNDS + 1) {
+            let mut word = [
+                round_keys[4 * i - 4],
+                round_keys[4 * i - 3],
+                round_keys[4 * i - 2],
+                round_keys[4 * i - 1],
+            ];
+            if i % 4 == 0 {
+                word.rotate_left(1);
+                for byte in word.iter_mut() {
+                    *byte = sub(*byte);
+                }
+                word[0] ^= RCON[i / 4 - 1];
+            }
+            for j in 0..4 {
+                round_keys[4 * i + j] = round_keys[4 * i - 16 + j] ^ word[j];
+            }
+        }
+
+        Aes128 { round_keys }
+    }
+
+    /// XORs the round key of the given round into the state.
+    fn add_round_key(&self, state: &mut [u8; 16], round: usize) {
+        let key = &self.round_keys[round * BLOCK_SIZE..(round + 1) * BLOCK_SIZE];
+        for (s, k) in state.iter_mut().zip(key.iter()) {
+            *s ^= *k;
+        }
+    }
+
+    /// Encrypts one block in place.
+    /// # Arguments
+    /// * `block` - a mutable reference to `[u8; 16]`, the plain text which is replaced by the cipher text.
+    pub fn encrypt_block(&self, block: &mut [u8; 16]) {
+        self.add_round_key(block, 0);
+        for round in 1..=ROUNDS {
+            // SubBytes.
+            for byte in block.iter_mut() {
+                *byte = sub(*byte);
+            }
+            shift_rows(block);
+            if round != ROUNDS {
+                mix_columns(block);
+            }
+            self.add_round_key(block, round);
+        }
+    }
+
+    /// Decrypts one block in place.
+    /// # Arguments
+    /// * `block` - a mutable reference to `[u8; 16]`, the cipher text which is replaced by the plain text.
+    pub fn decrypt_block(&self, block: &mut [u8; 16]) {
+        self.add_round_key(block, ROUNDS);
+        for round in (0..ROUNDS).rev() {
+            inv_shift_rows(block);
+            for byte in block.iter_mut() {
+                *byte = inv_sub(*byte);
+            }
+            self.add_round_key(block, round);
+            if round != 0 {
+                inv_mix_columns(block);
+            }
+        }
+    }
+
+    /// Encrypts or decrypts data in counter (CTR) mode.
+    /// The same call is used in both directions. The counter block is
+    /// incremented as a 128 bit big endian number after every block, including
+    /// a last partial one whose remaining key stream is thrown away. A following
+    /// call thus continues the key stream only if this one processed whole blocks.
+    /// A counter value must never be reused with the same key.
+    /// # Arguments
+    /// * `counter` - a mutable reference to `[u8; 16]`, the nonce and initial counter value.
+    /// * `data` - a mutable reference to `[u8]`, the data to be processed in place, of any length.
+    pub fn ctr_apply(&self, counter: &mut [u8; 16], data: &mut [u8]) {
+        for chunk in data.chunks_mut(BLOCK_SIZE) {
+            let mut stream = *counter;
+            self.encrypt_block(&mut stream);
+            for (d, s) in chunk.iter_mut().zip(stream.iter()) {
+                *d ^= *s;
+            }
+            increment_counter(counter);
+        }
+    }
+
+    /// Encrypts data in place in cipher block chaining (CBC) mode.
+    /// No padding is done, the caller has to pad the data to full blocks.
+    /// # Arguments
+    /// * `iv` - a reference to `[u8; 16]`, the initialization vector.
+    /// * `data` - a mutable reference to `[u8]`, the data to be encrypted in place.
+    /// # Returns
+    /// * `a boolean` - false if the length of data is not a multiple of the block size.
+    pub fn cbc_encrypt(&self, iv: &[u8; 16], data: &mut [u8]) -> bool {
+        if data.len() % BLOCK_SIZE != 0 {
+            return false;
+        }
+        let mut prev = *iv;
+        for chunk in data.chunks_mut(BLOCK_SIZE) {
+            for (p, c) in prev.iter_mut().zip(chunk.iter()) {
+                *p ^= *c;
+            }
+            self.encrypt_block(&mut prev);
+            chunk.copy_from_slice(&prev);
+        }
+        true
+    }
+
+    /// Decrypts data in place in cipher block chaining (CBC) mode.
+    /// # Arguments
+    /// * `iv` - a reference to `[u8; 16]`, the initialization vector used for encryption.
+    /// * `data` - a mutable reference to `[u8]`, the data to be decrypted in place.
+    /// # Returns
+    /// * `a boolean` - false if the length of data is not a multiple of the block size.
+    pub fn cbc_decrypt(&self, iv: &[u8; 16], data: &mut [u8]) -> bool {
+        if data.len() % BLOCK_SIZE != 0 {
+            return false;
+        }
+        let mut prev = *iv;
+        for chunk in data.chunks_mut(BLOCK_SIZE) {
+            let mut block = [0; BLOCK_SIZE];
+            block.copy_from_slice(chunk);
+            let cipher = block;
+            self.decrypt_block(&mut block);
+            for (b, p) in block.iter_mut().zip(prev.iter()) {
+                *b ^= *p;
+            }
+            chunk.copy_from_slice(&block);
+            prev = cipher;
+        }
+        true
+    }
+}
+
+/// Cyclically shifts row `r` of the state left by `r` places.
+/// The state is stored column wise, so row `r` is bytes `r, r+4, r+8, r+12`.
+fn shift_rows(state: &mut [u8; 16]) {
+    for r in 1..4 {
+        for _ in 0..r {
+            let first = state[r];
+            state[r] = state[r + 4];
+            state[r + 4] = state[r + 8];
+            state[r + 8] = state[r + 12];
+            state[r + 12] = first;
+        }
+    }
+}
+
+/// Inverse of `shift_rows`.
+fn inv_shift_rows(state: &mut [u8; 16]) {
+    for r in 1..4 {
+        for _ in 0..r {
+            let last = state[r + 12];
+            state[r + 12] = state[r + 8];
+            state[r + 8] = state[r + 4];
+            state[r + 4] = state[r];
+            state[r] = last;
+        }
+    }
+}
+
+/// Mixes every column of the state.
+fn mix_columns(state: &mut [u8; 16]) {
+    for col in state.chunks_mut(4) {
+        let (a0, a1, a2, a3) = (col[0], col[1], col[2], col[3]);
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        col[0] ^= all ^ xtime(a0 ^ a1);
+        col[1] ^= all ^ xtime(a1 ^ a2);
+        col[2] ^= all ^ xtime(a2 ^ a3);
+        col[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
+
+/// Inverse of `mix_columns`.
+fn inv_mix_columns(state: &mut [u8; 16]) {
+    for col in state.chunks_mut(4) {
+        let (a0, a1, a2, a3) = (col[0], col[1], col[2], col[3]);
+        col[0] = gmul(a0, 14) ^ gmul(a1, 11) ^ gmul(a2, 13) ^ gmul(a3, 9);
+        col[1] = gmul(a0, 9) ^ gmul(a1, 14) ^ gmul(a2, 11) ^ gmul(a3, 13);
+        col[2] = gmul(a0, 13) ^ gmul(a1, 9) ^ gmul(a2, 14) ^ gmul(a3, 11);
+        col[3] = gmul(a0, 11) ^ gmul(a1, 13) ^ gmul(a2, 9) ^ gmul(a3, 14);
+    }
+}
+
+/// Increments a 128 bit big endian counter block.
+fn increment_counter(counter: &mut [u8; 16]) {
+    for byte in counter.iter_mut().rev() {
+        *byte = byte.wrapping_add(1);
+        if *byte != 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Example vector from FIPS-197 appendix C.1.
+    const KEY: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    const PLAIN: [u8; 16] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+    const CIPHER: [u8; 16] = [
+        0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5,
+        0x5a,
+    ];
+
+    #[test]
+    fn block_matches_fips_197() {
+        let aes = Aes128::new(&KEY);
+        let mut block = PLAIN;
+        aes.encrypt_block(&mut block);
+        assert_eq!(block, CIPHER);
+        aes.decrypt_block(&mut block);
+        assert_eq!(block, PLAIN);
+    }
+
+    #[test]
+    fn modes_round_trip() {
+        let aes = Aes128::new(&KEY);
+        let mut data = [0x5a; 48];
+        assert!(aes.cbc_encrypt(&PLAIN, &mut data));
+        assert!(aes.cbc_decrypt(&PLAIN, &mut data));
+        assert_eq!(data, [0x5a; 48]);
+        assert!(!aes.cbc_encrypt(&PLAIN, &mut data[..20]));
+
+        let mut counter = PLAIN;
+        aes.ctr_apply(&mut counter, &mut data[..37]);
+        let mut counter = PLAIN;
+        aes.ctr_apply(&mut counter, &mut data[..37]);
+        assert_eq!(data, [0x5a; 48]);
+    }
+}
@@ -0,0 +1,276 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Challenge-response authentication of commands, so that a command recorded
+//! on a serial line or a radio link cannot be replayed. The device issues a
+//! fresh nonce with `Authenticator::challenge`, the remote computes `respond`,
+//! the HMAC-SHA256 of the nonce and the command with the shared key, and the
+//! device checks it with `Authenticator::verify`. A nonce is only good for one
+//! attempt. Tags are compared with `ct_eq`, which takes the same time whatever
+//! the bytes, so the tag cannot be guessed byte by byte from the response time.
+//! The key is kept in the EEPROM or another `Storage` through `KeyStore`.
+
+use super::hmac::HmacSha256;
+use super::sha256::DIGEST_SIZE;
+use crate::storage::{Plain, Settings, Storage, SETTINGS_HEADER_SIZE};
+
+/// Size of the shared key in bytes.
+pub const KEY_SIZE: usize = 32;
+
+/// Size of a nonce in bytes.
+pub const NONCE_SIZE: usize = 16;
+
+/// Size of a response in bytes.
+pub const TAG_SIZE: usize = DIGEST_SIZE;
+
+/// Compares two byte strings in a time depending only on their length.
+/// # Arguments
+/// * `a` - a reference to `[u8]`, the first string.
+/// * `b` - a reference to `[u8]`, the second string.
+/// # Returns
+/// * `a boolean` - true if both strings are equal.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut difference = 0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        difference |= x ^ y;
+    }
+    // Keeps the compiler from stopping at the first difference.
+    unsafe { core::ptr::read_volatile(&difference) == 0 }
+}
+
+/// Computes the response to a challenge, on the side sending the command.
+/// # Arguments
+/// * `key` - a reference to `[u8; KEY_SIZE]`, the shared key.
+/// * `nonce` - a reference to `[u8; NONCE_SIZE]`, the challenge received from the device.
+/// * `message` - a reference to `[u8]`, the command being authenticated.
+/// # Returns
+/// * `a [u8; TAG_SIZE]` - the response, sent along with the command.
+pub fn respond(key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE], message: &[u8]) -> [u8; TAG_SIZE] {
+    let mut mac = HmacSha256::new(key);
+    mac.update(nonce);
+    mac.update(message);
+    mac.finalize()
+}
+
+/// The side of the device receiving commands.
+/// # Elements
+/// * `key` - a `[u8; KEY_SIZE]`, the shared key.
+/// * `counter` - a u32, the number of challenges issued.
+/// * `nonce` - the challenge waiting for a response, `None` if there is none.
+pub struct Authenticator {
+    key: [u8; KEY_SIZE],
+    counter: u32,
+    nonce: Option<[u8; NONCE_SIZE]>,
+}
+
+impl Authenticator {
+    /// Creates an authenticator without pending challenge.
+    /// # Arguments
+    /// * `key` - a `[u8; KEY_SIZE]`, the shared key, for example from `KeyStore::load`.
+    /// # Returns
+    /// * `a Authenticator object` - Which will be used for further implementations.
+    pub fn new(key: [u8; KEY_SIZE]) -> Authenticator {
+        Authenticator {
+            key,
+            counter: 0,
+            nonce: None,
+        }
+    }
+
+    /// Issues a new challenge, replacing the pending one.
+    /// The nonce is derived from the key, a counter and the entropy given, which
+    /// must differ after every reset, like bits of `math::random` or the noise of an
+    /// unconnected analog input, otherwise the nonces repeat and so do the responses.
+    /// # Arguments
+    /// * `entropy` - a reference to `[u8]`, unpredictable bytes.
+    /// # Returns
+    /// * `a [u8; NONCE_SIZE]` - the nonce, sent to the remote.
+    pub fn challenge(&mut self, entropy: &[u8]) -> [u8; NONCE_SIZE] {
+        self.counter = self.counter.wrapping_add(1);
+        let mut mac = HmacSha256::new(&self.key);
+        mac.update(b"nonce");
+        mac.update(&self.counter.to_le_bytes());
+        mac.update(entropy);
+        let mut nonce = [0; NONCE_SIZE];
+        nonce.copy_from_slice(&mac.finalize()[..NONCE_SIZE]);
+        self.nonce = Some(nonce);
+        nonce
+    }
+
+    /// Returns the challenge waiting for a response.
+    pub fn pending(&self) -> Option<&[u8; NONCE_SIZE]> {
+        self.nonce.as_ref()
+    }
+
+    /// Checks the response to the pending challenge, which is then used up
+    /// whatever the result, so every attempt needs a new challenge.
+    /// # Arguments
+    /// * `message` - a reference to `[u8]`, the command received.
+    /// * `tag` - a reference to `[u8]`, the response received with it.
+    /// # Returns
+    /// * `a boolean` - true if the command comes from a holder of the key.
+    pub fn verify(&mut self, message: &[u8], tag: &[u8]) -> bool {
+        match self.nonce.take() {
+            Some(nonce) => ct_eq(&respond(&self.key, &nonce, message), tag),
+            None => false,
+        }
+    }
+
+    /// Drops the pending challenge, for example after a timeout.
+    pub fn cancel(&mut self) {
+        self.nonce = None;
+    }
+}
+
+/// A key as stored by `KeyStore`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct StoredKey {
+    bytes: [u8; KEY_SIZE],
+}
+
+unsafe impl Plain for StoredKey {}
+
+/// Version of the layout of the stored key.
+const KEY_VERSION: u16 = 1;
+
+/// The shared key persisted in the EEPROM or another `Storage`, behind the
+/// header of `storage::Settings`, so an erased or corrupted key is noticed.
+/// Anyone reading the memory, through ISP for example, learns the key,
+/// the lock bits of the chip should be set in products.
+pub struct KeyStore<S: Storage> {
+    settings: Settings<StoredKey, S>,
+}
+
+impl<S: Storage> KeyStore<S> {
+    /// Number of bytes the key takes in the storage.
+    pub const STORED_SIZE: u32 = SETTINGS_HEADER_SIZE + KEY_SIZE as u32;
+
+    /// Creates the key store.
+    /// # Arguments
+    /// * `storage` - a `Storage` object, like the `Eeprom`.
+    /// * `address` - a u32, the address of the key, `STORED_SIZE` bytes being used.
+    /// # Returns
+    /// * `a KeyStore object` - Which will be used for further implementations.
+    pub fn new(storage: S, address: u32) -> KeyStore<S> {
+        let blank = StoredKey {
+            bytes: [0; KEY_SIZE],
+        };
+        KeyStore {
+            settings: Settings::new(storage, address, KEY_VERSION, blank),
+        }
+    }
+
+    /// Reads the key. No copy of it is kept by the store.
+    /// # Returns
+    /// * `a Option<[u8; KEY_SIZE]>` - the key, `None` if none was stored, it was erased or it is corrupted.
+    pub fn load(&mut self) -> Option<[u8; KEY_SIZE]> {
+        let valid = self.settings.load();
+        let key = self.settings.get().bytes;
+        self.settings.get_mut().bytes = [0; KEY_SIZE];
+        // An erased key is stored as zeros.
+        if valid && key != [0; KEY_SIZE] {
+            Some(key)
+        } else {
+            None
+        }
+    }
+
+    /// Stores a key, replacing the previous one.
+    /// # Arguments
+    /// * `key` - a reference to `[u8; KEY_SIZE]`, the new key, which must not be all zeros.
+    /// # Returns
+    /// * `a boolean` - false if the key is all zeros or the storage could not be written.
+    pub fn store(&mut self, key: &[u8; KEY_SIZE]) -> bool {
+        if *key == [0; KEY_SIZE] {
+            return false;
+        }
+        self.settings.get_mut().bytes = *key;
+        let saved = self.settings.save();
+        self.settings.get_mut().bytes = [0; KEY_SIZE];
+        saved
+    }
+
+    /// Overwrites the stored key with zeros, after which `load` finds no key.
+    /// # Returns
+    /// * `a boolean` - false if the storage could not be written.
+    pub fn erase(&mut self) -> bool {
+        self.settings.reset()
+    }
+
+    /// Gives back the storage.
+    pub fn release(self) -> S {
+        self.settings.release()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Result;
+
+    struct Memory([u8; 48]);
+
+    impl Storage for Memory {
+        fn capacity(&self) -> u32 {
+            48
+        }
+
+        fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+            let start = address as usize;
+            data.copy_from_slice(&self.0[start..start + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+            let start = address as usize;
+            self.0[start..start + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn challenge_response() {
+        assert!(ct_eq(b"open", b"open"));
+        assert!(!ct_eq(b"open", b"opem") && !ct_eq(b"open", b"ope"));
+
+        let mut store = KeyStore::new(Memory([0xFF; 48]), 4);
+        assert_eq!(store.load(), None);
+        assert!(!store.store(&[0; KEY_SIZE]));
+        assert!(store.store(&[7; KEY_SIZE]));
+        let key = store.load().unwrap();
+        assert_eq!(key, [7; KEY_SIZE]);
+
+        let mut device = Authenticator::new(key);
+        assert!(!device.verify(b"open", &[0; TAG_SIZE]));
+        let nonce = device.challenge(&[1, 2, 3]);
+        let tag = respond(&key, &nonce, b"open");
+        assert!(!device.verify(b"close", &tag));
+        // The failed attempt used the challenge up, the response is now a replay.
+        assert!(!device.verify(b"open", &tag));
+
+        let next = device.challenge(&[1, 2, 3]);
+        assert_ne!(next, nonce);
+        assert!(device.verify(b"open", &respond(&key, &next, b"open")));
+        assert!(device.pending().is_none());
+
+        assert!(store.erase());
+        assert_eq!(store.load(), None);
+    }
+}
@@ -0,0 +1,105 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! HMAC-SHA256 message authentication code as described in RFC 2104.
+//! Used to authenticate telemetry frames with a shared secret key.
+
+use super::sha256::{Sha256, BLOCK_SIZE, DIGEST_SIZE};
+
+/// Contains the running state of a HMAC-SHA256 computation.
+#[derive(Clone)]
+pub struct HmacSha256 {
+    inner: Sha256,
+    outer_pad: [u8; BLOCK_SIZE],
+}
+
+impl HmacSha256 {
+    /// Creates a new HMAC computation for the given key.
+    /// Keys longer than 64 bytes are hashed first as the standard requires.
+    /// # Arguments
+    /// * `key` - a reference to `[u8]`, the secret key.
+    /// # Returns
+    /// * `a HmacSha256 object` - to which the message is fed.
+    pub fn new(key: &[u8]) -> HmacSha256 {
+        let mut block = [0; BLOCK_SIZE];
+        if key.len() > BLOCK_SIZE {
+            let mut hash = Sha256::new();
+            hash.update(key);
+            block[..DIGEST_SIZE].copy_from_slice(&hash.finalize());
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner_pad = [0x36; BLOCK_SIZE];
+        let mut outer_pad = [0x5c; BLOCK_SIZE];
+        for i in 0..BLOCK_SIZE {
+            inner_pad[i] ^= block[i];
+            outer_pad[i] ^= block[i];
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&inner_pad);
+        HmacSha256 { inner, outer_pad }
+    }
+
+    /// Feeds more of the message into the computation.
+    /// # Arguments
+    /// * `data` - a reference to `[u8]`, the next part of the message.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Finishes the computation.
+    /// # Returns
+    /// * `a [u8; 32]` - the authentication tag.
+    pub fn finalize(self) -> [u8; DIGEST_SIZE] {
+        let inner = self.inner.finalize();
+        let mut outer = Sha256::new();
+        outer.update(&self.outer_pad);
+        outer.update(&inner);
+        outer.finalize()
+    }
+}
+
+/// Computes the HMAC-SHA256 tag of a message available at once.
+/// # Arguments
+/// * `key` - a reference to `[u8]`, the secret key.
+/// * `data` - a reference to `[u8]`, the message.
+/// # Returns
+/// * `a [u8; 32]` - the authentication tag.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; DIGEST_SIZE] {
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.finalize()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Test case 2 of RFC 4231.
+    #[test]
+    fn rfc_4231_case_2() {
+        assert_eq!(
+            hmac_sha256(b"Jefe", b"what do ya want for nothing?"),
+            [
+                0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e, 0x6a, 0x04, 0x24, 0x26, 0x08, 0x95,
+                0x75, 0xc7, 0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83, 0x9d, 0xec, 0x58, 0xb9,
+                0x64, 0xec, 0x38, 0x43,
+            ]
+        );
+    }
+}
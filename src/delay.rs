@@ -17,34 +17,44 @@
 //! This module contains the delay functions which would be used in
 //! various places in the library for pausing the program for the
 //! given amount of time.
+//! The `delay_*` functions count CPU cycles, while `wait_ms`, with the
+//! `millis` feature, watches the millis counter so its length does not
+//! depend on the time spent in interrupt service routines.
+//! `Delay` gives the cycle counted delays to drivers written against
+//! the `embedded_hal::delay::DelayNs` trait.
+
+#[cfg(all(
+    feature = "millis",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+use crate::hal::{millis, sleep_mode};
+use core::arch::asm;
+use embedded_hal::delay::DelayNs;
+
+/// Number of CPU cycles in one microsecond.
+const CYCLES_PER_US: u32 = crate::config::CPU_FREQUENCY_HZ / 1_000_000;
 
 /// Internal function to implement a variable busy-wait loop.
+/// Every cycle of the loop takes 4 CPU cycles.
 /// # Arguments
-/// * `count` - an i32, the number of times to cycle the loop.
+/// * `count` - an u32, the number of times to cycle the loop.
 #[inline(always)]
 pub fn delay(count: u32) {
     // Our asm busy-wait takes a 16 bit word as an argument,
     // so the max number of loops is 2^16
     let outer_count = count / 65536;
-    let last_count = ((count % 65536) + 1) as u16;
+    let last_count = (count % 65536) as u16;
     for _ in 0..outer_count {
         // Each loop through should be 4 cycles.
         unsafe {
-            llvm_asm!("1: sbiw $0,1
-                      brne 1b"
-                     :
-                     : "w" (0)
-                     :
-                     :)
+            asm!("1: sbiw {0}, 1", "brne 1b", inout(reg_iw) 0u16 => _);
         }
     }
-    unsafe {
-        llvm_asm!("1: sbiw $0,1
-                      brne 1b"
-                 :
-                 : "w" (last_count)
-                 :
-                 :)
+    // A count of 0 would run the loop 2^16 times.
+    if last_count != 0 {
+        unsafe {
+            asm!("1: sbiw {0}, 1", "brne 1b", inout(reg_iw) last_count => _);
+        }
     }
 }
 
@@ -63,19 +73,98 @@ pub fn delay_s(s: u32) {
 /// * `ms` - an u32, number of milliseconds to busy-wait
 #[inline(always)]
 pub fn delay_ms(ms: u32) {
-    // microseconds
-    let us = ms * 1000;
-    delay_us(us);
+    // One millisecond at a time, so that long delays do not overflow.
+    for _ in 0..ms {
+        delay_us(1000);
+    }
 }
 
 ///delay for N microseconds
+/// The wait is exact to 4 CPU cycles when `us` is a constant,
+/// so it can be used for the timing of bit banged protocols.
 /// # Arguments
 /// * `us` - an u32, number of microseconds to busy-wait
 #[inline(always)]
 pub fn delay_us(us: u32) {
-    // nanoseconds
-    let ns = us * 1000;
-    let ns_lp = 1000000000 / (crate::config::CPU_FREQUENCY_HZ / 4);
-    let loops = (ns / ns_lp) as u32;
-    delay(loops);
+    delay(us * CYCLES_PER_US / 4);
+}
+
+/// Busy-wait delay provider for drivers using `embedded_hal::delay::DelayNs`.
+/// The waits are never shorter than asked, and longer only by the
+/// rounding to 4 CPU cycles and the time spent in interrupts.
+#[derive(Clone, Copy, Default)]
+pub struct Delay;
+
+impl Delay {
+    /// Creates a delay provider.
+    /// # Returns
+    /// * `a Delay object` - which can be given to any number of drivers.
+    pub fn new() -> Delay {
+        Delay
+    }
+}
+
+impl DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        let us = ns / 1000;
+        self.delay_us(us);
+        // The rest rounded up to whole loops of 4 cycles.
+        delay(((ns % 1000) * CYCLES_PER_US + 3999) / 4000);
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        // Whole milliseconds apart, so that `us * CYCLES_PER_US` cannot overflow.
+        delay_ms(us / 1000);
+        delay_us(us % 1000);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        delay_ms(ms);
+    }
+}
+
+/// Waits for N milliseconds by watching the millis counter.
+/// Interrupts are served during the wait without making it longer,
+/// which keeps buffered serial and software PWM working during long waits.
+/// If the counter was not started by `millis_init` this falls back to `delay_ms`.
+/// # Arguments
+/// * `ms` - an u32, number of milliseconds to wait
+#[cfg(all(
+    feature = "millis",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+pub fn wait_ms(ms: u32) {
+    wait(ms, false);
+}
+
+/// Waits for N milliseconds like `wait_ms`, but the CPU sleeps in
+/// Idle mode until the next interrupt instead of spinning.
+/// # Arguments
+/// * `ms` - an u32, number of milliseconds to wait
+#[cfg(all(
+    feature = "millis",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+pub fn wait_ms_idle(ms: u32) {
+    wait(ms, true);
+}
+
+/// Internal function for `wait_ms` and `wait_ms_idle`.
+#[cfg(all(
+    feature = "millis",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+fn wait(ms: u32, idle: bool) {
+    if !millis::millis_running() {
+        delay_ms(ms);
+        return;
+    }
+
+    let start = millis::millis();
+    while millis::millis().wrapping_sub(start) < ms {
+        if idle {
+            // The Timer0 overflow wakes the CPU at least once per millisecond.
+            sleep_mode::idle();
+        }
+    }
 }
@@ -0,0 +1,134 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Standard base64 (RFC 4648) with `=` padding.
+
+/// The base64 alphabet.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Returns the number of bytes needed to encode `len` bytes of data.
+pub const fn encoded_len(len: usize) -> usize {
+    (len + 2) / 3 * 4
+}
+
+/// Returns the largest number of bytes that `len` encoded characters can decode to.
+pub const fn decoded_len(len: usize) -> usize {
+    len / 4 * 3
+}
+
+/// Encodes binary data as base64 text.
+/// # Arguments
+/// * `input` - a reference to `[u8]`, the data to be encoded.
+/// * `output` - a mutable reference to `[u8]`, where the ASCII text is written.
+/// # Returns
+/// * `a Option<usize>` - the number of bytes written, `None` if `output` is too small.
+pub fn encode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let len = encoded_len(input.len());
+    if output.len() < len {
+        return None;
+    }
+
+    for (chunk, out) in input.chunks(3).zip(output.chunks_mut(4)) {
+        let b0 = chunk[0] as usize;
+        let b1 = *chunk.get(1).unwrap_or(&0) as usize;
+        let b2 = *chunk.get(2).unwrap_or(&0) as usize;
+
+        out[0] = ALPHABET[b0 >> 2];
+        out[1] = ALPHABET[((b0 & 0x03) << 4) | (b1 >> 4)];
+        out[2] = if chunk.len() > 1 {
+            ALPHABET[((b1 & 0x0f) << 2) | (b2 >> 6)]
+        } else {
+            b'='
+        };
+        out[3] = if chunk.len() > 2 {
+            ALPHABET[b2 & 0x3f]
+        } else {
+            b'='
+        };
+    }
+    Some(len)
+}
+
+/// Gives the 6 bit value of a base64 character.
+fn value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decodes base64 text back to binary data.
+/// # Arguments
+/// * `input` - a reference to `[u8]`, the ASCII text, its length must be a multiple of 4.
+/// * `output` - a mutable reference to `[u8]`, where the data is written.
+/// # Returns
+/// * `a Option<usize>` - the number of bytes written, `None` if the text is invalid or `output` is too small.
+pub fn decode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    if input.len() % 4 != 0 {
+        return None;
+    }
+
+    let mut written = 0;
+    let blocks = input.len() / 4;
+    for (i, chunk) in input.chunks(4).enumerate() {
+        // Padding is only allowed at the end of the last block.
+        let pad = if i + 1 == blocks {
+            chunk.iter().rev().take_while(|c| **c == b'=').count()
+        } else {
+            0
+        };
+        if pad > 2 {
+            return None;
+        }
+
+        let mut bits: u32 = 0;
+        for c in &chunk[..4 - pad] {
+            bits = (bits << 6) | value(*c)? as u32;
+        }
+        bits <<= 6 * pad as u32;
+
+        let bytes = 3 - pad;
+        if output.len() < written + bytes {
+            return None;
+        }
+        for j in 0..bytes {
+            output[written + j] = (bits >> (16 - 8 * j)) as u8;
+        }
+        written += bytes;
+    }
+    Some(written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut text = [0; 8];
+        let mut data = [0; 6];
+        assert_eq!(encode(b"rust", &mut text), Some(8));
+        assert_eq!(&text, b"cnVzdA==");
+        assert_eq!(decode(&text, &mut data), Some(4));
+        assert_eq!(&data[..4], b"rust");
+        assert_eq!(decode(b"cn=zdA==", &mut data), None);
+        assert_eq!(encode(b"rust", &mut data), None);
+    }
+}
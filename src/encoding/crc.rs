@@ -0,0 +1,168 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Bitwise cyclic redundancy checks, small in flash and needing no tables.
+
+/// CRC-8 with the Dallas/Maxim polynomial (reflected 0x8C), as used by 1-Wire devices.
+/// # Arguments
+/// * `data` - a reference to `[u8]`, the bytes to be checked.
+/// # Returns
+/// * `a u8` - the CRC, starting from 0.
+pub fn crc8_maxim(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0;
+    for byte in data {
+        let mut b = *byte;
+        for _ in 0..8 {
+            let mix = (crc ^ b) & 0x01;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            b >>= 1;
+        }
+    }
+    crc
+}
+
+/// CRC-8 with the polynomial 0x31 and initial value 0xFF, as used by Sensirion and Aosong sensors.
+/// # Arguments
+/// * `data` - a reference to `[u8]`, the bytes to be checked.
+/// # Returns
+/// * `a u8` - the CRC.
+pub fn crc8_sensirion(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xFF;
+    for byte in data {
+        crc ^= *byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-7 with the polynomial 0x09, as used by the commands of SD and MMC cards.
+/// # Arguments
+/// * `data` - a reference to `[u8]`, the bytes to be checked.
+/// # Returns
+/// * `a u8` - the CRC in the low 7 bits, starting from 0.
+pub fn crc7_mmc(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0;
+    for byte in data {
+        crc ^= *byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x12
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc >> 1
+}
+
+/// Continues a CRC-16/CCITT-FALSE computation (polynomial 0x1021) with more data.
+/// # Arguments
+/// * `crc` - a u16, the CRC of the previous data, 0xFFFF to start.
+/// * `data` - a reference to `[u8]`, the next bytes.
+/// # Returns
+/// * `a u16` - the updated CRC.
+pub fn crc16_ccitt_update(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-16/CCITT-FALSE of a block of data.
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    crc16_ccitt_update(0xFFFF, data)
+}
+
+/// Continues a CRC-16/MCRF4XX computation, the X.25 checksum without final inversion used by MAVLink.
+/// # Arguments
+/// * `crc` - a u16, the CRC of the previous data, 0xFFFF to start.
+/// * `data` - a reference to `[u8]`, the next bytes.
+/// # Returns
+/// * `a u16` - the updated CRC.
+pub fn crc16_x25_update(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        let mut tmp = *byte ^ crc as u8;
+        tmp ^= tmp << 4;
+        let tmp = tmp as u16;
+        crc = (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4);
+    }
+    crc
+}
+
+/// CRC-16/MCRF4XX of a block of data.
+pub fn crc16_x25(data: &[u8]) -> u16 {
+    crc16_x25_update(0xFFFF, data)
+}
+
+/// Continues a CRC-32 (IEEE 802.3, reflected 0xEDB88320) computation with more data.
+/// # Arguments
+/// * `crc` - a u32, the value returned for the previous data, 0 to start.
+/// * `data` - a reference to `[u8]`, the next bytes.
+/// # Returns
+/// * `a u32` - the updated CRC.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// CRC-32 of a block of data, the same as used by zip and Ethernet.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Check values of the catalogue of parametrised CRC algorithms.
+    #[test]
+    fn check_values() {
+        let data = b"123456789";
+        assert_eq!(crc7_mmc(data), 0x75);
+        assert_eq!(crc8_maxim(data), 0xA1);
+        assert_eq!(crc8_sensirion(data), 0xF7);
+        assert_eq!(crc16_ccitt(data), 0x29B1);
+        assert_eq!(crc16_x25(data), 0x6F91);
+        assert_eq!(crc32(data), 0xCBF4_3926);
+        assert_eq!(crc32_update(crc32(b"1234"), b"56789"), 0xCBF4_3926);
+    }
+}
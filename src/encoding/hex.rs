@@ -0,0 +1,126 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Hexadecimal encoding, two ASCII characters per byte.
+
+/// The lower case hexadecimal digits.
+const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Encodes binary data as lower case hexadecimal text.
+/// # Arguments
+/// * `input` - a reference to `[u8]`, the data to be encoded.
+/// * `output` - a mutable reference to `[u8]`, where the ASCII text is written.
+/// # Returns
+/// * `a Option<usize>` - the number of bytes written, `None` if `output` is too small.
+pub fn encode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    if output.len() < 2 * input.len() {
+        return None;
+    }
+    for (byte, out) in input.iter().zip(output.chunks_mut(2)) {
+        out[0] = DIGITS[(byte >> 4) as usize];
+        out[1] = DIGITS[(byte & 0x0f) as usize];
+    }
+    Some(2 * input.len())
+}
+
+/// Gives the value of one hexadecimal digit, either case is accepted.
+fn nibble(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes hexadecimal text back to binary data.
+/// # Arguments
+/// * `input` - a reference to `[u8]`, the ASCII text, of even length.
+/// * `output` - a mutable reference to `[u8]`, where the data is written.
+/// # Returns
+/// * `a Option<usize>` - the number of bytes written, `None` if the text is invalid or `output` is too small.
+pub fn decode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    if input.len() % 2 != 0 || output.len() < input.len() / 2 {
+        return None;
+    }
+    for (pair, out) in input.chunks(2).zip(output.iter_mut()) {
+        *out = (nibble(pair[0])? << 4) | nibble(pair[1])?;
+    }
+    Some(input.len() / 2)
+}
+
+/// Writes a hexadecimal dump of the data to a serial port, 16 bytes per line.
+/// Every line starts with the offset of its first byte.
+/// # Arguments
+/// * `data` - a reference to `[u8]`, the data to be printed.
+/// * `write` - a function which sends one byte, for example a USART `transmit_data`.
+pub fn dump<F: FnMut(u8)>(data: &[u8], mut write: F) {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let offset = (line * 16) as u16;
+        let mut text = [0; 4];
+        encode(&offset.to_be_bytes(), &mut text);
+        text.iter().for_each(|c| write(*c));
+        write(b':');
+        for byte in chunk {
+            write(b' ');
+            write(DIGITS[(byte >> 4) as usize]);
+            write(DIGITS[(byte & 0x0f) as usize]);
+        }
+        write(b'\r');
+        write(b'\n');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut text = [0; 8];
+        let mut data = [0; 4];
+        assert_eq!(encode(&[0x00, 0x7f, 0xa5, 0xff], &mut text), Some(8));
+        assert_eq!(&text, b"007fa5ff");
+        assert_eq!(decode(b"007FA5ff", &mut data), Some(4));
+        assert_eq!(data, [0x00, 0x7f, 0xa5, 0xff]);
+        assert_eq!(encode(&data, &mut text[..7]), None);
+        assert_eq!(decode(b"0a1b2c3d4e", &mut data), None);
+    }
+
+    #[test]
+    fn malformed_text() {
+        let mut data = [0; 4];
+        assert_eq!(decode(b"abc", &mut data), None);
+        assert_eq!(decode(b"0g", &mut data), None);
+        assert_eq!(decode(b":1", &mut data), None);
+        assert_eq!(decode(b"", &mut data), Some(0));
+    }
+
+    #[test]
+    fn dump_lines() {
+        let mut text = [0; 96];
+        let mut len = 0;
+        let data: [u8; 18] = core::array::from_fn(|i| i as u8);
+        dump(&data, |c| {
+            text[len] = c;
+            len += 1;
+        });
+        assert_eq!(
+            &text[..len],
+            &b"0000: 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\r\n0010: 10 11\r\n"[..]
+        );
+    }
+}
@@ -0,0 +1,373 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Morse code, as text of dots and dashes or keyed on an output pin.
+//! The `Keyer` switches a LED, a buzzer or any other `OutputPin` on and off
+//! without blocking, `Keyer::poll` being called from the main loop or a task
+//! of the scheduler, so a board without a serial port can still report its state.
+//! The timing follows the word PARIS: a dot lasts one unit of `1200 / wpm`
+//! milliseconds, a dash three, and the gaps are one unit between the symbols
+//! of a character, three between characters and seven between words.
+//! Letters of either case, digits and the usual punctuation are supported.
+
+use embedded_hal::digital::OutputPin;
+
+/// Packs the symbols of a character into a byte, read from the lowest bit,
+/// 1 for a dash and 0 for a dot, followed by a 1 marking the end.
+const fn pack(symbols: &[u8]) -> u8 {
+    let mut code = 1 << symbols.len();
+    let mut i = 0;
+    while i < symbols.len() {
+        if symbols[i] == b'-' {
+            code |= 1 << i;
+        }
+        i += 1;
+    }
+    code
+}
+
+/// The letters A to Z.
+const LETTERS: [u8; 26] = [
+    pack(b".-"),
+    pack(b"-..."),
+    pack(b"-.-."),
+    pack(b"-.."),
+    pack(b"."),
+    pack(b"..-."),
+    pack(b"--."),
+    pack(b"...."),
+    pack(b".."),
+    pack(b".---"),
+    pack(b"-.-"),
+    pack(b".-.."),
+    pack(b"--"),
+    pack(b"-."),
+    pack(b"---"),
+    pack(b".--."),
+    pack(b"--.-"),
+    pack(b".-."),
+    pack(b"..."),
+    pack(b"-"),
+    pack(b"..-"),
+    pack(b"...-"),
+    pack(b".--"),
+    pack(b"-..-"),
+    pack(b"-.--"),
+    pack(b"--.."),
+];
+
+/// The digits 0 to 9.
+const DIGITS: [u8; 10] = [
+    pack(b"-----"),
+    pack(b".----"),
+    pack(b"..---"),
+    pack(b"...--"),
+    pack(b"....-"),
+    pack(b"....."),
+    pack(b"-...."),
+    pack(b"--..."),
+    pack(b"---.."),
+    pack(b"----."),
+];
+
+/// Returns the packed symbols of a character.
+/// # Arguments
+/// * `c` - a u8, the ASCII character.
+/// # Returns
+/// * `a Option<u8>` - the symbols, `None` for spaces and characters without a code.
+fn code(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(LETTERS[(c - b'A') as usize]),
+        b'a'..=b'z' => Some(LETTERS[(c - b'a') as usize]),
+        b'0'..=b'9' => Some(DIGITS[(c - b'0') as usize]),
+        b'.' => Some(pack(b".-.-.-")),
+        b',' => Some(pack(b"--..--")),
+        b'?' => Some(pack(b"..--..")),
+        b'/' => Some(pack(b"-..-.")),
+        b'=' => Some(pack(b"-...-")),
+        b'+' => Some(pack(b".-.-.")),
+        b'-' => Some(pack(b"-....-")),
+        b'@' => Some(pack(b".--.-.")),
+        _ => None,
+    }
+}
+
+/// Returns the length of a unit for a given speed.
+/// # Arguments
+/// * `wpm` - a u8, the speed in words per minute.
+/// # Returns
+/// * `a u32` - the length of a dot in milliseconds.
+pub fn unit_ms(wpm: u8) -> u32 {
+    1200 / wpm.max(1) as u32
+}
+
+/// Encodes text as dots and dashes, the characters separated by a space
+/// and the words by ` / `.
+/// # Arguments
+/// * `input` - a reference to `[u8]`, the ASCII text.
+/// * `output` - a mutable reference to `[u8]`, where the dots and dashes are written.
+/// # Returns
+/// * `a Option<usize>` - the number of bytes written, `None` if a character has no code
+///   or `output` is too small.
+pub fn encode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut length = 0;
+    let mut separator: &[u8] = b"";
+    for &c in input {
+        if c == b' ' {
+            if length > 0 {
+                separator = b" / ";
+            }
+            continue;
+        }
+        let mut symbols = code(c)?;
+        for &byte in separator {
+            *output.get_mut(length)? = byte;
+            length += 1;
+        }
+        while symbols > 1 {
+            *output.get_mut(length)? = if symbols & 1 != 0 { b'-' } else { b'.' };
+            length += 1;
+            symbols >>= 1;
+        }
+        separator = b" ";
+    }
+    Some(length)
+}
+
+/// Keys an output pin with the Morse code of a text.
+/// # Elements
+/// * `pin` - a `OutputPin` object, high while the key is down.
+/// * `text` - a reference to `[u8]`, the ASCII text being sent.
+/// * `index` - a usize, the position of the next character in `text`.
+/// * `symbols` - a u8, the symbols of the current character not sent yet, packed like `pack`.
+/// * `unit` - a u32, the length of a dot in milliseconds.
+/// * `next` - the time of the next change of the pin in milliseconds, `None` to change it at the next poll.
+/// * `down` - a boolean, true while the key is down.
+/// * `busy` - a boolean, true while the text is being sent.
+/// * `repeat` - a boolean, true if the text is sent again and again, like a beacon.
+pub struct Keyer<'a, P> {
+    pin: P,
+    text: &'a [u8],
+    index: usize,
+    symbols: u8,
+    unit: u32,
+    next: Option<u32>,
+    down: bool,
+    busy: bool,
+    repeat: bool,
+}
+
+impl<'a, P: OutputPin> Keyer<'a, P> {
+    /// Creates a keyer with nothing to send, the pin being set low.
+    /// # Arguments
+    /// * `pin` - a `OutputPin` object, already an output, driving a LED or a buzzer.
+    /// * `wpm` - a u8, the speed in words per minute, 5 to 20 being easy to read.
+    /// # Returns
+    /// * `a Keyer object` - Which will be used for further implementations.
+    pub fn new(mut pin: P, wpm: u8) -> Keyer<'a, P> {
+        pin.set_low().ok();
+        Keyer {
+            pin,
+            text: b"",
+            index: 0,
+            symbols: 1,
+            unit: unit_ms(wpm),
+            next: None,
+            down: false,
+            busy: false,
+            repeat: false,
+        }
+    }
+
+    /// Changes the speed, from the next symbol on.
+    /// # Arguments
+    /// * `wpm` - a u8, the speed in words per minute.
+    pub fn set_wpm(&mut self, wpm: u8) {
+        self.unit = unit_ms(wpm);
+    }
+
+    /// Sends the text again and again, with a word gap in between, till `stop` is called.
+    pub fn set_repeat(&mut self, repeat: bool) {
+        self.repeat = repeat;
+    }
+
+    /// Starts sending a text, replacing the one being sent.
+    /// The characters without a code are skipped.
+    /// # Arguments
+    /// * `text` - a reference to `[u8]`, the ASCII text.
+    pub fn send(&mut self, text: &'a [u8]) {
+        self.stop();
+        self.text = text;
+        self.index = 0;
+        self.busy = self.load().is_some();
+    }
+
+    /// Stops sending and releases the key.
+    pub fn stop(&mut self) {
+        self.key(false);
+        self.busy = false;
+        self.symbols = 1;
+        self.next = None;
+    }
+
+    /// Returns true while a text is being sent.
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    /// Switches the pin when it is due.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, usually `millis()`.
+    /// # Returns
+    /// * `a boolean` - true while the text is being sent.
+    pub fn poll(&mut self, now: u32) -> bool {
+        if !self.busy {
+            return false;
+        }
+        if matches!(self.next, Some(next) if (now.wrapping_sub(next) as i32) < 0) {
+            return true;
+        }
+        let units = if self.down {
+            self.key(false);
+            if self.symbols > 1 {
+                1
+            } else {
+                match self.load() {
+                    Some(gap) => gap,
+                    None => {
+                        self.busy = false;
+                        return false;
+                    }
+                }
+            }
+        } else {
+            let dash = self.symbols & 1 != 0;
+            self.symbols >>= 1;
+            self.key(true);
+            if dash {
+                3
+            } else {
+                1
+            }
+        };
+        self.next = Some(now.wrapping_add(units * self.unit));
+        true
+    }
+
+    /// Gives back the pin, left low.
+    pub fn release(mut self) -> P {
+        self.stop();
+        self.pin
+    }
+
+    /// Sets the pin and remembers its state.
+    fn key(&mut self, down: bool) {
+        self.down = down;
+        if down {
+            self.pin.set_high().ok();
+        } else {
+            self.pin.set_low().ok();
+        }
+    }
+
+    /// Moves to the next character with a code, wrapping around when repeating.
+    /// # Returns
+    /// * `a Option<u32>` - the gap before the character in units, `None` at the end of the text.
+    fn load(&mut self) -> Option<u32> {
+        let mut gap = 3;
+        let mut wrapped = false;
+        loop {
+            if self.index >= self.text.len() {
+                // A text without any code is not repeated forever.
+                if !self.repeat || wrapped {
+                    return None;
+                }
+                self.index = 0;
+                wrapped = true;
+                gap = 7;
+            }
+            let c = self.text[self.index];
+            self.index += 1;
+            match code(c) {
+                Some(symbols) => {
+                    self.symbols = symbols;
+                    return Some(gap);
+                }
+                None if c == b' ' => gap = 7,
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_hal::digital::ErrorType;
+
+    struct Pin(bool);
+
+    impl ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin {
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0 = true;
+            Ok(())
+        }
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0 = false;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn encode_text() {
+        let mut out = [0u8; 32];
+        let n = encode(b"SOS hi", &mut out).unwrap();
+        assert_eq!(&out[..n], b"... --- ... / .... ..");
+        assert_eq!(encode(b"a#", &mut out), None);
+        assert_eq!(encode(b"0", &mut out[..4]), None);
+    }
+
+    #[test]
+    fn keying() {
+        // At 12 words per minute a unit is 100 ms, "E T" is a dot, a word gap and a dash.
+        let mut keyer = Keyer::new(Pin(true), 12);
+        assert!(!keyer.pin.0);
+        keyer.send(b"E T");
+        let mut changes = [0u32; 4];
+        let mut count = 0;
+        let mut level = false;
+        for now in (0..2000).step_by(10) {
+            keyer.poll(now);
+            if keyer.pin.0 != level {
+                level = keyer.pin.0;
+                changes[count] = now;
+                count += 1;
+            }
+        }
+        assert_eq!(changes, [0, 100, 800, 1100]);
+        assert!(!keyer.is_busy());
+
+        keyer.set_repeat(true);
+        keyer.send(b"#");
+        assert!(!keyer.is_busy());
+    }
+}
@@ -0,0 +1,237 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Parser for the RMC sentences of NMEA 0183 GPS receivers, which carry the
+//! position, speed and course of a fix. Values are converted to integers:
+//! coordinates in ten millionths of a degree, positive to the north and east,
+//! speeds in millimeters per second and courses in hundredths of a degree.
+//! Sentences with another type or a wrong checksum are ignored.
+
+/// Longest NMEA sentence, including the `$` and the checksum.
+pub const MAX_SENTENCE: usize = 82;
+
+/// A fix from an RMC sentence.
+/// # Elements
+/// * `time` - a u32, the UTC time of day in seconds.
+/// * `valid` - a boolean, false while the receiver has no fix.
+/// * `latitude` - a i32, in ten millionths of a degree.
+/// * `longitude` - a i32, in ten millionths of a degree.
+/// * `speed` - a u32, the speed over ground in millimeters per second.
+/// * `course` - a u16, the course over ground in hundredths of a degree clockwise from north.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Fix {
+    pub time: u32,
+    pub valid: bool,
+    pub latitude: i32,
+    pub longitude: i32,
+    pub speed: u32,
+    pub course: u16,
+}
+
+/// Gives the value of one hexadecimal digit of the checksum.
+fn hex(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Reads a decimal number, multiplied by 10 to the power `digits`,
+/// ignoring the decimals beyond `digits`.
+fn decimal(field: &[u8], digits: u32) -> Option<i64> {
+    let mut value: i64 = 0;
+    let mut decimals = None;
+    for &c in field {
+        match c {
+            b'.' if decimals.is_none() => decimals = Some(0),
+            b'0'..=b'9' => {
+                if decimals == Some(digits) {
+                    continue;
+                }
+                value = value * 10 + (c - b'0') as i64;
+                decimals = decimals.map(|d| d + 1);
+            }
+            _ => return None,
+        }
+    }
+    Some(value * 10i64.pow(digits - decimals.unwrap_or(0)))
+}
+
+/// Reads a coordinate written as degrees and minutes, `dddmm.mmmm`.
+fn coordinate(field: &[u8], hemisphere: &[u8]) -> Option<i32> {
+    let dot = field.iter().position(|c| *c == b'.').unwrap_or(field.len());
+    if dot < 2 {
+        return None;
+    }
+    let degrees = decimal(&field[..dot - 2], 0)?;
+    let minutes = decimal(&field[dot - 2..], 5)?;
+    let value = (degrees * 10_000_000 + minutes * 100 / 60) as i32;
+    match hemisphere {
+        b"N" | b"E" => Some(value),
+        b"S" | b"W" => Some(-value),
+        _ => None,
+    }
+}
+
+/// Checks the checksum of a sentence and returns its content between `$` and `*`.
+fn content(sentence: &[u8]) -> Option<&[u8]> {
+    let sentence = sentence.strip_suffix(b"\r\n").unwrap_or(sentence);
+    let star = sentence.iter().rposition(|c| *c == b'*')?;
+    if sentence.first() != Some(&b'$') || sentence.len() != star + 3 {
+        return None;
+    }
+    let expected = (hex(sentence[star + 1])? << 4) | hex(sentence[star + 2])?;
+    let body = &sentence[1..star];
+    if body.iter().fold(0, |sum, c| sum ^ c) != expected {
+        return None;
+    }
+    Some(body)
+}
+
+/// Parses an RMC sentence from any talker, like `$GPRMC` or `$GNRMC`.
+/// # Arguments
+/// * `sentence` - a reference to `[u8]`, the sentence with or without the line end.
+/// # Returns
+/// * `a Option<Fix>` - `None` for another sentence, a wrong checksum or a malformed field.
+pub fn parse_rmc(sentence: &[u8]) -> Option<Fix> {
+    let mut fields = content(sentence)?.split(|c| *c == b',');
+    let kind = fields.next()?;
+    if kind.len() != 5 || &kind[2..] != b"RMC" {
+        return None;
+    }
+    let time = fields.next()?;
+    let status = fields.next()?;
+    let (latitude, north) = (fields.next()?, fields.next()?);
+    let (longitude, east) = (fields.next()?, fields.next()?);
+    let (speed, course) = (fields.next()?, fields.next()?);
+
+    let mut fix = Fix {
+        valid: status == b"A",
+        ..Fix::default()
+    };
+    if time.len() >= 6 {
+        let clock = decimal(&time[..6], 0)? as u32;
+        fix.time = clock / 10_000 * 3600 + clock / 100 % 100 * 60 + clock % 100;
+    }
+    if fix.valid {
+        fix.latitude = coordinate(latitude, north)?;
+        fix.longitude = coordinate(longitude, east)?;
+        // One knot is 514.444 millimeters per second.
+        fix.speed = (decimal(speed, 3)? * 514_444 / 1_000_000) as u32;
+        fix.course = if course.is_empty() {
+            0
+        } else {
+            (decimal(course, 2)? % 36_000) as u16
+        };
+    }
+    Some(fix)
+}
+
+/// Collects sentences from the bytes received from the GPS.
+pub struct NmeaParser {
+    buffer: [u8; MAX_SENTENCE],
+    len: usize,
+}
+
+impl NmeaParser {
+    /// Creates an empty parser.
+    pub const fn new() -> NmeaParser {
+        NmeaParser {
+            buffer: [0; MAX_SENTENCE],
+            len: 0,
+        }
+    }
+
+    /// Handles one received byte.
+    /// # Arguments
+    /// * `byte` - a u8, the byte received.
+    /// # Returns
+    /// * `a Option<Fix>` - the fix of an RMC sentence ended by the byte.
+    pub fn push(&mut self, byte: u8) -> Option<Fix> {
+        match byte {
+            b'$' => {
+                self.buffer[0] = byte;
+                self.len = 1;
+                None
+            }
+            b'\r' | b'\n' => {
+                let len = core::mem::replace(&mut self.len, 0);
+                if len == 0 {
+                    return None;
+                }
+                parse_rmc(&self.buffer[..len])
+            }
+            _ if self.len > 0 && self.len < MAX_SENTENCE => {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+                None
+            }
+            // Too long or outside of a sentence.
+            _ => {
+                self.len = 0;
+                None
+            }
+        }
+    }
+}
+
+impl Default for NmeaParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SENTENCE: &[u8] =
+        b"$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n";
+
+    #[test]
+    fn parses_rmc() {
+        let fix = parse_rmc(SENTENCE).unwrap();
+        assert!(fix.valid);
+        assert_eq!(fix.time, 12 * 3600 + 35 * 60 + 19);
+        assert_eq!(fix.latitude, 481_173_000);
+        assert_eq!(fix.longitude, 115_166_666);
+        assert_eq!(fix.speed, 11_523);
+        assert_eq!(fix.course, 8440);
+
+        let mut parser = NmeaParser::new();
+        let fixes: usize = b"garbage\r\n"
+            .iter()
+            .chain(SENTENCE)
+            .filter_map(|b| parser.push(*b))
+            .count();
+        assert_eq!(fixes, 1);
+    }
+
+    #[test]
+    fn rejects_bad_sentences() {
+        let mut corrupted = [0; MAX_SENTENCE];
+        corrupted[..SENTENCE.len()].copy_from_slice(SENTENCE);
+        corrupted[20] = b'9';
+        assert_eq!(parse_rmc(&corrupted[..SENTENCE.len()]), None);
+        assert_eq!(
+            parse_rmc(b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47"),
+            None
+        );
+        let no_fix = parse_rmc(b"$GPRMC,000000,V,,,,,,,,,,N*53").unwrap();
+        assert!(!no_fix.valid);
+    }
+}
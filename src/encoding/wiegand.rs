@@ -0,0 +1,124 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Wiegand frames sent by access control card readers and keypads.
+//! A frame is a burst of bits, the first one received being the most
+//! significant. Card readers send 26 or 34 bit frames made of an even parity
+//! bit over the first half of the data, a facility code, a card number and an
+//! odd parity bit over the second half. Keypads send every key as a 4 bit
+//! frame, or as an 8 bit frame whose upper nibble is the complement of the key.
+
+/// The content of a Wiegand frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WiegandData {
+    /// A card, read from a 26 or 34 bit frame.
+    Card {
+        facility: u16,
+        number: u16,
+        bits: u8,
+    },
+    /// A key of a keypad, 0 to 9, 10 for `*` and 11 for `#`.
+    Key(u8),
+}
+
+/// Returns true if `value` has an odd number of set bits.
+fn odd_ones(value: u64) -> bool {
+    value.count_ones() % 2 == 1
+}
+
+/// Decodes a card frame whose facility code is `facility_bits` long.
+fn card(frame: u64, facility_bits: u8) -> Option<WiegandData> {
+    let bits = facility_bits + 18;
+    let half = (bits - 2) / 2;
+    let data = (frame >> 1) & ((1 << (bits - 2)) - 1);
+    let first_half = data >> half;
+    let second_half = data & ((1 << half) - 1);
+    let even = (frame >> (bits - 1)) & 1 != 0;
+    let odd = frame & 1 != 0;
+    if odd_ones(first_half) != even || odd_ones(second_half) == odd {
+        return None;
+    }
+    Some(WiegandData::Card {
+        facility: (data >> 16) as u16,
+        number: data as u16,
+        bits,
+    })
+}
+
+/// Decodes a received frame.
+/// # Arguments
+/// * `frame` - a u64, the bits in the order received, the last one in bit 0.
+/// * `bits` - a u8, the number of bits received.
+/// # Returns
+/// * `a Option<WiegandData>` - `None` for a wrong parity or an unknown frame length.
+pub fn decode(frame: u64, bits: u8) -> Option<WiegandData> {
+    match bits {
+        4 if frame < 12 => Some(WiegandData::Key(frame as u8)),
+        8 if (frame >> 4) ^ (frame & 0x0F) == 0x0F && frame & 0x0F < 12 => {
+            Some(WiegandData::Key((frame & 0x0F) as u8))
+        }
+        26 => card(frame, 8),
+        34 => card(frame, 16),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a card frame with correct parity bits.
+    fn frame(data: u64, bits: u8) -> u64 {
+        let half = (bits - 2) / 2;
+        let even = odd_ones(data >> half) as u64;
+        let odd = !odd_ones(data & ((1 << half) - 1)) as u64;
+        (even << (bits - 1)) | (data << 1) | odd
+    }
+
+    #[test]
+    fn decodes_cards() {
+        let w26 = frame((123 << 16) | 45678, 26);
+        assert_eq!(
+            decode(w26, 26),
+            Some(WiegandData::Card {
+                facility: 123,
+                number: 45678,
+                bits: 26
+            })
+        );
+        assert_eq!(decode(w26 ^ 1, 26), None);
+        assert_eq!(decode(w26 ^ (1 << 25), 26), None);
+        let w34 = frame((4321 << 16) | 1234, 34);
+        assert_eq!(
+            decode(w34, 34),
+            Some(WiegandData::Card {
+                facility: 4321,
+                number: 1234,
+                bits: 34
+            })
+        );
+        assert_eq!(decode(w34 ^ (1 << 20), 34), None);
+    }
+
+    #[test]
+    fn decodes_keys() {
+        assert_eq!(decode(7, 4), Some(WiegandData::Key(7)));
+        assert_eq!(decode(0x4B, 8), Some(WiegandData::Key(11)));
+        assert_eq!(decode(0x4A, 8), None);
+        assert_eq!(decode(12, 4), None);
+        assert_eq!(decode(0, 30), None);
+    }
+}
@@ -0,0 +1,145 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! The error type shared by the communication and sensor drivers.
+//! Drivers return `rustduino::Result` so that a missing device or a
+//! stuck bus can be handled by the application instead of halting it.
+
+/// Reasons for which a driver operation can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The addressed device did not acknowledge its address on the bus.
+    AddressNack,
+    /// The device did not acknowledge a data byte.
+    DataNack,
+    /// Another master took over the bus during the transfer.
+    ArbitrationLost,
+    /// The bus is held by another device.
+    BusBusy,
+    /// An illegal start or stop condition was seen on the bus.
+    BusError,
+    /// The hardware did not respond in the expected time.
+    Timeout,
+    /// The checksum of the received data does not match.
+    Crc,
+    /// A received frame had a wrong stop bit.
+    FrameError,
+    /// A received frame had a wrong parity bit.
+    ParityError,
+    /// A received byte was lost because the previous one was not read in time.
+    Overrun,
+    /// An argument is outside of the range supported by the hardware.
+    InvalidArgument,
+    /// The operation is not available in the mode the driver is in.
+    InvalidMode,
+    /// The given buffer cannot hold the data.
+    BufferTooSmall,
+    /// The device has not finished its previous operation.
+    NotReady,
+    /// The requested file or entry does not exist.
+    NotFound,
+    /// No free space is left on the storage.
+    StorageFull,
+    /// The data found on the storage is not in the expected format.
+    Corrupted,
+}
+
+impl Error {
+    /// Converts the error of any embedded-hal I2C bus, so that the drivers
+    /// which are generic over the bus still return this error type.
+    /// # Arguments
+    /// * `error` - the error returned by the bus.
+    /// # Returns
+    /// * `a Error` - The error with the same meaning, `BusError` if it has none.
+    pub fn from_i2c<E: embedded_hal::i2c::Error>(error: E) -> Error {
+        use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+        match error.kind() {
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => Error::DataNack,
+            ErrorKind::NoAcknowledge(_) => Error::AddressNack,
+            ErrorKind::ArbitrationLoss => Error::ArbitrationLost,
+            ErrorKind::Overrun => Error::Overrun,
+            _ => Error::BusError,
+        }
+    }
+}
+
+/// The result type returned by the drivers of this crate.
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl embedded_hal::i2c::Error for Error {
+    fn kind(&self) -> embedded_hal::i2c::ErrorKind {
+        use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+        match self {
+            Error::AddressNack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            Error::DataNack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            Error::ArbitrationLost => ErrorKind::ArbitrationLoss,
+            Error::BusError => ErrorKind::Bus,
+            Error::Overrun => ErrorKind::Overrun,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_hal::spi::Error for Error {
+    fn kind(&self) -> embedded_hal::spi::ErrorKind {
+        use embedded_hal::spi::ErrorKind;
+        match self {
+            Error::Overrun => ErrorKind::Overrun,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        use embedded_io::ErrorKind;
+        match self {
+            Error::Timeout => ErrorKind::TimedOut,
+            Error::FrameError | Error::ParityError | Error::Crc => ErrorKind::InvalidData,
+            Error::InvalidArgument => ErrorKind::InvalidInput,
+            Error::BufferTooSmall | Error::StorageFull => ErrorKind::OutOfMemory,
+            Error::NotFound => ErrorKind::NotFound,
+            Error::Corrupted => ErrorKind::InvalidData,
+            Error::NotReady | Error::BusBusy => ErrorKind::Interrupted,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_hal_nb::serial::Error for Error {
+    fn kind(&self) -> embedded_hal_nb::serial::ErrorKind {
+        use embedded_hal_nb::serial::ErrorKind;
+        match self {
+            Error::Overrun => ErrorKind::Overrun,
+            Error::FrameError => ErrorKind::FrameFormat,
+            Error::ParityError => ErrorKind::Parity,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_hal::pwm::Error for Error {
+    fn kind(&self) -> embedded_hal::pwm::ErrorKind {
+        embedded_hal::pwm::ErrorKind::Other
+    }
+}
+
+impl embedded_hal::digital::Error for Error {
+    fn kind(&self) -> embedded_hal::digital::ErrorKind {
+        embedded_hal::digital::ErrorKind::Other
+    }
+}
@@ -0,0 +1,359 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Experimental single threaded executor for `async` code.
+//! Futures of this module check their condition every time they are polled.
+//! The executor polls a task right away when it is woken and otherwise puts
+//! the MCU in idle sleep mode, re-polling every pending task after each interrupt.
+//! As the millisecond counter interrupts at least once every millisecond,
+//! timers and pin levels are noticed within about a millisecond while the
+//! CPU sleeps the rest of the time. `millis_init` must be called before use.
+
+use crate::collections::Queue;
+use crate::delay::delay_us;
+use crate::hal::interrupts;
+use crate::hal::millis::millis;
+use crate::hal::sleep_mode;
+use crate::{Error, Result};
+use core::future::Future;
+use core::pin::Pin;
+use core::ptr::read_volatile;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// Bit mask of the tasks which were woken and have to be polled.
+static mut WOKEN: u8 = 0;
+
+/// Marks a task as woken.
+fn wake_task(index: usize) {
+    interrupts::free(|| unsafe { WOKEN |= 1 << index });
+}
+
+/// Returns the mask of woken tasks and clears it.
+fn take_woken() -> u8 {
+    interrupts::free(|| unsafe {
+        let woken = WOKEN;
+        WOKEN = 0;
+        woken
+    })
+}
+
+/// Clones a waker, the data is the index of the task.
+fn waker_clone(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &VTABLE)
+}
+
+/// Wakes the task whose index is the data of the waker.
+fn waker_wake(data: *const ()) {
+    wake_task(data as usize);
+}
+
+/// Nothing has to be freed when a waker is dropped.
+fn waker_drop(_data: *const ()) {}
+
+static VTABLE: RawWakerVTable =
+    RawWakerVTable::new(waker_clone, waker_wake, waker_wake, waker_drop);
+
+/// Creates the waker of the task at `index`.
+fn waker(index: usize) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(index as *const (), &VTABLE)) }
+}
+
+/// Pins a future to the stack, so that it can be given to `Executor::spawn`.
+#[macro_export]
+macro_rules! pin_mut {
+    ($x:ident) => {
+        let mut $x = $x;
+        #[allow(unused_mut)]
+        let mut $x = unsafe { core::pin::Pin::new_unchecked(&mut $x) };
+    };
+}
+
+/// Runs a future to completion, sleeping while it is pending.
+/// # Arguments
+/// * `future` - a `Future`, the work to be done.
+/// # Returns
+/// * `the output of the future`.
+pub fn block_on<F: Future>(mut future: F) -> F::Output {
+    // The future is never moved after this point.
+    let mut future = unsafe { Pin::new_unchecked(&mut future) };
+    let waker = waker(0);
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        if take_woken() == 0 {
+            sleep_mode::idle();
+        }
+    }
+}
+
+/// An executor running at most `N` tasks concurrently, `N` can be at most 8.
+pub struct Executor<'a, const N: usize> {
+    tasks: [Option<Pin<&'a mut dyn Future<Output = ()>>>; N],
+}
+
+impl<'a, const N: usize> Executor<'a, N> {
+    /// An empty task slot.
+    const EMPTY: Option<Pin<&'a mut dyn Future<Output = ()>>> = None;
+
+    /// Creates a new executor without any task.
+    pub fn new() -> Self {
+        Executor {
+            tasks: [Self::EMPTY; N],
+        }
+    }
+
+    /// Adds a task to the executor, pin it with `pin_mut!` first.
+    /// # Arguments
+    /// * `task` - a pinned future, the task to be run.
+    /// # Returns
+    /// * `a boolean` - false if the executor already has `N` tasks.
+    pub fn spawn(&mut self, task: Pin<&'a mut dyn Future<Output = ()>>) -> bool {
+        match self.tasks.iter().position(|t| t.is_none()) {
+            Some(index) if index < 8 => {
+                self.tasks[index] = Some(task);
+                wake_task(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs all the tasks until every one of them has completed.
+    pub fn run(&mut self) {
+        // Every task is polled on the first pass.
+        let mut poll_mask: u8 = 0xFF;
+        loop {
+            poll_mask |= take_woken();
+            let mut pending = false;
+            for (index, slot) in self.tasks.iter_mut().enumerate() {
+                if let Some(task) = slot {
+                    pending = true;
+                    if poll_mask & (1 << index) == 0 {
+                        continue;
+                    }
+                    let waker = waker(index);
+                    let mut cx = Context::from_waker(&waker);
+                    if task.as_mut().poll(&mut cx).is_ready() {
+                        *slot = None;
+                    }
+                }
+            }
+            if !pending {
+                return;
+            }
+
+            poll_mask = take_woken();
+            if poll_mask == 0 {
+                sleep_mode::idle();
+                // Any interrupt may have made a pending future ready.
+                poll_mask = 0xFF;
+            }
+        }
+    }
+}
+
+impl<'a, const N: usize> Default for Executor<'a, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns true if the time `at` has been reached at time `now`.
+fn is_due(now: u32, at: u32) -> bool {
+    (now.wrapping_sub(at) as i32) >= 0
+}
+
+/// A future which completes at a given value of `millis`.
+pub struct Timer {
+    deadline: u32,
+}
+
+impl Timer {
+    /// Creates a timer which completes after `ms` milliseconds.
+    pub fn after(ms: u32) -> Timer {
+        Timer {
+            deadline: millis().wrapping_add(ms),
+        }
+    }
+
+    /// Creates a timer which completes when `millis` reaches `time`.
+    pub fn at(time: u32) -> Timer {
+        Timer { deadline: time }
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if is_due(millis(), self.deadline) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Delay provider for async drivers using `embedded_hal_async::delay::DelayNs`.
+/// Waits of a millisecond or more let the other tasks run, shorter
+/// ones are busy-waits as the millis counter cannot measure them.
+#[derive(Clone, Copy, Default)]
+pub struct AsyncDelay;
+
+impl embedded_hal_async::delay::DelayNs for AsyncDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        if ns >= 1_000_000 {
+            // One more, as the counter may be about to tick.
+            Timer::after(ns / 1_000_000 + 1).await;
+        } else {
+            delay_us(ns / 1000 + 1);
+        }
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        if us >= 1000 {
+            Timer::after(us / 1000 + 1).await;
+        } else {
+            delay_us(us);
+        }
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        Timer::after(ms + 1).await;
+    }
+}
+
+/// A future which completes when all the bits of `mask` are set in a register.
+/// The flags of the peripherals waited for do not raise interrupts, so the
+/// task wakes itself and is polled again after the other tasks instead
+/// of letting the MCU sleep.
+pub struct WaitForBits {
+    register: *const u8,
+    mask: u8,
+    deadline: u32,
+}
+
+impl WaitForBits {
+    /// Creates the future.
+    /// # Arguments
+    /// * `register` - a pointer to the I/O register to be read.
+    /// * `mask` - a u8, the bits which have to be set.
+    /// * `timeout` - a u32, milliseconds after which the future fails with `Timeout`.
+    /// # Safety
+    /// `register` must be the address of a memory mapped register.
+    pub unsafe fn new(register: *const u8, mask: u8, timeout: u32) -> WaitForBits {
+        WaitForBits {
+            register,
+            mask,
+            deadline: millis().wrapping_add(timeout),
+        }
+    }
+}
+
+impl Future for WaitForBits {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if unsafe { read_volatile(self.register) } & self.mask == self.mask {
+            Poll::Ready(Ok(()))
+        } else if is_due(millis(), self.deadline) {
+            Poll::Ready(Err(Error::Timeout))
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// A future which completes when a pin reaches the wanted level.
+pub struct WaitForLevel {
+    pinx: *const u8,
+    mask: u8,
+    high: bool,
+}
+
+impl Future for WaitForLevel {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        let level = unsafe { read_volatile(self.pinx) } & self.mask != 0;
+        if level == self.high {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl crate::hal::port::Pin {
+    /// Returns a future which completes when the pin is read as high.
+    pub fn wait_for_high(&self) -> WaitForLevel {
+        self.wait_for_level(true)
+    }
+
+    /// Returns a future which completes when the pin is read as low.
+    pub fn wait_for_low(&self) -> WaitForLevel {
+        self.wait_for_level(false)
+    }
+
+    /// Returns a future waiting for the given level on the PINx register.
+    fn wait_for_level(&self, high: bool) -> WaitForLevel {
+        let port = self.port;
+        WaitForLevel {
+            pinx: unsafe { &(*port).pin },
+            mask: 1 << self.pin,
+            high,
+        }
+    }
+}
+
+impl crate::hal::pin::DigitalPin {
+    /// Returns a future which completes when the pin is read as high.
+    pub fn wait_for_high(&self) -> WaitForLevel {
+        self.pin.wait_for_high()
+    }
+
+    /// Returns a future which completes when the pin is read as low.
+    pub fn wait_for_low(&self) -> WaitForLevel {
+        self.pin.wait_for_low()
+    }
+}
+
+/// A future which completes with the next byte of a USART reception buffer.
+pub struct ReadByte<'a, const N: usize> {
+    buffer: &'a Queue<u8, N>,
+}
+
+impl<'a, const N: usize> ReadByte<'a, N> {
+    /// Creates a future reading from the given reception buffer.
+    pub(crate) fn new(buffer: &'a Queue<u8, N>) -> Self {
+        ReadByte { buffer }
+    }
+}
+
+impl<'a, const N: usize> Future for ReadByte<'a, N> {
+    type Output = u8;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<u8> {
+        // Only the main program reads from the buffer.
+        match unsafe { self.buffer.dequeue() } {
+            Some(byte) => Poll::Ready(byte),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! The desktop side of the telemetry links, for ground station tools written
+//! in Rust which depend on this crate with the `std` feature and no chip.
+//! The messages are the very types of `net::telemetry` sent by the firmware,
+//! deriving `serde::Serialize` and `Deserialize` with this feature, so they
+//! can be logged as JSON or CSV without a second definition drifting apart.
+//! `Decoder` finds the frames in the bytes read from a serial port or a socket.
+
+use crate::net::telemetry::{self, FrameHeader, Parser, Telemetry, MAX_FRAME};
+use std::vec::Vec;
+
+pub use crate::net::telemetry::{
+    Attitude, BatteryStatus, Environment, Heartbeat, Position, RawImu, TelemetryMessage,
+};
+
+/// A message received, with its sender.
+/// # Elements
+/// * `header` - a `FrameHeader`, the sender and sequence number.
+/// * `telemetry` - a `Telemetry`, the message.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Received {
+    pub header: FrameHeader,
+    pub telemetry: Telemetry,
+}
+
+/// Finds the standard messages in a stream of bytes.
+/// # Elements
+/// * `parser` - a `Parser`, the frame parser of the firmware.
+/// * `unknown` - a u32, the number of valid frames holding other messages.
+pub struct Decoder {
+    parser: Parser,
+    unknown: u32,
+}
+
+impl Decoder {
+    /// Creates a decoder for the standard messages.
+    pub fn new() -> Decoder {
+        Decoder::with_registry(telemetry::crc_extra)
+    }
+
+    /// Creates a decoder also checking application specific messages,
+    /// which are counted by `unknown` as they cannot be decoded.
+    /// # Arguments
+    /// * `registry` - a function giving the `CRC_EXTRA` of a message id, see `Parser::with_registry`.
+    pub fn with_registry(registry: fn(u8) -> Option<u8>) -> Decoder {
+        Decoder {
+            parser: Parser::with_registry(registry),
+            unknown: 0,
+        }
+    }
+
+    /// Handles received bytes, which may hold parts of frames.
+    /// # Arguments
+    /// * `bytes` - a reference to `[u8]`, the bytes received.
+    /// # Returns
+    /// * `a Vec<Received>` - the messages completed by the bytes.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Received> {
+        let mut received = Vec::new();
+        for byte in bytes {
+            if let Some(frame) = self.parser.push(*byte) {
+                match Telemetry::from_frame(&frame) {
+                    Some(telemetry) => received.push(Received {
+                        header: frame.header,
+                        telemetry,
+                    }),
+                    None => self.unknown += 1,
+                }
+            }
+        }
+        received
+    }
+
+    /// Returns the number of frames dropped for a wrong CRC or an unknown message id.
+    pub fn errors(&self) -> u16 {
+        self.parser.errors()
+    }
+
+    /// Returns the number of valid frames holding messages other than the standard ones.
+    pub fn unknown(&self) -> u32 {
+        self.unknown
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encodes a message into a frame, for simulators and tests of the ground station.
+/// # Arguments
+/// * `received` - a reference to `Received`, the message and its sender.
+/// # Returns
+/// * `a Vec<u8>` - the frame, as sent by the firmware.
+pub fn encode(received: &Received) -> Vec<u8> {
+    let mut frame = [0; MAX_FRAME];
+    let header = received.header;
+    let len = match &received.telemetry {
+        Telemetry::Heartbeat(message) => telemetry::encode(message, header, &mut frame),
+        Telemetry::RawImu(message) => telemetry::encode(message, header, &mut frame),
+        Telemetry::Attitude(message) => telemetry::encode(message, header, &mut frame),
+        Telemetry::Position(message) => telemetry::encode(message, header, &mut frame),
+        Telemetry::BatteryStatus(message) => telemetry::encode(message, header, &mut frame),
+        Telemetry::Environment(message) => telemetry::encode(message, header, &mut frame),
+    };
+    // Every standard message fits in `MAX_FRAME`.
+    frame[..len.unwrap_or(0)].to_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let message = Received {
+            header: FrameHeader {
+                sequence: 3,
+                system: 1,
+                component: 0,
+            },
+            telemetry: Telemetry::Heartbeat(Heartbeat {
+                uptime: 60_000,
+                mode: 2,
+                status: 1,
+            }),
+        };
+        let mut bytes = vec![0x00, 0x55];
+        bytes.extend(encode(&message));
+        let mut decoder = Decoder::new();
+        let (first, second) = bytes.split_at(5);
+        assert!(decoder.push(first).is_empty());
+        assert_eq!(decoder.push(second), vec![message]);
+        assert_eq!(decoder.errors(), 0);
+    }
+}
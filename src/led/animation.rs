@@ -0,0 +1,387 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Frame based LED animations. An `Effect` renders one frame into a buffer
+//! from the frame number, and an `Animator` composes up to `L` effects as
+//! layers, each one replacing, adding to or mixing with the layers below,
+//! at a fixed frame rate. `Animator::poll` is meant to be called from the
+//! main loop or a periodic task and tells when a new frame is ready to be
+//! sent to the strip.
+
+use super::color::{fill, fill_rainbow, scale8, Rgb};
+
+/// Something drawn on the strip.
+pub trait Effect {
+    /// Draws one frame.
+    /// # Arguments
+    /// * `leds` - a mutable reference to `[Rgb]`, cleared to black before the call.
+    /// * `frame` - a u32, the number of the frame, counting from 0.
+    fn render(&mut self, leds: &mut [Rgb], frame: u32);
+}
+
+/// How a layer is combined with the layers below it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Blend {
+    /// The layer replaces the LEDs it lights, black LEDs let the layers below show.
+    Replace,
+    /// The colors are added, saturating.
+    Add,
+    /// The layer is mixed in, by the given amount in 256ths.
+    Mix(u8),
+}
+
+/// Fades back and forth between two colors.
+pub struct Fade {
+    pub from: Rgb,
+    pub to: Rgb,
+    /// Number of frames of a fade in one direction.
+    pub frames: u16,
+}
+
+impl Effect for Fade {
+    fn render(&mut self, leds: &mut [Rgb], frame: u32) {
+        let frames = self.frames.max(1) as u32;
+        let position = frame % (2 * frames);
+        let position = if position > frames {
+            2 * frames - position
+        } else {
+            position
+        };
+        fill(
+            leds,
+            self.from.blend(self.to, (position * 255 / frames) as u8),
+        );
+    }
+}
+
+/// Segments of light running along the strip.
+pub struct Chase {
+    pub color: Rgb,
+    /// Number of lit LEDs per segment.
+    pub width: u8,
+    /// Distance between the starts of two segments, 0 for a single segment.
+    pub spacing: u8,
+    /// Number of frames per step of one LED.
+    pub frames_per_step: u8,
+}
+
+impl Effect for Chase {
+    fn render(&mut self, leds: &mut [Rgb], frame: u32) {
+        if leds.is_empty() {
+            return;
+        }
+        let period = if self.spacing == 0 {
+            leds.len()
+        } else {
+            self.spacing as usize
+        };
+        let offset = (frame / self.frames_per_step.max(1) as u32) as usize % period;
+        for (i, led) in leds.iter_mut().enumerate() {
+            if (i + period - offset) % period < self.width as usize {
+                *led = self.color;
+            }
+        }
+    }
+}
+
+/// A rainbow moving along the strip.
+pub struct Rainbow {
+    /// Hue difference between neighbouring LEDs.
+    pub step: u8,
+    /// Hue change per frame.
+    pub speed: u8,
+    /// Brightness in 256ths.
+    pub brightness: u8,
+}
+
+impl Effect for Rainbow {
+    fn render(&mut self, leds: &mut [Rgb], frame: u32) {
+        fill_rainbow(leds, (frame as u8).wrapping_mul(self.speed), self.step);
+        if self.brightness != 255 {
+            for led in leds.iter_mut() {
+                *led = led.scale(self.brightness);
+            }
+        }
+    }
+}
+
+/// Number of sparkles shown at the same time.
+const SPARKLES: usize = 8;
+
+/// Random LEDs flashing up and fading out.
+pub struct Sparkle {
+    color: Rgb,
+    /// Chance of a new sparkle per frame, in 256ths.
+    density: u8,
+    /// Brightness lost per frame.
+    decay: u8,
+    seed: u16,
+    /// Position and brightness of the sparkles.
+    sparkles: [(u16, u8); SPARKLES],
+}
+
+impl Sparkle {
+    /// Creates the effect.
+    /// # Arguments
+    /// * `color` - a `Rgb`, the color of the sparkles.
+    /// * `density` - a u8, the chance of a new sparkle per frame in 256ths.
+    /// * `decay` - a u8, the brightness lost by a sparkle per frame.
+    /// * `seed` - a u16, the start of the pseudo random sequence, not 0.
+    pub fn new(color: Rgb, density: u8, decay: u8, seed: u16) -> Sparkle {
+        Sparkle {
+            color,
+            density,
+            decay,
+            seed: seed.max(1),
+            sparkles: [(0, 0); SPARKLES],
+        }
+    }
+
+    /// Returns the next value of a 16 bit xorshift generator.
+    fn random(&mut self) -> u16 {
+        self.seed ^= self.seed << 7;
+        self.seed ^= self.seed >> 9;
+        self.seed ^= self.seed << 8;
+        self.seed
+    }
+}
+
+impl Effect for Sparkle {
+    fn render(&mut self, leds: &mut [Rgb], _frame: u32) {
+        for sparkle in self.sparkles.iter_mut() {
+            sparkle.1 = sparkle.1.saturating_sub(self.decay);
+        }
+        if !leds.is_empty() && (self.random() as u8) < self.density {
+            let position = self.random() % leds.len() as u16;
+            if let Some(slot) = self.sparkles.iter_mut().min_by_key(|s| s.1) {
+                *slot = (position, 255);
+            }
+        }
+        for &(position, brightness) in self.sparkles.iter() {
+            if let Some(led) = leds.get_mut(position as usize) {
+                if brightness > 0 {
+                    *led = led.add(self.color.scale(brightness));
+                }
+            }
+        }
+    }
+}
+
+/// An effect with the way it is combined.
+struct Layer<'a> {
+    effect: &'a mut dyn Effect,
+    blend: Blend,
+    enabled: bool,
+}
+
+/// Composes up to `L` effects on a strip of `N` LEDs.
+pub struct Animator<'a, const N: usize, const L: usize> {
+    layers: [Option<Layer<'a>>; L],
+    scratch: [Rgb; N],
+    frame: u32,
+    /// Milliseconds between frames.
+    period: u32,
+    next: u32,
+    brightness: u8,
+}
+
+impl<'a, const N: usize, const L: usize> Animator<'a, N, L> {
+    /// An empty layer slot.
+    const EMPTY: Option<Layer<'a>> = None;
+
+    /// Creates an animator without layers, running at 50 frames per second.
+    pub fn new() -> Self {
+        Animator {
+            layers: [Self::EMPTY; L],
+            scratch: [Rgb::BLACK; N],
+            frame: 0,
+            period: 20,
+            next: 0,
+            brightness: 255,
+        }
+    }
+
+    /// Sets the frame rate.
+    /// # Arguments
+    /// * `fps` - a u8, the number of frames per second.
+    pub fn set_frame_rate(&mut self, fps: u8) {
+        self.period = 1000 / fps.max(1) as u32;
+    }
+
+    /// Sets the brightness applied to the whole strip, in 256ths.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    /// Adds a layer on top of the others.
+    /// # Arguments
+    /// * `effect` - a mutable reference to an `Effect`, the effect drawn.
+    /// * `blend` - a `Blend`, how the layer is combined with the layers below.
+    /// # Returns
+    /// * `a Option<usize>` - the index of the layer, `None` if all the layers are used.
+    pub fn push(&mut self, effect: &'a mut dyn Effect, blend: Blend) -> Option<usize> {
+        let index = self.layers.iter().position(|l| l.is_none())?;
+        self.layers[index] = Some(Layer {
+            effect,
+            blend,
+            enabled: true,
+        });
+        Some(index)
+    }
+
+    /// Removes a layer.
+    pub fn remove(&mut self, index: usize) {
+        if let Some(layer) = self.layers.get_mut(index) {
+            *layer = None;
+        }
+    }
+
+    /// Shows or hides a layer without removing it.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(Some(layer)) = self.layers.get_mut(index) {
+            layer.enabled = enabled;
+        }
+    }
+
+    /// Returns the number of the next frame.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Renders the next frame, whatever the time.
+    /// # Arguments
+    /// * `leds` - a mutable reference to the strip buffer, sent to the LEDs afterwards.
+    pub fn render(&mut self, leds: &mut [Rgb; N]) {
+        fill(leds, Rgb::BLACK);
+        for layer in self.layers.iter_mut().flatten().filter(|l| l.enabled) {
+            fill(&mut self.scratch, Rgb::BLACK);
+            layer.effect.render(&mut self.scratch, self.frame);
+            for (led, drawn) in leds.iter_mut().zip(self.scratch.iter()) {
+                *led = match layer.blend {
+                    Blend::Replace if *drawn == Rgb::BLACK => *led,
+                    Blend::Replace => *drawn,
+                    Blend::Add => led.add(*drawn),
+                    Blend::Mix(amount) => led.blend(*drawn, amount),
+                };
+            }
+        }
+        if self.brightness != 255 {
+            for led in leds.iter_mut() {
+                *led = Rgb::new(
+                    scale8(led.r, self.brightness),
+                    scale8(led.g, self.brightness),
+                    scale8(led.b, self.brightness),
+                );
+            }
+        }
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// Renders a frame if it is due.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, usually `millis()`.
+    /// * `leds` - a mutable reference to the strip buffer.
+    /// # Returns
+    /// * `a boolean` - true if a new frame was rendered and should be sent to the LEDs.
+    pub fn poll(&mut self, now: u32, leds: &mut [Rgb; N]) -> bool {
+        if (now.wrapping_sub(self.next) as i32) < 0 {
+            return false;
+        }
+        self.next = self.next.wrapping_add(self.period);
+        // Frames missed by a busy main loop are skipped.
+        if (now.wrapping_sub(self.next) as i32) >= 0 {
+            self.next = now.wrapping_add(self.period);
+        }
+        self.render(leds);
+        true
+    }
+}
+
+impl<'a, const N: usize, const L: usize> Default for Animator<'a, N, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chase_moves_and_layers_compose() {
+        let mut chase = Chase {
+            color: Rgb::RED,
+            width: 1,
+            spacing: 4,
+            frames_per_step: 1,
+        };
+        let mut fade = Fade {
+            from: Rgb::BLACK,
+            to: Rgb::BLUE,
+            frames: 2,
+        };
+        let mut animator: Animator<8, 2> = Animator::new();
+        animator.push(&mut fade, Blend::Replace).unwrap();
+        animator.push(&mut chase, Blend::Add).unwrap();
+        let mut leds = [Rgb::BLACK; 8];
+
+        animator.render(&mut leds);
+        assert_eq!(leds[0], Rgb::RED);
+        assert_eq!(leds[1], Rgb::BLACK);
+        assert_eq!(leds[4], Rgb::RED);
+
+        animator.render(&mut leds);
+        assert_eq!(leds[1], Rgb::new(255, 0, 127));
+        assert_eq!(leds[2], Rgb::new(0, 0, 127));
+
+        animator.render(&mut leds);
+        assert_eq!(leds[0], Rgb::BLUE);
+        assert_eq!(leds[2], Rgb::new(255, 0, 255));
+    }
+
+    #[test]
+    fn frames_follow_the_frame_rate() {
+        let mut rainbow = Rainbow {
+            step: 32,
+            speed: 1,
+            brightness: 255,
+        };
+        let mut animator: Animator<4, 1> = Animator::new();
+        animator.set_frame_rate(10);
+        animator.push(&mut rainbow, Blend::Replace);
+        let mut leds = [Rgb::BLACK; 4];
+        assert!(animator.poll(0, &mut leds));
+        assert!(!animator.poll(99, &mut leds));
+        assert!(animator.poll(100, &mut leds));
+        assert!(animator.poll(450, &mut leds));
+        assert!(!animator.poll(549, &mut leds));
+        assert_eq!(animator.frame(), 3);
+    }
+
+    #[test]
+    fn sparkles_fade_out() {
+        let mut sparkle = Sparkle::new(Rgb::WHITE, 255, 64, 1234);
+        let mut leds = [Rgb::BLACK; 16];
+        sparkle.render(&mut leds, 0);
+        assert_eq!(leds.iter().filter(|l| **l == Rgb::WHITE).count(), 1);
+        sparkle.density = 0;
+        for frame in 1..5 {
+            leds = [Rgb::BLACK; 16];
+            sparkle.render(&mut leds, frame);
+        }
+        assert!(leds.iter().all(|l| *l == Rgb::BLACK));
+    }
+}
@@ -0,0 +1,292 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Color math for addressable LED strips, shared by the strip drivers and the
+//! animations. Everything is 8 bit integer math: hues go around the color wheel
+//! from 0 to 255, brightness and blending amounts are in 256ths. The gamma and
+//! color temperature tables are kept in program memory.
+
+use crate::progmem;
+
+/// A color as sent to the LEDs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// A color given by its hue, saturation and value, all from 0 to 255.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Hsv {
+    pub h: u8,
+    pub s: u8,
+    pub v: u8,
+}
+
+progmem! {
+    /// Gamma correction with an exponent of 2.8, so that brightness steps look even.
+    static GAMMA: [u8; 256] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2,
+        2, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 5, 5, 5,
+        5, 6, 6, 6, 6, 7, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10,
+        10, 10, 11, 11, 11, 12, 12, 13, 13, 13, 14, 14, 15, 15, 16, 16,
+        17, 17, 18, 18, 19, 19, 20, 20, 21, 21, 22, 22, 23, 24, 24, 25,
+        25, 26, 27, 27, 28, 29, 29, 30, 31, 32, 32, 33, 34, 35, 35, 36,
+        37, 38, 39, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 50,
+        51, 52, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 66, 67, 68,
+        69, 70, 72, 73, 74, 75, 77, 78, 79, 81, 82, 83, 85, 86, 87, 89,
+        90, 92, 93, 95, 96, 98, 99, 101, 102, 104, 105, 107, 109, 110, 112, 114,
+        115, 117, 119, 120, 122, 124, 126, 127, 129, 131, 133, 135, 137, 138, 140, 142,
+        144, 146, 148, 150, 152, 154, 156, 158, 160, 162, 164, 167, 169, 171, 173, 175,
+        177, 180, 182, 184, 186, 189, 191, 193, 196, 198, 200, 203, 205, 208, 210, 213,
+        215, 218, 220, 223, 225, 228, 231, 233, 236, 239, 241, 244, 247, 249, 252, 255,
+    ];
+
+    /// Color of a black body from 1000 K to 12000 K, every 500 K.
+    static TEMPERATURE: [[u8; 3]; 23] = [
+        [255, 68, 0], [255, 108, 0], [255, 137, 14], [255, 159, 70], [255, 177, 110], [255, 193, 141],
+        [255, 206, 166], [255, 218, 187], [255, 228, 206], [255, 237, 222], [255, 246, 237], [255, 254, 250],
+        [243, 242, 255], [230, 235, 255], [221, 230, 255], [215, 226, 255], [210, 223, 255], [205, 220, 255],
+        [202, 218, 255], [199, 216, 255], [196, 214, 255], [193, 213, 255], [191, 211, 255],
+    ];
+}
+
+/// Scales an 8 bit value by `scale` 256ths, 255 keeping it as it is.
+pub fn scale8(value: u8, scale: u8) -> u8 {
+    ((value as u16 * (scale as u16 + 1)) >> 8) as u8
+}
+
+/// Mixes two 8 bit values, giving `a` for an `amount` of 0 and `b` for 255.
+pub fn lerp8(a: u8, b: u8, amount: u8) -> u8 {
+    let delta = b as i32 - a as i32;
+    (a as i32 + delta * amount as i32 / 255) as u8
+}
+
+/// Applies the gamma correction to a brightness.
+pub fn gamma8(value: u8) -> u8 {
+    GAMMA.get(value as usize).unwrap_or(value)
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+    pub const RED: Rgb = Rgb::new(255, 0, 0);
+    pub const GREEN: Rgb = Rgb::new(0, 255, 0);
+    pub const BLUE: Rgb = Rgb::new(0, 0, 255);
+
+    /// Creates a color from its components.
+    pub const fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    /// Dims the color.
+    /// # Arguments
+    /// * `brightness` - a u8, in 256ths, 255 keeping the color as it is.
+    pub fn scale(&self, brightness: u8) -> Rgb {
+        Rgb::new(
+            scale8(self.r, brightness),
+            scale8(self.g, brightness),
+            scale8(self.b, brightness),
+        )
+    }
+
+    /// Mixes with another color.
+    /// # Arguments
+    /// * `other` - a `Rgb`, the color mixed in.
+    /// * `amount` - a u8, the share of `other` in 256ths.
+    pub fn blend(&self, other: Rgb, amount: u8) -> Rgb {
+        Rgb::new(
+            lerp8(self.r, other.r, amount),
+            lerp8(self.g, other.g, amount),
+            lerp8(self.b, other.b, amount),
+        )
+    }
+
+    /// Adds another color, saturating every component, to layer light.
+    pub fn add(&self, other: Rgb) -> Rgb {
+        Rgb::new(
+            self.r.saturating_add(other.r),
+            self.g.saturating_add(other.g),
+            self.b.saturating_add(other.b),
+        )
+    }
+
+    /// Applies the gamma correction to every component, right before sending the color out.
+    pub fn gamma(&self) -> Rgb {
+        Rgb::new(gamma8(self.r), gamma8(self.g), gamma8(self.b))
+    }
+
+    /// Returns the color of a black body, the white of an incandescent lamp or of daylight.
+    /// # Arguments
+    /// * `kelvin` - a u16, the temperature, limited to 1000 K to 12000 K.
+    pub fn from_temperature(kelvin: u16) -> Rgb {
+        let kelvin = kelvin.clamp(1000, 12000) - 1000;
+        let index = (kelvin / 500) as usize;
+        let amount = ((kelvin % 500) as u32 * 255 / 500) as u8;
+        let low = TEMPERATURE.get(index).unwrap_or([255; 3]);
+        let high = TEMPERATURE.get(index + 1).unwrap_or(low);
+        Rgb::new(low[0], low[1], low[2]).blend(Rgb::new(high[0], high[1], high[2]), amount)
+    }
+}
+
+impl From<Hsv> for Rgb {
+    /// Converts around the color wheel in six sectors of equal width.
+    fn from(hsv: Hsv) -> Rgb {
+        let sector = hsv.h / 43;
+        let position = (hsv.h - sector * 43) * 6;
+        let low = scale8(hsv.v, 255 - hsv.s);
+        let falling = scale8(hsv.v, 255 - scale8(hsv.s, position));
+        let rising = scale8(hsv.v, 255 - scale8(hsv.s, 255 - position));
+        match sector {
+            0 => Rgb::new(hsv.v, rising, low),
+            1 => Rgb::new(falling, hsv.v, low),
+            2 => Rgb::new(low, hsv.v, rising),
+            3 => Rgb::new(low, falling, hsv.v),
+            4 => Rgb::new(rising, low, hsv.v),
+            _ => Rgb::new(hsv.v, low, falling),
+        }
+    }
+}
+
+impl Hsv {
+    /// Creates a color from its hue, saturation and value.
+    pub const fn new(h: u8, s: u8, v: u8) -> Hsv {
+        Hsv { h, s, v }
+    }
+}
+
+/// Sets every LED of a strip to one color.
+pub fn fill(leds: &mut [Rgb], color: Rgb) {
+    for led in leds.iter_mut() {
+        *led = color;
+    }
+}
+
+/// Paints a rainbow over a strip.
+/// # Arguments
+/// * `leds` - a mutable reference to `[Rgb]`, the strip.
+/// * `hue` - a u8, the hue of the first LED.
+/// * `step` - a u8, the hue added from one LED to the next.
+pub fn fill_rainbow(leds: &mut [Rgb], hue: u8, step: u8) {
+    let mut h = hue;
+    for led in leds.iter_mut() {
+        *led = Hsv::new(h, 255, 255).into();
+        h = h.wrapping_add(step);
+    }
+}
+
+/// Dims every LED of a strip, for trails fading out.
+/// # Arguments
+/// * `leds` - a mutable reference to `[Rgb]`, the strip.
+/// * `amount` - a u8, how much is taken off in 256ths.
+pub fn fade_to_black(leds: &mut [Rgb], amount: u8) {
+    for led in leds.iter_mut() {
+        *led = led.scale(255 - amount);
+    }
+}
+
+/// Mixes every LED of a strip towards the matching LED of another one.
+/// # Arguments
+/// * `leds` - a mutable reference to `[Rgb]`, the strip changed.
+/// * `other` - a reference to `[Rgb]`, the colors mixed in.
+/// * `amount` - a u8, the share of `other` in 256ths.
+pub fn blend_into(leds: &mut [Rgb], other: &[Rgb], amount: u8) {
+    for (led, other) in leds.iter_mut().zip(other) {
+        *led = led.blend(*other, amount);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hsv_primaries() {
+        assert_eq!(Rgb::from(Hsv::new(0, 255, 255)), Rgb::RED);
+        assert_eq!(Rgb::from(Hsv::new(86, 255, 255)), Rgb::GREEN);
+        assert_eq!(Rgb::from(Hsv::new(172, 255, 255)), Rgb::BLUE);
+        assert_eq!(Rgb::from(Hsv::new(123, 0, 200)), Rgb::new(200, 200, 200));
+        assert_eq!(Rgb::from(Hsv::new(50, 255, 0)), Rgb::BLACK);
+    }
+
+    #[test]
+    fn scaling_and_blending() {
+        assert_eq!(Rgb::WHITE.scale(255), Rgb::WHITE);
+        assert_eq!(Rgb::WHITE.scale(127), Rgb::new(127, 127, 127));
+        assert_eq!(Rgb::RED.blend(Rgb::BLUE, 0), Rgb::RED);
+        assert_eq!(Rgb::RED.blend(Rgb::BLUE, 255), Rgb::BLUE);
+        assert_eq!(Rgb::RED.add(Rgb::new(10, 20, 30)), Rgb::new(255, 20, 30));
+        let mut leds = [Rgb::WHITE; 3];
+        fade_to_black(&mut leds, 255);
+        assert_eq!(leds, [Rgb::BLACK; 3]);
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(gamma8(0), 0);
+        assert_eq!(gamma8(255), 255);
+        assert!(gamma8(128) < 64);
+        assert_eq!(Rgb::from_temperature(6000), Rgb::new(255, 246, 237));
+        assert!(Rgb::from_temperature(2000).b < 50);
+        assert!(Rgb::from_temperature(12000).b == 255);
+    }
+
+    #[test]
+    fn helpers() {
+        assert_eq!(scale8(200, 0), 0);
+        assert_eq!(scale8(200, 255), 200);
+        assert_eq!(scale8(200, 127), 100);
+        assert_eq!(lerp8(10, 20, 0), 10);
+        assert_eq!(lerp8(10, 20, 255), 20);
+        assert_eq!(lerp8(20, 10, 255), 10);
+        assert_eq!(lerp8(0, 255, 128), 128);
+
+        // The gamma curve never gets darker as the input rises.
+        let mut last = 0;
+        for value in 0..=255 {
+            let corrected = gamma8(value);
+            assert!(corrected >= last && corrected <= value);
+            last = corrected;
+        }
+        assert_eq!(Rgb::new(0, 128, 255).gamma(), Rgb::new(0, gamma8(128), 255));
+    }
+
+    #[test]
+    fn temperature_interpolation() {
+        // Halfway between the entries for 6000 K and 6500 K, and clamped outside the table.
+        let halfway = Rgb::new(255, 246, 237).blend(Rgb::new(255, 254, 250), 127);
+        assert_eq!(Rgb::from_temperature(6250), halfway);
+        assert_eq!(Rgb::from_temperature(500), Rgb::new(255, 68, 0));
+        assert_eq!(Rgb::from_temperature(40000), Rgb::new(191, 211, 255));
+    }
+
+    #[test]
+    fn strips() {
+        let mut leds = [Rgb::BLACK; 4];
+        fill_rainbow(&mut leds, 0, 86);
+        assert_eq!(leds[..3], [Rgb::RED, Rgb::GREEN, Rgb::BLUE]);
+        assert_eq!(leds[3], Rgb::from(Hsv::new(2, 255, 255)));
+
+        fill(&mut leds, Rgb::WHITE);
+        blend_into(&mut leds[..2], &[Rgb::BLACK; 2], 255);
+        assert_eq!(leds, [Rgb::BLACK, Rgb::BLACK, Rgb::WHITE, Rgb::WHITE]);
+        fade_to_black(&mut leds, 0);
+        assert_eq!(leds[2], Rgb::WHITE);
+    }
+}
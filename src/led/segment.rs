@@ -0,0 +1,386 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Text and numbers on seven segment displays. The formatting works on
+//! plain segment bytes, so the same code drives a TM1637, a MAX7219 or
+//! digits wired to shift registers: a driver only implements
+//! `SegmentDisplay::write_segments` and gets the `show_*` functions.
+//!
+//! A segment byte has segment a in bit 0 up to segment g in bit 6 and the
+//! decimal point in bit 7, the order used by the TM1637. `to_max7219` and
+//! `to_msb_first` convert it for the other wirings.
+
+use crate::{Error, Result};
+
+/// Segments of a blank digit.
+pub const BLANK: u8 = 0;
+
+/// Segment of the decimal point.
+pub const DOT: u8 = 0x80;
+
+/// Segments of a minus sign.
+pub const MINUS: u8 = 0x40;
+
+/// Maximum number of digits handled by the `show_*` functions.
+pub const MAX_DIGITS: usize = 16;
+
+/// Returns the segments showing a character, blank for the ones which cannot be shown.
+/// Letters are shown in the case which is readable on seven segments.
+/// # Arguments
+/// * `c` - a u8, the ASCII character.
+pub const fn encode(c: u8) -> u8 {
+    match c {
+        b'0' | b'O' => 0x3F,
+        b'1' | b'I' => 0x06,
+        b'2' | b'Z' | b'z' => 0x5B,
+        b'3' => 0x4F,
+        b'4' => 0x66,
+        b'5' | b'S' | b's' => 0x6D,
+        b'6' => 0x7D,
+        b'7' => 0x07,
+        b'8' | b'B' => 0x7F,
+        b'9' | b'g' => 0x6F,
+        b'A' | b'a' => 0x77,
+        b'b' => 0x7C,
+        b'C' => 0x39,
+        b'c' => 0x58,
+        b'D' | b'd' => 0x5E,
+        b'E' | b'e' => 0x79,
+        b'F' | b'f' => 0x71,
+        b'G' => 0x3D,
+        b'H' => 0x76,
+        b'h' => 0x74,
+        b'i' => 0x04,
+        b'J' | b'j' => 0x1E,
+        b'L' | b'l' => 0x38,
+        b'N' | b'n' => 0x54,
+        b'o' => 0x5C,
+        b'P' | b'p' => 0x73,
+        b'q' | b'Q' => 0x67,
+        b'R' | b'r' => 0x50,
+        b'T' | b't' => 0x78,
+        b'U' => 0x3E,
+        b'u' | b'v' | b'V' => 0x1C,
+        b'Y' | b'y' => 0x6E,
+        b'-' => MINUS,
+        b'_' => 0x08,
+        b'=' => 0x48,
+        b'\'' => 0x02,
+        b'"' => 0x22,
+        b'[' | b'(' => 0x39,
+        b']' | b')' => 0x0F,
+        b'.' | b',' => DOT,
+        _ => BLANK,
+    }
+}
+
+/// Returns the segments showing a hexadecimal digit.
+/// # Arguments
+/// * `digit` - a u8, the value of the digit, only the lower 4 bits are used.
+pub const fn encode_hex(digit: u8) -> u8 {
+    let digit = digit & 0x0F;
+    encode(if digit < 10 {
+        b'0' + digit
+    } else {
+        b'A' + digit - 10
+    })
+}
+
+/// Converts segments to the order of the MAX7219 without decoding,
+/// the decimal point in bit 7 and segment a in bit 6 down to g in bit 0.
+pub const fn to_max7219(segments: u8) -> u8 {
+    (segments & DOT) | ((segments & 0x7F).reverse_bits() >> 1)
+}
+
+/// Converts segments to the order with segment a in bit 7 and the decimal point in bit 0,
+/// as used by `myfn_num_to_bits` for displays wired to a shift register.
+pub const fn to_msb_first(segments: u8) -> u8 {
+    segments.reverse_bits()
+}
+
+/// Writes an integer right aligned.
+/// # Arguments
+/// * `buffer` - a mutable reference to `[u8]`, the segments of the digits from left to right.
+/// * `value` - a i32, the number to show.
+/// # Returns
+/// * `a Result<()>` - `BufferTooSmall` if the number does not fit, the digits then show dashes.
+pub fn format_int(buffer: &mut [u8], value: i32) -> Result<()> {
+    format_fixed(buffer, value, 0)
+}
+
+/// Writes a fixed point number right aligned, the decimal point lit after the integer part.
+/// # Arguments
+/// * `buffer` - a mutable reference to `[u8]`, the segments of the digits from left to right.
+/// * `value` - a i32, the number multiplied by 10 to the power `decimals`, like 1234 for 12.34.
+/// * `decimals` - a u8, the number of digits after the decimal point.
+/// # Returns
+/// * `a Result<()>` - `BufferTooSmall` if the number does not fit, the digits then show dashes.
+pub fn format_fixed(buffer: &mut [u8], value: i32, decimals: u8) -> Result<()> {
+    let mut magnitude = value.unsigned_abs();
+    let mut position = buffer.len();
+    let mut written = 0;
+    // Digits are written from the right, at least one before the decimal point.
+    while magnitude != 0 || written <= decimals as usize {
+        if position == 0 {
+            return overflow(buffer);
+        }
+        position -= 1;
+        buffer[position] = encode_hex((magnitude % 10) as u8);
+        if written == decimals as usize && decimals != 0 {
+            buffer[position] |= DOT;
+        }
+        magnitude /= 10;
+        written += 1;
+    }
+    if value < 0 {
+        if position == 0 {
+            return overflow(buffer);
+        }
+        position -= 1;
+        buffer[position] = MINUS;
+    }
+    buffer[..position].fill(BLANK);
+    Ok(())
+}
+
+/// Writes a number in hexadecimal right aligned, with leading zeros.
+/// # Arguments
+/// * `buffer` - a mutable reference to `[u8]`, the segments of the digits from left to right.
+/// * `value` - a u32, the number to show.
+pub fn format_hex(buffer: &mut [u8], mut value: u32) {
+    for digit in buffer.iter_mut().rev() {
+        *digit = encode_hex(value as u8);
+        value >>= 4;
+    }
+}
+
+/// Fills the digits with dashes and returns the overflow error.
+fn overflow(buffer: &mut [u8]) -> Result<()> {
+    buffer.fill(MINUS);
+    Err(Error::BufferTooSmall)
+}
+
+/// Splits a text into the segments of its digits, a dot lighting the
+/// decimal point of the character before it instead of taking a digit.
+pub fn glyphs(text: &str) -> impl Iterator<Item = u8> + '_ {
+    let bytes = text.as_bytes();
+    let mut index = 0;
+    core::iter::from_fn(move || {
+        let c = *bytes.get(index)?;
+        index += 1;
+        let mut segments = encode(c);
+        if c != b'.' && bytes.get(index) == Some(&b'.') {
+            segments |= DOT;
+            index += 1;
+        }
+        Some(segments)
+    })
+}
+
+/// Writes a text left aligned, blanking the remaining digits.
+/// # Arguments
+/// * `buffer` - a mutable reference to `[u8]`, the segments of the digits from left to right.
+/// * `text` - a string slice, the text to show, dots are merged into the previous digit.
+/// # Returns
+/// * `a usize` - the number of digits the whole text takes, more than the buffer if it was cut.
+pub fn format_str(buffer: &mut [u8], text: &str) -> usize {
+    buffer.fill(BLANK);
+    let mut count = 0;
+    for segments in glyphs(text) {
+        if let Some(digit) = buffer.get_mut(count) {
+            *digit = segments;
+        }
+        count += 1;
+    }
+    count
+}
+
+/// Scrolls a text too long for the display from right to left.
+pub struct Scroller<'a> {
+    text: &'a str,
+    length: usize,
+    offset: usize,
+    /// Milliseconds between two steps.
+    period: u32,
+    next: u32,
+}
+
+impl<'a> Scroller<'a> {
+    /// Creates a scroller.
+    /// # Arguments
+    /// * `text` - a string slice, the text to scroll.
+    /// * `period` - a u32, the time in milliseconds between two steps.
+    pub fn new(text: &'a str, period: u32) -> Scroller<'a> {
+        Scroller {
+            text,
+            length: glyphs(text).count(),
+            offset: 0,
+            period,
+            next: 0,
+        }
+    }
+
+    /// Writes the current window of the text, which enters from the right
+    /// and leaves on the left before starting again.
+    /// # Arguments
+    /// * `buffer` - a mutable reference to `[u8]`, the segments of the digits from left to right.
+    pub fn render(&self, buffer: &mut [u8]) {
+        buffer.fill(BLANK);
+        let width = buffer.len();
+        for (index, segments) in glyphs(self.text).enumerate() {
+            // The text starts just after the right end of the display.
+            let position = (width + index) as isize - self.offset as isize;
+            if position >= 0 && (position as usize) < width {
+                buffer[position as usize] = segments;
+            }
+        }
+    }
+
+    /// Moves the text one digit to the left.
+    /// # Arguments
+    /// * `width` - a usize, the number of digits of the display.
+    pub fn step(&mut self, width: usize) {
+        self.offset = (self.offset + 1) % (width + self.length + 1);
+    }
+
+    /// Moves and renders the text when a step is due.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, usually `millis()`.
+    /// * `buffer` - a mutable reference to `[u8]`, the segments of the digits from left to right.
+    /// # Returns
+    /// * `a boolean` - true if the buffer changed and should be sent to the display.
+    pub fn poll(&mut self, now: u32, buffer: &mut [u8]) -> bool {
+        if (now.wrapping_sub(self.next) as i32) < 0 {
+            return false;
+        }
+        self.next = now.wrapping_add(self.period);
+        self.step(buffer.len());
+        self.render(buffer);
+        true
+    }
+}
+
+/// A display made of seven segment digits.
+pub trait SegmentDisplay {
+    /// Returns the number of digits.
+    fn digits(&self) -> usize;
+
+    /// Shows raw segments, in the order of this module.
+    /// # Arguments
+    /// * `segments` - a reference to `[u8]`, the segments of the digits from left to right.
+    fn write_segments(&mut self, segments: &[u8]) -> Result<()>;
+
+    /// Shows an integer right aligned, dashes if it does not fit.
+    fn show_int(&mut self, value: i32) -> Result<()> {
+        self.show_fixed(value, 0)
+    }
+
+    /// Shows a fixed point number right aligned, dashes if it does not fit.
+    /// # Arguments
+    /// * `value` - a i32, the number multiplied by 10 to the power `decimals`.
+    /// * `decimals` - a u8, the number of digits after the decimal point.
+    fn show_fixed(&mut self, value: i32, decimals: u8) -> Result<()> {
+        let mut buffer = [BLANK; MAX_DIGITS];
+        let digits = self.digits().min(MAX_DIGITS);
+        let result = format_fixed(&mut buffer[..digits], value, decimals);
+        self.write_segments(&buffer[..digits])?;
+        result
+    }
+
+    /// Shows a number in hexadecimal with leading zeros.
+    fn show_hex(&mut self, value: u32) -> Result<()> {
+        let mut buffer = [BLANK; MAX_DIGITS];
+        let digits = self.digits().min(MAX_DIGITS);
+        format_hex(&mut buffer[..digits], value);
+        self.write_segments(&buffer[..digits])
+    }
+
+    /// Shows a text left aligned, cut at the last digit.
+    fn show_str(&mut self, text: &str) -> Result<()> {
+        let mut buffer = [BLANK; MAX_DIGITS];
+        let digits = self.digits().min(MAX_DIGITS);
+        format_str(&mut buffer[..digits], text);
+        self.write_segments(&buffer[..digits])
+    }
+
+    /// Blanks all the digits.
+    fn clear(&mut self) -> Result<()> {
+        let digits = self.digits().min(MAX_DIGITS);
+        self.write_segments(&[BLANK; MAX_DIGITS][..digits])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(buffer: &[u8]) -> [u8; 4] {
+        let mut out = [0; 4];
+        out.copy_from_slice(buffer);
+        out
+    }
+
+    #[test]
+    fn numbers_are_right_aligned() {
+        let mut buffer = [0xFF; 4];
+        format_int(&mut buffer, 42).unwrap();
+        assert_eq!(text(&buffer), [BLANK, BLANK, 0x66, 0x5B]);
+        format_int(&mut buffer, -7).unwrap();
+        assert_eq!(text(&buffer), [BLANK, BLANK, MINUS, 0x07]);
+        format_int(&mut buffer, 0).unwrap();
+        assert_eq!(text(&buffer), [BLANK, BLANK, BLANK, 0x3F]);
+        assert_eq!(format_int(&mut buffer, -1000), Err(Error::BufferTooSmall));
+        assert_eq!(text(&buffer), [MINUS; 4]);
+    }
+
+    #[test]
+    fn fixed_point_places_the_dot() {
+        let mut buffer = [0; 4];
+        format_fixed(&mut buffer, 1234, 2).unwrap();
+        assert_eq!(text(&buffer), [0x06, 0x5B | DOT, 0x4F, 0x66]);
+        format_fixed(&mut buffer, -5, 2).unwrap();
+        assert_eq!(text(&buffer), [MINUS, 0x3F | DOT, 0x3F, 0x6D]);
+        format_hex(&mut buffer, 0xBEEF);
+        assert_eq!(text(&buffer), [0x7F, 0x79, 0x79, 0x71]);
+    }
+
+    #[test]
+    fn strings_merge_dots_and_scroll() {
+        let mut buffer = [0; 4];
+        assert_eq!(format_str(&mut buffer, "1.2.3"), 3);
+        assert_eq!(text(&buffer), [0x06 | DOT, 0x5B | DOT, 0x4F, BLANK]);
+
+        let mut scroller = Scroller::new("HI", 100);
+        assert!(scroller.poll(0, &mut buffer));
+        assert_eq!(text(&buffer), [BLANK, BLANK, BLANK, 0x76]);
+        assert!(!scroller.poll(50, &mut buffer));
+        assert!(scroller.poll(100, &mut buffer));
+        assert_eq!(text(&buffer), [BLANK, BLANK, 0x76, 0x06]);
+        for step in 0..5 {
+            scroller.poll(200 + step * 100, &mut buffer);
+        }
+        assert_eq!(text(&buffer), [BLANK; 4]);
+        scroller.poll(700, &mut buffer);
+        assert_eq!(text(&buffer), [BLANK, BLANK, BLANK, 0x76]);
+    }
+
+    #[test]
+    fn wirings() {
+        assert_eq!(to_max7219(0x3F | DOT), 0xFE);
+        assert_eq!(to_max7219(MINUS), 0x01);
+        assert_eq!(to_msb_first(encode(b'0')), 0b11111100);
+    }
+}
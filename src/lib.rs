@@ -1,7 +1,7 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(warnings)]
-#![feature(asm)]
-#![feature(llvm_asm)]
+#![cfg_attr(target_arch = "avr", feature(asm_experimental_arch))]
+#![feature(abi_avr_interrupt)]
 
 /// Library for AVR ATMEGA2560P Micro-controller
 /// For more information see the data sheet provided below
@@ -10,6 +10,27 @@
 pub mod atmega2560p {
 
     /// Hardware Abstraction Library (HAL)
+    ///
+    /// Timers and interrupt vectors owned by the modules. A module with interrupt
+    /// service routines needs the cargo feature given in the table, so the vectors
+    /// of the modules left out can be given other handlers. Modules sharing a timer
+    /// cannot be used at the same time, and a timer taken over gives no `analog` PWM.
+    ///
+    /// | Module | Feature | Timer | Vectors |
+    /// |---|---|---|---|
+    /// | `millis` | `millis` | Timer0 | 21 (compare A), 23 (overflow) |
+    /// | `counter` | `counter` | Timer1, Timer5 | 20, 50 (overflows) |
+    /// | `dimmer` | `dimmer` | Timer1 | 17, 18 (compare A and B), 28 (analog comparator) |
+    /// | `rc_meter` | | Timer4, during a measurement | |
+    /// | `fan` | | Timer4 | |
+    /// | `tone` | `tone` | Timer2 | 13 (compare A) |
+    /// | `sigma_delta` | `sigma-delta` | Timer2 | 14 (compare B) |
+    /// | `audio`, `async_timer` | `audio`, `async-timer` | Timer2 | 15 (overflow) |
+    /// | `sampler` | `sampler` | | 29 (ADC) |
+    /// | `external_interrupt`, `powerfail` | `external-interrupt`, `powerfail` | | 1 to 8 (INT0 to INT7) |
+    /// | `pcint`, `pulse`, `wiegand` | `pcint` | | 9 to 11 |
+    /// | `watchdog` | `watchdog-interrupt` | | 12 |
+    /// | `com::usart_buffered` | `usart-buffered` | | 25, 36, 51, 54 (receive complete) |
     pub mod hal {
 
         pub mod watchdog;
@@ -29,6 +50,62 @@ pub mod atmega2560p {
         pub mod digital;
 
         pub mod shift;
+
+        #[cfg(feature = "millis")]
+        pub mod millis;
+
+        pub mod eeprom;
+
+        #[cfg(feature = "tone")]
+        pub mod tone;
+
+        #[cfg(feature = "audio")]
+        pub mod audio;
+
+        #[cfg(feature = "dimmer")]
+        pub mod dimmer;
+
+        #[cfg(feature = "counter")]
+        pub mod counter;
+
+        #[cfg(feature = "powerfail")]
+        pub mod powerfail;
+
+        pub mod mem;
+
+        #[cfg(feature = "pcint")]
+        pub mod pcint;
+
+        #[cfg(all(feature = "millis", feature = "pcint"))]
+        pub mod wiegand;
+
+        #[cfg(all(feature = "millis", feature = "pcint"))]
+        pub mod pulse;
+
+        #[cfg(feature = "external-interrupt")]
+        pub mod external_interrupt;
+
+        pub mod fan;
+
+        #[cfg(feature = "sampler")]
+        pub mod sampler;
+
+        pub mod arduino_mega;
+
+        pub mod static_pin;
+
+        #[cfg(feature = "millis")]
+        pub mod debounce;
+
+        #[cfg(feature = "sigma-delta")]
+        pub mod sigma_delta;
+
+        pub mod rc_meter;
+
+        pub mod adc;
+
+        #[cfg(feature = "async-timer")]
+        pub mod async_timer;
     }
 
     /// Communication Control Library
@@ -45,6 +122,15 @@ pub mod atmega2560p {
         pub mod usart_recieve;
 
         pub mod i2c;
+
+        pub mod i2c_sniffer;
+
+        pub mod spi;
+
+        #[cfg(feature = "usart-buffered")]
+        pub mod usart_buffered;
+
+        pub mod usart_io;
     }
 }
 
@@ -65,6 +151,27 @@ cfg_if::cfg_if! {
 pub mod atmega328p {
 
     /// Hardware Abstraction Library (HAL)
+    ///
+    /// Timers and interrupt vectors owned by the modules. A module with interrupt
+    /// service routines needs the cargo feature given in the table, so the vectors
+    /// of the modules left out can be given other handlers. Modules sharing a timer
+    /// cannot be used at the same time, and a timer taken over gives no `analog` PWM.
+    ///
+    /// | Module | Feature | Timer | Vectors |
+    /// |---|---|---|---|
+    /// | `millis` | `millis` | Timer0 | 14 (compare A), 16 (overflow) |
+    /// | `counter` | `counter` | Timer1 | 13 (overflow) |
+    /// | `dimmer` | `dimmer` | Timer1 | 1 (INT0), 11, 12 (compare A and B), 23 (analog comparator) |
+    /// | `rc_meter` | | Timer1, during a measurement | |
+    /// | `fan` | | Timer2 | |
+    /// | `tone` | `tone` | Timer2 | 7 (compare A) |
+    /// | `sigma_delta` | `sigma-delta` | Timer2 | 8 (compare B) |
+    /// | `audio`, `async_timer` | `audio`, `async-timer` | Timer2 | 9 (overflow) |
+    /// | `sampler` | `sampler` | | 21 (ADC) |
+    /// | `powerfail` | `powerfail` | | 2 (INT1) |
+    /// | `pcint`, `pulse`, `wiegand` | `pcint` | | 3 to 5 |
+    /// | `watchdog` | `watchdog-interrupt` | | 6 |
+    /// | `com::usart_buffered` | `usart-buffered` | | 18 (receive complete) |
     pub mod hal {
         pub mod power;
 
@@ -83,6 +190,59 @@ pub mod atmega328p {
         pub mod digital;
 
         pub mod shift;
+
+        #[cfg(feature = "millis")]
+        pub mod millis;
+
+        pub mod eeprom;
+
+        #[cfg(feature = "tone")]
+        pub mod tone;
+
+        #[cfg(feature = "audio")]
+        pub mod audio;
+
+        #[cfg(feature = "dimmer")]
+        pub mod dimmer;
+
+        #[cfg(feature = "counter")]
+        pub mod counter;
+
+        #[cfg(feature = "powerfail")]
+        pub mod powerfail;
+
+        pub mod mem;
+
+        #[cfg(feature = "pcint")]
+        pub mod pcint;
+
+        #[cfg(all(feature = "millis", feature = "pcint"))]
+        pub mod wiegand;
+
+        #[cfg(all(feature = "millis", feature = "pcint"))]
+        pub mod pulse;
+
+        pub mod fan;
+
+        #[cfg(feature = "sampler")]
+        pub mod sampler;
+
+        pub mod arduino_uno;
+
+        pub mod arduino_nano;
+
+        pub mod static_pin;
+
+        #[cfg(feature = "millis")]
+        pub mod debounce;
+
+        #[cfg(feature = "sigma-delta")]
+        pub mod sigma_delta;
+
+        pub mod rc_meter;
+
+        #[cfg(feature = "async-timer")]
+        pub mod async_timer;
     }
 
     /// Communication Control Library
@@ -99,6 +259,15 @@ pub mod atmega328p {
         pub mod usart_recieve;
 
         pub mod i2c;
+
+        pub mod i2c_sniffer;
+
+        pub mod spi;
+
+        #[cfg(feature = "usart-buffered")]
+        pub mod usart_buffered;
+
+        pub mod usart_io;
     }
 }
 
@@ -118,6 +287,61 @@ pub mod sensors;
 #[cfg(feature = "math")]
 pub mod math;
 
+/// System services like task scheduling
+#[cfg(any(feature = "atmega328p", feature = "atmega2560p"))]
+pub mod system;
+
+/// Experimental executor for async code
+#[cfg(all(
+    feature = "async",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+pub mod executor;
+
+/// Non volatile storage and settings persistence
+pub mod storage;
+
+/// Fixed capacity containers
+pub mod collections;
+
+/// Joystick mixing and motor control for wheeled robots
+pub mod robotics;
+
+/// Color math and animations for LED strips, text on seven segment displays
+pub mod led;
+
+/// Multi hop networking and time synchronization for sensor nodes
+pub mod net;
+
+/// Encoders and decoders for binary data
+pub mod encoding;
+
+/// Cryptographic primitives
+#[cfg(feature = "crypto")]
+pub mod crypto;
+
+/// Decoding of the telemetry on a desktop, with serde support
+#[cfg(feature = "std")]
+pub mod host;
+
+/// Error type returned by the drivers
+pub mod error;
+
+pub use error::{Error, Result};
+
+/// Constants and strings stored in program memory
+pub mod progmem;
+
+/// Variables surviving resets in the `.noinit` RAM section
+pub mod noinit;
+
+/// defmt logger over USART0
+#[cfg(all(
+    feature = "defmt-uart",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+mod logging;
+
 /// Low level control for AVR Chips
 pub mod llvm;
 
@@ -127,3 +351,6 @@ pub use llvm::*;
 /// Configuration setup and time control
 pub mod config;
 pub mod delay;
+
+/// Real and simulated clocks for the code measuring time
+pub mod time;
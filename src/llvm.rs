@@ -14,8 +14,48 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>
 
+use core::arch::asm;
+
 /// The `__nop` function is equivalent to the NOP machine instruction.
 /// A NOP is a computer instruction that does nothing and still takes fixed clock cyles to process.
 pub fn __nop() {
-    unsafe { llvm_asm!("nop") }
+    unsafe { asm!("nop") }
+}
+
+/// The `__sleep` function is equivalent to the SLEEP machine instruction.
+/// It puts the MCU in the sleep mode selected in the sleep mode control register,
+/// provided the sleep enable bit is set.
+pub fn __sleep() {
+    unsafe { asm!("sleep") }
+}
+
+/// The `__wdr` function is equivalent to the WDR machine instruction.
+/// It restarts the watchdog timer, which must be done regularly once
+/// the watchdog is enabled to prevent it from timing out.
+pub fn __wdr() {
+    unsafe { asm!("wdr") }
+}
+
+/// The `__lpm` function is equivalent to the LPM machine instruction.
+/// It loads one byte from the program memory (flash) at the given address,
+/// which is how data placed in the `.progmem.data` section has to be read.
+/// On non AVR targets program and data memory share one address space
+/// so a normal read is done instead.
+/// # Arguments
+/// * `address` - a `*const u8`, the flash address of the byte to be read.
+/// # Returns
+/// * `a u8` - the byte stored at that address.
+/// # Safety
+/// `address` must point into the lower 64K of flash on AVR, or be a valid pointer elsewhere.
+pub unsafe fn __lpm(address: *const u8) -> u8 {
+    #[cfg(target_arch = "avr")]
+    {
+        let byte: u8;
+        asm!("lpm {0}, Z", out(reg) byte, in("Z") address, options(pure, readonly, nostack));
+        byte
+    }
+    #[cfg(not(target_arch = "avr"))]
+    {
+        core::ptr::read_volatile(address)
+    }
 }
@@ -0,0 +1,87 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Global `defmt` logger which sends the encoded frames over USART0.
+//! The format strings stay in the ELF file on the host, only their indices
+//! and the arguments are transmitted, and every frame is stamped with
+//! `millis()`. USART0 has to be initialized by the application, for example
+//! with `begin()`, and should not be used for anything else.
+//! Decode the output on the host with `defmt-print -e <elf>`.
+
+#[cfg(not(feature = "atmega2560p"))]
+use crate::com::usart_initialize::Usart;
+use crate::com::usart_initialize::UsartNum;
+#[cfg(feature = "atmega2560p")]
+use crate::com::usart_initialize::UsartObject as Usart;
+use crate::hal::interrupts::Interrupt;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Address of the status register holding the global interrupt flag.
+const SREG: *mut u8 = 0x5F as *mut u8;
+
+/// True while a frame is being written.
+static mut TAKEN: bool = false;
+
+/// Status register saved by `acquire` and restored by `release`.
+static mut RESTORE: u8 = 0;
+
+static mut ENCODER: defmt::Encoder = defmt::Encoder::new();
+
+#[defmt::global_logger]
+struct UartLogger;
+
+unsafe impl defmt::Logger for UartLogger {
+    fn acquire() {
+        let sreg = unsafe { read_volatile(SREG) };
+        unsafe { Interrupt::new() }.disable();
+
+        unsafe {
+            if TAKEN {
+                panic!("defmt logger taken reentrantly");
+            }
+            TAKEN = true;
+            RESTORE = sreg;
+            ENCODER.start_frame(transmit);
+        }
+    }
+
+    unsafe fn flush() {
+        // Every byte is handed to the transmitter before `write` returns.
+    }
+
+    unsafe fn release() {
+        ENCODER.end_frame(transmit);
+        TAKEN = false;
+        write_volatile(SREG, RESTORE);
+    }
+
+    unsafe fn write(bytes: &[u8]) {
+        ENCODER.write(bytes, transmit);
+    }
+}
+
+/// Sends encoded bytes through USART0, a stuck transmitter drops them.
+/// # Arguments
+/// * `bytes` - a slice of u8, the bytes to be sent.
+fn transmit(bytes: &[u8]) {
+    #[allow(unused_mut)]
+    let mut usart = unsafe { Usart::new(UsartNum::Usart0) };
+    for &byte in bytes {
+        let _ = usart.transmit_data(byte);
+    }
+}
+
+defmt::timestamp!("{=u32:ms}", crate::hal::millis::millis());
@@ -0,0 +1,325 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Fixed point digital filters for sampled signals, like removing the
+//! baseline wander and the mains hum of biopotentials or smoothing noisy
+//! sensors. Coefficients are computed once with floating point when a filter
+//! is created, samples are then filtered with integers only, so that the
+//! filters keep up with sample rates of several hundred Hz.
+//! Filters can be put one after the other with `Chain`.
+
+/// A filter of a stream of samples.
+pub trait Filter {
+    /// Filters a sample.
+    /// # Arguments
+    /// * `sample` - an i32, the next input sample, within +-32767.
+    /// # Returns
+    /// * `an i32` - The next output sample.
+    fn process(&mut self, sample: i32) -> i32;
+
+    /// Clears the state of the filter, as if it had only seen zeros.
+    fn reset(&mut self);
+}
+
+/// Fractional bits of the state of the first order filters.
+const STATE_BITS: u32 = 8;
+
+/// Fractional bits of the coefficients of `Biquad`.
+const COEFFICIENT_BITS: u32 = 14;
+
+/// Returns the sine and cosine of an angle from 0 to pi, with Taylor series
+/// accurate to about 1e-5 over that range, which is enough for coefficients.
+fn sin_cos(angle: f32) -> (f32, f32) {
+    let square = angle * angle;
+    let (mut sin, mut cos) = (0.0, 0.0);
+    let (mut sin_term, mut cos_term) = (angle, 1.0);
+    for n in 1..9 {
+        sin += sin_term;
+        cos += cos_term;
+        sin_term *= -square / ((2 * n) * (2 * n + 1)) as f32;
+        cos_term *= -square / ((2 * n - 1) * (2 * n)) as f32;
+    }
+    (sin, cos)
+}
+
+/// Returns the angle covered in one sample by a frequency.
+fn angle(frequency: f32, rate: f32) -> f32 {
+    (2.0 * core::f32::consts::PI * frequency / rate).clamp(0.0, core::f32::consts::PI)
+}
+
+/// First order high pass filter, which removes the offset and slow drifts of a signal.
+/// # Elements
+/// * `alpha` - an i64, the feedback coefficient with 16 fractional bits.
+/// * `input` - an i32, the previous input sample.
+/// * `output` - an i64, the previous output sample with `STATE_BITS` fractional bits.
+#[derive(Clone, Copy)]
+pub struct HighPass {
+    alpha: i64,
+    input: i32,
+    output: i64,
+}
+
+impl HighPass {
+    /// Creates the filter.
+    /// # Arguments
+    /// * `cutoff` - a f32, the cutoff frequency in Hz.
+    /// * `rate` - a f32, the sample rate in Hz.
+    /// # Returns
+    /// * `a HighPass object` - Which will be used for further implementations.
+    pub fn new(cutoff: f32, rate: f32) -> HighPass {
+        let rc = 1.0 / (2.0 * core::f32::consts::PI * cutoff.max(0.001));
+        let alpha = rc / (rc + 1.0 / rate);
+        HighPass {
+            alpha: (alpha * 65536.0) as i64,
+            input: 0,
+            output: 0,
+        }
+    }
+}
+
+impl Filter for HighPass {
+    fn process(&mut self, sample: i32) -> i32 {
+        let step = ((sample - self.input) as i64) << STATE_BITS;
+        self.output = (self.alpha * (self.output + step)) >> 16;
+        self.input = sample;
+        (self.output >> STATE_BITS) as i32
+    }
+
+    fn reset(&mut self) {
+        self.input = 0;
+        self.output = 0;
+    }
+}
+
+/// First order low pass filter, an exponential moving average.
+/// # Elements
+/// * `alpha` - an i64, the weight of a new sample with 16 fractional bits.
+/// * `output` - an i64, the previous output sample with `STATE_BITS` fractional bits.
+#[derive(Clone, Copy)]
+pub struct LowPass {
+    alpha: i64,
+    output: i64,
+}
+
+impl LowPass {
+    /// Creates the filter.
+    /// # Arguments
+    /// * `cutoff` - a f32, the cutoff frequency in Hz.
+    /// * `rate` - a f32, the sample rate in Hz.
+    /// # Returns
+    /// * `a LowPass object` - Which will be used for further implementations.
+    pub fn new(cutoff: f32, rate: f32) -> LowPass {
+        let rc = 1.0 / (2.0 * core::f32::consts::PI * cutoff.max(0.001));
+        let dt = 1.0 / rate;
+        LowPass {
+            alpha: ((dt / (rc + dt)) * 65536.0) as i64,
+            output: 0,
+        }
+    }
+}
+
+impl Filter for LowPass {
+    fn process(&mut self, sample: i32) -> i32 {
+        let target = (sample as i64) << STATE_BITS;
+        self.output += ((target - self.output) * self.alpha) >> 16;
+        (self.output >> STATE_BITS) as i32
+    }
+
+    fn reset(&mut self) {
+        self.output = 0;
+    }
+}
+
+/// Second order filter, designed after the Audio EQ Cookbook of R. Bristow-Johnson.
+/// Poles close to the unit circle, as given by cutoffs far below the sample
+/// rate, are not represented well by the 14 bit coefficients, `HighPass` and
+/// `LowPass` suit those better.
+/// # Elements
+/// * `b` - an array of i32, the feed forward coefficients.
+/// * `a` - an array of i32, the feedback coefficients a1 and a2.
+/// * `inputs` - an array of i32, the previous two input samples.
+/// * `outputs` - an array of i32, the previous two output samples.
+#[derive(Clone, Copy)]
+pub struct Biquad {
+    b: [i32; 3],
+    a: [i32; 2],
+    inputs: [i32; 2],
+    outputs: [i32; 2],
+}
+
+impl Biquad {
+    /// Normalizes and quantizes the coefficients.
+    fn from_coefficients(b: [f32; 3], a: [f32; 3]) -> Biquad {
+        let scale = (1 << COEFFICIENT_BITS) as f32 / a[0];
+        Biquad {
+            b: [
+                (b[0] * scale) as i32,
+                (b[1] * scale) as i32,
+                (b[2] * scale) as i32,
+            ],
+            a: [(a[1] * scale) as i32, (a[2] * scale) as i32],
+            inputs: [0; 2],
+            outputs: [0; 2],
+        }
+    }
+
+    /// Creates a low pass filter.
+    /// # Arguments
+    /// * `cutoff` - a f32, the cutoff frequency in Hz.
+    /// * `q` - a f32, the quality factor, 0.707 for a flat pass band.
+    /// * `rate` - a f32, the sample rate in Hz.
+    /// # Returns
+    /// * `a Biquad object` - Which will be used for further implementations.
+    pub fn low_pass(cutoff: f32, q: f32, rate: f32) -> Biquad {
+        let (sin, cos) = sin_cos(angle(cutoff, rate));
+        let alpha = sin / (2.0 * q);
+        Biquad::from_coefficients(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Creates a high pass filter.
+    /// # Arguments
+    /// * `cutoff` - a f32, the cutoff frequency in Hz.
+    /// * `q` - a f32, the quality factor, 0.707 for a flat pass band.
+    /// * `rate` - a f32, the sample rate in Hz.
+    /// # Returns
+    /// * `a Biquad object` - Which will be used for further implementations.
+    pub fn high_pass(cutoff: f32, q: f32, rate: f32) -> Biquad {
+        let (sin, cos) = sin_cos(angle(cutoff, rate));
+        let alpha = sin / (2.0 * q);
+        Biquad::from_coefficients(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Creates a band pass filter with a gain of 1 at its center.
+    /// # Arguments
+    /// * `center` - a f32, the center frequency in Hz.
+    /// * `q` - a f32, the quality factor, the center frequency divided by the bandwidth.
+    /// * `rate` - a f32, the sample rate in Hz.
+    /// # Returns
+    /// * `a Biquad object` - Which will be used for further implementations.
+    pub fn band_pass(center: f32, q: f32, rate: f32) -> Biquad {
+        let (sin, cos) = sin_cos(angle(center, rate));
+        let alpha = sin / (2.0 * q);
+        Biquad::from_coefficients([alpha, 0.0, -alpha], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    /// Creates a notch filter, for example to remove the 50 or 60 Hz mains hum.
+    /// # Arguments
+    /// * `center` - a f32, the frequency removed in Hz.
+    /// * `q` - a f32, the quality factor, higher for a narrower notch.
+    /// * `rate` - a f32, the sample rate in Hz.
+    /// # Returns
+    /// * `a Biquad object` - Which will be used for further implementations.
+    pub fn notch(center: f32, q: f32, rate: f32) -> Biquad {
+        let (sin, cos) = sin_cos(angle(center, rate));
+        let alpha = sin / (2.0 * q);
+        Biquad::from_coefficients(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+}
+
+impl Filter for Biquad {
+    fn process(&mut self, sample: i32) -> i32 {
+        let accumulator = self.b[0] as i64 * sample as i64
+            + self.b[1] as i64 * self.inputs[0] as i64
+            + self.b[2] as i64 * self.inputs[1] as i64
+            - self.a[0] as i64 * self.outputs[0] as i64
+            - self.a[1] as i64 * self.outputs[1] as i64;
+        let output = (accumulator >> COEFFICIENT_BITS) as i32;
+        self.inputs = [sample, self.inputs[0]];
+        self.outputs = [output, self.outputs[0]];
+        output
+    }
+
+    fn reset(&mut self) {
+        self.inputs = [0; 2];
+        self.outputs = [0; 2];
+    }
+}
+
+/// Two filters one after the other, chains of more filters nest them,
+/// like `Chain(HighPass::new(..), Chain(Biquad::notch(..), LowPass::new(..)))`.
+#[derive(Clone, Copy)]
+pub struct Chain<F: Filter, G: Filter>(pub F, pub G);
+
+impl<F: Filter, G: Filter> Filter for Chain<F, G> {
+    fn process(&mut self, sample: i32) -> i32 {
+        self.1.process(self.0.process(sample))
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+        self.1.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the peak output of a filter for a sine of the given frequency, once settled.
+    fn peak<F: Filter>(filter: &mut F, frequency: f32, rate: f32) -> i32 {
+        let mut peak = 0;
+        for n in 0..4000 {
+            let phase = (2.0 * core::f32::consts::PI * frequency * n as f32 / rate) as f64;
+            let output = filter.process((1000.0 * phase.sin()) as i32);
+            if n >= 3000 {
+                peak = peak.max(output.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn first_order() {
+        let mut high = HighPass::new(0.5, 500.0);
+        let mut output = 0;
+        for _ in 0..5000 {
+            output = high.process(512);
+        }
+        assert!(output.abs() <= 1, "{}", output);
+        let mut low = LowPass::new(5.0, 500.0);
+        for _ in 0..1000 {
+            output = low.process(512);
+        }
+        assert!((output - 512).abs() <= 1, "{}", output);
+        assert!(peak(&mut LowPass::new(5.0, 500.0), 100.0, 500.0) < 100);
+    }
+
+    #[test]
+    fn biquads() {
+        let (sin, cos) = sin_cos(core::f32::consts::PI);
+        assert!(sin.abs() < 1e-4 && (cos + 1.0).abs() < 1e-4);
+        assert!(peak(&mut Biquad::notch(50.0, 5.0, 500.0), 50.0, 500.0) < 30);
+        assert!(peak(&mut Biquad::notch(50.0, 5.0, 500.0), 10.0, 500.0) > 950);
+        let band = peak(&mut Biquad::band_pass(10.0, 1.0, 500.0), 10.0, 500.0);
+        assert!((band - 1000).abs() < 30, "{}", band);
+        assert!(peak(&mut Biquad::low_pass(20.0, 0.707, 500.0), 200.0, 500.0) < 50);
+        assert!(peak(&mut Biquad::high_pass(20.0, 0.707, 500.0), 2.0, 500.0) < 50);
+        let mut chain = Chain(
+            HighPass::new(0.5, 500.0),
+            Biquad::low_pass(40.0, 0.707, 500.0),
+        );
+        assert!(peak(&mut chain, 10.0, 500.0) > 900);
+    }
+}
@@ -14,10 +14,14 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>
 
+mod filters;
 mod map;
+mod stats;
 
+pub use filters::*;
 pub use map::*;
 pub use micromath::*;
+pub use stats::*;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "random")] {
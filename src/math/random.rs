@@ -14,11 +14,14 @@
 //     You should have received a copy of the GNU Affero General Public License
 //     along with this program.  If not, see <https://www.gnu.org/licenses/>
 
-use crate::delay::delay_ms;
+use crate::delay::{delay_ms, Delay};
 use crate::hal::pin::Pins;
 
 use crate::sensors::*;
+use crate::{Error, Result};
 use bit_field::BitField;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
 
 /// Selection of method to generate number.
 #[derive(Clone, Copy)]
@@ -30,48 +33,52 @@ pub enum Generator {
 /// Controls the implementation of Random Number Generators.
 /// # Elements
 /// * `pins` - structure containing array to control all pins of micro-controller.
-/// * `mpu` - a static mutable reference to the pointer location to control MPU6050 gyroscope.
+/// * `mpu` - an optional MPU6050 gyroscope driver, used in the `Mpu` mode.
 /// * `mode` - a `Generator` object, which stores the implementation method for random number generator.
-#[repr(C, packed)]
-pub struct RandomNumberGenerator {
+pub struct RandomNumberGenerator<I2C = &'static I2cBus, D = Delay> {
     pins: Pins,
-    mpu: &'static mut MPU6050<'static>,
+    mpu: Option<MPU6050<I2C, D>>,
     mode: Generator,
 }
 
 impl RandomNumberGenerator {
     /// Create a new structure object for Random Number Generation.
-    /// This structure contains elements for both ways of number generation implemented.
+    /// A generator in the `Mpu` mode needs the sensor, see `with_mpu()`.
+    /// # Arguments
+    /// * `mode1` - a `Generator` object, the method of number generation.
     /// # Returns
     /// * `a struct of type Random Number Generator` - to be used for the struct's implementation.
     pub fn new(mode1: Generator) -> RandomNumberGenerator {
         RandomNumberGenerator {
             pins: Pins::new(),
-            mpu: MPU6050::new(),
+            mpu: None,
             mode: mode1,
         }
     }
+}
 
+impl<I2C, D> RandomNumberGenerator<I2C, D> {
     /// Generation of random number through random noise in environment
     /// detected by read through analog pins input.
     /// # Returns
-    /// * `a u8` - a random number generated by random noise as detected by analog pins during reading.
-    pub fn generate_by_analog(&mut self) -> u8 {
+    /// * `a Result<u8>` - a random number generated by random noise as detected by analog pins during reading,
+    ///                    or `InvalidMode` if the generator was created for the MPU6050.
+    pub fn generate_by_analog(&mut self) -> Result<u8> {
         match self.mode {
-            Generator::Mpu => unreachable!(),
+            Generator::Mpu => return Err(Error::InvalidMode),
             Generator::Analog => (),
         }
 
-        let mut bits1: u8 = unsafe { xor_rotate() };
+        let mut bits1: u8 = unsafe { xor_rotate()? };
 
         bits1 = xor_shift(bits1);
 
-        let bits2: u8 = unsafe { xor_rotate() };
+        let bits2: u8 = unsafe { xor_rotate()? };
 
         bits1 = xor(bits1, bits2);
 
-        let mut lbuf: u8 = unsafe { xor_rotate() };
-        let mut rbuf: u8 = unsafe { xor_rotate() };
+        let mut lbuf: u8 = unsafe { xor_rotate()? };
+        let mut rbuf: u8 = unsafe { xor_rotate()? };
         let buf: u8 = xor(lbuf, rbuf);
 
         let mut bits3: u8 = 0;
@@ -81,10 +88,10 @@ impl RandomNumberGenerator {
             let right: u8;
 
             delay_ms(100);
-            left = self.pins.analog[0].read() as u8;
+            left = read_noise(&mut self.pins)?;
 
             delay_ms(100);
-            right = self.pins.analog[0].read() as u8;
+            right = read_noise(&mut self.pins)?;
 
             bits3 = xor(bits3, rotate(left, i));
             bits3 = xor(bits3, rotate(right, 7 - i));
@@ -107,20 +114,40 @@ impl RandomNumberGenerator {
 
         bits1 = xor(bits1, bits3);
 
-        xor(bits1, xor(lbuf, rbuf))
+        Ok(xor(bits1, xor(lbuf, rbuf)))
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> RandomNumberGenerator<I2C, D> {
+    /// Create a new structure object which generates numbers through the given MPU6050 sensor.
+    /// # Arguments
+    /// * `mpu` - a `MPU6050` object, the sensor used as source of noise.
+    /// # Returns
+    /// * `a struct of type Random Number Generator` - to be used for the struct's implementation.
+    pub fn with_mpu(mpu: MPU6050<I2C, D>) -> RandomNumberGenerator<I2C, D> {
+        RandomNumberGenerator {
+            pins: Pins::new(),
+            mpu: Some(mpu),
+            mode: Generator::Mpu,
+        }
     }
 
     /// Generation of random number through random noise in environment
     /// detected through the MPU6050 sensor in the orthonormal set of axes.
     /// # Returns
-    /// * `a u8` - a random number generated by multiple seeding within numbers generated by MPU6050 sensor.
-    pub fn generate_by_mpu(&mut self) -> u8 {
+    /// * `a Result<u8>` - a random number generated by multiple seeding within numbers generated by MPU6050 sensor,
+    ///                    `InvalidMode` if the generator was created for the analog pins
+    ///                    or `NotReady` if it has no sensor.
+    pub fn generate_by_mpu(&mut self) -> Result<u8> {
         match self.mode {
-            Generator::Analog => unreachable!(),
+            Generator::Analog => return Err(Error::InvalidMode),
             Generator::Mpu => (),
         }
 
-        let (a, b, c, d, e, f) = generate_mpu();
+        let (a, b, c, d, e, f) = match self.mpu.as_mut() {
+            Some(mpu) => generate_mpu(mpu)?,
+            None => return Err(Error::NotReady),
+        };
 
         let a1 = (a & 0x3) << 6;
         let a2 = (d & 0x3) << 6;
@@ -131,7 +158,7 @@ impl RandomNumberGenerator {
 
         bits1 = xor(bits1, bits2);
 
-        bits1
+        Ok(bits1)
     }
 }
 
@@ -173,20 +200,34 @@ pub fn xor_shift(a: u8) -> u8 {
     ans
 }
 
+/// Reads the noise on the first analog pin.
+/// # Arguments
+/// * `pins` - a mutable reference to `Pins`, the pins of the board.
+/// # Returns
+/// * `a Result<u8>` - The low bits of the reading, or the error of the conversion.
+fn read_noise(pins: &mut Pins) -> Result<u8> {
+    #[cfg(feature = "atmega2560p")]
+    let value = pins.analog[0].read()?;
+    #[cfg(not(feature = "atmega2560p"))]
+    let value = pins.analog[0].read();
+    Ok(value as u8)
+}
+
 /// Generate XOR Rotation number.
 /// # Returns
-/// * `a u8` - A random number generated by various XOR's on sample generated through analog read.
-pub unsafe fn xor_rotate() -> u8 {
+/// * `a Result<u8>` - A random number generated by various XOR's on sample generated through analog read,
+///                    or the error of a conversion.
+pub unsafe fn xor_rotate() -> Result<u8> {
     let mut bits1: u8 = 0;
     let mut obj = RandomNumberGenerator::new(Generator::Analog);
 
     for i in 1..8 {
-        let a: u8 = obj.pins.analog[0].read() as u8;
+        let a: u8 = read_noise(&mut obj.pins)?;
         bits1 = xor(bits1, rotate(a, i));
         delay_ms(20);
     }
 
-    bits1
+    Ok(bits1)
 }
 
 /// Push the required bit with left bias.
@@ -211,25 +252,28 @@ pub fn push_right(val: u8, change: u8) -> u8 {
 
 /// Function to generate tuple containing u8 numbers
 /// accordingly through MPU6050 Gyroscopic Sensor.
+/// The waits between the readings use the delay provider of the sensor,
+/// so a `VirtualClock` skips them.
+/// # Arguments
+/// * `mpu` - a mutable reference to the `MPU6050` object to be read.
 /// # Returns
-/// * `a tuple of 6 u8's` - The x,y,z axes accelerations and gyroscopic detections by MPU6050 sensor respectively.
-pub fn generate_mpu() -> (u8, u8, u8, u8, u8, u8) {
-    let obj = RandomNumberGenerator::new(Generator::Mpu);
-
-    obj.mpu
-        .begin(MPUdpsT::MPU6050Scale250DPS, MPURangeT::MPU6050Range2G);
-
-    obj.mpu.read_gyro();
-    delay_ms(1000);
-
-    obj.mpu.read_accel();
-    delay_ms(1000);
-
-    let d: u8 = obj.mpu.gyro_output[0] as u8;
-    let e: u8 = obj.mpu.gyro_output[1] as u8;
-    let f: u8 = obj.mpu.gyro_output[2] as u8;
-    let a: u8 = obj.mpu.accel_output[0] as u8;
-    let b: u8 = obj.mpu.accel_output[1] as u8;
-    let c: u8 = obj.mpu.accel_output[2] as u8;
-    (a, b, c, d, e, f)
+/// * `a Result with tuple of 6 u8's` - The x,y,z axes accelerations and gyroscopic detections by MPU6050 sensor respectively.
+pub fn generate_mpu<I2C: I2c, D: DelayNs>(
+    mpu: &mut MPU6050<I2C, D>,
+) -> Result<(u8, u8, u8, u8, u8, u8)> {
+    mpu.begin(MPUdpsT::MPU6050Scale250DPS, MPURangeT::MPU6050Range2G)?;
+
+    mpu.read_gyro()?;
+    mpu.delay().delay_ms(1000);
+
+    mpu.read_accel()?;
+    mpu.delay().delay_ms(1000);
+
+    let d: u8 = mpu.gyro_output[0] as u8;
+    let e: u8 = mpu.gyro_output[1] as u8;
+    let f: u8 = mpu.gyro_output[2] as u8;
+    let a: u8 = mpu.accel_output[0] as u8;
+    let b: u8 = mpu.accel_output[1] as u8;
+    let c: u8 = mpu.accel_output[2] as u8;
+    Ok((a, b, c, d, e, f))
 }
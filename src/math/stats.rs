@@ -0,0 +1,296 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Integer only statistics over streams of u16 samples.
+//! Nothing here uses floating point, so these helpers are cheap enough
+//! to be run on every ADC conversion (RMS current, sensor self checks etc.).
+
+/// Computes the integer square root of a number using the bitwise method.
+/// # Arguments
+/// * `val` - a u32, the number whose square root is needed.
+/// # Returns
+/// * `a u16` - the largest number whose square is less than or equal to `val`.
+pub fn isqrt(val: u32) -> u16 {
+    let mut num = val;
+    let mut res: u32 = 0;
+    let mut bit: u32 = 1 << 30;
+
+    while bit > num {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if num >= res + bit {
+            num -= res + bit;
+            res = (res >> 1) + bit;
+        } else {
+            res >>= 1;
+        }
+        bit >>= 2;
+    }
+    res as u16
+}
+
+/// Computes the integer square root of a 64 bit number using the bitwise method.
+/// # Arguments
+/// * `val` - a u64, the number whose square root is needed.
+/// # Returns
+/// * `a u32` - the largest number whose square is less than or equal to `val`.
+pub fn isqrt64(val: u64) -> u32 {
+    let mut num = val;
+    let mut res: u64 = 0;
+    let mut bit: u64 = 1 << 62;
+
+    while bit > num {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if num >= res + bit {
+            num -= res + bit;
+            res = (res >> 1) + bit;
+        } else {
+            res >>= 1;
+        }
+        bit >>= 2;
+    }
+    res as u32
+}
+
+/// Running mean, variance and RMS of a stream of u16 samples.
+/// Sums are kept in 64 bits so that about 4 billion full scale
+/// samples can be pushed before the accumulator overflows.
+#[derive(Clone, Copy)]
+pub struct MeanVariance {
+    count: u32,
+    sum: u64,
+    sum_sq: u64,
+}
+
+impl MeanVariance {
+    /// Creates a new empty accumulator.
+    pub const fn new() -> MeanVariance {
+        MeanVariance {
+            count: 0,
+            sum: 0,
+            sum_sq: 0,
+        }
+    }
+
+    /// Adds a sample to the accumulator.
+    /// # Arguments
+    /// * `sample` - a u16, the new sample.
+    pub fn push(&mut self, sample: u16) {
+        let s = sample as u64;
+        self.count = self.count.wrapping_add(1);
+        self.sum += s;
+        self.sum_sq += s * s;
+    }
+
+    /// Clears all the samples pushed till now.
+    pub fn reset(&mut self) {
+        *self = MeanVariance::new();
+    }
+
+    /// Returns the number of samples pushed since the last reset.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the mean of the samples, rounded down.
+    /// # Returns
+    /// * `a u16` - the mean, 0 if no sample was pushed.
+    pub fn mean(&self) -> u16 {
+        if self.count == 0 {
+            return 0;
+        }
+        (self.sum / self.count as u64) as u16
+    }
+
+    /// Returns the population variance of the samples.
+    /// # Returns
+    /// * `a u32` - the variance, 0 if less than two samples were pushed.
+    pub fn variance(&self) -> u32 {
+        if self.count < 2 {
+            return 0;
+        }
+        // The variance is (n * sum(x^2) - sum(x)^2) / n^2, whose products overflow
+        // 64 bits for long streams. With sum = a * n + b and sum(x^2) = c * n + d it
+        // is c - a^2 + (d - 2 * a * b) / n - b^2 / n^2, where every term fits.
+        let n = self.count as u64;
+        let (a, b) = (self.sum / n, self.sum % n);
+        let (c, d) = (self.sum_sq / n, self.sum_sq % n);
+        let t = d as i64 - 2 * (a * b) as i64;
+        let (quotient, remainder) = (t.div_euclid(n as i64), t.rem_euclid(n as i64) as u64);
+        // remainder / n - b^2 / n^2 lies between -1 and 1.
+        let borrow = (remainder * n < b * b) as i64;
+        (c as i64 - (a * a) as i64 + quotient - borrow) as u32
+    }
+
+    /// Returns the standard deviation of the samples.
+    pub fn std_dev(&self) -> u16 {
+        isqrt(self.variance())
+    }
+
+    /// Returns the root mean square of the samples.
+    /// Useful for AC current measurement when the samples are already offset corrected.
+    pub fn rms(&self) -> u16 {
+        if self.count == 0 {
+            return 0;
+        }
+        isqrt64(self.sum_sq / self.count as u64) as u16
+    }
+}
+
+impl Default for MeanVariance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tracks the minimum and maximum of a stream of u16 samples.
+#[derive(Clone, Copy)]
+pub struct MinMax {
+    min: u16,
+    max: u16,
+    seen: bool,
+}
+
+impl MinMax {
+    /// Creates a new tracker which has not seen any sample.
+    pub const fn new() -> MinMax {
+        MinMax {
+            min: u16::MAX,
+            max: 0,
+            seen: false,
+        }
+    }
+
+    /// Adds a sample to the tracker.
+    /// # Arguments
+    /// * `sample` - a u16, the new sample.
+    pub fn push(&mut self, sample: u16) {
+        if sample < self.min {
+            self.min = sample;
+        }
+        if sample > self.max {
+            self.max = sample;
+        }
+        self.seen = true;
+    }
+
+    /// Forgets all the samples pushed till now.
+    pub fn reset(&mut self) {
+        *self = MinMax::new();
+    }
+
+    /// Returns the smallest sample seen, `None` if nothing was pushed.
+    pub fn min(&self) -> Option<u16> {
+        if self.seen {
+            Some(self.min)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the largest sample seen, `None` if nothing was pushed.
+    pub fn max(&self) -> Option<u16> {
+        if self.seen {
+            Some(self.max)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the difference between the largest and the smallest sample.
+    /// A stuck sensor shows a span of zero over a long window.
+    pub fn span(&self) -> u16 {
+        if self.seen {
+            self.max - self.min
+        } else {
+            0
+        }
+    }
+}
+
+impl Default for MinMax {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn square_roots() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(15), 3);
+        assert_eq!(isqrt(16), 4);
+        assert_eq!(isqrt(u32::MAX), 65535);
+        assert_eq!(isqrt64(u64::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn mean_and_variance() {
+        let mut stats = MeanVariance::new();
+        assert_eq!((stats.mean(), stats.variance(), stats.rms()), (0, 0, 0));
+        stats.push(1000);
+        stats.push(1001);
+        assert_eq!(stats.mean(), 1000);
+        // The exact variance is 0.25.
+        assert_eq!(stats.variance(), 0);
+        stats.reset();
+        for sample in [2, 4, 4, 4, 5, 5, 7, 9].iter() {
+            stats.push(*sample);
+        }
+        assert_eq!(stats.count(), 8);
+        assert_eq!((stats.mean(), stats.variance(), stats.std_dev()), (5, 4, 2));
+        assert_eq!(stats.rms(), 5);
+        stats.reset();
+        for _ in 0..1000 {
+            stats.push(u16::MAX);
+            stats.push(u16::MAX - 2);
+        }
+        assert_eq!(stats.variance(), 1);
+        // Four billion samples, half of them full scale and half zero.
+        let stats = MeanVariance {
+            count: 4_000_000_000,
+            sum: 2_000_000_000 * 65535,
+            sum_sq: 2_000_000_000 * 65535 * 65535,
+        };
+        assert_eq!(stats.variance(), 65535 * 65535 / 4);
+    }
+
+    #[test]
+    fn min_and_max() {
+        let mut tracker = MinMax::new();
+        assert_eq!(
+            (tracker.min(), tracker.max(), tracker.span()),
+            (None, None, 0)
+        );
+        for sample in [300, 12, 4000, 12].iter() {
+            tracker.push(*sample);
+        }
+        assert_eq!((tracker.min(), tracker.max()), (Some(12), Some(4000)));
+        assert_eq!(tracker.span(), 3988);
+        tracker.reset();
+        tracker.push(7);
+        assert_eq!(
+            (tracker.min(), tracker.max(), tracker.span()),
+            (Some(7), Some(7), 0)
+        );
+    }
+}
@@ -0,0 +1,589 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A small multi hop network for packet radios like the nRF24L01 or LoRa modules,
+//! so that sensor nodes out of the range of the base station can reach it through
+//! other nodes. Every node has a one byte id, the base station being `BASE_STATION`.
+//! Every frame names its final destination and the node which should forward it
+//! next, so all the nodes can share one radio address. A node forwards the frames
+//! for unknown destinations to its parent, towards the base station, and learns
+//! the way back to every node it hears from. Messages sent with `Mesh::send`
+//! are acknowledged by their destination and repeated until the acknowledgement
+//! arrives or the retries are exhausted.
+
+/// Largest frame sent over the radio, the payload size of an nRF24L01.
+pub const MAX_FRAME: usize = 32;
+
+/// Bytes of every frame taken by the header.
+pub const HEADER_LEN: usize = 6;
+
+/// Largest payload of a message.
+pub const MAX_PAYLOAD: usize = MAX_FRAME - HEADER_LEN;
+
+/// Id of a node in the network.
+pub type NodeId = u8;
+
+/// Id of the base station.
+pub const BASE_STATION: NodeId = 0;
+
+/// Id addressing every node in range.
+pub const BROADCAST: NodeId = 0xFF;
+
+/// Number of hops after which a frame is dropped.
+const MAX_HOPS: u8 = 15;
+
+/// Kinds of frames, in the upper nibble of the last header byte.
+const DATA: u8 = 0x10;
+const ACK: u8 = 0x20;
+
+/// A packet radio, as used by the network.
+pub trait Radio {
+    /// Sends a frame to every node in range.
+    /// # Returns
+    /// * `a boolean` - false if the frame could not be sent.
+    fn transmit(&mut self, frame: &[u8]) -> bool;
+
+    /// Takes a received frame.
+    /// # Returns
+    /// * `a Option<usize>` - the length of the frame written to `buffer`, `None` if none was received.
+    fn receive(&mut self, buffer: &mut [u8; MAX_FRAME]) -> Option<usize>;
+}
+
+/// A message received from another node.
+/// # Elements
+/// * `source` - a `NodeId`, the node which sent the message.
+/// * `len` - a usize, the number of bytes of `data` in use.
+/// * `data` - an array of u8, the payload.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Message {
+    pub source: NodeId,
+    pub len: usize,
+    pub data: [u8; MAX_PAYLOAD],
+}
+
+impl Message {
+    /// Returns the payload.
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// State of the last message sent with `Mesh::send`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Delivery {
+    /// Waiting for the acknowledgement.
+    Pending,
+    /// The destination acknowledged the message.
+    Delivered,
+    /// No acknowledgement came back after all the retries.
+    Failed,
+}
+
+/// A frame header.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Header {
+    destination: NodeId,
+    source: NodeId,
+    /// The node which should handle the frame next.
+    hop: NodeId,
+    /// The node which sent the frame over the last hop.
+    previous: NodeId,
+    sequence: u8,
+    /// Kind in the upper nibble, hops left in the lower one.
+    kind: u8,
+}
+
+impl Header {
+    fn write(&self, frame: &mut [u8]) {
+        frame[..HEADER_LEN].copy_from_slice(&[
+            self.destination,
+            self.source,
+            self.hop,
+            self.previous,
+            self.sequence,
+            self.kind,
+        ]);
+    }
+
+    fn read(frame: &[u8]) -> Header {
+        Header {
+            destination: frame[0],
+            source: frame[1],
+            hop: frame[2],
+            previous: frame[3],
+            sequence: frame[4],
+            kind: frame[5],
+        }
+    }
+}
+
+/// A route learnt from the traffic.
+#[derive(Clone, Copy)]
+struct Route {
+    destination: NodeId,
+    next_hop: NodeId,
+}
+
+/// The message waiting for its acknowledgement.
+#[derive(Clone, Copy)]
+struct Pending {
+    frame: [u8; MAX_FRAME],
+    len: usize,
+    sent_at: u32,
+    attempts: u8,
+}
+
+/// A node of the network, remembering up to `R` routes.
+pub struct Mesh<T: Radio, const R: usize> {
+    radio: T,
+    id: NodeId,
+    parent: Option<NodeId>,
+    routes: [Option<Route>; R],
+    sequence: u8,
+    pending: Option<Pending>,
+    delivery: Delivery,
+    inbox: Option<Message>,
+    /// Source and sequence of the last messages received, to drop repeated ones.
+    seen: [(NodeId, u8); 4],
+    retries: u8,
+    timeout: u32,
+}
+
+impl<T: Radio, const R: usize> Mesh<T, R> {
+    /// Creates a node.
+    /// # Arguments
+    /// * `radio` - a `Radio` object, listening to the address shared by the network.
+    /// * `id` - a `NodeId`, the id of this node, `BASE_STATION` for the base station.
+    /// * `parent` - a `Option<NodeId>`, the neighbour towards the base station, `None` for the base station or a node in its range.
+    pub fn new(radio: T, id: NodeId, parent: Option<NodeId>) -> Self {
+        Mesh {
+            radio,
+            id,
+            parent,
+            routes: [None; R],
+            sequence: 0,
+            pending: None,
+            delivery: Delivery::Delivered,
+            inbox: None,
+            seen: [(BROADCAST, 0); 4],
+            retries: 3,
+            timeout: 100,
+        }
+    }
+
+    /// Sets how often and after how long an unacknowledged message is repeated.
+    /// # Arguments
+    /// * `retries` - a u8, the number of repetitions.
+    /// * `timeout` - a u32, the time to wait for the acknowledgement, in the unit of `now` given to `poll`.
+    pub fn set_retries(&mut self, retries: u8, timeout: u32) {
+        self.retries = retries;
+        self.timeout = timeout;
+    }
+
+    /// Returns the id of this node.
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Returns the next hop towards a node.
+    fn next_hop(&self, destination: NodeId) -> NodeId {
+        if destination == BROADCAST {
+            return BROADCAST;
+        }
+        self.routes
+            .iter()
+            .flatten()
+            .find(|r| r.destination == destination)
+            .map(|r| r.next_hop)
+            .or(self.parent)
+            .unwrap_or(destination)
+    }
+
+    /// Remembers that `destination` can be reached through `next_hop`.
+    fn learn(&mut self, destination: NodeId, next_hop: NodeId) {
+        if destination == self.id || destination == BROADCAST {
+            return;
+        }
+        let slot = self
+            .routes
+            .iter()
+            .position(|r| matches!(r, Some(r) if r.destination == destination))
+            .or_else(|| self.routes.iter().position(|r| r.is_none()));
+        // Without a free slot the oldest route is forgotten.
+        let index = match slot {
+            Some(index) => index,
+            None if R > 0 => {
+                self.routes.rotate_left(1);
+                R - 1
+            }
+            None => return,
+        };
+        self.routes[index] = Some(Route {
+            destination,
+            next_hop,
+        });
+    }
+
+    /// Sends a frame over the next hop.
+    fn transmit(&mut self, frame: &mut [u8], header: Header) -> bool {
+        Header {
+            hop: self.next_hop(header.destination),
+            previous: self.id,
+            ..header
+        }
+        .write(frame);
+        self.radio.transmit(frame)
+    }
+
+    /// Sends a message without waiting for an acknowledgement.
+    /// # Arguments
+    /// * `destination` - a `NodeId`, the destination, `BROADCAST` for every node in range.
+    /// * `payload` - a reference to `[u8]`, at most `MAX_PAYLOAD` bytes.
+    /// # Returns
+    /// * `a boolean` - false if the payload is too long or the radio failed.
+    pub fn send_unreliable(&mut self, destination: NodeId, payload: &[u8]) -> bool {
+        if payload.len() > MAX_PAYLOAD {
+            return false;
+        }
+        let mut frame = [0; MAX_FRAME];
+        frame[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+        self.sequence = self.sequence.wrapping_add(1);
+        let header = self.header(destination, DATA);
+        self.transmit(&mut frame[..HEADER_LEN + payload.len()], header)
+    }
+
+    /// Sends a message which the destination has to acknowledge.
+    /// Only one such message is in flight at a time, `delivery` tells its fate.
+    /// # Arguments
+    /// * `destination` - a `NodeId`, the destination.
+    /// * `payload` - a reference to `[u8]`, at most `MAX_PAYLOAD` bytes.
+    /// * `now` - a u32, the current time, usually `millis()`.
+    /// # Returns
+    /// * `a boolean` - false if the payload is too long or the previous message is still pending.
+    pub fn send(&mut self, destination: NodeId, payload: &[u8], now: u32) -> bool {
+        if payload.len() > MAX_PAYLOAD || destination == BROADCAST || self.pending.is_some() {
+            return false;
+        }
+        let mut frame = [0; MAX_FRAME];
+        let len = HEADER_LEN + payload.len();
+        frame[HEADER_LEN..len].copy_from_slice(payload);
+        self.sequence = self.sequence.wrapping_add(1);
+        let header = self.header(destination, DATA);
+        self.transmit(&mut frame[..len], header);
+        self.pending = Some(Pending {
+            frame,
+            len,
+            sent_at: now,
+            attempts: 0,
+        });
+        self.delivery = Delivery::Pending;
+        true
+    }
+
+    fn header(&self, destination: NodeId, kind: u8) -> Header {
+        Header {
+            destination,
+            source: self.id,
+            hop: BROADCAST,
+            previous: self.id,
+            sequence: self.sequence,
+            kind: kind | MAX_HOPS,
+        }
+    }
+
+    /// Returns the state of the last message sent with `send`.
+    pub fn delivery(&self) -> Delivery {
+        self.delivery
+    }
+
+    /// Takes the last message received for this node.
+    pub fn receive(&mut self) -> Option<Message> {
+        self.inbox.take()
+    }
+
+    /// Handles the received frames, forwarding, acknowledging or keeping them,
+    /// and repeats the pending message when its acknowledgement is late.
+    /// Has to be called often, for example from the main loop.
+    /// # Arguments
+    /// * `now` - a u32, the current time, usually `millis()`.
+    pub fn poll(&mut self, now: u32) {
+        let mut frame = [0; MAX_FRAME];
+        while let Some(len) = self.radio.receive(&mut frame) {
+            if (HEADER_LEN..=MAX_FRAME).contains(&len) {
+                self.handle(&mut frame[..len]);
+            }
+        }
+
+        if let Some(mut pending) = self.pending {
+            if now.wrapping_sub(pending.sent_at) >= self.timeout {
+                if pending.attempts >= self.retries {
+                    self.pending = None;
+                    self.delivery = Delivery::Failed;
+                } else {
+                    pending.attempts += 1;
+                    pending.sent_at = now;
+                    let header = Header::read(&pending.frame);
+                    self.transmit(&mut pending.frame[..pending.len], header);
+                    self.pending = Some(pending);
+                }
+            }
+        }
+    }
+
+    /// Handles one received frame.
+    fn handle(&mut self, frame: &mut [u8]) {
+        let header = Header::read(frame);
+        if header.hop != self.id && header.hop != BROADCAST {
+            return;
+        }
+        if header.source == self.id {
+            return;
+        }
+        self.learn(header.source, header.previous);
+
+        if header.destination != self.id && header.destination != BROADCAST {
+            let hops = header.kind & 0x0F;
+            if hops > 1 {
+                let header = Header {
+                    kind: (header.kind & 0xF0) | (hops - 1),
+                    ..header
+                };
+                self.transmit(frame, header);
+            }
+            return;
+        }
+
+        match header.kind & 0xF0 {
+            DATA => {
+                let repeated = self.seen.contains(&(header.source, header.sequence));
+                if !repeated {
+                    if self.inbox.is_some() {
+                        // Not acknowledged, so that the sender repeats it later.
+                        return;
+                    }
+                    let mut data = [0; MAX_PAYLOAD];
+                    let len = frame.len() - HEADER_LEN;
+                    data[..len].copy_from_slice(&frame[HEADER_LEN..]);
+                    self.inbox = Some(Message {
+                        source: header.source,
+                        len,
+                        data,
+                    });
+                    self.seen.rotate_right(1);
+                    self.seen[0] = (header.source, header.sequence);
+                }
+                if header.destination == self.id {
+                    let mut ack = [0; HEADER_LEN];
+                    let reply = Header {
+                        destination: header.source,
+                        sequence: header.sequence,
+                        ..self.header(header.source, ACK)
+                    };
+                    self.transmit(&mut ack, reply);
+                }
+            }
+            ACK => {
+                if let Some(pending) = self.pending {
+                    let sent = Header::read(&pending.frame);
+                    if sent.destination == header.source && sent.sequence == header.sequence {
+                        self.pending = None;
+                        self.delivery = Delivery::Delivered;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use core::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    /// Frames in the air, per receiving node, and the links between nodes.
+    struct Air {
+        queues: [VecDeque<Vec<u8>>; 4],
+        links: &'static [(u8, u8)],
+        drop_next: usize,
+    }
+
+    struct TestRadio<'a> {
+        id: u8,
+        air: &'a RefCell<Air>,
+    }
+
+    impl<'a> Radio for TestRadio<'a> {
+        fn transmit(&mut self, frame: &[u8]) -> bool {
+            let mut air = self.air.borrow_mut();
+            if air.drop_next > 0 {
+                air.drop_next -= 1;
+                return true;
+            }
+            for &(a, b) in air.links {
+                let to = if a == self.id {
+                    b
+                } else if b == self.id {
+                    a
+                } else {
+                    continue;
+                };
+                air.queues[to as usize].push_back(frame.to_vec());
+            }
+            true
+        }
+
+        fn receive(&mut self, buffer: &mut [u8; MAX_FRAME]) -> Option<usize> {
+            let frame = self.air.borrow_mut().queues[self.id as usize].pop_front()?;
+            buffer[..frame.len()].copy_from_slice(&frame);
+            Some(frame.len())
+        }
+    }
+
+    fn air(links: &'static [(u8, u8)]) -> RefCell<Air> {
+        RefCell::new(Air {
+            queues: Default::default(),
+            links,
+            drop_next: 0,
+        })
+    }
+
+    #[test]
+    fn frame_layout() {
+        let header = Header {
+            destination: 0,
+            source: 2,
+            hop: 1,
+            previous: 2,
+            sequence: 7,
+            kind: DATA | MAX_HOPS,
+        };
+        let mut frame = [0; HEADER_LEN];
+        header.write(&mut frame);
+        assert_eq!(frame, [0, 2, 1, 2, 7, 0x1F]);
+        assert_eq!(Header::read(&frame), header);
+
+        // A node sends its frames to the parent, with all the hops left.
+        let air = air(&[(1, 2)]);
+        let mut node: Mesh<_, 2> = Mesh::new(TestRadio { id: 2, air: &air }, 2, Some(1));
+        assert!(node.send_unreliable(BASE_STATION, b"hi"));
+        assert!(!node.send_unreliable(BASE_STATION, &[0; MAX_PAYLOAD + 1]));
+        let sent = air.borrow_mut().queues[1].pop_front().unwrap();
+        assert_eq!(sent, [0, 2, 1, 2, 1, 0x1F, b'h', b'i']);
+    }
+
+    #[test]
+    fn forwarding_counts_the_hops() {
+        let air = air(&[(0, 1), (1, 2)]);
+        let mut relay: Mesh<_, 2> = Mesh::new(TestRadio { id: 1, air: &air }, 1, Some(0));
+
+        // A frame from node 2 to the base station goes on with one hop less.
+        air.borrow_mut().queues[1].push_back([0, 2, 1, 2, 5, DATA | 3].to_vec());
+        relay.poll(0);
+        assert_eq!(
+            air.borrow_mut().queues[0].pop_front().unwrap(),
+            [0, 2, 0, 1, 5, DATA | 2]
+        );
+
+        // One on its last hop or meant for another relay is dropped.
+        air.borrow_mut().queues[1].push_back([0, 2, 1, 2, 6, DATA | 1].to_vec());
+        air.borrow_mut().queues[1].push_back([0, 2, 3, 2, 7, DATA | 3].to_vec());
+        relay.poll(0);
+        assert!(air.borrow().queues[0].is_empty());
+        assert_eq!(relay.receive(), None);
+    }
+
+    #[test]
+    fn repeated_messages_are_only_kept_once() {
+        let air = air(&[(0, 1)]);
+        let mut base: Mesh<_, 2> = Mesh::new(TestRadio { id: 0, air: &air }, 0, None);
+        let frame = [0, 1, 0, 1, 9, DATA | MAX_HOPS, 42];
+        air.borrow_mut().queues[0].push_back(frame.to_vec());
+        base.poll(0);
+        assert_eq!(base.receive().unwrap().payload(), [42]);
+
+        // The acknowledgement was lost, so the message comes again.
+        air.borrow_mut().queues[0].push_back(frame.to_vec());
+        base.poll(0);
+        assert_eq!(base.receive(), None);
+        let mut air = air.borrow_mut();
+        assert_eq!(air.queues[1].len(), 2);
+        assert_eq!(
+            air.queues[1].pop_front().unwrap(),
+            [1, 0, 1, 0, 9, ACK | MAX_HOPS]
+        );
+    }
+
+    #[test]
+    fn messages_travel_over_two_hops_and_back() {
+        // 0 - 1 - 2, node 2 is out of the range of the base station.
+        let air = air(&[(0, 1), (1, 2)]);
+        let mut base: Mesh<_, 4> = Mesh::new(TestRadio { id: 0, air: &air }, 0, None);
+        let mut relay: Mesh<_, 4> = Mesh::new(TestRadio { id: 1, air: &air }, 1, None);
+        let mut node: Mesh<_, 4> = Mesh::new(TestRadio { id: 2, air: &air }, 2, Some(1));
+
+        assert!(node.send(BASE_STATION, b"21.5C", 0));
+        for _ in 0..3 {
+            relay.poll(1);
+            base.poll(1);
+            node.poll(1);
+        }
+        let message = base.receive().unwrap();
+        assert_eq!(message.source, 2);
+        assert_eq!(message.payload(), b"21.5C");
+        assert_eq!(node.delivery(), Delivery::Delivered);
+
+        // The base station learnt the way back through the relay.
+        assert!(base.send(2, b"ok", 2));
+        for _ in 0..3 {
+            relay.poll(3);
+            node.poll(3);
+            base.poll(3);
+        }
+        assert_eq!(node.receive().unwrap().payload(), b"ok");
+        assert_eq!(base.delivery(), Delivery::Delivered);
+    }
+
+    #[test]
+    fn lost_frames_are_repeated_then_given_up() {
+        let air = air(&[(0, 1)]);
+        let mut base: Mesh<_, 2> = Mesh::new(TestRadio { id: 0, air: &air }, 0, None);
+        let mut node: Mesh<_, 2> = Mesh::new(TestRadio { id: 1, air: &air }, 1, None);
+        node.set_retries(2, 10);
+
+        air.borrow_mut().drop_next = 1;
+        assert!(node.send(BASE_STATION, b"x", 0));
+        base.poll(5);
+        node.poll(5);
+        assert_eq!(node.delivery(), Delivery::Pending);
+        node.poll(10);
+        base.poll(11);
+        node.poll(11);
+        assert_eq!(node.delivery(), Delivery::Delivered);
+        assert_eq!(base.receive().unwrap().payload(), b"x");
+
+        air.borrow_mut().drop_next = 10;
+        assert!(node.send(BASE_STATION, b"y", 20));
+        for now in [30, 40, 50] {
+            node.poll(now);
+        }
+        assert_eq!(node.delivery(), Delivery::Failed);
+        assert_eq!(base.receive(), None);
+    }
+}
@@ -0,0 +1,561 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A compact telemetry format for links to a ground station, framed like
+//! MAVLink 1: a start byte, the payload length, a sequence number, the ids of
+//! the sending system and component, the message id, the little endian payload
+//! and a CRC-16/MCRF4XX (X.25) over everything after the start byte. As in
+//! MAVLink, the CRC also covers a `CRC_EXTRA` byte known to both sides for each
+//! message, so that a peer with a different idea of a message rejects it.
+//! The message ids and layouts are this crate's own, a subset sized for small
+//! rovers and drones, so the frames are not readable by MAVLink tools.
+//! Payloads are limited to `MAX_PAYLOAD` bytes to keep the parser small.
+//! With the `std` feature the messages can be serialized with serde, see `host`.
+
+use crate::encoding::crc::crc16_x25_update;
+
+/// First byte of every frame.
+pub const STX: u8 = 0xFE;
+
+/// Bytes before the payload, including the start byte.
+pub const FRAME_HEADER_LEN: usize = 6;
+
+/// Largest payload of a message.
+pub const MAX_PAYLOAD: usize = 32;
+
+/// Largest frame, with the header and the CRC.
+pub const MAX_FRAME: usize = FRAME_HEADER_LEN + MAX_PAYLOAD + 2;
+
+/// A message which can be sent in a telemetry frame.
+pub trait TelemetryMessage: Sized {
+    /// Id of the message.
+    const ID: u8;
+    /// Byte added to the CRC, specific to the layout of the message.
+    const CRC_EXTRA: u8;
+    /// Length of the payload.
+    const LEN: usize;
+
+    /// Writes the payload, `payload` being `LEN` bytes long.
+    fn write(&self, payload: &mut [u8]);
+
+    /// Reads the payload, `payload` being `LEN` bytes long.
+    fn read(payload: &[u8]) -> Self;
+}
+
+/// Sender and sequence number of a frame.
+/// # Elements
+/// * `sequence` - a u8, incremented by the sender for every frame, to detect losses.
+/// * `system` - a u8, the id of the vehicle.
+/// * `component` - a u8, the id of the part of the vehicle sending.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameHeader {
+    pub sequence: u8,
+    pub system: u8,
+    pub component: u8,
+}
+
+/// Encodes a message into a frame.
+/// # Arguments
+/// * `message` - a reference to a `TelemetryMessage`, the message.
+/// * `header` - a `FrameHeader`, the sender and sequence number.
+/// * `out` - a mutable reference to `[u8]`, where the frame is written.
+/// # Returns
+/// * `a Option<usize>` - the length of the frame, `None` if `out` is too small.
+pub fn encode<M: TelemetryMessage>(
+    message: &M,
+    header: FrameHeader,
+    out: &mut [u8],
+) -> Option<usize> {
+    let end = FRAME_HEADER_LEN + M::LEN;
+    if M::LEN > MAX_PAYLOAD || out.len() < end + 2 {
+        return None;
+    }
+    out[..FRAME_HEADER_LEN].copy_from_slice(&[
+        STX,
+        M::LEN as u8,
+        header.sequence,
+        header.system,
+        header.component,
+        M::ID,
+    ]);
+    message.write(&mut out[FRAME_HEADER_LEN..end]);
+    let crc = crc16_x25_update(crc16_x25_update(0xFFFF, &out[1..end]), &[M::CRC_EXTRA]);
+    out[end..end + 2].copy_from_slice(&crc.to_le_bytes());
+    Some(end + 2)
+}
+
+/// A received frame whose CRC matched.
+/// # Elements
+/// * `header` - a `FrameHeader`, the sender and sequence number.
+/// * `id` - a u8, the message id.
+/// * `len` - a usize, the length of the payload.
+/// * `payload` - an array of u8, the payload.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Frame {
+    pub header: FrameHeader,
+    pub id: u8,
+    pub len: usize,
+    pub payload: [u8; MAX_PAYLOAD],
+}
+
+impl Frame {
+    /// Reads the frame as a given message.
+    /// # Returns
+    /// * `a Option<M>` - `None` if the frame holds another message.
+    pub fn message<M: TelemetryMessage>(&self) -> Option<M> {
+        if self.id != M::ID || self.len != M::LEN {
+            return None;
+        }
+        Some(M::read(&self.payload[..self.len]))
+    }
+}
+
+/// States of the parser.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Idle,
+    Header,
+    Body,
+}
+
+/// Finds frames in a stream of received bytes.
+pub struct Parser {
+    state: State,
+    buffer: [u8; MAX_FRAME],
+    index: usize,
+    registry: fn(u8) -> Option<u8>,
+    errors: u16,
+}
+
+impl Parser {
+    /// Creates a parser for the standard messages of this module.
+    pub const fn new() -> Parser {
+        Parser::with_registry(crc_extra)
+    }
+
+    /// Creates a parser also accepting application specific messages.
+    /// # Arguments
+    /// * `registry` - a function giving the `CRC_EXTRA` of a message id, `None` for an unknown message.
+    pub const fn with_registry(registry: fn(u8) -> Option<u8>) -> Parser {
+        Parser {
+            state: State::Idle,
+            buffer: [0; MAX_FRAME],
+            index: 0,
+            registry,
+            errors: 0,
+        }
+    }
+
+    /// Returns the number of frames dropped for a wrong CRC or an unknown message id.
+    pub fn errors(&self) -> u16 {
+        self.errors
+    }
+
+    /// Handles one received byte.
+    /// # Arguments
+    /// * `byte` - a u8, the byte received.
+    /// # Returns
+    /// * `a Option<Frame>` - the frame completed by the byte, if it is valid.
+    pub fn push(&mut self, byte: u8) -> Option<Frame> {
+        match self.state {
+            State::Idle => {
+                if byte == STX {
+                    self.buffer[0] = byte;
+                    self.index = 1;
+                    self.state = State::Header;
+                }
+                None
+            }
+            State::Header => {
+                if self.index == 1 && byte as usize > MAX_PAYLOAD {
+                    self.state = State::Idle;
+                    self.errors = self.errors.wrapping_add(1);
+                    return None;
+                }
+                self.buffer[self.index] = byte;
+                self.index += 1;
+                if self.index == FRAME_HEADER_LEN {
+                    self.state = State::Body;
+                }
+                None
+            }
+            State::Body => {
+                self.buffer[self.index] = byte;
+                self.index += 1;
+                let len = self.buffer[1] as usize;
+                let end = FRAME_HEADER_LEN + len;
+                if self.index < end + 2 {
+                    return None;
+                }
+                self.state = State::Idle;
+                let id = self.buffer[5];
+                let valid = (self.registry)(id).map_or(false, |extra| {
+                    let crc =
+                        crc16_x25_update(crc16_x25_update(0xFFFF, &self.buffer[1..end]), &[extra]);
+                    crc.to_le_bytes() == [self.buffer[end], self.buffer[end + 1]]
+                });
+                if !valid {
+                    self.errors = self.errors.wrapping_add(1);
+                    return None;
+                }
+                let mut payload = [0; MAX_PAYLOAD];
+                payload[..len].copy_from_slice(&self.buffer[FRAME_HEADER_LEN..end]);
+                Some(Frame {
+                    header: FrameHeader {
+                        sequence: self.buffer[2],
+                        system: self.buffer[3],
+                        component: self.buffer[4],
+                    },
+                    id,
+                    len,
+                    payload,
+                })
+            }
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn put_u16(out: &mut [u8], at: usize, value: u16) {
+    out[at..at + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut [u8], at: usize, value: u32) {
+    out[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn get_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// Sent regularly so that the ground station knows the vehicle is alive.
+/// # Elements
+/// * `uptime` - a u32, the milliseconds since start up.
+/// * `mode` - a u8, the mode of the vehicle, defined by the application.
+/// * `status` - a u8, the state of the vehicle, defined by the application.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct Heartbeat {
+    pub uptime: u32,
+    pub mode: u8,
+    pub status: u8,
+}
+
+impl TelemetryMessage for Heartbeat {
+    const ID: u8 = 0;
+    const CRC_EXTRA: u8 = 50;
+    const LEN: usize = 6;
+
+    fn write(&self, payload: &mut [u8]) {
+        put_u32(payload, 0, self.uptime);
+        payload[4] = self.mode;
+        payload[5] = self.status;
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        Heartbeat {
+            uptime: get_u32(payload, 0),
+            mode: payload[4],
+            status: payload[5],
+        }
+    }
+}
+
+/// Raw readings of an inertial measurement unit, in the units of the sensor.
+/// # Elements
+/// * `acceleration` - an array of i16, along x, y and z.
+/// * `rotation` - an array of i16, around x, y and z.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawImu {
+    pub acceleration: [i16; 3],
+    pub rotation: [i16; 3],
+}
+
+impl TelemetryMessage for RawImu {
+    const ID: u8 = 27;
+    const CRC_EXTRA: u8 = 144;
+    const LEN: usize = 12;
+
+    fn write(&self, payload: &mut [u8]) {
+        for (i, value) in self.acceleration.iter().chain(&self.rotation).enumerate() {
+            put_u16(payload, 2 * i, *value as u16);
+        }
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        let value = |i: usize| get_u16(payload, 2 * i) as i16;
+        RawImu {
+            acceleration: [value(0), value(1), value(2)],
+            rotation: [value(3), value(4), value(5)],
+        }
+    }
+}
+
+/// Orientation of the vehicle, in hundredths of a degree.
+/// # Elements
+/// * `roll` - a i16, positive with the right side down.
+/// * `pitch` - a i16, positive with the nose up.
+/// * `yaw` - a i16, clockwise from north.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct Attitude {
+    pub roll: i16,
+    pub pitch: i16,
+    pub yaw: i16,
+}
+
+impl TelemetryMessage for Attitude {
+    const ID: u8 = 30;
+    const CRC_EXTRA: u8 = 39;
+    const LEN: usize = 6;
+
+    fn write(&self, payload: &mut [u8]) {
+        put_u16(payload, 0, self.roll as u16);
+        put_u16(payload, 2, self.pitch as u16);
+        put_u16(payload, 4, self.yaw as u16);
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        Attitude {
+            roll: get_u16(payload, 0) as i16,
+            pitch: get_u16(payload, 2) as i16,
+            yaw: get_u16(payload, 4) as i16,
+        }
+    }
+}
+
+/// Position of the vehicle.
+/// # Elements
+/// * `latitude` - a i32, in ten millionths of a degree.
+/// * `longitude` - a i32, in ten millionths of a degree.
+/// * `altitude` - a i32, above sea level in millimeters.
+/// * `heading` - a u16, the direction of travel in hundredths of a degree.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub latitude: i32,
+    pub longitude: i32,
+    pub altitude: i32,
+    pub heading: u16,
+}
+
+impl TelemetryMessage for Position {
+    const ID: u8 = 33;
+    const CRC_EXTRA: u8 = 104;
+    const LEN: usize = 14;
+
+    fn write(&self, payload: &mut [u8]) {
+        put_u32(payload, 0, self.latitude as u32);
+        put_u32(payload, 4, self.longitude as u32);
+        put_u32(payload, 8, self.altitude as u32);
+        put_u16(payload, 12, self.heading);
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        Position {
+            latitude: get_u32(payload, 0) as i32,
+            longitude: get_u32(payload, 4) as i32,
+            altitude: get_u32(payload, 8) as i32,
+            heading: get_u16(payload, 12),
+        }
+    }
+}
+
+/// State of the battery.
+/// # Elements
+/// * `millivolts` - a u16, the voltage of the pack.
+/// * `current` - a i16, the current drawn in units of 10 mA, negative while charging.
+/// * `remaining` - a u8, the charge left in percent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatteryStatus {
+    pub millivolts: u16,
+    pub current: i16,
+    pub remaining: u8,
+}
+
+impl TelemetryMessage for BatteryStatus {
+    const ID: u8 = 147;
+    const CRC_EXTRA: u8 = 154;
+    const LEN: usize = 5;
+
+    fn write(&self, payload: &mut [u8]) {
+        put_u16(payload, 0, self.millivolts);
+        put_u16(payload, 2, self.current as u16);
+        payload[4] = self.remaining;
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        BatteryStatus {
+            millivolts: get_u16(payload, 0),
+            current: get_u16(payload, 2) as i16,
+            remaining: payload[4],
+        }
+    }
+}
+
+/// Readings of environmental sensors.
+/// # Elements
+/// * `temperature` - a i16, in hundredths of a degree Celsius.
+/// * `humidity` - a u16, the relative humidity in hundredths of a percent.
+/// * `pressure` - a u32, in pascals.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub struct Environment {
+    pub temperature: i16,
+    pub humidity: u16,
+    pub pressure: u32,
+}
+
+impl TelemetryMessage for Environment {
+    const ID: u8 = 200;
+    const CRC_EXTRA: u8 = 75;
+    const LEN: usize = 8;
+
+    fn write(&self, payload: &mut [u8]) {
+        put_u16(payload, 0, self.temperature as u16);
+        put_u16(payload, 2, self.humidity);
+        put_u32(payload, 4, self.pressure);
+    }
+
+    fn read(payload: &[u8]) -> Self {
+        Environment {
+            temperature: get_u16(payload, 0) as i16,
+            humidity: get_u16(payload, 2),
+            pressure: get_u32(payload, 4),
+        }
+    }
+}
+
+/// Gives the `CRC_EXTRA` of the standard messages, the registry used by `Parser::new`.
+/// Application registries should fall back to this function for the ids they do not define.
+/// # Arguments
+/// * `id` - a u8, the message id.
+/// # Returns
+/// * `a Option<u8>` - `None` for an unknown message.
+pub fn crc_extra(id: u8) -> Option<u8> {
+    match id {
+        Heartbeat::ID => Some(Heartbeat::CRC_EXTRA),
+        RawImu::ID => Some(RawImu::CRC_EXTRA),
+        Attitude::ID => Some(Attitude::CRC_EXTRA),
+        Position::ID => Some(Position::CRC_EXTRA),
+        BatteryStatus::ID => Some(BatteryStatus::CRC_EXTRA),
+        Environment::ID => Some(Environment::CRC_EXTRA),
+        _ => None,
+    }
+}
+
+/// Any of the standard messages.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+pub enum Telemetry {
+    Heartbeat(Heartbeat),
+    RawImu(RawImu),
+    Attitude(Attitude),
+    Position(Position),
+    BatteryStatus(BatteryStatus),
+    Environment(Environment),
+}
+
+impl Telemetry {
+    /// Reads a frame holding one of the standard messages.
+    /// # Arguments
+    /// * `frame` - a reference to `Frame`, as returned by `Parser::push`.
+    /// # Returns
+    /// * `a Option<Telemetry>` - `None` for another message.
+    pub fn from_frame(frame: &Frame) -> Option<Telemetry> {
+        match frame.id {
+            Heartbeat::ID => frame.message().map(Telemetry::Heartbeat),
+            RawImu::ID => frame.message().map(Telemetry::RawImu),
+            Attitude::ID => frame.message().map(Telemetry::Attitude),
+            Position::ID => frame.message().map(Telemetry::Position),
+            BatteryStatus::ID => frame.message().map(Telemetry::BatteryStatus),
+            Environment::ID => frame.message().map(Telemetry::Environment),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEADER: FrameHeader = FrameHeader {
+        sequence: 7,
+        system: 1,
+        component: 2,
+    };
+
+    #[test]
+    fn frames_round_trip_through_the_parser() {
+        let position = Position {
+            latitude: 265_123_456,
+            longitude: 803_654_321,
+            altitude: -12_000,
+            heading: 27_000,
+        };
+        let mut frame = [0; MAX_FRAME];
+        let len = encode(&position, HEADER, &mut frame).unwrap();
+        assert_eq!(len, FRAME_HEADER_LEN + Position::LEN + 2);
+        assert_eq!(&frame[..6], &[STX, 14, 7, 1, 2, 33]);
+
+        let mut parser = Parser::new();
+        // Noise before the frame is skipped.
+        assert_eq!(parser.push(0x00), None);
+        let mut received = None;
+        for byte in &frame[..len] {
+            received = parser.push(*byte).or(received);
+        }
+        let received = received.unwrap();
+        assert_eq!(received.header, HEADER);
+        assert_eq!(
+            Telemetry::from_frame(&received),
+            Some(Telemetry::Position(position))
+        );
+        assert_eq!(received.message::<Attitude>(), None);
+    }
+
+    #[test]
+    fn corrupted_frames_are_dropped() {
+        let battery = BatteryStatus {
+            millivolts: 11_100,
+            current: -150,
+            remaining: 80,
+        };
+        let mut frame = [0; MAX_FRAME];
+        let len = encode(&battery, HEADER, &mut frame).unwrap();
+        frame[7] ^= 0x10;
+        let mut parser = Parser::new();
+        assert!(frame[..len].iter().all(|b| parser.push(*b).is_none()));
+        assert_eq!(parser.errors(), 1);
+
+        frame[7] ^= 0x10;
+        let frame = &frame[..len];
+        let heard = frame.iter().filter_map(|b| parser.push(*b)).next().unwrap();
+        assert_eq!(heard.message(), Some(battery));
+    }
+}
@@ -0,0 +1,252 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A lightweight time synchronization protocol, so that the logs of several
+//! nodes share the time base of one of them, usually the base station.
+//! A node sends a request stamped with its local time, the time server answers
+//! with the times at which it received the request and sent the answer, and the
+//! node stamps the arrival of the answer. Like in NTP, the offset between both
+//! clocks is then computed assuming the same latency in both directions, which
+//! cancels the latency of the link. The messages are plain bytes, so they can be
+//! carried over a serial port, the mesh network or a LoRa link alike.
+//! Times are in milliseconds, usually from `millis()`.
+
+/// Length of a request message.
+pub const REQUEST_LEN: usize = 7;
+
+/// Length of a response message.
+pub const RESPONSE_LEN: usize = 15;
+
+/// First bytes of the messages.
+const REQUEST: [u8; 2] = *b"TQ";
+const RESPONSE: [u8; 2] = *b"TR";
+
+/// Number of samples among which the one with the shortest round trip is used.
+const WINDOW: usize = 4;
+
+/// One measurement of the offset between the local clock and the server clock.
+/// # Elements
+/// * `offset` - a i32, the time of the server minus the local time in milliseconds.
+/// * `delay` - a u32, the round trip time of the exchange in milliseconds, without the time spent in the server.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeSample {
+    pub offset: i32,
+    pub delay: u32,
+}
+
+fn put(out: &mut [u8], at: usize, value: u32) {
+    out[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// Answers a request, on the time server.
+/// # Arguments
+/// * `request` - a reference to `[u8]`, the received request.
+/// * `received_at` - a u32, the time of the server when the request arrived.
+/// * `now` - a u32, the time of the server now, right before sending the response.
+/// * `out` - a mutable reference to `[u8]`, where the response is written.
+/// # Returns
+/// * `a Option<usize>` - the length of the response, `None` if the request is malformed or `out` too small.
+pub fn respond(request: &[u8], received_at: u32, now: u32, out: &mut [u8]) -> Option<usize> {
+    if request.len() != REQUEST_LEN || request[..2] != REQUEST || out.len() < RESPONSE_LEN {
+        return None;
+    }
+    out[..2].copy_from_slice(&RESPONSE);
+    out[2..REQUEST_LEN].copy_from_slice(&request[2..]);
+    put(out, REQUEST_LEN, received_at);
+    put(out, REQUEST_LEN + 4, now);
+    Some(RESPONSE_LEN)
+}
+
+/// The client side, keeping the offset to the server clock.
+pub struct TimeSync {
+    sequence: u8,
+    samples: [Option<TimeSample>; WINDOW],
+    next: usize,
+    best: Option<TimeSample>,
+}
+
+impl TimeSync {
+    /// Creates a client which is not synchronized yet.
+    pub const fn new() -> TimeSync {
+        TimeSync {
+            sequence: 0,
+            samples: [None; WINDOW],
+            next: 0,
+            best: None,
+        }
+    }
+
+    /// Writes a new request, to be sent to the server.
+    /// # Arguments
+    /// * `now` - a u32, the local time.
+    /// * `out` - a mutable reference to `[u8]`, where the request is written.
+    /// # Returns
+    /// * `a Option<usize>` - the length of the request, `None` if `out` is too small.
+    pub fn request(&mut self, now: u32, out: &mut [u8]) -> Option<usize> {
+        if out.len() < REQUEST_LEN {
+            return None;
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        out[..2].copy_from_slice(&REQUEST);
+        out[2] = self.sequence;
+        put(out, 3, now);
+        Some(REQUEST_LEN)
+    }
+
+    /// Handles a response of the server. Responses to older requests are ignored.
+    /// # Arguments
+    /// * `response` - a reference to `[u8]`, the received response.
+    /// * `now` - a u32, the local time at which the response arrived.
+    /// # Returns
+    /// * `a Option<TimeSample>` - the new measurement, `None` if the response is malformed or outdated.
+    pub fn handle(&mut self, response: &[u8], now: u32) -> Option<TimeSample> {
+        if response.len() != RESPONSE_LEN
+            || response[..2] != RESPONSE
+            || response[2] != self.sequence
+        {
+            return None;
+        }
+        let sent = get(response, 3);
+        let received = get(response, REQUEST_LEN);
+        let replied = get(response, REQUEST_LEN + 4);
+        let round_trip = now.wrapping_sub(sent) as i32;
+        let in_server = replied.wrapping_sub(received) as i32;
+        if round_trip < 0 || in_server < 0 || in_server > round_trip {
+            return None;
+        }
+        let sample = TimeSample {
+            offset: (received.wrapping_sub(sent) as i32)
+                .wrapping_add(replied.wrapping_sub(now) as i32)
+                / 2,
+            delay: (round_trip - in_server) as u32,
+        };
+        // A sample is only handled once.
+        self.sequence = self.sequence.wrapping_add(1);
+        self.samples[self.next] = Some(sample);
+        self.next = (self.next + 1) % WINDOW;
+        self.best = self
+            .samples
+            .iter()
+            .flatten()
+            .min_by_key(|s| s.delay)
+            .copied();
+        Some(sample)
+    }
+
+    /// Returns the measurement in use, the one with the shortest round trip of the last few.
+    pub fn sample(&self) -> Option<TimeSample> {
+        self.best
+    }
+
+    /// Returns true once a response was received.
+    pub fn is_synced(&self) -> bool {
+        self.best.is_some()
+    }
+
+    /// Converts a local time to the time of the server.
+    /// # Arguments
+    /// * `local` - a u32, the local time.
+    /// # Returns
+    /// * `a Option<u32>` - the time of the server, `None` before the first response.
+    pub fn network_time(&self, local: u32) -> Option<u32> {
+        Some(local.wrapping_add(self.best?.offset as u32))
+    }
+
+    /// Converts a time of the server to the local time.
+    /// # Arguments
+    /// * `network` - a u32, the time of the server.
+    /// # Returns
+    /// * `a Option<u32>` - the local time, `None` before the first response.
+    pub fn local_time(&self, network: u32) -> Option<u32> {
+        Some(network.wrapping_sub(self.best?.offset as u32))
+    }
+}
+
+impl Default for TimeSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Runs one exchange with the server clock ahead by `offset` and the given one way latencies.
+    fn exchange(
+        sync: &mut TimeSync,
+        local: u32,
+        offset: u32,
+        up: u32,
+        down: u32,
+    ) -> Option<TimeSample> {
+        let mut request = [0; REQUEST_LEN];
+        let mut response = [0; RESPONSE_LEN];
+        let len = sync.request(local, &mut request).unwrap();
+        let received = local + up + offset;
+        respond(&request[..len], received, received + 3, &mut response).unwrap();
+        sync.handle(&response, local + up + 3 + down)
+    }
+
+    #[test]
+    fn symmetric_latency_cancels() {
+        let mut sync = TimeSync::new();
+        assert_eq!(sync.network_time(0), None);
+        let sample = exchange(&mut sync, 1000, 50_000, 20, 20).unwrap();
+        assert_eq!(
+            sample,
+            TimeSample {
+                offset: 50_000,
+                delay: 40
+            }
+        );
+        assert_eq!(sync.network_time(2000), Some(52_000));
+        assert_eq!(sync.local_time(52_000), Some(2000));
+    }
+
+    #[test]
+    fn shortest_round_trip_wins() {
+        let mut sync = TimeSync::new();
+        exchange(&mut sync, 0, 10_000, 100, 10);
+        assert_eq!(sync.sample().unwrap().offset, 10_045);
+        exchange(&mut sync, 500, 10_000, 5, 5);
+        exchange(&mut sync, 900, 10_000, 80, 2);
+        assert_eq!(
+            sync.sample(),
+            Some(TimeSample {
+                offset: 10_000,
+                delay: 10
+            })
+        );
+    }
+
+    #[test]
+    fn stale_or_malformed_responses_are_ignored() {
+        let mut sync = TimeSync::new();
+        let mut request = [0; REQUEST_LEN];
+        let mut response = [0; RESPONSE_LEN];
+        sync.request(0, &mut request).unwrap();
+        respond(&request, 5, 6, &mut response).unwrap();
+        sync.request(10, &mut request).unwrap();
+        assert_eq!(sync.handle(&response, 12), None);
+        assert_eq!(respond(&request[..3], 0, 0, &mut response), None);
+        assert!(!sync.is_synced());
+    }
+}
@@ -0,0 +1,228 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Variables kept in the `.noinit` RAM section, which the start up code
+//! neither zeroes nor initializes, so their content survives a watchdog or
+//! external reset as long as the supply stays up. On power up the section
+//! holds garbage, so every value is stored next to a magic number and a CRC
+//! and only read back when both match. This suits crash counters, the state
+//! of a random generator or the last error. `record_panic` uses it to keep
+//! the location of the last panic, and the `panic-noinit` feature installs a
+//! panic handler calling it before resetting through the watchdog.
+
+use crate::encoding::crc::crc16_ccitt_update;
+use core::cell::UnsafeCell;
+use core::mem::{size_of, MaybeUninit};
+use core::ptr::{read_volatile, write_volatile};
+
+/// Declares statics which are kept in the `.noinit` section.
+/// A static of type `T` becomes a `NoInit<T>`.
+///
+/// ```ignore
+/// noinit! {
+///     static BOOTS: u16;
+///     pub static LAST_ERROR: u8;
+/// }
+/// ```
+#[macro_export]
+macro_rules! noinit {
+    ($(#[$attr:meta])* $vis:vis static $name:ident : $ty:ty ; $($rest:tt)*) => {
+        $(#[$attr])*
+        #[cfg_attr(target_arch = "avr", link_section = ".noinit")]
+        $vis static $name: $crate::noinit::NoInit<$ty> = $crate::noinit::NoInit::uninit();
+        $crate::noinit! { $($rest)* }
+    };
+    () => {};
+}
+
+/// Marks a valid value, together with the CRC.
+const MAGIC: u16 = 0x5AFE;
+
+/// A value surviving resets, guarded by a magic number and a CRC-16.
+/// Accesses are not atomic, a value shared with an interrupt service
+/// routine must only be accessed inside `interrupts::free`.
+#[repr(C)]
+pub struct NoInit<T> {
+    magic: UnsafeCell<u16>,
+    crc: UnsafeCell<u16>,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Copy> Sync for NoInit<T> {}
+
+impl<T: Copy> NoInit<T> {
+    /// Creates an uninitialized value, used by the `noinit!` macro.
+    pub const fn uninit() -> NoInit<T> {
+        NoInit {
+            magic: UnsafeCell::new(0),
+            crc: UnsafeCell::new(0),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Computes the CRC of the stored bytes.
+    fn checksum(&self) -> u16 {
+        let data = self.value.get() as *const u8;
+        (0..size_of::<T>()).fold(0xFFFF, |crc, i| {
+            crc16_ccitt_update(crc, &[unsafe { read_volatile(data.add(i)) }])
+        })
+    }
+
+    /// Returns the value if it was set since the last power up.
+    /// # Returns
+    /// * `a Option<T>` - `None` if the magic number or the CRC do not match.
+    pub fn get(&self) -> Option<T> {
+        unsafe {
+            if read_volatile(self.magic.get()) != MAGIC
+                || read_volatile(self.crc.get()) != self.checksum()
+            {
+                return None;
+            }
+            Some(read_volatile(self.value.get()).assume_init())
+        }
+    }
+
+    /// Stores a value.
+    /// # Arguments
+    /// * `value` - the value to keep across resets.
+    pub fn set(&self, value: T) {
+        unsafe {
+            write_volatile(self.value.get(), MaybeUninit::new(value));
+            write_volatile(self.crc.get(), self.checksum());
+            write_volatile(self.magic.get(), MAGIC);
+        }
+    }
+
+    /// Updates the value, starting from `default` if none is valid.
+    /// # Arguments
+    /// * `default` - the value used when nothing survived.
+    /// * `update` - a function computing the new value from the current one.
+    /// # Returns
+    /// * `the new value`.
+    pub fn update(&self, default: T, update: impl FnOnce(T) -> T) -> T {
+        let value = update(self.get().unwrap_or(default));
+        self.set(value);
+        value
+    }
+
+    /// Invalidates the value, so that `get` returns `None`.
+    pub fn clear(&self) {
+        unsafe { write_volatile(self.magic.get(), 0) }
+    }
+}
+
+/// Location of the last panic, kept by `record_panic`.
+/// # Elements
+/// * `count` - a u16, the number of panics since power up.
+/// * `line` - a u32, the line of the panic.
+/// * `file` - an array of u8, the end of the source file name, padded with zeros.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CrashRecord {
+    pub count: u16,
+    pub line: u32,
+    pub file: [u8; 16],
+}
+
+impl CrashRecord {
+    /// Returns the end of the file name as a string.
+    pub fn file(&self) -> &str {
+        let len = self
+            .file
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(self.file.len());
+        core::str::from_utf8(&self.file[..len]).unwrap_or("")
+    }
+}
+
+noinit! {
+    static CRASH: CrashRecord;
+}
+
+/// Records the location of a panic, to be called from a panic handler.
+/// # Arguments
+/// * `info` - a reference to `PanicInfo`, given to the panic handler.
+pub fn record_panic(info: &core::panic::PanicInfo) {
+    let mut file = [0; 16];
+    let mut line = 0;
+    if let Some(location) = info.location() {
+        line = location.line();
+        // Keeps the end of the path, which holds the file name.
+        let name = location.file().as_bytes();
+        let tail = &name[name.len().saturating_sub(file.len())..];
+        file[..tail.len()].copy_from_slice(tail);
+    }
+    CRASH.update(
+        CrashRecord {
+            count: 0,
+            line: 0,
+            file,
+        },
+        |last| CrashRecord {
+            count: last.count.wrapping_add(1),
+            line,
+            file,
+        },
+    );
+}
+
+/// Returns the last panic recorded since power up.
+/// # Returns
+/// * `a Option<CrashRecord>` - `None` if no panic happened since power up.
+pub fn last_crash() -> Option<CrashRecord> {
+    CRASH.get()
+}
+
+/// Forgets the recorded panics.
+pub fn clear_crash() {
+    CRASH.clear()
+}
+
+/// Panic handler recording the location of the panic and resetting through the watchdog.
+#[cfg(all(
+    feature = "panic-noinit",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    record_panic(info);
+    unsafe { crate::hal::watchdog::WatchDog::new() }.system_reset()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn value_is_valid_only_once_set() {
+        let value: NoInit<[u16; 3]> = NoInit::uninit();
+        assert_eq!(value.get(), None);
+        value.set([1, 2, 3]);
+        assert_eq!(value.get(), Some([1, 2, 3]));
+        assert_eq!(value.update([0; 3], |v| [v[0] + 1, v[1], v[2]]), [2, 2, 3]);
+        value.clear();
+        assert_eq!(value.get(), None);
+        assert_eq!(value.update([7; 3], |v| v), [7; 3]);
+    }
+
+    #[test]
+    fn corrupted_value_is_rejected() {
+        let value: NoInit<u32> = NoInit::uninit();
+        value.set(0x1234_5678);
+        unsafe { *(value.value.get() as *mut u8) ^= 0x01 };
+        assert_eq!(value.get(), None);
+    }
+}
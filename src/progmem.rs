@@ -0,0 +1,240 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Constants, lookup tables and strings stored in program memory (flash).
+//! AVR chips have separate address spaces for flash and RAM, and a static
+//! is normally copied to RAM at start up, which 2 KB of RAM on an ATMEGA328P
+//! cannot afford for fonts or large tables. The `progmem!` macro places a
+//! static in the `.progmem.data` section instead and wraps it in `ProgMem`,
+//! whose accessors read it back with the LPM instruction.
+//! Only the lower 64 KB of flash can be reached this way, which is where
+//! the linker puts `.progmem.data`.
+
+use crate::__lpm;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+
+/// Declares statics which are kept in program memory.
+/// A static of type `T` becomes a `ProgMem<T>`, and a static of type `str`
+/// becomes a `ProgMem<[u8; N]>` holding the bytes of the string.
+///
+/// ```ignore
+/// progmem! {
+///     static GAMMA: [u8; 4] = [0, 12, 80, 255];
+///     pub static GREETING: str = "Hello World!";
+/// }
+/// ```
+#[macro_export]
+macro_rules! progmem {
+    ($(#[$attr:meta])* $vis:vis static $name:ident : str = $value:expr ; $($rest:tt)*) => {
+        $(#[$attr])*
+        #[cfg_attr(target_arch = "avr", link_section = ".progmem.data")]
+        $vis static $name: $crate::progmem::ProgMem<[u8; $value.len()]> =
+            unsafe { $crate::progmem::ProgMem::new($crate::progmem::str_bytes($value)) };
+        $crate::progmem! { $($rest)* }
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident : $ty:ty = $value:expr ; $($rest:tt)*) => {
+        $(#[$attr])*
+        #[cfg_attr(target_arch = "avr", link_section = ".progmem.data")]
+        $vis static $name: $crate::progmem::ProgMem<$ty> =
+            unsafe { $crate::progmem::ProgMem::new($value) };
+        $crate::progmem! { $($rest)* }
+    };
+    () => {};
+}
+
+/// A value stored in program memory.
+/// It cannot be dereferenced, its content has to be loaded with the accessors.
+#[repr(transparent)]
+pub struct ProgMem<T>(T);
+
+impl<T> ProgMem<T> {
+    /// Wraps a value, used by the `progmem!` macro.
+    /// # Arguments
+    /// * `value` - the value to be stored.
+    /// # Returns
+    /// * `a ProgMem object` - The wrapped value.
+    /// # Safety
+    /// The result must be a static placed in the `.progmem.data` section,
+    /// otherwise the accessors read flash at the address of a RAM variable.
+    pub const unsafe fn new(value: T) -> ProgMem<T> {
+        ProgMem(value)
+    }
+
+    /// Gives the address of the value in program memory.
+    /// # Returns
+    /// * `a *const T` - The address, which must be read with `__lpm`.
+    pub fn as_ptr(&self) -> *const T {
+        &self.0 as *const T
+    }
+}
+
+impl<T: Copy> ProgMem<T> {
+    /// Copies the whole value from program memory to RAM.
+    /// # Returns
+    /// * `the value stored`.
+    pub fn load(&self) -> T {
+        unsafe { read(self.as_ptr()) }
+    }
+}
+
+impl<T: Copy, const N: usize> ProgMem<[T; N]> {
+    /// Returns the number of elements of the array.
+    pub fn len(&self) -> usize {
+        N
+    }
+
+    /// Returns true if the array has no elements.
+    pub fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// Loads one element of the array.
+    /// # Arguments
+    /// * `index` - a usize, the position of the element.
+    /// # Returns
+    /// * `a Option` - The element, or `None` if `index` is out of range.
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index < N {
+            Some(unsafe { read((self.as_ptr() as *const T).add(index)) })
+        } else {
+            None
+        }
+    }
+
+    /// Loads consecutive elements of the array.
+    /// # Arguments
+    /// * `start` - a usize, the position of the first element.
+    /// * `buffer` - a mutable slice, filled with `buffer.len()` elements.
+    /// # Returns
+    /// * `a boolean` - false if the range is outside of the array.
+    pub fn load_into(&self, start: usize, buffer: &mut [T]) -> bool {
+        if start > N || buffer.len() > N - start {
+            return false;
+        }
+        for (i, value) in buffer.iter_mut().enumerate() {
+            *value = unsafe { read((self.as_ptr() as *const T).add(start + i)) };
+        }
+        true
+    }
+
+    /// Iterates over the elements of the array, loading one at a time.
+    /// # Returns
+    /// * `a Iter object` - The iterator.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            ptr: self.as_ptr() as *const T,
+            remaining: N,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<const N: usize> ProgMem<[u8; N]> {
+    /// Copies a string stored by `progmem!` into a RAM buffer.
+    /// # Arguments
+    /// * `buffer` - a mutable slice of u8, which must hold at least `len()` bytes.
+    /// # Returns
+    /// * `a Option<&str>` - The string in the buffer, or `None` if the buffer is too small.
+    pub fn load_str<'b>(&self, buffer: &'b mut [u8]) -> Option<&'b str> {
+        if buffer.len() < N || !self.load_into(0, &mut buffer[..N]) {
+            return None;
+        }
+        core::str::from_utf8(&buffer[..N]).ok()
+    }
+}
+
+/// Iterator over the elements of an array in program memory.
+pub struct Iter<'a, T> {
+    ptr: *const T,
+    remaining: usize,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: Copy> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let value = unsafe { read(self.ptr) };
+        self.ptr = unsafe { self.ptr.add(1) };
+        self.remaining -= 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// Converts a string into an array of its bytes, used by the `progmem!` macro.
+#[doc(hidden)]
+pub const fn str_bytes<const N: usize>(string: &str) -> [u8; N] {
+    let bytes = string.as_bytes();
+    let mut array = [0; N];
+    let mut i = 0;
+    while i < N {
+        array[i] = bytes[i];
+        i += 1;
+    }
+    array
+}
+
+/// Reads a value from program memory one byte at a time.
+/// # Safety
+/// `ptr` must point to a value of type `T` in program memory.
+unsafe fn read<T: Copy>(ptr: *const T) -> T {
+    let mut value = MaybeUninit::<T>::uninit();
+    let src = ptr as *const u8;
+    let dst = value.as_mut_ptr() as *mut u8;
+    for i in 0..size_of::<T>() {
+        *dst.add(i) = __lpm(src.add(i));
+    }
+    value.assume_init()
+}
+
+#[cfg(test)]
+mod test {
+    progmem! {
+        static TABLE: [u16; 4] = [1, 300, 65535, 42];
+        static TEXT: str = "flash";
+    }
+
+    #[test]
+    fn reads_tables_and_strings() {
+        assert_eq!(TABLE.len(), 4);
+        assert_eq!(TABLE.get(1), Some(300));
+        assert_eq!(TABLE.get(4), None);
+        assert_eq!(TABLE.load(), [1, 300, 65535, 42]);
+
+        let mut part = [0; 2];
+        assert!(TABLE.load_into(2, &mut part));
+        assert_eq!(part, [65535, 42]);
+        assert!(!TABLE.load_into(3, &mut part));
+
+        let mut sum = 0u32;
+        for value in TABLE.iter() {
+            sum += value as u32;
+        }
+        assert_eq!(sum, 65878);
+
+        let mut buffer = [0; 8];
+        assert_eq!(TEXT.load_str(&mut buffer), Some("flash"));
+        assert_eq!(TEXT.load_str(&mut buffer[..3]), None);
+    }
+}
@@ -0,0 +1,429 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Smooth servo moves and keyframed sequences for several servos.
+//! An `EasedServo` moves to a target angle in a given time, following an
+//! easing curve instead of jumping, and a `Sequencer` plays a list of
+//! `Keyframe`s on a group of them, as needed for animatronics.
+//! Both are driven by calling `update` often, for example from a 20 ms task
+//! of the `system::scheduler::Scheduler`, with the current value of `millis`.
+
+// Source codes required
+use super::odometry::cos_q15;
+
+/// Progress of a move in thousandths, from 0 at the start to 1000 at the end.
+const DONE: u32 = 1000;
+
+/// An output which turns a servo to an angle.
+pub trait ServoOutput {
+    /// Turns the servo.
+    /// # Arguments
+    /// * `degrees` - a u8, the angle from 0 to 180.
+    fn set_angle(&mut self, degrees: u8);
+}
+
+impl<S: ServoOutput> ServoOutput for &mut S {
+    fn set_angle(&mut self, degrees: u8) {
+        (**self).set_angle(degrees)
+    }
+}
+
+#[cfg(feature = "sensors")]
+impl ServoOutput for crate::sensors::Servo {
+    fn set_angle(&mut self, degrees: u8) {
+        self.write(degrees.min(180));
+    }
+}
+
+/// Curves giving the position along a move from the elapsed time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+    /// Starts slowly and stops abruptly.
+    InQuad,
+    /// Starts abruptly and stops slowly.
+    OutQuad,
+    /// Starts and stops slowly.
+    InOutQuad,
+    /// Starts and stops slowly, with a faster middle part.
+    InOutCubic,
+    /// Starts and stops slowly following a half cosine wave, the most natural for heads and eyes.
+    InOutSine,
+}
+
+impl Easing {
+    /// Computes the position along a move.
+    /// # Arguments
+    /// * `t` - a u32, the elapsed time in thousandths of the move, at most 1000.
+    /// # Returns
+    /// * `a u32` - The position in thousandths of the distance.
+    pub fn apply(&self, t: u32) -> u32 {
+        let t = t.min(DONE);
+        let rest = DONE - t;
+        match self {
+            Easing::Linear => t,
+            Easing::InQuad => t * t / DONE,
+            Easing::OutQuad => DONE - rest * rest / DONE,
+            Easing::InOutQuad => {
+                if t < DONE / 2 {
+                    2 * t * t / DONE
+                } else {
+                    DONE - 2 * rest * rest / DONE
+                }
+            }
+            Easing::InOutCubic => {
+                if t < DONE / 2 {
+                    4 * t * t / DONE * t / DONE
+                } else {
+                    DONE - 4 * rest * rest / DONE * rest / DONE
+                }
+            }
+            Easing::InOutSine => {
+                let angle = (t * 32768 / DONE) as u16;
+                let cosine = cos_q15(angle) as i32;
+                ((32767 - cosine) as u32 * DONE / 65534).min(DONE)
+            }
+        }
+    }
+}
+
+/// A servo which moves smoothly to its targets.
+/// # Elements
+/// * `servo` - the output turning the servo.
+/// * `position` - a u8, the angle last written.
+/// * `from` - a u8, the angle at the start of the move.
+/// * `to` - a u8, the target angle.
+/// * `start` - a u32, the time at which the move started in milliseconds.
+/// * `duration` - a u32, the length of the move in milliseconds.
+/// * `easing` - an `Easing` object, the curve of the move.
+/// * `moving` - a boolean, true until the target is reached.
+/// * `on_arrival` - an optional function called once the target is reached.
+pub struct EasedServo<S> {
+    servo: S,
+    position: u8,
+    from: u8,
+    to: u8,
+    start: u32,
+    duration: u32,
+    easing: Easing,
+    moving: bool,
+    on_arrival: Option<fn(u8)>,
+}
+
+impl<S: ServoOutput> EasedServo<S> {
+    /// Takes control of a servo and turns it to its initial angle at once.
+    /// # Arguments
+    /// * `servo` - the output turning the servo, for example a `sensors::Servo`.
+    /// * `position` - a u8, the initial angle.
+    /// # Returns
+    /// * `an EasedServo object` - Which will be used for further implementations.
+    pub fn new(mut servo: S, position: u8) -> EasedServo<S> {
+        servo.set_angle(position);
+        EasedServo {
+            servo,
+            position,
+            from: position,
+            to: position,
+            start: 0,
+            duration: 0,
+            easing: Easing::Linear,
+            moving: false,
+            on_arrival: None,
+        }
+    }
+
+    /// Gives back the servo.
+    pub fn release(self) -> S {
+        self.servo
+    }
+
+    /// Sets a function called with the angle each time a move ends.
+    /// # Arguments
+    /// * `on_arrival` - an optional function, None to call nothing.
+    pub fn set_on_arrival(&mut self, on_arrival: Option<fn(u8)>) {
+        self.on_arrival = on_arrival;
+    }
+
+    /// Starts a move from the current angle, replacing any move going on.
+    /// # Arguments
+    /// * `target` - a u8, the angle to reach, at most 180.
+    /// * `duration` - a u32, the length of the move in milliseconds, 0 to jump.
+    /// * `easing` - an `Easing` object, the curve of the move.
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    pub fn move_to(&mut self, target: u8, duration: u32, easing: Easing, now: u32) {
+        self.from = self.position;
+        self.to = target.min(180);
+        self.start = now;
+        self.duration = duration;
+        self.easing = easing;
+        self.moving = true;
+        self.update(now);
+    }
+
+    /// Stops the move at the current angle, without calling the arrival function.
+    pub fn stop(&mut self) {
+        self.moving = false;
+        self.to = self.position;
+    }
+
+    /// Returns the angle last written to the servo.
+    pub fn position(&self) -> u8 {
+        self.position
+    }
+
+    /// Returns the angle being moved to.
+    pub fn target(&self) -> u8 {
+        self.to
+    }
+
+    /// Returns true while a move is going on.
+    pub fn is_moving(&self) -> bool {
+        self.moving
+    }
+
+    /// Writes the angle for the current time to the servo.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    /// # Returns
+    /// * `a boolean` - True while the move is going on.
+    pub fn update(&mut self, now: u32) -> bool {
+        if !self.moving {
+            return false;
+        }
+        let elapsed = now.wrapping_sub(self.start);
+        let t = if elapsed >= self.duration {
+            DONE
+        } else {
+            (elapsed as u64 * DONE as u64 / self.duration as u64) as u32
+        };
+        let progress = self.easing.apply(t) as i32;
+        let distance = self.to as i32 - self.from as i32;
+        let position = (self.from as i32 + distance * progress / DONE as i32) as u8;
+        if position != self.position {
+            self.position = position;
+            self.servo.set_angle(position);
+        }
+        if t == DONE {
+            self.moving = false;
+            if let Some(on_arrival) = self.on_arrival {
+                on_arrival(self.position);
+            }
+        }
+        self.moving
+    }
+}
+
+/// The angles of a group of servos at a point of a sequence.
+/// # Elements
+/// * `time` - a u32, the time in milliseconds from the start of the sequence at which the angles are reached.
+/// * `angles` - an array of u8, the angle of each servo, or `KEEP` to leave it where it is.
+/// * `easing` - an `Easing` object, the curve of the moves from the previous keyframe.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Keyframe<const N: usize> {
+    pub time: u32,
+    pub angles: [u8; N],
+    pub easing: Easing,
+}
+
+/// Angle of a keyframe for a servo which does not move.
+pub const KEEP: u8 = 0xFF;
+
+/// Player of keyframed sequences on a group of servos.
+/// # Elements
+/// * `servos` - an array of `EasedServo` objects, the servos in the order of the keyframe angles.
+/// * `frames` - a slice of `Keyframe` objects, the sequence being played sorted by time.
+/// * `next` - a usize, the index of the keyframe being moved to.
+/// * `start` - a u32, the time at which the sequence started in milliseconds.
+/// * `looping` - a boolean, true to start again at the end.
+/// * `on_finished` - an optional function called when the sequence ends.
+pub struct Sequencer<'a, S, const N: usize> {
+    servos: [EasedServo<S>; N],
+    frames: &'a [Keyframe<N>],
+    next: usize,
+    start: u32,
+    looping: bool,
+    on_finished: Option<fn()>,
+}
+
+impl<'a, S: ServoOutput, const N: usize> Sequencer<'a, S, N> {
+    /// Creates the sequencer with no sequence playing.
+    /// # Arguments
+    /// * `servos` - an array of `EasedServo` objects, in the order of the keyframe angles.
+    /// # Returns
+    /// * `a Sequencer object` - Which will be used for further implementations.
+    pub fn new(servos: [EasedServo<S>; N]) -> Sequencer<'a, S, N> {
+        Sequencer {
+            servos,
+            frames: &[],
+            next: 0,
+            start: 0,
+            looping: false,
+            on_finished: None,
+        }
+    }
+
+    /// Gives back the servos.
+    pub fn release(self) -> [EasedServo<S>; N] {
+        self.servos
+    }
+
+    /// Gives access to a servo, for example to move it while no sequence is playing.
+    /// # Arguments
+    /// * `index` - a usize, the index of the servo.
+    /// # Returns
+    /// * `a Option<&mut EasedServo>` - The servo, or None if there is no such index.
+    pub fn servo(&mut self, index: usize) -> Option<&mut EasedServo<S>> {
+        self.servos.get_mut(index)
+    }
+
+    /// Sets a function called when a sequence which is not looping ends.
+    /// # Arguments
+    /// * `on_finished` - an optional function, None to call nothing.
+    pub fn set_on_finished(&mut self, on_finished: Option<fn()>) {
+        self.on_finished = on_finished;
+    }
+
+    /// Starts a sequence, from the current angles to the first keyframe.
+    /// # Arguments
+    /// * `frames` - a slice of `Keyframe` objects, sorted by time.
+    /// * `looping` - a boolean, true to play the sequence again and again.
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    pub fn play(&mut self, frames: &'a [Keyframe<N>], looping: bool, now: u32) {
+        self.frames = frames;
+        self.looping = looping;
+        self.start = now;
+        self.next = 0;
+        self.begin_frame(now);
+    }
+
+    /// Stops the sequence, leaving the servos where they are.
+    pub fn stop(&mut self) {
+        self.frames = &[];
+        for servo in self.servos.iter_mut() {
+            servo.stop();
+        }
+    }
+
+    /// Returns true while a sequence is playing.
+    pub fn is_playing(&self) -> bool {
+        self.next < self.frames.len()
+    }
+
+    /// Moves the servos for the current time and goes on with the next keyframe when one is reached.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    /// # Returns
+    /// * `a boolean` - True while the sequence is playing.
+    pub fn update(&mut self, now: u32) -> bool {
+        while self.is_playing() {
+            let reached = self.start.wrapping_add(self.frames[self.next].time);
+            if (now.wrapping_sub(reached) as i32) < 0 {
+                break;
+            }
+            // Finish the moves to the keyframe, the next ones start from its angles.
+            for servo in self.servos.iter_mut() {
+                servo.update(reached);
+            }
+            self.next += 1;
+            if self.next == self.frames.len() {
+                if self.looping && reached != self.start {
+                    self.start = reached;
+                    self.next = 0;
+                } else {
+                    self.frames = &[];
+                    if let Some(on_finished) = self.on_finished {
+                        on_finished();
+                    }
+                    return false;
+                }
+            }
+            self.begin_frame(reached);
+        }
+        for servo in self.servos.iter_mut() {
+            servo.update(now);
+        }
+        self.is_playing()
+    }
+
+    /// Starts the moves towards the next keyframe.
+    /// # Arguments
+    /// * `from` - a u32, the time at which the previous keyframe was reached.
+    fn begin_frame(&mut self, from: u32) {
+        let frame = match self.frames.get(self.next) {
+            Some(frame) => *frame,
+            None => return,
+        };
+        let at = self.start.wrapping_add(frame.time);
+        let duration = at.wrapping_sub(from);
+        for (servo, &angle) in self.servos.iter_mut().zip(frame.angles.iter()) {
+            if angle != KEEP {
+                servo.move_to(angle, duration, frame.easing, from);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Recorder(u8);
+
+    impl ServoOutput for Recorder {
+        fn set_angle(&mut self, degrees: u8) {
+            self.0 = degrees;
+        }
+    }
+
+    #[test]
+    fn eased_moves() {
+        assert_eq!(Easing::InOutSine.apply(0), 0);
+        assert_eq!(Easing::InOutSine.apply(500), 500);
+        assert_eq!(Easing::InOutCubic.apply(1000), 1000);
+
+        let mut servo = EasedServo::new(Recorder(0), 0);
+        servo.move_to(100, 1000, Easing::InOutQuad, 0);
+        servo.update(250);
+        assert_eq!(servo.position(), 12);
+        assert!(!servo.update(1000));
+        assert_eq!(servo.position(), 100);
+
+        let frames = [
+            Keyframe {
+                time: 100,
+                angles: [90, KEEP],
+                easing: Easing::Linear,
+            },
+            Keyframe {
+                time: 300,
+                angles: [0, 180],
+                easing: Easing::Linear,
+            },
+        ];
+        let servos = [
+            EasedServo::new(Recorder(0), 0),
+            EasedServo::new(Recorder(0), 0),
+        ];
+        let mut sequencer = Sequencer::new(servos);
+        sequencer.play(&frames, false, 1000);
+        sequencer.update(1050);
+        assert_eq!(sequencer.servo(0).unwrap().position(), 45);
+        sequencer.update(1200);
+        assert_eq!(sequencer.servo(1).unwrap().position(), 90);
+        assert!(!sequencer.update(1300));
+        assert_eq!(sequencer.servo(0).unwrap().position(), 0);
+    }
+}
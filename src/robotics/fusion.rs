@@ -0,0 +1,287 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Dead reckoning between GPS fixes for slow rovers.
+//! GPS receivers give a fix once a second with a few meters of noise, while
+//! odometry and gyroscopes are smooth but drift. `Fusion` keeps a position in
+//! millimeters east and north of the first fix and moves it between fixes,
+//! either at constant velocity with `predict`, turned by the gyroscope with
+//! `rotate`, or by the distance measured by the odometry with `advance`.
+//! Every fix pulls the estimate towards it by a fixed gain, like a complementary
+//! filter, and corrects the heading from the GPS course while the rover moves
+//! fast enough for the course to be meaningful. Headings are binary angles as
+//! in `Odometry`, 0 towards the east and growing counterclockwise.
+
+use super::odometry::{cos_q15, sin_q15};
+use crate::encoding::nmea::Fix;
+
+/// Millimeters per ten millionth of a degree of latitude, times 1000.
+const MICRODEGREE_MM: i64 = 11_132;
+
+/// Converts hundredths of a degree clockwise from north to a heading.
+fn course_to_heading(course: u16) -> u16 {
+    let bam = (course as u32 * 65536 / 36_000) as u16;
+    0x4000u16.wrapping_sub(bam)
+}
+
+/// Rotates a vector by a binary angle.
+fn rotate_vector(x: i32, y: i32, angle: u16) -> (i32, i32) {
+    let (sin, cos) = (sin_q15(angle) as i64, cos_q15(angle) as i64);
+    let (x, y) = (x as i64, y as i64);
+    (
+        ((x * cos - y * sin) / 32767) as i32,
+        ((x * sin + y * cos) / 32767) as i32,
+    )
+}
+
+/// Position, velocity and heading estimate blending GPS with dead reckoning.
+pub struct Fusion {
+    /// Coordinates of the first fix, in ten millionths of a degree.
+    origin: Option<(i32, i32)>,
+    /// Cosine of the latitude of the origin in Q15, scaling longitudes to distances.
+    cos_latitude: i16,
+    /// Position in millimeters east and north of the origin.
+    x: i32,
+    y: i32,
+    /// Velocity in millimeters per second.
+    vx: i32,
+    vy: i32,
+    heading: u16,
+    /// Time of the last update in milliseconds.
+    last: u32,
+    position_gain: u8,
+    heading_gain: u8,
+    min_speed: u32,
+}
+
+impl Fusion {
+    /// Creates an estimate waiting for its first fix.
+    pub const fn new() -> Fusion {
+        Fusion {
+            origin: None,
+            cos_latitude: 32767,
+            x: 0,
+            y: 0,
+            vx: 0,
+            vy: 0,
+            heading: 0,
+            last: 0,
+            position_gain: 64,
+            heading_gain: 32,
+            min_speed: 500,
+        }
+    }
+
+    /// Sets how much every fix corrects the estimate.
+    /// # Arguments
+    /// * `position` - a u8, the share of the position and velocity error corrected, in 256ths.
+    /// * `heading` - a u8, the share of the heading error corrected, in 256ths.
+    /// * `min_speed` - a u32, the speed in millimeters per second above which the GPS course corrects the heading.
+    pub fn set_gains(&mut self, position: u8, heading: u8, min_speed: u32) {
+        self.position_gain = position;
+        self.heading_gain = heading;
+        self.min_speed = min_speed;
+    }
+
+    /// Returns true once the first fix arrived.
+    pub fn has_fix(&self) -> bool {
+        self.origin.is_some()
+    }
+
+    /// Converts coordinates to millimeters east and north of the origin.
+    fn project(&self, origin: (i32, i32), latitude: i32, longitude: i32) -> (i32, i32) {
+        let north = (latitude - origin.0) as i64 * MICRODEGREE_MM / 1000;
+        let east = (longitude - origin.1) as i64 * MICRODEGREE_MM / 1000 * self.cos_latitude as i64
+            / 32767;
+        (east as i32, north as i32)
+    }
+
+    /// Moves the estimate at constant velocity up to `now`.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds.
+    pub fn predict(&mut self, now: u32) {
+        let dt = now.wrapping_sub(self.last) as i64;
+        self.last = now;
+        self.x += (self.vx as i64 * dt / 1000) as i32;
+        self.y += (self.vy as i64 * dt / 1000) as i32;
+    }
+
+    /// Turns the heading and the velocity, for example by the angle integrated from a gyroscope.
+    /// # Arguments
+    /// * `angle` - a i16, the rotation as a binary angle, positive counterclockwise.
+    pub fn rotate(&mut self, angle: i16) {
+        self.heading = self.heading.wrapping_add(angle as u16);
+        let (vx, vy) = rotate_vector(self.vx, self.vy, angle as u16);
+        self.vx = vx;
+        self.vy = vy;
+    }
+
+    /// Moves the estimate by the distance and rotation measured by the odometry,
+    /// instead of `predict`, and derives the velocity from them.
+    /// # Arguments
+    /// * `distance` - a i32, the distance travelled in millimeters, negative backwards.
+    /// * `angle` - a i16, the rotation as a binary angle, positive counterclockwise.
+    /// * `now` - a u32, the current time in milliseconds.
+    pub fn advance(&mut self, distance: i32, angle: i16, now: u32) {
+        // Moving along the mean heading follows an arc more closely.
+        let mean = self.heading.wrapping_add((angle / 2) as u16);
+        let dx = distance as i64 * cos_q15(mean) as i64 / 32767;
+        let dy = distance as i64 * sin_q15(mean) as i64 / 32767;
+        self.x += dx as i32;
+        self.y += dy as i32;
+        self.heading = self.heading.wrapping_add(angle as u16);
+        let dt = now.wrapping_sub(self.last) as i64;
+        self.last = now;
+        if dt > 0 {
+            self.vx = (dx * 1000 / dt) as i32;
+            self.vy = (dy * 1000 / dt) as i32;
+        }
+    }
+
+    /// Corrects the estimate with a GPS fix, the first one setting the origin.
+    /// Fixes without a valid position are ignored.
+    /// # Arguments
+    /// * `fix` - a reference to `Fix`, parsed from the NMEA output of the receiver.
+    /// * `now` - a u32, the time in milliseconds at which the fix was received.
+    pub fn fix(&mut self, fix: &Fix, now: u32) {
+        if !fix.valid {
+            return;
+        }
+        let heading = course_to_heading(fix.course);
+        let (gvx, gvy) = (
+            (fix.speed as i64 * cos_q15(heading) as i64 / 32767) as i32,
+            (fix.speed as i64 * sin_q15(heading) as i64 / 32767) as i32,
+        );
+        let origin = match self.origin {
+            Some(origin) => origin,
+            None => {
+                let origin = (fix.latitude, fix.longitude);
+                self.origin = Some(origin);
+                let latitude = (fix.latitude as i64 * 65536 / 3_600_000_000) as u16;
+                self.cos_latitude = cos_q15(latitude);
+                self.x = 0;
+                self.y = 0;
+                self.vx = gvx;
+                self.vy = gvy;
+                self.heading = heading;
+                self.last = now;
+                return;
+            }
+        };
+        self.predict(now);
+        let (fx, fy) = self.project(origin, fix.latitude, fix.longitude);
+        let gain = self.position_gain as i64;
+        self.x += ((fx - self.x) as i64 * gain / 256) as i32;
+        self.y += ((fy - self.y) as i64 * gain / 256) as i32;
+        self.vx += ((gvx - self.vx) as i64 * gain / 256) as i32;
+        self.vy += ((gvy - self.vy) as i64 * gain / 256) as i32;
+        if fix.speed >= self.min_speed {
+            let error = heading.wrapping_sub(self.heading) as i16 as i32;
+            let correction = error * self.heading_gain as i32 / 256;
+            self.heading = self.heading.wrapping_add(correction as u16);
+        }
+    }
+
+    /// Returns the position in millimeters east and north of the first fix.
+    pub fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    /// Returns the velocity in millimeters per second towards the east and north.
+    pub fn velocity(&self) -> (i32, i32) {
+        (self.vx, self.vy)
+    }
+
+    /// Returns the heading as a binary angle, 0 towards the east and growing counterclockwise.
+    pub fn heading(&self) -> u16 {
+        self.heading
+    }
+
+    /// Returns the estimated position as coordinates.
+    /// # Returns
+    /// * `a Option<(i32, i32)>` - the latitude and longitude in ten millionths of a degree, `None` before the first fix.
+    pub fn coordinates(&self) -> Option<(i32, i32)> {
+        let origin = self.origin?;
+        let latitude = origin.0 + (self.y as i64 * 1000 / MICRODEGREE_MM) as i32;
+        let longitude = origin.1
+            + (self.x as i64 * 1000 * 32767 / (MICRODEGREE_MM * self.cos_latitude.max(1) as i64))
+                as i32;
+        Some((latitude, longitude))
+    }
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fix(latitude: i32, longitude: i32, speed: u32, course: u16) -> Fix {
+        Fix {
+            time: 0,
+            valid: true,
+            latitude,
+            longitude,
+            speed,
+            course,
+        }
+    }
+
+    #[test]
+    fn coasts_between_fixes_and_converges() {
+        let mut fusion = Fusion::new();
+        assert_eq!(fusion.coordinates(), None);
+        // Driving north at 1 m/s, on the equator.
+        fusion.fix(&fix(0, 0, 1000, 0), 0);
+        assert_eq!(fusion.heading(), 0x4000);
+        assert_eq!(fusion.velocity(), (0, 1000));
+        fusion.predict(500);
+        assert_eq!(fusion.position(), (0, 500));
+
+        // Fixes 2 m further east than the estimate pull it over.
+        for second in 1..40 {
+            let north = second * 1000 * 1000 / MICRODEGREE_MM as i32;
+            fusion.fix(&fix(north, 180, 1000, 0), second as u32 * 1000);
+        }
+        let (x, y) = fusion.position();
+        assert!((x - 2003).abs() < 20, "{}", x);
+        assert!((y - 39_000).abs() < 20, "{}", y);
+        let (latitude, longitude) = fusion.coordinates().unwrap();
+        assert!((latitude - 3503).abs() < 3);
+        assert!((longitude - 180).abs() < 3);
+    }
+
+    #[test]
+    fn odometry_and_gyro_move_the_estimate() {
+        let mut fusion = Fusion::new();
+        fusion.fix(&fix(0, 0, 0, 9000), 0);
+        assert_eq!(fusion.heading(), 0);
+        fusion.advance(1000, 0, 1000);
+        assert_eq!(fusion.position(), (1000, 0));
+        assert_eq!(fusion.velocity(), (1000, 0));
+        fusion.rotate(0x4000);
+        assert_eq!(fusion.heading(), 0x4000);
+        let (vx, vy) = fusion.velocity();
+        assert!(vx.abs() <= 1 && vy >= 999);
+        fusion.predict(2000);
+        let (x, y) = fusion.position();
+        assert!((x - 1000).abs() <= 1 && (y - 1000).abs() <= 1);
+    }
+}
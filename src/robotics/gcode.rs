@@ -0,0 +1,579 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! An interpreter for a small subset of G-code, driving a `XyPlanner` and
+//! PWM outputs, which turns a board into the controller of a pen plotter or
+//! another two axis machine. Each line received over serial is answered with
+//! `ok` once it is queued, so the usual senders stream lines as fast as the
+//! planner takes them, or with `error:` if it cannot be run.
+//! Coordinates are in millimeters with up to three decimals.
+//! * `G0` and `G1` with `X`, `Y` and `F` - rapid and linear moves, the feed rate in millimeters per minute.
+//! * `G28` - moves to the origin, homing against end stops is left to the application.
+//! * `G90`, `G91` - absolute and relative coordinates.
+//! * `G92` with `X` and `Y` - sets the current position, to 0 without arguments.
+//! * `M3 S`, `M5` - sets output 0, a laser or spindle, from 0 to 255 and off.
+//! * `M106 P S`, `M107 P` - sets output `P` from 0 to 255 and off.
+//! * `M114` - reports the position.
+//! * `M400` - waits for the moves to end.
+//!
+//! Outputs and positions are only changed once the moves queued before are done.
+
+// Source codes required
+use super::planner::{isqrt, Stepper, XyPlanner};
+use crate::{Error, Result};
+use embedded_hal::pwm::SetDutyCycle;
+use embedded_io::WriteFmtError;
+
+/// Longest line accepted, without its line end.
+pub const MAX_LINE: usize = 64;
+
+/// A command of a G-code line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    /// `G0` or `G1`, the coordinates in micrometers and the feed rate in millimeters per minute.
+    Move {
+        rapid: bool,
+        x: Option<i32>,
+        y: Option<i32>,
+        feed: Option<u32>,
+    },
+    /// `G28`.
+    Home,
+    /// `G90`.
+    Absolute,
+    /// `G91`.
+    Relative,
+    /// `G92`, the coordinates in micrometers.
+    SetPosition { x: Option<i32>, y: Option<i32> },
+    /// `M3`, `M5`, `M106` and `M107`, the value from 0 to 255.
+    Output { index: u8, value: u8 },
+    /// `M114`.
+    Report,
+    /// `M400`.
+    Wait,
+}
+
+/// Reads a decimal number, multiplied by 1000, ignoring the decimals beyond the third.
+fn number(text: &[u8]) -> Option<i32> {
+    let (negative, digits) = match text.first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut value: i64 = 0;
+    let mut decimals = None;
+    for &c in digits {
+        match c {
+            b'.' if decimals.is_none() => decimals = Some(0),
+            b'0'..=b'9' => {
+                if decimals == Some(3) {
+                    continue;
+                }
+                value = value * 10 + (c - b'0') as i64;
+                decimals = decimals.map(|d| d + 1);
+                if value > i32::MAX as i64 {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+    let value = value * 10i64.pow(3 - decimals.unwrap_or(0));
+    if value > i32::MAX as i64 {
+        return None;
+    }
+    Some(if negative { -value } else { value } as i32)
+}
+
+/// Gives the integer value of a code or parameter word read by `number`.
+fn integer(value: Option<i32>) -> Result<Option<i32>> {
+    match value {
+        Some(value) if value % 1000 != 0 => Err(Error::InvalidArgument),
+        value => Ok(value.map(|value| value / 1000)),
+    }
+}
+
+/// Parses one line, without its line end. Line numbers, checksums and comments are ignored.
+/// # Arguments
+/// * `line` - a reference to `[u8]`, the line received.
+/// # Returns
+/// * `a Result<Option<Command>>` - `None` for an empty line, `InvalidArgument` for an unsupported or malformed command.
+pub fn parse_line(line: &[u8]) -> Result<Option<Command>> {
+    let (mut g, mut m) = (None, None);
+    let (mut x, mut y, mut f, mut s, mut p) = (None, None, None, None, None);
+    let mut i = 0;
+    while i < line.len() {
+        let letter = line[i].to_ascii_uppercase();
+        i += 1;
+        match letter {
+            b' ' | b'\t' => continue,
+            b';' | b'*' => break,
+            b'(' => {
+                while i < line.len() && line[i] != b')' {
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let start = i;
+        while i < line.len() && matches!(line[i], b'0'..=b'9' | b'.' | b'-' | b'+') {
+            i += 1;
+        }
+        let value = Some(number(&line[start..i]).ok_or(Error::InvalidArgument)?);
+        let word = match letter {
+            b'N' => continue,
+            b'G' => &mut g,
+            b'M' => &mut m,
+            b'X' => &mut x,
+            b'Y' => &mut y,
+            b'F' => &mut f,
+            b'S' => &mut s,
+            b'P' => &mut p,
+            _ => return Err(Error::InvalidArgument),
+        };
+        if word.is_some() {
+            return Err(Error::InvalidArgument);
+        }
+        *word = value;
+    }
+
+    let level =
+        |s: Option<i32>| -> Result<u8> { Ok(integer(s)?.unwrap_or(255).clamp(0, 255) as u8) };
+    let index = integer(p)?.unwrap_or(0);
+    if !(0..=255).contains(&index) {
+        return Err(Error::InvalidArgument);
+    }
+    let command = match (integer(g)?, integer(m)?) {
+        (None, None) if x.is_none() && y.is_none() => return Ok(None),
+        (Some(code @ (0 | 1)), None) => Command::Move {
+            rapid: code == 0,
+            x,
+            y,
+            feed: match f {
+                Some(feed) if feed <= 0 => return Err(Error::InvalidArgument),
+                feed => feed.map(|feed| feed as u32 / 1000),
+            },
+        },
+        (Some(28), None) => Command::Home,
+        (Some(90), None) => Command::Absolute,
+        (Some(91), None) => Command::Relative,
+        (Some(92), None) => Command::SetPosition { x, y },
+        (None, Some(3)) => Command::Output {
+            index: 0,
+            value: level(s)?,
+        },
+        (None, Some(5)) => Command::Output { index: 0, value: 0 },
+        (None, Some(106)) => Command::Output {
+            index: index as u8,
+            value: level(s)?,
+        },
+        (None, Some(107)) => Command::Output {
+            index: index as u8,
+            value: 0,
+        },
+        (None, Some(114)) => Command::Report,
+        (None, Some(400)) => Command::Wait,
+        _ => return Err(Error::InvalidArgument),
+    };
+    Ok(Some(command))
+}
+
+/// A machine run by G-code lines.
+/// # Elements
+/// * `planner` - a `XyPlanner` object, moving the axes.
+/// * `outputs` - an array of PWM outputs, set by the M-codes.
+/// * `steps_per_mm` - a tuple of u32, the steps of each axis per millimeter.
+/// * `relative` - a boolean, true after `G91`.
+/// * `feed` - a u32, the feed rate of `G1` in millimeters per minute, 0 for the maximum speed.
+/// * `target` - a tuple of i32, the position at the end of the queued moves in micrometers.
+/// * `line` - an array of u8, the line being received.
+/// * `len` - a usize, the length of the line being received.
+/// * `overflow` - a boolean, true if the line being received is too long.
+/// * `pending` - an optional `Command`, the command waiting for the planner.
+pub struct GcodeMachine<X, Y, P, const N: usize, const O: usize> {
+    planner: XyPlanner<X, Y, N>,
+    outputs: [P; O],
+    steps_per_mm: (u32, u32),
+    relative: bool,
+    feed: u32,
+    target: (i32, i32),
+    line: [u8; MAX_LINE],
+    len: usize,
+    overflow: bool,
+    pending: Option<Command>,
+}
+
+impl<X, Y, P, const N: usize, const O: usize> GcodeMachine<X, Y, P, N, O>
+where
+    X: Stepper,
+    Y: Stepper,
+    P: SetDutyCycle<Error = Error>,
+{
+    /// Creates a machine at the origin, in absolute coordinates.
+    /// # Arguments
+    /// * `planner` - a `XyPlanner` object, with no moves queued.
+    /// * `outputs` - an array of PWM outputs, for example `FanPwm` objects.
+    /// * `steps_per_mm` - a tuple of u32, the steps of each axis per millimeter.
+    /// # Returns
+    /// * `a GcodeMachine object` - Which will be used for further implementations.
+    pub fn new(
+        mut planner: XyPlanner<X, Y, N>,
+        outputs: [P; O],
+        steps_per_mm: (u32, u32),
+    ) -> GcodeMachine<X, Y, P, N, O> {
+        planner.stop();
+        planner.set_position(0, 0).ok();
+        GcodeMachine {
+            planner,
+            outputs,
+            steps_per_mm: (steps_per_mm.0.max(1), steps_per_mm.1.max(1)),
+            relative: false,
+            feed: 0,
+            target: (0, 0),
+            line: [0; MAX_LINE],
+            len: 0,
+            overflow: false,
+            pending: None,
+        }
+    }
+
+    /// Gives back the planner and the outputs.
+    pub fn release(self) -> (XyPlanner<X, Y, N>, [P; O]) {
+        (self.planner, self.outputs)
+    }
+
+    /// Returns the planner, for example to home the axes against end stops
+    /// and set their position with `G92` afterwards.
+    pub fn planner(&mut self) -> &mut XyPlanner<X, Y, N> {
+        &mut self.planner
+    }
+
+    /// Returns the position reached in micrometers.
+    pub fn position(&self) -> (i32, i32) {
+        let (x, y) = self.planner.position();
+        (
+            (x as i64 * 1000 / self.steps_per_mm.0 as i64) as i32,
+            (y as i64 * 1000 / self.steps_per_mm.1 as i64) as i32,
+        )
+    }
+
+    /// Returns true if no command is waiting for the planner, so that a new line can be sent.
+    pub fn is_ready(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    /// Handles one byte received over serial, and runs the line it ends.
+    /// Senders must wait for the answer to a line before sending the next one.
+    /// # Arguments
+    /// * `byte` - a u8, the byte received.
+    /// * `out` - a `embedded_io::Write` object, receiving the answers.
+    pub fn push<W: embedded_io::Write>(
+        &mut self,
+        byte: u8,
+        out: &mut W,
+    ) -> core::result::Result<(), WriteFmtError<W::Error>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::replace(&mut self.len, 0);
+                if core::mem::replace(&mut self.overflow, false) {
+                    return write!(out, "error: line too long\r\n");
+                }
+                match parse_line(&self.line[..len]) {
+                    Ok(None) if len == 0 => Ok(()),
+                    Ok(None) => write!(out, "ok\r\n"),
+                    Ok(Some(_)) if self.pending.is_some() => write!(out, "error: busy\r\n"),
+                    Ok(Some(command)) => {
+                        self.pending = Some(command);
+                        self.run(out)
+                    }
+                    Err(_) => write!(out, "error: unsupported command\r\n"),
+                }
+            }
+            _ if self.len < MAX_LINE => {
+                self.line[self.len] = byte;
+                self.len += 1;
+                Ok(())
+            }
+            _ => {
+                self.overflow = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Steps the axes and runs the command waiting for the planner once it can.
+    /// It must be called as often as `XyPlanner::poll`.
+    /// # Arguments
+    /// * `now` - a u32, the current time in microseconds, as returned by `micros`.
+    /// * `out` - a `embedded_io::Write` object, receiving the answers.
+    /// # Returns
+    /// * `a boolean` - True while moves are going on.
+    pub fn poll<W: embedded_io::Write>(
+        &mut self,
+        now: u32,
+        out: &mut W,
+    ) -> core::result::Result<bool, WriteFmtError<W::Error>> {
+        let moving = self.planner.poll(now);
+        if self.pending.is_some() {
+            self.run(out)?;
+        }
+        Ok(moving)
+    }
+
+    /// Runs the waiting command and answers it, unless it must wait further.
+    fn run<W: embedded_io::Write>(
+        &mut self,
+        out: &mut W,
+    ) -> core::result::Result<(), WriteFmtError<W::Error>> {
+        let command = match self.pending {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        match self.execute(command) {
+            Ok(false) => Ok(()),
+            Ok(true) => {
+                self.pending = None;
+                if command == Command::Report {
+                    let (x, y) = self.position();
+                    let mm = |value: i32| {
+                        let sign = if value < 0 { "-" } else { "" };
+                        (
+                            sign,
+                            value.unsigned_abs() / 1000,
+                            value.unsigned_abs() % 1000,
+                        )
+                    };
+                    let ((xs, xi, xf), (ys, yi, yf)) = (mm(x), mm(y));
+                    write!(out, "X:{}{}.{:03} Y:{}{}.{:03}\r\n", xs, xi, xf, ys, yi, yf)?;
+                }
+                write!(out, "ok\r\n")
+            }
+            Err(_) => {
+                self.pending = None;
+                write!(out, "error: command failed\r\n")
+            }
+        }
+    }
+
+    /// Converts a position in micrometers to steps.
+    fn steps(&self, position: (i32, i32)) -> (i32, i32) {
+        let convert = |value: i32, steps_per_mm: u32| {
+            (value as i64 * steps_per_mm as i64 + 500).div_euclid(1000) as i32
+        };
+        (
+            convert(position.0, self.steps_per_mm.0),
+            convert(position.1, self.steps_per_mm.1),
+        )
+    }
+
+    /// Runs a command.
+    /// # Returns
+    /// * `a Result<bool>` - False if the command must wait for the planner.
+    fn execute(&mut self, command: Command) -> Result<bool> {
+        let idle = self.planner.is_idle();
+        match command {
+            Command::Move { rapid, x, y, feed } => {
+                if self.planner.free() == 0 {
+                    return Ok(false);
+                }
+                if let Some(feed) = feed {
+                    self.feed = feed;
+                }
+                let target = if self.relative {
+                    (
+                        self.target.0 + x.unwrap_or(0),
+                        self.target.1 + y.unwrap_or(0),
+                    )
+                } else {
+                    (x.unwrap_or(self.target.0), y.unwrap_or(self.target.1))
+                };
+                let speed = if rapid { 0 } else { self.speed(target) };
+                let (sx, sy) = self.steps(target);
+                self.planner.move_to(sx, sy, speed)?;
+                self.target = target;
+            }
+            Command::Home => {
+                if self.planner.free() == 0 {
+                    return Ok(false);
+                }
+                self.planner.move_to(0, 0, 0)?;
+                self.target = (0, 0);
+            }
+            Command::Absolute => self.relative = false,
+            Command::Relative => self.relative = true,
+            Command::SetPosition { x, y } => {
+                if !idle {
+                    return Ok(false);
+                }
+                let position = match (x, y) {
+                    (None, None) => (0, 0),
+                    (x, y) => (x.unwrap_or(self.target.0), y.unwrap_or(self.target.1)),
+                };
+                let (sx, sy) = self.steps(position);
+                self.planner.set_position(sx, sy)?;
+                self.target = position;
+            }
+            Command::Output { index, value } => {
+                if !idle {
+                    return Ok(false);
+                }
+                let output = self
+                    .outputs
+                    .get_mut(index as usize)
+                    .ok_or(Error::InvalidArgument)?;
+                output.set_duty_cycle_fraction(value as u16, 255)?;
+            }
+            Command::Report => {}
+            Command::Wait => return Ok(idle),
+        }
+        Ok(true)
+    }
+
+    /// Converts the feed rate to the speed of the planner along the line to a position.
+    /// # Returns
+    /// * `a u16` - the speed in steps per second, 0 for the maximum speed.
+    fn speed(&self, target: (i32, i32)) -> u16 {
+        if self.feed == 0 {
+            return 0;
+        }
+        let (from, to) = (self.steps(self.target), self.steps(target));
+        let length = |a: (i32, i32), b: (i32, i32)| {
+            let (dx, dy) = ((b.0 - a.0) as i64, (b.1 - a.1) as i64);
+            isqrt((dx * dx + dy * dy) as u64) as u64
+        };
+        let micrometers = length(self.target, target);
+        if micrometers == 0 {
+            return 0;
+        }
+        let speed = self.feed as u64 * length(from, to) * 1000 / (60 * micrometers);
+        speed.clamp(1, u16::MAX as u64) as u16
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::convert::Infallible;
+
+    #[derive(Default)]
+    struct Axis {
+        position: i32,
+        forward: bool,
+        high: bool,
+    }
+
+    impl Stepper for Axis {
+        fn set_direction(&mut self, forward: bool) {
+            self.forward = forward;
+        }
+
+        fn set_step(&mut self, high: bool) {
+            if high && !self.high {
+                self.position += if self.forward { 1 } else { -1 };
+            }
+            self.high = high;
+        }
+    }
+
+    struct Pwm(u16);
+
+    impl embedded_hal::pwm::ErrorType for Pwm {
+        type Error = Error;
+    }
+
+    impl SetDutyCycle for Pwm {
+        fn max_duty_cycle(&self) -> u16 {
+            255
+        }
+
+        fn set_duty_cycle(&mut self, duty: u16) -> Result<()> {
+            self.0 = duty;
+            Ok(())
+        }
+    }
+
+    struct Out([u8; 128], usize);
+
+    impl embedded_io::ErrorType for Out {
+        type Error = Infallible;
+    }
+
+    impl embedded_io::Write for Out {
+        fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, Infallible> {
+            self.0[self.1..self.1 + buf.len()].copy_from_slice(buf);
+            self.1 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> core::result::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(
+            parse_line(b"N10 G1 X12.5 y-3 F600 ; comment"),
+            Ok(Some(Command::Move {
+                rapid: false,
+                x: Some(12_500),
+                y: Some(-3_000),
+                feed: Some(600),
+            }))
+        );
+        assert_eq!(
+            parse_line(b"M106P1S128*57"),
+            Ok(Some(Command::Output {
+                index: 1,
+                value: 128
+            }))
+        );
+        assert_eq!(parse_line(b"(pen up)"), Ok(None));
+        assert_eq!(parse_line(b"G2 X1"), Err(Error::InvalidArgument));
+        assert_eq!(parse_line(b"G1.5"), Err(Error::InvalidArgument));
+        assert_eq!(parse_line(b"G1 X1 X2"), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn plotting() {
+        let planner: XyPlanner<Axis, Axis, 2> =
+            XyPlanner::new(Axis::default(), Axis::default(), 4000, 100_000);
+        let mut machine = GcodeMachine::new(planner, [Pwm(0)], (80, 80));
+        let mut out = Out([0; 128], 0);
+        let mut now = 0u32;
+        for line in [&b"G91\n"[..], b"G1 X2 Y1 F1200\n", b"M3 S255\n", b"M114\n"] {
+            for &byte in line {
+                machine.push(byte, &mut out).unwrap();
+            }
+            while !machine.is_ready() {
+                machine.poll(now, &mut out).unwrap();
+                now = now.wrapping_add(20);
+            }
+        }
+        assert_eq!(machine.planner().position(), (160, 80));
+        assert_eq!(machine.position(), (2000, 1000));
+        assert_eq!(machine.outputs[0].0, 255);
+        assert_eq!(
+            &out.0[..out.1],
+            b"ok\r\nok\r\nok\r\nX:2.000 Y:1.000\r\nok\r\n"
+        );
+    }
+}
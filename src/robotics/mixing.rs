@@ -0,0 +1,191 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Conditioning of joystick axes and mixing of the stick positions into
+//! the speeds of the left and right motors of a differential drive robot.
+//! All values are in thousandths of full scale, from -1000 to 1000.
+
+/// Full scale of the axis values and motor speeds.
+pub const FULL_SCALE: i16 = 1000;
+
+/// Sets the values close to the center to zero, and stretches the rest so that
+/// the output still moves smoothly from zero to full scale.
+/// # Arguments
+/// * `value` - an i16, from -1000 to 1000.
+/// * `width` - an i16, the largest value which becomes zero.
+/// # Returns
+/// * `an i16` - The value with the dead band removed.
+pub fn deadband(value: i16, width: i16) -> i16 {
+    let width = width.clamp(0, FULL_SCALE - 1) as i32;
+    let value = value.clamp(-FULL_SCALE, FULL_SCALE) as i32;
+    if value.abs() <= width {
+        return 0;
+    }
+    let magnitude = (value.abs() - width) * FULL_SCALE as i32 / (FULL_SCALE as i32 - width);
+    (magnitude * value.signum()) as i16
+}
+
+/// Applies an exponential curve, giving finer control around the center
+/// while keeping full scale at the ends of the stick travel.
+/// # Arguments
+/// * `value` - an i16, from -1000 to 1000.
+/// * `amount` - a u8, the share of the cubic curve in percent, 0 for a linear response.
+/// # Returns
+/// * `an i16` - The value after the curve.
+pub fn expo(value: i16, amount: u8) -> i16 {
+    let x = value.clamp(-FULL_SCALE, FULL_SCALE) as i32;
+    let amount = amount.min(100) as i32;
+    let full = FULL_SCALE as i32;
+    let cube = x * x / full * x / full;
+    ((x * (100 - amount) + cube * amount) / 100) as i16
+}
+
+/// Mixes a single stick into the motor speeds: forward and backward drive
+/// both sides, left and right turn the robot. When a side would go past full
+/// scale both sides are reduced by the same ratio so that the turn keeps its shape.
+/// # Arguments
+/// * `throttle` - an i16, from -1000 for full backward to 1000 for full forward.
+/// * `steering` - an i16, from -1000 for a full left turn to 1000 for a full right turn.
+/// # Returns
+/// * `a tuple of i16` - The speeds of the left and right motors.
+pub fn arcade_drive(throttle: i16, steering: i16) -> (i16, i16) {
+    let throttle = throttle.clamp(-FULL_SCALE, FULL_SCALE) as i32;
+    let steering = steering.clamp(-FULL_SCALE, FULL_SCALE) as i32;
+    let left = throttle + steering;
+    let right = throttle - steering;
+    let largest = left.abs().max(right.abs());
+    if largest > FULL_SCALE as i32 {
+        let full = FULL_SCALE as i32;
+        (
+            (left * full / largest) as i16,
+            (right * full / largest) as i16,
+        )
+    } else {
+        (left as i16, right as i16)
+    }
+}
+
+/// Mixes two sticks into the motor speeds, each stick driving one side.
+/// # Arguments
+/// * `left` - an i16, the position of the left stick from -1000 to 1000.
+/// * `right` - an i16, the position of the right stick from -1000 to 1000.
+/// # Returns
+/// * `a tuple of i16` - The speeds of the left and right motors.
+pub fn tank_drive(left: i16, right: i16) -> (i16, i16) {
+    (
+        left.clamp(-FULL_SCALE, FULL_SCALE),
+        right.clamp(-FULL_SCALE, FULL_SCALE),
+    )
+}
+
+/// Conversion of the ADC readings of one joystick axis to a value from -1000 to 1000.
+/// # Elements
+/// * `center` - a u16, the reading with the stick released.
+/// * `range` - a u16, the difference between the center and the readings at the ends.
+/// * `deadband` - an i16, the width of the dead band around the center.
+/// * `expo` - a u8, the share of the cubic curve in percent.
+/// * `inverted` - a boolean, true to swap the ends of the axis.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JoystickAxis {
+    center: u16,
+    range: u16,
+    deadband: i16,
+    expo: u8,
+    inverted: bool,
+}
+
+impl JoystickAxis {
+    /// Creates an axis for a 10 bit ADC reading, centered at 512,
+    /// with a dead band of 5% and a linear response.
+    /// # Returns
+    /// * `a JoystickAxis object` - Which will be used for further implementations.
+    pub fn new() -> JoystickAxis {
+        JoystickAxis {
+            center: 512,
+            range: 511,
+            deadband: 50,
+            expo: 0,
+            inverted: false,
+        }
+    }
+
+    /// Sets the reading of the released stick, usually read once at start up.
+    /// # Arguments
+    /// * `center` - a u16, the reading with the stick released.
+    pub fn calibrate(&mut self, center: u16) {
+        self.center = center;
+        self.range = center.max(1023u16.saturating_sub(center)).max(1);
+    }
+
+    /// Sets the width of the dead band.
+    /// # Arguments
+    /// * `deadband` - an i16, from 0 to 1000.
+    pub fn set_deadband(&mut self, deadband: i16) {
+        self.deadband = deadband;
+    }
+
+    /// Sets the exponential curve.
+    /// # Arguments
+    /// * `expo` - a u8, the share of the cubic curve in percent.
+    pub fn set_expo(&mut self, expo: u8) {
+        self.expo = expo;
+    }
+
+    /// Swaps the ends of the axis.
+    /// # Arguments
+    /// * `inverted` - a boolean, true to make the low readings positive.
+    pub fn set_inverted(&mut self, inverted: bool) {
+        self.inverted = inverted;
+    }
+
+    /// Converts a reading.
+    /// # Arguments
+    /// * `reading` - a u32, the value read by the ADC, as returned by `AnalogPin::read`.
+    /// # Returns
+    /// * `an i16` - The position of the stick from -1000 to 1000.
+    pub fn value(&self, reading: u32) -> i16 {
+        let offset = reading.min(1023) as i32 - self.center as i32;
+        let mut value = (offset * FULL_SCALE as i32 / self.range as i32)
+            .clamp(-FULL_SCALE as i32, FULL_SCALE as i32) as i16;
+        if self.inverted {
+            value = -value;
+        }
+        expo(deadband(value, self.deadband), self.expo)
+    }
+}
+
+impl Default for JoystickAxis {
+    fn default() -> Self {
+        JoystickAxis::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mixing() {
+        assert_eq!(arcade_drive(1000, 0), (1000, 1000));
+        assert_eq!(arcade_drive(0, 500), (500, -500));
+        assert_eq!(arcade_drive(1000, 1000), (1000, 0));
+        assert_eq!(tank_drive(-1200, 300), (-1000, 300));
+        assert_eq!(deadband(40, 50), 0);
+        assert_eq!(deadband(-1000, 50), -1000);
+        assert_eq!(expo(500, 100), 125);
+        assert_eq!(JoystickAxis::new().value(1023), 1000);
+        assert_eq!(JoystickAxis::new().value(0), -1000);
+    }
+}
@@ -0,0 +1,32 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+mod easing;
+mod fusion;
+mod gcode;
+mod mixing;
+mod motor;
+mod odometry;
+mod planner;
+mod tachometer;
+
+pub use easing::*;
+pub use fusion::*;
+pub use gcode::*;
+pub use mixing::*;
+pub use motor::*;
+pub use odometry::*;
+pub use planner::*;
+pub use tachometer::*;
@@ -0,0 +1,206 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Motors driven with a signed speed, and a differential drive which
+//! sends the output of the mixing functions to its left and right motors.
+
+// Source codes required
+use super::mixing::{arcade_drive, tank_drive, FULL_SCALE};
+use crate::{Error, Result};
+use core::convert::Infallible;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+
+/// A motor whose speed and direction can be set.
+pub trait Motor {
+    /// Sets the speed of the motor.
+    /// # Arguments
+    /// * `speed` - an i16, from -1000 for full reverse to 1000 for full forward.
+    fn set_speed(&mut self, speed: i16) -> Result<()>;
+
+    /// Stops driving the motor.
+    fn stop(&mut self) -> Result<()> {
+        self.set_speed(0)
+    }
+}
+
+impl<M: Motor> Motor for &mut M {
+    fn set_speed(&mut self, speed: i16) -> Result<()> {
+        (**self).set_speed(speed)
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        (**self).stop()
+    }
+}
+
+/// A DC motor on a H-bridge like the L298N or TB6612, with two direction
+/// inputs and a PWM enable input.
+/// # Elements
+/// * `enable` - the PWM output connected to the enable input.
+/// * `forward` - the output connected to the first direction input.
+/// * `backward` - the output connected to the second direction input.
+pub struct HBridge<PWM, DIR> {
+    enable: PWM,
+    forward: DIR,
+    backward: DIR,
+}
+
+impl<PWM, DIR> HBridge<PWM, DIR>
+where
+    PWM: SetDutyCycle<Error = Error>,
+    DIR: OutputPin<Error = Infallible>,
+{
+    /// Creates the motor, stopped. The pins must already be outputs.
+    /// # Arguments
+    /// * `enable` - the PWM output connected to the enable input, for example a `DigitalPin`.
+    /// * `forward` - the output connected to the first direction input.
+    /// * `backward` - the output connected to the second direction input.
+    /// # Returns
+    /// * `a Result<HBridge>` - The motor, or the error of the PWM output.
+    pub fn new(enable: PWM, forward: DIR, backward: DIR) -> Result<HBridge<PWM, DIR>> {
+        let mut motor = HBridge {
+            enable,
+            forward,
+            backward,
+        };
+        motor.stop()?;
+        Ok(motor)
+    }
+
+    /// Gives back the pins.
+    pub fn release(self) -> (PWM, DIR, DIR) {
+        (self.enable, self.forward, self.backward)
+    }
+
+    /// Shorts the motor through the bridge, which stops it faster than `stop`.
+    pub fn brake(&mut self) -> Result<()> {
+        self.forward.set_high().ok();
+        self.backward.set_high().ok();
+        self.enable.set_duty_cycle_fully_on()
+    }
+}
+
+impl<PWM, DIR> Motor for HBridge<PWM, DIR>
+where
+    PWM: SetDutyCycle<Error = Error>,
+    DIR: OutputPin<Error = Infallible>,
+{
+    fn set_speed(&mut self, speed: i16) -> Result<()> {
+        let speed = speed.clamp(-FULL_SCALE, FULL_SCALE);
+        if speed >= 0 {
+            self.backward.set_low().ok();
+            self.forward.set_high().ok();
+        } else {
+            self.forward.set_low().ok();
+            self.backward.set_high().ok();
+        }
+        self.enable
+            .set_duty_cycle_fraction(speed.unsigned_abs(), FULL_SCALE as u16)
+    }
+
+    /// Lets the motor coast.
+    fn stop(&mut self) -> Result<()> {
+        self.forward.set_low().ok();
+        self.backward.set_low().ok();
+        self.enable.set_duty_cycle_fully_off()
+    }
+}
+
+/// A brushless motor on a DShot ESC, which only turns forward, so negative speeds stop it.
+#[cfg(feature = "sensors")]
+impl Motor for crate::sensors::DShot {
+    fn set_speed(&mut self, speed: i16) -> Result<()> {
+        if speed <= 0 {
+            self.disarm();
+        } else {
+            let throttle = speed.min(FULL_SCALE) as u32 * crate::sensors::DSHOT_MAX_THROTTLE as u32
+                / FULL_SCALE as u32;
+            self.throttle(throttle as u16, false);
+        }
+        Ok(())
+    }
+}
+
+/// A robot steered by the speed difference between its left and right motors.
+/// # Elements
+/// * `left` - the motor of the left side.
+/// * `right` - the motor of the right side.
+/// * `inverted` - a tuple of booleans, true for a side whose motor is mounted backwards.
+pub struct DifferentialDrive<L, R> {
+    left: L,
+    right: R,
+    inverted: (bool, bool),
+}
+
+impl<L: Motor, R: Motor> DifferentialDrive<L, R> {
+    /// Creates the drive.
+    /// # Arguments
+    /// * `left` - the motor of the left side.
+    /// * `right` - the motor of the right side.
+    /// # Returns
+    /// * `a DifferentialDrive object` - Which will be used for further implementations.
+    pub fn new(left: L, right: R) -> DifferentialDrive<L, R> {
+        DifferentialDrive {
+            left,
+            right,
+            inverted: (false, false),
+        }
+    }
+
+    /// Gives back the motors.
+    pub fn release(self) -> (L, R) {
+        (self.left, self.right)
+    }
+
+    /// Reverses the direction of the motors mounted backwards, usually one of both.
+    /// # Arguments
+    /// * `left` - a boolean, true to reverse the left motor.
+    /// * `right` - a boolean, true to reverse the right motor.
+    pub fn set_inverted(&mut self, left: bool, right: bool) {
+        self.inverted = (left, right);
+    }
+
+    /// Drives with a single stick, see `arcade_drive`.
+    /// # Arguments
+    /// * `throttle` - an i16, from -1000 for full backward to 1000 for full forward.
+    /// * `steering` - an i16, from -1000 for a full left turn to 1000 for a full right turn.
+    pub fn arcade(&mut self, throttle: i16, steering: i16) -> Result<()> {
+        let (left, right) = arcade_drive(throttle, steering);
+        self.drive(left, right)
+    }
+
+    /// Drives with one stick per side, see `tank_drive`.
+    /// # Arguments
+    /// * `left` - an i16, the speed of the left side from -1000 to 1000.
+    /// * `right` - an i16, the speed of the right side from -1000 to 1000.
+    pub fn tank(&mut self, left: i16, right: i16) -> Result<()> {
+        let (left, right) = tank_drive(left, right);
+        self.drive(left, right)
+    }
+
+    /// Stops both motors.
+    pub fn stop(&mut self) -> Result<()> {
+        self.left.stop()?;
+        self.right.stop()
+    }
+
+    fn drive(&mut self, left: i16, right: i16) -> Result<()> {
+        let left = if self.inverted.0 { -left } else { left };
+        let right = if self.inverted.1 { -right } else { right };
+        self.left.set_speed(left)?;
+        self.right.set_speed(right)
+    }
+}
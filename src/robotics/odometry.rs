@@ -0,0 +1,209 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Dead reckoning of the pose of a differential drive robot from the counts
+//! of its two wheel encoders. Everything is in fixed point so that no float
+//! code is pulled in: positions are in micrometers and angles are binary
+//! angles, where 65536 is a full turn and which wrap around on their own.
+//! `Odometry::update` is meant to be called from a periodic task, for example
+//! every 10 ms from the `system::scheduler::Scheduler`, with the current counts.
+
+/// Number of binary angle units of a full turn in the internal 32 bit heading per radian.
+const BAM32_PER_RADIAN: i64 = 683_565_276;
+
+/// A quarter of a sine wave in Q15, in 64 steps.
+const QUARTER_SINE: [i16; 65] = [
+    0, 804, 1608, 2411, 3212, 4011, 4808, 5602, 6393, 7180, 7962, 8740, 9512, 10279, 11039, 11793,
+    12540, 13279, 14010, 14733, 15447, 16151, 16846, 17531, 18205, 18868, 19520, 20160, 20788,
+    21403, 22006, 22595, 23170, 23732, 24279, 24812, 25330, 25833, 26320, 26791, 27246, 27684,
+    28106, 28511, 28899, 29269, 29622, 29957, 30274, 30572, 30853, 31114, 31357, 31581, 31786,
+    31972, 32138, 32286, 32413, 32522, 32610, 32679, 32729, 32758, 32767,
+];
+
+/// Returns the sine of a binary angle.
+/// # Arguments
+/// * `angle` - a u16, the angle where 65536 is a full turn.
+/// # Returns
+/// * `an i16` - The sine in Q15, 32767 for 1.
+pub fn sin_q15(angle: u16) -> i16 {
+    let within = angle & 0x3FFF;
+    // Mirror the second and fourth quarters.
+    let position = if angle & 0x4000 != 0 {
+        0x4000 - within
+    } else {
+        within
+    };
+    let index = (position >> 8) as usize;
+    let fraction = (position & 0xFF) as i32;
+    let value = if index == 64 {
+        QUARTER_SINE[64] as i32
+    } else {
+        let low = QUARTER_SINE[index] as i32;
+        let high = QUARTER_SINE[index + 1] as i32;
+        low + (high - low) * fraction / 256
+    };
+    if angle & 0x8000 != 0 {
+        -value as i16
+    } else {
+        value as i16
+    }
+}
+
+/// Returns the cosine of a binary angle.
+/// # Arguments
+/// * `angle` - a u16, the angle where 65536 is a full turn.
+/// # Returns
+/// * `an i16` - The cosine in Q15, 32767 for 1.
+pub fn cos_q15(angle: u16) -> i16 {
+    sin_q15(angle.wrapping_add(0x4000))
+}
+
+/// Position and orientation of the robot.
+/// The heading is 0 along the x axis and grows counterclockwise.
+/// # Elements
+/// * `x` - an i32, the position along the x axis in micrometers.
+/// * `y` - an i32, the position along the y axis in micrometers.
+/// * `heading` - a u16, the orientation as a binary angle, 16384 is a quarter turn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Pose {
+    pub x: i32,
+    pub y: i32,
+    pub heading: u16,
+}
+
+impl Pose {
+    /// Returns the heading in milliradians, from -3141 to 3141.
+    pub fn heading_milliradians(&self) -> i16 {
+        (self.heading as i16 as i32 * 6283 / 65536) as i16
+    }
+}
+
+/// Pose estimation from two wheel encoders.
+/// # Elements
+/// * `um_per_tick` - a u32, the distance travelled by a wheel per count, in 1/65536 micrometers.
+/// * `track` - a u32, the distance between the wheels in micrometers.
+/// * `last` - a `Option` of a tuple of i32, the counts at the previous update.
+/// * `x` - an i32, the x position in micrometers.
+/// * `y` - an i32, the y position in micrometers.
+/// * `heading` - a u32, the orientation where 2^32 is a full turn.
+pub struct Odometry {
+    um_per_tick: u32,
+    track: u32,
+    last: Option<(i32, i32)>,
+    x: i32,
+    y: i32,
+    heading: u32,
+}
+
+impl Odometry {
+    /// Creates the odometry at the origin, heading along the x axis.
+    /// # Arguments
+    /// * `ticks_per_revolution` - a u32, the counts of an encoder for one turn of its wheel, with all four quadrature edges.
+    /// * `wheel_diameter` - a u32, the diameter of the wheels in micrometers.
+    /// * `track` - a u32, the distance between the contact points of the wheels in micrometers.
+    /// # Returns
+    /// * `an Odometry object` - Which will be used for further implementations.
+    pub fn new(ticks_per_revolution: u32, wheel_diameter: u32, track: u32) -> Odometry {
+        // Circumference with pi as 355 / 113.
+        let circumference = wheel_diameter as u64 * 355 * 65536 / 113;
+        Odometry {
+            um_per_tick: (circumference / ticks_per_revolution.max(1) as u64) as u32,
+            track: track.max(1),
+            last: None,
+            x: 0,
+            y: 0,
+            heading: 0,
+        }
+    }
+
+    /// Adds the movement since the previous update to the pose.
+    /// The first update only stores the counts.
+    /// # Arguments
+    /// * `left` - an i32, the current count of the left encoder, growing when the wheel goes forward.
+    /// * `right` - an i32, the current count of the right encoder, growing when the wheel goes forward.
+    /// # Returns
+    /// * `a Pose object` - The new pose.
+    pub fn update(&mut self, left: i32, right: i32) -> Pose {
+        let (last_left, last_right) = self.last.unwrap_or((left, right));
+        self.last = Some((left, right));
+
+        let left = (left.wrapping_sub(last_left) as i64 * self.um_per_tick as i64) >> 16;
+        let right = (right.wrapping_sub(last_right) as i64 * self.um_per_tick as i64) >> 16;
+        let distance = (left + right) / 2;
+        let turn = (right - left) * BAM32_PER_RADIAN / self.track as i64;
+
+        // Moving along the mean heading of the step follows arcs closely.
+        let middle = self.heading.wrapping_add((turn / 2) as u32);
+        let angle = (middle >> 16) as u16;
+        self.x = self
+            .x
+            .wrapping_add((distance * cos_q15(angle) as i64 / 32767) as i32);
+        self.y = self
+            .y
+            .wrapping_add((distance * sin_q15(angle) as i64 / 32767) as i32);
+        self.heading = self.heading.wrapping_add(turn as u32);
+        self.pose()
+    }
+
+    /// Returns the current pose.
+    pub fn pose(&self) -> Pose {
+        Pose {
+            x: self.x,
+            y: self.y,
+            heading: (self.heading >> 16) as u16,
+        }
+    }
+
+    /// Moves the robot to a known pose, for example when it reaches a landmark.
+    /// # Arguments
+    /// * `pose` - a `Pose` object, the new pose.
+    pub fn set_pose(&mut self, pose: Pose) {
+        self.x = pose.x;
+        self.y = pose.y;
+        self.heading = (pose.heading as u32) << 16;
+    }
+
+    /// Moves the robot back to the origin, heading along the x axis.
+    pub fn reset(&mut self) {
+        self.set_pose(Pose::default());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dead_reckoning() {
+        assert_eq!(sin_q15(0), 0);
+        assert_eq!(sin_q15(0x4000), 32767);
+        assert_eq!(cos_q15(0x8000), -32767);
+
+        // 100 mm wheels, 200 mm apart, 1000 counts per turn.
+        let mut odometry = Odometry::new(1000, 100_000, 200_000);
+        odometry.update(0, 0);
+        let pose = odometry.update(1000, 1000);
+        assert!((pose.x - 314_159).abs() < 10 && pose.y == 0);
+
+        // Turning in place by a quarter turn takes a quarter of the track circumference.
+        let pose = odometry.update(500, 1500);
+        assert!((pose.heading as i32 - 0x4000).abs() < 8);
+        assert!((pose.x - 314_159).abs() < 10);
+
+        let pose = odometry.update(1500, 2500);
+        assert!((pose.y - 314_159).abs() < 20);
+        assert!((pose.heading_milliradians() - 1571).abs() < 2);
+    }
+}
@@ -0,0 +1,449 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Stepper motors on A4988 style drivers, and a planner moving two of them
+//! together along straight lines, enough for pen plotters and camera sliders.
+//! The axis with the most steps paces each move with a trapezoidal speed
+//! profile, and the other axis is stepped in between with Bresenham's line
+//! algorithm, so both arrive together and the path stays straight.
+//! Each move starts and ends at rest, there is no blending between moves.
+
+// Source codes required
+use crate::collections::RingBuffer;
+use crate::{Error, Result};
+use core::convert::Infallible;
+use embedded_hal::digital::OutputPin;
+
+/// A stepper motor driver moved one step on each rising edge of its step input.
+pub trait Stepper {
+    /// Sets the direction of the next steps.
+    /// # Arguments
+    /// * `forward` - a boolean, true to count the position up.
+    fn set_direction(&mut self, forward: bool);
+
+    /// Sets the level of the step input.
+    /// # Arguments
+    /// * `high` - a boolean, true for a high level, which moves the motor one step.
+    fn set_step(&mut self, high: bool);
+}
+
+/// A stepper motor on an A4988, DRV8825 or similar driver, with its enable
+/// input tied low or driven separately.
+/// # Elements
+/// * `step` - the output connected to the STEP input.
+/// * `dir` - the output connected to the DIR input.
+/// * `inverted` - a boolean, true if the motor turns backwards for a high DIR.
+pub struct A4988<STEP, DIR> {
+    step: STEP,
+    dir: DIR,
+    inverted: bool,
+}
+
+impl<STEP, DIR> A4988<STEP, DIR>
+where
+    STEP: OutputPin<Error = Infallible>,
+    DIR: OutputPin<Error = Infallible>,
+{
+    /// Takes control of a driver, with its step input low. The pins must already be outputs.
+    /// # Arguments
+    /// * `step` - the output connected to the STEP input, for example a `DigitalPin`.
+    /// * `dir` - the output connected to the DIR input.
+    /// # Returns
+    /// * `an A4988 object` - Which will be used for further implementations.
+    pub fn new(mut step: STEP, dir: DIR) -> A4988<STEP, DIR> {
+        step.set_low().ok();
+        A4988 {
+            step,
+            dir,
+            inverted: false,
+        }
+    }
+
+    /// Swaps the direction of the motor, for one wired or mounted backwards.
+    /// # Arguments
+    /// * `inverted` - a boolean, true if the motor turns backwards for a high DIR.
+    pub fn set_inverted(&mut self, inverted: bool) {
+        self.inverted = inverted;
+    }
+
+    /// Gives back the pins.
+    pub fn release(self) -> (STEP, DIR) {
+        (self.step, self.dir)
+    }
+}
+
+impl<STEP, DIR> Stepper for A4988<STEP, DIR>
+where
+    STEP: OutputPin<Error = Infallible>,
+    DIR: OutputPin<Error = Infallible>,
+{
+    fn set_direction(&mut self, forward: bool) {
+        if forward != self.inverted {
+            self.dir.set_high().ok();
+        } else {
+            self.dir.set_low().ok();
+        }
+    }
+
+    fn set_step(&mut self, high: bool) {
+        if high {
+            self.step.set_high().ok();
+        } else {
+            self.step.set_low().ok();
+        }
+    }
+}
+
+/// A straight move of the planner.
+/// # Elements
+/// * `x` - an i32, the position to reach on the X axis in steps.
+/// * `y` - an i32, the position to reach on the Y axis in steps.
+/// * `speed` - a u16, the speed along the line in steps per second, 0 for the maximum speed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Move {
+    pub x: i32,
+    pub y: i32,
+    pub speed: u16,
+}
+
+/// The move being stepped by the planner.
+/// # Elements
+/// * `forward` - a tuple of booleans, the direction of each axis.
+/// * `delta` - a tuple of u32, the number of steps of each axis.
+/// * `major` - a u32, the number of steps of the axis pacing the move.
+/// * `done` - a u32, the number of steps made on that axis.
+/// * `error` - a u32, the Bresenham error term of the other axis.
+/// * `speed` - a u32, the cruise speed of the pacing axis in steps per second.
+/// * `acceleration` - a u32, the acceleration of the pacing axis in steps per second squared.
+#[derive(Clone, Copy)]
+struct Line {
+    forward: (bool, bool),
+    delta: (u32, u32),
+    major: u32,
+    done: u32,
+    error: u32,
+    speed: u32,
+    acceleration: u32,
+}
+
+/// Two steppers moved together along queued straight lines.
+/// # Elements
+/// * `x` - the stepper of the X axis.
+/// * `y` - the stepper of the Y axis.
+/// * `queue` - a RingBuffer object, the moves waiting to be started.
+/// * `position` - a tuple of i32, the position reached in steps.
+/// * `end` - a tuple of i32, the position at the end of the last queued move.
+/// * `max_speed` - a u16, the highest speed along a line in steps per second.
+/// * `acceleration` - a u32, the acceleration along a line in steps per second squared.
+/// * `line` - an optional `Line`, the move being stepped.
+/// * `last_step` - a u32, the time of the last step in microseconds.
+/// * `interval` - a u32, the time until the next step in microseconds.
+/// * `pulse` - a boolean, true while step inputs are left high.
+pub struct XyPlanner<X, Y, const N: usize> {
+    x: X,
+    y: Y,
+    queue: RingBuffer<Move, N>,
+    position: (i32, i32),
+    end: (i32, i32),
+    max_speed: u16,
+    acceleration: u32,
+    line: Option<Line>,
+    last_step: u32,
+    interval: u32,
+    pulse: bool,
+}
+
+impl<X: Stepper, Y: Stepper, const N: usize> XyPlanner<X, Y, N> {
+    /// Takes control of two steppers, at position (0, 0).
+    /// # Arguments
+    /// * `x` - the stepper of the X axis, for example an `A4988`.
+    /// * `y` - the stepper of the Y axis.
+    /// * `max_speed` - a u16, the highest speed along a line in steps per second.
+    /// * `acceleration` - a u32, the acceleration along a line in steps per second squared.
+    /// # Returns
+    /// * `a XyPlanner object` - Which will be used for further implementations.
+    pub fn new(x: X, y: Y, max_speed: u16, acceleration: u32) -> XyPlanner<X, Y, N> {
+        XyPlanner {
+            x,
+            y,
+            queue: RingBuffer::new(),
+            position: (0, 0),
+            end: (0, 0),
+            max_speed: max_speed.max(1),
+            acceleration: acceleration.max(1),
+            line: None,
+            last_step: 0,
+            interval: 0,
+            pulse: false,
+        }
+    }
+
+    /// Gives back the steppers.
+    pub fn release(self) -> (X, Y) {
+        (self.x, self.y)
+    }
+
+    /// Sets the limits of the moves started from now on.
+    /// # Arguments
+    /// * `max_speed` - a u16, the highest speed along a line in steps per second.
+    /// * `acceleration` - a u32, the acceleration along a line in steps per second squared.
+    pub fn set_limits(&mut self, max_speed: u16, acceleration: u32) {
+        self.max_speed = max_speed.max(1);
+        self.acceleration = acceleration.max(1);
+    }
+
+    /// Queues a move to an absolute position.
+    /// # Arguments
+    /// * `x` - an i32, the position to reach on the X axis in steps.
+    /// * `y` - an i32, the position to reach on the Y axis in steps.
+    /// * `speed` - a u16, the speed along the line in steps per second, 0 for the maximum speed.
+    /// # Returns
+    /// * `a Result` - `NotReady` if the queue is full.
+    pub fn move_to(&mut self, x: i32, y: i32, speed: u16) -> Result<()> {
+        if !self.queue.push(Move { x, y, speed }) {
+            return Err(Error::NotReady);
+        }
+        self.end = (x, y);
+        Ok(())
+    }
+
+    /// Queues a move relative to the end of the last queued move.
+    /// # Arguments
+    /// * `dx` - an i32, the steps to make on the X axis.
+    /// * `dy` - an i32, the steps to make on the Y axis.
+    /// * `speed` - a u16, the speed along the line in steps per second, 0 for the maximum speed.
+    /// # Returns
+    /// * `a Result` - `NotReady` if the queue is full.
+    pub fn move_by(&mut self, dx: i32, dy: i32, speed: u16) -> Result<()> {
+        self.move_to(self.end.0 + dx, self.end.1 + dy, speed)
+    }
+
+    /// Stops at once, without decelerating, and drops the queued moves.
+    /// Steps may be lost when stopping at high speed.
+    pub fn stop(&mut self) {
+        self.queue.clear();
+        self.line = None;
+        self.end = self.position;
+    }
+
+    /// Sets the current position, for example after homing against end stops.
+    /// # Arguments
+    /// * `x` - an i32, the new position on the X axis in steps.
+    /// * `y` - an i32, the new position on the Y axis in steps.
+    /// # Returns
+    /// * `a Result` - `InvalidMode` while moves are going on.
+    pub fn set_position(&mut self, x: i32, y: i32) -> Result<()> {
+        if !self.is_idle() {
+            return Err(Error::InvalidMode);
+        }
+        self.position = (x, y);
+        self.end = (x, y);
+        Ok(())
+    }
+
+    /// Returns the position reached, in steps.
+    pub fn position(&self) -> (i32, i32) {
+        self.position
+    }
+
+    /// Returns the position at the end of the queued moves, in steps.
+    pub fn target(&self) -> (i32, i32) {
+        self.end
+    }
+
+    /// Returns the number of moves which can still be queued.
+    pub fn free(&self) -> usize {
+        N - self.queue.len()
+    }
+
+    /// Returns true once all the moves are done.
+    pub fn is_idle(&self) -> bool {
+        self.line.is_none() && self.queue.is_empty()
+    }
+
+    /// Makes the step due at the current time, if any, and starts the next move.
+    /// It must be called more often than the step interval at the maximum speed,
+    /// each step pulse lasts until the next call.
+    /// # Arguments
+    /// * `now` - a u32, the current time in microseconds, as returned by `micros`.
+    /// # Returns
+    /// * `a boolean` - True while moves are going on.
+    pub fn poll(&mut self, now: u32) -> bool {
+        if self.pulse {
+            self.x.set_step(false);
+            self.y.set_step(false);
+            self.pulse = false;
+        }
+        let mut line = match self.line {
+            Some(line) => line,
+            None => return self.start(now),
+        };
+        if now.wrapping_sub(self.last_step) < self.interval {
+            return true;
+        }
+        self.last_step = now;
+
+        // The pacing axis steps every time, the other one when its error overflows.
+        let x_major = line.delta.0 >= line.delta.1;
+        line.error += if x_major { line.delta.1 } else { line.delta.0 };
+        let minor = line.error >= line.major;
+        if minor {
+            line.error -= line.major;
+        }
+        if x_major || minor {
+            self.x.set_step(true);
+            self.position.0 += if line.forward.0 { 1 } else { -1 };
+        }
+        if !x_major || minor {
+            self.y.set_step(true);
+            self.position.1 += if line.forward.1 { 1 } else { -1 };
+        }
+        self.pulse = true;
+
+        line.done += 1;
+        if line.done == line.major {
+            self.line = None;
+        } else {
+            self.interval = Self::interval(&line);
+            self.line = Some(line);
+        }
+        true
+    }
+
+    /// Starts the next queued move which is not empty.
+    fn start(&mut self, now: u32) -> bool {
+        while let Some(next) = self.queue.pop() {
+            let dx = next.x - self.position.0;
+            let dy = next.y - self.position.1;
+            let delta = (dx.unsigned_abs(), dy.unsigned_abs());
+            let major = delta.0.max(delta.1);
+            if major == 0 {
+                continue;
+            }
+
+            // Scales the limits along the line to the pacing axis, so that neither axis exceeds them.
+            let length = isqrt(delta.0 as u64 * delta.0 as u64 + delta.1 as u64 * delta.1 as u64);
+            let speed = match next.speed {
+                0 => self.max_speed,
+                speed => speed.min(self.max_speed),
+            };
+            let line = Line {
+                forward: (dx >= 0, dy >= 0),
+                delta,
+                major,
+                done: 0,
+                error: major / 2,
+                speed: (speed as u64 * major as u64 / length as u64).max(1) as u32,
+                acceleration: (self.acceleration as u64 * major as u64 / length as u64).max(1)
+                    as u32,
+            };
+            self.x.set_direction(line.forward.0);
+            self.y.set_direction(line.forward.1);
+            self.last_step = now;
+            self.interval = Self::interval(&line);
+            self.line = Some(line);
+            return true;
+        }
+        false
+    }
+
+    /// Time until the next step of a line in microseconds, from the speed
+    /// reachable after accelerating over the steps made, or still able to
+    /// stop over the steps left.
+    fn interval(line: &Line) -> u32 {
+        let steps = (line.done + 1).min(line.major - line.done);
+        let reachable = isqrt(2 * line.acceleration as u64 * steps as u64);
+        1_000_000 / reachable.clamp(1, line.speed)
+    }
+}
+
+/// Computes the integer square root of a number using the bitwise method.
+pub(super) fn isqrt(val: u64) -> u32 {
+    let mut num = val;
+    let mut res: u64 = 0;
+    let mut bit: u64 = 1 << 62;
+
+    while bit > num {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if num >= res + bit {
+            num -= res + bit;
+            res = (res >> 1) + bit;
+        } else {
+            res >>= 1;
+        }
+        bit >>= 2;
+    }
+    res as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct Axis {
+        position: i32,
+        forward: bool,
+        high: bool,
+    }
+
+    impl Stepper for Axis {
+        fn set_direction(&mut self, forward: bool) {
+            self.forward = forward;
+        }
+
+        fn set_step(&mut self, high: bool) {
+            if high && !self.high {
+                self.position += if self.forward { 1 } else { -1 };
+            }
+            self.high = high;
+        }
+    }
+
+    #[test]
+    fn straight_lines() {
+        let mut planner: XyPlanner<Axis, Axis, 4> =
+            XyPlanner::new(Axis::default(), Axis::default(), 2000, 50_000);
+        planner.move_to(300, -100, 0).unwrap();
+        planner.move_by(-300, 0, 500).unwrap();
+        assert_eq!(planner.target(), (0, -100));
+
+        let mut now = 0u32;
+        let mut first = None;
+        let mut fastest = u32::MAX;
+        while planner.poll(now) {
+            let (x, y) = planner.position();
+            if planner.line.is_some() && planner.end == (0, -100) && planner.queue.len() == 1 {
+                // The Y axis stays within a step of the line while X paces it.
+                assert!((x + 3 * y).abs() <= 3);
+                first.get_or_insert(planner.interval);
+                fastest = fastest.min(planner.interval);
+            }
+            now = now.wrapping_add(50);
+        }
+        planner.poll(now);
+        assert_eq!(planner.position(), (0, -100));
+        assert_eq!(planner.x.position, 0);
+        assert_eq!(planner.y.position, -100);
+        assert!(!planner.x.high && !planner.y.high);
+        // Accelerates from rest up to the X share of the speed along the line.
+        assert!(first.unwrap() > 4 * fastest);
+        assert_eq!(fastest, 1_000_000 / (2000 * 300 / 316));
+        assert!(planner.set_position(10, 10).is_ok());
+    }
+}
@@ -0,0 +1,209 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Rotation speed of fans and motors from the pulses of a tachometer output
+//! or an encoder. The pulses are counted elsewhere, by `hal::counter` or by
+//! any other `PulseCounter`, and `Tachometer::update` turns the count into
+//! revolutions per minute averaged over the last samples. A shaft which gives
+//! no pulse for a while is reported as stalled through a callback.
+
+/// A source of a running count of pulses, which may wrap around.
+pub trait PulseCounter {
+    /// Returns the number of pulses counted so far.
+    fn pulses(&self) -> u32;
+}
+
+#[cfg(all(
+    feature = "counter",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+impl PulseCounter for crate::hal::counter::FrequencyCounter {
+    fn pulses(&self) -> u32 {
+        self.count()
+    }
+}
+
+/// Rotation speed measurement with a rolling average of `N` samples.
+/// # Elements
+/// * `pulses_per_revolution` - a u16, the pulses given by one turn, 2 for most PC fans.
+/// * `period` - a u32, the time between samples in milliseconds.
+/// * `samples` - an array of u32, the last speeds measured.
+/// * `next` - a usize, the index where the next sample goes.
+/// * `filled` - a usize, the number of samples measured, at most `N`.
+/// * `last` - a `Option` of a tuple of u32, the count and time of the previous sample.
+/// * `last_pulse` - a u32, the time at which the count last changed.
+/// * `stall_timeout` - a u32, the time without pulses after which the shaft is stalled, 0 to never report it.
+/// * `stalled` - a boolean, true while the shaft is stalled.
+/// * `on_stall` - an optional function called with true when the shaft stalls and false when it turns again.
+pub struct Tachometer<const N: usize> {
+    pulses_per_revolution: u16,
+    period: u32,
+    samples: [u32; N],
+    next: usize,
+    filled: usize,
+    last: Option<(u32, u32)>,
+    last_pulse: u32,
+    stall_timeout: u32,
+    stalled: bool,
+    on_stall: Option<fn(bool)>,
+}
+
+impl<const N: usize> Tachometer<N> {
+    /// Creates a tachometer without stall detection.
+    /// # Arguments
+    /// * `pulses_per_revolution` - a u16, the pulses given by one turn of the shaft.
+    /// * `period` - a u32, the time between samples in milliseconds, longer for slow shafts.
+    /// # Returns
+    /// * `a Tachometer object` - Which will be used for further implementations.
+    pub fn new(pulses_per_revolution: u16, period: u32) -> Tachometer<N> {
+        Tachometer {
+            pulses_per_revolution: pulses_per_revolution.max(1),
+            period: period.max(1),
+            samples: [0; N],
+            next: 0,
+            filled: 0,
+            last: None,
+            last_pulse: 0,
+            stall_timeout: 0,
+            stalled: false,
+            on_stall: None,
+        }
+    }
+
+    /// Enables the stall detection.
+    /// # Arguments
+    /// * `timeout` - a u32, the time without pulses after which the shaft is stalled in milliseconds, 0 to disable.
+    /// * `on_stall` - an optional function called with true when the shaft stalls and false when it turns again.
+    pub fn set_stall_detection(&mut self, timeout: u32, on_stall: Option<fn(bool)>) {
+        self.stall_timeout = timeout;
+        self.on_stall = on_stall;
+    }
+
+    /// Takes a sample if the period has passed since the previous one.
+    /// # Arguments
+    /// * `count` - a u32, the running count of pulses.
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    /// # Returns
+    /// * `a Option<u32>` - The averaged speed in revolutions per minute when a sample was taken.
+    pub fn update(&mut self, count: u32, now: u32) -> Option<u32> {
+        let (last_count, last_time) = match self.last {
+            Some(last) => last,
+            None => {
+                self.last = Some((count, now));
+                self.last_pulse = now;
+                return None;
+            }
+        };
+        let elapsed = now.wrapping_sub(last_time);
+        if elapsed < self.period {
+            return None;
+        }
+        let pulses = count.wrapping_sub(last_count);
+        self.last = Some((count, now));
+
+        let rpm = pulses as u64 * 60_000 / (self.pulses_per_revolution as u64 * elapsed as u64);
+        self.push(rpm as u32);
+        if pulses != 0 {
+            self.last_pulse = now;
+        }
+        self.check_stall(pulses != 0, now);
+        Some(self.rpm())
+    }
+
+    /// Takes a sample from a counter if the period has passed.
+    /// # Arguments
+    /// * `counter` - a reference to the `PulseCounter`, for example a `FrequencyCounter`.
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    /// # Returns
+    /// * `a Option<u32>` - The averaged speed in revolutions per minute when a sample was taken.
+    pub fn poll<P: PulseCounter>(&mut self, counter: &P, now: u32) -> Option<u32> {
+        self.update(counter.pulses(), now)
+    }
+
+    /// Returns the average of the speeds measured, in revolutions per minute.
+    pub fn rpm(&self) -> u32 {
+        if self.filled == 0 || self.stalled {
+            return 0;
+        }
+        let sum: u64 = self.samples[..self.filled]
+            .iter()
+            .map(|&sample| sample as u64)
+            .sum();
+        (sum / self.filled as u64) as u32
+    }
+
+    /// Returns true while the shaft is stalled.
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    /// Forgets the samples, for example after a change of the speed setting.
+    pub fn clear(&mut self) {
+        self.filled = 0;
+        self.next = 0;
+    }
+
+    fn push(&mut self, rpm: u32) {
+        if N == 0 {
+            return;
+        }
+        self.samples[self.next] = rpm;
+        self.next = (self.next + 1) % N;
+        self.filled = (self.filled + 1).min(N);
+    }
+
+    fn check_stall(&mut self, turning: bool, now: u32) {
+        if self.stall_timeout == 0 {
+            return;
+        }
+        let stalled = if turning {
+            false
+        } else {
+            now.wrapping_sub(self.last_pulse) >= self.stall_timeout
+        };
+        if stalled != self.stalled {
+            self.stalled = stalled;
+            if stalled {
+                self.clear();
+            }
+            if let Some(on_stall) = self.on_stall {
+                on_stall(stalled);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fan_speed() {
+        // A PC fan with 2 pulses per turn, sampled every 500 ms.
+        let mut tachometer: Tachometer<4> = Tachometer::new(2, 500);
+        tachometer.set_stall_detection(1000, None);
+        assert_eq!(tachometer.update(0, 0), None);
+        assert_eq!(tachometer.update(10, 250), None);
+        assert_eq!(tachometer.update(20, 500), Some(1200));
+        assert_eq!(tachometer.update(42, 1000), Some(1260));
+        assert_eq!(tachometer.update(42, 1500), Some(840));
+        assert!(!tachometer.is_stalled());
+        assert_eq!(tachometer.update(42, 2000), Some(0));
+        assert!(tachometer.is_stalled());
+        tachometer.update(62, 2500);
+        assert!(!tachometer.is_stalled());
+        assert_eq!(tachometer.rpm(), 1200);
+    }
+}
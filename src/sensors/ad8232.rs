@@ -0,0 +1,242 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! AD8232 single lead heart rate monitor front ends, which amplify the
+//! biopotential of an ECG or EMG electrode pair into an analog output
+//! centered on half the supply. The output is sampled at a steady rate by
+//! `hal::sampler`, cleaned up by a chain of `math` filters removing the
+//! baseline wander, the mains hum and the muscle noise, and searched for
+//! the QRS complexes of heart beats with a simplified Pan-Tompkins detector:
+//! band pass, derivative, square, integration and an adaptive threshold.
+//! The LO+ and LO- outputs of the board go high when an electrode is off,
+//! the signal is meaningless meanwhile and the detector starts over.
+//! The module needs the `math` and `sampler` features.
+//! For more information see `<https://www.analog.com/media/en/technical-documentation/data-sheets/ad8232.pdf>`
+
+// Source codes required
+use crate::hal::sampler::{self, SampleRate};
+use crate::math::{Biquad, Chain, Filter, HighPass, LowPass};
+use embedded_hal::digital::InputPin;
+
+/// Number of beat to beat intervals averaged by `heart_rate`.
+const INTERVALS: usize = 4;
+
+/// Filters of the displayed signal: baseline removal, mains notch and muscle noise low pass.
+pub type EcgFilter = Chain<HighPass, Chain<Biquad, LowPass>>;
+
+/// A filtered sample.
+/// # Elements
+/// * `value` - an i32, the filtered signal in ADC counts around 0, 0 while an electrode is off.
+/// * `beat` - a boolean, true for the sample at which a heart beat was detected.
+/// * `leads_off` - a boolean, true while an electrode is off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EcgSample {
+    pub value: i32,
+    pub beat: bool,
+    pub leads_off: bool,
+}
+
+/// An AD8232 board.
+/// # Elements
+/// * `lo_plus` - the input pin connected to LO+.
+/// * `lo_minus` - the input pin connected to LO-.
+/// * `channel` - a u8, the analog input connected to OUTPUT.
+/// * `rate` - a `SampleRate` object, the sample rate.
+/// * `filter` - the filters of the displayed signal.
+/// * `band` - the band pass filter keeping the QRS complexes, around 10 Hz.
+/// * `integrator` - the low pass filter integrating the energy of the slopes.
+/// * `previous` - an i32, the previous band passed sample, for the derivative.
+/// * `peak` - an i64, the decaying peak of the integrated energy.
+/// * `decay` - an i64, the decay of the peak per sample, with 16 fractional bits.
+/// * `above` - a boolean, true while the energy is above the threshold.
+/// * `count` - a u32, the number of samples processed since the detector started.
+/// * `last_beat` - an optional u32, the sample of the last beat.
+/// * `refractory` - a u32, the shortest number of samples between two beats.
+/// * `intervals` - an array of u16, the last beat to beat intervals in milliseconds.
+/// * `filled` - a usize, the number of intervals measured, at most `INTERVALS`.
+pub struct Ad8232<P: InputPin> {
+    lo_plus: P,
+    lo_minus: P,
+    channel: u8,
+    rate: SampleRate,
+    filter: EcgFilter,
+    band: Biquad,
+    integrator: LowPass,
+    previous: i32,
+    peak: i64,
+    decay: i64,
+    above: bool,
+    count: u32,
+    last_beat: Option<u32>,
+    refractory: u32,
+    intervals: [u16; INTERVALS],
+    filled: usize,
+}
+
+impl<P: InputPin> Ad8232<P> {
+    /// Creates the driver, the sampling is started by `start`.
+    /// # Arguments
+    /// * `lo_plus` - an input pin connected to LO+.
+    /// * `lo_minus` - an input pin connected to LO-.
+    /// * `channel` - a u8, the analog input connected to OUTPUT.
+    /// * `mains` - a u16, the frequency of the mains in Hz, 50 or 60, removed by a notch.
+    /// * `rate` - a `SampleRate` object, `SampleRate::Timer0` suits heart beats.
+    /// # Returns
+    /// * `a Ad8232 object` - Which will be used for further implementations.
+    pub fn new(lo_plus: P, lo_minus: P, channel: u8, mains: u16, rate: SampleRate) -> Ad8232<P> {
+        let hertz = rate.hertz();
+        Ad8232 {
+            lo_plus,
+            lo_minus,
+            channel,
+            rate,
+            filter: Chain(
+                HighPass::new(0.5, hertz),
+                Chain(
+                    Biquad::notch(mains as f32, 5.0, hertz),
+                    LowPass::new(40.0, hertz),
+                ),
+            ),
+            band: Biquad::band_pass(10.0, 1.0, hertz),
+            integrator: LowPass::new(5.0, hertz),
+            previous: 0,
+            peak: 0,
+            decay: (65536.0 / hertz) as i64,
+            above: false,
+            count: 0,
+            last_beat: None,
+            refractory: (hertz / 5.0) as u32,
+            intervals: [0; INTERVALS],
+            filled: 0,
+        }
+    }
+
+    /// Starts sampling the output.
+    /// # Returns
+    /// * `a boolean` - false if the analog input does not exist.
+    pub fn start(&mut self) -> bool {
+        self.restart();
+        sampler::start(self.channel, self.rate)
+    }
+
+    /// Stops sampling the output.
+    pub fn stop(&mut self) {
+        sampler::stop();
+    }
+
+    /// Returns true while an electrode is off, or if a lead off pin cannot be read.
+    pub fn leads_off(&mut self) -> bool {
+        self.lo_plus.is_high().unwrap_or(true) || self.lo_minus.is_high().unwrap_or(true)
+    }
+
+    /// Processes the oldest sample taken, to be called until it returns `None`.
+    /// # Returns
+    /// * `a Option<EcgSample>` - The filtered sample, `None` if no sample is waiting.
+    pub fn poll(&mut self) -> Option<EcgSample> {
+        let sample = sampler::take()?;
+        if self.leads_off() {
+            self.restart();
+            return Some(EcgSample {
+                value: 0,
+                beat: false,
+                leads_off: true,
+            });
+        }
+        Some(self.process(sample))
+    }
+
+    /// Filters a sample and looks for a heart beat, for samples taken some other way.
+    /// # Arguments
+    /// * `sample` - a u16, the 10 bit sample of the output.
+    /// # Returns
+    /// * `a EcgSample object` - The filtered sample.
+    pub fn process(&mut self, sample: u16) -> EcgSample {
+        let centered = sample as i32 - 512;
+        let value = self.filter.process(centered);
+
+        let band = self.band.process(centered);
+        let slope = (band - self.previous).clamp(-2000, 2000);
+        self.previous = band;
+        let energy = self.integrator.process(slope * slope) as i64;
+
+        self.peak = (self.peak - ((self.peak * self.decay) >> 16)).max(energy);
+        let threshold = (self.peak / 2).max(16);
+        let mut beat = false;
+        if !self.above && energy > threshold {
+            self.above = true;
+            beat = self.beat();
+        } else if self.above && energy < threshold / 2 {
+            self.above = false;
+        }
+        self.count = self.count.wrapping_add(1);
+        EcgSample {
+            value,
+            beat,
+            leads_off: false,
+        }
+    }
+
+    /// Records a beat unless it comes within the refractory period of the previous one.
+    fn beat(&mut self) -> bool {
+        if let Some(last) = self.last_beat {
+            let samples = self.count.wrapping_sub(last);
+            if samples < self.refractory {
+                return false;
+            }
+            let interval = (samples as f32 * 1000.0 / self.rate.hertz()) as u32;
+            // Intervals of 30 to 240 beats per minute, longer ones come from missed beats.
+            if (250..=2000).contains(&interval) {
+                self.intervals.copy_within(0..INTERVALS - 1, 1);
+                self.intervals[0] = interval as u16;
+                self.filled = (self.filled + 1).min(INTERVALS);
+            }
+        }
+        self.last_beat = Some(self.count);
+        true
+    }
+
+    /// Forgets the filter states and the beats, as after an electrode was off.
+    fn restart(&mut self) {
+        self.filter.reset();
+        self.band.reset();
+        self.integrator.reset();
+        self.previous = 0;
+        self.peak = 0;
+        self.above = false;
+        self.last_beat = None;
+        self.filled = 0;
+    }
+
+    /// Returns the heart rate averaged over the last beat to beat intervals.
+    /// # Returns
+    /// * `a Option<u16>` - The heart rate in beats per minute, `None` before two beats were detected.
+    pub fn heart_rate(&self) -> Option<u16> {
+        if self.filled == 0 {
+            return None;
+        }
+        let sum: u32 = self.intervals[..self.filled]
+            .iter()
+            .map(|&i| i as u32)
+            .sum();
+        Some((60_000 * self.filled as u32 / sum) as u16)
+    }
+
+    /// Stops sampling and gives back the lead off pins.
+    pub fn release(mut self) -> (P, P) {
+        self.stop();
+        (self.lo_plus, self.lo_minus)
+    }
+}
@@ -18,152 +18,170 @@
 //! sensor which could be used to read the temperature and
 //! humidity and stored in a sliced vector which could be given
 //! as an output.
+//! The driver is generic over the embedded-hal I2C and delay traits,
+//! use `&I2cBus` and `Delay` on the chips of this crate.
 
-use crate::com::i2c;
-use crate::delay::delay_ms;
-use fixed_slice_vec::FixedSliceVec;
+use crate::system::thermostat::TemperatureSensor;
+use crate::{Error, Result};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
 
 /// Used to control the AHT10 Arduino sensor
 /// # Elements
+/// * `i2c` - the I2C bus to which the sensor is attached.
+/// * `delay` - the delay provider used while the sensor is busy.
 /// * `address` - a u8, used to store the address to control the functioning AHT10 sensor.
-/// * `i2c` - a `twi` struct object, which would be used to interface the functioning of sensor with communication protocol I2C.
-/// * `vec` - a vector with u8 objects, It would be used to store the data read through the sensors.
-#[repr(C, packed)]
-pub struct AHT10<'a> {
+/// * `buffer` - an array of u8, It would be used to store the data read through the sensors.
+pub struct AHT10<I2C, D> {
+    i2c: I2C,
+    delay: D,
     address: u8,
-    i2c: i2c::Twi,
-    vec: FixedSliceVec<'a, u8>,
+    buffer: [u8; 6],
 }
 
 // Constant values for AHT10 temperature and humity sensor.
+const AHT10_ADDRESS: u8 = 0x38; //address of the sensor on the bus
 const AHT10_INIT_CMD: u8 = 0xE1; //initialization command for AHT10/AHT15
 const AHT10_START_MEASURMENT_CMD: u8 = 0xAC; //start measurment command
 const AHT10_SOFT_RESET_CMD: u8 = 0xBA; //soft reset command
 const AHT10_INIT_CAL_ENABLE: u8 = 0x08; //load factory calibration coeff
-const AHT10_INIT_BUSY: u8 = 0x08; //Status bit for busy
+const AHT10_INIT_BUSY: u8 = 0x80; //Status bit for busy
 
-impl<'a> AHT10<'a> {
-    /// Creates a new memory mapped IO at the correct location including a 20ms reset delay for wake-up.
-    /// # Returns
-    /// * `a reference to AHT10 structure` - Which would be used to control the sensor.
-    pub fn new(&mut self) -> &'static mut Self {
-        delay_ms(20);
-
-        self.soft_reset();
+// Number of 5ms polls of the busy bit before giving up.
+const AHT10_BUSY_TIMEOUT: u8 = 40;
 
-        unsafe {
-            if !self.initialise() {
-                unreachable!();
-            }
-        }
-        unsafe { &mut *(0x38 as *mut Self) }
+impl<I2C: I2c, D: DelayNs> AHT10<I2C, D> {
+    /// Creates the driver for a sensor on the given bus including a 20ms reset delay for wake-up.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the sensor is attached, for example `&I2cBus`.
+    /// * `delay` - the delay provider, for example `Delay`.
+    /// # Returns
+    /// * `a Result<AHT10>` - The AHT10 object which would be used to control the sensor,
+    ///                       or the error if the sensor did not calibrate.
+    pub fn new(i2c: I2C, delay: D) -> Result<AHT10<I2C, D>> {
+        let mut sensor = AHT10 {
+            i2c,
+            delay,
+            address: AHT10_ADDRESS,
+            buffer: [0; 6],
+        };
+        sensor.delay.delay_ms(20);
+
+        sensor.soft_reset()?;
+        sensor.delay.delay_ms(20);
+        sensor.initialise()?;
+
+        Ok(sensor)
     }
 
-    /// Returns reference to the structure without any reset delay.
+    /// Gives back the bus and the delay provider.
     /// # Returns
-    /// * `a reference to AHT10 structure` - Which would be used to control the sensor.
-    pub fn get() -> &'static mut Self {
-        unsafe { &mut *(0x38 as *mut Self) }
+    /// * `a tuple` - The I2C bus and the delay provider.
+    pub fn release(self) -> (I2C, D) {
+        (self.i2c, self.delay)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.i2c.write(self.address, bytes).map_err(Error::from_i2c)
     }
 
     /// Initiates the transmission by self initiating the sensor.
     /// # Returns
-    /// * `a boolean` - Which is true if transmission initiated otherwise false.
-    pub unsafe fn initialise(&mut self) -> bool {
-        self.vec.clear();
-        self.vec.push(AHT10_INIT_CMD);
-        self.vec.push(0x33);
-        self.vec.push(0x00);
-
-        if !self.i2c.write_to_slave(self.address, &self.vec) {
-            unreachable!();
-        }
-        self.wait_for_idle();
-        if !(self.status() == 0 && AHT10_INIT_CAL_ENABLE == 0) {
-            return false;
+    /// * `a Result` - Which is `NotReady` if the calibration was not loaded.
+    pub fn initialise(&mut self) -> Result<()> {
+        self.write(&[AHT10_INIT_CMD, 0x33, 0x00])?;
+        self.wait_for_idle()?;
+        if self.status()? & AHT10_INIT_CAL_ENABLE == 0 {
+            return Err(Error::NotReady);
         }
-        return true;
+        Ok(())
     }
 
     /// Restart sensor, without power off in around ~20ms with all registers restored to default.
-    pub fn soft_reset(&mut self) {
-        self.vec.clear();
-        self.vec.push(AHT10_SOFT_RESET_CMD);
-
-        if !self.i2c.write_to_slave(self.address, &self.vec) {
-            unreachable!()
-        }
+    /// # Returns
+    /// * `a Result` - Which is an error if the sensor did not answer.
+    pub fn soft_reset(&mut self) -> Result<()> {
+        self.write(&[AHT10_SOFT_RESET_CMD])
     }
 
     /// Reads data from slave mode using the I2C protocol.
-    pub unsafe fn read_to_buffer(&mut self) {
-        if !self
-            .i2c
-            .read_from_slave(self.address, self.vec.len(), &mut self.vec)
-        {
-            unreachable!();
-        }
+    /// # Returns
+    /// * `a Result` - Which is an error if the sensor did not answer.
+    pub fn read_to_buffer(&mut self) -> Result<()> {
+        let mut buffer: [u8; 6] = [0; 6];
+        self.i2c
+            .read(self.address, &mut buffer)
+            .map_err(Error::from_i2c)?;
+        self.buffer = buffer;
+        Ok(())
     }
 
     /// Triggers the AHT10 to read temperature/humidity.
-    pub unsafe fn trigger_slave(&mut self) {
-        self.vec.clear();
-        self.vec.push(AHT10_START_MEASURMENT_CMD);
-        self.vec.push(0x33);
-        self.vec.push(0x00);
-
-        if !self.i2c.write_to_slave(self.address, &self.vec) {
-            unreachable!();
-        }
+    /// # Returns
+    /// * `a Result` - Which is an error if the sensor did not answer.
+    pub fn trigger_slave(&mut self) -> Result<()> {
+        self.write(&[AHT10_START_MEASURMENT_CMD, 0x33, 0x00])
     }
 
-    /// Adds a delay of 5ms when the sensor is already busy with some processing.
-    pub unsafe fn wait_for_idle(&mut self) {
-        while (self.status() == 0 && AHT10_INIT_BUSY == 0) == true {
-            delay_ms(5);
+    /// Adds a delay of 5ms while the sensor is busy with some processing.
+    /// # Returns
+    /// * `a Result` - Which is `Timeout` if the sensor stays busy for 200ms.
+    pub fn wait_for_idle(&mut self) -> Result<()> {
+        let mut i: u8 = 0;
+        while self.status()? & AHT10_INIT_BUSY != 0 {
+            if i >= AHT10_BUSY_TIMEOUT {
+                return Err(Error::Timeout);
+            }
+            self.delay.delay_ms(5);
+            i += 1;
         }
+        Ok(())
     }
 
     /// Performs measurement of temperature using the functions `trigger_slave()` and `read_to_buffer()`.
-    pub unsafe fn perform_measurement(&mut self) {
-        self.trigger_slave();
-        self.wait_for_idle();
-        self.read_to_buffer();
+    /// # Returns
+    /// * `a Result` - Which is an error if the sensor did not answer.
+    pub fn perform_measurement(&mut self) -> Result<()> {
+        self.trigger_slave()?;
+        self.wait_for_idle()?;
+        self.read_to_buffer()
     }
 
     /// Reads value returned by the slave.
     /// # Returns
-    /// * `a u8` - The read value.
-    pub unsafe fn status(&mut self) -> u8 {
-        self.read_to_buffer();
-        return self.vec[0];
+    /// * `a Result<u8>` - The status byte of the sensor.
+    pub fn status(&mut self) -> Result<u8> {
+        self.read_to_buffer()?;
+        Ok(self.buffer[0])
     }
 
     /// Reads 20 bit raw humidity data.
     /// # Returns
-    /// * `a f64` - The relative humidity in percentage.
-    pub fn relative_humidity(&mut self) -> f64 {
-        unsafe {
-            self.perform_measurement();
-            let mut humid: f64 = (((self.vec[1] as u32) << 12)
-                | ((self.vec[2] as u32) << 4)
-                | ((self.vec[3] as u32) >> 4)) as f64;
-            humid = (humid * 100.0) / 0x100000 as f64;
-            return humid;
-        }
+    /// * `a Result<f64>` - The relative humidity in percentage.
+    pub fn relative_humidity(&mut self) -> Result<f64> {
+        self.perform_measurement()?;
+        let mut humid: f64 = (((self.buffer[1] as u32) << 12)
+            | ((self.buffer[2] as u32) << 4)
+            | ((self.buffer[3] as u32) >> 4)) as f64;
+        humid = (humid * 100.0) / 0x100000 as f64;
+        Ok(humid)
     }
 
     /// Reads 20 bit raw temperature data.
     /// # Returns
-    /// * `a f64` - The temperature in degree celsius.
-    pub fn temperature(&mut self) -> f64 {
-        unsafe {
-            self.perform_measurement();
-            let mut temp: f64 = ((((self.vec[3] as u32) & 0xF) << 16)
-                | (self.vec[4] as u32) << 8
-                | (self.vec[5]) as u32) as f64;
-            temp = ((temp as f64 * 200.0) / 0x100000 as f64) - 50.0;
-            return temp;
-        }
+    /// * `a Result<f64>` - The temperature in degree celsius.
+    pub fn temperature(&mut self) -> Result<f64> {
+        self.perform_measurement()?;
+        let mut temp: f64 = ((((self.buffer[3] as u32) & 0xF) << 16)
+            | (self.buffer[4] as u32) << 8
+            | (self.buffer[5]) as u32) as f64;
+        temp = ((temp as f64 * 200.0) / 0x100000 as f64) - 50.0;
+        Ok(temp)
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> TemperatureSensor for AHT10<I2C, D> {
+    fn temperature(&mut self) -> Result<i32> {
+        Ok((AHT10::temperature(self)? * 100.0) as i32)
     }
 }
@@ -0,0 +1,291 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A handle to the I2C bus which is shared by the sensor drivers.
+//! The handle owns the TWI hardware and every sensor keeps a shared
+//! reference to it, so several sensors can be used on the same bus
+//! without creating aliasing mutable references to the registers.
+//! `SpiDevice` does the same for a SPI bus, where every device also
+//! has its own chip select pin.
+
+use crate::com::i2c::Twi;
+use crate::{Error, Result};
+use core::cell::RefCell;
+use core::convert::Infallible;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use embedded_hal::spi::{self, SpiBus};
+
+/// Owner of the TWI hardware which lends it to one transfer at a time.
+/// # Elements
+/// * `twi` - a `RefCell` holding the TWI registers.
+pub struct I2cBus {
+    twi: RefCell<&'static mut Twi>,
+}
+
+impl I2cBus {
+    /// Takes the TWI hardware and initiates the bus.
+    /// # Returns
+    /// * `a Result<I2cBus>` - The bus handle, or `BusBusy` if the TWI is already owned.
+    pub fn take() -> Result<I2cBus> {
+        match Twi::take() {
+            Some(twi) => I2cBus::new(twi),
+            None => Err(Error::BusBusy),
+        }
+    }
+
+    /// Creates the bus handle from the TWI registers and initiates the bus.
+    /// # Arguments
+    /// * `twi` - a static mutable reference to the TWI registers, as given by `Twi::take()`.
+    /// # Returns
+    /// * `a Result<I2cBus>` - The bus handle, or the error of the initialisation.
+    pub fn new(twi: &'static mut Twi) -> Result<I2cBus> {
+        twi.init()?;
+        Ok(I2cBus {
+            twi: RefCell::new(twi),
+        })
+    }
+
+    /// Gives back the TWI registers.
+    /// # Returns
+    /// * `a static mutable reference to Twi` - The registers owned by the bus.
+    pub fn release(self) -> &'static mut Twi {
+        self.twi.into_inner()
+    }
+
+    /// Writes bytes to a device.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the device.
+    /// * `bytes` - a slice of u8, the bytes to be written.
+    /// # Returns
+    /// * `a Result` - Which is an error if the transfer failed.
+    pub fn write(&self, address: u8, bytes: &[u8]) -> Result<()> {
+        self.transfer(|twi| twi.write_to_slave(address, bytes))
+    }
+
+    /// Reads bytes from a device.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the device.
+    /// * `buffer` - a mutable slice of u8, filled with the bytes read.
+    /// # Returns
+    /// * `a Result` - Which is an error if the transfer failed.
+    pub fn read(&self, address: u8, buffer: &mut [u8]) -> Result<()> {
+        self.transfer(|twi| twi.read_from_slave(address, buffer))
+    }
+
+    /// Writes bytes to a device and reads its answer after a repeated start.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the device.
+    /// * `bytes` - a slice of u8, the bytes to be written, for example a register number.
+    /// * `buffer` - a mutable slice of u8, filled with the bytes read.
+    /// # Returns
+    /// * `a Result` - Which is an error if the transfer failed.
+    pub fn write_read(&self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<()> {
+        self.transfer(|twi| twi.write_read(address, bytes, buffer))
+    }
+
+    /// Runs a sequence of reads and writes with a device, see `Twi::transaction`.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the device.
+    /// * `operations` - a mutable slice of `Operation`, the reads and writes to be done.
+    /// # Returns
+    /// * `a Result` - Which is an error if the transfer failed.
+    pub fn transaction(&self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        self.transfer(|twi| twi.transaction(address, operations))
+    }
+
+    /// Same as `transaction`, but awaits the end of every byte.
+    /// The bus stays borrowed until the transfer ends, other transfers get `BusBusy` meanwhile.
+    /// # Arguments
+    /// * `address` - a u8, the seven bit address of the device.
+    /// * `operations` - a mutable slice of `Operation`, the reads and writes to be done.
+    /// # Returns
+    /// * `a Result` - Which is an error if the transfer failed.
+    #[cfg(feature = "async")]
+    pub async fn transaction_async(
+        &self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<()> {
+        match self.twi.try_borrow_mut() {
+            Ok(mut twi) => twi.transaction_async(address, operations).await,
+            Err(_) => Err(Error::BusBusy),
+        }
+    }
+
+    /// Runs one transfer with the registers borrowed.
+    /// A transfer started while another one is running gets `BusBusy`.
+    fn transfer<F: FnOnce(&mut Twi) -> Result<()>>(&self, f: F) -> Result<()> {
+        match self.twi.try_borrow_mut() {
+            Ok(mut twi) => f(&mut twi),
+            Err(_) => Err(Error::BusBusy),
+        }
+    }
+}
+
+// Implementations of the embedded-hal I2C trait. Drivers from other crates
+// can own the bus, or take `&I2cBus` to share it with the sensors of this crate.
+impl ErrorType for I2cBus {
+    type Error = Error;
+}
+
+impl I2c for I2cBus {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        I2cBus::transaction(self, address, operations)
+    }
+}
+
+impl<'a> ErrorType for &'a I2cBus {
+    type Error = Error;
+}
+
+impl<'a> I2c for &'a I2cBus {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        I2cBus::transaction(self, address, operations)
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c for I2cBus {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        self.transaction_async(address, operations).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> embedded_hal_async::i2c::I2c for &'a I2cBus {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+        self.transaction_async(address, operations).await
+    }
+}
+
+/// A device on a SPI bus shared through a `RefCell`, selected by its own pin.
+/// The chip select pin is driven low for the time of a transaction.
+/// # Elements
+/// * `bus` - a reference to the `RefCell` holding the bus, for example `Spi`.
+/// * `cs` - the chip select pin of the device.
+pub struct SpiDevice<'a, BUS, CS> {
+    bus: &'a RefCell<BUS>,
+    cs: CS,
+}
+
+impl<'a, BUS, CS> SpiDevice<'a, BUS, CS>
+where
+    BUS: SpiBus<Error = Error>,
+    CS: OutputPin<Error = Infallible>,
+{
+    /// Creates the device and deselects it.
+    /// # Arguments
+    /// * `bus` - a reference to the `RefCell` holding the bus.
+    /// * `cs` - the chip select pin, which must be an output.
+    /// # Returns
+    /// * `a SpiDevice object` - The device.
+    pub fn new(bus: &'a RefCell<BUS>, mut cs: CS) -> SpiDevice<'a, BUS, CS> {
+        let _ = cs.set_high();
+        SpiDevice { bus, cs }
+    }
+
+    /// Gives back the chip select pin.
+    /// # Returns
+    /// * `the chip select pin`.
+    pub fn release(self) -> CS {
+        self.cs
+    }
+
+    /// Runs the operations with the chip select pin low.
+    /// A transaction started while another one is running gets `BusBusy`.
+    fn run(&mut self, operations: &mut [spi::Operation<'_, u8>]) -> Result<()> {
+        let mut bus = match self.bus.try_borrow_mut() {
+            Ok(bus) => bus,
+            Err(_) => return Err(Error::BusBusy),
+        };
+        let _ = self.cs.set_low();
+        let result = run_operations(&mut *bus, operations).and_then(|_| bus.flush());
+        let _ = self.cs.set_high();
+        result
+    }
+}
+
+/// Runs the operations of a SPI transaction on a bus.
+fn run_operations<BUS: SpiBus<Error = Error>>(
+    bus: &mut BUS,
+    operations: &mut [spi::Operation<'_, u8>],
+) -> Result<()> {
+    for operation in operations.iter_mut() {
+        match operation {
+            spi::Operation::Read(words) => bus.read(words)?,
+            spi::Operation::Write(words) => bus.write(words)?,
+            spi::Operation::Transfer(read, write) => bus.transfer(read, write)?,
+            spi::Operation::TransferInPlace(words) => bus.transfer_in_place(words)?,
+            spi::Operation::DelayNs(ns) => {
+                bus.flush()?;
+                // Rounded up, a longer delay is allowed.
+                crate::delay::delay_us(*ns / 1000 + 1);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<'a, BUS, CS> spi::ErrorType for SpiDevice<'a, BUS, CS> {
+    type Error = Error;
+}
+
+impl<'a, BUS, CS> spi::SpiDevice for SpiDevice<'a, BUS, CS>
+where
+    BUS: SpiBus<Error = Error>,
+    CS: OutputPin<Error = Infallible>,
+{
+    fn transaction(&mut self, operations: &mut [spi::Operation<'_, u8>]) -> Result<()> {
+        self.run(operations)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, BUS, CS> embedded_hal_async::spi::SpiDevice for SpiDevice<'a, BUS, CS>
+where
+    BUS: embedded_hal_async::spi::SpiBus<Error = Error>,
+    CS: OutputPin<Error = Infallible>,
+{
+    async fn transaction(&mut self, operations: &mut [spi::Operation<'_, u8>]) -> Result<()> {
+        let mut bus = match self.bus.try_borrow_mut() {
+            Ok(bus) => bus,
+            Err(_) => return Err(Error::BusBusy),
+        };
+        let _ = self.cs.set_low();
+        let mut result = Ok(());
+        for operation in operations.iter_mut() {
+            result = match operation {
+                spi::Operation::Read(words) => bus.read(words).await,
+                spi::Operation::Write(words) => bus.write(words).await,
+                spi::Operation::Transfer(read, write) => bus.transfer(read, write).await,
+                spi::Operation::TransferInPlace(words) => bus.transfer_in_place(words).await,
+                spi::Operation::DelayNs(ns) => {
+                    crate::delay::delay_us(*ns / 1000 + 1);
+                    Ok(())
+                }
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+        if result.is_ok() {
+            result = bus.flush().await;
+        }
+        let _ = self.cs.set_high();
+        result
+    }
+}
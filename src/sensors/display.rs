@@ -16,6 +16,7 @@
 
 use crate::hal::pin::Pins;
 use crate::hal::shift::*;
+use crate::led::segment::{to_msb_first, SegmentDisplay};
 use core::usize;
 
 /// Setup for the 7-Segment Display.
@@ -135,3 +136,56 @@ pub fn myfn_num_to_bits(somenumber: u8) -> u8 {
         return 0b10010010; // Error condition, displays three vertical bars
     }
 }
+
+/// `N` digits each driven by a 74HC595 shift register, the registers chained.
+/// The segments of the last digit are shifted out first, so the first digit
+/// is the one wired to the register nearest to the board.
+pub struct ShiftRegisterDisplay<const N: usize> {
+    datapin: usize,
+    clockpin: usize,
+    latchpin: usize,
+    common_anode: bool,
+}
+
+impl<const N: usize> ShiftRegisterDisplay<N> {
+    /// Sets the pins as outputs and creates the display.
+    /// # Arguments
+    /// * `datapin` - a usize, the digital pin connected to the data input of the first register.
+    /// * `clockpin` - a usize, the digital pin connected to the shift clock of the registers.
+    /// * `latchpin` - a usize, the digital pin connected to the latch of the registers.
+    /// * `common_anode` - a boolean, true for common anode digits.
+    pub fn new(datapin: usize, clockpin: usize, latchpin: usize, common_anode: bool) -> Self {
+        let pins = Pins::new();
+        for pin in [datapin, clockpin, latchpin] {
+            let mut pin = pins.digital[pin];
+            pin.set_output();
+        }
+        ShiftRegisterDisplay {
+            datapin,
+            clockpin,
+            latchpin,
+            common_anode,
+        }
+    }
+}
+
+impl<const N: usize> SegmentDisplay for ShiftRegisterDisplay<N> {
+    fn digits(&self) -> usize {
+        N
+    }
+
+    fn write_segments(&mut self, segments: &[u8]) -> crate::Result<()> {
+        let pins = Pins::new();
+        let mut latch = pins.digital[self.latchpin];
+        latch.low();
+        for digit in (0..N).rev() {
+            let mut value = to_msb_first(segments.get(digit).copied().unwrap_or(0));
+            if self.common_anode {
+                value ^= 0xFF;
+            }
+            shift_out(self.datapin, self.clockpin, BitOrder::LSBFIRST, value);
+        }
+        latch.high();
+        Ok(())
+    }
+}
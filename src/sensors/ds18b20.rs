@@ -0,0 +1,282 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Driver for the DS18B20 and DS18S20 1-Wire thermometers.
+//! Any number of them can share one `OneWire` bus, each addressed by its ROM code.
+//! Powered from the data line alone, a thermometer needs the strong pull-up
+//! during a conversion, so `parasite` has to be set for such wiring.
+//! Each thermometer has high and low alarm limits, after a conversion
+//! outside of them it answers `OneWire::alarm_search`.
+//! For more information see `<https://datasheets.maximintegrated.com/en/ds/DS18B20.pdf>`
+
+// Source codes required
+use super::onewire::{OneWire, Rom};
+use crate::delay::delay_ms;
+use crate::encoding::crc::crc8_maxim;
+use crate::{Error, Result};
+
+/// Family codes of the supported thermometers.
+pub const DS18S20_FAMILY: u8 = 0x10;
+pub const DS18B20_FAMILY: u8 = 0x28;
+
+/// Function commands of the thermometers.
+const CONVERT_T: u8 = 0x44;
+const WRITE_SCRATCHPAD: u8 = 0x4E;
+const READ_SCRATCHPAD: u8 = 0xBE;
+const COPY_SCRATCHPAD: u8 = 0x48;
+
+/// Resolutions of the DS18B20, a finer one takes longer to convert.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resolution {
+    /// 0.5 degree in 94 ms.
+    Bits9,
+    /// 0.25 degree in 188 ms.
+    Bits10,
+    /// 0.125 degree in 375 ms.
+    Bits11,
+    /// 0.0625 degree in 750 ms.
+    Bits12,
+}
+
+impl Resolution {
+    /// Returns the longest conversion time in milliseconds.
+    pub fn conversion_time(&self) -> u32 {
+        match self {
+            Resolution::Bits9 => 94,
+            Resolution::Bits10 => 188,
+            Resolution::Bits11 => 375,
+            Resolution::Bits12 => 750,
+        }
+    }
+
+    /// Returns the value of the configuration register.
+    fn config(&self) -> u8 {
+        match self {
+            Resolution::Bits9 => 0x1F,
+            Resolution::Bits10 => 0x3F,
+            Resolution::Bits11 => 0x5F,
+            Resolution::Bits12 => 0x7F,
+        }
+    }
+}
+
+/// Starts a conversion on all thermometers of the bus and waits for its end.
+/// # Arguments
+/// * `bus` - a mutable reference to the `OneWire` bus.
+/// * `resolution` - a `Resolution` object, the finest resolution set on the bus.
+/// * `parasite` - a boolean, true if any thermometer is powered from the data line.
+/// # Returns
+/// * `a Result` - `Error::AddressNack` if no device is present.
+pub fn convert_all(bus: &mut OneWire, resolution: Resolution, parasite: bool) -> Result<()> {
+    bus.skip_rom()?;
+    convert(bus, resolution, parasite);
+    Ok(())
+}
+
+/// Sends the conversion command and waits for its end.
+fn convert(bus: &mut OneWire, resolution: Resolution, parasite: bool) {
+    if parasite {
+        bus.write_byte_power(CONVERT_T);
+        delay_ms(resolution.conversion_time());
+        bus.release();
+    } else {
+        bus.write_byte(CONVERT_T);
+        // The thermometer sends ones once the conversion is done.
+        let mut polls = resolution.conversion_time() + 10;
+        while !bus.read_bit() && polls > 0 {
+            delay_ms(1);
+            polls -= 1;
+        }
+    }
+}
+
+/// A DS18B20 or DS18S20 thermometer on a 1-Wire bus.
+/// # Elements
+/// * `rom` - a `Rom` object, the code of the thermometer.
+/// * `resolution` - a `Resolution` object, 12 bits after power up.
+/// * `parasite` - a boolean, true if it is powered from the data line.
+pub struct DS18B20 {
+    rom: Rom,
+    resolution: Resolution,
+    parasite: bool,
+}
+
+impl DS18B20 {
+    /// Creates the driver for a thermometer found by a search.
+    /// # Arguments
+    /// * `rom` - a `Rom` object, the code of the thermometer.
+    /// * `parasite` - a boolean, true if it is powered from the data line, see `OneWire::parasite_powered`.
+    /// # Returns
+    /// * `a Result<DS18B20>` - The driver, or `Error::InvalidArgument` if the code is not a thermometer.
+    pub fn new(rom: Rom, parasite: bool) -> Result<DS18B20> {
+        match rom.family() {
+            DS18S20_FAMILY | DS18B20_FAMILY => Ok(DS18B20 {
+                rom,
+                resolution: Resolution::Bits12,
+                parasite,
+            }),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Returns the code of the thermometer.
+    pub fn rom(&self) -> Rom {
+        self.rom
+    }
+
+    /// Returns the resolution set by `configure`.
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Sets the resolution and alarm limits, and optionally stores them in
+    /// the EEPROM of the thermometer so that they survive a power cycle.
+    /// The DS18S20 has a fixed resolution of 9 bits and ignores it.
+    /// # Arguments
+    /// * `bus` - a mutable reference to the `OneWire` bus.
+    /// * `resolution` - a `Resolution` object, the new resolution.
+    /// * `alarm_low` - an i8, the low alarm limit in degree Celsius.
+    /// * `alarm_high` - an i8, the high alarm limit in degree Celsius.
+    /// * `save` - a boolean, true to copy the settings to the EEPROM.
+    /// # Returns
+    /// * `a Result` - `Error::AddressNack` if the thermometer is not present.
+    pub fn configure(
+        &mut self,
+        bus: &mut OneWire,
+        resolution: Resolution,
+        alarm_low: i8,
+        alarm_high: i8,
+        save: bool,
+    ) -> Result<()> {
+        bus.select(&self.rom)?;
+        bus.write_byte(WRITE_SCRATCHPAD);
+        bus.write_bytes(&[alarm_high as u8, alarm_low as u8]);
+        if self.rom.family() == DS18B20_FAMILY {
+            bus.write_byte(resolution.config());
+            self.resolution = resolution;
+        } else {
+            self.resolution = Resolution::Bits9;
+        }
+
+        if save {
+            bus.select(&self.rom)?;
+            if self.parasite {
+                bus.write_byte_power(COPY_SCRATCHPAD);
+                delay_ms(10);
+                bus.release();
+            } else {
+                bus.write_byte(COPY_SCRATCHPAD);
+                delay_ms(10);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a conversion on this thermometer and waits for its end.
+    /// # Arguments
+    /// * `bus` - a mutable reference to the `OneWire` bus.
+    /// # Returns
+    /// * `a Result` - `Error::AddressNack` if the thermometer is not present.
+    pub fn convert(&mut self, bus: &mut OneWire) -> Result<()> {
+        bus.select(&self.rom)?;
+        convert(bus, self.resolution, self.parasite);
+        Ok(())
+    }
+
+    /// Reads the temperature of the last conversion, started by `convert` or `convert_all`.
+    /// # Arguments
+    /// * `bus` - a mutable reference to the `OneWire` bus.
+    /// # Returns
+    /// * `a Result<f32>` - The temperature in degree Celsius, or `Error::Crc` if the transfer was disturbed.
+    pub fn temperature(&mut self, bus: &mut OneWire) -> Result<f32> {
+        let scratchpad = self.read_scratchpad(bus)?;
+        Ok(celsius(self.rom.family(), &scratchpad))
+    }
+
+    /// Reads the alarm limits stored in the thermometer.
+    /// # Arguments
+    /// * `bus` - a mutable reference to the `OneWire` bus.
+    /// # Returns
+    /// * `a Result<(i8, i8)>` - The low and high limits in degree Celsius.
+    pub fn alarm_limits(&mut self, bus: &mut OneWire) -> Result<(i8, i8)> {
+        let scratchpad = self.read_scratchpad(bus)?;
+        Ok((scratchpad[3] as i8, scratchpad[2] as i8))
+    }
+
+    fn read_scratchpad(&mut self, bus: &mut OneWire) -> Result<[u8; 9]> {
+        bus.select(&self.rom)?;
+        bus.write_byte(READ_SCRATCHPAD);
+        let mut scratchpad = [0; 9];
+        bus.read_bytes(&mut scratchpad);
+        if crc8_maxim(&scratchpad[..8]) != scratchpad[8] {
+            return Err(Error::Crc);
+        }
+        Ok(scratchpad)
+    }
+}
+
+/// Converts the temperature held in a scratchpad.
+/// # Arguments
+/// * `family` - a u8, the family code of the thermometer.
+/// * `scratchpad` - a reference to `[u8; 9]`, the scratchpad read from it.
+/// # Returns
+/// * `a f32` - The temperature in degree Celsius.
+fn celsius(family: u8, scratchpad: &[u8; 9]) -> f32 {
+    let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]);
+    if family == DS18S20_FAMILY {
+        // Half degrees, refined with the count remaining of the conversion.
+        let count_remain = scratchpad[6] as f32;
+        let count_per_c = scratchpad[7] as f32;
+        let whole = (raw >> 1) as f32;
+        whole - 0.25 + (count_per_c - count_remain) / count_per_c
+    } else {
+        raw as f32 / 16.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scratchpad(raw: u16, count_remain: u8) -> [u8; 9] {
+        let [low, high] = raw.to_le_bytes();
+        [low, high, 0x4B, 0x46, 0x7F, 0xFF, count_remain, 0x10, 0]
+    }
+
+    #[test]
+    fn conversion() {
+        // The examples of the DS18B20 data sheet.
+        assert_eq!(celsius(DS18B20_FAMILY, &scratchpad(0x07D0, 0)), 125.0);
+        assert_eq!(celsius(DS18B20_FAMILY, &scratchpad(0x0191, 0)), 25.0625);
+        assert_eq!(celsius(DS18B20_FAMILY, &scratchpad(0x0008, 0)), 0.5);
+        assert_eq!(celsius(DS18B20_FAMILY, &scratchpad(0xFF5E, 0)), -10.125);
+        assert_eq!(celsius(DS18B20_FAMILY, &scratchpad(0xFC90, 0)), -55.0);
+
+        // Half degrees of the DS18S20, refined with COUNT_REMAIN.
+        assert_eq!(celsius(DS18S20_FAMILY, &scratchpad(0x0032, 12)), 25.0);
+        assert_eq!(celsius(DS18S20_FAMILY, &scratchpad(0x0032, 8)), 25.25);
+        assert_eq!(celsius(DS18S20_FAMILY, &scratchpad(0xFFCE, 12)), -25.0);
+    }
+
+    #[test]
+    fn power_on_scratchpad() {
+        // 85 degrees with the alarm limits and resolution of a new DS18B20.
+        let scratchpad = [0x50, 0x05, 0x4B, 0x46, 0x7F, 0xFF, 0x0C, 0x10, 0x1C];
+        assert_eq!(crc8_maxim(&scratchpad[..8]), scratchpad[8]);
+        assert_eq!(celsius(DS18B20_FAMILY, &scratchpad), 85.0);
+        assert_eq!(Resolution::Bits12.config(), scratchpad[4]);
+        assert_eq!(Resolution::Bits9.conversion_time(), 94);
+    }
+}
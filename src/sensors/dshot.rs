@@ -0,0 +1,214 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! DShot is a digital protocol for brushless motor ESCs, replacing the 1 to 2 ms
+//! servo pulses. Each frame has 16 bits: an 11 bit throttle, a bit asking
+//! the ESC for telemetry and a 4 bit checksum. Bits have a fixed period and
+//! are told apart by the length of their high part, 75% for a one and 37.5% for
+//! a zero. The frames are bit banged with cycle counted loops and interrupts
+//! disabled, which takes about 110 us for DShot150 and 55 us for DShot300.
+//! For more information see `<https://brushlesswhoop.com/dshot-and-bidirectional-dshot/>`
+
+// Source codes required
+use crate::config::CPU_FREQUENCY_HZ;
+use crate::hal::interrupts;
+use crate::hal::pin::Pins;
+use crate::hal::port::Pin;
+use core::arch::asm;
+use core::ptr::{read_volatile, write_volatile};
+
+/// Largest throttle value, the throttle field goes from 48 to 2047.
+pub const DSHOT_MAX_THROTTLE: u16 = 1999;
+
+/// Offset of the throttle field, the values below it are commands.
+const THROTTLE_OFFSET: u16 = 48;
+
+/// CPU cycles spent out of the busy loops in each half of a bit.
+const LOOP_OVERHEAD: u32 = 8;
+
+/// Selection of the bit rate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DShotSpeed {
+    /// 150 kbit/s, supported by all DShot ESCs.
+    DShot150,
+    /// 300 kbit/s.
+    DShot300,
+}
+
+/// Special commands understood by BLHeli_32 and BLHeli_S ESCs while the motor is stopped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum DShotCommand {
+    MotorStop = 0,
+    Beep1 = 1,
+    Beep2 = 2,
+    Beep3 = 3,
+    Beep4 = 4,
+    Beep5 = 5,
+    EscInfo = 6,
+    SpinDirection1 = 7,
+    SpinDirection2 = 8,
+    Mode3dOff = 9,
+    Mode3dOn = 10,
+    SaveSettings = 12,
+    SpinDirectionNormal = 20,
+    SpinDirectionReversed = 21,
+}
+
+/// Builds a DShot frame with its checksum.
+/// # Arguments
+/// * `value` - a u16, the 11 bit field, a command below 48 or a throttle up to 2047.
+/// * `telemetry` - a boolean, true to ask the ESC for a telemetry packet.
+/// # Returns
+/// * `a u16` - The frame, sent most significant bit first.
+pub fn dshot_frame(value: u16, telemetry: bool) -> u16 {
+    let packet = ((value & 0x07ff) << 1) | telemetry as u16;
+    let crc = (packet ^ (packet >> 4) ^ (packet >> 8)) & 0x0f;
+    (packet << 4) | crc
+}
+
+/// Structure to control an ESC with the DShot protocol.
+/// # Elements
+/// * `pin` - a `Pin` object, the pin connected to the signal wire of the ESC.
+/// * `one_high` - a u16, the loop count of the high part of a one.
+/// * `one_low` - a u16, the loop count of the low part of a one.
+/// * `zero_high` - a u16, the loop count of the high part of a zero.
+/// * `zero_low` - a u16, the loop count of the low part of a zero.
+pub struct DShot {
+    pin: Pin,
+    one_high: u16,
+    one_low: u16,
+    zero_high: u16,
+    zero_low: u16,
+}
+
+impl DShot {
+    /// Sets up a digital pin as a DShot output, with the signal low.
+    /// # Arguments
+    /// * `pinno` - a usize, the number of the digital pin connected to the ESC.
+    /// * `speed` - a `DShotSpeed` object, the bit rate expected by the ESC.
+    /// # Returns
+    /// * `a DShot object` - Which will be used for further implementations.
+    pub fn new(pinno: usize, speed: DShotSpeed) -> DShot {
+        let mut digital = Pins::new().digital[pinno];
+        digital.set_output();
+        let pin = digital.pin;
+
+        let bit_rate = match speed {
+            DShotSpeed::DShot150 => 150_000,
+            DShotSpeed::DShot300 => 300_000,
+        };
+        let period = CPU_FREQUENCY_HZ / bit_rate;
+        let one = period * 3 / 4;
+        let zero = period * 3 / 8;
+
+        let mut dshot = DShot {
+            pin,
+            one_high: loops(one),
+            one_low: loops(period - one),
+            zero_high: loops(zero),
+            zero_low: loops(period - zero),
+        };
+        dshot.send(0);
+        dshot
+    }
+
+    /// Sends a throttle frame, which has to be repeated at least every few
+    /// milliseconds or the ESC stops the motor.
+    /// # Arguments
+    /// * `throttle` - a u16, from 0 to `DSHOT_MAX_THROTTLE`, larger values are clamped.
+    /// * `telemetry` - a boolean, true to ask the ESC for a telemetry packet.
+    pub fn throttle(&mut self, throttle: u16, telemetry: bool) {
+        let value = throttle.min(DSHOT_MAX_THROTTLE) + THROTTLE_OFFSET;
+        self.send(dshot_frame(value, telemetry));
+    }
+
+    /// Sends a frame stopping the motor, which also arms the ESC when sent for a while after power up.
+    pub fn disarm(&mut self) {
+        self.send(dshot_frame(DShotCommand::MotorStop as u16, false));
+    }
+
+    /// Sends a special command. The ESC only accepts them while the motor
+    /// is stopped, and the settings commands only when received several
+    /// times with the telemetry bit set, so every command is sent ten times.
+    /// # Arguments
+    /// * `command` - a `DShotCommand` object, the command to be sent.
+    pub fn command(&mut self, command: DShotCommand) {
+        let frame = dshot_frame(command as u16, true);
+        for _ in 0..10 {
+            self.send(frame);
+            crate::delay::delay_us(100);
+        }
+    }
+
+    /// Sends a raw frame on the pin.
+    /// # Arguments
+    /// * `frame` - a u16, the frame built by `dshot_frame`.
+    pub fn send(&mut self, frame: u16) {
+        let port = unsafe { &mut (*self.pin.port).port as *mut u8 };
+        let mask = 1u8 << self.pin.pin;
+        interrupts::free(|| unsafe {
+            let low = read_volatile(port) & !mask;
+            let high = low | mask;
+            let mut bits = frame;
+            for _ in 0..16 {
+                write_volatile(port, high);
+                if bits & 0x8000 != 0 {
+                    spin(self.one_high);
+                    write_volatile(port, low);
+                    spin(self.one_low);
+                } else {
+                    spin(self.zero_high);
+                    write_volatile(port, low);
+                    spin(self.zero_low);
+                }
+                bits <<= 1;
+            }
+        });
+    }
+}
+
+/// Converts a number of CPU cycles to a count of the 4 cycle busy loop.
+fn loops(cycles: u32) -> u16 {
+    (cycles.saturating_sub(LOOP_OVERHEAD) / 4).max(1) as u16
+}
+
+/// Busy-waits 4 CPU cycles per count, the count must not be 0.
+#[inline(always)]
+fn spin(count: u16) {
+    unsafe {
+        asm!("1: sbiw {0}, 1", "brne 1b", inout(reg_iw) count => _);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames() {
+        // Motor stop, the lowest throttle and the highest throttle.
+        assert_eq!(dshot_frame(0, false), 0x0000);
+        assert_eq!(dshot_frame(0, true), 0x0011);
+        assert_eq!(dshot_frame(48, false), 0x0606);
+        assert_eq!(dshot_frame(48, true), 0x0617);
+        assert_eq!(dshot_frame(2047, false), 0xFFEE);
+        assert_eq!(dshot_frame(2047, true), 0xFFFF);
+        // The example of the protocol description, 1046 without telemetry.
+        assert_eq!(dshot_frame(1046, false), 0b1000_0010_1100_0110);
+        // Only 11 bits of the value are sent.
+        assert_eq!(dshot_frame(2048, false), dshot_frame(0, false));
+    }
+}
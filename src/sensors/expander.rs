@@ -0,0 +1,607 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Drivers for the MCP23017 and PCF8574 I2C port expanders.
+//! Both implement `Expander`, and `ExpanderPin` gives access to a single pin
+//! of an expander through the same embedded-hal digital traits as the pins
+//! of the chip, so drivers and application code work with either of them.
+//! The expander is kept in a `RefCell` so that all its pins can be used at once.
+//! The interrupt output of an expander can be wired to a pin of the chip,
+//! after which `Expander::take_interrupts` tells which pins changed.
+//! For more information see the following links.
+//! `<https://ww1.microchip.com/downloads/en/devicedoc/20001952c.pdf>`
+//! `<https://www.ti.com/lit/ds/symlink/pcf8574.pdf>`
+
+// Source codes required
+use crate::hal::port::IOMode;
+use crate::{Error, Result};
+use core::cell::RefCell;
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
+use embedded_hal::i2c::I2c;
+
+/// Registers of the MCP23017, port A with port B at the next address.
+const MCP_IODIR: u8 = 0x00;
+const MCP_GPINTEN: u8 = 0x04;
+const MCP_INTCON: u8 = 0x08;
+const MCP_IOCON: u8 = 0x0A;
+const MCP_GPPU: u8 = 0x0C;
+const MCP_INTF: u8 = 0x0E;
+const MCP_INTCAP: u8 = 0x10;
+const MCP_GPIO: u8 = 0x12;
+const MCP_OLAT: u8 = 0x14;
+
+/// IOCON bit joining the interrupt outputs of both ports.
+const MCP_IOCON_MIRROR: u8 = 0x40;
+
+/// Operations shared by the port expanders, on pins numbered from 0.
+pub trait Expander {
+    /// Returns the number of pins of the expander.
+    fn pin_count(&self) -> u8;
+
+    /// Makes a pin an input or an output. The quasi bidirectional pins of the
+    /// PCF8574 are always open drain outputs, the MCP23017 has no open drain mode.
+    /// # Arguments
+    /// * `pin` - a u8, the pin number.
+    /// * `mode` - a `IOMode` object, the new mode of the pin.
+    fn set_mode(&mut self, pin: u8, mode: IOMode) -> Result<()>;
+
+    /// Enables or disables the pull-up resistor of an input.
+    /// # Arguments
+    /// * `pin` - a u8, the pin number.
+    /// * `enable` - a boolean, true to enable the pull-up.
+    fn set_pull_up(&mut self, pin: u8, enable: bool) -> Result<()>;
+
+    /// Sets the level of an output.
+    /// # Arguments
+    /// * `pin` - a u8, the pin number.
+    /// * `high` - a boolean, true for a high level.
+    fn write_pin(&mut self, pin: u8, high: bool) -> Result<()>;
+
+    /// Returns the level last written to an output.
+    /// # Arguments
+    /// * `pin` - a u8, the pin number.
+    fn output_state(&self, pin: u8) -> bool;
+
+    /// Reads the level on a pin.
+    /// # Arguments
+    /// * `pin` - a u8, the pin number.
+    fn read_pin(&mut self, pin: u8) -> Result<bool>;
+
+    /// Enables or disables the interrupt output when an input changes.
+    /// # Arguments
+    /// * `pin` - a u8, the pin number.
+    /// * `enable` - a boolean, true to watch the pin.
+    fn set_interrupt(&mut self, pin: u8, enable: bool) -> Result<()>;
+
+    /// Returns the watched pins which changed since the last call and clears the interrupt.
+    /// # Returns
+    /// * `a Result<u16>` - A bit mask of the changed pins, bit 0 for pin 0.
+    fn take_interrupts(&mut self) -> Result<u16>;
+}
+
+/// Sets or clears the bit of a pin in a mask.
+fn set_bit(mask: u16, pin: u8, value: bool) -> u16 {
+    if value {
+        mask | (1 << pin)
+    } else {
+        mask & !(1 << pin)
+    }
+}
+
+/// A MCP23017 expander with 16 pins, A0 to A7 as pins 0 to 7 and B0 to B7 as pins 8 to 15.
+/// The driver keeps a copy of the configuration so that changing a pin only writes its register.
+/// # Elements
+/// * `i2c` - the I2C bus to which the expander is attached.
+/// * `address` - a u8, the seven bit address of the expander.
+/// * `direction` - a u16, the pins which are inputs.
+/// * `pull_up` - a u16, the inputs with a pull-up.
+/// * `latch` - a u16, the levels of the outputs.
+/// * `interrupts` - a u16, the inputs watched for changes.
+pub struct MCP23017<I2C> {
+    i2c: I2C,
+    address: u8,
+    direction: u16,
+    pull_up: u16,
+    latch: u16,
+    interrupts: u16,
+}
+
+impl<I2C: I2c> MCP23017<I2C> {
+    /// Resets the expander to all pins as inputs without pull-ups,
+    /// with a single interrupt output for both ports.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the expander is attached, for example `&I2cBus`.
+    /// * `pins` - a u8, the levels of the A2, A1 and A0 address pins.
+    /// # Returns
+    /// * `a Result<MCP23017>` - The driver, or the error of the bus if the expander does not answer.
+    pub fn new(i2c: I2C, pins: u8) -> Result<MCP23017<I2C>> {
+        let mut expander = MCP23017 {
+            i2c,
+            address: 0x20 | (pins & 0x07),
+            direction: 0xFFFF,
+            pull_up: 0,
+            latch: 0,
+            interrupts: 0,
+        };
+        expander.write_register(MCP_IOCON, MCP_IOCON_MIRROR)?;
+        expander.write_pair(MCP_IODIR, 0xFFFF)?;
+        expander.write_pair(MCP_GPPU, 0)?;
+        expander.write_pair(MCP_GPINTEN, 0)?;
+        expander.write_pair(MCP_INTCON, 0)?;
+        expander.write_pair(MCP_OLAT, 0)?;
+        Ok(expander)
+    }
+
+    /// Gives back the bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Reads the levels of all pins at once.
+    /// # Returns
+    /// * `a Result<u16>` - The levels, bit 0 for pin 0.
+    pub fn read_port(&mut self) -> Result<u16> {
+        self.read_pair(MCP_GPIO)
+    }
+
+    /// Sets the levels of all outputs at once.
+    /// # Arguments
+    /// * `levels` - a u16, the levels, bit 0 for pin 0.
+    pub fn write_port(&mut self, levels: u16) -> Result<()> {
+        self.write_pair(MCP_OLAT, levels)?;
+        self.latch = levels;
+        Ok(())
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<()> {
+        self.i2c
+            .write(self.address, &[register, value])
+            .map_err(Error::from_i2c)
+    }
+
+    fn write_pair(&mut self, register: u8, value: u16) -> Result<()> {
+        let [a, b] = value.to_le_bytes();
+        self.i2c
+            .write(self.address, &[register, a, b])
+            .map_err(Error::from_i2c)
+    }
+
+    fn read_pair(&mut self, register: u8) -> Result<u16> {
+        let mut value = [0; 2];
+        self.i2c
+            .write_read(self.address, &[register], &mut value)
+            .map_err(Error::from_i2c)?;
+        Ok(u16::from_le_bytes(value))
+    }
+}
+
+impl<I2C: I2c> Expander for MCP23017<I2C> {
+    fn pin_count(&self) -> u8 {
+        16
+    }
+
+    fn set_mode(&mut self, pin: u8, mode: IOMode) -> Result<()> {
+        // Open drain would need the direction changed at every write.
+        if let IOMode::OpenDrain = mode {
+            return Err(Error::InvalidArgument);
+        }
+        let direction = set_bit(self.direction, pin, matches!(mode, IOMode::Input));
+        self.write_pair(MCP_IODIR, direction)?;
+        self.direction = direction;
+        Ok(())
+    }
+
+    fn set_pull_up(&mut self, pin: u8, enable: bool) -> Result<()> {
+        let pull_up = set_bit(self.pull_up, pin, enable);
+        self.write_pair(MCP_GPPU, pull_up)?;
+        self.pull_up = pull_up;
+        Ok(())
+    }
+
+    fn write_pin(&mut self, pin: u8, high: bool) -> Result<()> {
+        self.write_port(set_bit(self.latch, pin, high))
+    }
+
+    fn output_state(&self, pin: u8) -> bool {
+        self.latch & (1 << pin) != 0
+    }
+
+    fn read_pin(&mut self, pin: u8) -> Result<bool> {
+        Ok(self.read_port()? & (1 << pin) != 0)
+    }
+
+    fn set_interrupt(&mut self, pin: u8, enable: bool) -> Result<()> {
+        let interrupts = set_bit(self.interrupts, pin, enable);
+        self.write_pair(MCP_GPINTEN, interrupts)?;
+        self.interrupts = interrupts;
+        Ok(())
+    }
+
+    fn take_interrupts(&mut self) -> Result<u16> {
+        let flags = self.read_pair(MCP_INTF)?;
+        // Reading the captured levels clears the interrupt.
+        self.read_pair(MCP_INTCAP)?;
+        Ok(flags)
+    }
+}
+
+/// A PCF8574 or PCF8574A expander with 8 quasi-bidirectional pins.
+/// An input is an output written high, which is pulled down by the
+/// outside circuit, so every input has a weak pull-up and the chip has
+/// no register besides the port. Changes are found by comparing reads.
+/// # Elements
+/// * `i2c` - the I2C bus to which the expander is attached.
+/// * `address` - a u8, the seven bit address of the expander.
+/// * `inputs` - a u8, the pins which are inputs.
+/// * `latch` - a u8, the levels of the outputs.
+/// * `interrupts` - a u8, the inputs watched for changes.
+/// * `last` - a u8, the levels found by the last read.
+pub struct PCF8574<I2C> {
+    i2c: I2C,
+    address: u8,
+    inputs: u8,
+    latch: u8,
+    interrupts: u8,
+    last: u8,
+}
+
+impl<I2C: I2c> PCF8574<I2C> {
+    /// Sets all pins as inputs.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the expander is attached, for example `&I2cBus`.
+    /// * `pins` - a u8, the levels of the A2, A1 and A0 address pins.
+    /// * `variant_a` - a boolean, true for a PCF8574A, which answers at other addresses.
+    /// # Returns
+    /// * `a Result<PCF8574>` - The driver, or the error of the bus if the expander does not answer.
+    pub fn new(i2c: I2C, pins: u8, variant_a: bool) -> Result<PCF8574<I2C>> {
+        let base = if variant_a { 0x38 } else { 0x20 };
+        let mut expander = PCF8574 {
+            i2c,
+            address: base | (pins & 0x07),
+            inputs: 0xFF,
+            latch: 0,
+            interrupts: 0,
+            last: 0xFF,
+        };
+        expander.flush()?;
+        expander.last = expander.read_port()?;
+        Ok(expander)
+    }
+
+    /// Gives back the bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Reads the levels of all pins at once.
+    /// # Returns
+    /// * `a Result<u8>` - The levels, bit 0 for pin 0.
+    pub fn read_port(&mut self) -> Result<u8> {
+        let mut value = [0];
+        self.i2c
+            .read(self.address, &mut value)
+            .map_err(Error::from_i2c)?;
+        Ok(value[0])
+    }
+
+    /// Sets the levels of all outputs at once, inputs stay high.
+    /// # Arguments
+    /// * `levels` - a u8, the levels, bit 0 for pin 0.
+    pub fn write_port(&mut self, levels: u8) -> Result<()> {
+        self.latch = levels;
+        self.flush()
+    }
+
+    /// Writes the outputs with the inputs released high.
+    fn flush(&mut self) -> Result<()> {
+        self.i2c
+            .write(self.address, &[self.latch | self.inputs])
+            .map_err(Error::from_i2c)
+    }
+}
+
+impl<I2C: I2c> Expander for PCF8574<I2C> {
+    fn pin_count(&self) -> u8 {
+        8
+    }
+
+    fn set_mode(&mut self, pin: u8, mode: IOMode) -> Result<()> {
+        self.inputs = set_bit(self.inputs as u16, pin, matches!(mode, IOMode::Input)) as u8;
+        self.flush()
+    }
+
+    fn set_pull_up(&mut self, _pin: u8, enable: bool) -> Result<()> {
+        // The pull-up is always there on an input.
+        if enable {
+            Ok(())
+        } else {
+            Err(Error::InvalidArgument)
+        }
+    }
+
+    fn write_pin(&mut self, pin: u8, high: bool) -> Result<()> {
+        self.write_port(set_bit(self.latch as u16, pin, high) as u8)
+    }
+
+    fn output_state(&self, pin: u8) -> bool {
+        self.latch & (1 << pin) != 0
+    }
+
+    fn read_pin(&mut self, pin: u8) -> Result<bool> {
+        Ok(self.read_port()? & (1 << pin) != 0)
+    }
+
+    fn set_interrupt(&mut self, pin: u8, enable: bool) -> Result<()> {
+        self.interrupts = set_bit(self.interrupts as u16, pin, enable) as u8;
+        Ok(())
+    }
+
+    fn take_interrupts(&mut self) -> Result<u16> {
+        // Reading the port clears the interrupt.
+        let levels = self.read_port()?;
+        let changed = (levels ^ self.last) & self.interrupts & self.inputs;
+        self.last = levels;
+        Ok(changed as u16)
+    }
+}
+
+/// One pin of a port expander, usable like a pin of the chip.
+/// # Elements
+/// * `expander` - a reference to the `RefCell` holding the expander.
+/// * `pin` - a u8, the pin number on the expander.
+pub struct ExpanderPin<'a, E> {
+    expander: &'a RefCell<E>,
+    pin: u8,
+}
+
+impl<'a, E: Expander> ExpanderPin<'a, E> {
+    /// Creates the handle of a pin.
+    /// # Arguments
+    /// * `expander` - a reference to the `RefCell` holding the expander.
+    /// * `pin` - a u8, the pin number on the expander.
+    /// # Returns
+    /// * `a Option<ExpanderPin>` - The pin, or None if the expander has no such pin.
+    pub fn new(expander: &'a RefCell<E>, pin: u8) -> Option<ExpanderPin<'a, E>> {
+        if pin < expander.borrow().pin_count() {
+            Some(ExpanderPin { expander, pin })
+        } else {
+            None
+        }
+    }
+
+    /// Changes the pin mode to input or output.
+    /// # Arguments
+    /// * `mode` - a `IOMode` object, which defines the mode of the pin to be set.
+    pub fn set_mode(&mut self, mode: IOMode) -> Result<()> {
+        self.with(|expander, pin| expander.set_mode(pin, mode))
+    }
+
+    /// Changes the pin mode to output.
+    pub fn set_output(&mut self) -> Result<()> {
+        self.set_mode(IOMode::Output)
+    }
+
+    /// Changes the pin mode to input.
+    pub fn set_input(&mut self) -> Result<()> {
+        self.set_mode(IOMode::Input)
+    }
+
+    /// Enables or disables the pull-up resistor of the pin.
+    /// # Arguments
+    /// * `enable` - a boolean, true to enable the pull-up.
+    pub fn set_pull_up(&mut self, enable: bool) -> Result<()> {
+        self.with(|expander, pin| expander.set_pull_up(pin, enable))
+    }
+
+    /// Enables or disables the interrupt output of the expander when the pin changes.
+    /// # Arguments
+    /// * `enable` - a boolean, true to watch the pin.
+    pub fn set_interrupt(&mut self, enable: bool) -> Result<()> {
+        self.with(|expander, pin| expander.set_interrupt(pin, enable))
+    }
+
+    /// Runs an operation on the expander, `BusBusy` if it is already borrowed.
+    fn with<R, F: FnOnce(&mut E, u8) -> Result<R>>(&self, f: F) -> Result<R> {
+        match self.expander.try_borrow_mut() {
+            Ok(mut expander) => f(&mut expander, self.pin),
+            Err(_) => Err(Error::BusBusy),
+        }
+    }
+}
+
+// Implementations of the embedded-hal digital traits, the pin must be in the right mode.
+impl<'a, E: Expander> ErrorType for ExpanderPin<'a, E> {
+    type Error = Error;
+}
+
+impl<'a, E: Expander> OutputPin for ExpanderPin<'a, E> {
+    fn set_low(&mut self) -> Result<()> {
+        self.with(|expander, pin| expander.write_pin(pin, false))
+    }
+
+    fn set_high(&mut self) -> Result<()> {
+        self.with(|expander, pin| expander.write_pin(pin, true))
+    }
+}
+
+impl<'a, E: Expander> StatefulOutputPin for ExpanderPin<'a, E> {
+    fn is_set_high(&mut self) -> Result<bool> {
+        self.with(|expander, pin| Ok(expander.output_state(pin)))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool> {
+        self.is_set_high().map(|high| !high)
+    }
+}
+
+impl<'a, E: Expander> InputPin for ExpanderPin<'a, E> {
+    fn is_high(&mut self) -> Result<bool> {
+        self.with(|expander, pin| expander.read_pin(pin))
+    }
+
+    fn is_low(&mut self) -> Result<bool> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    /// The registers of a MCP23017 with sequential addressing, or the port of a PCF8574.
+    /// # Elements
+    /// * `address` - a u8, the address the chip answers at.
+    /// * `registers` - the registers of a MCP23017, only the first byte is used by a PCF8574.
+    /// * `pointer` - a usize, the register accessed next.
+    /// * `pcf` - a boolean, true to act as a PCF8574.
+    /// * `outside` - a u8, the levels the outside circuit forces on the pins of a PCF8574.
+    struct Chip {
+        address: u8,
+        registers: [u8; 0x16],
+        pointer: usize,
+        pcf: bool,
+        outside: u8,
+    }
+
+    impl Chip {
+        fn new(address: u8, pcf: bool) -> Chip {
+            Chip {
+                address,
+                registers: [0; 0x16],
+                pointer: 0,
+                pcf,
+                outside: 0xFF,
+            }
+        }
+
+        fn pair(&self, register: u8) -> u16 {
+            let register = register as usize;
+            u16::from_le_bytes([self.registers[register], self.registers[register + 1]])
+        }
+    }
+
+    impl ErrorType for Chip {
+        type Error = Error;
+    }
+
+    impl I2c for Chip {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+            if address != self.address {
+                return Err(Error::AddressNack);
+            }
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) if self.pcf => self.registers[0] = bytes[0],
+                    Operation::Read(bytes) if self.pcf => {
+                        bytes[0] = self.registers[0] & self.outside
+                    }
+                    Operation::Write(bytes) => {
+                        self.pointer = bytes[0] as usize;
+                        for byte in &bytes[1..] {
+                            self.registers[self.pointer] = *byte;
+                            self.pointer += 1;
+                        }
+                    }
+                    Operation::Read(bytes) => {
+                        for byte in bytes.iter_mut() {
+                            *byte = self.registers[self.pointer];
+                            self.pointer += 1;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mcp23017() {
+        assert_eq!(
+            MCP23017::new(Chip::new(0x21, false), 5).err(),
+            Some(Error::AddressNack)
+        );
+        let mut expander = MCP23017::new(Chip::new(0x25, false), 5).unwrap();
+        expander.i2c.registers[MCP_IODIR as usize] = 0;
+        expander.i2c.registers[MCP_GPPU as usize] = 0xFF;
+        let mut expander = MCP23017::new(expander.release(), 5).unwrap();
+        assert_eq!(expander.i2c.registers[MCP_IOCON as usize], MCP_IOCON_MIRROR);
+        assert_eq!(expander.i2c.pair(MCP_IODIR), 0xFFFF);
+        assert_eq!(expander.i2c.pair(MCP_GPPU), 0);
+
+        expander.set_mode(9, IOMode::Output).unwrap();
+        expander.write_pin(9, true).unwrap();
+        expander.set_pull_up(2, true).unwrap();
+        assert_eq!(expander.i2c.pair(MCP_IODIR), 0xFDFF);
+        assert_eq!(expander.i2c.pair(MCP_OLAT), 0x0200);
+        assert_eq!(expander.i2c.pair(MCP_GPPU), 0x0004);
+        assert!(expander.output_state(9) && !expander.output_state(8));
+        assert_eq!(
+            expander.set_mode(3, IOMode::OpenDrain),
+            Err(Error::InvalidArgument)
+        );
+
+        expander.i2c.registers[MCP_GPIO as usize + 1] = 0x80;
+        assert!(expander.read_pin(15).unwrap());
+        assert!(!expander.read_pin(7).unwrap());
+
+        expander.set_interrupt(4, true).unwrap();
+        assert_eq!(expander.i2c.pair(MCP_GPINTEN), 0x0010);
+        expander.i2c.registers[MCP_INTF as usize] = 0x10;
+        assert_eq!(expander.take_interrupts(), Ok(0x0010));
+    }
+
+    #[test]
+    fn pcf8574() {
+        let mut expander = PCF8574::new(Chip::new(0x3A, true), 2, true).unwrap();
+        assert_eq!(expander.i2c.registers[0], 0xFF);
+
+        // An output written low, the inputs stay released high.
+        expander.set_mode(0, IOMode::Output).unwrap();
+        expander.write_pin(0, false).unwrap();
+        assert_eq!(expander.i2c.registers[0], 0xFE);
+        expander.write_pin(0, true).unwrap();
+        assert_eq!(expander.i2c.registers[0], 0xFF);
+        expander.set_mode(1, IOMode::Output).unwrap();
+        assert_eq!(expander.i2c.registers[0], 0xFD);
+        assert_eq!(expander.set_pull_up(4, false), Err(Error::InvalidArgument));
+
+        // Only watched inputs are reported.
+        expander.set_interrupt(4, true).unwrap();
+        expander.i2c.outside = 0xCF;
+        assert!(!expander.read_pin(4).unwrap());
+        assert_eq!(expander.take_interrupts(), Ok(0x10));
+        assert_eq!(expander.take_interrupts(), Ok(0));
+    }
+
+    #[test]
+    fn pins() {
+        let expander = RefCell::new(PCF8574::new(Chip::new(0x20, true), 0, false).unwrap());
+        assert!(ExpanderPin::new(&expander, 8).is_none());
+        let mut led = ExpanderPin::new(&expander, 3).unwrap();
+        let mut button = ExpanderPin::new(&expander, 6).unwrap();
+        led.set_output().unwrap();
+        led.set_low().unwrap();
+        assert_eq!(led.is_set_low(), Ok(true));
+        expander.borrow_mut().i2c.outside = 0xBF;
+        assert_eq!(button.is_low(), Ok(true));
+
+        // The expander is busy while borrowed elsewhere.
+        let borrowed = expander.borrow();
+        assert_eq!(led.set_high(), Err(Error::BusBusy));
+        drop(borrowed);
+        assert_eq!(led.set_high(), Ok(()));
+    }
+}
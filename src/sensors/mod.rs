@@ -14,12 +14,40 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>
 
+#[cfg(all(feature = "math", feature = "sampler"))]
+mod ad8232;
 mod aht10;
+mod bus;
 mod display;
+mod ds18b20;
+mod dshot;
+mod expander;
 mod mpu6050;
+#[cfg(feature = "millis")]
+mod nixie;
+mod onewire;
+mod rtc;
 mod servo;
+mod ssd1306;
+#[cfg(feature = "millis")]
+mod tof;
+mod touchscreen;
 
+#[cfg(all(feature = "math", feature = "sampler"))]
+pub use ad8232::*;
 pub use aht10::*;
+pub use bus::*;
 pub use display::*;
+pub use ds18b20::*;
+pub use dshot::*;
+pub use expander::*;
 pub use mpu6050::*;
+#[cfg(feature = "millis")]
+pub use nixie::*;
+pub use onewire::*;
+pub use rtc::*;
 pub use servo::*;
+pub use ssd1306::*;
+#[cfg(feature = "millis")]
+pub use tof::*;
+pub use touchscreen::*;
@@ -17,10 +17,13 @@
 //! Source code for implementation of MPU6050 Gyroscopic Sensor
 //! which might be attached or in-built to the current
 //! AVR Micro-controller.
+//! The driver is generic over the embedded-hal I2C and delay traits,
+//! use `&I2cBus` and `Delay` on the chips of this crate.
 
-use crate::{com::i2c, delay::delay_ms};
+use crate::{Error, Result};
 use bit_field::BitField;
-use fixed_slice_vec::FixedSliceVec;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
 
 const MPU6050_ADDRESS: u8 = 0x68; // 0x69 when AD0 pin to Vcc
 const _MPU6050_REG_ACCEL_XOFFS_H: u8 = 0x06; //defining registers for accelerometer X,Y & Z axis for high(H) and low(L).
@@ -219,55 +222,102 @@ pub enum MPUdlpfT {
 
 /// Controls the MPU6050 Gyroscopic Sensor.
 /// # Elements
-/// * `address` - a u8, used to store the address to control the functioning AHT10 sensor.
-/// * `accel_output` - a vector with u8 objects, It would be used to store the two byte accelerometer data read through the sensors.
-/// * `gyro_output` - a vector with u8 objects, It would be used to store the two byte gyroscopic data read through the sensors.
-#[repr(C, packed)]
-pub struct MPU6050<'a> {
+/// * `i2c` - the I2C bus to which the sensor is attached.
+/// * `delay` - the delay provider used while the sensor starts.
+/// * `address` - a u8, used to store the address to control the functioning MPU6050 sensor.
+/// * `accel_output` - an array of f32, It would be used to store the two byte accelerometer data read through the sensors.
+/// * `gyro_output` - an array of f32, It would be used to store the two byte gyroscopic data read through the sensors.
+pub struct MPU6050<I2C, D> {
+    i2c: I2C,
+    delay: D,
     pub address: u8,
-    pub accel_output: FixedSliceVec<'a, f32>,
-    pub gyro_output: FixedSliceVec<'a, f32>,
+    pub accel_output: [f32; 3],
+    pub gyro_output: [f32; 3],
 }
 
-impl<'a> MPU6050<'a> {
-    /// Creates a mutable refernce to the struct to be used in the implementations.
+impl<I2C: I2c, D: DelayNs> MPU6050<I2C, D> {
+    /// Creates the driver for a sensor at the default address on the given bus.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the sensor is attached, for example `&I2cBus`.
+    /// * `delay` - the delay provider, for example `Delay`.
     /// # Returns
     /// * `a MPU6050 object` - To control the sensor through I2C data protocol.
-    pub fn new() -> &'static mut Self {
-        unsafe { &mut *(0x00 as *mut Self) }
+    pub fn new(i2c: I2C, delay: D) -> MPU6050<I2C, D> {
+        MPU6050::with_address(i2c, delay, MPU6050_ADDRESS)
     }
 
-    fn readregister(&mut self, reg: u8) -> u8 {
-        let mut vec1: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
-        vec1.push(reg);
-        let i2c = i2c::Twi::new();
-        i2c.read_from_slave(MPU6050_ADDRESS, 1, &mut vec1);
-        return vec1[1];
+    /// Creates the driver for a sensor at the given address,
+    /// which is 0x69 when the AD0 pin is connected to Vcc.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the sensor is attached.
+    /// * `delay` - the delay provider.
+    /// * `address` - a u8, the address of the sensor on the bus.
+    /// # Returns
+    /// * `a MPU6050 object` - To control the sensor through I2C data protocol.
+    pub fn with_address(i2c: I2C, delay: D, address: u8) -> MPU6050<I2C, D> {
+        MPU6050 {
+            i2c,
+            delay,
+            address,
+            accel_output: [0.0; 3],
+            gyro_output: [0.0; 3],
+        }
+    }
+
+    /// Returns the delay provider, for the waits between readings.
+    pub fn delay(&mut self) -> &mut D {
+        &mut self.delay
+    }
+
+    /// Gives back the bus and the delay provider.
+    /// # Returns
+    /// * `a tuple` - The I2C bus and the delay provider.
+    pub fn release(self) -> (I2C, D) {
+        (self.i2c, self.delay)
     }
 
-    fn writeregister(&mut self, reg: u8, value: u8) {
-        let mut vec2: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
-        vec2.push(reg);
-        vec2.push(value);
-        let i2c = i2c::Twi::new();
-        i2c.write_to_slave(MPU6050_ADDRESS, &vec2);
+    fn readregister(&mut self, reg: u8) -> Result<u8> {
+        let mut value: [u8; 1] = [0];
+        self.i2c
+            .write_read(self.address, &[reg], &mut value)
+            .map_err(Error::from_i2c)?;
+        Ok(value[0])
     }
 
-    fn writeregister_bit(&mut self, reg: u8, pos: u8, state: bool) {
+    fn writeregister(&mut self, reg: u8, value: u8) -> Result<()> {
+        self.i2c
+            .write(self.address, &[reg, value])
+            .map_err(Error::from_i2c)
+    }
+
+    fn writeregister_bit(&mut self, reg: u8, pos: u8, state: bool) -> Result<()> {
         let mut value: u8;
-        value = self.readregister(reg);
+        value = self.readregister(reg)?;
         if state {
             value |= 1 << pos;
         } else {
             value &= !(1 << pos);
         }
-        self.writeregister(reg, value);
+        self.writeregister(reg, value)
+    }
+
+    /// Reads the three, two-byte values of consecutive registers starting at `reg`.
+    fn read_axes(&mut self, reg: u8) -> Result<[f32; 3]> {
+        let mut v: [u8; 6] = [0; 6];
+        self.i2c
+            .write_read(self.address, &[reg], &mut v)
+            .map_err(Error::from_i2c)?; //input from slave
+        Ok([
+            (((v[0] as u16) << 8) | (v[1] as u16)) as i16 as f32, //input of X axis
+            (((v[2] as u16) << 8) | (v[3] as u16)) as i16 as f32, //input of Y axis
+            (((v[4] as u16) << 8) | (v[5] as u16)) as i16 as f32, //input of Z axis
+        ])
     }
 
     /// Set the DLPF mode according to the instruction from user.
-    pub fn set_dlpf_mode(&mut self, dlpf: MPUdlpfT) {
+    pub fn set_dlpf_mode(&mut self, dlpf: MPUdlpfT) -> Result<()> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_CONFIG);
+        value = self.readregister(MPU6050_REG_CONFIG)?;
         value &= 0b11111000;
         value |= match dlpf {
             MPUdlpfT::MPU6050dlpf6 => 0b110,
@@ -278,13 +328,13 @@ impl<'a> MPU6050<'a> {
             MPUdlpfT::MPU6050dlpf1 => 0b001,
             MPUdlpfT::MPU6050dlpf0 => 0b000,
         };
-        self.writeregister(MPU6050_REG_CONFIG, value);
+        self.writeregister(MPU6050_REG_CONFIG, value)
     }
 
     /// Set the DHPF mode according to the instruction from user.
-    pub fn set_dhpf_mode(&mut self, dhpf: MPUdhpfT) {
+    pub fn set_dhpf_mode(&mut self, dhpf: MPUdhpfT) -> Result<()> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_CONFIG);
+        value = self.readregister(MPU6050_REG_CONFIG)?;
         value &= 0b11111100;
         value |= match dhpf {
             MPUdhpfT::MPU6050dhpfReset => 0b000,
@@ -294,13 +344,13 @@ impl<'a> MPU6050<'a> {
             MPUdhpfT::MPU6050dhpf0_63HZ => 0b100,
             MPUdhpfT::MPU6050dhpfHold => 0b101,
         };
-        self.writeregister(MPU6050_REG_CONFIG, value);
+        self.writeregister(MPU6050_REG_CONFIG, value)
     }
 
     /// Set the DPS scale for MPU6050 according to the instruction from user.
-    pub fn set_scale(&mut self, scale: MPUdpsT) {
+    pub fn set_scale(&mut self, scale: MPUdpsT) -> Result<()> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_GYRO_CONFIG);
+        value = self.readregister(MPU6050_REG_GYRO_CONFIG)?;
         value &= 0b11100111;
         value |= (match scale {
             MPUdpsT::MPU6050Scale2000DPS => 3,
@@ -308,30 +358,30 @@ impl<'a> MPU6050<'a> {
             MPUdpsT::MPU6050Scale500DPS => 1,
             MPUdpsT::MPU6050Scale250DPS => 0,
         } << 3);
-        self.writeregister(MPU6050_REG_GYRO_CONFIG, value);
+        self.writeregister(MPU6050_REG_GYRO_CONFIG, value)
     }
 
     /// Get the scale in DPS on which MPU6050 is currently set.
-    pub fn get_scale(&mut self) -> MPUdpsT {
+    pub fn get_scale(&mut self) -> Result<MPUdpsT> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_GYRO_CONFIG);
+        value = self.readregister(MPU6050_REG_GYRO_CONFIG)?;
         value &= 0b00011000;
         value >>= 3;
         if value == 3 {
-            return MPUdpsT::MPU6050Scale2000DPS;
+            return Ok(MPUdpsT::MPU6050Scale2000DPS);
         } else if value == 2 {
-            return MPUdpsT::MPU6050Scale1000DPS;
+            return Ok(MPUdpsT::MPU6050Scale1000DPS);
         } else if value == 1 {
-            return MPUdpsT::MPU6050Scale500DPS;
+            return Ok(MPUdpsT::MPU6050Scale500DPS);
         } else {
-            return MPUdpsT::MPU6050Scale250DPS;
+            return Ok(MPUdpsT::MPU6050Scale250DPS);
         }
     }
 
     /// Set the bandwidth range of MPU6050.
-    pub fn set_range(&mut self, range: MPURangeT) {
+    pub fn set_range(&mut self, range: MPURangeT) -> Result<()> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_ACCEL_CONFIG);
+        value = self.readregister(MPU6050_REG_ACCEL_CONFIG)?;
         value &= 0b11100111;
         value |= (match range {
             MPURangeT::MPU6050Range2G => 0,
@@ -339,30 +389,30 @@ impl<'a> MPU6050<'a> {
             MPURangeT::MPU6050Range8G => 2,
             MPURangeT::MPU6050Range16G => 3,
         } << 3);
-        self.writeregister(MPU6050_REG_ACCEL_CONFIG, value);
+        self.writeregister(MPU6050_REG_ACCEL_CONFIG, value)
     }
 
     /// Get the bandwidth range of MPU6050 currently set.
-    pub fn get_range(&mut self) -> MPURangeT {
+    pub fn get_range(&mut self) -> Result<MPURangeT> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_ACCEL_CONFIG);
+        value = self.readregister(MPU6050_REG_ACCEL_CONFIG)?;
         value &= 0b00011000;
         value >>= 3;
         if value == 3 {
-            return MPURangeT::MPU6050Range16G;
+            return Ok(MPURangeT::MPU6050Range16G);
         } else if value == 2 {
-            return MPURangeT::MPU6050Range8G;
+            return Ok(MPURangeT::MPU6050Range8G);
         } else if value == 1 {
-            return MPURangeT::MPU6050Range4G;
+            return Ok(MPURangeT::MPU6050Range4G);
         } else {
-            return MPURangeT::MPU6050Range2G;
+            return Ok(MPURangeT::MPU6050Range2G);
         }
     }
 
     /// Set the clock source for MPU6050 according to user input.
-    pub fn set_clock_source(&mut self, source: MPUClockSourceT) {
+    pub fn set_clock_source(&mut self, source: MPUClockSourceT) -> Result<()> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_PWR_MGMT_1);
+        value = self.readregister(MPU6050_REG_PWR_MGMT_1)?;
         value &= 0b11111000;
         value |= match source {
             MPUClockSourceT::MPU6050ClockInternal8MHZ => 0,
@@ -373,35 +423,35 @@ impl<'a> MPU6050<'a> {
             MPUClockSourceT::MPU6050ClockExternal19MHZ => 5,
             MPUClockSourceT::MPU6050ClockKeepReset => 7,
         };
-        self.writeregister(MPU6050_REG_PWR_MGMT_1, value);
+        self.writeregister(MPU6050_REG_PWR_MGMT_1, value)
     }
 
     /// Get the clock source for MPU6050 currently set.
-    pub fn get_clock_source(&mut self) -> MPUClockSourceT {
+    pub fn get_clock_source(&mut self) -> Result<MPUClockSourceT> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_PWR_MGMT_1);
+        value = self.readregister(MPU6050_REG_PWR_MGMT_1)?;
         value &= 0b00000111;
         if value == 0 {
-            return MPUClockSourceT::MPU6050ClockInternal8MHZ;
+            return Ok(MPUClockSourceT::MPU6050ClockInternal8MHZ);
         } else if value == 1 {
-            return MPUClockSourceT::MPU6050ClockPllGyrox;
+            return Ok(MPUClockSourceT::MPU6050ClockPllGyrox);
         } else if value == 2 {
-            return MPUClockSourceT::MPU6050ClockPllGyroy;
+            return Ok(MPUClockSourceT::MPU6050ClockPllGyroy);
         } else if value == 3 {
-            return MPUClockSourceT::MPU6050ClockPllGyroz;
+            return Ok(MPUClockSourceT::MPU6050ClockPllGyroz);
         } else if value == 4 {
-            return MPUClockSourceT::MPU6050ClockExternal32MHZ;
+            return Ok(MPUClockSourceT::MPU6050ClockExternal32MHZ);
         } else if value == 5 {
-            return MPUClockSourceT::MPU6050ClockExternal19MHZ;
+            return Ok(MPUClockSourceT::MPU6050ClockExternal19MHZ);
         } else {
-            return MPUClockSourceT::MPU6050ClockKeepReset;
+            return Ok(MPUClockSourceT::MPU6050ClockKeepReset);
         }
     }
 
     /// Set the acceleration power of MPU6050 on appropriate delay given by the user.
-    pub fn set_accel_power_on_delay(&mut self, delay: MPUOnDelayT) {
+    pub fn set_accel_power_on_delay(&mut self, delay: MPUOnDelayT) -> Result<()> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_MOT_DETECT_CTRL);
+        value = self.readregister(MPU6050_REG_MOT_DETECT_CTRL)?;
         value &= 0b11001111;
         value |= match delay {
             MPUOnDelayT::MPU6050Delay3MS => 3,
@@ -409,180 +459,165 @@ impl<'a> MPU6050<'a> {
             MPUOnDelayT::MPU6050Delay1MS => 1,
             MPUOnDelayT::MPU6050NoDelay => 0,
         };
-        self.writeregister(MPU6050_REG_MOT_DETECT_CTRL, value);
+        self.writeregister(MPU6050_REG_MOT_DETECT_CTRL, value)
     }
 
     /// Get the acceleration power of MPU6050 currently set.
-    pub fn get_accel_power_on_delay(&mut self) -> MPUOnDelayT {
+    pub fn get_accel_power_on_delay(&mut self) -> Result<MPUOnDelayT> {
         let mut value: u8;
-        value = self.readregister(MPU6050_REG_MOT_DETECT_CTRL);
+        value = self.readregister(MPU6050_REG_MOT_DETECT_CTRL)?;
         value &= 0b00110000;
         if value == 3 {
-            return MPUOnDelayT::MPU6050Delay3MS;
+            return Ok(MPUOnDelayT::MPU6050Delay3MS);
         } else if value == 2 {
-            return MPUOnDelayT::MPU6050Delay2MS;
+            return Ok(MPUOnDelayT::MPU6050Delay2MS);
         } else if value == 1 {
-            return MPUOnDelayT::MPU6050Delay1MS;
+            return Ok(MPUOnDelayT::MPU6050Delay1MS);
         } else {
-            return MPUOnDelayT::MPU6050NoDelay;
+            return Ok(MPUOnDelayT::MPU6050NoDelay);
         }
     }
 
-    pub fn set_int_free_fall_enabled(&mut self, state: bool) {
-        self.writeregister_bit(MPU6050_REG_INT_ENABLE, 7, state);
+    pub fn set_int_free_fall_enabled(&mut self, state: bool) -> Result<()> {
+        self.writeregister_bit(MPU6050_REG_INT_ENABLE, 7, state)
     }
 
-    pub fn get_int_free_fall_enabled(&mut self) -> bool {
-        let value = self.readregister(MPU6050_REG_INT_ENABLE);
-        return value.get_bit(6);
+    pub fn get_int_free_fall_enabled(&mut self) -> Result<bool> {
+        let value = self.readregister(MPU6050_REG_INT_ENABLE)?;
+        return Ok(value.get_bit(6));
     }
 
-    pub fn set_motion_detection_threshold(&mut self, threshold: u8) {
-        self.writeregister(MPU6050_REG_MOT_THRESHOLD, threshold);
+    pub fn set_motion_detection_threshold(&mut self, threshold: u8) -> Result<()> {
+        self.writeregister(MPU6050_REG_MOT_THRESHOLD, threshold)
     }
 
-    pub fn get_motion_detection_threshold(&mut self) -> u8 {
+    pub fn get_motion_detection_threshold(&mut self) -> Result<u8> {
         return self.readregister(MPU6050_REG_MOT_THRESHOLD);
     }
 
-    pub fn set_motion_detection_duration(&mut self, duration: u8) {
-        self.writeregister(MPU6050_REG_MOT_DURATION, duration);
+    pub fn set_motion_detection_duration(&mut self, duration: u8) -> Result<()> {
+        self.writeregister(MPU6050_REG_MOT_DURATION, duration)
     }
 
-    pub fn get_motion_detection_duration(&mut self) -> u8 {
+    pub fn get_motion_detection_duration(&mut self) -> Result<u8> {
         return self.readregister(MPU6050_REG_MOT_DURATION);
     }
 
-    pub fn set_zero_motion_detection_threshold(&mut self, threshold: u8) {
-        self.writeregister(MPU6050_REG_ZMOT_THRESHOLD, threshold);
+    pub fn set_zero_motion_detection_threshold(&mut self, threshold: u8) -> Result<()> {
+        self.writeregister(MPU6050_REG_ZMOT_THRESHOLD, threshold)
     }
 
-    pub fn get_zero_motion_detection_threshold(&mut self) -> u8 {
+    pub fn get_zero_motion_detection_threshold(&mut self) -> Result<u8> {
         return self.readregister(MPU6050_REG_ZMOT_THRESHOLD);
     }
 
-    pub fn set_zero_motion_detection_duration(&mut self, duration: u8) {
-        self.writeregister(MPU6050_REG_ZMOT_DURATION, duration);
+    pub fn set_zero_motion_detection_duration(&mut self, duration: u8) -> Result<()> {
+        self.writeregister(MPU6050_REG_ZMOT_DURATION, duration)
     }
 
-    pub fn get_zero_motion_detection_duration(&mut self) -> u8 {
+    pub fn get_zero_motion_detection_duration(&mut self) -> Result<u8> {
         return self.readregister(MPU6050_REG_ZMOT_DURATION);
     }
 
-    pub fn set_free_fall_detection_threshold(&mut self, threshold: u8) {
-        self.writeregister(MPU6050_REG_FF_THRESHOLD, threshold);
+    pub fn set_free_fall_detection_threshold(&mut self, threshold: u8) -> Result<()> {
+        self.writeregister(MPU6050_REG_FF_THRESHOLD, threshold)
     }
 
-    pub fn get_free_fall_detection_threshold(&mut self) -> u8 {
+    pub fn get_free_fall_detection_threshold(&mut self) -> Result<u8> {
         return self.readregister(MPU6050_REG_FF_THRESHOLD);
     }
 
-    pub fn set_free_fall_detection_duration(&mut self, duration: u8) {
-        self.writeregister(MPU6050_REG_FF_DURATION, duration);
+    pub fn set_free_fall_detection_duration(&mut self, duration: u8) -> Result<()> {
+        self.writeregister(MPU6050_REG_FF_DURATION, duration)
     }
 
-    pub fn get_free_fall_detection_duration(&mut self) -> u8 {
+    pub fn get_free_fall_detection_duration(&mut self) -> Result<u8> {
         return self.readregister(MPU6050_REG_FF_DURATION);
     }
 
-    pub fn set_sleep_enabled(&mut self, state: bool) {
-        self.writeregister_bit(MPU6050_REG_PWR_MGMT_1, 6, state);
+    pub fn set_sleep_enabled(&mut self, state: bool) -> Result<()> {
+        self.writeregister_bit(MPU6050_REG_PWR_MGMT_1, 6, state)
     }
 
-    pub fn get_sleep_enabled(&mut self) -> bool {
-        let value = self.readregister(MPU6050_REG_PWR_MGMT_1);
-        return value.get_bit(6);
+    pub fn get_sleep_enabled(&mut self) -> Result<bool> {
+        let value = self.readregister(MPU6050_REG_PWR_MGMT_1)?;
+        return Ok(value.get_bit(6));
     }
 
-    pub fn get_int_zero_motion_enabled(&mut self) -> bool {
-        let value = self.readregister(MPU6050_REG_INT_ENABLE);
-        return value.get_bit(5);
+    pub fn get_int_zero_motion_enabled(&mut self) -> Result<bool> {
+        let value = self.readregister(MPU6050_REG_INT_ENABLE)?;
+        return Ok(value.get_bit(5));
     }
 
-    pub fn set_int_zero_motion_enabled(&mut self, state: bool) {
-        self.writeregister_bit(MPU6050_REG_INT_ENABLE, 5, state);
+    pub fn set_int_zero_motion_enabled(&mut self, state: bool) -> Result<()> {
+        self.writeregister_bit(MPU6050_REG_INT_ENABLE, 5, state)
     }
 
-    pub fn get_int_motion_enabled(&mut self) -> bool {
-        let value = self.readregister(MPU6050_REG_INT_ENABLE);
-        return value.get_bit(6);
+    pub fn get_int_motion_enabled(&mut self) -> Result<bool> {
+        let value = self.readregister(MPU6050_REG_INT_ENABLE)?;
+        return Ok(value.get_bit(6));
     }
 
-    pub fn set_int_motion_enabled(&mut self, state: bool) {
-        self.writeregister_bit(MPU6050_REG_INT_ENABLE, 6, state);
+    pub fn set_int_motion_enabled(&mut self, state: bool) -> Result<()> {
+        self.writeregister_bit(MPU6050_REG_INT_ENABLE, 6, state)
     }
 
-    pub fn set_i2c_master_mode_enabled(&mut self, state: bool) {
-        self.writeregister_bit(MPU6050_REG_USER_CTRL, 5, state);
+    pub fn set_i2c_master_mode_enabled(&mut self, state: bool) -> Result<()> {
+        self.writeregister_bit(MPU6050_REG_USER_CTRL, 5, state)
     }
 
-    pub fn get_i2c_master_mode_enabled(&mut self) -> bool {
-        let value = self.readregister(MPU6050_REG_USER_CTRL);
-        return value.get_bit(5);
+    pub fn get_i2c_master_mode_enabled(&mut self) -> Result<bool> {
+        let value = self.readregister(MPU6050_REG_USER_CTRL)?;
+        return Ok(value.get_bit(5));
     }
 
-    pub fn set_i2c_byepass_enabled(&mut self, state: bool) {
-        self.writeregister_bit(MPU6050_REG_INT_PIN_CFG, 1, state);
+    pub fn set_i2c_byepass_enabled(&mut self, state: bool) -> Result<()> {
+        self.writeregister_bit(MPU6050_REG_INT_PIN_CFG, 1, state)
     }
 
-    pub fn get_i2c_byepass_enabled(&mut self) -> bool {
-        let value = self.readregister(MPU6050_REG_INT_PIN_CFG);
-        return value.get_bit(1);
+    pub fn get_i2c_byepass_enabled(&mut self) -> Result<bool> {
+        let value = self.readregister(MPU6050_REG_INT_PIN_CFG)?;
+        return Ok(value.get_bit(1));
     }
 
-    pub fn get_int_status(&mut self) -> u8 {
+    pub fn get_int_status(&mut self) -> Result<u8> {
         return self.readregister(MPU6050_REG_INT_STATUS);
     }
 
     /// Reads the three, two-byte accelerometer values from the sensor.
     /// Returns the two-byte raw accelerometer values as a 32-bit float.
-    /// The vec accel_output stores the raw values of the accelerometer where `accel_output[0]` is the x-axis, `accel_output[1]` is the y-axis and `accel_output[2]` is the z-axis output respectively. These raw values are then converted to g's per second according to the scale given as input in `begin()` function.
-    pub fn read_accel(&mut self) {
-        let mut v: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
-        v.push(MPU6050_REG_ACCEL_XOUT_H);
-        let i2c = i2c::Twi::new();
-        i2c.read_from_slave(MPU6050_ADDRESS, 6, &mut v); //input from slave
-        self.accel_output
-            .push((((v[1] as u16) << 8) | (v[2] as u16)) as f32); //input of X axis
-        self.accel_output
-            .push((((v[3] as u16) << 8) | (v[4] as u16)) as f32); //input of Y axis
-        self.accel_output
-            .push((((v[5] as u16) << 8) | (v[6] as u16)) as f32); //input of Z axis
+    /// The array accel_output stores the raw values of the accelerometer where `accel_output[0]` is the x-axis, `accel_output[1]` is the y-axis and `accel_output[2]` is the z-axis output respectively. These raw values are then converted to g's per second according to the scale given as input in `begin()` function.
+    /// # Returns
+    /// * `a Result` - Which is an error if the sensor could not be read, `accel_output` is unchanged then.
+    pub fn read_accel(&mut self) -> Result<()> {
+        self.accel_output = self.read_axes(MPU6050_REG_ACCEL_XOUT_H)?;
+        Ok(())
     }
 
     /// Reads the three, two-byte gyroscope values from the sensor.
     /// Returns the two-byte raw gyroscope values as a 32-bit float.
-    /// The vec gyro_output stores the raw values of the gyroscope where `gyro_output[0]` is the x-axis, `gyro_output[1]` is the y-axis and `gyro_output[2]` is the z-axis output respectively. These raw values are then converted to degrees per second according to the scale given as input in `begin()` function.
-    pub fn read_gyro(&mut self) {
-        let mut v: FixedSliceVec<u8> = FixedSliceVec::new(&mut []);
-        v.push(MPU6050_REG_GYRO_XOUT_H);
-        let i2c = i2c::Twi::new();
-
-        i2c.read_from_slave(MPU6050_ADDRESS, 6, &mut v); //input from slave
-        self.gyro_output
-            .push((((v[1] as u16) << 8) | (v[2] as u16)) as f32); //input of X axis
-        self.gyro_output
-            .push((((v[3] as u16) << 8) | (v[4] as u16)) as f32); //input of Y axis
-        self.gyro_output
-            .push((((v[5] as u16) << 8) | (v[6] as u16)) as f32); //input of Z axis
+    /// The array gyro_output stores the raw values of the gyroscope where `gyro_output[0]` is the x-axis, `gyro_output[1]` is the y-axis and `gyro_output[2]` is the z-axis output respectively. These raw values are then converted to degrees per second according to the scale given as input in `begin()` function.
+    /// # Returns
+    /// * `a Result` - Which is an error if the sensor could not be read, `gyro_output` is unchanged then.
+    pub fn read_gyro(&mut self) -> Result<()> {
+        self.gyro_output = self.read_axes(MPU6050_REG_GYRO_XOUT_H)?;
+        Ok(())
     }
 
     /// Starts the sensor by setting the device to active mode ,setting the accelerometer range and gyroscope scale.
     /// # Returns
-    /// * `a boolean value` - true if started successfully otherwise false
-    pub fn begin(&mut self, scale: MPUdpsT, range: MPURangeT) -> bool {
-        delay_ms(5);
+    /// * `a Result` - Which is an error if the sensor did not answer.
+    pub fn begin(&mut self, scale: MPUdpsT, range: MPURangeT) -> Result<()> {
+        self.delay.delay_ms(5);
 
         //Set clock source.
-        self.set_clock_source(MPUClockSourceT::MPU6050ClockPllGyrox);
+        self.set_clock_source(MPUClockSourceT::MPU6050ClockPllGyrox)?;
 
         //Set scale and range.
-        self.set_range(range);
-        self.set_scale(scale);
+        self.set_range(range)?;
+        self.set_scale(scale)?;
 
         //disable sleep mode.
-        self.set_sleep_enabled(false);
-
-        return true;
+        self.set_sleep_enabled(false)
     }
 }
@@ -0,0 +1,359 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Multiplexed nixie and VFD tubes for clocks. Every tube has its anode, or
+//! grid for a VFD, switched by a high voltage transistor, and all tubes share
+//! their cathodes, driven by a 74141 BCD decoder for nixies or by segment
+//! drivers for VFDs. Two chained 74HC595 shift registers hold the outputs:
+//! the one nearest to the board gives the BCD code on QA to QD or the
+//! segments, the next one the anode select bits, tube 0 on QA.
+//! The tubes are lit one at a time from the `attach_tick` interrupt of
+//! `hal::millis`, 976 times a second, so that 6 tubes are refreshed 163 times
+//! a second, with all anodes off for a moment between two tubes against ghosting.
+//! A new digit can cross fade in: during the fade the old and the new digit
+//! share the refreshes of the tube, the new one getting more of them over time.
+//! `show_time` shows a `DateTime` read from a RTC, for example with `DS3231::now`.
+//! The module needs the `millis` feature, the tubes being refreshed by its tick.
+
+// Source codes required
+use crate::hal::interrupts;
+use crate::hal::millis::{attach_tick, detach_tick};
+use crate::hal::pin::Pins;
+use crate::hal::shift::{shift_out, BitOrder};
+use crate::led::segment::{encode_hex, DOT};
+use crate::system::clock::DateTime;
+
+/// Maximum number of tubes, one per anode select bit.
+pub const MAX_TUBES: usize = 8;
+
+/// Digit showing nothing.
+pub const BLANK_DIGIT: u8 = 0xFF;
+
+/// BCD code blanking a 74141, any code from 10 to 15.
+const BCD_BLANK: u8 = 0x0F;
+
+/// Number of refreshes of the display per second.
+const TICKS_PER_SECOND: u32 = 976;
+
+/// Kinds of tubes, which differ by how their cathodes are driven.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TubeKind {
+    /// Nixie tubes, with a 74141 decoding a BCD digit.
+    Nixie,
+    /// Seven segment VFD tubes, with the segments in gfedcba order and the dot in bit 7.
+    Vfd,
+}
+
+/// State shared with the refresh interrupt.
+/// # Elements
+/// * `datapin` - a usize, the digital pin connected to the data input of the registers.
+/// * `clockpin` - a usize, the digital pin connected to the shift clock of the registers.
+/// * `latchpin` - a usize, the digital pin connected to the latch of the registers.
+/// * `kind` - a `TubeKind` object, the kind of tubes.
+/// * `tubes` - a u8, the number of tubes, 0 while stopped.
+/// * `digits` - an array of u8, the digit shown by every tube.
+/// * `previous` - an array of u8, the digit every tube fades out of.
+/// * `remaining` - an array of u8, the refreshes left in the fade of every tube.
+/// * `dither` - an array of u16, the accumulated share of the new digit of every tube.
+/// * `fade` - a u8, the number of refreshes a fade lasts, 0 for no fade.
+/// * `dots` - a u8, the tubes of a VFD whose dot is lit.
+/// * `slot` - a u8, the tube refreshed next.
+struct NixieState {
+    datapin: usize,
+    clockpin: usize,
+    latchpin: usize,
+    kind: TubeKind,
+    tubes: u8,
+    digits: [u8; MAX_TUBES],
+    previous: [u8; MAX_TUBES],
+    remaining: [u8; MAX_TUBES],
+    dither: [u16; MAX_TUBES],
+    fade: u8,
+    dots: u8,
+    slot: u8,
+}
+
+static mut NIXIE: NixieState = NixieState {
+    datapin: 0,
+    clockpin: 0,
+    latchpin: 0,
+    kind: TubeKind::Nixie,
+    tubes: 0,
+    digits: [BLANK_DIGIT; MAX_TUBES],
+    previous: [BLANK_DIGIT; MAX_TUBES],
+    remaining: [0; MAX_TUBES],
+    dither: [0; MAX_TUBES],
+    fade: 0,
+    dots: 0,
+    slot: 0,
+};
+
+/// Shifts out the anode select bits and the cathodes and latches them.
+unsafe fn write(anodes: u8, cathodes: u8) {
+    let mut latch = Pins::new().digital[NIXIE.latchpin];
+    latch.low();
+    shift_out(NIXIE.datapin, NIXIE.clockpin, BitOrder::MSBFIRST, anodes);
+    shift_out(NIXIE.datapin, NIXIE.clockpin, BitOrder::MSBFIRST, cathodes);
+    latch.high();
+}
+
+/// Returns the cathode outputs showing a digit.
+fn cathodes(kind: TubeKind, digit: u8, dot: bool) -> u8 {
+    match kind {
+        TubeKind::Nixie if digit < 10 => digit,
+        TubeKind::Nixie => BCD_BLANK,
+        TubeKind::Vfd => {
+            let segments = if digit < 16 { encode_hex(digit) } else { 0 };
+            if dot {
+                segments | DOT
+            } else {
+                segments
+            }
+        }
+    }
+}
+
+/// Decides which digit a fading tube shows on this refresh. The new digit
+/// gets a share of the refreshes growing with the progress of the fade.
+/// # Arguments
+/// * `dither` - a mutable reference to u16, the accumulated share of the new digit.
+/// * `fade` - a u8, the number of refreshes the fade lasts.
+/// * `remaining` - a u8, the refreshes left in the fade, from `fade` down to 1.
+/// # Returns
+/// * `a boolean` - true to show the new digit, false for the previous one.
+fn crossfade(dither: &mut u16, fade: u8, remaining: u8) -> bool {
+    *dither += (fade - remaining) as u16;
+    if *dither >= fade as u16 {
+        *dither -= fade as u16;
+        true
+    } else {
+        false
+    }
+}
+
+/// Returns the digits showing a number right aligned, without leading zeros.
+/// # Arguments
+/// * `value` - a u32, the number, only its lowest digits are kept if it is too long.
+/// * `tubes` - a usize, the number of tubes, at most `MAX_TUBES`.
+fn number_digits(mut value: u32, tubes: usize) -> [u8; MAX_TUBES] {
+    let mut digits = [BLANK_DIGIT; MAX_TUBES];
+    for tube in (0..tubes).rev() {
+        if value != 0 || tube == tubes - 1 {
+            digits[tube] = (value % 10) as u8;
+        }
+        value /= 10;
+    }
+    digits
+}
+
+/// Lights the next tube, called by the tick interrupt.
+fn refresh() {
+    unsafe {
+        if NIXIE.tubes == 0 {
+            return;
+        }
+        let tube = NIXIE.slot as usize;
+        NIXIE.slot = (NIXIE.slot + 1) % NIXIE.tubes;
+        let mut digit = NIXIE.digits[tube];
+        if NIXIE.remaining[tube] > 0 {
+            if !crossfade(&mut NIXIE.dither[tube], NIXIE.fade, NIXIE.remaining[tube]) {
+                digit = NIXIE.previous[tube];
+            }
+            NIXIE.remaining[tube] -= 1;
+        }
+        let blank = cathodes(NIXIE.kind, BLANK_DIGIT, false);
+        write(0, blank);
+        write(
+            1 << tube,
+            cathodes(NIXIE.kind, digit, NIXIE.dots & (1 << tube) != 0),
+        );
+    }
+}
+
+/// A multiplexed nixie or VFD display. There is only one, as it is refreshed
+/// from an interrupt, and all its methods can be called at any time.
+pub struct NixieDisplay {
+    _private: (),
+}
+
+impl NixieDisplay {
+    /// Sets the pins as outputs, blanks the tubes and starts the refresh.
+    /// `millis_init` must have been called.
+    /// # Arguments
+    /// * `datapin` - a usize, the digital pin connected to the data input of the registers.
+    /// * `clockpin` - a usize, the digital pin connected to the shift clock of the registers.
+    /// * `latchpin` - a usize, the digital pin connected to the latch of the registers.
+    /// * `tubes` - a u8, the number of tubes, from 1 to `MAX_TUBES`.
+    /// * `kind` - a `TubeKind` object, the kind of tubes.
+    /// # Returns
+    /// * `a Option<NixieDisplay>` - `None` if the number of tubes is out of range.
+    pub fn begin(
+        datapin: usize,
+        clockpin: usize,
+        latchpin: usize,
+        tubes: u8,
+        kind: TubeKind,
+    ) -> Option<NixieDisplay> {
+        if tubes == 0 || tubes as usize > MAX_TUBES {
+            return None;
+        }
+        let pins = Pins::new();
+        for pin in [datapin, clockpin, latchpin] {
+            let mut pin = pins.digital[pin];
+            pin.set_output();
+        }
+        interrupts::free(|| unsafe {
+            NIXIE = NixieState {
+                datapin,
+                clockpin,
+                latchpin,
+                kind,
+                tubes,
+                digits: [BLANK_DIGIT; MAX_TUBES],
+                previous: [BLANK_DIGIT; MAX_TUBES],
+                remaining: [0; MAX_TUBES],
+                dither: [0; MAX_TUBES],
+                fade: NIXIE.fade,
+                dots: 0,
+                slot: 0,
+            };
+        });
+        attach_tick(refresh);
+        Some(NixieDisplay { _private: () })
+    }
+
+    /// Stops the refresh and switches all the anodes off.
+    pub fn stop(self) {
+        detach_tick();
+        interrupts::free(|| unsafe {
+            NIXIE.tubes = 0;
+            write(0, cathodes(NIXIE.kind, BLANK_DIGIT, false));
+        });
+    }
+
+    /// Returns the number of tubes.
+    pub fn tubes(&self) -> usize {
+        unsafe { NIXIE.tubes as usize }
+    }
+
+    /// Sets the length of the cross fades.
+    /// # Arguments
+    /// * `millis` - a u16, the length of a fade in milliseconds, 0 to change digits at once.
+    pub fn set_fade(&mut self, millis: u16) {
+        interrupts::free(|| unsafe {
+            let refreshes = millis as u32 * TICKS_PER_SECOND / 1000 / NIXIE.tubes as u32;
+            NIXIE.fade = refreshes.min(255) as u8;
+        });
+    }
+
+    /// Sets the digit of a tube, fading from the digit shown.
+    /// # Arguments
+    /// * `tube` - a usize, the tube, 0 for the one on QA.
+    /// * `digit` - a u8, the digit, 0 to 9 for nixies, 0 to 15 for VFDs, `BLANK_DIGIT` for nothing.
+    pub fn set_digit(&mut self, tube: usize, digit: u8) {
+        interrupts::free(|| unsafe {
+            if tube >= NIXIE.tubes as usize || NIXIE.digits[tube] == digit {
+                return;
+            }
+            NIXIE.previous[tube] = NIXIE.digits[tube];
+            NIXIE.digits[tube] = digit;
+            NIXIE.remaining[tube] = NIXIE.fade;
+            NIXIE.dither[tube] = 0;
+        });
+    }
+
+    /// Returns the digit of a tube, `BLANK_DIGIT` for a blank tube or one which does not exist.
+    pub fn digit(&self, tube: usize) -> u8 {
+        interrupts::free(|| unsafe {
+            if tube < NIXIE.tubes as usize {
+                NIXIE.digits[tube]
+            } else {
+                BLANK_DIGIT
+            }
+        })
+    }
+
+    /// Sets the digits of the first tubes.
+    /// # Arguments
+    /// * `digits` - a slice of u8, the digits from tube 0 on, the extra ones are ignored.
+    pub fn set_digits(&mut self, digits: &[u8]) {
+        for (tube, &digit) in digits.iter().enumerate() {
+            self.set_digit(tube, digit);
+        }
+    }
+
+    /// Lights the dots of a VFD.
+    /// # Arguments
+    /// * `dots` - a u8, bit n set to light the dot of tube n.
+    pub fn set_dots(&mut self, dots: u8) {
+        interrupts::free(|| unsafe { NIXIE.dots = dots });
+    }
+
+    /// Shows a number right aligned, without leading zeros.
+    /// # Arguments
+    /// * `value` - a u32, the number, only its lowest digits are shown if it is too long.
+    pub fn show_number(&mut self, value: u32) {
+        let tubes = self.tubes();
+        self.set_digits(&number_digits(value, tubes)[..tubes]);
+    }
+
+    /// Shows the time as HHMM on four tubes or HHMMSS on six or more, from tube 0 on.
+    /// # Arguments
+    /// * `time` - a reference to the `DateTime`.
+    pub fn show_time(&mut self, time: &DateTime) {
+        let fields = [time.hour(), time.minute(), time.second()];
+        let count = if self.tubes() >= 6 { 3 } else { 2 };
+        for (i, field) in fields.iter().take(count).enumerate() {
+            self.set_digit(2 * i, field / 10);
+            self.set_digit(2 * i + 1, field % 10);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn digit_encoding() {
+        assert_eq!(cathodes(TubeKind::Nixie, 7, false), 7);
+        assert_eq!(cathodes(TubeKind::Nixie, 7, true), 7);
+        assert_eq!(cathodes(TubeKind::Nixie, 10, false), BCD_BLANK);
+        assert_eq!(cathodes(TubeKind::Nixie, BLANK_DIGIT, false), BCD_BLANK);
+        assert_eq!(cathodes(TubeKind::Vfd, 1, false), encode_hex(1));
+        assert_eq!(cathodes(TubeKind::Vfd, 0xF, true), encode_hex(0xF) | DOT);
+        assert_eq!(cathodes(TubeKind::Vfd, BLANK_DIGIT, false), 0);
+        assert_eq!(cathodes(TubeKind::Vfd, BLANK_DIGIT, true), DOT);
+    }
+
+    #[test]
+    fn numbers() {
+        let b = BLANK_DIGIT;
+        assert_eq!(number_digits(42, 4)[..4], [b, b, 4, 2]);
+        assert_eq!(number_digits(0, 4)[..4], [b, b, b, 0]);
+        assert_eq!(number_digits(1205, 4)[..4], [1, 2, 0, 5]);
+        assert_eq!(number_digits(123456, 4)[..4], [3, 4, 5, 6]);
+        assert_eq!(number_digits(7, 1)[..1], [7]);
+    }
+
+    #[test]
+    fn crossfade_share() {
+        // Over a fade of 8 refreshes the new digit is shown more and more often.
+        let mut dither = 0;
+        let shown: [bool; 8] = core::array::from_fn(|i| crossfade(&mut dither, 8, 8 - i as u8));
+        assert_eq!(shown, [false, false, false, false, true, false, true, true]);
+    }
+}
@@ -0,0 +1,388 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Bit banged 1-Wire bus master on any digital pin, with an external pull-up
+//! resistor of about 4.7 kOhm. The pin is driven low or released to the
+//! pull-up, except after `write_byte_power` where it is driven high to give
+//! parasite powered devices the current they need while converting.
+//! Every device has a 64 bit ROM code, which `search` finds one after the other,
+//! so that many devices can share the bus and be addressed with `select`.
+//! For more information see `<https://www.analog.com/en/technical-articles/1wire-search-algorithm.html>`
+
+// Source codes required
+use crate::delay::delay_us;
+use crate::encoding::crc::crc8_maxim;
+use crate::hal::interrupts;
+use crate::hal::pin::Pins;
+use crate::hal::port::Pin;
+use crate::{Error, Result};
+use embedded_hal::digital::{InputPin, OutputPin};
+
+/// ROM commands understood by all devices.
+const SEARCH_ROM: u8 = 0xF0;
+const READ_ROM: u8 = 0x33;
+const MATCH_ROM: u8 = 0x55;
+const SKIP_ROM: u8 = 0xCC;
+const ALARM_SEARCH: u8 = 0xEC;
+const READ_POWER_SUPPLY: u8 = 0xB4;
+
+/// The 64 bit ROM code of a device: family code, serial number and CRC.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Rom(pub [u8; 8]);
+
+impl Rom {
+    /// Returns the family code, which tells the type of the device.
+    pub fn family(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// Checks the CRC in the last byte of the code.
+    pub fn is_valid(&self) -> bool {
+        crc8_maxim(&self.0[..7]) == self.0[7]
+    }
+}
+
+/// Progress of a search through the ROM codes on the bus.
+/// # Elements
+/// * `rom` - a `Rom` object, the code found last.
+/// * `last_discrepancy` - a u8, the bit where the last search took the 0 branch.
+/// * `done` - a boolean, true once the last device was found.
+#[derive(Clone, Copy, Default)]
+pub struct SearchState {
+    rom: Rom,
+    last_discrepancy: u8,
+    done: bool,
+}
+
+impl SearchState {
+    /// Creates a search starting from the first device.
+    pub fn new() -> SearchState {
+        SearchState::default()
+    }
+}
+
+/// Structure to control a 1-Wire bus.
+/// # Elements
+/// * `pin` - a `Pin` object, the pin to which the data line is connected.
+pub struct OneWire {
+    pin: Pin,
+}
+
+impl OneWire {
+    /// Creates the bus on a digital pin and releases the line.
+    /// # Arguments
+    /// * `pinno` - a usize, the number of the digital pin connected to the data line.
+    /// # Returns
+    /// * `a OneWire object` - Which will be used for further implementations.
+    pub fn new(pinno: usize) -> OneWire {
+        let mut bus = OneWire {
+            pin: Pins::new().digital[pinno].pin,
+        };
+        bus.release();
+        bus
+    }
+
+    /// Sends a reset pulse and listens for the presence pulse of the devices.
+    /// # Returns
+    /// * `a Result<bool>` - True if at least one device answered, or `Error::BusError` if the line is held low.
+    pub fn reset(&mut self) -> Result<bool> {
+        self.release();
+        // The line must be high before the reset, it rises slowly on long cables.
+        let mut retries = 125;
+        while self.pin.is_low().unwrap_or(true) {
+            if retries == 0 {
+                return Err(Error::BusError);
+            }
+            retries -= 1;
+            delay_us(2);
+        }
+        self.drive_low();
+        delay_us(480);
+        let present = interrupts::free(|| {
+            self.release();
+            delay_us(70);
+            self.pin.is_low().unwrap_or(false)
+        });
+        delay_us(410);
+        Ok(present)
+    }
+
+    /// Writes one bit, with a short low pulse for a 1 and a long one for a 0.
+    /// # Arguments
+    /// * `bit` - a boolean, the bit to be written.
+    pub fn write_bit(&mut self, bit: bool) {
+        interrupts::free(|| {
+            self.drive_low();
+            if bit {
+                delay_us(6);
+                self.release();
+                delay_us(64);
+            } else {
+                delay_us(60);
+                self.release();
+                delay_us(10);
+            }
+        });
+    }
+
+    /// Reads one bit, sampled shortly after starting the slot.
+    /// # Returns
+    /// * `a boolean` - The bit sent by the device.
+    pub fn read_bit(&mut self) -> bool {
+        let bit = interrupts::free(|| {
+            self.drive_low();
+            delay_us(6);
+            self.release();
+            delay_us(9);
+            self.pin.is_high().unwrap_or(true)
+        });
+        delay_us(55);
+        bit
+    }
+
+    /// Writes one byte, least significant bit first.
+    /// # Arguments
+    /// * `byte` - a u8, the byte to be written.
+    pub fn write_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.write_bit(byte & (1 << i) != 0);
+        }
+    }
+
+    /// Writes one byte and then drives the line high to power parasite devices,
+    /// until `release` or the next reset. Used for commands such as a temperature
+    /// conversion or a copy to EEPROM, during which the devices draw more current
+    /// than the pull-up resistor can give.
+    /// # Arguments
+    /// * `byte` - a u8, the byte to be written.
+    pub fn write_byte_power(&mut self, byte: u8) {
+        self.write_byte(byte);
+        self.strong_pullup();
+    }
+
+    /// Reads one byte, least significant bit first.
+    /// # Returns
+    /// * `a u8` - The byte sent by the device.
+    pub fn read_byte(&mut self) -> u8 {
+        let mut byte = 0;
+        for i in 0..8 {
+            if self.read_bit() {
+                byte |= 1 << i;
+            }
+        }
+        byte
+    }
+
+    /// Writes bytes one after the other.
+    /// # Arguments
+    /// * `bytes` - a slice of u8, the bytes to be written.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    /// Reads bytes one after the other.
+    /// # Arguments
+    /// * `buffer` - a mutable slice of u8, filled with the bytes read.
+    pub fn read_bytes(&mut self, buffer: &mut [u8]) {
+        for byte in buffer.iter_mut() {
+            *byte = self.read_byte();
+        }
+    }
+
+    /// Resets the bus and addresses one device, the next command is only executed by it.
+    /// # Arguments
+    /// * `rom` - a `Rom` object, the code of the device.
+    /// # Returns
+    /// * `a Result` - `Error::AddressNack` if no device is present.
+    pub fn select(&mut self, rom: &Rom) -> Result<()> {
+        self.presence()?;
+        self.write_byte(MATCH_ROM);
+        self.write_bytes(&rom.0);
+        Ok(())
+    }
+
+    /// Resets the bus and addresses all devices at once, for example to start
+    /// all temperature conversions together.
+    /// # Returns
+    /// * `a Result` - `Error::AddressNack` if no device is present.
+    pub fn skip_rom(&mut self) -> Result<()> {
+        self.presence()?;
+        self.write_byte(SKIP_ROM);
+        Ok(())
+    }
+
+    /// Reads the code of the only device on the bus.
+    /// # Returns
+    /// * `a Result<Rom>` - The code, or `Error::Crc` if several devices answered at once.
+    pub fn read_rom(&mut self) -> Result<Rom> {
+        self.presence()?;
+        self.write_byte(READ_ROM);
+        let mut rom = Rom::default();
+        self.read_bytes(&mut rom.0);
+        if rom.is_valid() {
+            Ok(rom)
+        } else {
+            Err(Error::Crc)
+        }
+    }
+
+    /// Checks if any addressed device is powered by the data line.
+    /// # Arguments
+    /// * `rom` - a `Option<Rom>`, the device to be checked, or None for all devices.
+    /// # Returns
+    /// * `a Result<bool>` - True if a parasite powered device answered.
+    pub fn parasite_powered(&mut self, rom: Option<&Rom>) -> Result<bool> {
+        match rom {
+            Some(rom) => self.select(rom)?,
+            None => self.skip_rom()?,
+        }
+        self.write_byte(READ_POWER_SUPPLY);
+        Ok(!self.read_bit())
+    }
+
+    /// Finds the next device on the bus.
+    /// # Arguments
+    /// * `state` - a mutable reference to a `SearchState` object, the progress of the search.
+    /// # Returns
+    /// * `a Result<Option<Rom>>` - The code of the next device, None once all were found.
+    pub fn search(&mut self, state: &mut SearchState) -> Result<Option<Rom>> {
+        self.search_command(state, SEARCH_ROM)
+    }
+
+    /// Finds the next device whose alarm flag is set, for example a
+    /// thermometer which measured a temperature outside of its limits.
+    /// # Arguments
+    /// * `state` - a mutable reference to a `SearchState` object, the progress of the search.
+    /// # Returns
+    /// * `a Result<Option<Rom>>` - The code of the next device, None once all were found.
+    pub fn alarm_search(&mut self, state: &mut SearchState) -> Result<Option<Rom>> {
+        self.search_command(state, ALARM_SEARCH)
+    }
+
+    /// Finds all devices on the bus.
+    /// # Arguments
+    /// * `roms` - a mutable slice of `Rom`, filled with the codes found.
+    /// # Returns
+    /// * `a Result<usize>` - The number of devices found, at most the length of `roms`.
+    pub fn search_all(&mut self, roms: &mut [Rom]) -> Result<usize> {
+        let mut state = SearchState::new();
+        let mut count = 0;
+        while count < roms.len() {
+            match self.search(&mut state)? {
+                Some(rom) => {
+                    roms[count] = rom;
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+        Ok(count)
+    }
+
+    /// Releases the line to the pull-up resistor, which also ends the strong pull-up.
+    pub fn release(&mut self) {
+        self.pin.set_input();
+        self.pin.set_low().ok();
+    }
+
+    /// Drives the line high.
+    fn strong_pullup(&mut self) {
+        self.pin.set_high().ok();
+        self.pin.set_output();
+    }
+
+    /// Drives the line low.
+    fn drive_low(&mut self) {
+        self.pin.set_low().ok();
+        self.pin.set_output();
+    }
+
+    /// Resets the bus and fails if no device answered.
+    fn presence(&mut self) -> Result<()> {
+        if self.reset()? {
+            Ok(())
+        } else {
+            Err(Error::AddressNack)
+        }
+    }
+
+    /// One pass of the search algorithm: the devices send each bit of their
+    /// code and its complement, a 0 and 1 read together means that the codes
+    /// differ there, and the branch written back keeps only part of the devices.
+    fn search_command(&mut self, state: &mut SearchState, command: u8) -> Result<Option<Rom>> {
+        if state.done || !self.reset()? {
+            return Ok(None);
+        }
+        self.write_byte(command);
+
+        let mut last_zero = 0;
+        for bit in 1..=64u8 {
+            let byte = ((bit - 1) / 8) as usize;
+            let mask = 1 << ((bit - 1) % 8);
+            let id = self.read_bit();
+            let complement = self.read_bit();
+            let direction = match (id, complement) {
+                (true, true) => return Ok(None),
+                (true, false) => true,
+                (false, true) => false,
+                (false, false) => {
+                    let direction = if bit < state.last_discrepancy {
+                        state.rom.0[byte] & mask != 0
+                    } else {
+                        bit == state.last_discrepancy
+                    };
+                    if !direction {
+                        last_zero = bit;
+                    }
+                    direction
+                }
+            };
+            if direction {
+                state.rom.0[byte] |= mask;
+            } else {
+                state.rom.0[byte] &= !mask;
+            }
+            self.write_bit(direction);
+        }
+
+        state.last_discrepancy = last_zero;
+        state.done = last_zero == 0;
+        if state.rom.is_valid() {
+            Ok(Some(state.rom))
+        } else {
+            Err(Error::Crc)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rom_codes() {
+        // The example of the Maxim application note 27.
+        let rom = Rom([0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xA2]);
+        assert!(rom.is_valid());
+        assert_eq!(rom.family(), 0x02);
+
+        let mut broken = rom;
+        broken.0[3] ^= 0x10;
+        assert!(!broken.is_valid());
+        assert!(Rom::default().is_valid());
+    }
+}
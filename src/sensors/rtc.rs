@@ -0,0 +1,260 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Maxim DS3231 and DS1307 battery backed real-time clocks over I2C.
+//! Both keep the time in the same BCD registers and implement `Clock`.
+//! The DS3231 has a temperature compensated crystal, drifting by about a
+//! minute a year, and a temperature sensor.
+//! The drivers are generic over the embedded-hal I2C trait, use `&I2cBus` on the chips of this crate.
+
+use crate::system::clock::{AlarmClock, Clock, DateTime};
+use crate::{Error, Result};
+use embedded_hal::i2c::I2c;
+
+/// Address of both clocks on the bus.
+const RTC_ADDRESS: u8 = 0x68;
+
+// Registers of the clocks.
+const RTC_SECONDS: u8 = 0x00; //first of the seven time registers
+const DS1307_CLOCK_HALT: u8 = 0x80; //bit of the seconds register stopping the DS1307
+const DS3231_ALARM1: u8 = 0x07; //first of the four alarm 1 registers
+const DS3231_CONTROL: u8 = 0x0E; //control register
+const DS3231_STATUS: u8 = 0x0F; //status register
+const DS3231_OSCILLATOR_STOPPED: u8 = 0x80; //status bit set when the time was lost
+const DS3231_ALARM1_FLAG: u8 = 0x01; //status bit set when alarm 1 fired
+const DS3231_ALARM1_ENABLE: u8 = 0x01; //control bit driving INT/SQW low on alarm 1
+const DS3231_INTERRUPT_CONTROL: u8 = 0x04; //control bit routing the alarms to INT/SQW
+const DS3231_TEMPERATURE: u8 = 0x11; //temperature, integer part then fraction
+
+/// Converts a BCD byte.
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Converts a value below 100 to BCD.
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Reads the seven time registers and decodes them.
+/// # Returns
+/// * `a Result<(DateTime, u8)>` - The time and the raw seconds register, `Corrupted` if the registers do not hold a valid time.
+fn read_time<I2C: I2c>(i2c: &mut I2C) -> Result<(DateTime, u8)> {
+    let mut r = [0; 7];
+    i2c.write_read(RTC_ADDRESS, &[RTC_SECONDS], &mut r)
+        .map_err(Error::from_i2c)?;
+    let hour = if r[2] & 0x40 != 0 {
+        // 12 hour mode, bit 5 is set after noon.
+        from_bcd(r[2] & 0x1F) % 12 + if r[2] & 0x20 != 0 { 12 } else { 0 }
+    } else {
+        from_bcd(r[2] & 0x3F)
+    };
+    // The DS3231 sets bit 7 of the month for the next century.
+    let year = 2000 + from_bcd(r[6]) as u16 + if r[5] & 0x80 != 0 { 100 } else { 0 };
+    let time = DateTime::new(
+        year,
+        from_bcd(r[5] & 0x1F),
+        from_bcd(r[4] & 0x3F),
+        hour,
+        from_bcd(r[1] & 0x7F),
+        from_bcd(r[0] & 0x7F),
+    )
+    .ok_or(Error::Corrupted)?;
+    Ok((time, r[0]))
+}
+
+/// Writes the seven time registers in 24 hour mode, which also starts a halted DS1307.
+/// # Returns
+/// * `a Result<()>` - `InvalidArgument` for years which cannot be stored.
+fn write_time<I2C: I2c>(i2c: &mut I2C, time: &DateTime) -> Result<()> {
+    if !(2000..=2199).contains(&time.year()) {
+        return Err(Error::InvalidArgument);
+    }
+    let century = if time.year() >= 2100 { 0x80 } else { 0 };
+    let data = [
+        RTC_SECONDS,
+        to_bcd(time.second()),
+        to_bcd(time.minute()),
+        to_bcd(time.hour()),
+        time.weekday(),
+        to_bcd(time.day()),
+        to_bcd(time.month()) | century,
+        to_bcd((time.year() % 100) as u8),
+    ];
+    i2c.write(RTC_ADDRESS, &data).map_err(Error::from_i2c)
+}
+
+/// A DS3231 real-time clock.
+/// # Elements
+/// * `i2c` - the I2C bus to which the clock is attached.
+pub struct DS3231<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: I2c> DS3231<I2C> {
+    /// Creates the driver.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the clock is attached, for example `&I2cBus`.
+    /// # Returns
+    /// * `a DS3231 object` - The driver.
+    pub fn new(i2c: I2C) -> DS3231<I2C> {
+        DS3231 { i2c }
+    }
+
+    /// Gives back the bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Returns true if the oscillator stopped since the time was last set,
+    /// for example because the battery ran flat, so the time is wrong.
+    pub fn lost_power(&mut self) -> Result<bool> {
+        Ok(self.read_register(DS3231_STATUS)? & DS3231_OSCILLATOR_STOPPED != 0)
+    }
+
+    /// Reads the temperature of the chip, updated every 64 seconds.
+    /// # Returns
+    /// * `a Result<f32>` - The temperature in degree Celsius, with a resolution of 0.25.
+    pub fn temperature(&mut self) -> Result<f32> {
+        let mut t = [0; 2];
+        self.i2c
+            .write_read(RTC_ADDRESS, &[DS3231_TEMPERATURE], &mut t)
+            .map_err(Error::from_i2c)?;
+        Ok(t[0] as i8 as f32 + (t[1] >> 6) as f32 * 0.25)
+    }
+
+    /// Enables or disables the square wave output on the INT/SQW pin.
+    /// When disabled, the pin is free for the alarm interrupts.
+    /// # Arguments
+    /// * `enable` - a boolean, true for a 1 Hz square wave.
+    pub fn set_square_wave(&mut self, enable: bool) -> Result<()> {
+        let control = self.read_register(DS3231_CONTROL)?;
+        // INTCN (bit 2) routes the alarms to the pin, RS2 and RS1 (bits 4 and 3) select 1 Hz.
+        let control = if enable {
+            control & !0x1C
+        } else {
+            control | 0x04
+        };
+        self.write_register(DS3231_CONTROL, control)
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8> {
+        let mut value = [0];
+        self.i2c
+            .write_read(RTC_ADDRESS, &[register], &mut value)
+            .map_err(Error::from_i2c)?;
+        Ok(value[0])
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<()> {
+        self.i2c
+            .write(RTC_ADDRESS, &[register, value])
+            .map_err(Error::from_i2c)
+    }
+}
+
+impl<I2C: I2c> Clock for DS3231<I2C> {
+    fn now(&mut self) -> Result<DateTime> {
+        if self.lost_power()? {
+            return Err(Error::NotReady);
+        }
+        read_time(&mut self.i2c).map(|(time, _)| time)
+    }
+
+    fn set(&mut self, time: &DateTime) -> Result<()> {
+        write_time(&mut self.i2c, time)?;
+        let status = self.read_register(DS3231_STATUS)?;
+        self.write_register(DS3231_STATUS, status & !DS3231_OSCILLATOR_STOPPED)
+    }
+}
+
+/// Uses alarm 1, which drives the INT/SQW pin low when it fires. The pin is open drain
+/// and stays low till `take_alarm` is called, so it can wake the MCU from power-down
+/// through a level triggered external interrupt.
+impl<I2C: I2c> AlarmClock for DS3231<I2C> {
+    fn set_alarm(&mut self, time: &DateTime) -> Result<()> {
+        // All mask bits cleared: the alarm matches the date, hours, minutes and seconds.
+        let data = [
+            DS3231_ALARM1,
+            to_bcd(time.second()),
+            to_bcd(time.minute()),
+            to_bcd(time.hour()),
+            to_bcd(time.day()),
+        ];
+        self.i2c
+            .write(RTC_ADDRESS, &data)
+            .map_err(Error::from_i2c)?;
+        self.take_alarm()?;
+        let control = self.read_register(DS3231_CONTROL)?;
+        self.write_register(
+            DS3231_CONTROL,
+            control | DS3231_INTERRUPT_CONTROL | DS3231_ALARM1_ENABLE,
+        )
+    }
+
+    fn take_alarm(&mut self) -> Result<bool> {
+        let status = self.read_register(DS3231_STATUS)?;
+        if status & DS3231_ALARM1_FLAG == 0 {
+            return Ok(false);
+        }
+        self.write_register(DS3231_STATUS, status & !DS3231_ALARM1_FLAG)?;
+        Ok(true)
+    }
+}
+
+/// A DS1307 real-time clock.
+/// # Elements
+/// * `i2c` - the I2C bus to which the clock is attached.
+pub struct DS1307<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: I2c> DS1307<I2C> {
+    /// Creates the driver.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the clock is attached, for example `&I2cBus`.
+    /// # Returns
+    /// * `a DS1307 object` - The driver.
+    pub fn new(i2c: I2C) -> DS1307<I2C> {
+        DS1307 { i2c }
+    }
+
+    /// Gives back the bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Returns true if the clock is counting, it is halted on first power up till it is set.
+    pub fn is_running(&mut self) -> Result<bool> {
+        let mut seconds = [0];
+        self.i2c
+            .write_read(RTC_ADDRESS, &[RTC_SECONDS], &mut seconds)
+            .map_err(Error::from_i2c)?;
+        Ok(seconds[0] & DS1307_CLOCK_HALT == 0)
+    }
+}
+
+impl<I2C: I2c> Clock for DS1307<I2C> {
+    fn now(&mut self) -> Result<DateTime> {
+        match read_time(&mut self.i2c)? {
+            (_, seconds) if seconds & DS1307_CLOCK_HALT != 0 => Err(Error::NotReady),
+            (time, _) => Ok(time),
+        }
+    }
+
+    fn set(&mut self, time: &DateTime) -> Result<()> {
+        write_time(&mut self.i2c, time)
+    }
+}
@@ -0,0 +1,515 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Driver for SSD1306 OLED displays on the I2C bus, and a `Console` showing
+//! scrolling text on them. Boards without a serial adapter attached can print
+//! their reports, log records and panic messages to the console, which
+//! implements `embedded_io::Write`, `core::fmt::Write` and `LogSink`.
+//! The console keeps no copy of the text in RAM: scrolling moves the start
+//! line of the display, so only the new line is cleared and redrawn.
+//! For more information see the following link.
+//! `<https://cdn-shop.adafruit.com/datasheets/SSD1306.pdf>`
+
+// Source codes required
+use crate::progmem;
+use crate::system::datalogger::{LogSink, Record};
+use crate::{Error, Result};
+use embedded_hal::i2c::{I2c, Operation};
+
+/// Address of the display when its SA0 pin is low, 0x3D when it is high.
+pub const SSD1306_ADDRESS: u8 = 0x3C;
+
+/// Width of the display in pixels.
+pub const SSD1306_WIDTH: u8 = 128;
+
+/// Number of pages of 8 rows in the display memory.
+const PAGES: u8 = 8;
+
+/// Control bytes announcing commands or data.
+const CONTROL_COMMAND: u8 = 0x00;
+const CONTROL_DATA: u8 = 0x40;
+
+/// Commands of the SSD1306.
+const SET_CONTRAST: u8 = 0x81;
+const DISPLAY_RESUME: u8 = 0xA4;
+const NORMAL: u8 = 0xA6;
+const INVERTED: u8 = 0xA7;
+const DISPLAY_OFF: u8 = 0xAE;
+const DISPLAY_ON: u8 = 0xAF;
+const SET_START_LINE: u8 = 0x40;
+const SET_PAGE: u8 = 0xB0;
+
+/// Structure to control a SSD1306 display of 128 columns.
+/// # Elements
+/// * `i2c` - the I2C bus to which the display is attached.
+/// * `address` - a u8, the address of the display.
+/// * `pages` - a u8, the number of visible pages of 8 rows.
+pub struct Ssd1306<I2C> {
+    i2c: I2C,
+    address: u8,
+    pages: u8,
+}
+
+impl<I2C: I2c> Ssd1306<I2C> {
+    /// Initiates the display, cleared and switched on.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the display is attached, for example `&I2cBus`.
+    /// * `address` - a u8, the address of the display, usually `SSD1306_ADDRESS`.
+    /// * `height` - a u8, the number of rows of the display, 32 or 64.
+    /// # Returns
+    /// * `a Result<Ssd1306>` - The display, or the error of the bus.
+    pub fn new(i2c: I2C, address: u8, height: u8) -> Result<Ssd1306<I2C>> {
+        if height != 32 && height != 64 {
+            return Err(Error::InvalidArgument);
+        }
+        let mut display = Ssd1306 {
+            i2c,
+            address,
+            pages: height / 8,
+        };
+        let pins = if height == 64 { 0x12 } else { 0x02 };
+        display.command(&[
+            DISPLAY_OFF,
+            0xD5, // Clock divider.
+            0x80,
+            0xA8, // Multiplex ratio.
+            height - 1,
+            0xD3, // Display offset.
+            0x00,
+            SET_START_LINE,
+            0x8D, // Charge pump on.
+            0x14,
+            0x20, // Page addressing.
+            0x02,
+            0xA1, // Column 127 on the left.
+            0xC8, // Rows scanned from the bottom.
+            0xDA, // COM pins configuration.
+            pins,
+            SET_CONTRAST,
+            0xCF,
+            0xD9, // Precharge period.
+            0xF1,
+            0xDB, // VCOMH level.
+            0x40,
+            DISPLAY_RESUME,
+            NORMAL,
+        ])?;
+        display.clear()?;
+        display.command(&[DISPLAY_ON])?;
+        Ok(display)
+    }
+
+    /// Gives back the I2C bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Returns the number of visible pages of 8 rows.
+    pub fn pages(&self) -> u8 {
+        self.pages
+    }
+
+    /// Sends commands.
+    /// # Arguments
+    /// * `commands` - a slice of u8, the command bytes and their arguments.
+    pub fn command(&mut self, commands: &[u8]) -> Result<()> {
+        self.i2c
+            .transaction(
+                self.address,
+                &mut [
+                    Operation::Write(&[CONTROL_COMMAND]),
+                    Operation::Write(commands),
+                ],
+            )
+            .map_err(Error::from_i2c)
+    }
+
+    /// Writes columns of 8 pixels at the position set by `set_position`,
+    /// the least significant bit of each byte being the top pixel.
+    /// # Arguments
+    /// * `data` - a slice of u8, the columns to write.
+    pub fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        self.i2c
+            .transaction(
+                self.address,
+                &mut [Operation::Write(&[CONTROL_DATA]), Operation::Write(data)],
+            )
+            .map_err(Error::from_i2c)
+    }
+
+    /// Moves the write position.
+    /// # Arguments
+    /// * `page` - a u8, the page of 8 rows in the display memory, from 0 to 7.
+    /// * `column` - a u8, the column from 0 to 127.
+    pub fn set_position(&mut self, page: u8, column: u8) -> Result<()> {
+        self.command(&[
+            SET_PAGE | (page % PAGES),
+            column & 0x0F,
+            0x10 | (column >> 4 & 0x07),
+        ])
+    }
+
+    /// Clears a page of the display memory.
+    /// # Arguments
+    /// * `page` - a u8, the page from 0 to 7.
+    pub fn clear_page(&mut self, page: u8) -> Result<()> {
+        self.set_position(page, 0)?;
+        let blank = [0u8; 16];
+        for _ in 0..SSD1306_WIDTH / 16 {
+            self.write_data(&blank)?;
+        }
+        Ok(())
+    }
+
+    /// Clears the whole display memory.
+    pub fn clear(&mut self) -> Result<()> {
+        for page in 0..PAGES {
+            self.clear_page(page)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the row of the display memory shown at the top of the screen.
+    /// # Arguments
+    /// * `line` - a u8, the row from 0 to 63.
+    pub fn set_start_line(&mut self, line: u8) -> Result<()> {
+        self.command(&[SET_START_LINE | (line & 0x3F)])
+    }
+
+    /// Sets the brightness.
+    /// # Arguments
+    /// * `contrast` - a u8, the contrast from 0 to 255.
+    pub fn set_contrast(&mut self, contrast: u8) -> Result<()> {
+        self.command(&[SET_CONTRAST, contrast])
+    }
+
+    /// Switches the display on or off, the memory is kept while it is off.
+    pub fn set_on(&mut self, on: bool) -> Result<()> {
+        self.command(&[if on { DISPLAY_ON } else { DISPLAY_OFF }])
+    }
+
+    /// Shows light text on a dark background or the other way round.
+    pub fn set_inverted(&mut self, inverted: bool) -> Result<()> {
+        self.command(&[if inverted { INVERTED } else { NORMAL }])
+    }
+}
+
+progmem! {
+    /// Glyphs of the printable ASCII characters from 0x20, 5 columns of 7 pixels.
+    /// The last one is a box shown for the characters without glyph.
+    static FONT: [[u8; 5]; 96] = [
+        [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+        [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], [0x08, 0x2A, 0x1C, 0x2A, 0x08], [0x08, 0x08, 0x3E, 0x08, 0x08], [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+        [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+        [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06],
+        [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22], [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x01, 0x01], [0x3E, 0x41, 0x41, 0x51, 0x32],
+        [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x04, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+        [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F],
+        [0x63, 0x14, 0x08, 0x14, 0x63], [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00], [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+        [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x08, 0x14, 0x54, 0x54, 0x3C],
+        [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00], [0x00, 0x7F, 0x10, 0x28, 0x44], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+        [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20], [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C],
+        [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08], [0x7F, 0x7F, 0x7F, 0x7F, 0x7F],
+    ];
+}
+
+/// Width of a character cell in pixels, the glyph and one blank column.
+const CELL_WIDTH: u8 = 6;
+
+/// Number of characters on a line.
+pub const CONSOLE_COLUMNS: u8 = SSD1306_WIDTH / CELL_WIDTH;
+
+/// A text terminal on a SSD1306, scrolling up when the last line is full.
+/// # Elements
+/// * `display` - the display the text is shown on.
+/// * `row` - a u8, the line of the cursor on the screen.
+/// * `column` - a u8, the column of the cursor.
+/// * `top` - a u8, the page of the display memory shown on the first line.
+pub struct Console<I2C> {
+    display: Ssd1306<I2C>,
+    row: u8,
+    column: u8,
+    top: u8,
+}
+
+impl<I2C: I2c> Console<I2C> {
+    /// Clears the display and puts the cursor on the first line.
+    /// # Arguments
+    /// * `display` - a `Ssd1306` object, the display to use.
+    pub fn new(mut display: Ssd1306<I2C>) -> Result<Console<I2C>> {
+        display.clear()?;
+        display.set_start_line(0)?;
+        Ok(Console {
+            display,
+            row: 0,
+            column: 0,
+            top: 0,
+        })
+    }
+
+    /// Gives back the display.
+    pub fn release(self) -> Ssd1306<I2C> {
+        self.display
+    }
+
+    /// Returns the number of lines of text.
+    pub fn rows(&self) -> u8 {
+        self.display.pages()
+    }
+
+    /// Clears the text and puts the cursor on the first line.
+    pub fn clear(&mut self) -> Result<()> {
+        self.display.clear()?;
+        self.display.set_start_line(0)?;
+        self.row = 0;
+        self.column = 0;
+        self.top = 0;
+        Ok(())
+    }
+
+    /// Moves the cursor to the start of the next line, scrolling if it is the last one.
+    pub fn new_line(&mut self) -> Result<()> {
+        self.column = 0;
+        if self.row + 1 < self.rows() {
+            self.row += 1;
+            return Ok(());
+        }
+        self.top = (self.top + 1) % PAGES;
+        self.display.clear_page(self.top + self.row)?;
+        self.display.set_start_line(self.top * 8)
+    }
+
+    /// Prints one character, lines longer than the screen are wrapped.
+    /// `\n` starts a new line and `\r` goes back to the start of the line.
+    /// # Arguments
+    /// * `c` - a u8, the ASCII character.
+    pub fn put(&mut self, c: u8) -> Result<()> {
+        match c {
+            b'\n' => return self.new_line(),
+            b'\r' => {
+                self.column = 0;
+                return Ok(());
+            }
+            _ => {}
+        }
+        if self.column == CONSOLE_COLUMNS {
+            self.new_line()?;
+        }
+        let glyph = match c {
+            0x20..=0x7E => FONT.get((c - 0x20) as usize),
+            _ => FONT.get(95),
+        }
+        .unwrap_or([0; 5]);
+        let mut cell = [0u8; CELL_WIDTH as usize];
+        cell[..5].copy_from_slice(&glyph);
+        self.display
+            .set_position(self.top + self.row, self.column * CELL_WIDTH)?;
+        self.display.write_data(&cell)?;
+        self.column += 1;
+        Ok(())
+    }
+
+    /// Prints text.
+    /// # Arguments
+    /// * `text` - a slice of u8, the ASCII characters.
+    pub fn print(&mut self, text: &[u8]) -> Result<()> {
+        for &c in text {
+            self.put(c)?;
+        }
+        Ok(())
+    }
+
+    /// Prints the message and the location of a panic, for use in a panic handler.
+    /// # Arguments
+    /// * `info` - a reference to `PanicInfo`, given to the panic handler.
+    pub fn print_panic(&mut self, info: &core::panic::PanicInfo) -> Result<()> {
+        use core::fmt::Write;
+        if self.column != 0 {
+            self.new_line()?;
+        }
+        self.print(b"PANIC")?;
+        if let Some(location) = info.location() {
+            let _ = write!(self, " {}:{}", location.file(), location.line());
+        }
+        self.new_line()?;
+        let _ = write!(self, "{}", info.message());
+        Ok(())
+    }
+}
+
+impl<I2C: I2c> embedded_io::ErrorType for Console<I2C> {
+    type Error = Error;
+}
+
+impl<I2C: I2c> embedded_io::Write for Console<I2C> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.print(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<I2C: I2c> core::fmt::Write for Console<I2C> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.print(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
+impl<I2C: I2c> LogSink for Console<I2C> {
+    /// Prints the record as a line `timestamp channel value`.
+    fn write(&mut self, record: &Record) -> bool {
+        use core::fmt::Write;
+        if self.column != 0 && self.new_line().is_err() {
+            return false;
+        }
+        write!(
+            self,
+            "{} {} {}",
+            record.timestamp, record.channel, record.value
+        )
+        .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::fmt::Write;
+    use embedded_hal::i2c::ErrorType;
+
+    /// The display memory and the page addressing of a SSD1306.
+    struct Oled {
+        memory: [[u8; 128]; 8],
+        page: usize,
+        column: usize,
+        start_line: u8,
+    }
+
+    impl Oled {
+        fn new() -> Oled {
+            Oled {
+                memory: [[0xFF; 128]; 8],
+                page: 0,
+                column: 0,
+                start_line: 0xFF,
+            }
+        }
+
+        fn command(&mut self, commands: &[u8]) {
+            let mut i = 0;
+            while i < commands.len() {
+                match commands[i] {
+                    c @ 0x00..=0x0F => self.column = (self.column & 0xF0) | c as usize,
+                    c @ 0x10..=0x17 => {
+                        self.column = (self.column & 0x0F) | (c as usize & 0x07) << 4
+                    }
+                    c @ 0x40..=0x7F => self.start_line = c & 0x3F,
+                    c @ 0xB0..=0xB7 => self.page = (c & 0x07) as usize,
+                    // Commands followed by an argument.
+                    0x20 | 0x81 | 0x8D | 0xA8 | 0xD3 | 0xD5 | 0xD9 | 0xDA | 0xDB => i += 1,
+                    _ => {}
+                }
+                i += 1;
+            }
+        }
+    }
+
+    impl ErrorType for Oled {
+        type Error = Error;
+    }
+
+    impl I2c for Oled {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<()> {
+            if address != SSD1306_ADDRESS {
+                return Err(Error::AddressNack);
+            }
+            match operations {
+                [Operation::Write([CONTROL_COMMAND]), Operation::Write(commands)] => {
+                    self.command(commands)
+                }
+                [Operation::Write([CONTROL_DATA]), Operation::Write(data)] => {
+                    for byte in data.iter() {
+                        self.memory[self.page][self.column] = *byte;
+                        self.column = (self.column + 1) % 128;
+                    }
+                }
+                _ => return Err(Error::InvalidArgument),
+            }
+            Ok(())
+        }
+    }
+
+    /// Returns the columns of a character cell in the display memory.
+    fn cell(oled: &Oled, page: usize, column: usize) -> &[u8] {
+        &oled.memory[page][column * 6..column * 6 + 6]
+    }
+
+    #[test]
+    fn initialisation() {
+        assert_eq!(
+            Ssd1306::new(Oled::new(), SSD1306_ADDRESS, 48).err(),
+            Some(Error::InvalidArgument)
+        );
+        let mut display = Ssd1306::new(Oled::new(), SSD1306_ADDRESS, 32).unwrap();
+        assert_eq!(display.pages(), 4);
+        display.set_position(2, 0x35).unwrap();
+        display.write_data(&[1, 2]).unwrap();
+        let oled = display.release();
+        assert_eq!(oled.memory[2][0x35..0x37], [1, 2]);
+        assert_eq!(oled.memory[7], [0; 128]);
+        assert_eq!(oled.start_line, 0);
+    }
+
+    #[test]
+    fn console_text() {
+        let display = Ssd1306::new(Oled::new(), SSD1306_ADDRESS, 32).unwrap();
+        let mut console = Console::new(display).unwrap();
+        console.print(b"A\x01").unwrap();
+        let oled = console.release().release();
+        assert_eq!(cell(&oled, 0, 0), [0x7E, 0x11, 0x11, 0x11, 0x7E, 0]);
+        assert_eq!(cell(&oled, 0, 1), [0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0]);
+
+        // Long lines wrap, and a carriage return goes back to the start of the line.
+        let display = Ssd1306::new(oled, SSD1306_ADDRESS, 32).unwrap();
+        let mut console = Console::new(display).unwrap();
+        for _ in 0..CONSOLE_COLUMNS {
+            console.put(b'-').unwrap();
+        }
+        write!(console, "ab\rc").unwrap();
+        let oled = console.release().release();
+        assert_eq!(cell(&oled, 0, 20), [0x08, 0x08, 0x08, 0x08, 0x08, 0]);
+        assert_eq!(cell(&oled, 1, 0), [0x38, 0x44, 0x44, 0x44, 0x20, 0]);
+        assert_eq!(cell(&oled, 1, 1), [0x7F, 0x48, 0x44, 0x44, 0x38, 0]);
+    }
+
+    #[test]
+    fn console_scrolling() {
+        let display = Ssd1306::new(Oled::new(), SSD1306_ADDRESS, 32).unwrap();
+        let mut console = Console::new(display).unwrap();
+        console.print(b"1\n2\n3\n4").unwrap();
+        assert_eq!(console.display.i2c.start_line, 0);
+
+        // The fifth line is drawn in the page below the screen, which then moves up.
+        console.print(b"\n5").unwrap();
+        let oled = console.release().release();
+        assert_eq!(oled.start_line, 8);
+        assert_eq!(cell(&oled, 4, 0), [0x27, 0x45, 0x45, 0x45, 0x39, 0]);
+        assert_eq!(oled.memory[4][6..], [0; 122]);
+    }
+}
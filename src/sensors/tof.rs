@@ -0,0 +1,319 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Time of flight measurements with ultrasonic transducers.
+//! A `Transducer` sends a burst and times its echo or its arrival at a
+//! second transducer. `TimeOfFlight` turns the round trip of an echo into a
+//! distance, correcting the speed of sound for the air temperature, and
+//! `Anemometer` computes the wind speed from two flights in opposite
+//! directions along the same path. `HCSR04` is the common ranging module.
+//! Times come from `micros`, so `millis_init` must have been called and the
+//! resolution is 4 microseconds, about 0.7 mm of distance, on a 16 MHz clock.
+//! The module needs the `millis` feature.
+
+// Source codes required
+use crate::delay::delay_us;
+use crate::hal::millis::micros;
+use crate::{Error, Result};
+use core::convert::Infallible;
+use embedded_hal::digital::{InputPin, OutputPin};
+
+/// Waits for a pulse of the given level and measures its length.
+/// A pulse already going on when the function is called is skipped.
+/// # Arguments
+/// * `pin` - the input pin to watch.
+/// * `high` - a boolean, true to time a high pulse and false for a low one.
+/// * `timeout` - a u32, the longest time to wait in microseconds, for the whole pulse.
+/// # Returns
+/// * `a Result<u32>` - The length of the pulse in microseconds, or `Error::Timeout`.
+pub fn pulse_in<P: InputPin<Error = Infallible>>(
+    pin: &mut P,
+    high: bool,
+    timeout: u32,
+) -> Result<u32> {
+    let start = micros();
+    let level = |pin: &mut P| pin.is_high().unwrap_or(false) == high;
+    let expired = || micros().wrapping_sub(start) > timeout;
+
+    while level(pin) {
+        if expired() {
+            return Err(Error::Timeout);
+        }
+    }
+    while !level(pin) {
+        if expired() {
+            return Err(Error::Timeout);
+        }
+    }
+    let rise = micros();
+    while level(pin) {
+        if expired() {
+            return Err(Error::Timeout);
+        }
+    }
+    Ok(micros().wrapping_sub(rise))
+}
+
+/// Returns the speed of sound in dry air.
+/// # Arguments
+/// * `temperature` - an i16, the air temperature in tenths of a degree Celsius.
+/// # Returns
+/// * `a u32` - The speed of sound in millimeters per second.
+pub fn speed_of_sound(temperature: i16) -> u32 {
+    (331_300 + temperature as i32 * 606 / 10).max(0) as u32
+}
+
+/// Converts the round trip time of an echo to the distance of the obstacle.
+/// # Arguments
+/// * `round_trip` - a u32, the time between the burst and its echo in microseconds.
+/// * `temperature` - an i16, the air temperature in tenths of a degree Celsius.
+/// # Returns
+/// * `a u32` - The distance in millimeters.
+pub fn round_trip_distance(round_trip: u32, temperature: i16) -> u32 {
+    (round_trip as u64 * speed_of_sound(temperature) as u64 / 2_000_000) as u32
+}
+
+/// A source of time of flight measurements.
+pub trait Transducer {
+    /// Sends a burst.
+    fn trigger(&mut self) -> Result<()>;
+
+    /// Waits for the burst sent by `trigger` to be received.
+    /// # Arguments
+    /// * `timeout` - a u32, the longest time to wait in microseconds.
+    /// # Returns
+    /// * `a Result<u32>` - The flight time in microseconds, or `Error::Timeout`.
+    fn capture(&mut self, timeout: u32) -> Result<u32>;
+}
+
+impl<T: Transducer> Transducer for &mut T {
+    fn trigger(&mut self) -> Result<()> {
+        (**self).trigger()
+    }
+
+    fn capture(&mut self, timeout: u32) -> Result<u32> {
+        (**self).capture(timeout)
+    }
+}
+
+/// Structure to control a HC-SR04 ultrasonic ranging module.
+/// The module answers a 10 us pulse on TRIG with a pulse on ECHO
+/// as long as the round trip of the sound.
+pub struct HCSR04<TRIG, ECHO> {
+    trig: TRIG,
+    echo: ECHO,
+}
+
+impl<TRIG, ECHO> HCSR04<TRIG, ECHO>
+where
+    TRIG: OutputPin<Error = Infallible>,
+    ECHO: InputPin<Error = Infallible>,
+{
+    /// Creates a HC-SR04 on the given pins, which must be in output and input mode.
+    /// # Arguments
+    /// * `trig` - the output pin connected to TRIG.
+    /// * `echo` - the input pin connected to ECHO.
+    /// # Returns
+    /// * `a HCSR04 object` - Which will be used for further implementations.
+    pub fn new(mut trig: TRIG, echo: ECHO) -> HCSR04<TRIG, ECHO> {
+        trig.set_low().ok();
+        HCSR04 { trig, echo }
+    }
+
+    /// Gives back the pins.
+    pub fn release(self) -> (TRIG, ECHO) {
+        (self.trig, self.echo)
+    }
+}
+
+impl<TRIG, ECHO> Transducer for HCSR04<TRIG, ECHO>
+where
+    TRIG: OutputPin<Error = Infallible>,
+    ECHO: InputPin<Error = Infallible>,
+{
+    fn trigger(&mut self) -> Result<()> {
+        self.trig.set_high().ok();
+        delay_us(10);
+        self.trig.set_low().ok();
+        Ok(())
+    }
+
+    fn capture(&mut self, timeout: u32) -> Result<u32> {
+        pulse_in(&mut self.echo, true, timeout)
+    }
+}
+
+/// Distance measurement from the echo of a transducer.
+/// # Elements
+/// * `transducer` - the transducer timing the echo.
+/// * `temperature` - an i16, the air temperature in tenths of a degree Celsius.
+/// * `offset` - a u32, the latency of the transducer in microseconds, removed from every flight.
+/// * `timeout` - a u32, the longest flight waited for in microseconds.
+pub struct TimeOfFlight<T> {
+    transducer: T,
+    temperature: i16,
+    offset: u32,
+    timeout: u32,
+}
+
+impl<T: Transducer> TimeOfFlight<T> {
+    /// Creates a measurement for air at 20 degrees Celsius and a range of about 5 meters.
+    /// # Arguments
+    /// * `transducer` - the transducer timing the echo.
+    /// # Returns
+    /// * `a TimeOfFlight object` - Which will be used for further implementations.
+    pub fn new(transducer: T) -> TimeOfFlight<T> {
+        TimeOfFlight {
+            transducer,
+            temperature: 200,
+            offset: 0,
+            timeout: 30_000,
+        }
+    }
+
+    /// Gives back the transducer.
+    pub fn release(self) -> T {
+        self.transducer
+    }
+
+    /// Sets the air temperature used to compute the speed of sound.
+    /// # Arguments
+    /// * `temperature` - an i16, the temperature in tenths of a degree Celsius.
+    pub fn set_temperature(&mut self, temperature: i16) {
+        self.temperature = temperature;
+    }
+
+    /// Sets the latency of the transducer, found by measuring a known distance.
+    /// # Arguments
+    /// * `offset` - a u32, the time in microseconds removed from every flight.
+    pub fn set_offset(&mut self, offset: u32) {
+        self.offset = offset;
+    }
+
+    /// Sets the longest flight waited for, which limits the range.
+    /// # Arguments
+    /// * `timeout` - a u32, the time in microseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Sends a burst and times its echo.
+    /// # Returns
+    /// * `a Result<u32>` - The round trip time in microseconds, or `Error::Timeout` if no echo came back.
+    pub fn measure(&mut self) -> Result<u32> {
+        self.transducer.trigger()?;
+        let flight = self.transducer.capture(self.timeout)?;
+        Ok(flight.saturating_sub(self.offset))
+    }
+
+    /// Measures the distance of the nearest obstacle.
+    /// # Returns
+    /// * `a Result<u32>` - The distance in millimeters, or `Error::Timeout` if no echo came back.
+    pub fn distance(&mut self) -> Result<u32> {
+        let round_trip = self.measure()?;
+        Ok(round_trip_distance(round_trip, self.temperature))
+    }
+}
+
+/// Computes the wind speed along a path from the flight times in both directions.
+/// The result does not depend on the air temperature.
+/// # Arguments
+/// * `path` - a u32, the distance between the transducers in millimeters.
+/// * `forward` - a u32, the flight time from the first to the second transducer in microseconds.
+/// * `backward` - a u32, the flight time from the second to the first transducer in microseconds.
+/// # Returns
+/// * `an i32` - The wind speed in millimeters per second, positive when blowing from the first transducer to the second.
+pub fn wind_speed(path: u32, forward: u32, backward: u32) -> i32 {
+    if forward == 0 || backward == 0 {
+        return 0;
+    }
+    let difference = backward as i64 - forward as i64;
+    let product = 2 * forward as i64 * backward as i64;
+    (path as i64 * difference * 1_000_000 / product) as i32
+}
+
+/// Computes the speed of sound along a path from the flight times in both directions,
+/// which gives the air temperature without the wind.
+/// # Arguments
+/// * `path` - a u32, the distance between the transducers in millimeters.
+/// * `forward` - a u32, the flight time from the first to the second transducer in microseconds.
+/// * `backward` - a u32, the flight time from the second to the first transducer in microseconds.
+/// # Returns
+/// * `a u32` - The speed of sound in millimeters per second.
+pub fn sound_speed(path: u32, forward: u32, backward: u32) -> u32 {
+    if forward == 0 || backward == 0 {
+        return 0;
+    }
+    let sum = forward as u64 + backward as u64;
+    let product = 2 * forward as u64 * backward as u64;
+    (path as u64 * sum * 1_000_000 / product) as u32
+}
+
+/// Ultrasonic anemometer with a pair of transducers facing each other.
+/// # Elements
+/// * `forward` - the transducer timing flights from the first to the second side.
+/// * `backward` - the transducer timing flights from the second to the first side.
+/// * `path` - a u32, the distance between the sides in millimeters.
+/// * `timeout` - a u32, the longest flight waited for in microseconds.
+pub struct Anemometer<F, B> {
+    forward: F,
+    backward: B,
+    path: u32,
+    timeout: u32,
+}
+
+impl<F: Transducer, B: Transducer> Anemometer<F, B> {
+    /// Creates an anemometer.
+    /// # Arguments
+    /// * `forward` - the transducer timing flights from the first to the second side.
+    /// * `backward` - the transducer timing flights from the second to the first side.
+    /// * `path` - a u32, the distance between the sides in millimeters.
+    /// # Returns
+    /// * `an Anemometer object` - Which will be used for further implementations.
+    pub fn new(forward: F, backward: B, path: u32) -> Anemometer<F, B> {
+        // Twice the flight time at the slowest speed of sound expected, at -40 degrees.
+        let timeout = (path as u64 * 2_000_000 / speed_of_sound(-400) as u64) as u32 + 100;
+        Anemometer {
+            forward,
+            backward,
+            path,
+            timeout,
+        }
+    }
+
+    /// Gives back the transducers.
+    pub fn release(self) -> (F, B) {
+        (self.forward, self.backward)
+    }
+
+    /// Times one flight in each direction.
+    /// # Returns
+    /// * `a Result<(u32, u32)>` - The forward and backward flight times in microseconds.
+    pub fn flights(&mut self) -> Result<(u32, u32)> {
+        self.forward.trigger()?;
+        let forward = self.forward.capture(self.timeout)?;
+        self.backward.trigger()?;
+        let backward = self.backward.capture(self.timeout)?;
+        Ok((forward, backward))
+    }
+
+    /// Measures the wind speed along the path.
+    /// # Returns
+    /// * `a Result<i32>` - The speed in millimeters per second, positive from the first side to the second.
+    pub fn wind_speed(&mut self) -> Result<i32> {
+        let (forward, backward) = self.flights()?;
+        Ok(wind_speed(self.path, forward, backward))
+    }
+}
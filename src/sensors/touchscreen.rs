@@ -0,0 +1,342 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Driver for resistive 4-wire touchscreens, like the ones on TFT display shields.
+//! A touchscreen is two resistive plates which touch where they are pressed.
+//! Driving one plate from ground to VCC makes the other one a probe whose
+//! voltage, read by the ADC, is the position of the touch along that plate.
+//! `YP` and `XM` must be analog pins, `XP` and `YM` may be any digital pins.
+//! On shields the pins are shared with the display data bus, so the display
+//! driver has to set them back to outputs after each read of the touchscreen.
+
+// Source codes required
+use crate::delay::delay_us;
+use crate::hal::pin::{AnalogPin, DigitalPin, Pins};
+use embedded_hal::digital::OutputPin;
+
+/// Number of microseconds given to the plates to settle after switching the pins.
+const SETTLE_US: u32 = 20;
+
+/// Largest difference allowed between two samples of a position.
+const MAX_JITTER: u32 = 8;
+
+/// A touch in raw ADC values or in screen coordinates.
+/// # Elements
+/// * `x` - a u16, the horizontal position.
+/// * `y` - a u16, the vertical position.
+/// * `pressure` - a u16, the resistance of the touch in ohms, lower for a firmer touch.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TouchPoint {
+    pub x: u16,
+    pub y: u16,
+    pub pressure: u16,
+}
+
+/// Mapping of raw readings to screen coordinates.
+/// The raw values are read with a stylus on the edges of the screen,
+/// a minimum larger than the maximum flips the axis.
+/// # Elements
+/// * `x_min` - a u16, the raw x reading at the left edge.
+/// * `x_max` - a u16, the raw x reading at the right edge.
+/// * `y_min` - a u16, the raw y reading at the top edge.
+/// * `y_max` - a u16, the raw y reading at the bottom edge.
+/// * `width` - a u16, the width of the screen in pixels.
+/// * `height` - a u16, the height of the screen in pixels.
+/// * `swap_axes` - a boolean, true if the screen is rotated by a quarter turn relative to the plates.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Calibration {
+    pub x_min: u16,
+    pub x_max: u16,
+    pub y_min: u16,
+    pub y_max: u16,
+    pub width: u16,
+    pub height: u16,
+    pub swap_axes: bool,
+}
+
+impl Calibration {
+    /// Converts a raw touch to screen coordinates, clamped to the screen.
+    /// # Arguments
+    /// * `raw` - a `TouchPoint` object, the touch as read from the ADC.
+    /// # Returns
+    /// * `a TouchPoint object` - The touch in pixels, with the same pressure.
+    pub fn map(&self, raw: TouchPoint) -> TouchPoint {
+        let (x, y) = if self.swap_axes {
+            (raw.y, raw.x)
+        } else {
+            (raw.x, raw.y)
+        };
+        TouchPoint {
+            x: scale(x, self.x_min, self.x_max, self.width),
+            y: scale(y, self.y_min, self.y_max, self.height),
+            pressure: raw.pressure,
+        }
+    }
+}
+
+/// Maps a raw value from the range between two readings to `0..size`.
+fn scale(value: u16, from: u16, to: u16, size: u16) -> u16 {
+    if from == to || size == 0 {
+        return 0;
+    }
+    let position = (value as i32 - from as i32) * (size as i32 - 1) / (to as i32 - from as i32);
+    position.clamp(0, size as i32 - 1) as u16
+}
+
+/// Structure to control a resistive touchscreen.
+/// # Elements
+/// * `xp` - a `DigitalPin` object, the pin on the positive end of the x plate.
+/// * `yp` - an `AnalogPin` object, the pin on the positive end of the y plate.
+/// * `xm` - an `AnalogPin` object, the pin on the negative end of the x plate.
+/// * `ym` - a `DigitalPin` object, the pin on the negative end of the y plate.
+/// * `x_plate` - a u16, the resistance of the x plate in ohms.
+/// * `threshold` - a u16, the largest touch resistance counted as a touch.
+/// * `calibration` - a `Calibration` object, used by `read`.
+pub struct TouchScreen {
+    xp: DigitalPin,
+    yp: AnalogPin,
+    xm: AnalogPin,
+    ym: DigitalPin,
+    x_plate: u16,
+    threshold: u16,
+    calibration: Calibration,
+}
+
+impl TouchScreen {
+    /// Creates a touchscreen on the given pins, calibrated for the full
+    /// ADC range on a 240x320 screen until `set_calibration` is called.
+    /// # Arguments
+    /// * `xp` - a usize, the digital pin number of X+.
+    /// * `yp` - a usize, the analog pin number of Y+.
+    /// * `xm` - a usize, the analog pin number of X-.
+    /// * `ym` - a usize, the digital pin number of Y-.
+    /// * `x_plate` - a u16, the resistance between X+ and X- in ohms, as measured with a multimeter.
+    /// # Returns
+    /// * `a TouchScreen object` - Which will be used for further implementations.
+    pub fn new(xp: usize, yp: usize, xm: usize, ym: usize, x_plate: u16) -> TouchScreen {
+        let pins = Pins::new();
+        TouchScreen {
+            xp: pins.digital[xp],
+            yp: pins.analog[yp],
+            xm: pins.analog[xm],
+            ym: pins.digital[ym],
+            x_plate,
+            threshold: 1000,
+            calibration: Calibration {
+                x_min: 0,
+                x_max: 1023,
+                y_min: 0,
+                y_max: 1023,
+                width: 240,
+                height: 320,
+                swap_axes: false,
+            },
+        }
+    }
+
+    /// Sets the mapping of raw readings to screen coordinates.
+    /// # Arguments
+    /// * `calibration` - a `Calibration` object, the new mapping.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Sets the largest touch resistance which is counted as a touch.
+    /// # Arguments
+    /// * `ohms` - a u16, the threshold, 1000 by default.
+    pub fn set_threshold(&mut self, ohms: u16) {
+        self.threshold = ohms;
+    }
+
+    /// Reads the touch position in raw ADC values.
+    /// # Returns
+    /// * `a Option<TouchPoint>` - The touch, or None if the screen is not pressed
+    /// or the stylus moved during the measurement.
+    pub fn read_raw(&mut self) -> Option<TouchPoint> {
+        let x = self.sample_x()?;
+        let y = self.sample_y()?;
+        let pressure = self.contact(x)?;
+        Some(TouchPoint { x, y, pressure })
+    }
+
+    /// Reads the touch position in screen coordinates.
+    /// # Returns
+    /// * `a Option<TouchPoint>` - The touch, or None if the screen is not pressed.
+    pub fn read(&mut self) -> Option<TouchPoint> {
+        self.read_raw().map(|raw| self.calibration.map(raw))
+    }
+
+    /// Checks if the screen is pressed firmly enough.
+    /// # Returns
+    /// * `a boolean` - True if a touch is detected.
+    pub fn is_touched(&mut self) -> bool {
+        match self.sample_x() {
+            Some(x) => self.contact(x).is_some(),
+            None => false,
+        }
+    }
+
+    /// Drives the x plate and reads the position on Y+ twice.
+    fn sample_x(&mut self) -> Option<u16> {
+        self.ym.set_input();
+        self.ym.pin.set_low().ok();
+        self.yp.set_input();
+        self.yp.pin.set_low().ok();
+        self.xp.set_output();
+        self.xp.pin.set_high().ok();
+        self.xm.set_output();
+        self.xm.pin.set_low().ok();
+        delay_us(SETTLE_US);
+        let first = sample(&mut self.yp)?;
+        let second = sample(&mut self.yp)?;
+        average(first, second)
+    }
+
+    /// Drives the y plate and reads the position on X- twice.
+    fn sample_y(&mut self) -> Option<u16> {
+        self.xp.set_input();
+        self.xp.pin.set_low().ok();
+        self.xm.set_input();
+        self.xm.pin.set_low().ok();
+        self.yp.set_output();
+        self.yp.pin.set_high().ok();
+        self.ym.set_output();
+        self.ym.pin.set_low().ok();
+        delay_us(SETTLE_US);
+        let first = sample(&mut self.xm)?;
+        let second = sample(&mut self.xm)?;
+        average(first, second)
+    }
+
+    /// Measures the resistance of the contact between the plates, by driving
+    /// X+ low and Y- high and reading both remaining ends.
+    /// # Arguments
+    /// * `x` - a u16, the raw x position of the touch.
+    /// # Returns
+    /// * `a Option<u16>` - The resistance in ohms, or None if it is above the threshold.
+    fn contact(&mut self, x: u16) -> Option<u16> {
+        self.xp.set_output();
+        self.xp.pin.set_low().ok();
+        self.ym.set_output();
+        self.ym.pin.set_high().ok();
+        self.xm.set_input();
+        self.xm.pin.set_low().ok();
+        self.yp.set_input();
+        self.yp.pin.set_low().ok();
+        delay_us(SETTLE_US);
+
+        let z1 = sample(&mut self.xm)?;
+        let z2 = sample(&mut self.yp)?;
+        let ohms = resistance(self.x_plate, x, z1, z2)?;
+        if ohms > self.threshold as u32 {
+            return None;
+        }
+        Some(ohms as u16)
+    }
+}
+
+/// Reads an analog pin.
+/// # Returns
+/// * `a Option<u32>` - The value read, or None if the conversion failed.
+fn sample(pin: &mut AnalogPin) -> Option<u32> {
+    #[cfg(feature = "atmega2560p")]
+    let value = pin.read().ok()?;
+    #[cfg(not(feature = "atmega2560p"))]
+    let value = pin.read();
+    Some(value)
+}
+
+/// Computes the resistance of the contact from the two readings of `contact`.
+/// # Arguments
+/// * `x_plate` - a u16, the resistance of the x plate in ohms.
+/// * `x` - a u16, the raw x position of the touch.
+/// * `z1` - a u32, the reading on X-.
+/// * `z2` - a u32, the reading on Y+.
+/// # Returns
+/// * `a Option<u32>` - The resistance in ohms, or None if the plates do not touch.
+fn resistance(x_plate: u16, x: u16, z1: u32, z2: u32) -> Option<u32> {
+    if z1 == 0 || z2 <= z1 {
+        return None;
+    }
+    Some(x_plate as u32 * x as u32 / 1024 * (z2 - z1) / z1)
+}
+
+/// Returns the average of two samples if they are close enough.
+fn average(first: u32, second: u32) -> Option<u16> {
+    let difference = if first > second {
+        first - second
+    } else {
+        second - first
+    };
+    if difference > MAX_JITTER {
+        return None;
+    }
+    Some(((first + second) / 2) as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCREEN: Calibration = Calibration {
+        x_min: 100,
+        x_max: 900,
+        y_min: 900,
+        y_max: 100,
+        width: 240,
+        height: 320,
+        swap_axes: false,
+    };
+
+    fn point(x: u16, y: u16) -> TouchPoint {
+        TouchPoint {
+            x,
+            y,
+            pressure: 300,
+        }
+    }
+
+    #[test]
+    fn calibration() {
+        assert_eq!(SCREEN.map(point(100, 900)), point(0, 0));
+        assert_eq!(SCREEN.map(point(900, 100)), point(239, 319));
+        assert_eq!(SCREEN.map(point(500, 500)), point(119, 159));
+        // Readings beyond the edges are clamped to the screen.
+        assert_eq!(SCREEN.map(point(20, 1000)), point(0, 0));
+        assert_eq!(SCREEN.map(point(1000, 20)), point(239, 319));
+
+        let rotated = Calibration {
+            swap_axes: true,
+            ..SCREEN
+        };
+        assert_eq!(rotated.map(point(900, 100)), point(0, 0));
+
+        // An uncalibrated axis maps everything to 0.
+        assert_eq!(scale(500, 300, 300, 240), 0);
+        assert_eq!(scale(500, 0, 1023, 0), 0);
+    }
+
+    #[test]
+    fn samples_and_pressure() {
+        assert_eq!(average(500, 508), Some(504));
+        assert_eq!(average(508, 500), Some(504));
+        assert_eq!(average(500, 509), None);
+
+        // A 400 ohm plate touched in the middle.
+        assert_eq!(resistance(400, 512, 300, 600), Some(200));
+        assert_eq!(resistance(400, 512, 300, 300), None);
+        assert_eq!(resistance(400, 512, 0, 600), None);
+    }
+}
@@ -0,0 +1,310 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! An append only log of fixed size records kept in a ring of slots, for
+//! recording events which must survive a reset, like a black box.
+//! Every slot holds a sequence number, the record and a CRC over both, so the
+//! newest and oldest records are found again by scanning the slots after a
+//! reset and a slot torn by a power loss is simply ignored.
+//! On memories which need erasing, like SPI flash, the slots are packed in the
+//! erase areas and a whole area of old records is dropped when the log wraps.
+
+use super::Storage;
+use crate::encoding::crc::{crc16_ccitt, crc16_ccitt_update};
+
+/// Bytes added to every record: a u32 sequence number before it and a u16 CRC after it.
+pub const LOG_SLOT_OVERHEAD: u32 = 6;
+
+/// A circular log of records of `N` bytes in the storage `S`.
+/// # Elements
+/// * `storage` - the storage holding the log.
+/// * `start` - the address of the first slot.
+/// * `slots` - the number of slots.
+/// * `per_unit` - the number of slots in an erase area, 1 if the storage needs no erasing.
+/// * `unit_size` - the number of bytes between the starts of two erase areas.
+/// * `head` - the slot the next record goes to.
+/// * `sequence` - the sequence number of the next record.
+/// * `count` - the number of records in the log.
+pub struct CircularLog<S: Storage, const N: usize> {
+    storage: S,
+    start: u32,
+    slots: u32,
+    per_unit: u32,
+    unit_size: u32,
+    head: u32,
+    sequence: u32,
+    count: u32,
+}
+
+impl<S: Storage, const N: usize> CircularLog<S, N> {
+    /// Creates the log in a region of the storage and recovers the records already there.
+    /// # Arguments
+    /// * `storage` - a `Storage` object, where the log is kept.
+    /// * `start` - a u32, the address of the region, aligned to the erase size if there is one.
+    /// * `length` - a u32, the size of the region in bytes.
+    /// # Returns
+    /// * `a Option<CircularLog>` - `None` if the region does not fit in the storage or
+    ///   has room for less than two slots, or two erase areas.
+    pub fn new(storage: S, start: u32, length: u32) -> Option<Self> {
+        let slot = N as u32 + LOG_SLOT_OVERHEAD;
+        let erase = storage.erase_size();
+        let (per_unit, unit_size) = if erase == 0 {
+            (1, slot)
+        } else {
+            (erase / slot, erase)
+        };
+        let units = length / unit_size;
+        if per_unit == 0
+            || units < 2
+            || (erase != 0 && start % erase != 0)
+            || start.checked_add(length)? > storage.capacity()
+        {
+            return None;
+        }
+        let mut log = CircularLog {
+            storage,
+            start,
+            slots: units * per_unit,
+            per_unit,
+            unit_size,
+            head: 0,
+            sequence: 0,
+            count: 0,
+        };
+        log.recover();
+        Some(log)
+    }
+
+    /// Gives back the storage.
+    pub fn release(self) -> S {
+        self.storage
+    }
+
+    /// Returns the number of records the log can hold.
+    pub fn capacity(&self) -> u32 {
+        self.slots
+    }
+
+    /// Returns the number of records in the log.
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns true if the log holds no record.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Appends a record, dropping the oldest ones if the log is full.
+    /// # Arguments
+    /// * `record` - a reference to `[u8; N]`, the record.
+    /// # Returns
+    /// * `a boolean` - false if the storage could not be written.
+    pub fn append(&mut self, record: &[u8; N]) -> bool {
+        let address = self.address(self.head);
+        if self.head % self.per_unit == 0 {
+            // The slots about to be reused hold the oldest records.
+            if self.storage.erase_size() != 0 && self.storage.erase(address).is_err() {
+                return false;
+            }
+            self.count = core::cmp::min(self.count, self.slots - self.per_unit);
+        }
+        let sequence = self.sequence.to_le_bytes();
+        let crc = crc16_ccitt_update(crc16_ccitt(&sequence), record);
+        // The CRC goes last, so that an interrupted append leaves an invalid slot.
+        let written = self
+            .storage
+            .write(address, &sequence)
+            .and_then(|_| self.storage.write(address + 4, record))
+            .and_then(|_| {
+                self.storage
+                    .write(address + 4 + N as u32, &crc.to_le_bytes())
+            })
+            .is_ok();
+        if written {
+            self.head = (self.head + 1) % self.slots;
+            self.sequence = self.sequence.wrapping_add(1);
+            self.count += 1;
+        }
+        written
+    }
+
+    /// Reads a record.
+    /// # Arguments
+    /// * `index` - a u32, 0 for the oldest record.
+    /// # Returns
+    /// * `a Option<[u8; N]>` - `None` if there is no such record or it cannot be read.
+    pub fn get(&mut self, index: u32) -> Option<[u8; N]> {
+        if index >= self.count {
+            return None;
+        }
+        let slot = (self.head + self.slots - self.count + index) % self.slots;
+        self.read_slot(slot).map(|(_, record)| record)
+    }
+
+    /// Reads the newest record.
+    pub fn latest(&mut self) -> Option<[u8; N]> {
+        self.count.checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// Finds the newest record and counts the consecutive ones before it.
+    fn recover(&mut self) {
+        let mut newest: Option<(u32, u32)> = None;
+        for slot in 0..self.slots {
+            if let Some((sequence, _)) = self.read_slot(slot) {
+                // Sequence numbers are compared as a distance, so that they may wrap.
+                let newer = match newest {
+                    None => true,
+                    Some((_, best)) => (sequence.wrapping_sub(best) as i32) > 0,
+                };
+                if newer {
+                    newest = Some((slot, sequence));
+                }
+            }
+        }
+        let (slot, sequence) = match newest {
+            Some(newest) => newest,
+            None => return,
+        };
+        self.head = (slot + 1) % self.slots;
+        self.sequence = sequence.wrapping_add(1);
+        self.count = 1;
+        while self.count < self.slots {
+            let previous = (slot + self.slots - self.count) % self.slots;
+            match self.read_slot(previous) {
+                Some((found, _)) if found == sequence.wrapping_sub(self.count) => self.count += 1,
+                _ => break,
+            }
+        }
+    }
+
+    /// Reads a slot and checks its CRC.
+    /// # Returns
+    /// * `a Option<(u32, [u8; N])>` - The sequence number and the record, `None` if the slot is not valid.
+    fn read_slot(&mut self, slot: u32) -> Option<(u32, [u8; N])> {
+        let address = self.address(slot);
+        let mut sequence = [0; 4];
+        let mut record = [0; N];
+        let mut crc = [0; 2];
+        self.storage.read(address, &mut sequence).ok()?;
+        self.storage.read(address + 4, &mut record).ok()?;
+        self.storage.read(address + 4 + N as u32, &mut crc).ok()?;
+        if crc16_ccitt_update(crc16_ccitt(&sequence), &record) != u16::from_le_bytes(crc) {
+            return None;
+        }
+        Some((u32::from_le_bytes(sequence), record))
+    }
+
+    /// Returns the address of a slot.
+    fn address(&self, slot: u32) -> u32 {
+        let slot_size = N as u32 + LOG_SLOT_OVERHEAD;
+        self.start + (slot / self.per_unit) * self.unit_size + (slot % self.per_unit) * slot_size
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Result;
+
+    /// A memory of 256 bytes, erased in areas of 64 bytes if `erase` is set.
+    struct Memory {
+        data: [u8; 256],
+        erase: bool,
+    }
+
+    impl Storage for Memory {
+        fn capacity(&self) -> u32 {
+            256
+        }
+
+        fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+            let address = address as usize;
+            data.copy_from_slice(&self.data[address..address + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+            let address = address as usize;
+            for (cell, byte) in self.data[address..].iter_mut().zip(data) {
+                // Flash can only clear bits.
+                *cell = if self.erase { *cell & *byte } else { *byte };
+            }
+            Ok(())
+        }
+
+        fn erase_size(&self) -> u32 {
+            if self.erase {
+                64
+            } else {
+                0
+            }
+        }
+
+        fn erase(&mut self, address: u32) -> Result<()> {
+            let start = (address - address % 64) as usize;
+            self.data[start..start + 64]
+                .iter_mut()
+                .for_each(|byte| *byte = 0xFF);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn wraps_and_recovers_in_place() {
+        let memory = Memory {
+            data: [0; 256],
+            erase: false,
+        };
+        // Slots of 10 bytes, 10 of them in 100 bytes.
+        let mut log: CircularLog<_, 4> = CircularLog::new(memory, 16, 100).unwrap();
+        assert!(log.is_empty());
+        for i in 0..13u32 {
+            assert!(log.append(&i.to_le_bytes()));
+        }
+        assert_eq!(log.len(), 10);
+        assert_eq!(log.get(0), Some(3u32.to_le_bytes()));
+
+        let log: CircularLog<_, 4> = CircularLog::new(log.release(), 16, 100).unwrap();
+        let mut log = log;
+        assert_eq!(log.len(), 10);
+        assert_eq!(log.get(0), Some(3u32.to_le_bytes()));
+        assert_eq!(log.latest(), Some(12u32.to_le_bytes()));
+        assert!(log.append(&13u32.to_le_bytes()));
+        assert_eq!(log.get(9), Some(13u32.to_le_bytes()));
+    }
+
+    #[test]
+    fn drops_erase_areas_on_flash() {
+        let memory = Memory {
+            data: [0xFF; 256],
+            erase: true,
+        };
+        // Six slots of 10 bytes in each of the 4 areas of 64 bytes.
+        let mut log: CircularLog<_, 4> = CircularLog::new(memory, 0, 256).unwrap();
+        assert_eq!(log.capacity(), 24);
+        for i in 0..25u32 {
+            assert!(log.append(&i.to_le_bytes()));
+        }
+        // The 25th record erased the first area and the six oldest records with it.
+        assert_eq!(log.len(), 19);
+        assert_eq!(log.get(0), Some(6u32.to_le_bytes()));
+
+        let mut log: CircularLog<_, 4> = CircularLog::new(log.release(), 0, 256).unwrap();
+        assert_eq!(log.len(), 19);
+        assert_eq!(log.get(0), Some(6u32.to_le_bytes()));
+        assert_eq!(log.latest(), Some(24u32.to_le_bytes()));
+    }
+}
@@ -0,0 +1,651 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! A minimal FAT16 and FAT32 filesystem on a `BlockDevice`.
+//! Files with 8.3 names in the root directory can be opened, read, created
+//! and appended to. Appended data is written to the device before the size in
+//! the directory entry is updated, so a power loss never leaves a file
+//! pointing to unwritten blocks. Subdirectories and long names are not supported.
+
+use super::{BlockDevice, BLOCK_SIZE};
+use crate::{Error, Result};
+
+/// Size of a directory entry in bytes.
+const ENTRY_SIZE: usize = 32;
+/// First byte of a deleted directory entry.
+const ENTRY_DELETED: u8 = 0xE5;
+/// Attribute bits of volume labels and directories, also set by long name entries.
+const ATTR_VOLUME_DIRECTORY: u8 = 0x18;
+/// Attribute of a newly created file.
+const ATTR_ARCHIVE: u8 = 0x20;
+/// 1980-01-01, the date given to new files as there is no clock to read.
+const DEFAULT_DATE: u16 = 0x0021;
+
+/// Partition types of FAT16 and FAT32 volumes in a master boot record.
+const PARTITION_TYPES: [u8; 5] = [0x04, 0x06, 0x0E, 0x0B, 0x0C];
+
+/// The variants of FAT which can be mounted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FatType {
+    Fat16,
+    Fat32,
+}
+
+/// A file of the root directory, as returned by `FatVolume::open` and `FatVolume::create`.
+/// # Elements
+/// * `entry_block` - the block holding the directory entry of the file.
+/// * `entry_offset` - the position of the directory entry in its block.
+/// * `first_cluster` - the first cluster of the data, 0 for an empty file.
+/// * `size` - the size of the file in bytes.
+/// * `position` - the position of the next byte to be read.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct File {
+    entry_block: u32,
+    entry_offset: usize,
+    first_cluster: u32,
+    size: u32,
+    position: u32,
+}
+
+impl File {
+    /// Returns the size of the file in bytes.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the position of the next byte to be read.
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// Moves the read position, at most to the end of the file.
+    /// # Arguments
+    /// * `position` - a u32, the new position in bytes from the start of the file.
+    pub fn seek(&mut self, position: u32) {
+        self.position = core::cmp::min(position, self.size);
+    }
+}
+
+/// A mounted FAT volume.
+/// # Elements
+/// * `device` - the block device holding the volume.
+/// * `buffer` - a copy of one block of the device.
+/// * `cached` - the number of the block in `buffer`, `None` if it holds nothing valid.
+/// * `fat_type` - the variant of FAT.
+/// * `cluster_blocks` - the number of blocks in a cluster.
+/// * `fat_start` - the first block of the first allocation table.
+/// * `fat_blocks` - the number of blocks of one allocation table.
+/// * `fat_count` - the number of copies of the allocation table.
+/// * `root_start` - the first block of the FAT16 root directory.
+/// * `root_blocks` - the number of blocks of the FAT16 root directory.
+/// * `root_cluster` - the first cluster of the FAT32 root directory.
+/// * `data_start` - the first block of cluster 2.
+/// * `clusters` - the number of data clusters.
+/// * `next_free` - the cluster where the search for a free one starts.
+pub struct FatVolume<B> {
+    device: B,
+    buffer: [u8; BLOCK_SIZE],
+    cached: Option<u32>,
+    fat_type: FatType,
+    cluster_blocks: u32,
+    fat_start: u32,
+    fat_blocks: u32,
+    fat_count: u32,
+    root_start: u32,
+    root_blocks: u32,
+    root_cluster: u32,
+    data_start: u32,
+    clusters: u32,
+    next_free: u32,
+}
+
+impl<B: BlockDevice> FatVolume<B> {
+    /// Mounts the volume found on the device.
+    /// The device may hold a single volume or a master boot record, in which case
+    /// the first FAT16 or FAT32 partition is used.
+    /// # Arguments
+    /// * `device` - the block device, for example an initialised `SdCard`.
+    /// # Returns
+    /// * `a Result<FatVolume>` - The volume, `Corrupted` if no valid boot sector is found
+    ///   and `InvalidMode` for FAT12 or sector sizes other than 512 bytes.
+    pub fn mount(mut device: B) -> Result<FatVolume<B>> {
+        let mut buffer = [0; BLOCK_SIZE];
+        device.read_block(0, &mut buffer)?;
+        if !has_signature(&buffer) {
+            return Err(Error::Corrupted);
+        }
+        let mut start = 0;
+        if !is_boot_sector(&buffer) {
+            start = (0..4)
+                .map(|i| 0x1BE + i * 16)
+                .find(|&entry| PARTITION_TYPES.contains(&buffer[entry + 4]))
+                .map(|entry| u32_at(&buffer, entry + 8))
+                .ok_or(Error::InvalidMode)?;
+            device.read_block(start, &mut buffer)?;
+            if !has_signature(&buffer) || !is_boot_sector(&buffer) {
+                return Err(Error::Corrupted);
+            }
+        }
+
+        let cluster_blocks = buffer[0x0D] as u32;
+        let reserved = u16_at(&buffer, 0x0E) as u32;
+        let fat_count = buffer[0x10] as u32;
+        let root_entries = u16_at(&buffer, 0x11) as u32;
+        let total = match u16_at(&buffer, 0x13) {
+            0 => u32_at(&buffer, 0x20),
+            blocks => blocks as u32,
+        };
+        let fat_blocks = match u16_at(&buffer, 0x16) {
+            0 => u32_at(&buffer, 0x24),
+            blocks => blocks as u32,
+        };
+        let root_blocks =
+            (root_entries * ENTRY_SIZE as u32 + BLOCK_SIZE as u32 - 1) / BLOCK_SIZE as u32;
+        let system = reserved + fat_count * fat_blocks + root_blocks;
+        if fat_count == 0 || total <= system {
+            return Err(Error::Corrupted);
+        }
+        let clusters = (total - system) / cluster_blocks;
+        // The variant is decided by the number of clusters alone.
+        let fat_type = if clusters < 4085 {
+            return Err(Error::InvalidMode);
+        } else if clusters < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        Ok(FatVolume {
+            device,
+            buffer,
+            cached: Some(start),
+            fat_type,
+            cluster_blocks,
+            fat_start: start + reserved,
+            fat_blocks,
+            fat_count,
+            root_start: start + reserved + fat_count * fat_blocks,
+            root_blocks,
+            root_cluster: u32_at(&buffer, 0x2C),
+            data_start: start + system,
+            clusters,
+            next_free: 2,
+        })
+    }
+
+    /// Gives back the block device.
+    pub fn release(self) -> B {
+        self.device
+    }
+
+    /// Returns the variant of FAT of the volume.
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    /// Opens a file of the root directory.
+    /// # Arguments
+    /// * `name` - a string slice, the 8.3 name of the file, like `"LOG.CSV"`, in any case.
+    /// # Returns
+    /// * `a Result<File>` - The file positioned at its start, `NotFound` if it does not
+    ///   exist and `InvalidArgument` if the name is not a valid 8.3 name.
+    pub fn open(&mut self, name: &str) -> Result<File> {
+        let name = short_name(name)?;
+        match self.find(&name)? {
+            (Some((block, offset)), _) => self.file_at(block, offset),
+            (None, _) => Err(Error::NotFound),
+        }
+    }
+
+    /// Opens a file of the root directory, creating it empty if it does not exist.
+    /// # Arguments
+    /// * `name` - a string slice, the 8.3 name of the file, like `"LOG.CSV"`, in any case.
+    /// # Returns
+    /// * `a Result<File>` - The file positioned at its start, `StorageFull` if the root
+    ///   directory has no free entry and `InvalidArgument` if the name is not a valid 8.3 name.
+    pub fn create(&mut self, name: &str) -> Result<File> {
+        let name = short_name(name)?;
+        let (block, offset) = match self.find(&name)? {
+            (Some((block, offset)), _) => return self.file_at(block, offset),
+            (None, Some(free)) => free,
+            (None, None) => return Err(Error::StorageFull),
+        };
+        self.load(block)?;
+        let entry = &mut self.buffer[offset..offset + ENTRY_SIZE];
+        entry.iter_mut().for_each(|byte| *byte = 0);
+        entry[..11].copy_from_slice(&name);
+        entry[11] = ATTR_ARCHIVE;
+        for &field in &[16, 18, 24] {
+            entry[field..field + 2].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+        }
+        self.store()?;
+        self.file_at(block, offset)
+    }
+
+    /// Reads from the current position of a file and advances it.
+    /// # Arguments
+    /// * `file` - a mutable reference to a `File` of this volume.
+    /// * `data` - a mutable reference to `[u8]`, filled with the bytes read.
+    /// # Returns
+    /// * `a Result<usize>` - The number of bytes read, 0 at the end of the file.
+    pub fn read(&mut self, file: &mut File, data: &mut [u8]) -> Result<usize> {
+        let count = core::cmp::min(data.len(), (file.size - file.position) as usize);
+        if count == 0 {
+            return Ok(0);
+        }
+        let cluster_size = self.cluster_size();
+        let mut cluster = self.cluster_at(file.first_cluster, file.position / cluster_size)?;
+        let mut done = 0;
+        while done < count {
+            let in_cluster = file.position % cluster_size;
+            if in_cluster == 0 && done > 0 {
+                cluster = self.next_cluster(cluster)?.ok_or(Error::Corrupted)?;
+            }
+            let offset = file.position as usize % BLOCK_SIZE;
+            let length = core::cmp::min(BLOCK_SIZE - offset, count - done);
+            self.load(self.cluster_block(cluster) + in_cluster / BLOCK_SIZE as u32)?;
+            data[done..done + length].copy_from_slice(&self.buffer[offset..offset + length]);
+            done += length;
+            file.position += length as u32;
+        }
+        Ok(done)
+    }
+
+    /// Writes bytes at the end of a file, allocating clusters as needed.
+    /// The read position is not changed.
+    /// # Arguments
+    /// * `file` - a mutable reference to a `File` of this volume.
+    /// * `data` - a reference to `[u8]`, the bytes to be appended.
+    /// # Returns
+    /// * `a Result<()>` - `StorageFull` if no free cluster is left, in which case
+    ///   the bytes which fitted are kept.
+    pub fn append(&mut self, file: &mut File, data: &[u8]) -> Result<()> {
+        let cluster_size = self.cluster_size();
+        let mut cluster = if file.first_cluster == 0 {
+            0
+        } else {
+            let last = file.size.saturating_sub(1) / cluster_size;
+            self.cluster_at(file.first_cluster, last)?
+        };
+        let mut done = 0;
+        let mut result = Ok(());
+        while done < data.len() {
+            if cluster == 0 || (file.size > 0 && file.size % cluster_size == 0) {
+                cluster = match self.next_or_allocate(cluster) {
+                    Ok(next) => next,
+                    Err(error) => {
+                        result = Err(error);
+                        break;
+                    }
+                };
+                if file.first_cluster == 0 {
+                    file.first_cluster = cluster;
+                }
+            }
+            let offset = file.size as usize % BLOCK_SIZE;
+            let length = core::cmp::min(BLOCK_SIZE - offset, data.len() - done);
+            let block =
+                self.cluster_block(cluster) + (file.size % cluster_size) / BLOCK_SIZE as u32;
+            if offset == 0 {
+                // A block started by this write has no content worth reading.
+                self.buffer = [0; BLOCK_SIZE];
+                self.cached = Some(block);
+            } else {
+                self.load(block)?;
+            }
+            self.buffer[offset..offset + length].copy_from_slice(&data[done..done + length]);
+            self.store()?;
+            done += length;
+            file.size += length as u32;
+        }
+        self.update_entry(file)?;
+        result
+    }
+
+    /// Writes the first cluster and the size of a file to its directory entry.
+    fn update_entry(&mut self, file: &File) -> Result<()> {
+        self.load(file.entry_block)?;
+        let entry = &mut self.buffer[file.entry_offset..file.entry_offset + ENTRY_SIZE];
+        entry[20..22].copy_from_slice(&((file.first_cluster >> 16) as u16).to_le_bytes());
+        entry[26..28].copy_from_slice(&(file.first_cluster as u16).to_le_bytes());
+        entry[28..32].copy_from_slice(&file.size.to_le_bytes());
+        self.store()
+    }
+
+    /// Reads the file described by a directory entry.
+    fn file_at(&mut self, block: u32, offset: usize) -> Result<File> {
+        self.load(block)?;
+        let entry = &self.buffer[offset..offset + ENTRY_SIZE];
+        let high = match self.fat_type {
+            FatType::Fat16 => 0,
+            FatType::Fat32 => u16_at(entry, 20) as u32,
+        };
+        Ok(File {
+            entry_block: block,
+            entry_offset: offset,
+            first_cluster: high << 16 | u16_at(entry, 26) as u32,
+            size: u32_at(entry, 28),
+            position: 0,
+        })
+    }
+
+    /// Searches the root directory for a file.
+    /// # Returns
+    /// * `a Result<(Option<(u32, usize)>, Option<(u32, usize)>)>` - The block and offset
+    ///   of the entry of the file and of the first free entry, if they exist.
+    #[allow(clippy::type_complexity)]
+    fn find(&mut self, name: &[u8; 11]) -> Result<(Option<(u32, usize)>, Option<(u32, usize)>)> {
+        let mut free = None;
+        let mut index = 0;
+        while let Some(block) = self.root_block(index)? {
+            self.load(block)?;
+            for offset in (0..BLOCK_SIZE).step_by(ENTRY_SIZE) {
+                match self.buffer[offset] {
+                    // The end of the directory, all following entries are free.
+                    0 => return Ok((None, free.or(Some((block, offset))))),
+                    ENTRY_DELETED => free = free.or(Some((block, offset))),
+                    _ => {
+                        if self.buffer[offset + 11] & ATTR_VOLUME_DIRECTORY == 0
+                            && self.buffer[offset..offset + 11] == name[..]
+                        {
+                            return Ok((Some((block, offset)), free));
+                        }
+                    }
+                }
+            }
+            index += 1;
+        }
+        Ok((None, free))
+    }
+
+    /// Returns the block of the root directory with the given index, `None` past its end.
+    fn root_block(&mut self, index: u32) -> Result<Option<u32>> {
+        match self.fat_type {
+            FatType::Fat16 if index < self.root_blocks => Ok(Some(self.root_start + index)),
+            FatType::Fat16 => Ok(None),
+            FatType::Fat32 => {
+                let mut cluster = self.root_cluster;
+                for _ in 0..index / self.cluster_blocks {
+                    cluster = match self.next_cluster(cluster)? {
+                        Some(next) => next,
+                        None => return Ok(None),
+                    };
+                }
+                Ok(Some(
+                    self.cluster_block(cluster) + index % self.cluster_blocks,
+                ))
+            }
+        }
+    }
+
+    /// Returns the size of a cluster in bytes.
+    fn cluster_size(&self) -> u32 {
+        self.cluster_blocks * BLOCK_SIZE as u32
+    }
+
+    /// Returns the first block of a cluster.
+    fn cluster_block(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.cluster_blocks
+    }
+
+    /// Follows a cluster chain for `count` steps.
+    fn cluster_at(&mut self, first: u32, count: u32) -> Result<u32> {
+        let mut cluster = first;
+        for _ in 0..count {
+            cluster = self.next_cluster(cluster)?.ok_or(Error::Corrupted)?;
+        }
+        Ok(cluster)
+    }
+
+    /// Returns the cluster following another one in its chain, `None` at the end.
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>> {
+        let next = self.fat_entry(cluster)?;
+        let end = match self.fat_type {
+            FatType::Fat16 => 0xFFF8,
+            FatType::Fat32 => 0x0FFF_FFF8,
+        };
+        if next >= end {
+            Ok(None)
+        } else if next < 2 || next >= self.clusters + 2 {
+            Err(Error::Corrupted)
+        } else {
+            Ok(Some(next))
+        }
+    }
+
+    /// Returns the cluster after `cluster`, allocating one if the chain ends there.
+    /// A `cluster` of 0 allocates the first cluster of a new chain.
+    fn next_or_allocate(&mut self, cluster: u32) -> Result<u32> {
+        if cluster != 0 {
+            if let Some(next) = self.next_cluster(cluster)? {
+                return Ok(next);
+            }
+        }
+        for i in 0..self.clusters {
+            let candidate = 2 + (self.next_free - 2 + i) % self.clusters;
+            if self.fat_entry(candidate)? == 0 {
+                self.set_fat_entry(candidate, 0x0FFF_FFFF)?;
+                if cluster != 0 {
+                    self.set_fat_entry(cluster, candidate)?;
+                }
+                self.next_free = candidate;
+                return Ok(candidate);
+            }
+        }
+        Err(Error::StorageFull)
+    }
+
+    /// Returns the block, relative to the table, and the offset of an allocation table entry.
+    fn fat_position(&self, cluster: u32) -> (u32, usize) {
+        let offset = match self.fat_type {
+            FatType::Fat16 => cluster * 2,
+            FatType::Fat32 => cluster * 4,
+        };
+        (
+            offset / BLOCK_SIZE as u32,
+            (offset % BLOCK_SIZE as u32) as usize,
+        )
+    }
+
+    /// Reads the allocation table entry of a cluster.
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32> {
+        let (block, offset) = self.fat_position(cluster);
+        self.load(self.fat_start + block)?;
+        Ok(match self.fat_type {
+            FatType::Fat16 => u16_at(&self.buffer, offset) as u32,
+            FatType::Fat32 => u32_at(&self.buffer, offset) & 0x0FFF_FFFF,
+        })
+    }
+
+    /// Writes the allocation table entry of a cluster in every copy of the table.
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<()> {
+        let (block, offset) = self.fat_position(cluster);
+        for copy in 0..self.fat_count {
+            self.load(self.fat_start + copy * self.fat_blocks + block)?;
+            match self.fat_type {
+                FatType::Fat16 => {
+                    self.buffer[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes())
+                }
+                FatType::Fat32 => {
+                    // The upper four bits are reserved and kept as they are.
+                    let value =
+                        (u32_at(&self.buffer, offset) & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                    self.buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            self.store()?;
+        }
+        Ok(())
+    }
+
+    /// Reads a block into the buffer, unless it is already there.
+    fn load(&mut self, block: u32) -> Result<()> {
+        if self.cached != Some(block) {
+            self.cached = None;
+            self.device.read_block(block, &mut self.buffer)?;
+            self.cached = Some(block);
+        }
+        Ok(())
+    }
+
+    /// Writes the buffer back to its block.
+    fn store(&mut self) -> Result<()> {
+        match self.cached {
+            Some(block) => self.device.write_block(block, &self.buffer),
+            None => Err(Error::Corrupted),
+        }
+    }
+}
+
+/// Converts a file name to the padded upper case form stored in directory entries.
+/// # Arguments
+/// * `name` - a string slice, the name like `"data.txt"`.
+/// # Returns
+/// * `a Result<[u8; 11]>` - The name like `b"DATA    TXT"`, `InvalidArgument` if the name
+///   has more than 8 characters, an extension of more than 3 or a character not allowed.
+pub fn short_name(name: &str) -> Result<[u8; 11]> {
+    let (base, extension) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return Err(Error::InvalidArgument);
+    }
+    let mut result = [b' '; 11];
+    let parts = base.bytes().zip(0..8).chain(extension.bytes().zip(8..11));
+    for (byte, index) in parts {
+        if !(byte.is_ascii_alphanumeric() || b"_-~!#$%&'()@^{}".contains(&byte)) {
+            return Err(Error::InvalidArgument);
+        }
+        result[index] = byte.to_ascii_uppercase();
+    }
+    Ok(result)
+}
+
+/// Returns true if a block ends with the boot signature.
+fn has_signature(block: &[u8; BLOCK_SIZE]) -> bool {
+    block[510] == 0x55 && block[511] == 0xAA
+}
+
+/// Returns true if a block looks like a FAT boot sector rather than a master boot record.
+fn is_boot_sector(block: &[u8; BLOCK_SIZE]) -> bool {
+    (block[0] == 0xEB || block[0] == 0xE9)
+        && u16_at(block, 0x0B) as usize == BLOCK_SIZE
+        && block[0x0D].is_power_of_two()
+}
+
+/// Reads a little endian u16.
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Reads a little endian u32.
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// Blocks of a RAM disk holding a FAT16 volume of 4200 clusters of one block.
+    const BLOCKS: u32 = 4400;
+
+    struct RamDisk(Vec<[u8; BLOCK_SIZE]>);
+
+    impl BlockDevice for RamDisk {
+        fn block_count(&self) -> u32 {
+            self.0.len() as u32
+        }
+
+        fn read_block(&mut self, block: u32, data: &mut [u8; BLOCK_SIZE]) -> Result<()> {
+            *data = self.0[block as usize];
+            Ok(())
+        }
+
+        fn write_block(&mut self, block: u32, data: &[u8; BLOCK_SIZE]) -> Result<()> {
+            self.0[block as usize] = *data;
+            Ok(())
+        }
+    }
+
+    /// Formats a RAM disk with one reserved block, two tables of 17 blocks and 512 root entries.
+    fn formatted() -> RamDisk {
+        let mut disk = RamDisk(vec![[0; BLOCK_SIZE]; BLOCKS as usize]);
+        let boot = &mut disk.0[0];
+        boot[0] = 0xEB;
+        boot[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+        boot[0x0D] = 1;
+        boot[0x0E..0x10].copy_from_slice(&1u16.to_le_bytes());
+        boot[0x10] = 2;
+        boot[0x11..0x13].copy_from_slice(&512u16.to_le_bytes());
+        boot[0x13..0x15].copy_from_slice(&(BLOCKS as u16).to_le_bytes());
+        boot[0x16..0x18].copy_from_slice(&17u16.to_le_bytes());
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+        for fat in &[1, 18] {
+            disk.0[*fat][..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+        }
+        disk
+    }
+
+    #[test]
+    fn short_names() {
+        assert_eq!(short_name("log.csv"), Ok(*b"LOG     CSV"));
+        assert_eq!(short_name("README"), Ok(*b"README     "));
+        assert_eq!(short_name("toolongname.txt"), Err(Error::InvalidArgument));
+        assert_eq!(short_name("a.text"), Err(Error::InvalidArgument));
+        assert_eq!(short_name("a b.txt"), Err(Error::InvalidArgument));
+        assert_eq!(short_name(".txt"), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn create_append_and_read() {
+        let mut volume = FatVolume::mount(formatted()).unwrap();
+        assert_eq!(volume.fat_type(), FatType::Fat16);
+        assert_eq!(volume.open("data.bin"), Err(Error::NotFound));
+
+        let mut file = volume.create("data.bin").unwrap();
+        let data: Vec<u8> = (0..1500u32).map(|i| i as u8).collect();
+        volume.append(&mut file, &data[..700]).unwrap();
+        volume.append(&mut file, &data[700..]).unwrap();
+        assert_eq!(file.size(), 1500);
+
+        let disk = volume.release();
+        let mut volume = FatVolume::mount(disk).unwrap();
+        let mut file = volume.open("DATA.BIN").unwrap();
+        assert_eq!(file.size(), 1500);
+        let mut read = vec![0; 2000];
+        assert_eq!(volume.read(&mut file, &mut read[..1000]), Ok(1000));
+        assert_eq!(volume.read(&mut file, &mut read[1000..]), Ok(500));
+        assert_eq!(volume.read(&mut file, &mut read[1500..]), Ok(0));
+        assert_eq!(&read[..1500], &data[..]);
+
+        // Both copies of the table hold the chain 2 -> 3 -> 4.
+        let disk = volume.release();
+        for fat in &[1, 18] {
+            assert_eq!(&disk.0[*fat][4..10], &[3, 0, 4, 0, 0xFF, 0xFF]);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Fujitsu MB85RC serial ferroelectric RAM over I2C.
+//! FRAM is written in place without erasing, without write delays and with an
+//! endurance of 10^12 cycles, so it suits data rewritten very often, like
+//! counters, settings or random seeds, much better than EEPROM.
+//! Parts with two address bytes, from MB85RC64 to MB85RC1M, are supported.
+//! The driver is generic over the embedded-hal I2C trait, use `&I2cBus` on the chips of this crate.
+
+use super::Storage;
+use crate::{Error, Result};
+use embedded_hal::i2c::{I2c, Operation};
+
+/// Base address of the memories, the pins A2 to A0 are added to it.
+const FRAM_ADDRESS: u8 = 0x50;
+/// Reserved address answering the device ID of a memory.
+const DEVICE_ID_ADDRESS: u8 = 0x7C;
+
+/// A MB85RC memory.
+/// # Elements
+/// * `i2c` - the I2C bus to which the memory is attached.
+/// * `address` - a u8, the address of the memory on the bus.
+/// * `capacity` - a u32, the size of the memory in bytes.
+pub struct MB85RC<I2C> {
+    i2c: I2C,
+    address: u8,
+    capacity: u32,
+}
+
+impl<I2C: I2c> MB85RC<I2C> {
+    /// Creates the driver and reads the size of the memory from its device ID.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the memory is attached, for example `&I2cBus`.
+    /// * `pins` - a u8, the levels of the pins A2, A1 and A0 as bits 2 to 0.
+    /// # Returns
+    /// * `a Result<MB85RC>` - The driver, `InvalidMode` if the memory has no supported device ID.
+    pub fn new(i2c: I2C, pins: u8) -> Result<MB85RC<I2C>> {
+        let mut fram = MB85RC::with_capacity(i2c, pins, 0);
+        let (_, product) = fram.device_id()?;
+        let density = (product >> 8) as u32 & 0x0F;
+        if !(3..=7).contains(&density) {
+            return Err(Error::InvalidMode);
+        }
+        fram.capacity = 1 << (density + 10);
+        Ok(fram)
+    }
+
+    /// Creates the driver for a memory of known size, for the parts without a device ID.
+    /// # Arguments
+    /// * `i2c` - the I2C bus to which the memory is attached.
+    /// * `pins` - a u8, the levels of the pins A2, A1 and A0 as bits 2 to 0.
+    /// * `capacity` - a u32, the size of the memory in bytes.
+    /// # Returns
+    /// * `a MB85RC object` - The driver.
+    pub fn with_capacity(i2c: I2C, pins: u8, capacity: u32) -> MB85RC<I2C> {
+        MB85RC {
+            i2c,
+            address: FRAM_ADDRESS | (pins & 0x07),
+            capacity,
+        }
+    }
+
+    /// Gives back the bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Returns the size of the memory in bytes.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Reads the device ID of the memory.
+    /// # Returns
+    /// * `a Result<(u16, u16)>` - The manufacturer, 0x00A for Fujitsu, and the product ID,
+    ///   whose bits 11 to 8 hold the density.
+    pub fn device_id(&mut self) -> Result<(u16, u16)> {
+        let mut id = [0; 3];
+        self.i2c
+            .write_read(DEVICE_ID_ADDRESS, &[self.address << 1], &mut id)
+            .map_err(Error::from_i2c)?;
+        let manufacturer = (id[0] as u16) << 4 | (id[1] as u16) >> 4;
+        let product = (id[1] as u16 & 0x0F) << 8 | id[2] as u16;
+        Ok((manufacturer, product))
+    }
+
+    /// Reads consecutive bytes.
+    /// # Arguments
+    /// * `address` - a u32, the address of the first byte.
+    /// * `data` - a mutable reference to `[u8]`, filled with the bytes read.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if the range is past the end of the memory.
+    pub fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+        self.check_range(address, data.len() as u32)?;
+        let mut done = 0;
+        for chunk in Self::split(address, data.len()).map(|length| length as usize) {
+            let (device, a) = self.select(address + done as u32);
+            self.i2c
+                .write_read(device, &a, &mut data[done..done + chunk])
+                .map_err(Error::from_i2c)?;
+            done += chunk;
+        }
+        Ok(())
+    }
+
+    /// Writes consecutive bytes, which are stored as soon as they are received.
+    /// # Arguments
+    /// * `address` - a u32, the address of the first byte.
+    /// * `data` - a reference to `[u8]`, the bytes to be stored.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if the range is past the end of the memory.
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.check_range(address, data.len() as u32)?;
+        let mut done = 0;
+        for chunk in Self::split(address, data.len()).map(|length| length as usize) {
+            let (device, a) = self.select(address + done as u32);
+            self.i2c
+                .transaction(
+                    device,
+                    &mut [
+                        Operation::Write(&a),
+                        Operation::Write(&data[done..done + chunk]),
+                    ],
+                )
+                .map_err(Error::from_i2c)?;
+            done += chunk;
+        }
+        Ok(())
+    }
+
+    /// Returns the bus address and the address bytes of a memory address.
+    /// The 17th address bit of the MB85RC1M takes the place of pin A0.
+    fn select(&self, address: u32) -> (u8, [u8; 2]) {
+        let device = self.address | (address >> 16) as u8 & 0x01;
+        (device, [(address >> 8) as u8, address as u8])
+    }
+
+    /// Splits a range into the lengths of its parts in each 64 KiB bank.
+    fn split(address: u32, length: usize) -> impl Iterator<Item = u32> {
+        let end = address + length as u32;
+        let mut start = address;
+        core::iter::from_fn(move || {
+            if start >= end {
+                return None;
+            }
+            let next = core::cmp::min(end, (start & !0xFFFF) + 0x1_0000);
+            let length = next - start;
+            start = next;
+            Some(length)
+        })
+    }
+
+    /// Checks that a range of bytes lies in the memory.
+    fn check_range(&self, address: u32, length: u32) -> Result<()> {
+        match address.checked_add(length) {
+            Some(end) if end <= self.capacity => Ok(()),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+impl<I2C: I2c> Storage for MB85RC<I2C> {
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+        MB85RC::read(self, address, data)
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        MB85RC::write(self, address, data)
+    }
+}
@@ -0,0 +1,160 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Non volatile storage devices and the facilities built on top of them.
+//! Every device implements the `Storage` trait, so the same settings or
+//! logging code can run on the internal EEPROM or on external memories.
+//! Memories read and written in whole blocks, like SD cards, implement
+//! `BlockDevice` instead and are used through the FAT filesystem.
+
+mod circular_log;
+mod fat;
+mod fram;
+mod sdcard;
+mod settings;
+mod w25q;
+
+pub use circular_log::*;
+pub use fat::*;
+pub use fram::*;
+pub use sdcard::*;
+pub use settings::*;
+pub use w25q::*;
+
+/// Size in bytes of the blocks of a `BlockDevice`.
+pub const BLOCK_SIZE: usize = 512;
+
+/// A memory read and written in blocks of `BLOCK_SIZE` bytes.
+pub trait BlockDevice {
+    /// Returns the number of blocks of the memory.
+    fn block_count(&self) -> u32;
+
+    /// Reads a block.
+    /// # Arguments
+    /// * `block` - a u32, the number of the block.
+    /// * `data` - a mutable reference to `[u8; BLOCK_SIZE]`, filled with the block.
+    /// # Returns
+    /// * `a Result<()>` - The error of the device if the read failed.
+    fn read_block(&mut self, block: u32, data: &mut [u8; BLOCK_SIZE]) -> crate::Result<()>;
+
+    /// Writes a block.
+    /// # Arguments
+    /// * `block` - a u32, the number of the block.
+    /// * `data` - a reference to `[u8; BLOCK_SIZE]`, the new content of the block.
+    /// # Returns
+    /// * `a Result<()>` - The error of the device if the write failed.
+    fn write_block(&mut self, block: u32, data: &[u8; BLOCK_SIZE]) -> crate::Result<()>;
+}
+
+impl<B: BlockDevice + ?Sized> BlockDevice for &mut B {
+    fn block_count(&self) -> u32 {
+        (**self).block_count()
+    }
+
+    fn read_block(&mut self, block: u32, data: &mut [u8; BLOCK_SIZE]) -> crate::Result<()> {
+        (**self).read_block(block, data)
+    }
+
+    fn write_block(&mut self, block: u32, data: &[u8; BLOCK_SIZE]) -> crate::Result<()> {
+        (**self).write_block(block, data)
+    }
+}
+
+/// A byte addressable non volatile memory.
+pub trait Storage {
+    /// Returns the size of the memory in bytes.
+    fn capacity(&self) -> u32;
+
+    /// Reads consecutive bytes.
+    /// # Arguments
+    /// * `address` - a u32, the address of the first byte.
+    /// * `data` - a mutable reference to `[u8]`, filled with the bytes read.
+    /// # Returns
+    /// * `a Result<()>` - The error of the memory, `InvalidArgument` if out of range.
+    fn read(&mut self, address: u32, data: &mut [u8]) -> crate::Result<()>;
+
+    /// Writes consecutive bytes.
+    /// # Arguments
+    /// * `address` - a u32, the address of the first byte.
+    /// * `data` - a reference to `[u8]`, the bytes to be stored.
+    /// # Returns
+    /// * `a Result<()>` - The error of the memory, `InvalidArgument` if out of range.
+    fn write(&mut self, address: u32, data: &[u8]) -> crate::Result<()>;
+
+    /// Returns the size of the areas which have to be erased before they are
+    /// written again, 0 for memories written in place like EEPROM.
+    fn erase_size(&self) -> u32 {
+        0
+    }
+
+    /// Erases the area of `erase_size` bytes holding an address.
+    /// # Arguments
+    /// * `address` - a u32, any address in the area.
+    /// # Returns
+    /// * `a Result<()>` - The error of the memory if the erase failed.
+    fn erase(&mut self, address: u32) -> crate::Result<()> {
+        let _ = address;
+        Ok(())
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for &mut S {
+    fn capacity(&self) -> u32 {
+        (**self).capacity()
+    }
+
+    fn read(&mut self, address: u32, data: &mut [u8]) -> crate::Result<()> {
+        (**self).read(address, data)
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> crate::Result<()> {
+        (**self).write(address, data)
+    }
+
+    fn erase_size(&self) -> u32 {
+        (**self).erase_size()
+    }
+
+    fn erase(&mut self, address: u32) -> crate::Result<()> {
+        (**self).erase(address)
+    }
+}
+
+#[cfg(any(feature = "atmega328p", feature = "atmega2560p"))]
+impl Storage for crate::hal::eeprom::Eeprom {
+    fn capacity(&self) -> u32 {
+        crate::hal::eeprom::EEPROM_SIZE as u32
+    }
+
+    fn read(&mut self, address: u32, data: &mut [u8]) -> crate::Result<()> {
+        if address < self.capacity() && crate::hal::eeprom::Eeprom::read(self, address as u16, data)
+        {
+            Ok(())
+        } else {
+            Err(crate::Error::InvalidArgument)
+        }
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> crate::Result<()> {
+        if address < self.capacity()
+            && crate::hal::eeprom::Eeprom::write(self, address as u16, data)
+        {
+            Ok(())
+        } else {
+            Err(crate::Error::InvalidArgument)
+        }
+    }
+}
@@ -0,0 +1,450 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! SD and MMC cards driven over SPI.
+//! The card is initialised in SPI mode and then read and written in blocks of
+//! 512 bytes with the single block commands CMD17 and CMD24. Both byte
+//! addressed (SDSC) and block addressed (SDHC/SDXC) cards are handled.
+//! See the SD Physical Layer Simplified Specification, section 7.
+
+use super::{BlockDevice, BLOCK_SIZE};
+use crate::encoding::crc::crc7_mmc;
+use crate::{Error, Result};
+use core::convert::Infallible;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+/// Commands used by the driver.
+const CMD0: u8 = 0; // GO_IDLE_STATE
+const CMD8: u8 = 8; // SEND_IF_COND
+const CMD9: u8 = 9; // SEND_CSD
+const CMD16: u8 = 16; // SET_BLOCKLEN
+const CMD17: u8 = 17; // READ_SINGLE_BLOCK
+const CMD24: u8 = 24; // WRITE_BLOCK
+const CMD55: u8 = 55; // APP_CMD
+const CMD58: u8 = 58; // READ_OCR
+const ACMD41: u8 = 41; // SD_SEND_OP_COND
+
+/// Bits of the R1 response.
+const R1_IDLE: u8 = 0x01;
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+
+/// Token starting a data block.
+const DATA_START: u8 = 0xFE;
+/// Mask and value of an accepted data response token.
+const DATA_RESPONSE_MASK: u8 = 0x1F;
+const DATA_ACCEPTED: u8 = 0x05;
+
+/// Time allowed for the card to leave the idle state, in milliseconds.
+const INIT_TIMEOUT: u32 = 1000;
+/// Time allowed for a data block to start, in milliseconds.
+const READ_TIMEOUT: u32 = 100;
+/// Time allowed for the card to finish writing a block, in milliseconds.
+const WRITE_TIMEOUT: u32 = 500;
+
+/// The kinds of cards told apart during initialisation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CardType {
+    /// Version 1 standard capacity card, byte addressed.
+    Sd1,
+    /// Version 2 standard capacity card, byte addressed.
+    Sd2,
+    /// High or extended capacity card, block addressed.
+    Sdhc,
+}
+
+/// A SD card on its own SPI bus.
+/// # Elements
+/// * `spi` - the SPI bus, for example `Spi`, in mode 0.
+/// * `cs` - the chip select pin of the card.
+/// * `delay` - a delay provider used for the timeouts.
+/// * `card_type` - the kind of card found by `init`, `None` before.
+/// * `blocks` - the number of blocks of the card.
+pub struct SdCard<SPI, CS, D> {
+    spi: SPI,
+    cs: CS,
+    delay: D,
+    card_type: Option<CardType>,
+    blocks: u32,
+}
+
+impl<SPI, CS, D> SdCard<SPI, CS, D>
+where
+    SPI: SpiBus<Error = Error>,
+    CS: OutputPin<Error = Infallible>,
+    D: DelayNs,
+{
+    /// Creates the driver and deselects the card.
+    /// `init` has to be called before the card can be used.
+    /// # Arguments
+    /// * `spi` - the SPI bus, which must run in mode 0 and at most at 400 kHz till `init` returns.
+    /// * `cs` - the chip select pin, which must be an output.
+    /// * `delay` - a delay provider, for example `Delay`.
+    /// # Returns
+    /// * `a SdCard object` - The driver.
+    pub fn new(spi: SPI, mut cs: CS, delay: D) -> SdCard<SPI, CS, D> {
+        let _ = cs.set_high();
+        SdCard {
+            spi,
+            cs,
+            delay,
+            card_type: None,
+            blocks: 0,
+        }
+    }
+
+    /// Gives back the bus, the chip select pin and the delay.
+    pub fn release(self) -> (SPI, CS, D) {
+        (self.spi, self.cs, self.delay)
+    }
+
+    /// Gives access to the bus, for example to raise its clock after `init`.
+    pub fn bus(&mut self) -> &mut SPI {
+        &mut self.spi
+    }
+
+    /// Returns the kind of card, `None` if it is not initialised.
+    pub fn card_type(&self) -> Option<CardType> {
+        self.card_type
+    }
+
+    /// Initialises the card in SPI mode and reads its size.
+    /// # Returns
+    /// * `a Result<CardType>` - The kind of card, `Timeout` if it does not respond
+    ///   and `InvalidMode` if it is not a SD card usable at 3.3 V.
+    pub fn init(&mut self) -> Result<CardType> {
+        self.card_type = None;
+        // At least 74 clock cycles with the card deselected put it in SPI mode.
+        let _ = self.cs.set_high();
+        for _ in 0..10 {
+            self.spi.transfer_in_place(&mut [0xFF])?;
+        }
+        let _ = self.cs.set_low();
+        let result = self.identify();
+        self.deselect();
+        let card_type = result?;
+        self.card_type = Some(card_type);
+        self.blocks = self.read_capacity()?;
+        Ok(card_type)
+    }
+
+    /// Runs the identification sequence with the card selected.
+    fn identify(&mut self) -> Result<CardType> {
+        let mut tries = 0;
+        while self.command(CMD0, 0)? != R1_IDLE {
+            tries += 1;
+            if tries == 10 {
+                return Err(Error::Timeout);
+            }
+        }
+
+        let version2 = if self.command(CMD8, 0x1AA)? & R1_ILLEGAL_COMMAND != 0 {
+            false
+        } else {
+            let mut r7 = [0xFF; 4];
+            self.spi.transfer_in_place(&mut r7)?;
+            if r7[2] & 0x0F != 0x01 || r7[3] != 0xAA {
+                return Err(Error::InvalidMode);
+            }
+            true
+        };
+
+        // Ask for high capacity support on version 2 cards.
+        let argument = if version2 { 0x4000_0000 } else { 0 };
+        let mut waited = 0;
+        loop {
+            self.command(CMD55, 0)?;
+            if self.command(ACMD41, argument)? == 0 {
+                break;
+            }
+            if waited == INIT_TIMEOUT {
+                return Err(Error::Timeout);
+            }
+            self.delay.delay_ms(1);
+            waited += 1;
+        }
+
+        if !version2 {
+            self.set_block_length()?;
+            return Ok(CardType::Sd1);
+        }
+        if self.command(CMD58, 0)? != 0 {
+            return Err(Error::InvalidMode);
+        }
+        let mut ocr = [0xFF; 4];
+        self.spi.transfer_in_place(&mut ocr)?;
+        if ocr[0] & 0x40 != 0 {
+            Ok(CardType::Sdhc)
+        } else {
+            self.set_block_length()?;
+            Ok(CardType::Sd2)
+        }
+    }
+
+    /// Sets the block length of byte addressed cards to 512 bytes.
+    fn set_block_length(&mut self) -> Result<()> {
+        if self.command(CMD16, BLOCK_SIZE as u32)? != 0 {
+            return Err(Error::InvalidMode);
+        }
+        Ok(())
+    }
+
+    /// Reads the CSD register and computes the number of blocks from it.
+    fn read_capacity(&mut self) -> Result<u32> {
+        let mut csd = [0; 16];
+        let _ = self.cs.set_low();
+        let result = match self.command(CMD9, 0) {
+            Ok(0) => self.read_data(&mut csd),
+            Ok(_) => Err(Error::InvalidMode),
+            Err(error) => Err(error),
+        };
+        self.deselect();
+        result?;
+        blocks_from_csd(&csd)
+    }
+
+    /// Reads a block of 512 bytes.
+    /// # Arguments
+    /// * `block` - a u32, the number of the block.
+    /// * `data` - a mutable reference to `[u8; 512]`, filled with the block.
+    /// # Returns
+    /// * `a Result<()>` - `NotReady` before `init`, `InvalidArgument` past the end of the card.
+    pub fn read_block(&mut self, block: u32, data: &mut [u8; BLOCK_SIZE]) -> Result<()> {
+        let address = self.address(block)?;
+        let _ = self.cs.set_low();
+        let result = match self.command(CMD17, address) {
+            Ok(0) => self.read_data(data),
+            Ok(_) => Err(Error::BusError),
+            Err(error) => Err(error),
+        };
+        self.deselect();
+        result
+    }
+
+    /// Writes a block of 512 bytes and waits till the card has stored it.
+    /// # Arguments
+    /// * `block` - a u32, the number of the block.
+    /// * `data` - a reference to `[u8; 512]`, the new content of the block.
+    /// # Returns
+    /// * `a Result<()>` - `NotReady` before `init`, `InvalidArgument` past the end of the card
+    ///   and `Crc` if the card rejected the data.
+    pub fn write_block(&mut self, block: u32, data: &[u8; BLOCK_SIZE]) -> Result<()> {
+        let address = self.address(block)?;
+        let _ = self.cs.set_low();
+        let result = match self.command(CMD24, address) {
+            Ok(0) => self.write_data(data),
+            Ok(_) => Err(Error::BusError),
+            Err(error) => Err(error),
+        };
+        self.deselect();
+        result
+    }
+
+    /// Converts a block number to the address the card expects.
+    fn address(&self, block: u32) -> Result<u32> {
+        match self.card_type {
+            None => Err(Error::NotReady),
+            Some(_) if block >= self.blocks => Err(Error::InvalidArgument),
+            Some(CardType::Sdhc) => Ok(block),
+            Some(_) => Ok(block * BLOCK_SIZE as u32),
+        }
+    }
+
+    /// Sends a command and returns its R1 response.
+    /// The card has to be selected.
+    fn command(&mut self, command: u8, argument: u32) -> Result<u8> {
+        // A card which is not reset yet may not release the data line.
+        if command != CMD0 {
+            self.wait_ready(READ_TIMEOUT)?;
+        }
+        self.spi.write(&command_frame(command, argument))?;
+        for _ in 0..10 {
+            let mut response = [0xFF];
+            self.spi.transfer_in_place(&mut response)?;
+            if response[0] & 0x80 == 0 {
+                return Ok(response[0]);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Waits for a data start token and reads the block after it.
+    fn read_data(&mut self, data: &mut [u8]) -> Result<()> {
+        let token = self.wait_for(READ_TIMEOUT, |byte| byte != 0xFF)?;
+        if token != DATA_START {
+            return Err(Error::BusError);
+        }
+        data.iter_mut().for_each(|byte| *byte = 0xFF);
+        self.spi.transfer_in_place(data)?;
+        // The CRC is not checked in SPI mode.
+        self.spi.transfer_in_place(&mut [0xFF; 2])?;
+        Ok(())
+    }
+
+    /// Sends a data block and waits till the card is done programming it.
+    fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        self.spi.write(&[0xFF, DATA_START])?;
+        self.spi.write(data)?;
+        self.spi.write(&[0xFF, 0xFF])?;
+        let mut response = [0xFF];
+        self.spi.transfer_in_place(&mut response)?;
+        if response[0] & DATA_RESPONSE_MASK != DATA_ACCEPTED {
+            return Err(Error::Crc);
+        }
+        self.wait_ready(WRITE_TIMEOUT)
+    }
+
+    /// Waits till the card releases the data line.
+    fn wait_ready(&mut self, timeout: u32) -> Result<()> {
+        self.wait_for(timeout, |byte| byte == 0xFF).map(|_| ())
+    }
+
+    /// Clocks bytes out of the card till one matches `done`.
+    fn wait_for<F: Fn(u8) -> bool>(&mut self, timeout: u32, done: F) -> Result<u8> {
+        for _ in 0..timeout * 10 {
+            let mut byte = [0xFF];
+            self.spi.transfer_in_place(&mut byte)?;
+            if done(byte[0]) {
+                return Ok(byte[0]);
+            }
+            self.delay.delay_us(100);
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Releases the card, with one more byte so that it frees the data line.
+    fn deselect(&mut self) {
+        let _ = self.spi.flush();
+        let _ = self.cs.set_high();
+        let _ = self.spi.transfer_in_place(&mut [0xFF]);
+    }
+}
+
+impl<SPI, CS, D> BlockDevice for SdCard<SPI, CS, D>
+where
+    SPI: SpiBus<Error = Error>,
+    CS: OutputPin<Error = Infallible>,
+    D: DelayNs,
+{
+    fn block_count(&self) -> u32 {
+        self.blocks
+    }
+
+    fn read_block(&mut self, block: u32, data: &mut [u8; BLOCK_SIZE]) -> Result<()> {
+        SdCard::read_block(self, block, data)
+    }
+
+    fn write_block(&mut self, block: u32, data: &[u8; BLOCK_SIZE]) -> Result<()> {
+        SdCard::write_block(self, block, data)
+    }
+}
+
+/// Builds the six bytes of a command with its CRC.
+/// Only CMD0 and CMD8 are checked before CRC is disabled in SPI mode,
+/// but a valid CRC is always sent.
+/// # Arguments
+/// * `command` - a u8, the index of the command.
+/// * `argument` - a u32, the argument of the command.
+/// # Returns
+/// * `a [u8; 6]` - The command frame.
+fn command_frame(command: u8, argument: u32) -> [u8; 6] {
+    let a = argument.to_be_bytes();
+    let mut frame = [0x40 | command, a[0], a[1], a[2], a[3], 0];
+    frame[5] = (crc7_mmc(&frame[..5]) << 1) | 0x01;
+    frame
+}
+
+/// Computes the number of 512 byte blocks from the CSD register.
+/// # Arguments
+/// * `csd` - a reference to `[u8; 16]`, the CSD register.
+/// # Returns
+/// * `a Result<u32>` - The number of blocks, `Corrupted` if the register is not valid.
+fn blocks_from_csd(csd: &[u8; 16]) -> Result<u32> {
+    match csd[0] >> 6 {
+        0 => {
+            let size =
+                ((csd[6] as u32 & 0x03) << 10) | ((csd[7] as u32) << 2) | (csd[8] as u32 >> 6);
+            let multiplier = ((csd[9] as u32 & 0x03) << 1) | (csd[10] as u32 >> 7);
+            // READ_BL_LEN may only be 512, 1024 or 2048 bytes.
+            let block_length = csd[5] as u32 & 0x0F;
+            if !(9..=11).contains(&block_length) {
+                return Err(Error::Corrupted);
+            }
+            Ok((size + 1) << (multiplier + 2 + block_length - 9))
+        }
+        1 => {
+            // CSD version 2, the size is counted in units of 512 KiB.
+            let size = ((csd[7] as u32 & 0x3F) << 16) | ((csd[8] as u32) << 8) | csd[9] as u32;
+            Ok((size + 1) * 1024)
+        }
+        _ => Err(Error::Corrupted),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_crc() {
+        // The two CRCs every SD card checks.
+        assert_eq!(command_frame(CMD0, 0), [0x40, 0, 0, 0, 0, 0x95]);
+        assert_eq!(command_frame(CMD8, 0x1AA), [0x48, 0, 0, 0x01, 0xAA, 0x87]);
+        assert_eq!(command_frame(CMD17, 0)[5], 0x55);
+    }
+
+    #[test]
+    fn csd_version_1() {
+        // A 2 GB card: C_SIZE 4095, C_SIZE_MULT 7, READ_BL_LEN 10.
+        let mut csd = [0; 16];
+        csd[5] = 0x5A;
+        csd[6] = 0x03;
+        csd[7] = 0xFF;
+        csd[8] = 0xC0;
+        csd[9] = 0x03;
+        csd[10] = 0x80;
+        assert_eq!(blocks_from_csd(&csd), Ok(4096 * 512 * 2));
+
+        // C_SIZE 0, C_SIZE_MULT 0 and 512 byte blocks is the smallest card.
+        let mut csd = [0; 16];
+        csd[5] = 0x09;
+        assert_eq!(blocks_from_csd(&csd), Ok(4));
+
+        // READ_BL_LEN below 9 used to underflow the shift.
+        csd[5] = 0x06;
+        assert_eq!(blocks_from_csd(&csd), Err(Error::Corrupted));
+        csd[5] = 0x0C;
+        assert_eq!(blocks_from_csd(&csd), Err(Error::Corrupted));
+    }
+
+    #[test]
+    fn csd_version_2() {
+        // A 32 GB card: C_SIZE 0xEE7F.
+        let mut csd = [0; 16];
+        csd[0] = 0x40;
+        csd[8] = 0xEE;
+        csd[9] = 0x7F;
+        assert_eq!(blocks_from_csd(&csd), Ok((0xEE7F + 1) * 1024));
+
+        // The upper bits of the 22 bit C_SIZE.
+        csd[7] = 0x01;
+        assert_eq!(blocks_from_csd(&csd), Ok((0x1_EE7F + 1) * 1024));
+
+        // Reserved structure versions.
+        csd[0] = 0x80;
+        assert_eq!(blocks_from_csd(&csd), Err(Error::Corrupted));
+    }
+}
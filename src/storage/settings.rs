@@ -0,0 +1,248 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Versioned persistence of a configuration structure.
+//! The structure is stored as raw bytes behind a header holding a magic number,
+//! a version, the length and a CRC of the data. If any of them does not match
+//! when loading, for example after a firmware update changed the structure,
+//! the defaults are used instead. This replaces hand written offset bookkeeping.
+
+use super::Storage;
+use crate::encoding::crc::crc16_ccitt;
+use core::mem::size_of;
+
+/// Marks a type which can be stored as its raw bytes.
+/// # Safety
+/// The type must be `#[repr(C)]` without padding bytes and every bit pattern must be a valid value,
+/// so it may only contain integers, arrays of them and other `Plain` structures
+/// (no `bool`, `char`, enums, references or pointers).
+pub unsafe trait Plain: Copy {}
+
+/// Magic number marking a settings block, "RD".
+const MAGIC: u16 = 0x5244;
+
+/// Size of the header stored before the data.
+pub const SETTINGS_HEADER_SIZE: u32 = 8;
+
+/// A configuration structure `T` persisted in the storage `S`.
+pub struct Settings<T: Plain, S: Storage> {
+    storage: S,
+    address: u32,
+    version: u16,
+    defaults: T,
+    value: T,
+}
+
+impl<T: Plain, S: Storage> Settings<T, S> {
+    /// Creates the settings, holding the defaults till `load` is called.
+    /// # Arguments
+    /// * `storage` - a `Storage` object, where the settings are kept.
+    /// * `address` - a u32, the address of the settings block.
+    /// * `version` - a u16, to be changed whenever the layout of `T` changes.
+    /// * `defaults` - a `T`, the values used when nothing valid is stored.
+    pub fn new(storage: S, address: u32, version: u16, defaults: T) -> Self {
+        Settings {
+            storage,
+            address,
+            version,
+            defaults,
+            value: defaults,
+        }
+    }
+
+    /// Returns the number of bytes the settings block takes in the storage.
+    pub fn stored_size(&self) -> u32 {
+        SETTINGS_HEADER_SIZE + size_of::<T>() as u32
+    }
+
+    /// Views a value as its bytes.
+    fn bytes(value: &T) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+    }
+
+    /// Loads the settings from the storage.
+    /// # Returns
+    /// * `a boolean` - true if valid settings were found, otherwise the defaults are used.
+    pub fn load(&mut self) -> bool {
+        let mut header = [0; SETTINGS_HEADER_SIZE as usize];
+        if self.storage.read(self.address, &mut header).is_err() {
+            self.value = self.defaults;
+            return false;
+        }
+        let field = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+        if field(0) != MAGIC || field(2) != self.version || field(4) as usize != size_of::<T>() {
+            self.value = self.defaults;
+            return false;
+        }
+
+        let mut value = self.defaults;
+        let data = unsafe {
+            core::slice::from_raw_parts_mut(&mut value as *mut T as *mut u8, size_of::<T>())
+        };
+        if self
+            .storage
+            .read(self.address + SETTINGS_HEADER_SIZE, data)
+            .is_err()
+            || crc16_ccitt(data) != field(6)
+        {
+            self.value = self.defaults;
+            return false;
+        }
+        self.value = value;
+        true
+    }
+
+    /// Stores the current settings.
+    /// # Returns
+    /// * `a boolean` - false if the storage could not be written.
+    pub fn save(&mut self) -> bool {
+        let data = Self::bytes(&self.value);
+        let mut header = [0; SETTINGS_HEADER_SIZE as usize];
+        header[0..2].copy_from_slice(&MAGIC.to_le_bytes());
+        header[2..4].copy_from_slice(&self.version.to_le_bytes());
+        header[4..6].copy_from_slice(&(size_of::<T>() as u16).to_le_bytes());
+        header[6..8].copy_from_slice(&crc16_ccitt(data).to_le_bytes());
+
+        // The data goes first, so that an interrupted save leaves an invalid CRC.
+        self.storage
+            .write(self.address + SETTINGS_HEADER_SIZE, data)
+            .and_then(|_| self.storage.write(self.address, &header))
+            .is_ok()
+    }
+
+    /// Restores the defaults and stores them.
+    pub fn reset(&mut self) -> bool {
+        self.value = self.defaults;
+        self.save()
+    }
+
+    /// Returns the current settings.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns the current settings for modification, call `save` to persist them.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    /// Gives back the storage.
+    pub fn release(self) -> S {
+        self.storage
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Error, Result};
+
+    /// A RAM memory of 32 bytes, starting erased.
+    struct Memory([u8; 32]);
+
+    impl Storage for Memory {
+        fn capacity(&self) -> u32 {
+            32
+        }
+
+        fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+            let start = address as usize;
+            let end = start + data.len();
+            if end > self.0.len() {
+                return Err(Error::InvalidArgument);
+            }
+            data.copy_from_slice(&self.0[start..end]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+            let start = address as usize;
+            let end = start + data.len();
+            if end > self.0.len() {
+                return Err(Error::InvalidArgument);
+            }
+            self.0[start..end].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    struct Config {
+        rate: u32,
+        limits: [u16; 2],
+    }
+
+    unsafe impl Plain for Config {}
+
+    const DEFAULTS: Config = Config {
+        rate: 9600,
+        limits: [10, 90],
+    };
+
+    #[test]
+    fn defaults_when_empty() {
+        let mut settings = Settings::new(Memory([0xFF; 32]), 4, 1, DEFAULTS);
+        assert_eq!(settings.stored_size(), 16);
+        assert!(!settings.load());
+        assert_eq!(*settings.get(), DEFAULTS);
+
+        // A block which does not fit in the memory cannot be stored.
+        let mut settings = Settings::new(Memory([0xFF; 32]), 24, 1, DEFAULTS);
+        assert!(!settings.save());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut settings = Settings::new(Memory([0xFF; 32]), 4, 1, DEFAULTS);
+        settings.get_mut().rate = 115_200;
+        settings.get_mut().limits[1] = 80;
+        assert!(settings.save());
+
+        let mut settings = Settings::new(settings.release(), 4, 1, DEFAULTS);
+        assert!(settings.load());
+        assert_eq!(settings.get().rate, 115_200);
+        assert_eq!(settings.get().limits, [10, 80]);
+
+        assert!(settings.reset());
+        assert!(settings.load());
+        assert_eq!(*settings.get(), DEFAULTS);
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let mut settings = Settings::new(Memory([0xFF; 32]), 0, 1, DEFAULTS);
+        settings.get_mut().rate = 1200;
+        assert!(settings.save());
+
+        let mut settings = Settings::new(settings.release(), 0, 2, DEFAULTS);
+        assert!(!settings.load());
+        assert_eq!(*settings.get(), DEFAULTS);
+    }
+
+    #[test]
+    fn corrupted_data() {
+        let mut settings = Settings::new(Memory([0xFF; 32]), 0, 1, DEFAULTS);
+        settings.get_mut().rate = 1200;
+        assert!(settings.save());
+
+        let mut memory = settings.release();
+        memory.0[SETTINGS_HEADER_SIZE as usize] ^= 0x01;
+        let mut settings = Settings::new(memory, 0, 1, DEFAULTS);
+        assert!(!settings.load());
+        assert_eq!(*settings.get(), DEFAULTS);
+    }
+}
@@ -0,0 +1,283 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Winbond W25Qxx serial NOR flash memories, and compatible ones, over SPI.
+//! Programming can only clear bits, so an area has to be erased, which sets
+//! all of its bytes to 0xFF, before it is written again. The smallest area
+//! which can be erased is a sector of 4 KiB.
+//! Memories up to 16 MiB, which use 3 byte addresses, are supported.
+
+use super::Storage;
+use crate::{Error, Result};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::spi::{Operation, SpiDevice};
+
+/// Instructions used by the driver.
+const WRITE_ENABLE: u8 = 0x06;
+const READ_STATUS: u8 = 0x05;
+const PAGE_PROGRAM: u8 = 0x02;
+const FAST_READ: u8 = 0x0B;
+const SECTOR_ERASE: u8 = 0x20;
+const BLOCK_ERASE: u8 = 0xD8;
+const CHIP_ERASE: u8 = 0xC7;
+const JEDEC_ID: u8 = 0x9F;
+
+/// Busy bit of the status register.
+const STATUS_BUSY: u8 = 0x01;
+
+/// Size of a page, the largest unit programmed at once.
+pub const FLASH_PAGE_SIZE: u32 = 256;
+/// Size of a sector, the smallest unit erased.
+pub const FLASH_SECTOR_SIZE: u32 = 4096;
+/// Size of a block erased by `erase_block`.
+pub const FLASH_BLOCK_SIZE: u32 = 65536;
+
+/// Maximum times of the operations, in milliseconds.
+const PROGRAM_TIMEOUT: u32 = 5;
+const SECTOR_TIMEOUT: u32 = 400;
+const BLOCK_TIMEOUT: u32 = 2000;
+const CHIP_TIMEOUT: u32 = 200_000;
+
+/// Identification of a memory as returned by the JEDEC ID instruction.
+/// # Elements
+/// * `manufacturer` - a u8, 0xEF for Winbond.
+/// * `memory_type` - a u8, the product family.
+/// * `capacity` - a u8, the base 2 logarithm of the size in bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JedecId {
+    pub manufacturer: u8,
+    pub memory_type: u8,
+    pub capacity: u8,
+}
+
+/// A W25Qxx flash memory.
+/// # Elements
+/// * `spi` - the SPI device of the memory, for example a `SpiDevice` of a shared `Spi`.
+/// * `delay` - a delay provider used while the memory is busy.
+/// * `id` - the identification read when the driver was created.
+pub struct W25Q<SPI, D> {
+    spi: SPI,
+    delay: D,
+    id: JedecId,
+}
+
+impl<SPI, D> W25Q<SPI, D>
+where
+    SPI: SpiDevice<Error = Error>,
+    D: DelayNs,
+{
+    /// Creates the driver and detects the size of the memory.
+    /// # Arguments
+    /// * `spi` - the SPI device of the memory, in mode 0 or 3.
+    /// * `delay` - a delay provider, for example `Delay`.
+    /// # Returns
+    /// * `a Result<W25Q>` - The driver, `InvalidMode` if no supported memory answers.
+    pub fn new(mut spi: SPI, delay: D) -> Result<W25Q<SPI, D>> {
+        let mut id = [0; 3];
+        spi.transaction(&mut [Operation::Write(&[JEDEC_ID]), Operation::Read(&mut id)])?;
+        // A missing memory reads as all zeros or all ones.
+        if id[0] == 0x00 || id[0] == 0xFF || !(0x11..=0x18).contains(&id[2]) {
+            return Err(Error::InvalidMode);
+        }
+        Ok(W25Q {
+            spi,
+            delay,
+            id: JedecId {
+                manufacturer: id[0],
+                memory_type: id[1],
+                capacity: id[2],
+            },
+        })
+    }
+
+    /// Gives back the SPI device and the delay.
+    pub fn release(self) -> (SPI, D) {
+        (self.spi, self.delay)
+    }
+
+    /// Returns the identification of the memory.
+    pub fn jedec_id(&self) -> JedecId {
+        self.id
+    }
+
+    /// Returns the size of the memory in bytes.
+    pub fn capacity(&self) -> u32 {
+        1 << self.id.capacity
+    }
+
+    /// Reads consecutive bytes with the fast read instruction.
+    /// # Arguments
+    /// * `address` - a u32, the address of the first byte.
+    /// * `data` - a mutable reference to `[u8]`, filled with the bytes read.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if the range is past the end of the memory.
+    pub fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+        self.check_range(address, data.len() as u32)?;
+        let a = address.to_be_bytes();
+        self.spi.transaction(&mut [
+            Operation::Write(&[FAST_READ, a[1], a[2], a[3], 0]),
+            Operation::Read(data),
+        ])
+    }
+
+    /// Programs bytes inside one page.
+    /// # Arguments
+    /// * `address` - a u32, the address of the first byte.
+    /// * `data` - a reference to `[u8]`, the bytes, which must not cross the end of the page.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if the data crosses a page, `Timeout` if the memory stays busy.
+    pub fn program_page(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.check_range(address, data.len() as u32)?;
+        if (address % FLASH_PAGE_SIZE) as usize + data.len() > FLASH_PAGE_SIZE as usize {
+            return Err(Error::InvalidArgument);
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.write_enable()?;
+        let a = address.to_be_bytes();
+        self.spi.transaction(&mut [
+            Operation::Write(&[PAGE_PROGRAM, a[1], a[2], a[3]]),
+            Operation::Write(data),
+        ])?;
+        self.wait_busy(PROGRAM_TIMEOUT)
+    }
+
+    /// Programs consecutive bytes, split in as many pages as needed.
+    /// # Arguments
+    /// * `address` - a u32, the address of the first byte.
+    /// * `data` - a reference to `[u8]`, the bytes to be programmed into an erased area.
+    /// # Returns
+    /// * `a Result<()>` - `InvalidArgument` if the range is past the end of the memory.
+    pub fn program(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.check_range(address, data.len() as u32)?;
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            let length = core::cmp::min(
+                (FLASH_PAGE_SIZE - address % FLASH_PAGE_SIZE) as usize,
+                data.len(),
+            );
+            self.program_page(address, &data[..length])?;
+            address += length as u32;
+            data = &data[length..];
+        }
+        Ok(())
+    }
+
+    /// Erases the 4 KiB sector holding an address.
+    /// # Arguments
+    /// * `address` - a u32, any address in the sector.
+    pub fn erase_sector(&mut self, address: u32) -> Result<()> {
+        self.erase(SECTOR_ERASE, address, SECTOR_TIMEOUT)
+    }
+
+    /// Erases the 64 KiB block holding an address.
+    /// # Arguments
+    /// * `address` - a u32, any address in the block.
+    pub fn erase_block(&mut self, address: u32) -> Result<()> {
+        self.erase(BLOCK_ERASE, address, BLOCK_TIMEOUT)
+    }
+
+    /// Erases the whole memory, which takes up to a few minutes on large ones.
+    pub fn erase_chip(&mut self) -> Result<()> {
+        self.write_enable()?;
+        self.spi.write(&[CHIP_ERASE])?;
+        self.wait_busy(CHIP_TIMEOUT)
+    }
+
+    /// Returns true while the memory is programming or erasing.
+    pub fn is_busy(&mut self) -> Result<bool> {
+        let mut status = [0];
+        self.spi.transaction(&mut [
+            Operation::Write(&[READ_STATUS]),
+            Operation::Read(&mut status),
+        ])?;
+        Ok(status[0] & STATUS_BUSY != 0)
+    }
+
+    /// Sends an erase instruction and waits for its end.
+    fn erase(&mut self, instruction: u8, address: u32, timeout: u32) -> Result<()> {
+        self.check_range(address, 1)?;
+        self.write_enable()?;
+        let a = address.to_be_bytes();
+        self.spi.write(&[instruction, a[1], a[2], a[3]])?;
+        self.wait_busy(timeout)
+    }
+
+    /// Allows the next program or erase instruction.
+    fn write_enable(&mut self) -> Result<()> {
+        self.spi.write(&[WRITE_ENABLE])
+    }
+
+    /// Polls the status register every millisecond till the memory is idle.
+    fn wait_busy(&mut self, timeout: u32) -> Result<()> {
+        let mut waited = 0;
+        while self.is_busy()? {
+            if waited == timeout {
+                return Err(Error::Timeout);
+            }
+            self.delay.delay_ms(1);
+            waited += 1;
+        }
+        Ok(())
+    }
+
+    /// Checks that a range of bytes lies in the memory.
+    fn check_range(&self, address: u32, length: u32) -> Result<()> {
+        match address.checked_add(length) {
+            Some(end) if end <= self.capacity() => Ok(()),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// The memory as a `Storage` erased in sectors. Writes only program the bytes, so they
+/// succeed when the area was erased before; the data is read back to detect when it was not,
+/// which is reported as `Corrupted`.
+impl<SPI, D> Storage for W25Q<SPI, D>
+where
+    SPI: SpiDevice<Error = Error>,
+    D: DelayNs,
+{
+    fn capacity(&self) -> u32 {
+        W25Q::capacity(self)
+    }
+
+    fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+        W25Q::read(self, address, data)
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.program(address, data)?;
+        let mut check = [0; 16];
+        for (i, chunk) in data.chunks(check.len()).enumerate() {
+            let address = address + (i * check.len()) as u32;
+            W25Q::read(self, address, &mut check[..chunk.len()])?;
+            if check[..chunk.len()] != *chunk {
+                return Err(Error::Corrupted);
+            }
+        }
+        Ok(())
+    }
+
+    fn erase_size(&self) -> u32 {
+        FLASH_SECTOR_SIZE
+    }
+
+    fn erase(&mut self, address: u32) -> Result<()> {
+        self.erase_sector(address)
+    }
+}
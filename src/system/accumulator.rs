@@ -0,0 +1,246 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Totals of pulse counting sensors like rain gauges and flow meters.
+//! A `PulseAccumulator` is fed with the pulses counted by `hal::pulse::take`
+//! and keeps the total of the current interval, for example the rain of the
+//! last hour, and a lifetime total which survives resets. The lifetime total
+//! is a 32 bit pulse count with a rollover counter, stored with a CRC in any
+//! `Storage` like the internal EEPROM. To spare the EEPROM it is only written
+//! when an interval ends with new pulses, or on `save`.
+
+use crate::encoding::crc::crc16_ccitt;
+use crate::storage::Storage;
+
+/// Number of bytes of storage used by an accumulator.
+pub const ACCUMULATOR_SIZE: u32 = 8;
+
+/// Counts pulses over intervals and over the lifetime of the sensor.
+pub struct PulseAccumulator<S: Storage> {
+    storage: S,
+    address: u32,
+    numerator: u32,
+    denominator: u32,
+    interval: u32,
+    next: Option<u32>,
+    current: u32,
+    last: u32,
+    pulses: u32,
+    rollovers: u16,
+    saved: bool,
+}
+
+impl<S: Storage> PulseAccumulator<S> {
+    /// Creates an accumulator counting one unit per pulse, with a lifetime total of 0.
+    /// # Arguments
+    /// * `storage` - a `Storage` object, where the lifetime total is kept.
+    /// * `address` - a u32, the address of the `ACCUMULATOR_SIZE` bytes used.
+    /// * `interval` - a u32, the length of the intervals in milliseconds.
+    pub fn new(storage: S, address: u32, interval: u32) -> Self {
+        PulseAccumulator {
+            storage,
+            address,
+            numerator: 1,
+            denominator: 1,
+            interval,
+            next: None,
+            current: 0,
+            last: 0,
+            pulses: 0,
+            rollovers: 0,
+            saved: true,
+        }
+    }
+
+    /// Sets the quantity of one pulse as a fraction, the totals are returned in that unit.
+    /// A rain gauge tipping every 0.2794 mm counts hundredths of a millimeter with
+    /// `set_scale(2794, 100)`, a flow meter giving 450 pulses per liter counts
+    /// milliliters with `set_scale(1000, 450)`.
+    /// # Arguments
+    /// * `numerator` - a u32, the quantity of `denominator` pulses.
+    /// * `denominator` - a u32, the number of pulses.
+    pub fn set_scale(&mut self, numerator: u32, denominator: u32) {
+        self.numerator = numerator;
+        self.denominator = denominator.max(1);
+    }
+
+    /// Converts a number of pulses to the unit set by `set_scale`.
+    pub fn scale(&self, pulses: u64) -> u64 {
+        pulses * self.numerator as u64 / self.denominator as u64
+    }
+
+    /// Reads the lifetime total from the storage.
+    /// # Returns
+    /// * `a boolean` - false if nothing valid was stored, the total then starts from 0.
+    pub fn load(&mut self) -> bool {
+        let mut bytes = [0; ACCUMULATOR_SIZE as usize];
+        if self.storage.read(self.address, &mut bytes).is_err()
+            || crc16_ccitt(&bytes[..6]) != u16::from_le_bytes([bytes[6], bytes[7]])
+        {
+            self.pulses = 0;
+            self.rollovers = 0;
+            return false;
+        }
+        self.pulses = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        self.rollovers = u16::from_le_bytes([bytes[4], bytes[5]]);
+        self.saved = true;
+        true
+    }
+
+    /// Writes the lifetime total to the storage if it changed.
+    /// # Returns
+    /// * `a boolean` - false if the write failed.
+    pub fn save(&mut self) -> bool {
+        if self.saved {
+            return true;
+        }
+        let mut bytes = [0; ACCUMULATOR_SIZE as usize];
+        bytes[..4].copy_from_slice(&self.pulses.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.rollovers.to_le_bytes());
+        let crc = crc16_ccitt(&bytes[..6]);
+        bytes[6..].copy_from_slice(&crc.to_le_bytes());
+        self.saved = self.storage.write(self.address, &bytes).is_ok();
+        self.saved
+    }
+
+    /// Clears the lifetime total, for example after emptying a rain gauge log.
+    pub fn reset_lifetime(&mut self) -> bool {
+        self.pulses = 0;
+        self.rollovers = 0;
+        self.saved = false;
+        self.save()
+    }
+
+    /// Adds pulses to the current interval and to the lifetime total.
+    /// # Arguments
+    /// * `pulses` - a u16, the pulses counted since the previous call.
+    pub fn add(&mut self, pulses: u16) {
+        if pulses == 0 {
+            return;
+        }
+        self.current = self.current.saturating_add(pulses as u32);
+        let (total, wrapped) = self.pulses.overflowing_add(pulses as u32);
+        self.pulses = total;
+        if wrapped {
+            self.rollovers = self.rollovers.wrapping_add(1);
+        }
+        self.saved = false;
+    }
+
+    /// Adds the new pulses and closes the interval when it is over.
+    /// Call it often from the main loop or from a scheduler task.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, usually `millis()`.
+    /// * `pulses` - a u16, the pulses counted since the previous call, usually `pulse::take(pin)`.
+    /// # Returns
+    /// * `a Option<u64>` - the total of the interval which just ended, scaled, `None` while it runs.
+    pub fn poll(&mut self, now: u32, pulses: u16) -> Option<u64> {
+        self.add(pulses);
+        let next = *self.next.get_or_insert(now.wrapping_add(self.interval));
+        if (now.wrapping_sub(next) as i32) < 0 {
+            return None;
+        }
+        self.next = Some(next.wrapping_add(self.interval));
+        if (now.wrapping_sub(next.wrapping_add(self.interval)) as i32) >= 0 {
+            self.next = Some(now.wrapping_add(self.interval));
+        }
+        self.last = self.current;
+        self.current = 0;
+        self.save();
+        Some(self.scale(self.last as u64))
+    }
+
+    /// Returns the total of the running interval, scaled.
+    pub fn current(&self) -> u64 {
+        self.scale(self.current as u64)
+    }
+
+    /// Returns the total of the last complete interval, scaled.
+    pub fn last_interval(&self) -> u64 {
+        self.scale(self.last as u64)
+    }
+
+    /// Returns the number of pulses counted over the lifetime of the sensor.
+    pub fn lifetime_pulses(&self) -> u64 {
+        ((self.rollovers as u64) << 32) | self.pulses as u64
+    }
+
+    /// Returns the lifetime total, scaled.
+    pub fn lifetime(&self) -> u64 {
+        self.scale(self.lifetime_pulses())
+    }
+
+    /// Gives back the storage.
+    pub fn release(self) -> S {
+        self.storage
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Result;
+
+    struct Memory([u8; 16]);
+
+    impl Storage for Memory {
+        fn capacity(&self) -> u32 {
+            16
+        }
+
+        fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+            let start = address as usize;
+            data.copy_from_slice(&self.0[start..start + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+            let start = address as usize;
+            self.0[start..start + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn intervals_and_lifetime() {
+        let mut gauge = PulseAccumulator::new(Memory([0xFF; 16]), 4, 60_000);
+        assert!(!gauge.load());
+        gauge.set_scale(2794, 100);
+
+        assert_eq!(gauge.poll(0, 3), None);
+        assert_eq!(gauge.current(), 83);
+        assert_eq!(gauge.poll(59_999, 1), None);
+        assert_eq!(gauge.poll(60_000, 0), Some(111));
+        assert_eq!(gauge.poll(120_000, 0), Some(0));
+        assert_eq!(gauge.lifetime_pulses(), 4);
+
+        let mut gauge = PulseAccumulator::new(gauge.release(), 4, 60_000);
+        assert!(gauge.load());
+        assert_eq!(gauge.lifetime_pulses(), 4);
+    }
+
+    #[test]
+    fn lifetime_rolls_over() {
+        let mut meter = PulseAccumulator::new(Memory([0; 16]), 0, 1000);
+        meter.pulses = u32::MAX - 1;
+        meter.add(3);
+        assert_eq!(meter.lifetime_pulses(), (1 << 32) + 1);
+        assert!(meter.save());
+        let mut meter = PulseAccumulator::new(meter.release(), 0, 1000);
+        assert!(meter.load());
+        assert_eq!(meter.lifetime_pulses(), (1 << 32) + 1);
+    }
+}
@@ -0,0 +1,276 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Alarms at given times of day on given days of the week, like a simple cron.
+//! The scheduler reads the time from an `AlarmClock`, runs the alarms which
+//! became due since it last looked and programs the clock for the next one, so
+//! the MCU can spend the time in between in power-down mode. This suits
+//! irrigation or lighting controllers which act a few times a day.
+//! `run_power_save` needs the `async-timer` feature.
+
+use super::clock::{AlarmClock, DateTime};
+#[cfg(feature = "async-timer")]
+use crate::hal::async_timer;
+use crate::hal::sleep_mode;
+use crate::Result;
+
+pub const MONDAY: u8 = 0x01;
+pub const TUESDAY: u8 = 0x02;
+pub const WEDNESDAY: u8 = 0x04;
+pub const THURSDAY: u8 = 0x08;
+pub const FRIDAY: u8 = 0x10;
+pub const SATURDAY: u8 = 0x20;
+pub const SUNDAY: u8 = 0x40;
+/// Monday to Friday.
+pub const WEEKDAYS: u8 = 0x1F;
+/// Saturday and Sunday.
+pub const WEEKEND: u8 = 0x60;
+pub const EVERY_DAY: u8 = 0x7F;
+
+/// Identifies an alarm added to an `AlarmScheduler`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AlarmId(u8);
+
+/// An alarm known to the scheduler.
+#[derive(Clone, Copy)]
+struct Alarm {
+    /// Seconds after midnight at which the alarm fires.
+    time: u32,
+    /// Days on which the alarm fires, bit 0 for Monday to bit 6 for Sunday.
+    days: u8,
+    run: fn(&DateTime),
+}
+
+impl Alarm {
+    /// Returns the Unix time of the first occurrence strictly after `after`.
+    fn next_after(&self, after: u32) -> Option<u32> {
+        next_occurrence(self.time, self.days, after)
+    }
+}
+
+/// Returns the Unix time of the first occurrence of a weekly time strictly after `after`.
+/// # Arguments
+/// * `time` - a u32, the seconds after midnight.
+/// * `days` - a u8, the days of the week, bit 0 for Monday to bit 6 for Sunday.
+/// * `after` - a u32, the Unix time to start from.
+pub(crate) fn next_occurrence(time: u32, days: u8, after: u32) -> Option<u32> {
+    let midnight = after - after % 86400;
+    (0..8)
+        .map(|day| midnight + day * 86400)
+        // 1970-01-01 was a Thursday, bit 3.
+        .filter(|start| days & (1 << ((start / 86400 + 3) % 7)) != 0)
+        .map(|start| start + time)
+        .find(|&at| at > after)
+}
+
+/// Returns the Unix time of the last occurrence of a weekly time at or before `at`.
+/// # Arguments
+/// * `time` - a u32, the seconds after midnight.
+/// * `days` - a u8, the days of the week, bit 0 for Monday to bit 6 for Sunday.
+/// * `at` - a u32, the Unix time to look back from.
+pub(crate) fn previous_occurrence(time: u32, days: u8, at: u32) -> Option<u32> {
+    let midnight = at - at % 86400;
+    (0..8)
+        .filter_map(|day| midnight.checked_sub(day * 86400))
+        .filter(|start| days & (1 << ((start / 86400 + 3) % 7)) != 0)
+        .map(|start| start + time)
+        .find(|&occurrence| occurrence <= at)
+}
+
+/// A scheduler holding at most `N` alarms.
+pub struct AlarmScheduler<const N: usize> {
+    alarms: [Option<Alarm>; N],
+    /// Unix time of the last call of `run_due`.
+    last: Option<u32>,
+}
+
+impl<const N: usize> AlarmScheduler<N> {
+    /// Creates a new scheduler without any alarm.
+    pub const fn new() -> Self {
+        AlarmScheduler {
+            alarms: [None; N],
+            last: None,
+        }
+    }
+
+    /// Adds an alarm.
+    /// # Arguments
+    /// * `hour` - a u8, from 0 to 23.
+    /// * `minute` - a u8, from 0 to 59.
+    /// * `days` - a u8, a combination of the day constants like `WEEKDAYS` or `MONDAY | FRIDAY`.
+    /// * `run` - a function, called with the current time when the alarm fires.
+    /// # Returns
+    /// * `a Option<AlarmId>` - `None` if the scheduler is full or the time or days are not valid.
+    pub fn at(&mut self, hour: u8, minute: u8, days: u8, run: fn(&DateTime)) -> Option<AlarmId> {
+        if hour > 23 || minute > 59 || days & EVERY_DAY == 0 {
+            return None;
+        }
+        let index = self.alarms.iter().position(|a| a.is_none())?;
+        self.alarms[index] = Some(Alarm {
+            time: hour as u32 * 3600 + minute as u32 * 60,
+            days: days & EVERY_DAY,
+            run,
+        });
+        Some(AlarmId(index as u8))
+    }
+
+    /// Removes an alarm.
+    pub fn remove(&mut self, id: AlarmId) {
+        if let Some(alarm) = self.alarms.get_mut(id.0 as usize) {
+            *alarm = None;
+        }
+    }
+
+    /// Returns the time at which the next alarm fires.
+    /// # Arguments
+    /// * `after` - a reference to `DateTime`, usually the current time.
+    /// # Returns
+    /// * `a Option<DateTime>` - The first time after `after`, `None` if there is no alarm.
+    pub fn next(&self, after: &DateTime) -> Option<DateTime> {
+        let after = after.timestamp();
+        self.alarms
+            .iter()
+            .flatten()
+            .filter_map(|alarm| alarm.next_after(after))
+            .min()
+            .map(DateTime::from_timestamp)
+    }
+
+    /// Runs the alarms which became due since the previous call, each at most once,
+    /// so that alarms are not lost if the MCU woke up late.
+    /// On the first call only the alarms of the current minute are run.
+    /// # Arguments
+    /// * `now` - a reference to `DateTime`, the current time.
+    /// # Returns
+    /// * `a usize` - The number of alarms run.
+    pub fn run_due(&mut self, now: &DateTime) -> usize {
+        let time = now.timestamp();
+        let since = match self.last {
+            Some(last) if last <= time => last,
+            _ => time - now.second() as u32 - 1,
+        };
+        self.last = Some(time);
+        let mut count = 0;
+        for alarm in self.alarms.iter().flatten() {
+            if matches!(alarm.next_after(since), Some(at) if at <= time) {
+                (alarm.run)(now);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Acknowledges the alarm of the clock, runs the due alarms and programs the clock for the next one.
+    /// # Arguments
+    /// * `clock` - a `AlarmClock` object, like a `DS3231`.
+    /// # Returns
+    /// * `a Result<usize>` - The number of alarms run, or the error of the clock.
+    pub fn poll<C: AlarmClock>(&mut self, clock: &mut C) -> Result<usize> {
+        clock.take_alarm()?;
+        let now = clock.now()?;
+        let count = self.run_due(&now);
+        if let Some(next) = self.next(&now) {
+            clock.set_alarm(&next)?;
+        }
+        Ok(count)
+    }
+
+    /// Calls `poll` and puts the MCU in power-down mode till it is woken up, forever.
+    /// The alarm output of the clock must be wired to an external interrupt pin whose
+    /// interrupt is enabled, otherwise nothing but the watchdog wakes the MCU.
+    /// Errors of the clock are passed to `error`, after which the MCU sleeps as well.
+    /// # Arguments
+    /// * `clock` - a `AlarmClock` object raising an interrupt, like a `DS3231`.
+    /// * `error` - a function, called with the errors of the clock.
+    pub fn run<C: AlarmClock>(&mut self, clock: &mut C, error: fn(crate::Error)) -> ! {
+        self.run_with(clock, error, sleep_mode::power_down)
+    }
+
+    /// Calls `poll` and puts the MCU in power-save mode till it is woken up, forever.
+    /// Timer2 keeps running from its watch crystal meanwhile, so this suits a
+    /// `CrystalClock`, whose timer wakes the MCU up at every overflow.
+    /// Errors of the clock are passed to `error`, after which the MCU sleeps as well.
+    /// # Arguments
+    /// * `clock` - a `AlarmClock` object, like a `CrystalClock`.
+    /// * `error` - a function, called with the errors of the clock.
+    #[cfg(feature = "async-timer")]
+    pub fn run_power_save<C: AlarmClock>(&mut self, clock: &mut C, error: fn(crate::Error)) -> ! {
+        self.run_with(clock, error, async_timer::sleep)
+    }
+
+    /// Polls the clock and sleeps with the given function, forever.
+    fn run_with<C: AlarmClock>(
+        &mut self,
+        clock: &mut C,
+        error: fn(crate::Error),
+        sleep: fn(),
+    ) -> ! {
+        loop {
+            if let Err(e) = self.poll(clock) {
+                error(e);
+            }
+            sleep();
+        }
+    }
+}
+
+impl<const N: usize> Default for AlarmScheduler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    static RUNS: AtomicU8 = AtomicU8::new(0);
+
+    fn count(_: &DateTime) {
+        RUNS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn next_and_catch_up() {
+        let mut scheduler: AlarmScheduler<2> = AlarmScheduler::new();
+        scheduler.at(6, 30, WEEKDAYS, count).unwrap();
+        scheduler.at(20, 0, SATURDAY, count).unwrap();
+
+        // Friday 2021-07-02 at 7:00.
+        let friday = DateTime::new(2021, 7, 2, 7, 0, 0).unwrap();
+        assert_eq!(scheduler.next(&friday), DateTime::new(2021, 7, 3, 20, 0, 0));
+        let saturday = DateTime::new(2021, 7, 3, 21, 0, 0).unwrap();
+        assert_eq!(
+            scheduler.next(&saturday),
+            DateTime::new(2021, 7, 5, 6, 30, 0)
+        );
+
+        // Waking up two minutes late still runs the alarm, once.
+        assert_eq!(
+            scheduler.run_due(&DateTime::new(2021, 7, 5, 6, 0, 0).unwrap()),
+            0
+        );
+        assert_eq!(
+            scheduler.run_due(&DateTime::new(2021, 7, 5, 6, 32, 0).unwrap()),
+            1
+        );
+        assert_eq!(
+            scheduler.run_due(&DateTime::new(2021, 7, 5, 6, 33, 0).unwrap()),
+            0
+        );
+        assert_eq!(RUNS.load(Ordering::Relaxed), 1);
+    }
+}
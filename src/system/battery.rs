@@ -0,0 +1,235 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Monitoring of a battery voltage with low and critical thresholds.
+//! The voltage comes from any function, typically `analog::vcc_millivolts`
+//! when the battery powers the chip directly, or an analog pin behind a
+//! resistor divider converted with `divider_millivolts`. Changes of state
+//! are published on `system::events::EVENTS` as `Event::Battery`, with a
+//! hysteresis so that a voltage sagging under load does not make them flicker.
+
+use super::events::{self, Event};
+
+/// Battery technologies with their discharge curves.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Chemistry {
+    /// Lithium polymer or lithium ion, 4.2 V per cell when full.
+    LiPo,
+    /// Nickel metal hydride, 1.4 V per cell when full.
+    NiMH,
+}
+
+/// Charge in percent at voltages per cell in millivolts, highest voltage first.
+const LIPO_CURVE: [(u16, u8); 9] = [
+    (4200, 100),
+    (4100, 90),
+    (4000, 77),
+    (3900, 63),
+    (3800, 48),
+    (3700, 30),
+    (3600, 12),
+    (3500, 5),
+    (3300, 0),
+];
+const NIMH_CURVE: [(u16, u8); 7] = [
+    (1400, 100),
+    (1300, 85),
+    (1250, 70),
+    (1200, 45),
+    (1150, 20),
+    (1100, 8),
+    (1000, 0),
+];
+
+impl Chemistry {
+    /// Returns the discharge curve of a cell.
+    fn curve(&self) -> &'static [(u16, u8)] {
+        match self {
+            Chemistry::LiPo => &LIPO_CURVE,
+            Chemistry::NiMH => &NIMH_CURVE,
+        }
+    }
+
+    /// Returns the default low and critical voltages of a cell in millivolts.
+    fn thresholds(&self) -> (u16, u16) {
+        match self {
+            Chemistry::LiPo => (3550, 3350),
+            Chemistry::NiMH => (1120, 1050),
+        }
+    }
+}
+
+/// Estimates the charge of a cell from its voltage by interpolating the discharge curve.
+/// # Arguments
+/// * `chemistry` - a `Chemistry` object, the technology of the cell.
+/// * `millivolts` - a u16, the voltage of the cell.
+/// # Returns
+/// * `a u8` - The charge in percent.
+pub fn cell_percent(chemistry: Chemistry, millivolts: u16) -> u8 {
+    let curve = chemistry.curve();
+    if millivolts >= curve[0].0 {
+        return 100;
+    }
+    for pair in curve.windows(2) {
+        let ((high_mv, high_pct), (low_mv, low_pct)) = (pair[0], pair[1]);
+        if millivolts >= low_mv {
+            let span = (high_pct - low_pct) as u32 * (millivolts - low_mv) as u32;
+            return low_pct + (span / (high_mv - low_mv) as u32) as u8;
+        }
+    }
+    0
+}
+
+/// Converts an ADC reading of a voltage divider to the voltage at its top.
+/// # Arguments
+/// * `reading` - a u32, the value read by the ADC, from 0 to 1023.
+/// * `reference` - a u16, the ADC reference voltage in millivolts.
+/// * `top` - a u32, the resistance between the battery and the pin.
+/// * `bottom` - a u32, the resistance between the pin and ground.
+/// # Returns
+/// * `a u16` - The battery voltage in millivolts.
+pub fn divider_millivolts(reading: u32, reference: u16, top: u32, bottom: u32) -> u16 {
+    let pin = reading as u64 * reference as u64 / 1024;
+    (pin * (top + bottom) as u64 / bottom as u64) as u16
+}
+
+/// The states of a monitored battery.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BatteryState {
+    Normal,
+    /// The voltage is below the low threshold, the battery should be replaced or charged soon.
+    Low,
+    /// The voltage is below the critical threshold, the application should save its data and shut down.
+    Critical,
+}
+
+/// A battery monitor.
+/// # Elements
+/// * `read` - a function returning the battery voltage in millivolts.
+/// * `chemistry` - the technology of the cells.
+/// * `cells` - a u8, the number of cells in series.
+/// * `low` - a u16, the low threshold of the whole battery in millivolts.
+/// * `critical` - a u16, the critical threshold of the whole battery in millivolts.
+/// * `hysteresis` - a u16, how far above a threshold the voltage must rise to leave its state.
+/// * `millivolts` - a u16, the filtered voltage, 0 before the first update.
+/// * `state` - the current state.
+pub struct BatteryMonitor {
+    read: fn() -> u16,
+    chemistry: Chemistry,
+    cells: u8,
+    low: u16,
+    critical: u16,
+    hysteresis: u16,
+    millivolts: u16,
+    state: BatteryState,
+}
+
+impl BatteryMonitor {
+    /// Creates a monitor with the default thresholds of the chemistry
+    /// and a hysteresis of 50 mV per cell.
+    /// # Arguments
+    /// * `read` - a function returning the battery voltage in millivolts.
+    /// * `chemistry` - a `Chemistry` object, the technology of the cells.
+    /// * `cells` - a u8, the number of cells in series, at least 1.
+    /// # Returns
+    /// * `a BatteryMonitor object` - The monitor in the `Normal` state.
+    pub fn new(read: fn() -> u16, chemistry: Chemistry, cells: u8) -> BatteryMonitor {
+        let cells = cells.max(1);
+        let (low, critical) = chemistry.thresholds();
+        BatteryMonitor {
+            read,
+            chemistry,
+            cells,
+            low: low * cells as u16,
+            critical: critical * cells as u16,
+            hysteresis: 50 * cells as u16,
+            millivolts: 0,
+            state: BatteryState::Normal,
+        }
+    }
+
+    /// Sets the thresholds for the whole battery.
+    /// # Arguments
+    /// * `low` - a u16, the voltage in millivolts under which the state becomes `Low`.
+    /// * `critical` - a u16, the voltage in millivolts under which the state becomes `Critical`.
+    /// * `hysteresis` - a u16, the rise in millivolts above a threshold needed to leave its state.
+    pub fn set_thresholds(&mut self, low: u16, critical: u16, hysteresis: u16) {
+        self.low = low;
+        self.critical = critical;
+        self.hysteresis = hysteresis;
+    }
+
+    /// Reads the voltage and updates the state, publishing `Event::Battery` if it changed.
+    /// The voltage is smoothed over about four updates.
+    /// # Returns
+    /// * `a BatteryState` - The new state.
+    pub fn update(&mut self) -> BatteryState {
+        let reading = (self.read)();
+        self.millivolts = if self.millivolts == 0 {
+            reading
+        } else {
+            ((self.millivolts as u32 * 3 + reading as u32) / 4) as u16
+        };
+        let state = self.next_state(self.millivolts);
+        if state != self.state {
+            self.state = state;
+            events::publish(Event::Battery(state));
+        }
+        state
+    }
+
+    /// Computes the state for a voltage, staying in the current one within the hysteresis.
+    fn next_state(&self, millivolts: u16) -> BatteryState {
+        let above = |threshold: u16| millivolts > threshold.saturating_add(self.hysteresis);
+        match self.state {
+            _ if millivolts < self.critical => BatteryState::Critical,
+            BatteryState::Critical if !above(self.critical) => BatteryState::Critical,
+            _ if millivolts < self.low => BatteryState::Low,
+            BatteryState::Critical | BatteryState::Low if !above(self.low) => BatteryState::Low,
+            _ => BatteryState::Normal,
+        }
+    }
+
+    /// Returns the state found by the last update.
+    pub fn state(&self) -> BatteryState {
+        self.state
+    }
+
+    /// Returns the filtered voltage of the battery in millivolts.
+    pub fn millivolts(&self) -> u16 {
+        self.millivolts
+    }
+
+    /// Estimates the remaining charge from the filtered voltage.
+    /// # Returns
+    /// * `a u8` - The charge in percent.
+    pub fn percent(&self) -> u8 {
+        cell_percent(self.chemistry, self.millivolts / self.cells as u16)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn charge_curves() {
+        assert_eq!(cell_percent(Chemistry::LiPo, 4250), 100);
+        assert_eq!(cell_percent(Chemistry::LiPo, 3850), 55);
+        assert_eq!(cell_percent(Chemistry::LiPo, 3000), 0);
+        assert_eq!(cell_percent(Chemistry::NiMH, 1225), 57);
+        assert_eq!(divider_millivolts(512, 5000, 10_000, 10_000), 5000);
+    }
+}
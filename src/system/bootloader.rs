@@ -0,0 +1,83 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Reset into the bootloader from the application, for reflashing boards in the field.
+//! A magic key is left either in RAM, which is not cleared by a watchdog reset,
+//! or in EEPROM, and the watchdog then resets the microcontroller. A bootloader
+//! checking for the key stays in programming mode instead of starting the application.
+//! The stock Optiboot starts the application right away after a watchdog reset,
+//! so it needs a bootloader built to look for the key.
+
+use crate::hal::eeprom::Eeprom;
+use crate::hal::interrupts;
+use crate::hal::watchdog::WatchDog;
+use core::ptr::write_volatile;
+
+/// Where the bootloader looks for the magic key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyLocation {
+    /// A RAM address, outside of the area the bootloader itself uses.
+    Ram(u16),
+    /// An EEPROM address.
+    Eeprom(u16),
+}
+
+/// The magic key telling the bootloader to stay in programming mode.
+/// # Elements
+/// * `location` - a `KeyLocation`, where the key is written.
+/// * `value` - a u16, the key, written little endian.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BootloaderKey {
+    pub location: KeyLocation,
+    pub value: u16,
+}
+
+impl BootloaderKey {
+    /// The key of the Caterina bootloader, 0x7777 at RAM address 0x0800.
+    pub const CATERINA: BootloaderKey = BootloaderKey {
+        location: KeyLocation::Ram(0x0800),
+        value: 0x7777,
+    };
+
+    /// Creates a key for a custom bootloader.
+    /// # Arguments
+    /// * `location` - a `KeyLocation`, where the bootloader looks for the key.
+    /// * `value` - a u16, the key.
+    pub const fn new(location: KeyLocation, value: u16) -> BootloaderKey {
+        BootloaderKey { location, value }
+    }
+}
+
+/// Writes the magic key and resets the microcontroller through the watchdog.
+/// Interrupts are disabled first, so nothing else runs until the reset.
+/// # Arguments
+/// * `key` - a `BootloaderKey`, the key expected by the bootloader.
+pub fn reset_to_bootloader(key: BootloaderKey) -> ! {
+    unsafe {
+        interrupts::Interrupt::disable(interrupts::Interrupt::new());
+    }
+    match key.location {
+        KeyLocation::Ram(address) => unsafe {
+            let bytes = key.value.to_le_bytes();
+            write_volatile(address as *mut u8, bytes[0]);
+            write_volatile((address + 1) as *mut u8, bytes[1]);
+        },
+        KeyLocation::Eeprom(address) => {
+            Eeprom::new().write(address, &key.value.to_le_bytes());
+        }
+    }
+    unsafe { WatchDog::new() }.system_reset()
+}
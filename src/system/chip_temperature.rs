@@ -0,0 +1,202 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Conversion and calibration of the temperature sensor inside the chip.
+//! The ATMEGA328P converts it on ADC channel 8 against the 1.1 V reference,
+//! see `analog::read_chip_temperature_c`, the ATMEGA2560P has no such sensor.
+//! The datasheet gives about 314 mV at 25 degrees and 1 mV per degree, but
+//! the offset varies by up to 10 degrees between chips, so a calibration
+//! taken at one or two known temperatures can be stored in the EEPROM and
+//! loaded at start up. The die runs a few degrees above the ambient air, the
+//! reading suits the derating of the board and the compensation of the drift
+//! of the RNG and of the timers rather than a thermometer.
+
+use crate::storage::{Plain, Settings, Storage, SETTINGS_HEADER_SIZE};
+
+/// Typical output of the sensor at 25 degrees in millivolts.
+pub const DATASHEET_MV_AT_25C: i32 = 314;
+
+/// Voltage of the reference used for the conversions in millivolts.
+const REFERENCE_MV: i32 = 1100;
+
+/// Version of the calibration settings block.
+const CALIBRATION_VERSION: u16 = 1;
+
+/// Number of bytes of storage used by the calibration.
+pub const CALIBRATION_SIZE: u32 = SETTINGS_HEADER_SIZE + 4;
+
+/// Converts a reading of the sensor to degrees with the typical values of the datasheet.
+/// # Arguments
+/// * `raw` - a u16, the 10 bit ADC reading against the 1.1 V reference.
+/// # Returns
+/// * `a f32` - The temperature of the die in degrees Celsius.
+pub fn datasheet_celsius(raw: u16) -> f32 {
+    let millivolts = raw as f32 * REFERENCE_MV as f32 / 1024.0;
+    25.0 + millivolts - DATASHEET_MV_AT_25C as f32
+}
+
+/// Correction applied over the datasheet conversion.
+/// # Elements
+/// * `offset` - a i16, added to the temperature, in hundredths of a degree.
+/// * `gain` - a u16, the slope applied to the temperature, in thousandths.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TemperatureCalibration {
+    pub offset: i16,
+    pub gain: u16,
+}
+
+unsafe impl Plain for TemperatureCalibration {}
+
+impl TemperatureCalibration {
+    /// No correction, the typical values of the datasheet.
+    pub const DATASHEET: TemperatureCalibration = TemperatureCalibration {
+        offset: 0,
+        gain: 1000,
+    };
+
+    /// Converts a reading of the sensor to degrees.
+    /// # Arguments
+    /// * `raw` - a u16, the 10 bit ADC reading against the 1.1 V reference.
+    /// # Returns
+    /// * `a f32` - The temperature of the die in degrees Celsius.
+    pub fn celsius(&self, raw: u16) -> f32 {
+        datasheet_celsius(raw) * self.gain as f32 / 1000.0 + self.offset as f32 / 100.0
+    }
+
+    /// Finds the offset from a reading taken at a known temperature, keeping the datasheet slope.
+    /// # Arguments
+    /// * `raw` - a u16, the reading of the sensor.
+    /// * `actual` - a f32, the temperature of the chip in degrees, after it settled for some minutes.
+    pub fn single_point(raw: u16, actual: f32) -> TemperatureCalibration {
+        let offset = (actual - datasheet_celsius(raw)) * 100.0;
+        TemperatureCalibration {
+            offset: clamp_i16(offset),
+            gain: 1000,
+        }
+    }
+
+    /// Finds the offset and the slope from readings taken at two known temperatures.
+    /// # Arguments
+    /// * `low` - a tuple of the reading and the actual temperature in degrees at the first point.
+    /// * `high` - a tuple of the reading and the actual temperature in degrees at the second point.
+    /// # Returns
+    /// * `a Option` - None if both readings are equal or the slope is out of range.
+    pub fn two_point(low: (u16, f32), high: (u16, f32)) -> Option<TemperatureCalibration> {
+        let (low_raw, high_raw) = (datasheet_celsius(low.0), datasheet_celsius(high.0));
+        if low.0 == high.0 {
+            return None;
+        }
+        let slope = (high.1 - low.1) / (high_raw - low_raw);
+        if !(0.0..=u16::MAX as f32 / 1000.0).contains(&slope) {
+            return None;
+        }
+        let gain = (slope * 1000.0 + 0.5) as u16;
+        let offset = (low.1 - low_raw * gain as f32 / 1000.0) * 100.0;
+        Some(TemperatureCalibration {
+            offset: clamp_i16(offset),
+            gain,
+        })
+    }
+
+    /// Loads the calibration from the storage.
+    /// # Arguments
+    /// * `storage` - a `Storage` object, like the `Eeprom`.
+    /// * `address` - a u32, the address of the `CALIBRATION_SIZE` bytes used.
+    /// # Returns
+    /// * `a TemperatureCalibration object` - The stored one, or `DATASHEET` if none is valid.
+    pub fn load<S: Storage>(storage: S, address: u32) -> TemperatureCalibration {
+        let mut settings = Settings::new(
+            storage,
+            address,
+            CALIBRATION_VERSION,
+            TemperatureCalibration::DATASHEET,
+        );
+        settings.load();
+        *settings.get()
+    }
+
+    /// Stores the calibration.
+    /// # Arguments
+    /// * `storage` - a `Storage` object, like the `Eeprom`.
+    /// * `address` - a u32, the address of the `CALIBRATION_SIZE` bytes used.
+    /// # Returns
+    /// * `a boolean` - false if the storage could not be written.
+    pub fn store<S: Storage>(&self, storage: S, address: u32) -> bool {
+        let mut settings = Settings::new(storage, address, CALIBRATION_VERSION, *self);
+        settings.save()
+    }
+}
+
+/// Rounds and saturates hundredths of a degree.
+fn clamp_i16(value: f32) -> i16 {
+    let rounded = if value < 0.0 {
+        value - 0.5
+    } else {
+        value + 0.5
+    };
+    rounded.max(i16::MIN as f32).min(i16::MAX as f32) as i16
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Result;
+
+    struct Memory([u8; 16]);
+
+    impl Storage for Memory {
+        fn capacity(&self) -> u32 {
+            16
+        }
+
+        fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+            let start = address as usize;
+            data.copy_from_slice(&self.0[start..start + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+            let start = address as usize;
+            self.0[start..start + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn calibration() {
+        // 314 mV is a reading of 292.3.
+        let datasheet = TemperatureCalibration::DATASHEET;
+        assert!((datasheet.celsius(292) - 24.7).abs() < 0.1);
+
+        let single = TemperatureCalibration::single_point(300, 25.0);
+        assert_eq!(single.gain, 1000);
+        assert!((single.celsius(300) - 25.0).abs() < 0.01);
+
+        let two = TemperatureCalibration::two_point((300, 20.0), (350, 70.0)).unwrap();
+        assert!((two.celsius(300) - 20.0).abs() < 0.05);
+        assert!((two.celsius(350) - 70.0).abs() < 0.05);
+        assert_eq!(
+            TemperatureCalibration::two_point((300, 20.0), (300, 70.0)),
+            None
+        );
+
+        let mut memory = Memory([0xFF; 16]);
+        assert_eq!(TemperatureCalibration::load(&mut memory, 0), datasheet);
+        assert!(two.store(&mut memory, 0));
+        assert_eq!(TemperatureCalibration::load(&mut memory, 0), two);
+    }
+}
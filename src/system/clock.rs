@@ -0,0 +1,519 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//! Calendar date and time, and clocks providing it.
+//! Code which needs the time of day uses the `Clock` trait, so it runs the
+//! same with a battery backed real-time clock chip or with `SoftwareClock`,
+//! which counts on from a set time with `millis` and can be corrected for the
+//! drift of the crystal.
+//! `SoftwareClock` needs the `millis` feature and `CrystalClock` the `async-timer` one.
+
+#[cfg(feature = "async-timer")]
+use crate::hal::async_timer::AsyncTimer;
+#[cfg(feature = "millis")]
+use crate::hal::millis::millis;
+use crate::Result;
+use core::fmt;
+
+/// Days before the first of each month in a year which is not a leap year.
+const DAYS_BEFORE_MONTH: [u16; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+
+/// Returns true if the year has a 29th of February.
+fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Returns the number of days of a month.
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A date and time of day, without time zone, between 1970 and 2105.
+/// # Elements
+/// * `year` - a u16, like 2021.
+/// * `month` - a u8, from 1 to 12.
+/// * `day` - a u8, from 1 to 31.
+/// * `hour` - a u8, from 0 to 23.
+/// * `minute` - a u8, from 0 to 59.
+/// * `second` - a u8, from 0 to 59.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct DateTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl DateTime {
+    /// Creates a date and time after checking it.
+    /// # Returns
+    /// * `a Option<DateTime>` - `None` if any field is out of range.
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Option<DateTime> {
+        if !(1970..=2105).contains(&year)
+            || !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return None;
+        }
+        Some(DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Converts a count of seconds since 1970-01-01 00:00:00.
+    /// # Arguments
+    /// * `timestamp` - a u32, the Unix time.
+    /// # Returns
+    /// * `a DateTime` - The date and time.
+    pub fn from_timestamp(timestamp: u32) -> DateTime {
+        let mut days = timestamp / 86400;
+        let seconds = timestamp % 86400;
+        let mut year = 1970;
+        loop {
+            let length = if is_leap_year(year) { 366 } else { 365 };
+            if days < length {
+                break;
+            }
+            days -= length;
+            year += 1;
+        }
+        let mut month = 1;
+        loop {
+            let length = days_in_month(year, month) as u32;
+            if days < length {
+                break;
+            }
+            days -= length;
+            month += 1;
+        }
+        DateTime {
+            year,
+            month,
+            day: days as u8 + 1,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+
+    /// Returns the number of seconds since 1970-01-01 00:00:00.
+    pub fn timestamp(&self) -> u32 {
+        self.days() * 86400 + self.hour as u32 * 3600 + self.minute as u32 * 60 + self.second as u32
+    }
+
+    /// Returns the number of days since 1970-01-01.
+    fn days(&self) -> u32 {
+        let previous = self.year as u32 - 1;
+        let leap_days = |year: u32| year / 4 - year / 100 + year / 400;
+        let mut days = (self.year as u32 - 1970) * 365 + leap_days(previous) - leap_days(1969);
+        days += DAYS_BEFORE_MONTH[self.month as usize - 1] as u32 + self.day as u32 - 1;
+        if self.month > 2 && is_leap_year(self.year) {
+            days += 1;
+        }
+        days
+    }
+
+    /// Returns the day of the week, from 1 for Monday to 7 for Sunday.
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday.
+        ((self.days() + 3) % 7) as u8 + 1
+    }
+
+    /// Returns the date and time moved by a number of seconds.
+    /// # Arguments
+    /// * `seconds` - a i32, negative to go back in time.
+    pub fn add_seconds(&self, seconds: i32) -> DateTime {
+        DateTime::from_timestamp((self.timestamp() as i64 + seconds as i64) as u32)
+    }
+
+    /// Returns the year, like 2021.
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    /// Returns the month, from 1 to 12.
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    /// Returns the day of the month, from 1 to 31.
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    /// Returns the hour, from 0 to 23.
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    /// Returns the minute, from 0 to 59.
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    /// Returns the second, from 0 to 59.
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    /// Formats the date and time as in ISO 8601 without allocating.
+    /// # Returns
+    /// * `a [u8; 19]` - The ASCII text like `2021-06-30 23:59:59`.
+    pub fn to_ascii(&self) -> [u8; 19] {
+        let mut text = *b"0000-00-00 00:00:00";
+        let mut put = |at: usize, value: u16, digits: usize| {
+            let mut value = value;
+            for i in (at..at + digits).rev() {
+                text[i] = b'0' + (value % 10) as u8;
+                value /= 10;
+            }
+        };
+        put(0, self.year, 4);
+        put(5, self.month as u16, 2);
+        put(8, self.day as u16, 2);
+        put(11, self.hour as u16, 2);
+        put(14, self.minute as u16, 2);
+        put(17, self.second as u16, 2);
+        text
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = self.to_ascii();
+        // The text holds only ASCII digits and separators.
+        f.write_str(core::str::from_utf8(&text).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// A source of the current date and time.
+pub trait Clock {
+    /// Returns the current date and time.
+    /// # Returns
+    /// * `a Result<DateTime>` - `NotReady` if the clock has not been set or lost the time.
+    fn now(&mut self) -> Result<DateTime>;
+
+    /// Sets the current date and time.
+    /// # Arguments
+    /// * `time` - a reference to `DateTime`, the new time.
+    fn set(&mut self, time: &DateTime) -> Result<()>;
+}
+
+/// A clock which can signal when a given time is reached.
+pub trait AlarmClock: Clock {
+    /// Programs the alarm, replacing the previous one.
+    /// # Arguments
+    /// * `time` - a reference to `DateTime`, the time at which the alarm fires.
+    fn set_alarm(&mut self, time: &DateTime) -> Result<()>;
+
+    /// Checks and acknowledges the alarm.
+    /// # Returns
+    /// * `a Result<bool>` - true if the alarm fired since the last call.
+    fn take_alarm(&mut self) -> Result<bool>;
+}
+
+impl<C: Clock + ?Sized> Clock for &mut C {
+    fn now(&mut self) -> Result<DateTime> {
+        (**self).now()
+    }
+
+    fn set(&mut self, time: &DateTime) -> Result<()> {
+        (**self).set(time)
+    }
+}
+
+impl<C: AlarmClock + ?Sized> AlarmClock for &mut C {
+    fn set_alarm(&mut self, time: &DateTime) -> Result<()> {
+        (**self).set_alarm(time)
+    }
+
+    fn take_alarm(&mut self) -> Result<bool> {
+        (**self).take_alarm()
+    }
+}
+
+/// A clock counting on from a set time with `millis`, which has to be running.
+/// It is lost on reset and `now` has to be called at least every 49 days,
+/// before the millisecond counter wraps twice.
+/// # Elements
+/// * `seconds` - a u32, the Unix time at `reference`.
+/// * `fraction` - a u32, the milliseconds after `seconds` at `reference`.
+/// * `reference` - a u32, the value of `millis` when the clock was last updated.
+/// * `drift` - a i32, the correction applied in parts per million.
+/// * `remainder` - a i64, the part of the correction not yet applied, in millionths of a millisecond.
+/// * `set_at` - a u32, the Unix time the clock was last set to.
+/// * `elapsed` - a u64, the milliseconds counted by `millis` since the clock was last set.
+/// * `valid` - a boolean, true once the clock has been set.
+/// * `alarm` - the Unix time of the alarm, `None` if there is none.
+#[cfg(feature = "millis")]
+pub struct SoftwareClock {
+    seconds: u32,
+    fraction: u32,
+    reference: u32,
+    drift: i32,
+    remainder: i64,
+    set_at: u32,
+    elapsed: u64,
+    valid: bool,
+    alarm: Option<u32>,
+}
+
+#[cfg(feature = "millis")]
+impl SoftwareClock {
+    /// Creates a clock which is not set yet.
+    pub const fn new() -> SoftwareClock {
+        SoftwareClock {
+            seconds: 0,
+            fraction: 0,
+            reference: 0,
+            drift: 0,
+            remainder: 0,
+            set_at: 0,
+            elapsed: 0,
+            valid: false,
+            alarm: None,
+        }
+    }
+
+    /// Returns the correction of the clock in parts per million.
+    pub fn drift(&self) -> i32 {
+        self.drift
+    }
+
+    /// Sets the correction of the clock, for example from a previous `calibrate`.
+    /// # Arguments
+    /// * `ppm` - a i32, positive if `millis` runs slow.
+    pub fn set_drift(&mut self, ppm: i32) {
+        self.update();
+        self.drift = ppm;
+    }
+
+    /// Computes the drift from a trusted time, received for example from a
+    /// GPS or a server, and sets the clock to it. The longer the clock ran since
+    /// it was set, the more precise the correction.
+    /// # Arguments
+    /// * `time` - a reference to `DateTime`, the correct current time.
+    /// # Returns
+    /// * `a Option<i32>` - The new correction in parts per million, `None` if the clock
+    ///   was not set before or ran less than a minute.
+    pub fn calibrate(&mut self, time: &DateTime) -> Option<i32> {
+        self.update();
+        let measured = self.elapsed as i64;
+        let result = if self.valid && measured >= 60_000 {
+            let actual = (time.timestamp() as i64 - self.set_at as i64) * 1000;
+            self.drift = ((actual - measured) * 1_000_000 / measured) as i32;
+            Some(self.drift)
+        } else {
+            None
+        };
+        self.set_time(time);
+        result
+    }
+
+    /// Sets the clock without failing.
+    fn set_time(&mut self, time: &DateTime) {
+        self.seconds = time.timestamp();
+        self.fraction = 0;
+        self.reference = millis();
+        self.remainder = 0;
+        self.set_at = self.seconds;
+        self.elapsed = 0;
+        self.valid = true;
+    }
+
+    /// Moves the time forward by the milliseconds elapsed since the last update.
+    fn update(&mut self) {
+        let now = millis();
+        let raw = now.wrapping_sub(self.reference);
+        self.reference = now;
+        self.elapsed += raw as u64;
+        self.remainder += raw as i64 * self.drift as i64;
+        let correction = self.remainder / 1_000_000;
+        self.remainder -= correction * 1_000_000;
+        let total = (self.fraction as i64 + raw as i64 + correction).max(0) as u64;
+        self.seconds = self.seconds.wrapping_add((total / 1000) as u32);
+        self.fraction = (total % 1000) as u32;
+    }
+}
+
+#[cfg(feature = "millis")]
+impl Default for SoftwareClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "millis")]
+impl Clock for SoftwareClock {
+    fn now(&mut self) -> Result<DateTime> {
+        if !self.valid {
+            return Err(crate::Error::NotReady);
+        }
+        self.update();
+        Ok(DateTime::from_timestamp(self.seconds))
+    }
+
+    fn set(&mut self, time: &DateTime) -> Result<()> {
+        self.set_time(time);
+        Ok(())
+    }
+}
+
+/// The alarm is only noticed when it is checked, as no interrupt is raised,
+/// so the MCU must not be put in power-down mode while waiting for it.
+#[cfg(feature = "millis")]
+impl AlarmClock for SoftwareClock {
+    fn set_alarm(&mut self, time: &DateTime) -> Result<()> {
+        self.alarm = Some(time.timestamp());
+        Ok(())
+    }
+
+    fn take_alarm(&mut self) -> Result<bool> {
+        let now = self.now()?.timestamp();
+        match self.alarm {
+            Some(alarm) if now >= alarm => {
+                self.alarm = None;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+/// A clock counting the seconds of Timer2 running from a 32.768 kHz watch crystal,
+/// which keeps the time while the MCU sleeps in power-save mode, unlike `SoftwareClock`.
+/// It is lost on reset. The overflows of the timer wake the MCU up, so with
+/// `AlarmScheduler::run_power_save` the alarm is checked once per timer period.
+/// # Elements
+/// * `timer` - a `AsyncTimer` object, running from the crystal.
+/// * `offset` - a u32, the Unix time when the timer was started.
+/// * `valid` - a boolean, true once the clock has been set.
+/// * `alarm` - the Unix time of the alarm, `None` if there is none.
+#[cfg(feature = "async-timer")]
+pub struct CrystalClock {
+    timer: AsyncTimer,
+    offset: u32,
+    valid: bool,
+    alarm: Option<u32>,
+}
+
+#[cfg(feature = "async-timer")]
+impl CrystalClock {
+    /// Creates a clock which is not set yet.
+    /// # Arguments
+    /// * `timer` - a `AsyncTimer` object, started with `Source::Crystal`.
+    /// # Returns
+    /// * `a CrystalClock object` - Which will be used for further implementations.
+    pub fn new(timer: AsyncTimer) -> CrystalClock {
+        CrystalClock {
+            timer,
+            offset: 0,
+            valid: false,
+            alarm: None,
+        }
+    }
+
+    /// Returns the timer, for example to put the MCU to sleep with it.
+    pub fn timer(&self) -> &AsyncTimer {
+        &self.timer
+    }
+
+    /// Gives back the timer, which keeps running.
+    pub fn release(self) -> AsyncTimer {
+        self.timer
+    }
+}
+
+#[cfg(feature = "async-timer")]
+impl Clock for CrystalClock {
+    fn now(&mut self) -> Result<DateTime> {
+        if !self.valid {
+            return Err(crate::Error::NotReady);
+        }
+        Ok(DateTime::from_timestamp(
+            self.offset.wrapping_add(self.timer.seconds()),
+        ))
+    }
+
+    fn set(&mut self, time: &DateTime) -> Result<()> {
+        self.offset = time.timestamp().wrapping_sub(self.timer.seconds());
+        self.valid = true;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async-timer")]
+impl AlarmClock for CrystalClock {
+    fn set_alarm(&mut self, time: &DateTime) -> Result<()> {
+        self.alarm = Some(time.timestamp());
+        Ok(())
+    }
+
+    fn take_alarm(&mut self) -> Result<bool> {
+        let now = self.now()?.timestamp();
+        match self.alarm {
+            Some(alarm) if now >= alarm => {
+                self.alarm = None;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timestamps_round_trip() {
+        let time = DateTime::new(2021, 6, 30, 23, 59, 58).unwrap();
+        assert_eq!(time.timestamp(), 1_625_097_598);
+        assert_eq!(DateTime::from_timestamp(1_625_097_598), time);
+        assert_eq!(time.weekday(), 3);
+        assert_eq!(&time.add_seconds(2).to_ascii(), b"2021-07-01 00:00:00");
+
+        let leap = DateTime::new(2024, 2, 29, 12, 0, 0).unwrap();
+        assert_eq!(DateTime::from_timestamp(leap.timestamp()), leap);
+        assert_eq!(DateTime::new(2023, 2, 29, 0, 0, 0), None);
+        assert_eq!(
+            DateTime::from_timestamp(0).to_ascii(),
+            *b"1970-01-01 00:00:00"
+        );
+    }
+}
@@ -0,0 +1,590 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A data logger sampling registered channels at fixed intervals.
+//! Every sample becomes a timestamped `Record` which is handed to a `LogSink`,
+//! so the same logging code can write to a serial port as CSV, to the
+//! internal EEPROM, to a `storage::CircularLog` on SPI flash or FRAM, to a
+//! CSV file on a SD card or to any other storage implementing the trait.
+//! `DataLogger` needs the `millis` feature, the sinks do not.
+
+use crate::hal::eeprom::{Eeprom, EEPROM_SIZE};
+#[cfg(feature = "millis")]
+use crate::hal::millis::millis;
+use crate::storage::{BlockDevice, CircularLog, FatVolume, File, Storage};
+use crate::Result;
+
+/// One sample of a channel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Record {
+    /// Value of `millis` when the sample was taken.
+    pub timestamp: u32,
+    /// Identifier of the channel.
+    pub channel: u8,
+    /// The sampled value, in the unit chosen by the channel.
+    pub value: i32,
+}
+
+/// Size of a record in the binary format.
+pub const RECORD_SIZE: usize = 9;
+
+impl Record {
+    /// Converts the record to its binary format,
+    /// little endian timestamp, channel and little endian value.
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[0..4].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[4] = self.channel;
+        bytes[5..9].copy_from_slice(&self.value.to_le_bytes());
+        bytes
+    }
+
+    /// Reads a record back from its binary format.
+    pub fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Record {
+        Record {
+            timestamp: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            channel: bytes[4],
+            value: i32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]),
+        }
+    }
+}
+
+/// A destination for records.
+pub trait LogSink {
+    /// Stores one record.
+    /// # Returns
+    /// * `a boolean` - false if the record could not be stored.
+    fn write(&mut self, record: &Record) -> bool;
+
+    /// Makes sure all the records written so far are stored.
+    fn flush(&mut self) -> bool {
+        true
+    }
+}
+
+/// A channel registered to the logger.
+#[cfg(feature = "millis")]
+#[derive(Clone, Copy)]
+struct Channel {
+    id: u8,
+    read: fn() -> i32,
+    interval: u32,
+    next_sample: u32,
+}
+
+/// A logger with at most `N` channels writing to the sink `S`.
+/// # Elements
+/// * `sink` - the `LogSink` receiving the records.
+/// * `channels` - the registered channels.
+/// * `failed` - a u16, the number of records the sink refused.
+/// * `clock` - a function returning the time in milliseconds, `millis` by default.
+#[cfg(feature = "millis")]
+pub struct DataLogger<S: LogSink, const N: usize> {
+    sink: S,
+    channels: [Option<Channel>; N],
+    failed: u16,
+    clock: fn() -> u32,
+}
+
+#[cfg(feature = "millis")]
+impl<S: LogSink, const N: usize> DataLogger<S, N> {
+    /// Creates a logger without any channel.
+    /// # Arguments
+    /// * `sink` - a `LogSink` object, where the records are written.
+    pub fn new(sink: S) -> Self {
+        DataLogger {
+            sink,
+            channels: [None; N],
+            failed: 0,
+            clock: millis,
+        }
+    }
+
+    /// Replaces `millis` as the source of the timestamps and of the sampling times,
+    /// before any channel is added.
+    /// # Arguments
+    /// * `clock` - a function returning the time in milliseconds, wrapping after 49 days.
+    pub fn set_clock(&mut self, clock: fn() -> u32) {
+        self.clock = clock;
+    }
+
+    /// Registers a channel which is sampled every `interval` milliseconds.
+    /// # Arguments
+    /// * `id` - a u8, the identifier written in the records of this channel.
+    /// * `interval` - a u32, the sampling interval in milliseconds.
+    /// * `read` - a function, which takes one sample.
+    /// # Returns
+    /// * `a boolean` - false if all the `N` channels are used.
+    pub fn add_channel(&mut self, id: u8, interval: u32, read: fn() -> i32) -> bool {
+        match self.channels.iter_mut().find(|c| c.is_none()) {
+            Some(slot) => {
+                *slot = Some(Channel {
+                    id,
+                    read,
+                    interval,
+                    next_sample: (self.clock)(),
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the channel with the given identifier.
+    pub fn remove_channel(&mut self, id: u8) {
+        for slot in self.channels.iter_mut() {
+            if let Some(channel) = slot {
+                if channel.id == id {
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    /// Samples every channel which is due and writes its record to the sink.
+    /// Call it often from the main loop or from a scheduler task.
+    /// # Returns
+    /// * `a usize` - the number of records written.
+    pub fn poll(&mut self) -> usize {
+        let mut written = 0;
+        for channel in self.channels.iter_mut().flatten() {
+            let now = (self.clock)();
+            if (now.wrapping_sub(channel.next_sample) as i32) < 0 {
+                continue;
+            }
+            channel.next_sample = channel.next_sample.wrapping_add(channel.interval);
+            if (now.wrapping_sub(channel.next_sample) as i32) >= 0 {
+                channel.next_sample = now.wrapping_add(channel.interval);
+            }
+
+            let record = Record {
+                timestamp: now,
+                channel: channel.id,
+                value: (channel.read)(),
+            };
+            if self.sink.write(&record) {
+                written += 1;
+            } else {
+                self.failed = self.failed.saturating_add(1);
+            }
+        }
+        written
+    }
+
+    /// Returns the number of records the sink refused.
+    pub fn failed(&self) -> u16 {
+        self.failed
+    }
+
+    /// Returns a mutable reference to the sink, for example to flush it.
+    pub fn sink(&mut self) -> &mut S {
+        &mut self.sink
+    }
+}
+
+/// Writes records as lines of comma separated values `timestamp,channel,value`.
+/// The bytes are given to a function, for example the `transmit_data` of a USART.
+pub struct CsvSink<F: FnMut(u8)> {
+    write_byte: F,
+}
+
+impl<F: FnMut(u8)> CsvSink<F> {
+    /// Creates a sink sending every byte to `write_byte`.
+    pub fn new(write_byte: F) -> Self {
+        CsvSink { write_byte }
+    }
+
+    /// Writes the header line naming the columns.
+    pub fn header(&mut self) {
+        for byte in b"timestamp,channel,value\r\n" {
+            (self.write_byte)(*byte);
+        }
+    }
+
+    /// Writes a number in decimal.
+    fn number(&mut self, value: i64) {
+        if value < 0 {
+            (self.write_byte)(b'-');
+        }
+        let mut digits = [0u8; 20];
+        let mut len = 0;
+        let mut rest = value.unsigned_abs();
+        loop {
+            digits[len] = b'0' + (rest % 10) as u8;
+            len += 1;
+            rest /= 10;
+            if rest == 0 {
+                break;
+            }
+        }
+        for digit in digits[..len].iter().rev() {
+            (self.write_byte)(*digit);
+        }
+    }
+}
+
+impl<F: FnMut(u8)> LogSink for CsvSink<F> {
+    fn write(&mut self, record: &Record) -> bool {
+        self.number(record.timestamp as i64);
+        (self.write_byte)(b',');
+        self.number(record.channel as i64);
+        (self.write_byte)(b',');
+        self.number(record.value as i64);
+        (self.write_byte)(b'\r');
+        (self.write_byte)(b'\n');
+        true
+    }
+}
+
+/// Stores records in binary form in a region of the internal EEPROM,
+/// overwriting the oldest records once the region is full.
+pub struct EepromSink {
+    start: u16,
+    slots: u16,
+    next: u16,
+}
+
+impl EepromSink {
+    /// Creates a sink using the EEPROM bytes from `start` up to `end`, excluded.
+    /// Writing starts again at the first slot after every reset.
+    /// # Returns
+    /// * `a Option<EepromSink>` - `None` if the region is outside the EEPROM or holds no record.
+    pub fn new(start: u16, end: u16) -> Option<EepromSink> {
+        if end > EEPROM_SIZE || end <= start {
+            return None;
+        }
+        let slots = (end - start) / RECORD_SIZE as u16;
+        if slots == 0 {
+            return None;
+        }
+        Some(EepromSink {
+            start,
+            slots,
+            next: 0,
+        })
+    }
+
+    /// Returns the number of records which fit in the region.
+    pub fn capacity(&self) -> u16 {
+        self.slots
+    }
+
+    /// Reads back the record stored in the given slot.
+    pub fn read(&self, slot: u16) -> Option<Record> {
+        if slot >= self.slots {
+            return None;
+        }
+        let mut bytes = [0; RECORD_SIZE];
+        Eeprom::new().read(self.start + slot * RECORD_SIZE as u16, &mut bytes);
+        Some(Record::from_bytes(&bytes))
+    }
+}
+
+impl LogSink for EepromSink {
+    fn write(&mut self, record: &Record) -> bool {
+        let address = self.start + self.next * RECORD_SIZE as u16;
+        self.next = (self.next + 1) % self.slots;
+        Eeprom::new().write(address, &record.to_bytes())
+    }
+}
+
+impl<S: Storage> LogSink for CircularLog<S, RECORD_SIZE> {
+    fn write(&mut self, record: &Record) -> bool {
+        self.append(&record.to_bytes())
+    }
+}
+
+/// Size of the buffer of a `FileSink`, a few lines of CSV.
+const FILE_BUFFER_SIZE: usize = 64;
+
+/// Longest CSV line of a record, with its end of line.
+const CSV_LINE_SIZE: usize = 30;
+
+/// Appends records as lines of CSV to a file of a FAT volume, for example on a SD card.
+/// Lines are kept in a small buffer and written when it is full or on `flush`,
+/// which saves rewriting the same block and directory entry for every record.
+/// # Elements
+/// * `volume` - the `FatVolume` holding the file.
+/// * `file` - the `File` appended to.
+/// * `buffer` - an array of u8, the lines not written yet.
+/// * `len` - a usize, the number of bytes in the buffer.
+pub struct FileSink<B: BlockDevice> {
+    volume: FatVolume<B>,
+    file: File,
+    buffer: [u8; FILE_BUFFER_SIZE],
+    len: usize,
+}
+
+impl<B: BlockDevice> FileSink<B> {
+    /// Opens a file for logging, creating it with a header line if it does not exist.
+    /// # Arguments
+    /// * `volume` - a `FatVolume` object, the mounted volume.
+    /// * `name` - a string slice, the 8.3 name of the file, like `"LOG.CSV"`.
+    /// # Returns
+    /// * `a Result<FileSink>` - The error of `FatVolume::create` or of the device.
+    pub fn new(mut volume: FatVolume<B>, name: &str) -> Result<FileSink<B>> {
+        let mut file = volume.create(name)?;
+        if file.size() == 0 {
+            volume.append(&mut file, b"timestamp,channel,value\r\n")?;
+        }
+        Ok(FileSink {
+            volume,
+            file,
+            buffer: [0; FILE_BUFFER_SIZE],
+            len: 0,
+        })
+    }
+
+    /// Writes the buffered lines and gives back the volume.
+    pub fn release(mut self) -> FatVolume<B> {
+        self.flush();
+        self.volume
+    }
+}
+
+impl<B: BlockDevice> LogSink for FileSink<B> {
+    fn write(&mut self, record: &Record) -> bool {
+        let mut line = [0; CSV_LINE_SIZE];
+        let mut length = 0;
+        CsvSink::new(|byte| {
+            line[length] = byte;
+            length += 1;
+        })
+        .write(record);
+        if self.len + length > FILE_BUFFER_SIZE && !self.flush() {
+            return false;
+        }
+        self.buffer[self.len..self.len + length].copy_from_slice(&line[..length]);
+        self.len += length;
+        true
+    }
+
+    fn flush(&mut self) -> bool {
+        if self.len == 0 {
+            return true;
+        }
+        let written = self
+            .volume
+            .append(&mut self.file, &self.buffer[..self.len])
+            .is_ok();
+        // On a full volume the bytes which fitted are kept, the others are lost.
+        self.len = 0;
+        written
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use crate::storage::BLOCK_SIZE;
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn binary_format() {
+        let record = Record {
+            timestamp: 0x0102_0304,
+            channel: 7,
+            value: -2,
+        };
+        let bytes = record.to_bytes();
+        assert_eq!(bytes, [4, 3, 2, 1, 7, 0xFE, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(Record::from_bytes(&bytes), record);
+    }
+
+    #[cfg(feature = "millis")]
+    #[test]
+    fn samples_channels_when_due() {
+        static mut NOW: u32 = 0;
+
+        fn clock() -> u32 {
+            unsafe { NOW }
+        }
+
+        fn temperature() -> i32 {
+            -1250
+        }
+
+        fn pressure() -> i32 {
+            101_325
+        }
+
+        struct Records(Vec<Record>);
+
+        impl LogSink for Records {
+            fn write(&mut self, record: &Record) -> bool {
+                self.0.push(*record);
+                true
+            }
+        }
+
+        let mut logger: DataLogger<Records, 2> = DataLogger::new(Records(Vec::new()));
+        logger.set_clock(clock);
+        unsafe { NOW = u32::MAX - 500 };
+        assert!(logger.add_channel(1, 1000, temperature));
+        assert!(logger.add_channel(2, 300, pressure));
+        assert!(!logger.add_channel(3, 10, pressure));
+        assert_eq!(logger.poll(), 2);
+        assert_eq!(logger.poll(), 0);
+        // Due times survive the overflow of the clock.
+        unsafe { NOW = u32::MAX - 200 };
+        assert_eq!(logger.poll(), 1);
+        unsafe { NOW = 499 };
+        assert_eq!(logger.poll(), 2);
+        // A late poll does not make up for the missed samples.
+        unsafe { NOW = 5000 };
+        assert_eq!(logger.poll(), 2);
+        unsafe { NOW = 5299 };
+        assert_eq!(logger.poll(), 0);
+        logger.remove_channel(1);
+        unsafe { NOW = 5300 };
+        assert_eq!(logger.poll(), 1);
+        let records = &logger.sink().0;
+        assert_eq!(records.len(), 8);
+        assert_eq!(
+            records[1],
+            Record {
+                timestamp: u32::MAX - 500,
+                channel: 2,
+                value: 101_325
+            }
+        );
+        assert_eq!(records[7].timestamp, 5300);
+    }
+
+    #[test]
+    fn csv_lines() {
+        let mut text = Vec::new();
+        let mut sink = CsvSink::new(|byte| text.push(byte));
+        sink.header();
+        sink.write(&Record {
+            timestamp: 4_294_967_295,
+            channel: 255,
+            value: i32::MIN,
+        });
+        assert_eq!(
+            &text[..],
+            &b"timestamp,channel,value\r\n4294967295,255,-2147483648\r\n"[..]
+        );
+    }
+
+    struct Memory([u8; 64]);
+
+    impl Storage for Memory {
+        fn capacity(&self) -> u32 {
+            64
+        }
+
+        fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+            let start = address as usize;
+            data.copy_from_slice(&self.0[start..start + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+            let start = address as usize;
+            self.0[start..start + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn circular_log_sink() {
+        let mut log: CircularLog<Memory, RECORD_SIZE> =
+            CircularLog::new(Memory([0xFF; 64]), 0, 64).unwrap();
+        for timestamp in 0..5 {
+            let record = Record {
+                timestamp,
+                channel: 1,
+                value: 10 * timestamp as i32,
+            };
+            assert!(log.write(&record));
+        }
+        assert_eq!(log.len(), 4);
+        let oldest = Record::from_bytes(&log.get(0).unwrap());
+        assert_eq!((oldest.timestamp, oldest.value), (1, 10));
+    }
+
+    struct RamDisk(Vec<[u8; BLOCK_SIZE]>);
+
+    impl BlockDevice for RamDisk {
+        fn block_count(&self) -> u32 {
+            self.0.len() as u32
+        }
+
+        fn read_block(&mut self, block: u32, data: &mut [u8; BLOCK_SIZE]) -> Result<()> {
+            *data = self.0[block as usize];
+            Ok(())
+        }
+
+        fn write_block(&mut self, block: u32, data: &[u8; BLOCK_SIZE]) -> Result<()> {
+            self.0[block as usize] = *data;
+            Ok(())
+        }
+    }
+
+    /// A FAT16 volume of 4200 clusters of one block, as in the tests of `storage::fat`.
+    fn formatted() -> RamDisk {
+        let mut disk = RamDisk(vec![[0; BLOCK_SIZE]; 4400]);
+        let boot = &mut disk.0[0];
+        boot[0] = 0xEB;
+        boot[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+        boot[0x0D] = 1;
+        boot[0x0E..0x10].copy_from_slice(&1u16.to_le_bytes());
+        boot[0x10] = 2;
+        boot[0x11..0x13].copy_from_slice(&512u16.to_le_bytes());
+        boot[0x13..0x15].copy_from_slice(&4400u16.to_le_bytes());
+        boot[0x16..0x18].copy_from_slice(&17u16.to_le_bytes());
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+        for fat in &[1, 18] {
+            disk.0[*fat][..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+        }
+        disk
+    }
+
+    #[test]
+    fn file_sink() {
+        let volume = FatVolume::mount(formatted()).unwrap();
+        let mut sink = FileSink::new(volume, "log.csv").unwrap();
+        for timestamp in 0..4 {
+            let record = Record {
+                timestamp: 1000 * timestamp,
+                channel: 3,
+                value: -7,
+            };
+            assert!(sink.write(&record));
+        }
+        // Opening the file again keeps its header and lines.
+        let mut sink = FileSink::new(sink.release(), "LOG.CSV").unwrap();
+        sink.write(&Record {
+            timestamp: 9,
+            channel: 0,
+            value: 0,
+        });
+        let mut volume = sink.release();
+        let mut file = volume.open("log.csv").unwrap();
+        let mut text = vec![0; 128];
+        let length = volume.read(&mut file, &mut text).unwrap();
+        assert_eq!(
+            &text[..length],
+            &b"timestamp,channel,value\r\n0,3,-7\r\n1000,3,-7\r\n2000,3,-7\r\n\
+3000,3,-7\r\n9,0,0\r\n"[..]
+        );
+    }
+}
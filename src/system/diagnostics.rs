@@ -0,0 +1,344 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Self tests for the bring-up of new boards, reported over serial.
+//! The I2C bus is scanned for devices, the SPI bus is checked with MOSI
+//! wired to MISO, pairs of pins jumpered together are driven and read back,
+//! and a byte of the EEPROM is written, read back and restored. Each check
+//! writes one line to the report, like `spi loopback: ok`, and a last line
+//! sums the checks up, so a board can be tested from a serial terminal.
+
+use crate::hal::eeprom::Eeprom;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::i2c::I2c;
+use embedded_hal::spi::SpiBus;
+use embedded_io::{Write, WriteFmtError};
+
+/// Bytes sent through the SPI loopback, with every bit both set and cleared.
+const SPI_PATTERN: [u8; 6] = [0x00, 0xFF, 0x55, 0xAA, 0x0F, 0xF0];
+
+/// The addresses answering an I2C scan.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct I2cScan {
+    found: u128,
+}
+
+impl I2cScan {
+    /// Returns true if a device acknowledged the address.
+    pub fn contains(&self, address: u8) -> bool {
+        address < 128 && self.found & (1 << address) != 0
+    }
+
+    /// Returns the number of devices found.
+    pub fn count(&self) -> u32 {
+        self.found.count_ones()
+    }
+
+    /// Returns the addresses of the devices found, in increasing order.
+    pub fn addresses(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..128u8).filter(move |address| self.contains(*address))
+    }
+}
+
+/// Probes every non reserved address of an I2C bus, 0x08 to 0x77, with an empty write.
+/// # Arguments
+/// * `bus` - an `I2c` object, the bus to be scanned.
+/// # Returns
+/// * `an I2cScan object` - the addresses which acknowledged.
+pub fn scan_i2c<I: I2c>(bus: &mut I) -> I2cScan {
+    let mut scan = I2cScan::default();
+    for address in 0x08..=0x77u8 {
+        if bus.write(address, &[]).is_ok() {
+            scan.found |= 1 << address;
+        }
+    }
+    scan
+}
+
+/// Checks that the bytes sent on a SPI bus come back, MOSI being wired to MISO.
+/// # Arguments
+/// * `bus` - a `SpiBus` object, the bus to be checked.
+/// # Returns
+/// * `a boolean` - true if every byte came back.
+pub fn spi_loopback<S: SpiBus>(bus: &mut S) -> bool {
+    let mut received = [0u8; SPI_PATTERN.len()];
+    if bus.transfer(&mut received, &SPI_PATTERN).is_err() || bus.flush().is_err() {
+        return false;
+    }
+    received == SPI_PATTERN
+}
+
+/// Checks that an output jumpered to an input drives it both ways.
+/// The output is left low.
+/// # Arguments
+/// * `output` - an `OutputPin` object, the pin driven, already an output.
+/// * `input` - an `InputPin` object, the pin read back, already an input without pull up.
+/// # Returns
+/// * `a boolean` - true if the input followed the output.
+pub fn gpio_loopback<O: OutputPin, I: InputPin>(output: &mut O, input: &mut I) -> bool {
+    let high = output.set_high().is_ok() && input.is_high().unwrap_or(false);
+    let low = output.set_low().is_ok() && input.is_low().unwrap_or(false);
+    high && low
+}
+
+/// Checks that a byte of the EEPROM can be written and read back, and restores it.
+/// # Arguments
+/// * `address` - a u16, the address of the byte used for the test.
+/// # Returns
+/// * `a boolean` - true if both test patterns were read back.
+pub fn eeprom_check(address: u16) -> bool {
+    let eeprom = Eeprom::new();
+    let original = eeprom.read_byte(address);
+    let mut passed = true;
+    for pattern in [0x55, 0xAA] {
+        passed &= eeprom.write_byte(address, pattern) && eeprom.read_byte(address) == pattern;
+    }
+    eeprom.write_byte(address, original) && passed
+}
+
+/// Runs checks and writes their results to a serial port or any other writer.
+/// # Elements
+/// * `out` - a `embedded_io::Write` object, receiving the report.
+/// * `passed` - a u8, the number of checks passed.
+/// * `failed` - a u8, the number of checks failed.
+pub struct Diagnostics<W> {
+    out: W,
+    passed: u8,
+    failed: u8,
+}
+
+impl<W: Write> Diagnostics<W> {
+    /// Starts a report.
+    /// # Arguments
+    /// * `out` - a `embedded_io::Write` object, for example the USART.
+    /// # Returns
+    /// * `a Diagnostics object` - Which will be used for further implementations.
+    pub fn new(out: W) -> Diagnostics<W> {
+        Diagnostics {
+            out,
+            passed: 0,
+            failed: 0,
+        }
+    }
+
+    /// Counts a check and writes its line.
+    fn result(&mut self, name: &str, passed: bool) -> Result<bool, WriteFmtError<W::Error>> {
+        if passed {
+            self.passed = self.passed.saturating_add(1);
+        } else {
+            self.failed = self.failed.saturating_add(1);
+        }
+        let outcome = if passed { "ok" } else { "FAIL" };
+        write!(self.out, "{}: {}\r\n", name, outcome)?;
+        Ok(passed)
+    }
+
+    /// Scans an I2C bus and lists the devices found, failing if the expected ones are missing.
+    /// # Arguments
+    /// * `bus` - an `I2c` object, the bus to be scanned.
+    /// * `expected` - a slice of u8, the addresses which must answer.
+    /// # Returns
+    /// * `an I2cScan object` - the addresses which acknowledged.
+    pub fn i2c<I: I2c>(
+        &mut self,
+        bus: &mut I,
+        expected: &[u8],
+    ) -> Result<I2cScan, WriteFmtError<W::Error>> {
+        let scan = scan_i2c(bus);
+        write!(self.out, "i2c:")?;
+        for address in scan.addresses() {
+            write!(self.out, " 0x{:02X}", address)?;
+        }
+        write!(self.out, " ({} found)\r\n", scan.count())?;
+        let complete = expected.iter().all(|address| scan.contains(*address));
+        self.result("i2c devices", complete)?;
+        Ok(scan)
+    }
+
+    /// Checks a SPI bus whose MOSI is wired to MISO.
+    /// # Arguments
+    /// * `bus` - a `SpiBus` object, the bus to be checked.
+    /// # Returns
+    /// * `a boolean` - true if the check passed.
+    pub fn spi<S: SpiBus>(&mut self, bus: &mut S) -> Result<bool, WriteFmtError<W::Error>> {
+        let passed = spi_loopback(bus);
+        self.result("spi loopback", passed)
+    }
+
+    /// Checks a pair of pins jumpered together.
+    /// # Arguments
+    /// * `name` - a string, naming the pair in the report, like `D2-D3`.
+    /// * `output` - an `OutputPin` object, the pin driven, already an output.
+    /// * `input` - an `InputPin` object, the pin read back, already an input without pull up.
+    /// # Returns
+    /// * `a boolean` - true if the check passed.
+    pub fn gpio<O: OutputPin, I: InputPin>(
+        &mut self,
+        name: &str,
+        output: &mut O,
+        input: &mut I,
+    ) -> Result<bool, WriteFmtError<W::Error>> {
+        let passed = gpio_loopback(output, input);
+        write!(self.out, "gpio ")?;
+        self.result(name, passed)
+    }
+
+    /// Checks a byte of the EEPROM.
+    /// # Arguments
+    /// * `address` - a u16, the address of the byte used for the test.
+    /// # Returns
+    /// * `a boolean` - true if the check passed.
+    pub fn eeprom(&mut self, address: u16) -> Result<bool, WriteFmtError<W::Error>> {
+        let passed = eeprom_check(address);
+        self.result("eeprom", passed)
+    }
+
+    /// Writes the summary line and gives back the writer.
+    /// # Returns
+    /// * `a tuple` - the writer, and true if every check passed.
+    pub fn finish(mut self) -> Result<(W, bool), WriteFmtError<W::Error>> {
+        write!(
+            self.out,
+            "diagnostics: {} passed, {} failed\r\n",
+            self.passed, self.failed
+        )?;
+        Ok((self.out, self.failed == 0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_hal::digital::ErrorType;
+    use embedded_hal::i2c::{ErrorKind, Operation};
+
+    struct Bus;
+
+    impl embedded_hal::i2c::ErrorType for Bus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Bus {
+        fn transaction(
+            &mut self,
+            address: u8,
+            _operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            match address {
+                0x3C | 0x68 => Ok(()),
+                _ => Err(ErrorKind::Other),
+            }
+        }
+    }
+
+    struct Loopback;
+
+    impl embedded_hal::spi::ErrorType for Loopback {
+        type Error = Infallible;
+    }
+
+    impl SpiBus for Loopback {
+        fn read(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+            words.fill(0);
+            Ok(())
+        }
+
+        fn write(&mut self, _words: &[u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+            read.copy_from_slice(write);
+            Ok(())
+        }
+
+        fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    /// A pin keeping the level last driven, an input left unconnected reads low.
+    struct Wire(bool);
+
+    impl ErrorType for Wire {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Wire {
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0 = true;
+            Ok(())
+        }
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0 = false;
+            Ok(())
+        }
+    }
+
+    impl InputPin for Wire {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    struct Out([u8; 128], usize);
+
+    impl embedded_io::ErrorType for Out {
+        type Error = Infallible;
+    }
+
+    impl Write for Out {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.0[self.1..self.1 + buf.len()].copy_from_slice(buf);
+            self.1 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn report() {
+        let scan = scan_i2c(&mut Bus);
+        assert_eq!(scan.count(), 2);
+        assert!(scan.contains(0x68) && !scan.contains(0x50));
+
+        let mut diagnostics = Diagnostics::new(Out([0; 128], 0));
+        diagnostics.i2c(&mut Bus, &[0x3C]).unwrap();
+        assert!(diagnostics.spi(&mut Loopback).unwrap());
+        let (mut output, mut open) = (Wire(false), Wire(false));
+        assert!(!diagnostics.gpio("D2", &mut output, &mut open).unwrap());
+        let (out, passed) = diagnostics.finish().unwrap();
+        assert!(!passed);
+        assert_eq!(
+            &out.0[..out.1],
+            &b"i2c: 0x3C 0x68 (2 found)\r\ni2c devices: ok\r\nspi loopback: ok\r\n\
+gpio D2: FAIL\r\ndiagnostics: 2 passed, 1 failed\r\n"[..]
+        );
+    }
+}
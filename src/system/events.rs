@@ -0,0 +1,139 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A fixed capacity queue of typed events.
+//! Interrupt service routines and drivers publish events and the main loop
+//! consumes them in order, which is the standard way for the interrupt driven
+//! parts of this crate to hand data over to the application.
+//! Publishing is done with interrupts disabled, so any number of interrupts
+//! and the main loop may publish, while only the main loop may consume.
+
+use super::battery::BatteryState;
+use crate::collections::Queue;
+use crate::hal::interrupts;
+use crate::hal::sleep_mode;
+
+/// The events which can be published on an `EventBus`.
+/// The numbers identify the source, for example the pin or the USART number.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// A button connected to the given pin was pressed.
+    ButtonPressed(u8),
+    /// A button connected to the given pin was released.
+    ButtonReleased(u8),
+    /// A byte was recieved by the given USART.
+    ByteReceived { usart: u8, byte: u8 },
+    /// The given timer or alarm fired.
+    TimerFired(u8),
+    /// The given sensor has a new reading ready.
+    SensorReady(u8),
+    /// The melody started by `hal::tone::play` is over.
+    MelodyFinished,
+    /// A `BatteryMonitor` changed to the given state.
+    Battery(BatteryState),
+    /// A `SoilProbe` started or stopped asking for water.
+    Soil { probe: u8, needs_water: bool },
+    /// A `Fan` stopped turning while commanded on, or turned again.
+    Fan { fan: u8, failed: bool },
+    /// An application defined event with a kind and a value.
+    User { kind: u8, value: u16 },
+}
+
+/// A queue of at most `N - 1` events.
+pub struct EventBus<const N: usize> {
+    queue: Queue<Event, N>,
+    dropped: core::cell::UnsafeCell<u16>,
+}
+
+unsafe impl<const N: usize> Sync for EventBus<N> {}
+
+impl<const N: usize> EventBus<N> {
+    /// Creates a new empty bus, usable in a `static`.
+    pub const fn new() -> Self {
+        EventBus {
+            queue: Queue::new(),
+            dropped: core::cell::UnsafeCell::new(0),
+        }
+    }
+
+    /// Publishes an event, from an interrupt service routine or the main loop.
+    /// # Arguments
+    /// * `event` - a `Event` object, the event to be published.
+    /// # Returns
+    /// * `a boolean` - false if the bus was full and the event was dropped.
+    pub fn publish(&self, event: Event) -> bool {
+        interrupts::free(|| {
+            // Producers are serialized by the critical section.
+            let ok = unsafe { self.queue.enqueue(event) }.is_ok();
+            if !ok {
+                unsafe { *self.dropped.get() = (*self.dropped.get()).saturating_add(1) };
+            }
+            ok
+        })
+    }
+
+    /// Takes the oldest event from the bus, must only be called from the main loop.
+    /// # Returns
+    /// * `a Option<Event>` - `None` if no event is waiting.
+    pub fn next(&self) -> Option<Event> {
+        unsafe { self.queue.dequeue() }
+    }
+
+    /// Waits for the next event, keeping the MCU in idle sleep mode meanwhile.
+    /// Must only be called from the main loop.
+    pub fn wait(&self) -> Event {
+        loop {
+            if let Some(event) = self.next() {
+                return event;
+            }
+            sleep_mode::idle();
+        }
+    }
+
+    /// Returns the number of events waiting on the bus.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns true if no event is waiting.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Returns the number of events dropped because the bus was full.
+    pub fn dropped(&self) -> u16 {
+        interrupts::free(|| unsafe { *self.dropped.get() })
+    }
+}
+
+impl<const N: usize> Default for EventBus<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The bus used by the drivers of this crate.
+pub static EVENTS: EventBus<16> = EventBus::new();
+
+/// Publishes an event on the crate wide bus `EVENTS`.
+pub fn publish(event: Event) -> bool {
+    EVENTS.publish(event)
+}
+
+/// Takes the oldest event from the crate wide bus `EVENTS`.
+pub fn next_event() -> Option<Event> {
+    EVENTS.next()
+}
@@ -0,0 +1,354 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Control of 4-wire PC fans, which have a PWM speed input and an open
+//! collector tach output giving, for most of them, two pulses per revolution.
+//! `FanCurve` maps a temperature to a speed with straight lines between
+//! points, and `Fan` drives the speed input, measures the speed with a
+//! `robotics::Tachometer` and reports a failure when the fan
+//! does not turn while commanded on. Failures and recoveries are published
+//! on `system::events::EVENTS` as `Event::Fan`.
+//! The PWM is given by `hal::fan::FanPwm` at 25 kHz, or any `SetDutyCycle`,
+//! and the tach pulses are counted by `hal::counter::FrequencyCounter`,
+//! with a pull up on the open collector output.
+//! Temperatures are in hundredths of a degree Celsius and speeds in percent.
+//! Many fans keep turning at their lowest speed with a duty cycle of 0,
+//! those do not stop when commanded off.
+
+use super::events::{self, Event};
+use super::thermostat::TemperatureSensor;
+use crate::robotics::{PulseCounter, Tachometer};
+use crate::{Error, Result};
+use embedded_hal::pwm::SetDutyCycle;
+
+/// A temperature to speed curve with `N` points.
+/// Below the first point the speed of the first point is used,
+/// above the last one the speed of the last one.
+pub struct FanCurve<const N: usize> {
+    points: [(i32, u8); N],
+}
+
+impl<const N: usize> FanCurve<N> {
+    /// Creates a curve.
+    /// # Arguments
+    /// * `points` - an array of tuples of a temperature and the speed in percent at it, by increasing temperatures.
+    /// # Returns
+    /// * `a Result<FanCurve>` - `InvalidArgument` if there is no point, if the temperatures do not increase or if a speed is above 100.
+    pub fn new(points: [(i32, u8); N]) -> Result<FanCurve<N>> {
+        if N == 0
+            || points.iter().any(|&(_, speed)| speed > 100)
+            || points.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+        {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(FanCurve { points })
+    }
+
+    /// Returns the speed at a temperature.
+    /// # Arguments
+    /// * `temperature` - an i32, the temperature in hundredths of a degree Celsius.
+    /// # Returns
+    /// * `a u8` - The speed in percent.
+    pub fn speed(&self, temperature: i32) -> u8 {
+        let (first, last) = (self.points[0], self.points[N - 1]);
+        if temperature <= first.0 {
+            return first.1;
+        }
+        if temperature >= last.0 {
+            return last.1;
+        }
+        let index = self
+            .points
+            .iter()
+            .position(|&(t, _)| t > temperature)
+            .unwrap_or(N - 1);
+        let ((t0, s0), (t1, s1)) = (self.points[index - 1], self.points[index]);
+        let (t0, s0, t1, s1) = (t0 as i64, s0 as i64, t1 as i64, s1 as i64);
+        (s0 + (s1 - s0) * (temperature as i64 - t0) / (t1 - t0)) as u8
+    }
+}
+
+/// A 4-wire fan.
+/// # Elements
+/// * `pwm` - the PWM driving the speed input.
+/// * `counter` - the counter of the tach pulses.
+/// * `tachometer` - a `Tachometer`, the speed averaged over `N` samples.
+/// * `id` - a u8, the number of the fan in the published events.
+/// * `speed` - a u8, the speed commanded in percent.
+/// * `min_speed` - a u8, the lowest speed other than 0 which is commanded.
+/// * `spin_up` - a u32, the time given to the fan to start turning in milliseconds.
+/// * `started` - a u32, the time at which the fan was last commanded on.
+/// * `failed` - a boolean, true while the fan does not turn while commanded on.
+/// * `publish` - a function receiving the events, `events::publish` by default.
+pub struct Fan<P: SetDutyCycle, C: PulseCounter, const N: usize> {
+    pwm: P,
+    counter: C,
+    tachometer: Tachometer<N>,
+    id: u8,
+    speed: u8,
+    min_speed: u8,
+    spin_up: u32,
+    started: u32,
+    failed: bool,
+    publish: fn(Event) -> bool,
+}
+
+impl<P: SetDutyCycle, C: PulseCounter, const N: usize> Fan<P, C, N> {
+    /// Creates a fan commanded off. The speed is sampled every second,
+    /// the lowest speed is 20 percent and the fan is given 3 seconds to start.
+    /// # Arguments
+    /// * `pwm` - a `SetDutyCycle` object, for example a `hal::fan::FanPwm`.
+    /// * `counter` - a `PulseCounter` object counting the tach pulses.
+    /// * `pulses_per_revolution` - a u16, the tach pulses given by one turn, 2 for most PC fans.
+    /// * `id` - a u8, the number of the fan in the published events.
+    /// # Returns
+    /// * `a Fan object` - Which will be used for further implementations.
+    pub fn new(mut pwm: P, counter: C, pulses_per_revolution: u16, id: u8) -> Fan<P, C, N> {
+        let _ = pwm.set_duty_cycle_fully_off();
+        Fan {
+            pwm,
+            counter,
+            tachometer: Tachometer::new(pulses_per_revolution, 1000),
+            id,
+            speed: 0,
+            min_speed: 20,
+            spin_up: 3000,
+            started: 0,
+            failed: false,
+            publish: events::publish,
+        }
+    }
+
+    /// Replaces `events::publish` as the receiver of the `Event::Fan` events,
+    /// for example to handle the failures without the event queue.
+    /// # Arguments
+    /// * `publish` - a function taking an event, false if it was dropped.
+    pub fn set_publisher(&mut self, publish: fn(Event) -> bool) {
+        self.publish = publish;
+    }
+
+    /// Sets the lowest speed other than 0, under which the fan may stall.
+    /// # Arguments
+    /// * `percent` - a u8, the lowest speed in percent.
+    pub fn set_min_speed(&mut self, percent: u8) {
+        self.min_speed = percent.min(100);
+    }
+
+    /// Sets the time given to the fan to start turning before it is failed.
+    /// # Arguments
+    /// * `spin_up` - a u32, the time in milliseconds, longer than the sampling period.
+    pub fn set_spin_up(&mut self, spin_up: u32) {
+        self.spin_up = spin_up;
+    }
+
+    /// Commands a speed. Speeds under the lowest speed, other than 0, are raised to it.
+    /// # Arguments
+    /// * `percent` - a u8, the speed in percent, 0 for off.
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    /// # Returns
+    /// * `a Result<()>` - the error of the PWM, mapped to `InvalidArgument`.
+    pub fn set_speed(&mut self, percent: u8, now: u32) -> Result<()> {
+        let mut percent = percent.min(100);
+        if percent != 0 && percent < self.min_speed {
+            percent = self.min_speed;
+        }
+        if percent == self.speed {
+            return Ok(());
+        }
+        self.pwm
+            .set_duty_cycle_percent(percent)
+            .map_err(|_| Error::InvalidArgument)?;
+        if self.speed == 0 {
+            self.started = now;
+        }
+        self.speed = percent;
+        self.tachometer.clear();
+        Ok(())
+    }
+
+    /// Commands the speed given by a curve for the temperature of a sensor.
+    /// The fan runs at full speed when the sensor cannot be read.
+    /// # Arguments
+    /// * `sensor` - a `TemperatureSensor` object.
+    /// * `curve` - a reference to the `FanCurve`.
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    /// # Returns
+    /// * `a Result<u8>` - The speed commanded, or the error of the sensor or the PWM.
+    pub fn regulate<S: TemperatureSensor, const M: usize>(
+        &mut self,
+        sensor: &mut S,
+        curve: &FanCurve<M>,
+        now: u32,
+    ) -> Result<u8> {
+        match sensor.temperature() {
+            Ok(temperature) => {
+                self.set_speed(curve.speed(temperature), now)?;
+                Ok(self.speed)
+            }
+            Err(error) => {
+                self.set_speed(100, now)?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Samples the speed if the period has passed and checks that the fan turns.
+    /// To be called often from the main loop.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, as returned by `millis`.
+    /// # Returns
+    /// * `a Option<u32>` - The averaged speed in revolutions per minute when a sample was taken.
+    pub fn poll(&mut self, now: u32) -> Option<u32> {
+        let rpm = self.tachometer.poll(&self.counter, now)?;
+        let failed = if rpm != 0 {
+            false
+        } else {
+            self.failed || (self.speed != 0 && now.wrapping_sub(self.started) >= self.spin_up)
+        };
+        if failed != self.failed {
+            self.failed = failed;
+            #[cfg(feature = "defmt")]
+            if failed {
+                defmt::warn!("fan {=u8} does not turn", self.id);
+            }
+            (self.publish)(Event::Fan {
+                fan: self.id,
+                failed,
+            });
+        }
+        Some(rpm)
+    }
+
+    /// Returns the speed commanded in percent.
+    pub fn speed(&self) -> u8 {
+        self.speed
+    }
+
+    /// Returns the averaged speed measured in revolutions per minute.
+    pub fn rpm(&self) -> u32 {
+        self.tachometer.rpm()
+    }
+
+    /// Returns true while the fan does not turn, from the end of its spin up
+    /// time until a sample finds it turning again.
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Switches the fan off and gives back the PWM and the counter.
+    pub fn release(mut self) -> (P, C) {
+        let _ = self.pwm.set_duty_cycle_fully_off();
+        (self.pwm, self.counter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test]
+    fn curve() {
+        let curve = FanCurve::new([(3000, 20), (4000, 50), (6000, 100)]).unwrap();
+        assert_eq!(curve.speed(-1000), 20);
+        assert_eq!(curve.speed(3000), 20);
+        assert_eq!(curve.speed(3500), 35);
+        assert_eq!(curve.speed(4000), 50);
+        assert_eq!(curve.speed(5000), 75);
+        assert_eq!(curve.speed(9000), 100);
+        assert!(FanCurve::new([(3000, 20), (3000, 50)]).is_err());
+        assert!(FanCurve::new([(3000, 120)]).is_err());
+    }
+
+    static mut PUBLISHED: Option<Event> = None;
+
+    fn publish(event: Event) -> bool {
+        unsafe { PUBLISHED = Some(event) };
+        true
+    }
+
+    fn published() -> Option<Event> {
+        unsafe {
+            let event = PUBLISHED;
+            PUBLISHED = None;
+            event
+        }
+    }
+
+    struct Pwm(u16);
+
+    impl embedded_hal::pwm::ErrorType for Pwm {
+        type Error = Error;
+    }
+
+    impl SetDutyCycle for Pwm {
+        fn max_duty_cycle(&self) -> u16 {
+            320
+        }
+
+        fn set_duty_cycle(&mut self, duty: u16) -> Result<()> {
+            self.0 = duty;
+            Ok(())
+        }
+    }
+
+    struct Counter(Cell<u32>);
+
+    impl PulseCounter for Counter {
+        fn pulses(&self) -> u32 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn failure() {
+        let mut fan: Fan<Pwm, Counter, 1> = Fan::new(Pwm(5), Counter(Cell::new(0)), 2, 0);
+        fan.set_publisher(publish);
+        assert_eq!(fan.pwm.0, 0);
+        fan.poll(0);
+        fan.set_speed(10, 0).unwrap();
+        assert_eq!((fan.speed(), fan.pwm.0), (20, 64));
+        // Not turning within the spin up time is tolerated.
+        assert_eq!(fan.poll(1000), Some(0));
+        assert!(!fan.is_failed());
+        fan.poll(2000);
+        assert_eq!(published(), None);
+        assert_eq!(fan.poll(3000), Some(0));
+        assert!(fan.is_failed());
+        assert_eq!(
+            published(),
+            Some(Event::Fan {
+                fan: 0,
+                failed: true
+            })
+        );
+        // 50 pulses in a second are 1500 revolutions per minute.
+        fan.counter.0.set(50);
+        assert_eq!(fan.poll(4000), Some(1500));
+        assert!(!fan.is_failed());
+        assert_eq!(
+            published(),
+            Some(Event::Fan {
+                fan: 0,
+                failed: false
+            })
+        );
+        fan.set_speed(0, 5000).unwrap();
+        assert_eq!(fan.poll(6000), Some(0));
+        assert!(!fan.is_failed());
+        assert_eq!(published(), None);
+    }
+}
@@ -0,0 +1,547 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! An in-system programmer for other AVR chips, speaking the STK500 version 1
+//! protocol of the ArduinoISP sketch over a serial port, so that avrdude can
+//! flash a second board through this one with `-c stk500v1 -b 19200`.
+//! The target is wired to the SPI bus, MOSI, MISO and SCK going to the same
+//! pins of the target, and its RESET to any output pin. The SPI clock must
+//! be below a quarter of the clock of the target, a factory new chip running
+//! at 1 MHz needs `SpiClock::Div128` or slower, in mode 0 with MSB first.
+//! Flash is written in pages, EEPROM byte by byte, with the waits of the
+//! datasheet after every write instead of polling the target.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+use embedded_io::{Read, Write};
+
+/// Bytes of the STK500 protocol.
+const STK_OK: u8 = 0x10;
+const STK_FAILED: u8 = 0x11;
+const STK_UNKNOWN: u8 = 0x12;
+const STK_INSYNC: u8 = 0x14;
+const STK_NOSYNC: u8 = 0x15;
+const CRC_EOP: u8 = 0x20;
+
+/// Commands of the STK500 protocol.
+const GET_SYNC: u8 = 0x30;
+const GET_SIGN_ON: u8 = 0x31;
+const GET_PARAMETER: u8 = 0x41;
+const SET_DEVICE: u8 = 0x42;
+const SET_DEVICE_EXT: u8 = 0x45;
+const ENTER_PROGMODE: u8 = 0x50;
+const LEAVE_PROGMODE: u8 = 0x51;
+const LOAD_ADDRESS: u8 = 0x55;
+const UNIVERSAL: u8 = 0x56;
+const PROG_FLASH: u8 = 0x60;
+const PROG_DATA: u8 = 0x61;
+const PROG_PAGE: u8 = 0x64;
+const READ_PAGE: u8 = 0x74;
+const READ_SIGN: u8 = 0x75;
+
+/// Largest page handled by `PROG_PAGE`.
+pub const MAX_PAGE: usize = 256;
+
+/// Time for a flash page to be written, in milliseconds.
+const FLASH_WRITE_MS: u32 = 5;
+
+/// Time for an EEPROM byte to be written, in milliseconds.
+const EEPROM_WRITE_MS: u32 = 4;
+
+/// Programs a target AVR chip over SPI for a host speaking STK500 version 1.
+/// # Elements
+/// * `spi` - a `SpiBus` object, wired to the target.
+/// * `reset` - a `OutputPin` object, wired to the RESET pin of the target.
+/// * `delay` - a `DelayNs` object, for the waits of the programming sequence.
+/// * `address` - a u16, the word address loaded by the host.
+/// * `page_size` - a u16, the flash page size of the target in bytes.
+/// * `eeprom_size` - a u16, the EEPROM size of the target in bytes.
+/// * `programming` - a boolean, true while the target is held in programming mode.
+/// * `buffer` - an array of u8, the page being written.
+pub struct IspProgrammer<S, R, D> {
+    spi: S,
+    reset: R,
+    delay: D,
+    address: u16,
+    page_size: u16,
+    eeprom_size: u16,
+    programming: bool,
+    buffer: [u8; MAX_PAGE],
+}
+
+impl<S: SpiBus, R: OutputPin, D: DelayNs> IspProgrammer<S, R, D> {
+    /// Creates the programmer, leaving the target running.
+    /// # Arguments
+    /// * `spi` - a `SpiBus` object, already set up in mode 0 and slow enough for the target.
+    /// * `reset` - a `OutputPin` object, already an output, wired to the RESET pin of the target.
+    /// * `delay` - a `DelayNs` object, like `delay::Delay`.
+    /// # Returns
+    /// * `a IspProgrammer object` - Which will be used for further implementations.
+    pub fn new(spi: S, mut reset: R, delay: D) -> IspProgrammer<S, R, D> {
+        reset.set_high().ok();
+        IspProgrammer {
+            spi,
+            reset,
+            delay,
+            address: 0,
+            page_size: 128,
+            eeprom_size: 1024,
+            programming: false,
+            buffer: [0; MAX_PAGE],
+        }
+    }
+
+    /// Returns true while the target is held in programming mode.
+    pub fn is_programming(&self) -> bool {
+        self.programming
+    }
+
+    /// Sends a 4 byte programming instruction to the target.
+    /// # Returns
+    /// * `a u8` - the last byte received, holding the data of read instructions.
+    fn instruction(&mut self, a: u8, b: u8, c: u8, d: u8) -> u8 {
+        let mut received = [0; 4];
+        match self.spi.transfer(&mut received, &[a, b, c, d]) {
+            Ok(()) => received[3],
+            Err(_) => 0xFF,
+        }
+    }
+
+    /// Resets the target and enables its serial programming.
+    /// # Returns
+    /// * `a boolean` - false if the target did not echo the programming enable instruction.
+    fn start(&mut self) -> bool {
+        // SCK is low, as in SPI mode 0, while RESET is pulsed and then held low.
+        self.reset.set_low().ok();
+        self.delay.delay_ms(20);
+        self.reset.set_high().ok();
+        self.delay.delay_us(100);
+        self.reset.set_low().ok();
+        self.delay.delay_ms(50);
+        let mut received = [0; 4];
+        let echoed = self
+            .spi
+            .transfer(&mut received, &[0xAC, 0x53, 0x00, 0x00])
+            .is_ok()
+            && received[2] == 0x53;
+        self.programming = true;
+        echoed
+    }
+
+    /// Releases the target, which starts its new program.
+    fn end(&mut self) {
+        self.reset.set_high().ok();
+        self.programming = false;
+    }
+
+    /// Returns the word address of the flash page holding a word address.
+    fn page_of(&self, address: u16) -> u16 {
+        let words = (self.page_size / 2).max(1);
+        address & !(words - 1)
+    }
+
+    /// Writes the bytes of the buffer to the flash, from the loaded address.
+    fn write_flash(&mut self, length: usize) {
+        let mut page = self.page_of(self.address);
+        for pair in 0..length / 2 {
+            if self.page_of(self.address) != page {
+                self.commit(page);
+                page = self.page_of(self.address);
+            }
+            let [high, low] = self.address.to_be_bytes();
+            let (first, second) = (self.buffer[2 * pair], self.buffer[2 * pair + 1]);
+            self.instruction(0x40, high, low, first);
+            self.instruction(0x48, high, low, second);
+            self.address = self.address.wrapping_add(1);
+        }
+        self.commit(page);
+    }
+
+    /// Writes the loaded flash page.
+    fn commit(&mut self, page: u16) {
+        let [high, low] = page.to_be_bytes();
+        self.instruction(0x4C, high, low, 0);
+        self.delay.delay_ms(FLASH_WRITE_MS);
+    }
+
+    /// Writes the bytes of the buffer to the EEPROM, from the loaded address.
+    fn write_eeprom(&mut self, length: usize) {
+        let start = self.address.wrapping_mul(2);
+        for i in 0..length {
+            let [high, low] = start.wrapping_add(i as u16).to_be_bytes();
+            let data = self.buffer[i];
+            self.instruction(0xC0, high, low, data);
+            self.delay.delay_ms(EEPROM_WRITE_MS);
+        }
+    }
+
+    /// Reads one byte from the host.
+    fn byte<P: Read>(port: &mut P) -> Result<u8, P::Error> {
+        let mut byte = [0];
+        while port.read(&mut byte)? == 0 {}
+        Ok(byte[0])
+    }
+
+    /// Reads and ignores a number of bytes from the host.
+    fn skip<P: Read>(port: &mut P, count: usize) -> Result<(), P::Error> {
+        for _ in 0..count {
+            Self::byte(port)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the end of the command and answers it.
+    /// # Arguments
+    /// * `port` - the serial port of the host.
+    /// * `data` - a reference to `[u8]`, the bytes answered between the sync and the status.
+    /// * `ok` - a boolean, false to answer a failure.
+    fn reply<P: Read + Write>(port: &mut P, data: &[u8], ok: bool) -> Result<(), P::Error> {
+        if Self::byte(port)? != CRC_EOP {
+            return port.write_all(&[STK_NOSYNC]);
+        }
+        port.write_all(&[STK_INSYNC])?;
+        port.write_all(data)?;
+        port.write_all(&[if ok { STK_OK } else { STK_FAILED }])
+    }
+
+    /// Receives and executes one command of the host.
+    /// # Arguments
+    /// * `port` - a `embedded_io::Read` and `Write` object, like the USART at 19200 baud.
+    /// # Returns
+    /// * `a Result` - the errors of the serial port.
+    pub fn serve<P: Read + Write>(&mut self, port: &mut P) -> Result<(), P::Error> {
+        match Self::byte(port)? {
+            GET_SYNC => Self::reply(port, &[], true),
+            GET_SIGN_ON => Self::reply(port, b"AVR ISP", true),
+            GET_PARAMETER => {
+                let value = match Self::byte(port)? {
+                    // Hardware version, software major and minor version.
+                    0x80 => 2,
+                    0x81 => 1,
+                    0x82 => 18,
+                    // A serial programmer.
+                    0x93 => b'S',
+                    _ => 0,
+                };
+                Self::reply(port, &[value], true)
+            }
+            SET_DEVICE => {
+                let mut parameters = [0; 20];
+                for parameter in parameters.iter_mut() {
+                    *parameter = Self::byte(port)?;
+                }
+                self.page_size = u16::from_be_bytes([parameters[12], parameters[13]]);
+                self.eeprom_size = u16::from_be_bytes([parameters[14], parameters[15]]);
+                Self::reply(port, &[], true)
+            }
+            SET_DEVICE_EXT => {
+                Self::skip(port, 5)?;
+                Self::reply(port, &[], true)
+            }
+            ENTER_PROGMODE => {
+                let ok = self.start();
+                Self::reply(port, &[], ok)
+            }
+            LEAVE_PROGMODE => {
+                self.end();
+                Self::reply(port, &[], true)
+            }
+            LOAD_ADDRESS => {
+                let low = Self::byte(port)?;
+                let high = Self::byte(port)?;
+                self.address = u16::from_le_bytes([low, high]);
+                Self::reply(port, &[], true)
+            }
+            UNIVERSAL => {
+                let mut bytes = [0; 4];
+                for byte in bytes.iter_mut() {
+                    *byte = Self::byte(port)?;
+                }
+                let result = self.instruction(bytes[0], bytes[1], bytes[2], bytes[3]);
+                Self::reply(port, &[result], true)
+            }
+            PROG_FLASH => {
+                Self::skip(port, 2)?;
+                Self::reply(port, &[], true)
+            }
+            PROG_DATA => {
+                Self::skip(port, 1)?;
+                Self::reply(port, &[], true)
+            }
+            PROG_PAGE => {
+                let length = u16::from_be_bytes([Self::byte(port)?, Self::byte(port)?]) as usize;
+                let memory = Self::byte(port)?;
+                if length > MAX_PAGE {
+                    Self::skip(port, length)?;
+                    return Self::reply(port, &[], false);
+                }
+                for i in 0..length {
+                    self.buffer[i] = Self::byte(port)?;
+                }
+                if Self::byte(port)? != CRC_EOP {
+                    return port.write_all(&[STK_NOSYNC]);
+                }
+                let ok = match memory {
+                    b'F' => {
+                        self.write_flash(length);
+                        true
+                    }
+                    b'E' if length <= self.eeprom_size as usize => {
+                        self.write_eeprom(length);
+                        true
+                    }
+                    _ => false,
+                };
+                port.write_all(&[STK_INSYNC, if ok { STK_OK } else { STK_FAILED }])
+            }
+            READ_PAGE => {
+                let length = u16::from_be_bytes([Self::byte(port)?, Self::byte(port)?]) as usize;
+                let memory = Self::byte(port)?;
+                if Self::byte(port)? != CRC_EOP {
+                    return port.write_all(&[STK_NOSYNC]);
+                }
+                port.write_all(&[STK_INSYNC])?;
+                let ok = match memory {
+                    b'F' => {
+                        for _ in 0..length / 2 {
+                            let [high, low] = self.address.to_be_bytes();
+                            let first = self.instruction(0x20, high, low, 0);
+                            let second = self.instruction(0x28, high, low, 0);
+                            port.write_all(&[first, second])?;
+                            self.address = self.address.wrapping_add(1);
+                        }
+                        true
+                    }
+                    b'E' => {
+                        let start = self.address.wrapping_mul(2);
+                        for i in 0..length {
+                            let [high, low] = start.wrapping_add(i as u16).to_be_bytes();
+                            let data = self.instruction(0xA0, high, low, 0);
+                            port.write_all(&[data])?;
+                        }
+                        true
+                    }
+                    _ => false,
+                };
+                port.write_all(&[if ok { STK_OK } else { STK_FAILED }])
+            }
+            READ_SIGN => {
+                let mut signature = [0; 3];
+                for (i, byte) in signature.iter_mut().enumerate() {
+                    *byte = self.instruction(0x30, 0, i as u8, 0);
+                }
+                Self::reply(port, &signature, true)
+            }
+            // A lone end of packet means the host lost the sync.
+            CRC_EOP => port.write_all(&[STK_NOSYNC]),
+            _ => {
+                if Self::byte(port)? == CRC_EOP {
+                    port.write_all(&[STK_UNKNOWN])
+                } else {
+                    port.write_all(&[STK_NOSYNC])
+                }
+            }
+        }
+    }
+
+    /// Serves the host forever, ignoring the errors of the serial port.
+    /// # Arguments
+    /// * `port` - a `embedded_io::Read` and `Write` object, like the USART at 19200 baud.
+    pub fn run<P: Read + Write>(&mut self, port: &mut P) -> ! {
+        loop {
+            self.serve(port).ok();
+        }
+    }
+
+    /// Releases the target and gives back the SPI bus, the reset pin and the delay.
+    pub fn release(mut self) -> (S, R, D) {
+        self.end();
+        (self.spi, self.reset, self.delay)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::convert::Infallible;
+
+    /// A target answering the signature of an ATmega328p and recording the page writes.
+    struct Target {
+        loaded: [u8; 8],
+        committed: [u16; 4],
+        commits: usize,
+    }
+
+    impl embedded_hal::spi::ErrorType for Target {
+        type Error = Infallible;
+    }
+
+    impl SpiBus for Target {
+        fn read(&mut self, _words: &mut [u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn write(&mut self, _words: &[u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+            read.fill(0);
+            match write[0] {
+                0xAC => read[2] = 0x53,
+                0x30 => read[3] = [0x1E, 0x95, 0x0F][write[2] as usize],
+                0x40 | 0x48 => {
+                    let index = 2 * (write[2] as usize % 4) + (write[0] == 0x48) as usize;
+                    self.loaded[index] = write[3];
+                }
+                0x4C => {
+                    self.committed[self.commits] = u16::from_be_bytes([write[1], write[2]]);
+                    self.commits += 1;
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    struct Reset(bool);
+
+    impl embedded_hal::digital::ErrorType for Reset {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Reset {
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0 = true;
+            Ok(())
+        }
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0 = false;
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    /// The host side of the serial line.
+    struct Host<'a> {
+        input: &'a [u8],
+        output: [u8; 32],
+        len: usize,
+    }
+
+    impl embedded_io::ErrorType for Host<'_> {
+        type Error = Infallible;
+    }
+
+    impl Read for Host<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            buf[0] = self.input[0];
+            self.input = &self.input[1..];
+            Ok(1)
+        }
+    }
+
+    impl Write for Host<'_> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.output[self.len..self.len + buf.len()].copy_from_slice(buf);
+            self.len += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stk500_session() {
+        let target = Target {
+            loaded: [0; 8],
+            committed: [0; 4],
+            commits: 0,
+        };
+        let mut programmer = IspProgrammer::new(target, Reset(false), NoDelay);
+        let mut device = [SET_DEVICE; 22];
+        device[1..21].fill(0);
+        // Pages of 4 bytes, 2 words, so that 4 words span 2 pages.
+        device[14] = 4;
+        device[21] = CRC_EOP;
+        let mut input = [0; 64];
+        let commands: [&[u8]; 6] = [
+            &[GET_SYNC, CRC_EOP],
+            &[ENTER_PROGMODE, CRC_EOP],
+            &[READ_SIGN, CRC_EOP],
+            &device,
+            &[LOAD_ADDRESS, 0x02, 0x00, CRC_EOP],
+            &[PROG_PAGE, 0, 8, b'F', 1, 2, 3, 4, 5, 6, 7, 8, CRC_EOP],
+        ];
+        let mut len = 0;
+        for command in commands.iter() {
+            input[len..len + command.len()].copy_from_slice(command);
+            len += command.len();
+        }
+        input[len..len + 2].copy_from_slice(&[0x99, CRC_EOP]);
+        let mut host = Host {
+            input: &input[..len + 2],
+            output: [0; 32],
+            len: 0,
+        };
+        for _ in 0..commands.len() + 1 {
+            programmer.serve(&mut host).unwrap();
+        }
+        assert!(programmer.is_programming());
+        assert_eq!(
+            &host.output[..host.len],
+            &[
+                STK_INSYNC,
+                STK_OK,
+                STK_INSYNC,
+                STK_OK,
+                STK_INSYNC,
+                0x1E,
+                0x95,
+                0x0F,
+                STK_OK,
+                STK_INSYNC,
+                STK_OK,
+                STK_INSYNC,
+                STK_OK,
+                STK_INSYNC,
+                STK_OK,
+                STK_UNKNOWN
+            ]
+        );
+        let (target, reset, _) = programmer.release();
+        assert!(reset.0);
+        assert_eq!(target.loaded, [5, 6, 7, 8, 1, 2, 3, 4]);
+        assert_eq!(&target.committed[..target.commits], &[2, 4]);
+    }
+}
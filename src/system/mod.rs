@@ -0,0 +1,61 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Chip independent system services built on top of the HAL.
+
+pub mod scheduler;
+
+pub mod events;
+
+pub mod datalogger;
+
+pub mod clock;
+
+pub mod alarms;
+
+pub mod battery;
+
+#[cfg(feature = "millis")]
+pub mod supervisor;
+
+pub mod bootloader;
+
+pub mod stack;
+
+pub mod stats;
+
+pub mod weather;
+
+pub mod accumulator;
+
+pub mod soil;
+
+pub mod relays;
+
+pub mod thermostat;
+
+pub mod fan;
+
+#[cfg(feature = "watchdog-interrupt")]
+pub mod node;
+
+pub mod diagnostics;
+
+pub mod watch;
+
+pub mod isp;
+
+pub mod chip_temperature;
@@ -0,0 +1,241 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Battery powered sensor nodes which wake up, read a sensor, transmit the
+//! reading and go back to sleep. Between two cycles every peripheral clock is
+//! stopped in PRR, the ADC is turned off and the chip sleeps in power-down
+//! mode, woken up by the watchdog interrupt. A bare ATmega328P then draws
+//! about 4 µA at 3.3 V with the brown-out detector disabled by the fuses,
+//! which is the figure to measure a node against with a µA meter in series.
+//! The board around it, regulators, USB chips and LEDs, often draws far more.
+//! `average_microamps` estimates the current of a whole cycle from those
+//! measures, to compare it with the capacity of the battery.
+//! The module needs the `watchdog-interrupt` feature, which wakes the node up.
+
+use crate::hal::interrupts::Interrupt;
+use crate::hal::power::{gate_all_except, set_clock_prescaler, ClockPrescaler, Peripherals};
+use crate::hal::sleep_mode::power_down_for;
+use crate::hal::watchdog::WatchdogTimeout;
+use crate::Result;
+
+/// The sensor read once per cycle.
+pub trait NodeSensor {
+    /// The reading handed to the link.
+    type Reading;
+
+    /// Reads the sensor, the peripherals kept running are clocked.
+    fn read(&mut self) -> Result<Self::Reading>;
+}
+
+/// The radio or serial link sending the readings.
+pub trait NodeLink<R> {
+    /// Sends a reading. It must only return once the last byte has left the
+    /// chip, since the clocks of the peripherals are stopped right after.
+    fn transmit(&mut self, reading: &R) -> Result<()>;
+}
+
+/// Estimates the average current of a node from measures taken while it is
+/// awake and while it sleeps.
+/// # Arguments
+/// * `active` - a u32, the current while awake in microamperes.
+/// * `active_millis` - a u32, the time awake per cycle in milliseconds.
+/// * `sleep` - a u32, the current while asleep in microamperes.
+/// * `sleep_millis` - a u32, the time asleep per cycle in milliseconds.
+/// # Returns
+/// * `a u32` - the average current in microamperes, rounded up.
+pub fn average_microamps(active: u32, active_millis: u32, sleep: u32, sleep_millis: u32) -> u32 {
+    let period = active_millis as u64 + sleep_millis as u64;
+    if period == 0 {
+        return sleep;
+    }
+    let charge = active as u64 * active_millis as u64 + sleep as u64 * sleep_millis as u64;
+    let average = charge / period;
+    if average * period < charge {
+        average as u32 + 1
+    } else {
+        average as u32
+    }
+}
+
+/// Sets up the power saving of a `SensorNode`.
+/// # Elements
+/// * `prescaler` - a `ClockPrescaler`, the division of the clock while awake.
+/// * `running` - a slice of `Peripherals`, the peripherals clocked while awake.
+/// * `timeout` - a `WatchdogTimeout`, the time of one power-down.
+/// * `sleeps` - a u16, the number of power-downs between two cycles.
+#[derive(Clone, Copy)]
+pub struct SensorNodeBuilder {
+    prescaler: ClockPrescaler,
+    running: &'static [Peripherals],
+    timeout: WatchdogTimeout,
+    sleeps: u16,
+}
+
+impl SensorNodeBuilder {
+    /// Starts with the undivided clock, no peripheral clocked and one
+    /// sleep of 8 seconds per cycle.
+    /// # Returns
+    /// * `a SensorNodeBuilder object` - Which will be used for further implementations.
+    pub fn new() -> SensorNodeBuilder {
+        SensorNodeBuilder {
+            prescaler: ClockPrescaler::Div1,
+            running: &[],
+            timeout: WatchdogTimeout::S8,
+            sleeps: 1,
+        }
+    }
+
+    /// Divides the clock while awake. The USART used by the link must then be
+    /// set up for the divided clock, and `millis` slows down as much.
+    /// # Arguments
+    /// * `prescaler` - a `ClockPrescaler`, the division of the clock.
+    pub fn clock_prescaler(mut self, prescaler: ClockPrescaler) -> SensorNodeBuilder {
+        self.prescaler = prescaler;
+        self
+    }
+
+    /// Sets the peripherals clocked while awake, the others are always stopped.
+    /// # Arguments
+    /// * `running` - a slice of `Peripherals`, for example the TWI of the sensor and the USART of the link.
+    pub fn peripherals(mut self, running: &'static [Peripherals]) -> SensorNodeBuilder {
+        self.running = running;
+        self
+    }
+
+    /// Sets the time asleep between two cycles, as a number of watchdog time-outs.
+    /// # Arguments
+    /// * `timeout` - a `WatchdogTimeout`, the time of one power-down.
+    /// * `sleeps` - a u16, the number of power-downs, at least 1.
+    pub fn sleep(mut self, timeout: WatchdogTimeout, sleeps: u16) -> SensorNodeBuilder {
+        self.timeout = timeout;
+        self.sleeps = sleeps.max(1);
+        self
+    }
+
+    /// Creates the node.
+    /// # Arguments
+    /// * `sensor` - a `NodeSensor` object, read once per cycle.
+    /// * `link` - a `NodeLink` object, sending the readings.
+    /// # Returns
+    /// * `a SensorNode object` - Which will be used for further implementations.
+    pub fn build<S, L>(self, sensor: S, link: L) -> SensorNode<S, L>
+    where
+        S: NodeSensor,
+        L: NodeLink<S::Reading>,
+    {
+        SensorNode {
+            sensor,
+            link,
+            config: self,
+            cycles: 0,
+            failures: 0,
+        }
+    }
+}
+
+impl Default for SensorNodeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A node reading a sensor and transmitting the reading once per cycle.
+/// # Elements
+/// * `sensor` - the sensor read once per cycle.
+/// * `link` - the link sending the readings.
+/// * `config` - a `SensorNodeBuilder` object, the power saving settings.
+/// * `cycles` - a u32, the number of cycles run.
+/// * `failures` - a u32, the number of cycles whose read or transmit failed.
+pub struct SensorNode<S, L> {
+    sensor: S,
+    link: L,
+    config: SensorNodeBuilder,
+    cycles: u32,
+    failures: u32,
+}
+
+impl<S, L> SensorNode<S, L>
+where
+    S: NodeSensor,
+    L: NodeLink<S::Reading>,
+{
+    /// Wakes the peripherals up, reads the sensor, transmits the reading,
+    /// and sleeps until the next cycle.
+    /// # Returns
+    /// * `a Result` - the error of the sensor or of the link.
+    pub fn cycle(&mut self) -> Result<()> {
+        set_clock_prescaler(self.config.prescaler);
+        gate_all_except(self.config.running);
+
+        let result = self
+            .sensor
+            .read()
+            .and_then(|reading| self.link.transmit(&reading));
+        self.cycles = self.cycles.wrapping_add(1);
+        if result.is_err() {
+            self.failures = self.failures.wrapping_add(1);
+        }
+
+        gate_all_except(&[]);
+        for _ in 0..self.config.sleeps {
+            power_down_for(self.config.timeout);
+        }
+        result
+    }
+
+    /// Enables the interrupts and runs cycles forever, counting the failed ones.
+    pub fn run(mut self) -> ! {
+        unsafe { Interrupt::new().enable() };
+        loop {
+            self.cycle().ok();
+        }
+    }
+
+    /// Returns the nominal time asleep per cycle in milliseconds.
+    pub fn sleep_millis(&self) -> u32 {
+        self.config.timeout.millis() * self.config.sleeps as u32
+    }
+
+    /// Returns the number of cycles run.
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    /// Returns the number of cycles whose read or transmit failed.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Gives back the sensor and the link.
+    pub fn release(self) -> (S, L) {
+        (self.sensor, self.link)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn microamp_budget() {
+        // 5 mA for 40 ms every 10 minutes on a node sleeping at 4 µA.
+        let average = average_microamps(5_000, 40, 4, 8_000 * 75);
+        assert_eq!(average, 5);
+        assert!(average < 10);
+        assert_eq!(average_microamps(1_000, 1, 0, 0), 1_000);
+        assert_eq!(average_microamps(0, 0, 4, 0), 4);
+    }
+}
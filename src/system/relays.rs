@@ -0,0 +1,392 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Relay outputs for irrigation valves, pumps, heaters or lights, switched on
+//! for a given time by hand or by weekly schedule entries. Every channel has
+//! a name, an optional maximum on time after which it is cut off whatever
+//! was requested, and an optional group: only one relay of a group is on at
+//! a time, for example the valves sharing one water supply, and scheduled
+//! runs of a busy group wait for their turn.
+//! Times come from a `DateTime`, usually read from a real time clock, and
+//! `next_event` gives the time to program as the next alarm of the clock,
+//! so the controller fits next to an `AlarmScheduler` in a sleeping design.
+
+use super::alarms::{next_occurrence, EVERY_DAY};
+use super::clock::DateTime;
+
+/// Identifies a channel of a `RelayController`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RelayId(u8);
+
+/// Identifies a schedule entry of a `RelayController`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EntryId(u8);
+
+/// Group value of the channels which are not exclusive with any other.
+pub const NO_GROUP: u8 = 0;
+
+/// A relay output.
+#[derive(Clone, Copy)]
+struct Channel {
+    name: &'static str,
+    /// Switches the relay, true for on.
+    set: fn(bool),
+    group: u8,
+    /// Longest time on in seconds, 0 for no limit.
+    max_on: u32,
+    /// Unix time at which the relay was switched on, `None` while off.
+    on_since: Option<u32>,
+    /// Unix time at which the relay is switched off.
+    off_at: u32,
+    /// Duration of a scheduled run waiting for its group, 0 if none.
+    pending: u32,
+}
+
+/// A weekly run of a channel.
+#[derive(Clone, Copy)]
+struct Entry {
+    channel: u8,
+    /// Seconds after midnight at which the run starts.
+    time: u32,
+    /// Days on which the run starts, bit 0 for Monday to bit 6 for Sunday.
+    days: u8,
+    /// Length of the run in seconds.
+    duration: u32,
+}
+
+/// A controller of at most `C` relays and `E` schedule entries.
+pub struct RelayController<const C: usize, const E: usize> {
+    channels: [Option<Channel>; C],
+    entries: [Option<Entry>; E],
+    /// Unix time of the last call of `update`.
+    last: Option<u32>,
+    cutoffs: u16,
+}
+
+impl<const C: usize, const E: usize> RelayController<C, E> {
+    /// Creates a controller without any channel.
+    pub const fn new() -> Self {
+        RelayController {
+            channels: [None; C],
+            entries: [None; E],
+            last: None,
+            cutoffs: 0,
+        }
+    }
+
+    /// Adds a channel, switched off.
+    /// # Arguments
+    /// * `name` - a string slice, the name of the channel, like "lawn".
+    /// * `set` - a function switching the relay, called with true for on.
+    /// * `group` - a u8, the channels of the same group are never on together, `NO_GROUP` for none.
+    /// * `max_on` - a u32, the longest time on in seconds, 0 for no limit.
+    /// # Returns
+    /// * `a Option<RelayId>` - `None` if the controller is full.
+    pub fn add_channel(
+        &mut self,
+        name: &'static str,
+        set: fn(bool),
+        group: u8,
+        max_on: u32,
+    ) -> Option<RelayId> {
+        let index = self.channels.iter().position(|c| c.is_none())?;
+        set(false);
+        self.channels[index] = Some(Channel {
+            name,
+            set,
+            group,
+            max_on,
+            on_since: None,
+            off_at: 0,
+            pending: 0,
+        });
+        Some(RelayId(index as u8))
+    }
+
+    /// Finds a channel by its name.
+    pub fn find(&self, name: &str) -> Option<RelayId> {
+        self.channels
+            .iter()
+            .position(|c| matches!(c, Some(c) if c.name == name))
+            .map(|index| RelayId(index as u8))
+    }
+
+    /// Returns the name of a channel.
+    pub fn name(&self, id: RelayId) -> Option<&'static str> {
+        Some(self.channel(id)?.name)
+    }
+
+    /// Returns true while a channel is on.
+    pub fn is_on(&self, id: RelayId) -> bool {
+        matches!(self.channel(id), Some(c) if c.on_since.is_some())
+    }
+
+    /// Returns the number of times a channel was cut off by its maximum on time.
+    pub fn cutoffs(&self) -> u16 {
+        self.cutoffs
+    }
+
+    fn channel(&self, id: RelayId) -> Option<&Channel> {
+        self.channels.get(id.0 as usize)?.as_ref()
+    }
+
+    /// Returns true if another channel of the group of `index` is on.
+    fn group_busy(&self, index: usize) -> bool {
+        let group = match &self.channels[index] {
+            Some(channel) if channel.group != NO_GROUP => channel.group,
+            _ => return false,
+        };
+        self.channels.iter().enumerate().any(|(i, c)| {
+            i != index && matches!(c, Some(c) if c.group == group && c.on_since.is_some())
+        })
+    }
+
+    /// Switches a channel on, or extends its run.
+    fn start(&mut self, index: usize, duration: u32, now: u32) {
+        if let Some(channel) = &mut self.channels[index] {
+            channel.pending = 0;
+            channel.off_at = match channel.on_since {
+                Some(_) => channel.off_at.max(now + duration),
+                None => now + duration,
+            };
+            if channel.on_since.is_none() {
+                channel.on_since = Some(now);
+                (channel.set)(true);
+            }
+        }
+    }
+
+    /// Switches a channel on by hand.
+    /// # Arguments
+    /// * `id` - a `RelayId`, the channel.
+    /// * `duration` - a u32, the time in seconds after which it is switched off.
+    /// * `now` - a reference to `DateTime`, the current time.
+    /// # Returns
+    /// * `a boolean` - false if another channel of its group is on.
+    pub fn turn_on(&mut self, id: RelayId, duration: u32, now: &DateTime) -> bool {
+        let index = id.0 as usize;
+        if self.channel(id).is_none() || self.group_busy(index) {
+            return false;
+        }
+        self.start(index, duration, now.timestamp());
+        true
+    }
+
+    /// Switches a channel off and drops its waiting scheduled run.
+    pub fn turn_off(&mut self, id: RelayId) {
+        if let Some(Some(channel)) = self.channels.get_mut(id.0 as usize) {
+            channel.pending = 0;
+            if channel.on_since.take().is_some() {
+                (channel.set)(false);
+            }
+        }
+    }
+
+    /// Switches all the channels off, for example when a leak is detected.
+    pub fn all_off(&mut self) {
+        for index in 0..C {
+            self.turn_off(RelayId(index as u8));
+        }
+    }
+
+    /// Adds a weekly run of a channel.
+    /// # Arguments
+    /// * `id` - a `RelayId`, the channel.
+    /// * `hour` - a u8, from 0 to 23.
+    /// * `minute` - a u8, from 0 to 59.
+    /// * `days` - a u8, a combination of the day constants of `alarms` like `WEEKDAYS`.
+    /// * `duration` - a u32, the length of the run in seconds.
+    /// # Returns
+    /// * `a Option<EntryId>` - `None` if the schedule is full or an argument is not valid.
+    pub fn schedule(
+        &mut self,
+        id: RelayId,
+        hour: u8,
+        minute: u8,
+        days: u8,
+        duration: u32,
+    ) -> Option<EntryId> {
+        if self.channel(id).is_none() || hour > 23 || minute > 59 || days & EVERY_DAY == 0 {
+            return None;
+        }
+        let index = self.entries.iter().position(|e| e.is_none())?;
+        self.entries[index] = Some(Entry {
+            channel: id.0,
+            time: hour as u32 * 3600 + minute as u32 * 60,
+            days: days & EVERY_DAY,
+            duration,
+        });
+        Some(EntryId(index as u8))
+    }
+
+    /// Removes a schedule entry.
+    pub fn remove_schedule(&mut self, id: EntryId) {
+        if let Some(entry) = self.entries.get_mut(id.0 as usize) {
+            *entry = None;
+        }
+    }
+
+    /// Starts the scheduled runs which became due since the previous call, switches
+    /// off the channels whose run is over or which reached their maximum on time,
+    /// and starts the waiting runs whose group became free.
+    /// Call it at least every minute, or at the times given by `next_event`.
+    /// # Arguments
+    /// * `now` - a reference to `DateTime`, the current time.
+    pub fn update(&mut self, now: &DateTime) {
+        let time = now.timestamp();
+        let since = match self.last {
+            Some(last) if last <= time => last,
+            _ => time - now.second() as u32 - 1,
+        };
+        self.last = Some(time);
+
+        for entry in self.entries.iter().flatten() {
+            if matches!(next_occurrence(entry.time, entry.days, since), Some(at) if at <= time) {
+                if let Some(channel) = &mut self.channels[entry.channel as usize] {
+                    channel.pending = channel.pending.max(entry.duration);
+                }
+            }
+        }
+
+        for channel in self.channels.iter_mut().flatten() {
+            let on_since = match channel.on_since {
+                Some(on_since) => on_since,
+                None => continue,
+            };
+            let cut = channel.max_on != 0 && time - on_since >= channel.max_on;
+            if cut || time >= channel.off_at {
+                channel.on_since = None;
+                (channel.set)(false);
+            }
+            if cut && time < channel.off_at {
+                self.cutoffs = self.cutoffs.saturating_add(1);
+                #[cfg(feature = "defmt")]
+                defmt::warn!("relay {} cut off", channel.name);
+            }
+        }
+
+        for index in 0..C {
+            let pending = match &self.channels[index] {
+                Some(channel) => channel.pending,
+                None => 0,
+            };
+            if pending != 0 && !self.group_busy(index) {
+                self.start(index, pending, time);
+            }
+        }
+    }
+
+    /// Returns the time at which `update` has something to do next.
+    /// # Arguments
+    /// * `after` - a reference to `DateTime`, usually the current time.
+    /// # Returns
+    /// * `a Option<DateTime>` - The next start or stop, `None` if nothing is on or scheduled.
+    pub fn next_event(&self, after: &DateTime) -> Option<DateTime> {
+        let after = after.timestamp();
+        let starts = self
+            .entries
+            .iter()
+            .flatten()
+            .filter_map(|entry| next_occurrence(entry.time, entry.days, after));
+        let stops = self.channels.iter().flatten().filter_map(|channel| {
+            let on_since = channel.on_since?;
+            Some(match channel.max_on {
+                0 => channel.off_at,
+                max_on => channel.off_at.min(on_since + max_on),
+            })
+        });
+        starts
+            .chain(stops)
+            .min()
+            .map(|at| DateTime::from_timestamp(at.max(after)))
+    }
+}
+
+impl<const C: usize, const E: usize> Default for RelayController<C, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::alarms::MONDAY;
+    use super::*;
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    static OUTPUTS: AtomicU8 = AtomicU8::new(0);
+
+    fn set(bit: u8, on: bool) {
+        if on {
+            OUTPUTS.fetch_or(bit, Ordering::Relaxed);
+        } else {
+            OUTPUTS.fetch_and(!bit, Ordering::Relaxed);
+        }
+    }
+
+    fn lawn(on: bool) {
+        set(1, on)
+    }
+
+    fn beds(on: bool) {
+        set(2, on)
+    }
+
+    fn pump(on: bool) {
+        set(4, on)
+    }
+
+    fn at(hour: u8, minute: u8) -> DateTime {
+        // 2021-03-01 was a Monday.
+        DateTime::new(2021, 3, 1, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn groups_schedules_and_cutoffs() {
+        let mut relays: RelayController<3, 4> = RelayController::new();
+        let lawn = relays.add_channel("lawn", lawn, 1, 0).unwrap();
+        let beds = relays.add_channel("beds", beds, 1, 0).unwrap();
+        let pump = relays.add_channel("pump", pump, NO_GROUP, 600).unwrap();
+        assert_eq!(relays.find("beds"), Some(beds));
+
+        relays.schedule(lawn, 6, 0, MONDAY, 900).unwrap();
+        relays.schedule(beds, 6, 5, EVERY_DAY, 300).unwrap();
+        assert_eq!(relays.next_event(&at(5, 0)), Some(at(6, 0)));
+
+        relays.update(&at(5, 59));
+        relays.update(&at(6, 0));
+        assert!(relays.is_on(lawn));
+        assert_eq!(OUTPUTS.load(Ordering::Relaxed) & 3, 1);
+
+        // The beds wait for the lawn, which shares the water supply.
+        relays.update(&at(6, 5));
+        assert!(!relays.is_on(beds));
+        assert_eq!(relays.next_event(&at(6, 5)), Some(at(6, 15)));
+        relays.update(&at(6, 15));
+        assert!(!relays.is_on(lawn));
+        assert!(relays.is_on(beds));
+        assert!(!relays.turn_on(lawn, 60, &at(6, 16)));
+
+        // The pump asked for an hour is cut off after ten minutes.
+        assert!(relays.turn_on(pump, 3600, &at(7, 0)));
+        relays.update(&at(7, 9));
+        assert!(relays.is_on(pump));
+        relays.update(&at(7, 10));
+        assert!(!relays.is_on(pump));
+        assert_eq!(relays.cutoffs(), 1);
+        assert_eq!(OUTPUTS.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,285 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A tiny cooperative run to completion task scheduler.
+//! Every task is a plain function which runs either periodically or once
+//! at a given time, as measured by `millis`. The main loop only has to call
+//! `Scheduler::run` repeatedly, which replaces the usual super loop full of delays.
+//! `ClockedScheduler` runs the tasks on any `Clocked` clock, a `VirtualClock`
+//! lets tests and simulations run hours of tasks at once.
+//! `Scheduler` needs the `millis` feature.
+
+use super::stats::TaskStats;
+use crate::time::Clocked;
+#[cfg(feature = "millis")]
+use crate::time::HardwareClock;
+
+/// Identifies a task added to a `Scheduler`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TaskId(u8);
+
+/// A task known to the scheduler.
+#[derive(Clone, Copy)]
+struct Task {
+    run: fn(),
+    /// Period in milliseconds, 0 for a task which runs once.
+    period: u32,
+    /// Value of `millis` at which the task is due next.
+    next_run: u32,
+    enabled: bool,
+    /// Execution times of the task.
+    stats: TaskStats,
+}
+
+/// Returns true if the time `at` has been reached at time `now`,
+/// correctly handling the overflow of the millisecond counter.
+fn is_due(now: u32, at: u32) -> bool {
+    (now.wrapping_sub(at) as i32) >= 0
+}
+
+/// A scheduler holding at most `N` tasks, timed by the `millis` counter.
+#[cfg(feature = "millis")]
+pub type Scheduler<const N: usize> = ClockedScheduler<HardwareClock, N>;
+
+/// A scheduler holding at most `N` tasks, timed by the clock `C`.
+pub struct ClockedScheduler<C, const N: usize> {
+    tasks: [Option<Task>; N],
+    idle: bool,
+    clock: C,
+}
+
+#[cfg(feature = "millis")]
+impl<const N: usize> ClockedScheduler<HardwareClock, N> {
+    /// Creates a new scheduler without any task.
+    /// Idling between tasks is enabled by default.
+    pub const fn new() -> Self {
+        ClockedScheduler {
+            tasks: [None; N],
+            idle: true,
+            clock: HardwareClock::new(),
+        }
+    }
+}
+
+impl<C: Clocked, const N: usize> ClockedScheduler<C, N> {
+    /// Creates a new scheduler without any task, timed by the given clock.
+    /// Idling between tasks is enabled by default.
+    /// # Arguments
+    /// * `clock` - a `Clocked` object, for example a `VirtualClock`.
+    /// # Returns
+    /// * `a ClockedScheduler object` - which will be used for further implementations.
+    pub fn with_clock(clock: C) -> Self {
+        ClockedScheduler {
+            tasks: [None; N],
+            idle: true,
+            clock,
+        }
+    }
+
+    /// Returns the clock of the scheduler, so that a simulated one can be moved forward.
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Adds a task in the first free slot.
+    fn add(&mut self, task: Task) -> Option<TaskId> {
+        let index = self.tasks.iter().position(|t| t.is_none())?;
+        self.tasks[index] = Some(task);
+        Some(TaskId(index as u8))
+    }
+
+    /// Adds a task which runs every `period` milliseconds, first after one period.
+    /// # Arguments
+    /// * `period` - a u32, the period of the task in milliseconds.
+    /// * `run` - a function, the task itself.
+    /// # Returns
+    /// * `a Option<TaskId>` - `None` if the scheduler is full.
+    pub fn every(&mut self, period: u32, run: fn()) -> Option<TaskId> {
+        let period = if period == 0 { 1 } else { period };
+        self.add(Task {
+            run,
+            period,
+            next_run: self.clock.millis().wrapping_add(period),
+            enabled: true,
+            stats: TaskStats::new(),
+        })
+    }
+
+    /// Adds a task which runs once after `delay` milliseconds.
+    /// # Arguments
+    /// * `delay` - a u32, the time after which the task is run in milliseconds.
+    /// * `run` - a function, the task itself.
+    /// # Returns
+    /// * `a Option<TaskId>` - `None` if the scheduler is full.
+    pub fn after(&mut self, delay: u32, run: fn()) -> Option<TaskId> {
+        self.at(self.clock.millis().wrapping_add(delay), run)
+    }
+
+    /// Adds a task which runs once when `millis` reaches `time`.
+    /// # Arguments
+    /// * `time` - a u32, the value of `millis` at which the task is run.
+    /// * `run` - a function, the task itself.
+    /// # Returns
+    /// * `a Option<TaskId>` - `None` if the scheduler is full.
+    pub fn at(&mut self, time: u32, run: fn()) -> Option<TaskId> {
+        self.add(Task {
+            run,
+            period: 0,
+            next_run: time,
+            enabled: true,
+            stats: TaskStats::new(),
+        })
+    }
+
+    /// Removes a task from the scheduler.
+    pub fn cancel(&mut self, id: TaskId) {
+        if let Some(slot) = self.tasks.get_mut(id.0 as usize) {
+            *slot = None;
+        }
+    }
+
+    /// Pauses or resumes a task without removing it.
+    /// A resumed periodic task runs one period after being resumed.
+    pub fn set_enabled(&mut self, id: TaskId, enabled: bool) {
+        if let Some(Some(task)) = self.tasks.get_mut(id.0 as usize) {
+            if enabled && !task.enabled && task.period != 0 {
+                task.next_run = self.clock.millis().wrapping_add(task.period);
+            }
+            task.enabled = enabled;
+        }
+    }
+
+    /// Changes the period of a periodic task, it takes effect after the next run.
+    pub fn set_period(&mut self, id: TaskId, period: u32) {
+        if let Some(Some(task)) = self.tasks.get_mut(id.0 as usize) {
+            if task.period != 0 && period != 0 {
+                task.period = period;
+            }
+        }
+    }
+
+    /// Returns the execution times of a periodic task.
+    /// # Arguments
+    /// * `id` - a `TaskId`, the task.
+    /// # Returns
+    /// * `a Option<TaskStats>` - `None` if there is no such task.
+    pub fn stats(&self, id: TaskId) -> Option<TaskStats> {
+        self.tasks.get(id.0 as usize)?.map(|t| t.stats)
+    }
+
+    /// Chooses whether `run` puts the MCU in idle sleep mode when no task is due.
+    pub fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+    }
+
+    /// Returns the number of milliseconds till the next task is due,
+    /// `None` if there is no enabled task.
+    pub fn next_due(&self) -> Option<u32> {
+        let now = self.clock.millis();
+        self.tasks
+            .iter()
+            .flatten()
+            .filter(|t| t.enabled)
+            .map(|t| {
+                if is_due(now, t.next_run) {
+                    0
+                } else {
+                    t.next_run.wrapping_sub(now)
+                }
+            })
+            .min()
+    }
+
+    /// Runs every task which is due, in the order in which they were added.
+    /// If no task was due and idling is enabled the MCU sleeps until the next interrupt,
+    /// which is at the latest the next tick of the millisecond counter.
+    /// A simulated clock moves forward to its next millisecond instead.
+    pub fn run(&mut self) {
+        let mut ran = false;
+        for slot in self.tasks.iter_mut() {
+            let task = match slot {
+                Some(task) if task.enabled => task,
+                _ => continue,
+            };
+            let now = self.clock.millis();
+            if !is_due(now, task.next_run) {
+                continue;
+            }
+
+            let run = task.run;
+            if task.period == 0 {
+                *slot = None;
+            } else {
+                task.next_run = task.next_run.wrapping_add(task.period);
+                // Skip the missed runs instead of running the task many times in a row.
+                if is_due(now, task.next_run) {
+                    task.next_run = now.wrapping_add(task.period);
+                }
+            }
+            let start = self.clock.micros();
+            run();
+            if let Some(task) = slot {
+                task.stats.record(self.clock.micros().wrapping_sub(start));
+            }
+            ran = true;
+        }
+
+        if !ran && self.idle {
+            self.clock.idle();
+        }
+    }
+}
+
+#[cfg(feature = "millis")]
+impl<const N: usize> Default for ClockedScheduler<HardwareClock, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::time::VirtualClock;
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    static TICKS: AtomicU32 = AtomicU32::new(0);
+    static ONCE: AtomicU32 = AtomicU32::new(0);
+
+    fn tick() {
+        TICKS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn once() {
+        ONCE.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn virtual_time() {
+        let mut scheduler: ClockedScheduler<VirtualClock, 2> =
+            ClockedScheduler::with_clock(VirtualClock::new());
+        scheduler.every(10, tick).unwrap();
+        scheduler.after(25, once).unwrap();
+        assert_eq!(scheduler.next_due(), Some(10));
+
+        while scheduler.clock().millis() <= 1000 {
+            scheduler.run();
+        }
+        assert_eq!(TICKS.load(Ordering::Relaxed), 100);
+        assert_eq!(ONCE.load(Ordering::Relaxed), 1);
+        assert_eq!(scheduler.next_due(), Some(9));
+    }
+}
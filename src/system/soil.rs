@@ -0,0 +1,294 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Analog soil moisture probes, capacitive or resistive. The dry and wet
+//! readings of every probe differ, so each one has a `SoilCalibration`
+//! taken in dry air and in water and persisted through `storage::Settings`.
+//! Readings are converted to a moisture percentage, and a change of the
+//! "needs watering" state is published on `system::events::EVENTS` as
+//! `Event::Soil`, with a hysteresis so that the state does not flicker
+//! while the water spreads in the soil.
+
+use super::events::{self, Event};
+use crate::storage::{Plain, Settings, Storage, SETTINGS_HEADER_SIZE};
+
+/// Readings of a probe in dry air and in water.
+/// # Elements
+/// * `dry` - a u16, the ADC reading of the dry probe.
+/// * `wet` - a u16, the ADC reading of the probe in water.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SoilCalibration {
+    pub dry: u16,
+    pub wet: u16,
+}
+
+unsafe impl Plain for SoilCalibration {}
+
+impl SoilCalibration {
+    /// Typical readings of a capacitive probe powered at 5 V.
+    pub const CAPACITIVE: SoilCalibration = SoilCalibration { dry: 600, wet: 280 };
+
+    /// Converts a reading to the moisture of the soil.
+    /// Readings may decrease with moisture, as for capacitive probes, or increase.
+    /// # Arguments
+    /// * `reading` - a u16, the ADC reading.
+    /// # Returns
+    /// * `a u8` - The moisture in percent, 0 when dry and 100 in water.
+    pub fn percent(&self, reading: u16) -> u8 {
+        let (dry, wet, reading) = (self.dry as i32, self.wet as i32, reading as i32);
+        if dry == wet {
+            return 0;
+        }
+        ((reading - dry) * 100 / (wet - dry)).clamp(0, 100) as u8
+    }
+}
+
+/// Version of the calibration settings block.
+const CALIBRATION_VERSION: u16 = 1;
+
+/// Number of bytes of storage used by the calibration of a probe.
+pub const CALIBRATION_SIZE: u32 = SETTINGS_HEADER_SIZE + 4;
+
+/// A soil moisture probe.
+/// # Elements
+/// * `read` - a function returning the ADC reading of the probe.
+/// * `id` - a u8, the number of the probe in the published events.
+/// * `calibration` - the persisted calibration.
+/// * `reading` - a u16, the filtered reading, 0 before the first update.
+/// * `start` - a u8, the moisture in percent under which the soil needs water.
+/// * `stop` - a u8, the moisture in percent above which it does not anymore.
+/// * `needs_water` - a boolean, the current state.
+/// * `publish` - a function receiving the events, `events::publish` by default.
+pub struct SoilProbe<S: Storage> {
+    read: fn() -> u16,
+    id: u8,
+    calibration: Settings<SoilCalibration, S>,
+    reading: u16,
+    start: u8,
+    stop: u8,
+    needs_water: bool,
+    publish: fn(Event) -> bool,
+}
+
+impl<S: Storage> SoilProbe<S> {
+    /// Creates a probe and loads its calibration, the `CAPACITIVE` one if none is stored.
+    /// It needs water under 30 percent of moisture and stops needing it above 45 percent.
+    /// # Arguments
+    /// * `read` - a function returning the ADC reading of the probe, for example of `analog_read`.
+    /// * `id` - a u8, the number of the probe in the published events.
+    /// * `storage` - a `Storage` object, where the calibration is kept.
+    /// * `address` - a u32, the address of the `CALIBRATION_SIZE` bytes used.
+    pub fn new(read: fn() -> u16, id: u8, storage: S, address: u32) -> Self {
+        let mut calibration = Settings::new(
+            storage,
+            address,
+            CALIBRATION_VERSION,
+            SoilCalibration::CAPACITIVE,
+        );
+        calibration.load();
+        SoilProbe {
+            read,
+            id,
+            calibration,
+            reading: 0,
+            start: 30,
+            stop: 45,
+            needs_water: false,
+            publish: events::publish,
+        }
+    }
+
+    /// Replaces `events::publish` as the receiver of the `Event::Soil` events,
+    /// for example to send them to another `EventBus`.
+    /// # Arguments
+    /// * `publish` - a function taking an event, false if it was dropped.
+    pub fn set_publisher(&mut self, publish: fn(Event) -> bool) {
+        self.publish = publish;
+    }
+
+    /// Sets the thresholds of the "needs watering" state.
+    /// # Arguments
+    /// * `start` - a u8, the moisture in percent under which the soil needs water.
+    /// * `stop` - a u8, the moisture in percent above which it does not anymore, above `start`.
+    pub fn set_thresholds(&mut self, start: u8, stop: u8) {
+        self.start = start;
+        self.stop = stop.max(start);
+    }
+
+    /// Returns the calibration in use.
+    pub fn calibration(&self) -> SoilCalibration {
+        *self.calibration.get()
+    }
+
+    /// Replaces the calibration and stores it.
+    /// # Returns
+    /// * `a boolean` - false if the storage could not be written.
+    pub fn set_calibration(&mut self, calibration: SoilCalibration) -> bool {
+        *self.calibration.get_mut() = calibration;
+        self.calibration.save()
+    }
+
+    /// Takes the current reading as the dry one and stores the calibration.
+    /// Call it with the probe in dry air.
+    pub fn calibrate_dry(&mut self) -> bool {
+        self.calibration.get_mut().dry = (self.read)();
+        self.calibration.save()
+    }
+
+    /// Takes the current reading as the wet one and stores the calibration.
+    /// Call it with the probe in water up to its line.
+    pub fn calibrate_wet(&mut self) -> bool {
+        self.calibration.get_mut().wet = (self.read)();
+        self.calibration.save()
+    }
+
+    /// Returns the moisture of the last update in percent.
+    pub fn percent(&self) -> u8 {
+        self.calibration.get().percent(self.reading)
+    }
+
+    /// Returns true while the soil needs water.
+    pub fn needs_water(&self) -> bool {
+        self.needs_water
+    }
+
+    /// Reads the probe and updates the state, publishing `Event::Soil` if it changed.
+    /// The reading is smoothed over about four updates.
+    /// # Returns
+    /// * `a u8` - The moisture in percent.
+    pub fn update(&mut self) -> u8 {
+        let reading = (self.read)();
+        self.reading = if self.reading == 0 {
+            reading
+        } else {
+            ((self.reading as u32 * 3 + reading as u32) / 4) as u16
+        };
+        let percent = self.percent();
+        let needs_water = if self.needs_water {
+            percent < self.stop
+        } else {
+            percent < self.start
+        };
+        if needs_water != self.needs_water {
+            self.needs_water = needs_water;
+            (self.publish)(Event::Soil {
+                probe: self.id,
+                needs_water,
+            });
+        }
+        percent
+    }
+
+    /// Gives back the storage.
+    pub fn release(self) -> S {
+        self.calibration.release()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Result;
+
+    #[test]
+    fn calibration_in_both_directions() {
+        let capacitive = SoilCalibration::CAPACITIVE;
+        assert_eq!(capacitive.percent(600), 0);
+        assert_eq!(capacitive.percent(700), 0);
+        assert_eq!(capacitive.percent(440), 50);
+        assert_eq!(capacitive.percent(200), 100);
+        let resistive = SoilCalibration { dry: 100, wet: 900 };
+        assert_eq!(resistive.percent(500), 50);
+        assert_eq!(SoilCalibration { dry: 5, wet: 5 }.percent(5), 0);
+    }
+
+    static mut READING: u16 = 600;
+
+    fn read() -> u16 {
+        unsafe { READING }
+    }
+
+    static mut PUBLISHED: Option<Event> = None;
+
+    fn publish(event: Event) -> bool {
+        unsafe { PUBLISHED = Some(event) };
+        true
+    }
+
+    fn published() -> Option<Event> {
+        unsafe {
+            let event = PUBLISHED;
+            PUBLISHED = None;
+            event
+        }
+    }
+
+    struct Memory([u8; 16]);
+
+    impl Storage for Memory {
+        fn capacity(&self) -> u32 {
+            16
+        }
+
+        fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+            let start = address as usize;
+            data.copy_from_slice(&self.0[start..start + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+            let start = address as usize;
+            self.0[start..start + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn watering_hysteresis_and_persistence() {
+        let mut probe = SoilProbe::new(read, 3, Memory([0xFF; 16]), 0);
+        probe.set_publisher(publish);
+        assert_eq!(probe.calibration(), SoilCalibration::CAPACITIVE);
+        unsafe { READING = 650 };
+        assert!(probe.calibrate_dry());
+
+        assert_eq!(probe.update(), 0);
+        assert!(probe.needs_water());
+        let watering = Event::Soil {
+            probe: 3,
+            needs_water: true,
+        };
+        assert_eq!(published(), Some(watering));
+        // 40 percent is not enough to stop the watering.
+        unsafe { READING = 502 };
+        probe.reading = 0;
+        assert_eq!(probe.update(), 40);
+        assert!(probe.needs_water());
+        assert_eq!(published(), None);
+        unsafe { READING = 475 };
+        probe.reading = 0;
+        assert_eq!(probe.update(), 47);
+        assert!(!probe.needs_water());
+        let watered = Event::Soil {
+            probe: 3,
+            needs_water: false,
+        };
+        assert_eq!(published(), Some(watered));
+
+        let probe = SoilProbe::new(read, 0, probe.release(), 0);
+        assert_eq!(probe.calibration().dry, 650);
+    }
+}
@@ -0,0 +1,113 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Stack usage monitoring for the AVR chips, whose stack grows down from the
+//! end of RAM towards the statics and silently overwrites them on overflow.
+//! `paint` fills the free RAM between the end of the statics and the stack with
+//! a known pattern right after boot. The deepest address the stack reached is
+//! then the first byte, counting up from the statics, which lost the pattern,
+//! which gives the high water mark of the stack. The lowest `CANARY_BYTES`
+//! bytes act as a canary, `check` can be run from a periodic task and reports
+//! once the stack came that close to the statics.
+
+use crate::hal::interrupts;
+use crate::hal::mem::{heap_start, stack_pointer, RAM_END};
+use core::ptr::{read_volatile, write_volatile};
+
+/// Pattern written to the unused RAM.
+const PAINT: u8 = 0xC5;
+
+/// Bytes left unpainted below the stack pointer, for the frame of `paint` itself.
+const MARGIN: usize = 16;
+
+/// Number of bytes right above the statics used as the canary.
+pub const CANARY_BYTES: usize = 16;
+
+/// Fills the unused RAM below the stack with the pattern.
+/// Should be called once, at the very beginning of `main`.
+pub fn paint() {
+    interrupts::free(|| {
+        let end = stack_pointer().saturating_sub(MARGIN);
+        for address in heap_start()..end {
+            unsafe { write_volatile(address as *mut u8, PAINT) };
+        }
+    });
+}
+
+/// Returns the lowest address which lost the pattern.
+fn deepest() -> usize {
+    let mut address = heap_start();
+    while address <= RAM_END && unsafe { read_volatile(address as *const u8) } == PAINT {
+        address += 1;
+    }
+    address
+}
+
+/// Returns the largest number of bytes the stack used since `paint`.
+pub fn high_water() -> usize {
+    RAM_END + 1 - deepest()
+}
+
+/// Returns the number of bytes between the statics and the stack never used since `paint`.
+pub fn headroom() -> usize {
+    deepest() - heap_start()
+}
+
+/// Returns the size of the whole region shared by the stack and the free RAM.
+pub fn capacity() -> usize {
+    RAM_END + 1 - heap_start()
+}
+
+/// Returns true while the canary right above the statics is untouched.
+pub fn canary_intact() -> bool {
+    (heap_start()..heap_start() + CANARY_BYTES)
+        .all(|address| unsafe { read_volatile(address as *const u8) } == PAINT)
+}
+
+/// Checks the canary, to be called from a periodic task.
+/// A damaged canary means the stack reached the statics or came within
+/// `CANARY_BYTES` of them, and is logged with the high water mark.
+/// # Returns
+/// * `a boolean` - true if the canary is intact.
+pub fn check() -> bool {
+    let intact = canary_intact();
+    #[cfg(feature = "defmt")]
+    if !intact {
+        defmt::error!(
+            "stack overflow, {=usize} of {=usize} bytes used",
+            high_water(),
+            capacity()
+        );
+    }
+    intact
+}
+
+/// Writes the stack usage as a line of text, for example to the serial port.
+/// # Arguments
+/// * `out` - a `embedded_io::Write` object, receiving the report.
+/// # Returns
+/// * `a Result` - the error of the writer if any.
+pub fn report<W: embedded_io::Write>(
+    out: &mut W,
+) -> Result<(), embedded_io::WriteFmtError<W::Error>> {
+    write!(
+        out,
+        "stack: {} of {} bytes used, canary {}\r\n",
+        high_water(),
+        capacity(),
+        if canary_intact() { "ok" } else { "damaged" }
+    )
+}
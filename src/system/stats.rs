@@ -0,0 +1,294 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Uptime and performance counters.
+//! `Stats::tick` is called once per iteration of the main loop and keeps the
+//! uptime beyond the 49 days after which `millis` overflows, and the number of
+//! loop iterations per second. Interrupt service routines of interest can be
+//! instrumented with `isr_enter` and `isr_exit`, which sample the time spent
+//! in them, giving the share of the CPU taken by interrupts. The scheduler
+//! keeps a `TaskStats` for every periodic task with its execution times.
+//! `Stats` and the instrumentation need the `millis` feature.
+
+#[cfg(feature = "millis")]
+use crate::hal::interrupts;
+#[cfg(feature = "millis")]
+use crate::hal::millis::{micros, millis};
+
+/// Execution times of a task, in microseconds.
+/// # Elements
+/// * `runs` - a u32, the number of runs.
+/// * `last` - a u32, the duration of the last run.
+/// * `max` - a u32, the longest run.
+/// * `total` - a u32, the sum of all runs, wrapping after about 71 minutes of execution.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TaskStats {
+    pub runs: u32,
+    pub last: u32,
+    pub max: u32,
+    pub total: u32,
+}
+
+impl TaskStats {
+    /// Creates empty statistics.
+    pub const fn new() -> TaskStats {
+        TaskStats {
+            runs: 0,
+            last: 0,
+            max: 0,
+            total: 0,
+        }
+    }
+
+    /// Adds a run.
+    /// # Arguments
+    /// * `duration` - a u32, the duration of the run in microseconds.
+    pub fn record(&mut self, duration: u32) {
+        self.runs = self.runs.wrapping_add(1);
+        self.last = duration;
+        self.max = self.max.max(duration);
+        self.total = self.total.wrapping_add(duration);
+    }
+
+    /// Returns the average duration of a run in microseconds.
+    pub fn average(&self) -> u32 {
+        self.total.checked_div(self.runs).unwrap_or(0)
+    }
+}
+
+/// Microseconds spent in the instrumented interrupt service routines.
+#[cfg(feature = "millis")]
+static mut ISR_MICROS: u32 = 0;
+
+/// Marks the start of an interrupt service routine.
+/// # Returns
+/// * `a u32` - the time of entry, to be passed to `isr_exit`.
+#[cfg(feature = "millis")]
+pub fn isr_enter() -> u32 {
+    micros()
+}
+
+/// Marks the end of an interrupt service routine and adds the time spent in it.
+/// # Arguments
+/// * `start` - a u32, the value returned by `isr_enter`.
+#[cfg(feature = "millis")]
+pub fn isr_exit(start: u32) {
+    let spent = micros().wrapping_sub(start);
+    interrupts::free(|| unsafe { ISR_MICROS = ISR_MICROS.wrapping_add(spent) });
+}
+
+/// Takes the time spent in interrupts since the last call.
+#[cfg(feature = "millis")]
+fn take_isr_micros() -> u32 {
+    interrupts::free(|| unsafe {
+        let spent = ISR_MICROS;
+        ISR_MICROS = 0;
+        spent
+    })
+}
+
+/// Uptime and main loop counters.
+#[cfg(feature = "millis")]
+pub struct Stats {
+    /// Value of `millis` at the last tick, to detect its overflow.
+    last: u32,
+    /// Number of overflows of `millis`.
+    wraps: u16,
+    /// Start of the current one second window.
+    window_start: u32,
+    loops: u32,
+    loops_per_second: u32,
+    isr_load: u16,
+    /// Source of the time in milliseconds, `millis` by default.
+    clock: fn() -> u32,
+    /// Source of the microseconds spent in interrupts since its last call.
+    isr_time: fn() -> u32,
+}
+
+#[cfg(feature = "millis")]
+impl Stats {
+    /// Creates the counters, uptime counts from the start of `millis`.
+    pub const fn new() -> Stats {
+        Stats {
+            last: 0,
+            wraps: 0,
+            window_start: 0,
+            loops: 0,
+            loops_per_second: 0,
+            isr_load: 0,
+            clock: millis,
+            isr_time: take_isr_micros,
+        }
+    }
+
+    /// Replaces the time sources, for example to run the counters on a simulated clock.
+    /// # Arguments
+    /// * `clock` - a function returning the time in milliseconds, wrapping after 49 days.
+    /// * `isr_time` - a function returning the microseconds spent in interrupts since its last call.
+    pub fn set_clock(&mut self, clock: fn() -> u32, isr_time: fn() -> u32) {
+        self.clock = clock;
+        self.isr_time = isr_time;
+    }
+
+    /// Counts an iteration of the main loop, to be called once per iteration.
+    pub fn tick(&mut self) {
+        let now = (self.clock)();
+        if now < self.last {
+            self.wraps = self.wraps.wrapping_add(1);
+        }
+        self.last = now;
+        self.loops = self.loops.wrapping_add(1);
+
+        let elapsed = now.wrapping_sub(self.window_start);
+        if elapsed >= 1000 {
+            self.loops_per_second = (self.loops as u64 * 1000 / elapsed as u64) as u32;
+            self.loops = 0;
+            self.window_start = now;
+            let isr = (self.isr_time)() as u64;
+            self.isr_load = (isr / elapsed as u64).min(1000) as u16;
+        }
+    }
+
+    /// Returns the time since start up in milliseconds.
+    /// `tick` has to run at least once every 49 days for the overflows of `millis` to be counted.
+    pub fn uptime_millis(&self) -> u64 {
+        (self.wraps as u64) << 32 | self.last as u64
+    }
+
+    /// Returns the time since start up in seconds.
+    pub fn uptime_seconds(&self) -> u32 {
+        (self.uptime_millis() / 1000) as u32
+    }
+
+    /// Returns the number of main loop iterations during the last full second.
+    pub fn loops_per_second(&self) -> u32 {
+        self.loops_per_second
+    }
+
+    /// Returns the share of the time spent in the instrumented interrupt service
+    /// routines during the last full second, in thousandths.
+    pub fn isr_load(&self) -> u16 {
+        self.isr_load
+    }
+
+    /// Writes the counters as a line of text, for example to the serial port.
+    /// # Arguments
+    /// * `out` - a `embedded_io::Write` object, receiving the report.
+    /// # Returns
+    /// * `a Result` - the error of the writer if any.
+    pub fn report<W: embedded_io::Write>(
+        &self,
+        out: &mut W,
+    ) -> Result<(), embedded_io::WriteFmtError<W::Error>> {
+        write!(
+            out,
+            "uptime {} s, {} loops/s, isr {}.{}%\r\n",
+            self.uptime_seconds(),
+            self.loops_per_second,
+            self.isr_load / 10,
+            self.isr_load % 10
+        )
+    }
+
+    /// Logs the counters with defmt.
+    #[cfg(feature = "defmt")]
+    pub fn log(&self) {
+        defmt::info!(
+            "uptime {=u32} s, {=u32} loops/s, isr load {=u16} per mille",
+            self.uptime_seconds(),
+            self.loops_per_second,
+            self.isr_load
+        );
+    }
+}
+
+#[cfg(feature = "millis")]
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn task_stats_track_runs() {
+        let mut stats = TaskStats::new();
+        assert_eq!(stats.average(), 0);
+        stats.record(100);
+        stats.record(300);
+        assert_eq!(stats.runs, 2);
+        assert_eq!(stats.last, 300);
+        assert_eq!(stats.max, 300);
+        assert_eq!(stats.average(), 200);
+    }
+
+    #[cfg(feature = "millis")]
+    #[test]
+    fn counts_loops_and_uptime() {
+        static mut NOW: u32 = 0;
+
+        fn clock() -> u32 {
+            unsafe { NOW }
+        }
+
+        // 250 microseconds in interrupts between two reads.
+        fn isr_time() -> u32 {
+            250
+        }
+
+        let mut stats = Stats::new();
+        stats.set_clock(clock, isr_time);
+        for now in 0..2000 {
+            unsafe { NOW = now };
+            stats.tick();
+            stats.tick();
+        }
+        assert_eq!(stats.loops_per_second(), 2001);
+        assert_eq!(stats.isr_load(), 0);
+        unsafe { NOW = u32::MAX };
+        stats.tick();
+        unsafe { NOW = 5 };
+        stats.tick();
+        assert_eq!(stats.uptime_millis(), (1 << 32) + 5);
+        assert_eq!(stats.uptime_seconds(), 4_294_967);
+    }
+
+    #[cfg(feature = "millis")]
+    #[test]
+    fn reports_the_interrupt_load() {
+        fn isr_time() -> u32 {
+            // 12.5 ms in interrupts during the first second.
+            12_500
+        }
+        fn clock() -> u32 {
+            1000
+        }
+        let mut stats = Stats::new();
+        stats.set_clock(clock, isr_time);
+        stats.tick();
+        assert_eq!(stats.loops_per_second(), 1);
+        assert_eq!(stats.isr_load(), 12);
+
+        let mut line = [0u8; 64];
+        let mut out = &mut line[..];
+        stats.report(&mut out).unwrap();
+        let len = 64 - out.len();
+        assert_eq!(&line[..len], b"uptime 1 s, 1 loops/s, isr 1.2%\r\n");
+    }
+}
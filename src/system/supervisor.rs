@@ -0,0 +1,272 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Soft real time supervision of long running tasks.
+//! Every supervised task gets a deadline within which it has to check in.
+//! `Supervisor::poll`, called from the main loop, feeds the hardware watchdog
+//! only as long as every task kept its deadline. Once a task misses it, the
+//! offender is logged and the watchdog resets the microcontroller, so a hung
+//! task cannot leave a deployed board stuck. After the reset the offender
+//! can be read back with `Supervisor::last_offender`.
+//! The module needs the `millis` feature.
+
+use crate::hal::eeprom::Eeprom;
+use crate::hal::millis::millis;
+use crate::hal::watchdog::{WatchDog, WatchdogTimeout};
+
+/// Identifies a task registered with a `Supervisor`.
+/// Ids are handed out in registration order, so a program registering its
+/// tasks in the same order on every boot gets the same ids back.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SupervisedId(u8);
+
+impl SupervisedId {
+    /// Returns the index of the task in the supervisor.
+    pub fn index(&self) -> u8 {
+        self.0
+    }
+}
+
+/// A task known to the supervisor.
+#[derive(Clone, Copy)]
+struct Supervised {
+    name: &'static str,
+    /// Time in milliseconds within which the task must check in.
+    deadline: u32,
+    /// Value of `millis` at the last check in.
+    last_check_in: u32,
+}
+
+impl Supervised {
+    /// Returns true if the task has not checked in within its deadline at time `now`.
+    fn is_overdue(&self, now: u32) -> bool {
+        now.wrapping_sub(self.last_check_in) > self.deadline
+    }
+}
+
+/// Value of the log byte in EEPROM when no offender is recorded.
+const NO_OFFENDER: u8 = 0xFF;
+
+/// A supervisor watching at most `N` tasks.
+/// # Elements
+/// * `tasks` - an array of `Option<Supervised>`, the registered tasks.
+/// * `timeout` - a `WatchdogTimeout`, the hardware watchdog timeout.
+/// * `log_address` - a `Option<u16>`, the EEPROM address of the offender log byte.
+/// * `on_violation` - an optional function, called with the offender before the reset.
+/// * `clock` - a function returning the time in milliseconds, `millis` by default.
+pub struct Supervisor<const N: usize> {
+    tasks: [Option<Supervised>; N],
+    timeout: WatchdogTimeout,
+    log_address: Option<u16>,
+    on_violation: Option<fn(SupervisedId, &'static str)>,
+    clock: fn() -> u32,
+}
+
+impl<const N: usize> Supervisor<N> {
+    /// Creates a new supervisor without any task.
+    /// The hardware watchdog is not touched until `start` is called.
+    /// # Arguments
+    /// * `timeout` - a `WatchdogTimeout`, the hardware watchdog timeout, `poll` must run more often than that.
+    pub const fn new(timeout: WatchdogTimeout) -> Self {
+        Supervisor {
+            tasks: [None; N],
+            timeout,
+            log_address: None,
+            on_violation: None,
+            clock: millis,
+        }
+    }
+
+    /// Replaces `millis` as the time base of the deadlines, before any task is registered.
+    /// # Arguments
+    /// * `clock` - a function returning the time in milliseconds, wrapping after 49 days.
+    pub fn set_clock(&mut self, clock: fn() -> u32) {
+        self.clock = clock;
+    }
+
+    /// Records the offending task in one byte of EEPROM before resetting,
+    /// so that it survives the reset.
+    /// # Arguments
+    /// * `address` - a u16, the EEPROM address of the log byte.
+    pub fn log_to_eeprom(&mut self, address: u16) {
+        self.log_address = Some(address);
+    }
+
+    /// Sets a function called with the offending task right before the reset,
+    /// for example to print it over the serial port.
+    /// # Arguments
+    /// * `handler` - a function, receiving the id and the name of the offender.
+    pub fn on_violation(&mut self, handler: fn(SupervisedId, &'static str)) {
+        self.on_violation = Some(handler);
+    }
+
+    /// Registers a task in the first free slot.
+    /// # Arguments
+    /// * `name` - a static string, the name of the task used when logging.
+    /// * `deadline` - a u32, the time in milliseconds within which the task must check in.
+    /// # Returns
+    /// * `a Option<SupervisedId>` - `None` if the supervisor is full.
+    pub fn register(&mut self, name: &'static str, deadline: u32) -> Option<SupervisedId> {
+        let index = self.tasks.iter().position(|t| t.is_none())?;
+        self.tasks[index] = Some(Supervised {
+            name,
+            deadline,
+            last_check_in: (self.clock)(),
+        });
+        Some(SupervisedId(index as u8))
+    }
+
+    /// Stops supervising a task.
+    /// # Arguments
+    /// * `id` - a `SupervisedId`, the task to remove.
+    pub fn unregister(&mut self, id: SupervisedId) {
+        if let Some(task) = self.tasks.get_mut(id.0 as usize) {
+            *task = None;
+        }
+    }
+
+    /// Tells the supervisor that a task is alive.
+    /// # Arguments
+    /// * `id` - a `SupervisedId`, the task checking in.
+    pub fn check_in(&mut self, id: SupervisedId) {
+        let now = (self.clock)();
+        if let Some(Some(task)) = self.tasks.get_mut(id.0 as usize) {
+            task.last_check_in = now;
+        }
+    }
+
+    /// Returns the first task which missed its deadline at time `now`.
+    fn offender(&self, now: u32) -> Option<SupervisedId> {
+        self.tasks
+            .iter()
+            .position(|t| matches!(t, Some(task) if task.is_overdue(now)))
+            .map(|index| SupervisedId(index as u8))
+    }
+
+    /// Restarts the deadline of every task and enables the hardware watchdog.
+    /// Should be called once all tasks are registered, right before the main loop.
+    pub fn start(&mut self) {
+        let now = (self.clock)();
+        for task in self.tasks.iter_mut().flatten() {
+            task.last_check_in = now;
+        }
+        if let Some(address) = self.log_address {
+            let eeprom = Eeprom::new();
+            if eeprom.read_byte(address) != NO_OFFENDER
+                && !unsafe { WatchDog::new() }.caused_reset()
+            {
+                eeprom.write_byte(address, NO_OFFENDER);
+            }
+        }
+        unsafe { WatchDog::new() }.enable(self.timeout);
+    }
+
+    /// Checks the deadline of every task. The hardware watchdog is fed if all of them
+    /// checked in on time, otherwise the offender is logged and the microcontroller is reset.
+    /// Must be called more often than the watchdog timeout.
+    pub fn poll(&mut self) {
+        let watchdog = unsafe { WatchDog::new() };
+        let id = match self.offender((self.clock)()) {
+            None => {
+                watchdog.feed();
+                return;
+            }
+            Some(id) => id,
+        };
+        let name = self.tasks[id.0 as usize].map(|t| t.name).unwrap_or("");
+        #[cfg(feature = "defmt")]
+        defmt::warn!("task {=str} missed its deadline, resetting", name);
+        if let Some(address) = self.log_address {
+            Eeprom::new().write_byte(address, id.0);
+        }
+        if let Some(handler) = self.on_violation {
+            handler(id, name);
+        }
+        watchdog.system_reset();
+    }
+
+    /// Returns the task which caused the last reset, if the last reset
+    /// was caused by the watchdog and an offender was logged to EEPROM.
+    /// # Returns
+    /// * `a Option<SupervisedId>` - the offending task, `None` otherwise.
+    pub fn last_offender(&self) -> Option<SupervisedId> {
+        let address = self.log_address?;
+        if !unsafe { WatchDog::new() }.caused_reset() {
+            return None;
+        }
+        match Eeprom::new().read_byte(address) {
+            index if (index as usize) < N => Some(SupervisedId(index)),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of a registered task.
+    /// # Arguments
+    /// * `id` - a `SupervisedId`, the task.
+    /// # Returns
+    /// * `a Option<&'static str>` - `None` if no task is registered under the id.
+    pub fn name(&self, id: SupervisedId) -> Option<&'static str> {
+        self.tasks.get(id.0 as usize)?.map(|t| t.name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_the_task_missing_its_deadline() {
+        // Every test has its own clock, as the tests run in parallel.
+        static mut NOW: u32 = 0;
+        fn clock() -> u32 {
+            unsafe { NOW }
+        }
+        fn set_now(now: u32) {
+            unsafe { NOW = now };
+        }
+
+        let mut supervisor: Supervisor<2> = Supervisor::new(WatchdogTimeout::S1);
+        supervisor.set_clock(clock);
+        let fast = supervisor.register("fast", 100).unwrap();
+        let slow = supervisor.register("slow", 1000).unwrap();
+        assert_eq!(supervisor.register("full", 10), None);
+        assert_eq!(supervisor.offender(100), None);
+        assert_eq!(supervisor.offender(101), Some(fast));
+        set_now(101);
+        supervisor.check_in(fast);
+        assert_eq!(supervisor.offender(1001), Some(fast));
+        set_now(1000);
+        supervisor.check_in(fast);
+        assert_eq!(supervisor.offender(1001), Some(slow));
+        supervisor.unregister(slow);
+        assert_eq!(supervisor.offender(1001), None);
+        assert_eq!(supervisor.name(fast), Some("fast"));
+        assert_eq!(supervisor.name(slow), None);
+    }
+
+    #[test]
+    fn deadlines_survive_millis_overflow() {
+        fn clock() -> u32 {
+            u32::MAX - 10
+        }
+
+        let mut supervisor: Supervisor<1> = Supervisor::new(WatchdogTimeout::S1);
+        supervisor.set_clock(clock);
+        let task = supervisor.register("task", 50).unwrap();
+        assert_eq!(supervisor.offender(20), None);
+        assert_eq!(supervisor.offender(40), Some(task));
+    }
+}
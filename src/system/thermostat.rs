@@ -0,0 +1,475 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A thermostat combining on/off and PID control. Far from the setpoint the
+//! output is fully on or off; within the proportional band a PID loop takes
+//! over, so the temperature settles without the swings of a plain on/off
+//! controller. With a band of 0 it is a plain on/off thermostat switching
+//! at the setpoint plus or minus the hysteresis.
+//! The temperature comes from any `TemperatureSensor` and the power goes to
+//! any `HeaterOutput`: `PwmOutput` for a heater driven by PWM or a SSR with
+//! zero crossing, `RelayOutput` for a relay switched on for a share of a
+//! time window. Setpoints can follow a weekly schedule, and `report` writes
+//! the state to a serial port or a console.
+//! Temperatures are in hundredths of a degree Celsius and powers in percent.
+
+use super::alarms::{previous_occurrence, EVERY_DAY};
+use super::clock::DateTime;
+use crate::{Error, Result};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::pwm::SetDutyCycle;
+
+/// A source of temperatures.
+pub trait TemperatureSensor {
+    /// Returns the temperature in hundredths of a degree Celsius.
+    fn temperature(&mut self) -> Result<i32>;
+}
+
+impl<T: TemperatureSensor> TemperatureSensor for &mut T {
+    fn temperature(&mut self) -> Result<i32> {
+        (**self).temperature()
+    }
+}
+
+/// Something which heats or cools with an adjustable power.
+pub trait HeaterOutput {
+    /// Sets the power.
+    /// # Arguments
+    /// * `percent` - a u8, the power from 0 to 100.
+    /// * `now` - a u32, the current time in milliseconds, for outputs switching over time.
+    fn set_power(&mut self, percent: u8, now: u32) -> Result<()>;
+}
+
+/// A heater driven by a PWM output.
+pub struct PwmOutput<P: SetDutyCycle>(pub P);
+
+impl<P: SetDutyCycle> HeaterOutput for PwmOutput<P> {
+    fn set_power(&mut self, percent: u8, _now: u32) -> Result<()> {
+        self.0
+            .set_duty_cycle_percent(percent.min(100))
+            .map_err(|_| Error::InvalidArgument)
+    }
+}
+
+/// A heater switched by a relay, on for a share of every window proportional to the power.
+/// Long windows spare the relay, short ones keep the temperature steadier.
+pub struct RelayOutput<P: OutputPin> {
+    pin: P,
+    window: u32,
+    start: u32,
+    on: bool,
+}
+
+impl<P: OutputPin> RelayOutput<P> {
+    /// Creates the output, switched off.
+    /// # Arguments
+    /// * `pin` - an output pin driving the relay, high for on.
+    /// * `window` - a u32, the length of a window in milliseconds, like 10000.
+    pub fn new(mut pin: P, window: u32) -> Self {
+        let _ = pin.set_low();
+        RelayOutput {
+            pin,
+            window: window.max(1),
+            start: 0,
+            on: false,
+        }
+    }
+
+    /// Gives back the pin.
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+impl<P: OutputPin> HeaterOutput for RelayOutput<P> {
+    fn set_power(&mut self, percent: u8, now: u32) -> Result<()> {
+        let mut elapsed = now.wrapping_sub(self.start);
+        if elapsed >= self.window {
+            self.start = now;
+            elapsed = 0;
+        }
+        let on = elapsed < self.window / 100 * percent.min(100) as u32;
+        if on != self.on {
+            self.on = on;
+            if on {
+                self.pin.set_high()
+            } else {
+                self.pin.set_low()
+            }
+            .map_err(|_| Error::BusError)?;
+        }
+        Ok(())
+    }
+}
+
+/// Gains of the PID loop.
+/// # Elements
+/// * `kp` - a i32, percent of power per degree of error.
+/// * `ki` - a i32, percent of power per degree of error lasting a minute.
+/// * `kd` - a i32, percent of power per degree per minute of temperature change.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Gains {
+    pub kp: i32,
+    pub ki: i32,
+    pub kd: i32,
+}
+
+/// How the thermostat decided the power.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// Disabled, the output is off.
+    Off,
+    /// Outside of the proportional band, full power or none.
+    OnOff,
+    /// Within the proportional band, the PID loop sets the power.
+    Pid,
+}
+
+/// The state of a thermostat after an update.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ThermostatState {
+    /// The last temperature read.
+    pub temperature: i32,
+    /// The setpoint in use.
+    pub setpoint: i32,
+    /// The power given to the output in percent.
+    pub power: u8,
+    pub mode: Mode,
+}
+
+/// A setpoint starting at a time of the week.
+#[derive(Clone, Copy)]
+struct SetpointEntry {
+    time: u32,
+    days: u8,
+    setpoint: i32,
+}
+
+/// A thermostat with a schedule of at most `N` setpoints.
+pub struct Thermostat<S: TemperatureSensor, O: HeaterOutput, const N: usize> {
+    sensor: S,
+    output: O,
+    setpoint: i32,
+    hysteresis: i32,
+    band: i32,
+    gains: Gains,
+    cooling: bool,
+    enabled: bool,
+    schedule: [Option<SetpointEntry>; N],
+    /// Sum of the errors over time in hundredths of a degree times milliseconds.
+    integral: i64,
+    previous: Option<(u32, i32)>,
+    state: ThermostatState,
+}
+
+impl<S: TemperatureSensor, O: HeaterOutput, const N: usize> Thermostat<S, O, N> {
+    /// Creates a heating thermostat at 20 degrees, with a proportional band of 1 degree,
+    /// a hysteresis of 0.3 degrees and gains of 50 %/°C, 5 %/(°C min) and 0.
+    /// # Arguments
+    /// * `sensor` - a `TemperatureSensor` object, the temperature to control.
+    /// * `output` - a `HeaterOutput` object, the heater.
+    pub fn new(sensor: S, output: O) -> Self {
+        Thermostat {
+            sensor,
+            output,
+            setpoint: 2000,
+            hysteresis: 30,
+            band: 100,
+            gains: Gains {
+                kp: 50,
+                ki: 5,
+                kd: 0,
+            },
+            cooling: false,
+            enabled: true,
+            schedule: [None; N],
+            integral: 0,
+            previous: None,
+            state: ThermostatState {
+                temperature: 0,
+                setpoint: 2000,
+                power: 0,
+                mode: Mode::Off,
+            },
+        }
+    }
+
+    /// Sets the temperature to reach.
+    pub fn set_setpoint(&mut self, setpoint: i32) {
+        self.setpoint = setpoint;
+    }
+
+    /// Returns the temperature to reach.
+    pub fn setpoint(&self) -> i32 {
+        self.setpoint
+    }
+
+    /// Sets the hysteresis of the on/off control, used when the band is 0.
+    pub fn set_hysteresis(&mut self, hysteresis: i32) {
+        self.hysteresis = hysteresis.max(0);
+    }
+
+    /// Sets the half width of the proportional band around the setpoint,
+    /// 0 for on/off control only.
+    pub fn set_band(&mut self, band: i32) {
+        self.band = band.max(0);
+    }
+
+    /// Sets the gains of the PID loop.
+    pub fn set_gains(&mut self, gains: Gains) {
+        self.gains = gains;
+        self.integral = 0;
+    }
+
+    /// Makes the output cool instead of heat, for a fan or a compressor.
+    pub fn set_cooling(&mut self, cooling: bool) {
+        self.cooling = cooling;
+        self.integral = 0;
+    }
+
+    /// Enables or disables the thermostat, the output is switched off while disabled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.integral = 0;
+    }
+
+    /// Adds a weekly setpoint change.
+    /// # Arguments
+    /// * `hour` - a u8, from 0 to 23.
+    /// * `minute` - a u8, from 0 to 59.
+    /// * `days` - a u8, a combination of the day constants of `alarms` like `WEEKDAYS`.
+    /// * `setpoint` - a i32, the temperature from then on.
+    /// # Returns
+    /// * `a boolean` - false if the schedule is full or an argument is not valid.
+    pub fn schedule(&mut self, hour: u8, minute: u8, days: u8, setpoint: i32) -> bool {
+        if hour > 23 || minute > 59 || days & EVERY_DAY == 0 {
+            return false;
+        }
+        match self.schedule.iter_mut().find(|e| e.is_none()) {
+            Some(slot) => {
+                *slot = Some(SetpointEntry {
+                    time: hour as u32 * 3600 + minute as u32 * 60,
+                    days: days & EVERY_DAY,
+                    setpoint,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes all the scheduled setpoints.
+    pub fn clear_schedule(&mut self) {
+        self.schedule = [None; N];
+    }
+
+    /// Sets the setpoint of the last schedule entry which started before `now`.
+    /// # Arguments
+    /// * `now` - a reference to `DateTime`, the current time.
+    pub fn apply_schedule(&mut self, now: &DateTime) {
+        let time = now.timestamp();
+        let latest = self
+            .schedule
+            .iter()
+            .flatten()
+            .filter_map(|e| Some((previous_occurrence(e.time, e.days, time)?, e.setpoint)))
+            .max_by_key(|&(at, _)| at);
+        if let Some((_, setpoint)) = latest {
+            self.setpoint = setpoint;
+        }
+    }
+
+    /// Returns the state of the last update.
+    pub fn state(&self) -> ThermostatState {
+        self.state
+    }
+
+    /// Reads the temperature and sets the power of the output.
+    /// Call it regularly, every second or few seconds.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, usually `millis()`.
+    /// # Returns
+    /// * `a Result<ThermostatState>` - The new state, or the error of the sensor or output.
+    ///                                 The output is switched off if the sensor fails.
+    pub fn update(&mut self, now: u32) -> Result<ThermostatState> {
+        let temperature = match self.sensor.temperature() {
+            Ok(temperature) => temperature,
+            Err(error) => {
+                self.state.power = 0;
+                self.state.mode = Mode::Off;
+                self.output.set_power(0, now)?;
+                return Err(error);
+            }
+        };
+        let error = if self.cooling {
+            temperature - self.setpoint
+        } else {
+            self.setpoint - temperature
+        };
+
+        let (power, mode) = if !self.enabled {
+            (0, Mode::Off)
+        } else if self.band == 0 {
+            // Plain on/off control, keeping the current power within the hysteresis.
+            let power = if error > self.hysteresis {
+                100
+            } else if error < -self.hysteresis {
+                0
+            } else {
+                self.state.power
+            };
+            (power, Mode::OnOff)
+        } else if error.abs() > self.band {
+            self.integral = 0;
+            (if error > 0 { 100 } else { 0 }, Mode::OnOff)
+        } else {
+            (self.pid(error, temperature, now), Mode::Pid)
+        };
+        self.previous = Some((now, temperature));
+
+        self.state = ThermostatState {
+            temperature,
+            setpoint: self.setpoint,
+            power,
+            mode,
+        };
+        self.output.set_power(power, now)?;
+        Ok(self.state)
+    }
+
+    /// Computes the power of the PID loop.
+    fn pid(&mut self, error: i32, temperature: i32, now: u32) -> u8 {
+        let (elapsed, change) = match self.previous {
+            Some((at, previous)) => (now.wrapping_sub(at) as i64, (temperature - previous) as i64),
+            None => (0, 0),
+        };
+        let change = if self.cooling { -change } else { change };
+        let proportional = self.gains.kp as i64 * error as i64 / 100;
+        // The derivative acts on the temperature, so a setpoint change gives no kick.
+        let derivative = match elapsed {
+            0 => 0,
+            _ => -(self.gains.kd as i64) * change * 60_000 / (elapsed * 100),
+        };
+        let integral = self.integral + error as i64 * elapsed;
+        let ki = self.gains.ki as i64;
+        let output = |integral: i64| proportional + ki * integral / (100 * 60_000) + derivative;
+        // The error is only integrated while the output is not saturated.
+        if (0..=100).contains(&output(integral)) {
+            self.integral = integral;
+        }
+        output(self.integral).clamp(0, 100) as u8
+    }
+
+    /// Writes the state as a line, like `21.35 C set 21.50 C power 40 % pid`.
+    /// # Arguments
+    /// * `out` - a `embedded_io::Write` object, receiving the report.
+    pub fn report<W: embedded_io::Write>(
+        &self,
+        out: &mut W,
+    ) -> core::result::Result<(), embedded_io::WriteFmtError<W::Error>> {
+        let degrees = |value: i32| {
+            let sign = if value < 0 { "-" } else { "" };
+            (sign, value.unsigned_abs() / 100, value.unsigned_abs() % 100)
+        };
+        let (ts, ti, tf) = degrees(self.state.temperature);
+        let (ss, si, sf) = degrees(self.state.setpoint);
+        let mode = match self.state.mode {
+            Mode::Off => "off",
+            Mode::OnOff => "on/off",
+            Mode::Pid => "pid",
+        };
+        write!(
+            out,
+            "{}{}.{:02} C set {}{}.{:02} C power {} % {}\r\n",
+            ts, ti, tf, ss, si, sf, self.state.power, mode
+        )
+    }
+
+    /// Gives back the sensor and the output.
+    pub fn release(self) -> (S, O) {
+        (self.sensor, self.output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Room(i32);
+
+    impl TemperatureSensor for Room {
+        fn temperature(&mut self) -> Result<i32> {
+            Ok(self.0)
+        }
+    }
+
+    struct Power(u8);
+
+    impl HeaterOutput for Power {
+        fn set_power(&mut self, percent: u8, _now: u32) -> Result<()> {
+            self.0 = percent;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn on_off_with_hysteresis() {
+        let mut thermostat: Thermostat<Room, Power, 0> = Thermostat::new(Room(1900), Power(0));
+        thermostat.set_band(0);
+        assert_eq!(thermostat.update(0).unwrap().power, 100);
+        thermostat.sensor.0 = 2020;
+        assert_eq!(thermostat.update(1000).unwrap().power, 100);
+        thermostat.sensor.0 = 2031;
+        assert_eq!(thermostat.update(2000).unwrap().power, 0);
+        thermostat.sensor.0 = 1980;
+        assert_eq!(thermostat.update(3000).unwrap().power, 0);
+        assert_eq!(thermostat.output.0, 0);
+    }
+
+    #[test]
+    fn pid_within_the_band() {
+        let mut thermostat: Thermostat<Room, Power, 0> = Thermostat::new(Room(1800), Power(0));
+        let state = thermostat.update(0).unwrap();
+        assert_eq!((state.power, state.mode), (100, Mode::OnOff));
+
+        // Half a degree below: 25 % from the proportional term, growing with the integral.
+        thermostat.sensor.0 = 1950;
+        let state = thermostat.update(1000).unwrap();
+        assert_eq!((state.power, state.mode), (25, Mode::Pid));
+        let state = thermostat.update(61_000).unwrap();
+        assert_eq!(state.power, 27);
+
+        thermostat.set_cooling(true);
+        assert_eq!(thermostat.update(62_000).unwrap().power, 0);
+
+        let mut buffer = [0u8; 64];
+        let mut out = &mut buffer[..];
+        thermostat.report(&mut out).unwrap();
+        let len = 64 - out.len();
+        assert_eq!(&buffer[..len], b"19.50 C set 20.00 C power 0 % pid\r\n");
+    }
+
+    #[test]
+    fn weekly_setpoints() {
+        let mut thermostat: Thermostat<Room, Power, 2> = Thermostat::new(Room(2000), Power(0));
+        assert!(thermostat.schedule(7, 0, EVERY_DAY, 2100));
+        assert!(thermostat.schedule(22, 30, EVERY_DAY, 1700));
+        thermostat.apply_schedule(&DateTime::new(2021, 3, 1, 6, 0, 0).unwrap());
+        assert_eq!(thermostat.setpoint(), 1700);
+        thermostat.apply_schedule(&DateTime::new(2021, 3, 1, 7, 0, 0).unwrap());
+        assert_eq!(thermostat.setpoint(), 2100);
+    }
+}
@@ -0,0 +1,416 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! A watch window over serial, a small debugger for tuning gains and
+//! thresholds at runtime without flashing the board again. The variables are
+//! `Watched` statics, registered by name in a `WatchWindow`, which executes
+//! the lines `list`, `get <name>` and `set <name> <value>` received from a
+//! terminal and writes the values back. Other lines are left to the caller,
+//! so the window fits next to the commands of an existing shell.
+//! Any type which can be printed and parsed can be watched, like the integers,
+//! `f32` and `bool`. A `Cell` can be watched too, for the variables which
+//! are not shared with interrupt service routines.
+//! `get` and `set` look the variables up by name without any terminal.
+
+use crate::hal::interrupts;
+use crate::{Error, Result};
+use core::cell::{Cell, UnsafeCell};
+use core::fmt::{self, Display};
+use core::ptr::{read_volatile, write_volatile};
+use core::str::FromStr;
+use embedded_io::{Write, WriteFmtError};
+
+/// Longest line handled by `WatchWindow::push`.
+pub const MAX_LINE: usize = 48;
+
+/// A variable which can be read and changed from the watch window,
+/// meant to be declared as a static and shared with interrupt service routines.
+pub struct Watched<T> {
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy> Sync for Watched<T> {}
+
+impl<T: Copy> Watched<T> {
+    /// Creates the variable.
+    /// # Arguments
+    /// * `value` - the initial value.
+    pub const fn new(value: T) -> Watched<T> {
+        Watched {
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Returns the value.
+    pub fn get(&self) -> T {
+        interrupts::free(|| unsafe { read_volatile(self.value.get()) })
+    }
+
+    /// Changes the value.
+    pub fn set(&self, value: T) {
+        interrupts::free(|| unsafe { write_volatile(self.value.get(), value) })
+    }
+}
+
+/// A variable as seen by the watch window, whatever its type.
+pub trait Watch {
+    /// Writes the value.
+    fn show(&self, f: &mut fmt::Formatter) -> fmt::Result;
+
+    /// Parses and stores a new value.
+    /// # Arguments
+    /// * `text` - a string, the new value.
+    /// # Returns
+    /// * `a boolean` - false if the text is not a valid value.
+    fn assign(&self, text: &str) -> bool;
+}
+
+impl<T: Copy + Display + FromStr> Watch for Watched<T> {
+    fn show(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.get().fmt(f)
+    }
+
+    fn assign(&self, text: &str) -> bool {
+        match text.parse() {
+            Ok(value) => {
+                self.set(value);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl<T: Copy + Display + FromStr> Watch for Cell<T> {
+    fn show(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.get().fmt(f)
+    }
+
+    fn assign(&self, text: &str) -> bool {
+        match text.parse() {
+            Ok(value) => {
+                self.set(value);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Prints a variable through `Display`.
+struct Shown<'a>(&'a dyn Watch);
+
+impl Display for Shown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.show(f)
+    }
+}
+
+/// A registered variable.
+/// # Elements
+/// * `name` - a string, the name typed in the terminal.
+/// * `variable` - a reference to the variable.
+/// * `writable` - a boolean, false if `set` is refused.
+#[derive(Clone, Copy)]
+struct Entry<'a> {
+    name: &'a str,
+    variable: &'a dyn Watch,
+    writable: bool,
+}
+
+/// Up to `N` variables which can be listed, read and changed by name.
+/// # Elements
+/// * `entries` - the registered variables.
+/// * `line` - an array of u8, the line being received by `push`.
+/// * `len` - a usize, the length of the line being received.
+pub struct WatchWindow<'a, const N: usize> {
+    entries: [Option<Entry<'a>>; N],
+    line: [u8; MAX_LINE],
+    len: usize,
+}
+
+impl<'a, const N: usize> WatchWindow<'a, N> {
+    /// Creates an empty watch window.
+    pub fn new() -> Self {
+        WatchWindow {
+            entries: [None; N],
+            line: [0; MAX_LINE],
+            len: 0,
+        }
+    }
+
+    /// Registers a variable which can be read and changed.
+    /// # Arguments
+    /// * `name` - a string, the name of the variable, without spaces.
+    /// * `variable` - a reference to the variable, usually a `Watched` static.
+    /// # Returns
+    /// * `a boolean` - false if the window is full or the name is taken.
+    pub fn watch(&mut self, name: &'a str, variable: &'a dyn Watch) -> bool {
+        self.add(name, variable, true)
+    }
+
+    /// Registers a variable which can only be read, like a measurement.
+    /// # Arguments
+    /// * `name` - a string, the name of the variable, without spaces.
+    /// * `variable` - a reference to the variable, usually a `Watched` static.
+    /// # Returns
+    /// * `a boolean` - false if the window is full or the name is taken.
+    pub fn watch_read_only(&mut self, name: &'a str, variable: &'a dyn Watch) -> bool {
+        self.add(name, variable, false)
+    }
+
+    /// Registers a variable in the first free entry.
+    fn add(&mut self, name: &'a str, variable: &'a dyn Watch, writable: bool) -> bool {
+        if self.find(name).is_some() {
+            return false;
+        }
+        match self.entries.iter_mut().find(|entry| entry.is_none()) {
+            Some(free) => {
+                *free = Some(Entry {
+                    name,
+                    variable,
+                    writable,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Finds a variable by name.
+    fn find(&self, name: &str) -> Option<Entry<'a>> {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|e| e.name == name)
+            .copied()
+    }
+
+    /// Returns a variable by name, which can be printed.
+    /// # Arguments
+    /// * `name` - a string, the name of the variable.
+    /// # Returns
+    /// * `a Result<impl Display>` - the variable, `NotFound` if no variable has this name.
+    pub fn get(&self, name: &str) -> Result<impl Display + 'a> {
+        self.find(name)
+            .map(|entry| Shown(entry.variable))
+            .ok_or(Error::NotFound)
+    }
+
+    /// Changes a variable by name.
+    /// # Arguments
+    /// * `name` - a string, the name of the variable.
+    /// * `text` - a string, the new value.
+    /// # Returns
+    /// * `a Result<impl Display>` - the variable, to print its new value, `NotFound` if no
+    ///   variable has this name, `InvalidMode` if it is read only and `InvalidArgument` if
+    ///   the text is not a valid value.
+    pub fn set(&self, name: &str, text: &str) -> Result<impl Display + 'a> {
+        let entry = self.find(name).ok_or(Error::NotFound)?;
+        if !entry.writable {
+            return Err(Error::InvalidMode);
+        }
+        if !entry.variable.assign(text) {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Shown(entry.variable))
+    }
+
+    /// Executes a line received from the terminal.
+    /// # Arguments
+    /// * `line` - a string, the line without its end of line.
+    /// * `out` - a `embedded_io::Write` object, like the USART, receiving the answer.
+    /// # Returns
+    /// * `a boolean` - false if the line is not a command of the watch window,
+    ///   nothing being written then.
+    pub fn execute<W: Write>(
+        &self,
+        line: &str,
+        out: &mut W,
+    ) -> core::result::Result<bool, WriteFmtError<W::Error>> {
+        let mut words = line.split_whitespace();
+        let command = words.next();
+        let name = words.next();
+        let value = words.next();
+        match (command, name, value) {
+            (Some("list"), None, _) => {
+                for entry in self.entries.iter().flatten() {
+                    write!(out, "{} = {}\r\n", entry.name, Shown(entry.variable))?;
+                }
+            }
+            (Some("get"), Some(name), None) => match self.get(name) {
+                Ok(variable) => write!(out, "{} = {}\r\n", name, variable)?,
+                Err(_) => write!(out, "unknown variable {}\r\n", name)?,
+            },
+            (Some("set"), Some(name), Some(value)) => match self.set(name, value) {
+                Ok(variable) => write!(out, "{} = {}\r\n", name, variable)?,
+                Err(Error::NotFound) => write!(out, "unknown variable {}\r\n", name)?,
+                Err(Error::InvalidMode) => write!(out, "{} is read only\r\n", name)?,
+                Err(_) => write!(out, "invalid value {}\r\n", value)?,
+            },
+            (Some("get"), Some(name), _) | (Some("set"), Some(name), _)
+                if self.find(name).is_none() =>
+            {
+                write!(out, "unknown variable {}\r\n", name)?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Collects a byte received from the terminal and executes the line once complete.
+    /// Backspace removes the last character, bytes past `MAX_LINE` are dropped.
+    /// # Arguments
+    /// * `byte` - a u8, the byte received.
+    /// * `out` - a `embedded_io::Write` object, receiving the answer.
+    /// # Returns
+    /// * `a boolean` - true if a command of the watch window was executed.
+    pub fn push<W: Write>(
+        &mut self,
+        byte: u8,
+        out: &mut W,
+    ) -> core::result::Result<bool, WriteFmtError<W::Error>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::replace(&mut self.len, 0);
+                match core::str::from_utf8(&self.line[..len]) {
+                    Ok(line) => self.execute(line, out),
+                    Err(_) => Ok(false),
+                }
+            }
+            0x08 | 0x7F => {
+                self.len = self.len.saturating_sub(1);
+                Ok(false)
+            }
+            _ => {
+                if self.len < MAX_LINE {
+                    self.line[self.len] = byte;
+                    self.len += 1;
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl<'a, const N: usize> Default for WatchWindow<'a, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::convert::Infallible;
+
+    static GAIN: Watched<f32> = Watched::new(1.5);
+    static LIMIT: Watched<u16> = Watched::new(300);
+    static ENABLED: Watched<bool> = Watched::new(false);
+
+    struct Out([u8; 96], usize);
+
+    impl embedded_io::ErrorType for Out {
+        type Error = Infallible;
+    }
+
+    impl Write for Out {
+        fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, Infallible> {
+            self.0[self.1..self.1 + buf.len()].copy_from_slice(buf);
+            self.1 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> core::result::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    /// Prints a value into a buffer, to compare it.
+    fn text(value: impl Display) -> ([u8; 16], usize) {
+        let mut out = Out([0; 96], 0);
+        write!(out, "{}", value).unwrap();
+        let mut text = [0; 16];
+        text[..out.1].copy_from_slice(&out.0[..out.1]);
+        (text, out.1)
+    }
+
+    #[test]
+    fn names_and_values() {
+        let threshold = Cell::new(12i32);
+        let ratio = Cell::new(0.5f32);
+        let count = Cell::new(7u8);
+        let mut window: WatchWindow<3> = WatchWindow::new();
+        assert!(window.watch("threshold", &threshold));
+        assert!(window.watch("ratio", &ratio));
+        assert!(window.watch_read_only("count", &count));
+        assert!(!window.watch("extra", &count));
+
+        let (value, len) = text(window.get("threshold").unwrap());
+        assert_eq!(&value[..len], b"12");
+        assert!(matches!(window.get("speed"), Err(Error::NotFound)));
+
+        let (value, len) = text(window.set("threshold", "-40").unwrap());
+        assert_eq!(&value[..len], b"-40");
+        assert_eq!(threshold.get(), -40);
+        assert!(window.set("ratio", "0.75").is_ok());
+        assert_eq!(ratio.get(), 0.75);
+        assert!(matches!(
+            window.set("ratio", "high"),
+            Err(Error::InvalidArgument)
+        ));
+        assert_eq!(ratio.get(), 0.75);
+        assert!(matches!(window.set("count", "0"), Err(Error::InvalidMode)));
+        assert!(matches!(
+            window.set("threshold", "1e3"),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(window.set("speed", "1"), Err(Error::NotFound)));
+        assert_eq!((threshold.get(), count.get()), (-40, 7));
+    }
+
+    #[test]
+    fn list_get_and_set() {
+        let mut window: WatchWindow<3> = WatchWindow::new();
+        assert!(window.watch("gain", &GAIN));
+        assert!(window.watch("enabled", &ENABLED));
+        assert!(!window.watch("gain", &LIMIT));
+        assert!(window.watch_read_only("limit", &LIMIT));
+
+        let mut out = Out([0; 96], 0);
+        for byte in b"set gain 2.25\rset limit 5\r" {
+            window.push(*byte, &mut out).unwrap();
+        }
+        assert!(window.execute("set enabled true", &mut out).unwrap());
+        assert!(!window.execute("reboot", &mut out).unwrap());
+        assert_eq!(GAIN.get(), 2.25);
+        assert_eq!(LIMIT.get(), 300);
+        assert_eq!(
+            &out.0[..out.1],
+            &b"gain = 2.25\r\nlimit is read only\r\nenabled = true\r\n"[..]
+        );
+
+        let mut out = Out([0; 96], 0);
+        window.execute("set gain fast", &mut out).unwrap();
+        window.execute("get speed", &mut out).unwrap();
+        window.execute("list", &mut out).unwrap();
+        assert_eq!(
+            &out.0[..out.1],
+            &b"invalid value fast\r\nunknown variable speed\r\n\
+gain = 2.25\r\nenabled = true\r\nlimit = 300\r\n"[..]
+        );
+    }
+}
@@ -0,0 +1,398 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Building blocks of a weather station. `WeatherStation` samples the sensors
+//! behind the `WeatherSensors` trait at a fixed interval and turns the
+//! readings into an `Observation`, adding the dew point and the pressure
+//! trend of the last three hours. Observations can be written to the data
+//! logger through any `LogSink` or sent as the `Environment` telemetry message.
+//! Values are integers: hundredths of a degree or of a percent, pascals,
+//! lux and hundredths of a millimeter of rain.
+//! See `examples/sensors/weather` for a station built on the AHT10.
+
+use crate::collections::RingBuffer;
+use crate::net::telemetry::Environment;
+use crate::system::datalogger::{LogSink, Record};
+use crate::{Error, Result};
+
+/// Channels of the records written by `Observation::log`.
+pub const CHANNEL_TEMPERATURE: u8 = 0;
+pub const CHANNEL_HUMIDITY: u8 = 1;
+pub const CHANNEL_DEW_POINT: u8 = 2;
+pub const CHANNEL_PRESSURE: u8 = 3;
+pub const CHANNEL_LIGHT: u8 = 4;
+pub const CHANNEL_RAIN: u8 = 5;
+
+/// Milliseconds in an hour, the interval of the pressure history.
+const HOUR: u32 = 3_600_000;
+
+/// Number of hours over which the pressure trend is computed.
+pub const TREND_HOURS: usize = 3;
+
+/// Computes the base 2 logarithm of a positive number.
+/// # Returns
+/// * `a i32` - the logarithm with 16 fractional bits.
+fn log2_q16(x: u32) -> i32 {
+    let integer = 31 - x.leading_zeros() as i32;
+    // Mantissa in [1, 2) with 16 fractional bits, squared once per bit of the result.
+    let mut mantissa = if integer > 16 {
+        x >> (integer - 16)
+    } else {
+        x << (16 - integer)
+    } as u64;
+    let mut fraction = 0;
+    for bit in (0..16).rev() {
+        mantissa = (mantissa * mantissa) >> 16;
+        if mantissa >= 2 << 16 {
+            mantissa >>= 1;
+            fraction |= 1 << bit;
+        }
+    }
+    (integer << 16) | fraction
+}
+
+/// Computes the dew point with the Magnus formula, accurate to about 0.1 degree
+/// for temperatures between -40 and 50 degrees Celsius.
+/// # Arguments
+/// * `temperature` - a i32, the air temperature in hundredths of a degree Celsius.
+/// * `humidity` - a u32, the relative humidity in hundredths of a percent.
+/// # Returns
+/// * `a i32` - The dew point in hundredths of a degree Celsius.
+pub fn dew_point(temperature: i32, humidity: u32) -> i32 {
+    let humidity = humidity.clamp(1, 10_000);
+    // ln(humidity / 100 %) with 16 fractional bits, ln 2 being 45426 / 65536.
+    let ln = ((log2_q16(humidity) - log2_q16(10_000)) as i64 * 45_426) >> 16;
+    let gamma = ln + ((1762 * temperature as i64) << 16) / (100 * (24_312 + temperature as i64));
+    (24_312 * gamma / (1_154_744 - gamma)) as i32
+}
+
+/// Direction of the change of the air pressure.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Trend {
+    /// Less than three hours of history.
+    Unknown,
+    /// Changed by at most 1 hPa in three hours.
+    Steady,
+    /// Rose by up to 6 hPa in three hours.
+    Rising,
+    /// Fell by up to 6 hPa in three hours.
+    Falling,
+    /// Rose by more than 6 hPa in three hours.
+    RisingQuickly,
+    /// Fell by more than 6 hPa in three hours, often a storm coming.
+    FallingQuickly,
+}
+
+impl Trend {
+    /// Classifies a change of the pressure over three hours.
+    /// # Arguments
+    /// * `change` - a i32, the change in pascals.
+    pub fn from_change(change: i32) -> Trend {
+        match change {
+            -100..=100 => Trend::Steady,
+            101..=600 => Trend::Rising,
+            -600..=-101 => Trend::Falling,
+            c if c > 0 => Trend::RisingQuickly,
+            _ => Trend::FallingQuickly,
+        }
+    }
+}
+
+/// Hourly history of the air pressure.
+pub struct PressureHistory {
+    samples: RingBuffer<u32, { TREND_HOURS + 1 }>,
+    next: Option<u32>,
+}
+
+impl PressureHistory {
+    /// Creates an empty history.
+    pub const fn new() -> PressureHistory {
+        PressureHistory {
+            samples: RingBuffer::new(),
+            next: None,
+        }
+    }
+
+    /// Gives a reading, kept if an hour passed since the last kept one.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds.
+    /// * `pressure` - a u32, the air pressure in pascals.
+    pub fn record(&mut self, now: u32, pressure: u32) {
+        if let Some(next) = self.next {
+            if (now.wrapping_sub(next) as i32) < 0 {
+                return;
+            }
+        }
+        self.next = Some(now.wrapping_add(HOUR));
+        self.samples.push_overwrite(pressure);
+    }
+
+    /// Returns the change of the pressure over the last three hours in pascals,
+    /// `None` before three hours of history are recorded.
+    pub fn change(&self) -> Option<i32> {
+        if !self.samples.is_full() {
+            return None;
+        }
+        let oldest = self.samples.get(0)?;
+        let newest = self.samples.get(TREND_HOURS)?;
+        Some(newest as i32 - oldest as i32)
+    }
+
+    /// Returns the trend of the pressure.
+    pub fn trend(&self) -> Trend {
+        self.change().map_or(Trend::Unknown, Trend::from_change)
+    }
+}
+
+impl Default for PressureHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sensors of a weather station. Temperature and humidity are required,
+/// the other readings return `Error::NotFound` when the sensor is missing.
+pub trait WeatherSensors {
+    /// Returns the air temperature in hundredths of a degree Celsius.
+    fn temperature(&mut self) -> Result<i32>;
+
+    /// Returns the relative humidity in hundredths of a percent.
+    fn humidity(&mut self) -> Result<u32>;
+
+    /// Returns the air pressure in pascals.
+    fn pressure(&mut self) -> Result<u32> {
+        Err(Error::NotFound)
+    }
+
+    /// Returns the illuminance in lux.
+    fn light(&mut self) -> Result<u32> {
+        Err(Error::NotFound)
+    }
+
+    /// Returns the rain fallen since the previous call in hundredths of a millimeter.
+    fn rain(&mut self) -> Result<u32> {
+        Err(Error::NotFound)
+    }
+}
+
+/// One set of readings of the station.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Observation {
+    /// Time of the readings in milliseconds.
+    pub timestamp: u32,
+    /// Air temperature in hundredths of a degree Celsius.
+    pub temperature: i32,
+    /// Relative humidity in hundredths of a percent.
+    pub humidity: u32,
+    /// Dew point in hundredths of a degree Celsius.
+    pub dew_point: i32,
+    /// Air pressure in pascals.
+    pub pressure: Option<u32>,
+    /// Trend of the air pressure.
+    pub trend: Trend,
+    /// Illuminance in lux.
+    pub light: Option<u32>,
+    /// Rain since the previous observation in hundredths of a millimeter.
+    pub rain: Option<u32>,
+}
+
+impl Observation {
+    /// Writes the readings to a data logger sink, one record per channel.
+    /// # Arguments
+    /// * `sink` - a `LogSink` object, where the records are written.
+    /// # Returns
+    /// * `a boolean` - false if the sink refused a record.
+    pub fn log<L: LogSink>(&self, sink: &mut L) -> bool {
+        let values = [
+            (CHANNEL_TEMPERATURE, Some(self.temperature)),
+            (CHANNEL_HUMIDITY, Some(self.humidity as i32)),
+            (CHANNEL_DEW_POINT, Some(self.dew_point)),
+            (CHANNEL_PRESSURE, self.pressure.map(|p| p as i32)),
+            (CHANNEL_LIGHT, self.light.map(|l| l as i32)),
+            (CHANNEL_RAIN, self.rain.map(|r| r as i32)),
+        ];
+        let mut stored = true;
+        for (channel, value) in values {
+            if let Some(value) = value {
+                stored &= sink.write(&Record {
+                    timestamp: self.timestamp,
+                    channel,
+                    value,
+                });
+            }
+        }
+        stored
+    }
+
+    /// Returns the readings as the `Environment` telemetry message, 0 Pa without barometer.
+    pub fn environment(&self) -> Environment {
+        Environment {
+            temperature: self.temperature.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            humidity: self.humidity.min(u16::MAX as u32) as u16,
+            pressure: self.pressure.unwrap_or(0),
+        }
+    }
+}
+
+/// Samples the sensors `S` at a fixed interval.
+pub struct WeatherStation<S: WeatherSensors> {
+    sensors: S,
+    interval: u32,
+    next: u32,
+    history: PressureHistory,
+    latest: Option<Observation>,
+    errors: u16,
+}
+
+impl<S: WeatherSensors> WeatherStation<S> {
+    /// Creates the station, the first observation is due at once.
+    /// # Arguments
+    /// * `sensors` - a `WeatherSensors` object, the sensors of the station.
+    /// * `interval` - a u32, the time between two observations in milliseconds.
+    pub fn new(sensors: S, interval: u32) -> Self {
+        WeatherStation {
+            sensors,
+            interval,
+            next: 0,
+            history: PressureHistory::new(),
+            latest: None,
+            errors: 0,
+        }
+    }
+
+    /// Returns a mutable reference to the sensors.
+    pub fn sensors(&mut self) -> &mut S {
+        &mut self.sensors
+    }
+
+    /// Returns the last observation.
+    pub fn latest(&self) -> Option<Observation> {
+        self.latest
+    }
+
+    /// Returns the number of observations which failed.
+    pub fn errors(&self) -> u16 {
+        self.errors
+    }
+
+    /// Reads all the sensors at once.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds.
+    /// # Returns
+    /// * `a Result<Observation>` - The observation, or the error of the thermometer or hygrometer.
+    pub fn measure(&mut self, now: u32) -> Result<Observation> {
+        let temperature = self.sensors.temperature()?;
+        let humidity = self.sensors.humidity()?;
+        let pressure = self.sensors.pressure().ok();
+        if let Some(pressure) = pressure {
+            self.history.record(now, pressure);
+        }
+        let observation = Observation {
+            timestamp: now,
+            temperature,
+            humidity,
+            dew_point: dew_point(temperature, humidity),
+            pressure,
+            trend: self.history.trend(),
+            light: self.sensors.light().ok(),
+            rain: self.sensors.rain().ok(),
+        };
+        self.latest = Some(observation);
+        Ok(observation)
+    }
+
+    /// Takes an observation if one is due.
+    /// Call it often from the main loop or from a scheduler task.
+    /// # Arguments
+    /// * `now` - a u32, the current time in milliseconds, usually `millis()`.
+    /// # Returns
+    /// * `a Option<Observation>` - The new observation, `None` if none was due or it failed.
+    pub fn poll(&mut self, now: u32) -> Option<Observation> {
+        if (now.wrapping_sub(self.next) as i32) < 0 {
+            return None;
+        }
+        self.next = now.wrapping_add(self.interval);
+        match self.measure(now) {
+            Ok(observation) => Some(observation),
+            Err(_) => {
+                self.errors = self.errors.saturating_add(1);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dew_point_matches_magnus() {
+        assert!((dew_point(2000, 5000) - 926).abs() <= 3);
+        assert!((dew_point(2500, 10_000) - 2500).abs() <= 3);
+        assert!((dew_point(0, 8000) + 304).abs() <= 3);
+        assert!((dew_point(-1000, 6000) + 1631).abs() <= 3);
+        assert!((dew_point(3000, 2000) - 458).abs() <= 3);
+    }
+
+    struct Fake {
+        pressure: u32,
+    }
+
+    impl WeatherSensors for Fake {
+        fn temperature(&mut self) -> Result<i32> {
+            Ok(2000)
+        }
+
+        fn humidity(&mut self) -> Result<u32> {
+            Ok(5000)
+        }
+
+        fn pressure(&mut self) -> Result<u32> {
+            self.pressure -= 250;
+            Ok(self.pressure)
+        }
+    }
+
+    struct Records(usize);
+
+    impl LogSink for Records {
+        fn write(&mut self, _record: &Record) -> bool {
+            self.0 += 1;
+            true
+        }
+    }
+
+    #[test]
+    fn pressure_trend_over_three_hours() {
+        let mut station = WeatherStation::new(Fake { pressure: 101_325 }, 60_000);
+        let first = station.poll(0).unwrap();
+        assert_eq!(first.trend, Trend::Unknown);
+        assert_eq!(first.light, None);
+        assert!(station.poll(59_999).is_none());
+
+        let mut last = first;
+        for minute in 1..=180 {
+            last = station.poll(minute * 60_000).unwrap();
+        }
+        assert_eq!(last.trend, Trend::FallingQuickly);
+        assert_eq!(last.environment().pressure, last.pressure.unwrap());
+
+        let mut records = Records(0);
+        assert!(last.log(&mut records));
+        assert_eq!(records.0, 4);
+    }
+}
@@ -0,0 +1,255 @@
+// RustDuino : A generic HAL implementation for Arduino Boards in Rust
+// Copyright (C) 2021 Devansh Kumar Jha, Indian Institute of Technology Kanpur
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Sources of time for the code measuring it, so that it can run on a
+//! simulated clock as well as on the real one.
+//! `Clocked` gives the time and waits, `HardwareClock` reads the `millis`
+//! counter and busy-waits or sleeps, while `VirtualClock` only moves forward
+//! when told to and returns from every wait at once, after adding its length.
+//! `HardwareClock` needs the `millis` feature.
+//! Host side tests and simavr runs of the scheduler, of timeouts and of the
+//! drivers taking a `DelayNs` provider then run in no time and always alike.
+
+#[cfg(all(
+    feature = "millis",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+use crate::hal::{millis, sleep_mode};
+use embedded_hal::delay::DelayNs;
+
+/// A clock giving the time and waiting.
+pub trait Clocked {
+    /// Returns the number of milliseconds since the clock started.
+    fn millis(&self) -> u32;
+
+    /// Returns the number of microseconds since the clock started.
+    fn micros(&self) -> u32;
+
+    /// Waits for N microseconds.
+    /// # Arguments
+    /// * `us` - an u32, number of microseconds to wait.
+    fn delay_us(&mut self, us: u32);
+
+    /// Waits for N milliseconds.
+    /// # Arguments
+    /// * `ms` - an u32, number of milliseconds to wait.
+    fn delay_ms(&mut self, ms: u32) {
+        for _ in 0..ms {
+            self.delay_us(1000);
+        }
+    }
+
+    /// Waits for something to happen while there is nothing to do,
+    /// at most until the next millisecond.
+    fn idle(&mut self);
+}
+
+/// The clock of the board, reading the counters started by `millis_init`.
+#[cfg(all(
+    feature = "millis",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+#[derive(Clone, Copy, Default)]
+pub struct HardwareClock;
+
+#[cfg(all(
+    feature = "millis",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+impl HardwareClock {
+    /// Returns the clock of the board.
+    pub const fn new() -> HardwareClock {
+        HardwareClock
+    }
+}
+
+#[cfg(all(
+    feature = "millis",
+    any(feature = "atmega328p", feature = "atmega2560p")
+))]
+impl Clocked for HardwareClock {
+    fn millis(&self) -> u32 {
+        millis::millis()
+    }
+
+    fn micros(&self) -> u32 {
+        millis::micros()
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        crate::delay::Delay.delay_us(us);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        crate::delay::wait_ms(ms);
+    }
+
+    fn idle(&mut self) {
+        // The Timer0 overflow wakes the CPU at least once per millisecond.
+        sleep_mode::idle();
+    }
+}
+
+/// A simulated clock, which starts at 0 and moves forward only through
+/// `advance` and the waits. It can be given to drivers as a `DelayNs` provider.
+/// # Elements
+/// * `nanos` - a u64, the time since the start in nanoseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VirtualClock {
+    nanos: u64,
+}
+
+impl VirtualClock {
+    /// Returns a clock at time 0.
+    pub const fn new() -> VirtualClock {
+        VirtualClock { nanos: 0 }
+    }
+
+    /// Returns a clock at the given time.
+    /// # Arguments
+    /// * `ms` - an u32, the time in milliseconds.
+    pub const fn starting_at(ms: u32) -> VirtualClock {
+        VirtualClock {
+            nanos: ms as u64 * 1_000_000,
+        }
+    }
+
+    /// Moves the time forward.
+    /// # Arguments
+    /// * `us` - an u32, number of microseconds to add.
+    pub fn advance(&mut self, us: u32) {
+        self.nanos += us as u64 * 1000;
+    }
+
+    /// Returns the time since the start in nanoseconds, which never wraps around.
+    pub fn nanos(&self) -> u64 {
+        self.nanos
+    }
+}
+
+impl Clocked for VirtualClock {
+    fn millis(&self) -> u32 {
+        (self.nanos / 1_000_000) as u32
+    }
+
+    fn micros(&self) -> u32 {
+        (self.nanos / 1000) as u32
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.advance(us);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.nanos += ms as u64 * 1_000_000;
+    }
+
+    fn idle(&mut self) {
+        // Like the hardware, wake up at the next tick of the millisecond counter.
+        self.nanos = (self.nanos / 1_000_000 + 1) * 1_000_000;
+    }
+}
+
+impl DelayNs for VirtualClock {
+    fn delay_ns(&mut self, ns: u32) {
+        self.nanos += ns as u64;
+    }
+}
+
+/// A time limit measured on a clock, for the loops waiting for a device.
+/// # Elements
+/// * `start` - a u32, the time at which the timeout started in milliseconds.
+/// * `length` - a u32, the length of the timeout in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct Timeout {
+    start: u32,
+    length: u32,
+}
+
+impl Timeout {
+    /// Starts a timeout.
+    /// # Arguments
+    /// * `clock` - a `Clocked` object, the clock measuring the timeout.
+    /// * `ms` - an u32, the length of the timeout in milliseconds.
+    /// # Returns
+    /// * `a Timeout object` - which will be used for further implementations.
+    pub fn start<C: Clocked>(clock: &C, ms: u32) -> Timeout {
+        Timeout {
+            start: clock.millis(),
+            length: ms,
+        }
+    }
+
+    /// Returns true once the length of the timeout has passed,
+    /// correctly handling the overflow of the millisecond counter.
+    pub fn expired<C: Clocked>(&self, clock: &C) -> bool {
+        clock.millis().wrapping_sub(self.start) >= self.length
+    }
+
+    /// Returns the number of milliseconds left, 0 once expired.
+    pub fn remaining<C: Clocked>(&self, clock: &C) -> u32 {
+        self.length
+            .saturating_sub(clock.millis().wrapping_sub(self.start))
+    }
+
+    /// Idles on the clock until the condition is true or the timeout expires.
+    /// # Arguments
+    /// * `clock` - a `Clocked` object, the clock measuring the timeout.
+    /// * `condition` - a closure, polled until it returns true.
+    /// # Returns
+    /// * `a boolean` - true if the condition became true in time.
+    pub fn wait_for<C: Clocked, F: FnMut() -> bool>(
+        &self,
+        clock: &mut C,
+        mut condition: F,
+    ) -> bool {
+        loop {
+            if condition() {
+                return true;
+            }
+            if self.expired(clock) {
+                return false;
+            }
+            clock.idle();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn virtual_time() {
+        let mut clock = VirtualClock::starting_at(u32::MAX - 5);
+        let timeout = Timeout::start(&clock, 10);
+        Clocked::delay_ms(&mut clock, 4);
+        assert!(!timeout.expired(&clock));
+        assert_eq!(timeout.remaining(&clock), 6);
+
+        let mut polls = 0;
+        assert!(!timeout.wait_for(&mut clock, || {
+            polls += 1;
+            false
+        }));
+        assert_eq!(polls, 7);
+        assert_eq!(clock.millis(), (u32::MAX - 5).wrapping_add(10));
+
+        DelayNs::delay_us(&mut clock, 1500);
+        clock.idle();
+        assert_eq!(clock.millis(), (u32::MAX - 5).wrapping_add(12));
+    }
+}
@@ -0,0 +1,90 @@
+# Builds selected examples for the AVR target and runs them under simavr.
+# Every file simavr/<chip>/<example>.spec lists the checks done on one example,
+# one per line, blank lines and lines starting with '#' are ignored.
+#   uart <text>                      - <text> must appear in the UART output.
+#   trace <name> <addr> <mask> <n>   - the I/O register at <addr> masked with <mask>
+#                                      must change at least <n> times.
+# Usage: bash test_simavr.sh [seconds to run each example, default 10]
+# Needs simavr 1.7 or newer in the PATH.
+
+RUN_TIME=${1:-10}
+ROOT=$(pwd)
+OUT=$ROOT/target/simavr
+mkdir -p $OUT
+failed=0
+
+for spec in simavr/*/*.spec ; do
+    chip=$(basename $(dirname $spec))
+    example=$(basename $spec .spec)
+    echo "Simulating $example for $chip"
+
+    if [[ $chip == "atmega328p" ]];
+    then
+        target=avr-atmega328p
+        mcu=atmega328p
+    else
+        target=avr-atmega2560
+        mcu=atmega2560
+    fi
+
+    cd examples/$chip/$example
+    AVR_CPU_FREQUENCY_HZ=16000000 cargo +nightly-2024-03-22 build -Z build-std=core --release --target ../../../avr-chips/$target.json >> /dev/null   || exit 1
+    cd $ROOT
+    package=$(sed -n 's/^name *= *"\(.*\)"/\1/p' examples/$chip/$example/Cargo.toml)
+    elf=examples/$chip/$example/target/$target/release/$package.elf
+
+    # Collect the register traces requested by the spec.
+    traces=()
+    while read -r kind name addr mask count ; do
+        if [[ $kind == "trace" ]];
+        then
+            traces+=(--add-trace "$name=trace@$addr/$mask")
+        fi
+    done < $spec
+
+    log=$OUT/$chip-$example.log
+    vcd=$OUT/$chip-$example.vcd
+    rm -f $log $vcd
+    if [[ ${#traces[@]} -ne 0 ]];
+    then
+        timeout $RUN_TIME simavr -m $mcu -f 16000000 "${traces[@]}" --output $vcd $elf > $log 2>&1
+    else
+        timeout $RUN_TIME simavr -m $mcu -f 16000000 $elf > $log 2>&1
+    fi
+
+    # simavr colours the UART output, remove the escape sequences.
+    sed -i 's/\x1b\[[0-9;]*m//g' $log
+
+    while read -r kind rest ; do
+        case $kind in
+            uart)
+                if ! grep -qF -- "$rest" $log ;
+                then
+                    echo "  FAILED: '$rest' was not sent on the UART"
+                    failed=1
+                fi
+                ;;
+            trace)
+                read -r name addr mask count <<< "$rest"
+                # Identifier given to the signal in the VCD header.
+                id=$(awk -v n="$name" '$1 == "$var" && $5 == n { print $4 }' $vcd 2>/dev/null)
+                changes=0
+                if [[ -n $id ]];
+                then
+                    changes=$(awk -v id="$id" '
+                        /^\$enddefinitions/ { body = 1; next }
+                        body && /^b/ && $2 == id { n++ }
+                        body && /^[01xz]/ && substr($0, 2) == id { n++ }
+                        END { print n + 0 }' $vcd)
+                fi
+                if [[ $changes -lt $count ]];
+                then
+                    echo "  FAILED: $name changed $changes times, expected at least $count"
+                    failed=1
+                fi
+                ;;
+        esac
+    done < <(grep -v '^\s*\(#\|$\)' $spec)
+done
+
+exit $failed
@@ -1,4 +1,4 @@
-rustup toolchain install nightly-2021-01-07
-rustup override set nightly-2021-01-07
-rustup +nightly-2021-01-07 component add rustfmt-preview
-rustup +nightly-2021-01-07 component add rust-src
\ No newline at end of file
+rustup toolchain install nightly-2024-03-22
+rustup override set nightly-2024-03-22
+rustup +nightly-2024-03-22 component add rustfmt
+rustup +nightly-2024-03-22 component add rust-src
\ No newline at end of file